@@ -0,0 +1,126 @@
+//! Who may talk to Claude: allowed senders, strangers waiting for `/allow`,
+//! unused `/invite` codes and the guests they let in.
+//!
+//! Persistence stays with the callers (`memory::persist_*`); this is the
+//! in-memory view every incoming message is checked against.
+
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::constants::{UNKNOWN_REPLIES_PER_HOUR, UNKNOWN_REPLY_INTERVAL_SECS};
+
+/// A stranger who messaged the bridge and waits for `/allow`.
+pub(crate) struct PendingSender {
+    pub(crate) name: String,
+    /// Number `/allow #n` and `/pending` refer to them by.
+    pub(crate) short_id: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct Access {
+    pub(crate) allowed_ids: DashMap<String, ()>,
+    pub(crate) pending_senders: DashMap<String, PendingSender>,
+    /// Last short id handed to a pending sender.
+    pending_counter: AtomicU64,
+    /// Unused `/invite` codes -> when they were made (epoch secs).
+    pub(crate) invites: DashMap<String, i64>,
+    /// Senders let in by an invite code, who get answers only and a small budget.
+    pub(crate) guests: DashMap<String, ()>,
+    /// Unknown senders -> (wrong invite codes tried, when the first was).
+    pub(crate) invite_misses: DashMap<String, (u32, i64)>,
+    /// Unknown senders -> when they were last sent the auto-reply (epoch secs).
+    auto_replies: DashMap<String, i64>,
+}
+
+impl Access {
+    pub(crate) fn new(
+        allowed_ids: DashMap<String, ()>,
+        invites: DashMap<String, i64>,
+        guests: DashMap<String, ()>,
+    ) -> Self {
+        Self {
+            allowed_ids,
+            invites,
+            guests,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_allowed(&self, sender: &str) -> bool {
+        !sender.is_empty() && self.allowed_ids.contains_key(sender)
+    }
+
+    /// Whether `sender` came in with an invite code and hasn't been `/allow`ed since.
+    pub(crate) fn is_guest(&self, sender: &str) -> bool {
+        self.guests.contains_key(sender)
+    }
+
+    /// Record `sender` as waiting for approval. Returns their short id and
+    /// whether they're new (already-pending senders keep theirs).
+    pub(crate) fn add_pending(&self, sender: &str, name: &str) -> (u64, bool) {
+        if let Some(pending) = self.pending_senders.get(sender) {
+            return (pending.short_id, false);
+        }
+        let short_id = self.pending_counter.fetch_add(1, Ordering::Relaxed) + 1;
+        self.pending_senders.insert(
+            sender.to_string(),
+            PendingSender {
+                name: name.to_string(),
+                short_id,
+            },
+        );
+        (short_id, true)
+    }
+
+    /// Whether unknown `sender` may be sent the auto-reply now, recording it
+    /// if so: once a day each, and [`UNKNOWN_REPLIES_PER_HOUR`] in all, so
+    /// messages from a stream of new numbers can't make the bridge send.
+    pub(crate) fn take_auto_reply(&self, sender: &str, now: i64) -> bool {
+        self.auto_replies
+            .retain(|_, at| now - *at < UNKNOWN_REPLY_INTERVAL_SECS);
+        if self.auto_replies.contains_key(sender) {
+            return false;
+        }
+        let last_hour = self
+            .auto_replies
+            .iter()
+            .filter(|e| now - *e.value() < 3600)
+            .count();
+        if last_hour >= UNKNOWN_REPLIES_PER_HOUR {
+            return false;
+        }
+        self.auto_replies.insert(sender.to_string(), now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_senders_keep_their_short_id() {
+        let access = Access::default();
+        assert_eq!(access.add_pending("+1", "Ann"), (1, true));
+        assert_eq!(access.add_pending("+2", "Bob"), (2, true));
+        assert_eq!(access.add_pending("+1", "Ann"), (1, false));
+        assert!(!access.is_allowed("+1"));
+        access.allowed_ids.insert("+1".to_string(), ());
+        assert!(access.is_allowed("+1"));
+        assert!(!access.is_allowed(""));
+    }
+
+    #[test]
+    fn test_auto_reply_once_a_day_and_capped_per_hour() {
+        let access = Access::default();
+        assert!(access.take_auto_reply("+1", 1_000));
+        assert!(!access.take_auto_reply("+1", 2_000));
+        for i in 2..=UNKNOWN_REPLIES_PER_HOUR {
+            assert!(access.take_auto_reply(&format!("+{i}"), 1_000));
+        }
+        assert!(!access.take_auto_reply("+new", 1_500));
+        // An hour on, new senders get one again; a day on, so does +1
+        assert!(access.take_auto_reply("+new", 1_000 + 3600));
+        assert!(access.take_auto_reply("+1", 1_000 + UNKNOWN_REPLY_INTERVAL_SECS));
+    }
+}
@@ -0,0 +1,180 @@
+//! Claude Code custom agents for `/agent`.
+//!
+//! Agents are Markdown files with YAML frontmatter, as Claude Code writes
+//! them: `name`, `description` and an optional comma-separated `tools` list,
+//! followed by the agent's system prompt. They are read from the bound
+//! project's `.claude/agents` and from `~/.claude/agents`; a project agent
+//! wins over a personal one with the same name.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Agent {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    /// Tools the agent may use (empty = all).
+    pub(crate) tools: Vec<String>,
+    pub(crate) prompt: String,
+}
+
+#[derive(Deserialize, Default)]
+struct Frontmatter {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    tools: Option<Tools>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Tools {
+    List(Vec<String>),
+    Csv(String),
+}
+
+impl Agent {
+    /// Parse an agent file; `stem` (the file name) is the fallback name.
+    pub(crate) fn parse(stem: &str, contents: &str) -> Option<Self> {
+        let rest = contents.trim_start().strip_prefix("---")?;
+        let (yaml, body) = rest.split_once("\n---")?;
+        let meta: Frontmatter = serde_yaml::from_str(yaml).ok()?;
+        let tools = match meta.tools {
+            Some(Tools::List(list)) => list,
+            Some(Tools::Csv(csv)) => csv
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect(),
+            None => Vec::new(),
+        };
+        Some(Self {
+            name: meta.name.unwrap_or_else(|| stem.to_string()),
+            description: meta.description.unwrap_or_default(),
+            tools,
+            prompt: body.trim_start_matches(|c| c != '\n').trim().to_string(),
+        })
+    }
+
+    /// System prompt section that puts Claude in this agent's role.
+    pub(crate) fn system_prompt(&self) -> String {
+        let mut prompt = format!("You are acting as the \"{}\" agent.", self.name);
+        if !self.description.is_empty() {
+            prompt.push_str(&format!(" {}", self.description));
+        }
+        if !self.tools.is_empty() {
+            prompt.push_str(&format!(
+                "\nOnly use these tools: {}.",
+                self.tools.join(", ")
+            ));
+        }
+        if !self.prompt.is_empty() {
+            prompt.push_str("\n\n");
+            prompt.push_str(&self.prompt);
+        }
+        prompt
+    }
+}
+
+/// Where to look for agents: the project's `.claude/agents` (if the chat is
+/// bound to one), then `~/.claude/agents`.
+pub(crate) fn agent_dirs(project: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(project) = project {
+        dirs.push(project.join(".claude").join("agents"));
+    }
+    if let Ok(home) = std::env::var("HOME") {
+        dirs.push(PathBuf::from(home).join(".claude").join("agents"));
+    }
+    dirs
+}
+
+/// Agents in `dirs`, sorted by name; earlier directories win on clashes.
+pub(crate) fn list_agents(dirs: &[PathBuf]) -> Vec<Agent> {
+    let mut agents: Vec<Agent> = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "md"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let Some(agent) = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|contents| Agent::parse(&stem, &contents))
+            else {
+                continue;
+            };
+            if !agents.iter().any(|a| a.name == agent.name) {
+                agents.push(agent);
+            }
+        }
+    }
+    agents.sort_by(|a, b| a.name.cmp(&b.name));
+    agents
+}
+
+pub(crate) fn find_agent(dirs: &[PathBuf], name: &str) -> Option<Agent> {
+    list_agents(dirs).into_iter().find(|a| a.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REVIEWER: &str = "---\nname: code-reviewer\ndescription: Reviews diffs for bugs.\ntools: Read, Grep, Glob\n---\nYou review code.\nBe terse.\n";
+
+    #[test]
+    fn test_parse_agent_file() {
+        let agent = Agent::parse("reviewer", REVIEWER).unwrap();
+        assert_eq!(agent.name, "code-reviewer");
+        assert_eq!(agent.tools, ["Read", "Grep", "Glob"]);
+        assert_eq!(agent.prompt, "You review code.\nBe terse.");
+        assert_eq!(
+            agent.system_prompt(),
+            "You are acting as the \"code-reviewer\" agent. Reviews diffs for bugs.\nOnly use these tools: Read, Grep, Glob.\n\nYou review code.\nBe terse."
+        );
+
+        let unnamed = Agent::parse("writer", "---\ndescription: Drafts docs\n---\nWrite.").unwrap();
+        assert_eq!(unnamed.name, "writer");
+        assert!(unnamed.tools.is_empty());
+        assert!(Agent::parse("plain", "No frontmatter here").is_none());
+    }
+
+    #[test]
+    fn test_project_agents_shadow_personal_ones() {
+        let root = std::env::temp_dir().join(format!("ccchat_agents_{}", std::process::id()));
+        let project = root.join("project");
+        let personal = root.join("personal");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(&personal).unwrap();
+        std::fs::write(project.join("reviewer.md"), REVIEWER).unwrap();
+        std::fs::write(
+            personal.join("reviewer.md"),
+            "---\nname: code-reviewer\ndescription: Personal copy\n---\n",
+        )
+        .unwrap();
+        std::fs::write(
+            personal.join("planner.md"),
+            "---\nname: planner\ndescription: Plans work\n---\nPlan.",
+        )
+        .unwrap();
+        std::fs::write(personal.join("notes.txt"), "ignored").unwrap();
+
+        let dirs = vec![project, personal];
+        let agents = list_agents(&dirs);
+        let names: Vec<&str> = agents.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["code-reviewer", "planner"]);
+        assert_eq!(agents[0].description, "Reviews diffs for bugs.");
+        assert!(find_agent(&dirs, "planner").is_some());
+        assert!(find_agent(&dirs, "missing").is_none());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
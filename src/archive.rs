@@ -0,0 +1,328 @@
+//! Off-site archives in S3-compatible object storage (`--archive-bucket`).
+//!
+//! Every `--archive-every-hours` the same encrypted bundle `ccchat sessions
+//! export` writes (conversation history, summaries, pins, the allowed list
+//! and schedules) is uploaded as `<prefix>ccchat-<timestamp>.bundle`. It is
+//! sealed with `--archive-passphrase` before it leaves the machine, so the
+//! storage provider only ever holds ciphertext. The newest `--archive-keep`
+//! archives are kept. `ccchat archive restore` downloads one and unpacks it
+//! into the config dir.
+//!
+//! Requests are signed with AWS Signature V4 and use path-style URLs
+//! (`<endpoint>/<bucket>/<key>`), which AWS, MinIO, R2, B2 and Wasabi accept.
+
+use regex::Regex;
+use ring::{digest, hmac};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::info;
+
+use crate::error::AppError;
+
+const SERVICE: &str = "s3";
+const ARCHIVE_SUFFIX: &str = ".bundle";
+
+#[derive(Clone, Debug)]
+pub(crate) struct ArchiveConfig {
+    /// Base URL of the S3 API, e.g. `https://s3.eu-west-2.amazonaws.com`.
+    pub(crate) endpoint: String,
+    pub(crate) bucket: String,
+    pub(crate) region: String,
+    pub(crate) access_key: String,
+    pub(crate) secret_key: String,
+    /// Key prefix the archives are stored under (e.g. `ccchat/`).
+    pub(crate) prefix: String,
+    /// Passphrase the bundles are sealed with.
+    pub(crate) passphrase: String,
+    pub(crate) every: Duration,
+    /// Newest archives kept; older ones are deleted after an upload (0 = all).
+    pub(crate) keep: usize,
+}
+
+/// Percent-encode as SigV4 wants: everything but `A-Za-z0-9-_.~`, and `/`
+/// too unless it separates path segments.
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> hmac::Tag {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+}
+
+/// SigV4 signing key for one day, region and service.
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> hmac::Tag {
+    let k_date = hmac_sha256(format!("AWS4{secret}").as_bytes(), date);
+    let k_region = hmac_sha256(k_date.as_ref(), region);
+    let k_service = hmac_sha256(k_region.as_ref(), service);
+    hmac_sha256(k_service.as_ref(), "aws4_request")
+}
+
+/// One signed request against the bucket. `key` is empty for bucket-level
+/// requests; `query` pairs must already be in sorted order.
+async fn request(
+    http: &reqwest::Client,
+    config: &ArchiveConfig,
+    method: reqwest::Method,
+    key: &str,
+    query: &[(&str, &str)],
+    body: Vec<u8>,
+) -> Result<reqwest::Response, AppError> {
+    let path = if key.is_empty() {
+        format!("/{}", uri_encode(&config.bucket, true))
+    } else {
+        format!(
+            "/{}/{}",
+            uri_encode(&config.bucket, true),
+            uri_encode(key, false)
+        )
+    };
+    let query = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", uri_encode(k, true), uri_encode(v, true)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let mut url = format!("{}{path}", config.endpoint.trim_end_matches('/'));
+    if !query.is_empty() {
+        url = format!("{url}?{query}");
+    }
+    let parsed = reqwest::Url::parse(&url)
+        .map_err(|e| AppError::from(format!("Bad archive endpoint {url}: {e}")))?;
+    let host = match (parsed.host_str(), parsed.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(format!("Bad archive endpoint {url}").into()),
+    };
+
+    let now = chrono::Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = sha256_hex(&body);
+    let canonical = format!(
+        "{method}\n{path}\n{query}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_hash}"
+    );
+    let scope = format!("{date}/{}/{SERVICE}/aws4_request", config.region);
+    let to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        sha256_hex(canonical.as_bytes())
+    );
+    let key = signing_key(&config.secret_key, &date, &config.region, SERVICE);
+    let signature = hex(hmac_sha256(key.as_ref(), &to_sign).as_ref());
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}",
+        config.access_key
+    );
+
+    let resp = http
+        .request(method, parsed)
+        .header("x-amz-date", amz_date)
+        .header("x-amz-content-sha256", payload_hash)
+        .header("authorization", authorization)
+        .body(body)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        return Err(format!("Object storage answered {status}: {}", text.trim()).into());
+    }
+    Ok(resp)
+}
+
+/// Archive keys under the prefix, oldest first.
+pub(crate) async fn list(
+    http: &reqwest::Client,
+    config: &ArchiveConfig,
+) -> Result<Vec<String>, AppError> {
+    let body = request(
+        http,
+        config,
+        reqwest::Method::GET,
+        "",
+        &[("list-type", "2"), ("prefix", &config.prefix)],
+        Vec::new(),
+    )
+    .await?
+    .text()
+    .await?;
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"<Key>([^<]+)</Key>").unwrap());
+    let mut keys: Vec<String> = re
+        .captures_iter(&body)
+        .map(|c| c[1].to_string())
+        .filter(|k| k.ends_with(ARCHIVE_SUFFIX))
+        .collect();
+    keys.sort();
+    Ok(keys)
+}
+
+/// Seal the state under `root` and upload it, then drop archives beyond
+/// `keep`. Returns the new key and the number of files archived.
+pub(crate) async fn push(
+    http: &reqwest::Client,
+    config: &ArchiveConfig,
+    root: &Path,
+    iterations: u32,
+) -> Result<(String, usize), AppError> {
+    let (root, passphrase) = (root.to_path_buf(), config.passphrase.clone());
+    let (data, count) = tokio::task::spawn_blocking(move || {
+        crate::bundle::export_bundle(&root, &passphrase, iterations)
+    })
+    .await
+    .map_err(|e| AppError::from(format!("Archive task failed: {e}")))??;
+    let key = format!(
+        "{}ccchat-{}{ARCHIVE_SUFFIX}",
+        config.prefix,
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+    );
+    request(http, config, reqwest::Method::PUT, &key, &[], data).await?;
+    info!(key = %key, files = count, "Uploaded archive");
+
+    if config.keep > 0 {
+        let keys = list(http, config).await?;
+        let excess = keys.len().saturating_sub(config.keep);
+        for old in &keys[..excess] {
+            request(http, config, reqwest::Method::DELETE, old, &[], Vec::new()).await?;
+            info!(key = %old, "Deleted old archive");
+        }
+    }
+    Ok((key, count))
+}
+
+/// Download an archive (the newest when `key` is None) and unpack it under
+/// `root`. Returns the key restored and the number of files written.
+pub(crate) async fn restore(
+    http: &reqwest::Client,
+    config: &ArchiveConfig,
+    root: &Path,
+    key: Option<&str>,
+    force: bool,
+) -> Result<(String, usize), AppError> {
+    let key = match key {
+        Some(key) => key.to_string(),
+        None => list(http, config)
+            .await?
+            .pop()
+            .ok_or_else(|| format!("No archives under {}/{}", config.bucket, config.prefix))?,
+    };
+    let data = request(http, config, reqwest::Method::GET, &key, &[], Vec::new())
+        .await?
+        .bytes()
+        .await?;
+    let count = crate::bundle::import_bundle(root, &data, &config.passphrase, force)?;
+    Ok((key, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header_exists, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_signing_key_matches_aws_example() {
+        // From the AWS docs on deriving a SigV4 signing key
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex(key.as_ref()),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+        assert_eq!(uri_encode("ccchat/a b+c", false), "ccchat/a%20b%2Bc");
+        assert_eq!(uri_encode("ccchat/", true), "ccchat%2F");
+    }
+
+    fn temp_root(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ccchat_archive_{name}_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("memories")).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_push_prunes_and_restore_takes_newest() {
+        let server = MockServer::start().await;
+        let listing = "<ListBucketResult>\
+            <Contents><Key>ccchat/ccchat-20260101T000000Z.bundle</Key></Contents>\
+            <Contents><Key>ccchat/ccchat-20260102T000000Z.bundle</Key></Contents>\
+            <Contents><Key>ccchat/notes.txt</Key></Contents>\
+            </ListBucketResult>";
+        Mock::given(method("GET"))
+            .and(path("/backups"))
+            .and(query_param("list-type", "2"))
+            .and(query_param("prefix", "ccchat/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(listing))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(header_exists("authorization"))
+            .and(header_exists("x-amz-content-sha256"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/backups/ccchat/ccchat-20260101T000000Z.bundle"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = ArchiveConfig {
+            endpoint: server.uri(),
+            bucket: "backups".to_string(),
+            region: "us-east-1".to_string(),
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: "secret".to_string(),
+            prefix: "ccchat/".to_string(),
+            passphrase: "correct horse".to_string(),
+            every: Duration::from_secs(3600),
+            keep: 1,
+        };
+        let src = temp_root("src");
+        std::fs::write(src.join("memories/abc.db"), b"history").unwrap();
+        let http = reqwest::Client::new();
+        let (key, count) = push(&http, &config, &src, 1_000).await.unwrap();
+        assert!(key.starts_with("ccchat/ccchat-") && key.ends_with(".bundle"));
+        assert_eq!(count, 1);
+
+        let (bundle, _) = crate::bundle::export_bundle(&src, "correct horse", 1_000).unwrap();
+        Mock::given(method("GET"))
+            .and(path("/backups/ccchat/ccchat-20260102T000000Z.bundle"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bundle))
+            .mount(&server)
+            .await;
+        let dst = temp_root("dst");
+        let (restored, count) = restore(&http, &config, &dst, None, false).await.unwrap();
+        assert_eq!(restored, "ccchat/ccchat-20260102T000000Z.bundle");
+        assert_eq!(count, 1);
+        assert_eq!(
+            std::fs::read(dst.join("memories/abc.db")).unwrap(),
+            b"history"
+        );
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dst);
+    }
+}
@@ -0,0 +1,310 @@
+//! Kept attachments (`--attachment-retention-days`, `--attachment-max-mb`).
+//!
+//! Files people send are downloaded to `TMP_DIR` and used to be deleted as
+//! soon as Claude had seen them. With retention on, each accepted file moves
+//! to `attachments/<sender hash>/` under the data directory instead and is
+//! recorded in the sender's memory database, so `/attachments` can list what
+//! they've sent. Files older than the retention period go, then the oldest
+//! until the whole store fits in `--attachment-max-mb`: whenever a new file
+//! arrives and as the `attachments` maintenance task.
+
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{error, warn};
+
+use crate::signal::AttachmentInfo;
+use crate::state::State;
+
+/// How long kept attachments stay, and how much room they get in total.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Retention {
+    /// 0 keeps nothing: files are deleted once used.
+    pub(crate) max_age_days: u32,
+    /// 0 is no size limit.
+    pub(crate) max_mb: u64,
+}
+
+impl Retention {
+    pub(crate) fn enabled(&self) -> bool {
+        self.max_age_days > 0
+    }
+
+    fn max_age(&self) -> Duration {
+        Duration::from_secs(u64::from(self.max_age_days) * crate::constants::SECS_PER_DAY as u64)
+    }
+
+    fn max_bytes(&self) -> u64 {
+        match self.max_mb {
+            0 => u64::MAX,
+            mb => mb * 1024 * 1024,
+        }
+    }
+}
+
+/// A kept attachment, as listed by `/attachments`.
+#[derive(Debug)]
+pub(crate) struct Entry {
+    pub(crate) filename: String,
+    pub(crate) content_type: String,
+    pub(crate) size: u64,
+    pub(crate) timestamp: i64,
+}
+
+/// Where kept attachments live.
+pub(crate) fn store_dir() -> PathBuf {
+    crate::memory::config_dir().join("attachments")
+}
+
+pub(crate) fn sender_dir(sender: &str) -> PathBuf {
+    store_dir().join(crate::memory::hash_sender(sender))
+}
+
+/// Whether `path` is in the store (and so outlives the message).
+pub(crate) fn is_kept(path: &Path) -> bool {
+    path.starts_with(store_dir())
+}
+
+/// Move a screened download into `sender`'s store and record it. Returns
+/// where the file is now; the temp path when retention is off or the move
+/// fails.
+pub(crate) fn keep(state: &State, sender: &str, att: &AttachmentInfo, tmp: PathBuf) -> PathBuf {
+    let retention = &state.config.attachment_retention;
+    if !retention.enabled() || crate::privacy::is_private(state, sender) {
+        return tmp;
+    }
+    let dir = sender_dir(sender);
+    let Some(name) = tmp.file_name() else {
+        return tmp;
+    };
+    let kept = dir.join(name);
+    // Make room first, so the new file can't be the one evicted.
+    if let Err(e) = evict(retention) {
+        warn!("Attachment eviction failed: {e}");
+    }
+    let moved = std::fs::create_dir_all(&dir).and_then(|()| {
+        std::fs::rename(&tmp, &kept).or_else(|_| {
+            std::fs::copy(&tmp, &kept)?;
+            std::fs::remove_file(&tmp)
+        })
+    });
+    if let Err(e) = moved {
+        warn!(sender = %sender, "Could not keep attachment {}: {e}", att.id);
+        return tmp;
+    }
+    let size = std::fs::metadata(&kept).map(|m| m.len()).unwrap_or(0);
+    let filename = att
+        .filename
+        .clone()
+        .unwrap_or_else(|| name.to_string_lossy().into_owned());
+    match crate::memory::open_memory_db(sender) {
+        Ok(conn) => record(&conn, &filename, &att.content_type, &kept, size),
+        Err(e) => error!("Failed to open memory DB: {e}"),
+    }
+    kept
+}
+
+fn record(conn: &Connection, filename: &str, content_type: &str, path: &Path, size: u64) {
+    if let Err(e) = conn.execute(
+        "INSERT INTO attachments (filename, content_type, path, size, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            filename,
+            content_type,
+            path.to_string_lossy(),
+            size as i64,
+            crate::helpers::epoch_now()
+        ],
+    ) {
+        error!("Failed to record attachment: {e}");
+    }
+}
+
+/// `sender`'s kept attachments, newest first. Entries whose file has been
+/// evicted are dropped from the registry on the way.
+pub(crate) fn list(sender: &str) -> Vec<Entry> {
+    let Ok(conn) = crate::memory::open_memory_db(sender) else {
+        return Vec::new();
+    };
+    let Ok(mut stmt) = conn.prepare(
+        "SELECT id, filename, content_type, path, size, timestamp FROM attachments
+         ORDER BY timestamp DESC, id DESC",
+    ) else {
+        return Vec::new();
+    };
+    let rows: Vec<(i64, Entry, String)> = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                Entry {
+                    filename: row.get(1)?,
+                    content_type: row.get(2)?,
+                    size: row.get::<_, i64>(4)? as u64,
+                    timestamp: row.get(5)?,
+                },
+                row.get(3)?,
+            ))
+        })
+        .ok()
+        .map(|iter| iter.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default();
+    let mut entries = Vec::new();
+    for (id, entry, path) in rows {
+        if Path::new(&path).exists() {
+            entries.push(entry);
+        } else {
+            let _ = conn.execute("DELETE FROM attachments WHERE id = ?1", [id]);
+        }
+    }
+    entries
+}
+
+/// `sender`'s newest kept attachment that's still there, as (filename, path).
+pub(crate) fn latest(sender: &str) -> Option<(String, PathBuf)> {
+    let conn = crate::memory::open_memory_db(sender).ok()?;
+    let mut stmt = conn
+        .prepare("SELECT filename, path FROM attachments ORDER BY timestamp DESC, id DESC")
+        .ok()?;
+    let rows: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .ok()?
+        .filter_map(|r| r.ok())
+        .collect();
+    rows.into_iter()
+        .map(|(filename, path)| (filename, PathBuf::from(path)))
+        .find(|(_, path)| path.exists())
+}
+
+/// Every file in the store at `root` with its size and modification time.
+fn stored_files(root: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(senders) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    senders
+        .filter_map(|e| e.ok())
+        .filter_map(|dir| std::fs::read_dir(dir.path()).ok())
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let meta = e.metadata().ok().filter(|m| m.is_file())?;
+            Some((e.path(), meta.len(), meta.modified().ok()?))
+        })
+        .collect()
+}
+
+/// Files and bytes in the store.
+pub(crate) fn usage() -> (usize, u64) {
+    let files = stored_files(&store_dir());
+    (files.len(), files.iter().map(|(_, size, _)| size).sum())
+}
+
+/// Delete kept files past their age, then the oldest until the store fits
+/// its size limit. Returns how many went and their size in bytes.
+pub(crate) fn evict(retention: &Retention) -> Result<(usize, u64), String> {
+    evict_in(&store_dir(), retention.max_age(), retention.max_bytes())
+}
+
+fn evict_in(root: &Path, max_age: Duration, max_bytes: u64) -> Result<(usize, u64), String> {
+    let mut files = stored_files(root);
+    files.sort_by_key(|(_, _, modified)| *modified);
+    let now = SystemTime::now();
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    let (mut removed, mut bytes) = (0, 0);
+    for (path, size, modified) in files {
+        let expired = now.duration_since(modified).is_ok_and(|age| age > max_age);
+        if !expired && total <= max_bytes {
+            // Sorted oldest first: nothing newer is past its age either.
+            break;
+        }
+        std::fs::remove_file(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        total -= size;
+        removed += 1;
+        bytes += size;
+    }
+    Ok((removed, bytes))
+}
+
+/// `/attachments`: what the sender has sent that's still kept.
+pub(crate) fn describe(state: &State, sender: &str) -> String {
+    let retention = &state.config.attachment_retention;
+    if !retention.enabled() {
+        return "Attachments aren't kept: files are deleted once Claude has seen them (--attachment-retention-days 0).".to_string();
+    }
+    let entries = list(sender);
+    let policy = format!(
+        "Kept for {} days, {} MB for everyone in total.",
+        retention.max_age_days, retention.max_mb
+    );
+    if entries.is_empty() {
+        return format!("No attachments kept for you. {policy}");
+    }
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    let mut lines = vec![format!(
+        "Your attachments ({}, {}):",
+        entries.len(),
+        crate::helpers::format_bytes(total)
+    )];
+    for entry in &entries {
+        lines.push(format!(
+            "  {} ({}, {}) {}",
+            entry.filename,
+            entry.content_type,
+            crate::helpers::format_bytes(entry.size),
+            crate::memory::format_epoch(entry.timestamp.max(0) as u64)
+        ));
+    }
+    lines.push(policy);
+    lines.join("\n")
+}
+
+/// Store usage and limits for the stats endpoint.
+pub(crate) fn stats_json(state: &State) -> serde_json::Value {
+    let retention = &state.config.attachment_retention;
+    let (files, bytes) = usage();
+    serde_json::json!({
+        "files": files,
+        "bytes": bytes,
+        "retention_days": retention.max_age_days,
+        "max_mb": retention.max_mb,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention_enabled() {
+        assert!(!Retention::default().enabled());
+        assert_eq!(Retention::default().max_bytes(), u64::MAX);
+        let retention = Retention {
+            max_age_days: 7,
+            max_mb: 1,
+        };
+        assert!(retention.enabled());
+        assert_eq!(retention.max_bytes(), 1024 * 1024);
+        assert_eq!(retention.max_age(), Duration::from_secs(7 * 86400));
+    }
+
+    #[test]
+    fn test_evict_by_age_then_size() {
+        let root = std::env::temp_dir().join(format!("ccchat-att-{}", uuid::Uuid::new_v4()));
+        let dir = root.join("sender");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("stale.jpg"), b"12345").unwrap();
+        std::thread::sleep(Duration::from_millis(60));
+        std::fs::write(dir.join("older.pdf"), b"1234").unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        std::fs::write(dir.join("newer.pdf"), b"1234").unwrap();
+
+        // Only the stale file is past its age while everything fits.
+        let (removed, bytes) = evict_in(&root, Duration::from_millis(40), 100).unwrap();
+        assert_eq!((removed, bytes), (1, 5));
+        // Over the size limit, the oldest goes first.
+        let (removed, _) = evict_in(&root, Duration::from_secs(60), 6).unwrap();
+        assert_eq!(removed, 1);
+        assert!(!dir.join("older.pdf").exists());
+        assert!(dir.join("newer.pdf").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -3,18 +3,8 @@ use tracing::error;
 
 use crate::error::AppError;
 
-fn config_dir() -> std::path::PathBuf {
-    let dir = std::env::var("HOME")
-        .map(std::path::PathBuf::from)
-        .unwrap_or_else(|_| std::path::PathBuf::from("."))
-        .join(".config")
-        .join("ccchat");
-    let _ = std::fs::create_dir_all(&dir);
-    dir
-}
-
 pub(crate) fn open_audit_db() -> Result<Connection, AppError> {
-    let path = config_dir().join("audit.db");
+    let path = crate::memory::config_dir().join("audit.db");
     let conn = Connection::open(&path)?;
     conn.execute_batch("PRAGMA journal_mode=WAL;")?;
     conn.execute_batch(
@@ -1,10 +1,17 @@
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tracing::{error, info};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
 
-use crate::memory::{open_memory_db, purge_old_messages, reload_config_full, save_memory};
+use crate::constants::{REPLY_SLO_CHECK_SECS, REPLY_SLO_MIN_SAMPLES, TAIL_BATCH_INTERVAL_SECS};
+
+use crate::memory::{
+    load_group_projects, load_model_router, load_resource_limits, load_sender_rate_limits,
+    reload_config_full,
+};
 use crate::state::State;
-use crate::{audit, commands, webhook};
+use crate::{audit, commands, lifecycle, logtail, ratelimit, webhook};
 
 pub(crate) fn spawn_session_reaper(state: &Arc<State>, ttl: Duration) {
     let state = Arc::clone(state);
@@ -21,23 +28,146 @@ pub(crate) fn spawn_session_reaper(state: &Arc<State>, ttl: Duration) {
                 .collect();
             for (sender, session_id) in &expired {
                 info!(sender = %sender, "Session expired by TTL reaper");
-                if let Some(summary) = state
-                    .claude_runner
-                    .summarize_session(session_id, &state.config.model)
-                    .await
-                {
-                    save_memory(sender, &summary);
-                    info!(sender = %sender, "Saved memory on expiry");
+                let summarized = state.archive_expired_session(sender, session_id).await;
+                commands::remove_session_workdir(sender, session_id);
+                if summarized {
+                    crate::starters::offer(&state, sender).await;
                 }
-                if let Ok(conn) = open_memory_db(sender) {
-                    purge_old_messages(&conn, 30);
+            }
+            state.named_sessions.retain(|(sender, name), session| {
+                let alive = now.duration_since(session.last_activity) <= ttl;
+                if !alive {
+                    info!(sender = %sender, name = %name, "Named session expired by TTL reaper");
+                    commands::remove_session_workdir(sender, &session.session_id);
                 }
-                state.session_mgr.sessions.remove(sender);
+                alive
+            });
+        }
+    });
+}
+
+/// Cancel runs that have shown no progress for `--stuck-run-mins` and free
+/// their sessions.
+pub(crate) fn spawn_stuck_run_watchdog(state: &Arc<State>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            let limit = state.config.stuck_after;
+            let stuck: Vec<String> = state
+                .run_progress
+                .iter()
+                .filter(|entry| entry.value().elapsed() > limit)
+                .map(|entry| entry.key().clone())
+                .collect();
+            for sender in stuck {
+                warn!(sender = %sender, "Claude run made no progress for {}s, cancelling", limit.as_secs());
+                state.unstick(&sender);
+                let _ = state
+                    .send_message(
+                        &sender,
+                        &format!(
+                            "Your request made no progress for {} minutes, so I cancelled it. Send it again to retry.",
+                            limit.as_secs() / 60
+                        ),
+                    )
+                    .await;
+            }
+        }
+    });
+}
+
+/// Upload an encrypted archive every `--archive-every-hours`.
+pub(crate) fn spawn_archive_loop(state: &Arc<State>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let Some(config) = state.config.archive.clone() else {
+            return;
+        };
+        info!(
+            "Archiving to {}/{} every {}h",
+            config.bucket,
+            config.prefix,
+            config.every.as_secs() / 3600
+        );
+        loop {
+            tokio::time::sleep(config.every).await;
+            let root = crate::memory::config_dir();
+            if let Err(e) = crate::archive::push(
+                &state.http,
+                &config,
+                &root,
+                crate::constants::BUNDLE_KDF_ITERATIONS,
+            )
+            .await
+            {
+                warn!("Archive upload failed: {e}");
+            }
+        }
+    });
+}
+
+/// Run `--maintenance-tasks` every day at `--maintenance-at` (local time).
+pub(crate) fn spawn_maintenance_loop(state: &Arc<State>, at: chrono::NaiveTime) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        info!(
+            "Maintenance daily at {} ({})",
+            at.format("%H:%M"),
+            crate::maintenance::task_list(&state.config.maintenance_tasks)
+        );
+        loop {
+            let wait = crate::maintenance::until_next(at, chrono::Local::now().naive_local());
+            tokio::time::sleep(wait).await;
+            crate::maintenance::run(&state, &state.config.maintenance_tasks).await;
+        }
+    });
+}
+
+/// Tell the admin when p95 reply time goes over `--reply-slo-ms`, and again
+/// once it's back under.
+pub(crate) fn spawn_reply_slo_monitor(state: &Arc<State>, slo_ms: u64) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(REPLY_SLO_CHECK_SECS)).await;
+            if let Some(alert) = check_reply_slo(&state, slo_ms) {
+                let _ = state.send_message(&state.config.account, &alert).await;
             }
         }
     });
 }
 
+/// The admin alert due for the current reply p95, if the SLO was just
+/// breached or just recovered.
+pub(crate) fn check_reply_slo(state: &State, slo_ms: u64) -> Option<String> {
+    let samples = state.metrics.reply_sample_count();
+    if samples < REPLY_SLO_MIN_SAMPLES {
+        return None;
+    }
+    let p95 = state.metrics.reply_percentile(0.95);
+    let over = p95 > slo_ms;
+    if state.metrics.slo_breached.swap(over, Ordering::Relaxed) == over {
+        return None;
+    }
+    let secs = |ms: u64| ms as f64 / 1000.0;
+    Some(if over {
+        warn!(p95, slo_ms, "Reply time SLO breached");
+        format!(
+            "⚠️ Replies are slow: p95 is {:.1}s over the last {samples} (SLO {:.1}s).",
+            secs(p95),
+            secs(slo_ms)
+        )
+    } else {
+        info!(p95, slo_ms, "Reply time back within SLO");
+        format!(
+            "✅ Reply times are back within the SLO: p95 {:.1}s (SLO {:.1}s).",
+            secs(p95),
+            secs(slo_ms)
+        )
+    })
+}
+
 #[cfg(unix)]
 pub(crate) fn spawn_sighup_handler(state: &Arc<State>) {
     let state = Arc::clone(state);
@@ -50,10 +180,30 @@ pub(crate) fn spawn_sighup_handler(state: &Arc<State>) {
             let (added, removed) = reload_config_full(
                 state.config.config_path.as_deref(),
                 &state.config.account,
-                &state.allowed_ids,
+                &state.access.allowed_ids,
                 &state.runtime_system_prompt,
                 &state.sender_prompts,
             );
+            state.group_projects.clear();
+            for (chat_id, dir) in load_group_projects(state.config.config_path.as_deref()) {
+                if let Err(e) = state.tool_policies.load(&dir) {
+                    warn!(
+                        "Claude won't run in {} until its tool policy is fixed: {e}",
+                        dir.display()
+                    );
+                }
+                state.group_projects.insert(chat_id, dir);
+            }
+            state.sender_rate_limits.clear();
+            for (key, limit) in load_sender_rate_limits(state.config.config_path.as_deref()) {
+                state.sender_rate_limits.insert(key, limit);
+            }
+            if let Ok(mut limits) = state.resource_limits.write() {
+                *limits = load_resource_limits(state.config.config_path.as_deref());
+            }
+            if let Ok(mut router) = state.model_router.write() {
+                *router = load_model_router(state.config.config_path.as_deref());
+            }
             audit::log_action("config_reload", "", &format!("+{added} -{removed}"));
             info!("Config reloaded: +{added} -{removed} senders");
         }
@@ -68,16 +218,7 @@ pub(crate) fn spawn_shutdown_handler(state: &Arc<State>) {
             tokio::signal::ctrl_c()
                 .await
                 .expect("Failed to listen for ctrl-c");
-            audit::log_action("shutdown", "", "graceful");
-            webhook::fire_if_configured(&state.config.webhook_url, "shutdown", "", "graceful");
-            info!("Shutdown signal received, saving active sessions...");
-            match tokio::time::timeout(Duration::from_secs(30), state.shutdown_save_sessions())
-                .await
-            {
-                Ok(()) => info!("Shutdown complete, all sessions saved"),
-                Err(_) => error!("Shutdown timed out after 30s"),
-            }
-            std::process::exit(0);
+            shutdown(&state, "SIGINT").await;
         });
     }
 
@@ -89,19 +230,28 @@ pub(crate) fn spawn_shutdown_handler(state: &Arc<State>) {
             let mut sig = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
                 .expect("Failed to register SIGTERM handler");
             sig.recv().await;
-            audit::log_action("shutdown", "", "SIGTERM");
-            info!("SIGTERM received, saving active sessions...");
-            match tokio::time::timeout(Duration::from_secs(30), state.shutdown_save_sessions())
-                .await
-            {
-                Ok(()) => info!("Shutdown complete, all sessions saved"),
-                Err(_) => error!("Shutdown timed out after 30s"),
-            }
-            std::process::exit(0);
+            shutdown(&state, "SIGTERM").await;
         });
     }
 }
 
+/// Persist state, save active sessions and exit.
+pub(crate) async fn shutdown(state: &State, reason: &str) -> ! {
+    audit::log_action("shutdown", "", reason);
+    webhook::notify(state, "shutdown", "", reason);
+    ratelimit::persist(state);
+    lifecycle::announce_offline(state, reason).await;
+    crate::profile::mark_offline(state).await;
+    info!("Shutting down ({reason}), saving active sessions...");
+    match tokio::time::timeout(Duration::from_secs(30), state.shutdown_save_sessions()).await {
+        Ok(()) => info!("Shutdown complete, all sessions saved"),
+        Err(_) => error!("Shutdown timed out after 30s"),
+    }
+    crate::memory::lock::flush();
+    crate::instance::release();
+    std::process::exit(0);
+}
+
 pub(crate) fn spawn_retry_loop(state: &Arc<State>) {
     let state = Arc::clone(state);
     tokio::spawn(async move {
@@ -112,6 +262,77 @@ pub(crate) fn spawn_retry_loop(state: &Arc<State>) {
     });
 }
 
+/// Pick up the prompts the last process didn't finish. The journal is read
+/// before anything new is journaled; senders are told (or the prompts re-run)
+/// in the background.
+pub(crate) fn spawn_journal_recovery(state: &Arc<State>) {
+    let lost = crate::journal::recover();
+    if lost.is_empty() {
+        return;
+    }
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        commands::recover_interrupted(&state, lost).await;
+    });
+}
+
+/// Save token bucket levels every minute so a crash loses little.
+pub(crate) fn spawn_rate_limit_persist_loop(state: &Arc<State>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            if !state.rate_limits.is_empty() {
+                ratelimit::persist(&state);
+            }
+        }
+    });
+}
+
+/// Ask senders with a locked memory to unlock it (nothing can read it after
+/// a restart), then keep sealing unlocked memories back to disk.
+pub(crate) fn spawn_memory_lock_loop(state: &Arc<State>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let locked: Vec<String> = state
+            .access
+            .allowed_ids
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|sender| crate::memory::lock::is_locked(sender))
+            .collect();
+        for sender in locked {
+            let _ = state
+                .send_message(
+                    &sender,
+                    "I restarted, so your memory is locked again. Send /memory unlock <passphrase> to pick up where we left off.",
+                )
+                .await;
+        }
+        loop {
+            tokio::time::sleep(Duration::from_secs(crate::constants::MEMORY_SEAL_SECS)).await;
+            let sealed = tokio::task::spawn_blocking(crate::memory::lock::flush)
+                .await
+                .unwrap_or_default();
+            if sealed > 0 {
+                debug!(count = sealed, "Sealed locked memories");
+            }
+        }
+    });
+}
+
+/// Check every model in use answers, at startup and then every `every`.
+pub(crate) fn spawn_probe_loop(state: &Arc<State>, every: Duration) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        loop {
+            let down = crate::probe::probe_all(&state).await;
+            debug!(down, "Probed models");
+            tokio::time::sleep(every).await;
+        }
+    });
+}
+
 pub(crate) fn spawn_reminder_loop(state: &Arc<State>) {
     let state = Arc::clone(state);
     tokio::spawn(async move {
@@ -122,6 +343,18 @@ pub(crate) fn spawn_reminder_loop(state: &Arc<State>) {
     });
 }
 
+/// Runs due `/followup`s. Separate from the reminder loop, since a prompt
+/// can take minutes.
+pub(crate) fn spawn_followup_loop(state: &Arc<State>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            commands::deliver_due_followups(&state).await;
+        }
+    });
+}
+
 pub(crate) fn spawn_cron_loop(state: &Arc<State>) {
     let state = Arc::clone(state);
     tokio::spawn(async move {
@@ -132,10 +365,65 @@ pub(crate) fn spawn_cron_loop(state: &Arc<State>) {
     });
 }
 
-pub(crate) async fn spawn_stats_server(state: &Arc<State>, port: u16) {
-    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+pub(crate) fn spawn_feed_loop(state: &Arc<State>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            commands::deliver_due_feeds(&state).await;
+            commands::deliver_due_reports(&state).await;
+        }
+    });
+}
+
+/// Keep the `/gh` digest warm so questions don't wait on the GitHub API.
+pub(crate) fn spawn_github_poll_loop(state: &Arc<State>) {
+    let state = Arc::clone(state);
+    let every = Duration::from_secs(state.config.github.poll_mins * 60);
+    tokio::spawn(async move {
+        loop {
+            match crate::github::fetch_digest(&state.http, &state.config.github).await {
+                Ok(digest) => {
+                    if let Ok(mut cached) = state.github_digest.write() {
+                        *cached = Some(digest);
+                    }
+                }
+                Err(e) => warn!("GitHub digest refresh failed: {e}"),
+            }
+            tokio::time::sleep(every).await;
+        }
+    });
+}
+
+/// Forward tailed log events to the admin every few seconds while `/tail` is on.
+pub(crate) fn spawn_log_tail_forwarder(state: &Arc<State>, mut rx: mpsc::Receiver<String>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(TAIL_BATCH_INTERVAL_SECS)).await;
+            logtail::flush_batch(&state, &mut rx).await;
+        }
+    });
+}
+
+/// Tell the account owner when signal-cli-api fails over to a standby.
+pub(crate) fn spawn_failover_alerts(state: &Arc<State>, mut rx: mpsc::UnboundedReceiver<String>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        while let Some(notice) = rx.recv().await {
+            let _ = state.send_message(&state.config.account, &notice).await;
+        }
+    });
+}
+
+pub(crate) async fn spawn_stats_server(
+    state: &Arc<State>,
+    addr: std::net::SocketAddr,
+    token: Option<String>,
+) {
+    let listener = tokio::net::TcpListener::bind(addr)
         .await
         .expect("Failed to bind stats port");
     let state = Arc::clone(state);
-    tokio::spawn(crate::stats::run_stats_server(listener, state));
+    tokio::spawn(crate::stats::run_stats_server(listener, state, token));
 }
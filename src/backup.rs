@@ -0,0 +1,335 @@
+//! Local snapshots of the data directory (`backup` maintenance task,
+//! `ccchat backup now|list|restore`).
+//!
+//! A snapshot is the whole data directory (memory, ledger, schedules, the
+//! allowed list, kept attachments) as a zstd-compressed tar, written to
+//! `--backup-dir` as `ccchat-<UTC timestamp>.tar.zst`. Databases are copied
+//! with `VACUUM INTO`, so a snapshot taken while ccchat runs is consistent.
+//! With `--backup-passphrase` the archive is sealed the way `sessions
+//! export` bundles are and named `.tar.zst.enc`. After each snapshot the
+//! rotation keeps the newest one of each of the last `--backup-keep-daily`
+//! days and `--backup-keep-weekly` ISO weeks.
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+
+/// Start of a sealed snapshot.
+const MAGIC: &[u8; 8] = b"CCCHATS1";
+const PREFIX: &str = "ccchat-";
+const SUFFIX: &str = ".tar.zst";
+const SEALED_SUFFIX: &str = ".tar.zst.enc";
+const STAMP: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Clone, Debug)]
+pub(crate) struct BackupConfig {
+    pub(crate) dir: PathBuf,
+    /// Seal snapshots with this passphrase (None: plain `.tar.zst`).
+    pub(crate) passphrase: Option<String>,
+    /// Days, newest first, whose last snapshot is kept.
+    pub(crate) keep_daily: usize,
+    /// ISO weeks, newest first, whose last snapshot is kept.
+    pub(crate) keep_weekly: usize,
+}
+
+/// When a snapshot file was taken, from its name.
+fn taken_at(name: &str) -> Option<NaiveDateTime> {
+    let stamp = name
+        .strip_prefix(PREFIX)?
+        .strip_suffix(SEALED_SUFFIX)
+        .or_else(|| name.strip_prefix(PREFIX)?.strip_suffix(SUFFIX))?;
+    NaiveDateTime::parse_from_str(stamp, STAMP).ok()
+}
+
+/// Snapshots in `dir`, oldest first.
+pub(crate) fn list(dir: &Path) -> Result<Vec<(PathBuf, NaiveDateTime)>, AppError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("{}: {e}", dir.display()).into()),
+    };
+    let mut snapshots: Vec<(PathBuf, NaiveDateTime)> = entries
+        .flatten()
+        .filter_map(|e| {
+            let at = taken_at(&e.file_name().to_string_lossy())?;
+            Some((e.path(), at))
+        })
+        .collect();
+    snapshots.sort_by_key(|(_, at)| *at);
+    Ok(snapshots)
+}
+
+/// Files under `dir` to put in a snapshot, skipping `skip` (the backup
+/// directory) and SQLite side files.
+fn files_under(dir: &Path, skip: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path == skip {
+            continue;
+        }
+        if path.is_dir() {
+            files_under(&path, skip, out)?;
+        } else if path.is_file() {
+            let name = path.to_string_lossy();
+            if !name.ends_with("-wal") && !name.ends_with("-shm") && !name.ends_with("-journal") {
+                out.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The tar.zst of everything under `root` but `skip`, and its file count.
+fn archive(root: &Path, skip: &Path) -> Result<(Vec<u8>, usize), AppError> {
+    let mut files = Vec::new();
+    files_under(root, skip, &mut files)?;
+    let scratch = std::env::temp_dir().join(format!("ccchat_backup_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&scratch)?;
+    let result = (|| {
+        let encoder = zstd::Encoder::new(Vec::new(), 3)?;
+        let mut tar = tar::Builder::new(encoder);
+        for (i, path) in files.iter().enumerate() {
+            let rel = path
+                .strip_prefix(root)
+                .map_err(|_| AppError::from("Backup path escaped the data dir"))?;
+            if path.extension().is_some_and(|ext| ext == "db") {
+                // A consistent copy, even with ccchat writing to it
+                let copy = scratch.join(format!("{i}.db"));
+                let conn = rusqlite::Connection::open(path)?;
+                conn.execute("VACUUM INTO ?1", [copy.to_string_lossy()])?;
+                tar.append_path_with_name(&copy, rel)?;
+            } else {
+                tar.append_path_with_name(path, rel)?;
+            }
+        }
+        let bytes = tar.into_inner()?.finish()?;
+        Ok::<_, AppError>((bytes, files.len()))
+    })();
+    let _ = std::fs::remove_dir_all(&scratch);
+    result
+}
+
+/// Snapshot `root` into `config.dir`, then apply the rotation. Returns the
+/// snapshot's path and how many files it holds.
+pub(crate) fn snapshot(
+    root: &Path,
+    config: &BackupConfig,
+    iterations: u32,
+    now: DateTime<Utc>,
+) -> Result<(PathBuf, usize), AppError> {
+    std::fs::create_dir_all(&config.dir)?;
+    let skip = config
+        .dir
+        .canonicalize()
+        .unwrap_or_else(|_| config.dir.clone());
+    let root = root.canonicalize()?;
+    let (mut bytes, count) = archive(&root, &skip)?;
+    let stamp = now.format(STAMP);
+    let name = match &config.passphrase {
+        Some(passphrase) => {
+            bytes = crate::bundle::seal(MAGIC, bytes, passphrase, iterations)?;
+            format!("{PREFIX}{stamp}{SEALED_SUFFIX}")
+        }
+        None => format!("{PREFIX}{stamp}{SUFFIX}"),
+    };
+    let path = config.dir.join(name);
+    let partial = path.with_extension("partial");
+    let mut file = std::fs::File::create(&partial)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&partial, &path)?;
+    rotate(&config.dir, config.keep_daily, config.keep_weekly)?;
+    Ok((path, count))
+}
+
+/// Snapshots the rotation would delete: all but the newest of each of the
+/// last `keep_daily` days and `keep_weekly` ISO weeks. Keeps everything
+/// when both are 0.
+fn expired(
+    snapshots: &[(PathBuf, NaiveDateTime)],
+    keep_daily: usize,
+    keep_weekly: usize,
+) -> Vec<PathBuf> {
+    if keep_daily == 0 && keep_weekly == 0 {
+        return Vec::new();
+    }
+    let mut kept = BTreeSet::new();
+    let mut days = BTreeSet::new();
+    let mut weeks = BTreeSet::new();
+    for (path, at) in snapshots.iter().rev() {
+        let day = at.date();
+        let week = (day.iso_week().year(), day.iso_week().week());
+        if days.len() < keep_daily && days.insert(day) {
+            kept.insert(path.clone());
+        }
+        if weeks.len() < keep_weekly && weeks.insert(week) {
+            kept.insert(path.clone());
+        }
+    }
+    snapshots
+        .iter()
+        .map(|(path, _)| path.clone())
+        .filter(|path| !kept.contains(path))
+        .collect()
+}
+
+/// Delete the snapshots in `dir` the rotation no longer keeps; returns how many.
+pub(crate) fn rotate(dir: &Path, keep_daily: usize, keep_weekly: usize) -> Result<usize, AppError> {
+    let expired = expired(&list(dir)?, keep_daily, keep_weekly);
+    for path in &expired {
+        std::fs::remove_file(path)?;
+    }
+    Ok(expired.len())
+}
+
+/// Unpack `snapshot` into `root`. Refuses to overwrite an existing data
+/// directory (one with `allowed.json`) unless `force`. Returns the number
+/// of files restored.
+pub(crate) fn restore(
+    snapshot: &Path,
+    root: &Path,
+    passphrase: Option<&str>,
+    force: bool,
+) -> Result<usize, AppError> {
+    let mut bytes = std::fs::read(snapshot)
+        .map_err(|e| AppError::from(format!("{}: {e}", snapshot.display())))?;
+    if crate::bundle::is_sealed(MAGIC, &bytes) {
+        let passphrase = passphrase.ok_or("This snapshot is encrypted; set --backup-passphrase")?;
+        bytes = crate::bundle::open(MAGIC, &bytes, passphrase)?;
+    }
+    if !force && root.join("allowed.json").exists() {
+        return Err(format!(
+            "{} already has ccchat data; stop ccchat and rerun with --force to replace it",
+            root.display()
+        )
+        .into());
+    }
+    std::fs::create_dir_all(root)?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(bytes.as_slice())?);
+    let mut count = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let rel = entry.path()?.into_owned();
+        // Stale WAL files would be replayed over the restored database.
+        for suffix in ["-wal", "-shm"] {
+            let mut side = root.join(&rel).into_os_string();
+            side.push(suffix);
+            let _ = std::fs::remove_file(side);
+        }
+        if entry.unpack_in(root)? {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ccchat_backup_{name}_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_taken_at() {
+        assert_eq!(
+            taken_at("ccchat-20260301T033000Z.tar.zst"),
+            Some(at("2026-03-01 03:30"))
+        );
+        assert_eq!(
+            taken_at("ccchat-20260301T033000Z.tar.zst.enc"),
+            Some(at("2026-03-01 03:30"))
+        );
+        assert_eq!(taken_at("ccchat-20260301T033000Z.tar.zst.partial"), None);
+        assert_eq!(taken_at("notes.txt"), None);
+    }
+
+    #[test]
+    fn test_rotation_keeps_daily_and_weekly() {
+        // Two a day for three weeks of March 2026 (Mondays: 2nd, 9th, 16th)
+        let mut snapshots = Vec::new();
+        for day in 1..=21 {
+            for hour in ["03:30", "15:00"] {
+                let stamp = format!("2026-03-{day:02} {hour}");
+                snapshots.push((PathBuf::from(&stamp), at(&stamp)));
+            }
+        }
+        let expired = expired(&snapshots, 3, 2);
+        let kept: Vec<&str> = snapshots
+            .iter()
+            .map(|(p, _)| p.to_str().unwrap())
+            .filter(|p| !expired.iter().any(|e| e.to_str() == Some(p)))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                "2026-03-15 15:00",
+                "2026-03-19 15:00",
+                "2026-03-20 15:00",
+                "2026-03-21 15:00"
+            ]
+        );
+        assert!(super::expired(&snapshots, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_and_restore_round_trip() {
+        let root = temp_dir("root");
+        std::fs::create_dir_all(root.join("memories")).unwrap();
+        std::fs::write(root.join("allowed.json"), r#"{"allowed":[]}"#).unwrap();
+        let conn = rusqlite::Connection::open(root.join("memories/abc.db")).unwrap();
+        conn.execute_batch("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('kept');")
+            .unwrap();
+        let config = BackupConfig {
+            // Inside the data dir, as by default; it mustn't back itself up
+            dir: root.join("backups"),
+            passphrase: Some("correct horse".to_string()),
+            keep_daily: 7,
+            keep_weekly: 0,
+        };
+        let utc = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+        let (first, count) = snapshot(&root, &config, 1_000, utc("2026-03-01T03:30:00Z")).unwrap();
+        assert_eq!(count, 2);
+        assert!(first.to_string_lossy().ends_with(SEALED_SUFFIX));
+        // Same day: only the newest is kept
+        let (second, _) = snapshot(&root, &config, 1_000, utc("2026-03-01T15:00:00Z")).unwrap();
+        let kept: Vec<PathBuf> = list(&config.dir)
+            .unwrap()
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect();
+        assert_eq!(kept, std::slice::from_ref(&second));
+
+        assert!(restore(&second, &root, Some("correct horse"), false).is_err());
+        assert!(restore(&second, &temp_dir("x"), None, false).is_err());
+        let target = temp_dir("target");
+        assert_eq!(
+            restore(&second, &target, Some("correct horse"), false).unwrap(),
+            2
+        );
+        let restored = rusqlite::Connection::open(target.join("memories/abc.db")).unwrap();
+        let value: String = restored
+            .query_row("SELECT v FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(value, "kept");
+        assert!(!target.join("backups").exists());
+        for dir in [root, target] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
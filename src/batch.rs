@@ -0,0 +1,244 @@
+//! `ccchat run-batch`: prompts from a JSON-lines file, run one after another
+//! without any chat transport, for scheduled jobs on the same machine.
+//!
+//! Each input line is `{"prompt": "..."}`, optionally with an `id` to match
+//! results up by (the line number otherwise) and a `model`. Results are
+//! written as they finish, one JSON line per prompt, so a batch cut short
+//! still leaves what it got. Every run is capped by `--max-budget` and
+//! recorded in the usage ledger as [`LEDGER_SENDER`], so it shows up in
+//! `ccchat costs`; `--budget` caps the batch as a whole.
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+use crate::traits::{ClaudeRunner, RunOptions};
+
+/// Who batch runs are charged to in the usage ledger.
+pub(crate) const LEDGER_SENDER: &str = "batch";
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Job {
+    #[serde(default)]
+    id: Option<String>,
+    prompt: String,
+    #[serde(default)]
+    model: Option<String>,
+}
+
+/// One result line.
+#[derive(Serialize, Debug)]
+struct Outcome<'a> {
+    id: &'a str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    cost_usd: f64,
+}
+
+/// How a batch runs.
+pub(crate) struct Options {
+    pub(crate) model: String,
+    /// Cap per prompt (USD).
+    pub(crate) max_budget: f64,
+    /// Cap for the whole batch (USD); prompts after it's spent are skipped.
+    pub(crate) budget: Option<f64>,
+    pub(crate) work_dir: PathBuf,
+}
+
+/// Prompts in `text`, with their ids. Blank lines are skipped.
+fn parse_jobs(text: &str) -> Result<Vec<(String, Job)>, String> {
+    let mut jobs = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let job: Job = serde_json::from_str(line).map_err(|e| format!("Line {}: {e}", i + 1))?;
+        if job.prompt.trim().is_empty() {
+            return Err(format!("Line {}: empty prompt", i + 1));
+        }
+        let id = job.id.clone().unwrap_or_else(|| (i + 1).to_string());
+        jobs.push((id, job));
+    }
+    Ok(jobs)
+}
+
+/// What a finished batch did.
+#[derive(Debug, Default, PartialEq)]
+struct Summary {
+    ran: usize,
+    failed: usize,
+    skipped: usize,
+    cost_usd: f64,
+}
+
+async fn run_jobs(
+    runner: &dyn ClaudeRunner,
+    jobs: &[(String, Job)],
+    options: &Options,
+    out: &mut impl Write,
+) -> Result<Summary, AppError> {
+    let mut summary = Summary::default();
+    for (id, job) in jobs {
+        let spent = options.budget.is_some_and(|b| summary.cost_usd >= b);
+        let outcome = if spent {
+            summary.skipped += 1;
+            Outcome {
+                id,
+                ok: false,
+                reply: None,
+                error: Some("skipped: batch budget spent".to_string()),
+                cost_usd: 0.0,
+            }
+        } else {
+            let session_id = uuid::Uuid::new_v4().to_string();
+            tracing::info!(id = %id, "Running batch prompt");
+            let result = runner
+                .run_claude(
+                    &job.prompt,
+                    &session_id,
+                    job.model.as_deref().unwrap_or(&options.model),
+                    &[],
+                    &options.work_dir,
+                    options.max_budget,
+                    "",
+                    &RunOptions::default(),
+                )
+                .await;
+            summary.ran += 1;
+            match result {
+                Ok(reply) => {
+                    let cost = reply.cost_usd.unwrap_or(0.0);
+                    summary.cost_usd += cost;
+                    crate::ledger::record_run(LEDGER_SENDER, &session_id, None, cost);
+                    Outcome {
+                        id,
+                        ok: true,
+                        reply: Some(reply.text),
+                        error: None,
+                        cost_usd: cost,
+                    }
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    Outcome {
+                        id,
+                        ok: false,
+                        reply: None,
+                        error: Some(e.to_string()),
+                        cost_usd: 0.0,
+                    }
+                }
+            }
+        };
+        writeln!(out, "{}", serde_json::to_string(&outcome)?)?;
+        out.flush()?;
+    }
+    Ok(summary)
+}
+
+/// Results file for `input` when `--output` isn't given.
+pub(crate) fn default_output(input: &Path) -> PathBuf {
+    input.with_extension("results.jsonl")
+}
+
+/// Run every prompt in `input`, writing results to `output`.
+pub(crate) async fn run(
+    runner: &dyn ClaudeRunner,
+    input: &Path,
+    output: &Path,
+    options: &Options,
+) -> Result<String, AppError> {
+    let text = std::fs::read_to_string(input)
+        .map_err(|e| AppError::Other(format!("{}: {e}", input.display())))?;
+    let jobs =
+        parse_jobs(&text).map_err(|e| AppError::Other(format!("{}: {e}", input.display())))?;
+    let mut out = std::fs::File::create(output)
+        .map_err(|e| AppError::Other(format!("{}: {e}", output.display())))?;
+    let summary = run_jobs(runner, &jobs, options, &mut out).await?;
+    let mut line = format!(
+        "Ran {} prompt(s), {} failed, ${:.4}",
+        summary.ran, summary.failed, summary.cost_usd
+    );
+    if summary.skipped > 0 {
+        line.push_str(&format!("; {} skipped over budget", summary.skipped));
+    }
+    Ok(format!("{line}. Results in {}", output.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::{ClaudeReply, MockClaudeRunner};
+
+    fn options(budget: Option<f64>) -> Options {
+        Options {
+            model: "sonnet".to_string(),
+            max_budget: 1.0,
+            budget,
+            work_dir: std::env::temp_dir(),
+        }
+    }
+
+    #[test]
+    fn test_parse_jobs() {
+        let jobs = parse_jobs(
+            "{\"prompt\": \"one\"}\n\n{\"id\": \"nightly\", \"prompt\": \"two\", \"model\": \"haiku\"}\n",
+        )
+        .unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].0, "1");
+        assert_eq!(jobs[1].0, "nightly");
+        assert_eq!(jobs[1].1.model.as_deref(), Some("haiku"));
+
+        assert!(parse_jobs("{\"prompt\": \"ok\"}\nnot json")
+            .unwrap_err()
+            .starts_with("Line 2:"));
+        assert!(parse_jobs("{\"prompt\": \" \"}").is_err());
+        assert_eq!(
+            default_output(Path::new("/tmp/jobs.jsonl")),
+            Path::new("/tmp/jobs.results.jsonl")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_jobs_writes_results_and_stops_at_budget() {
+        let mut claude = MockClaudeRunner::new();
+        claude.expect_run_claude().times(2).returning(
+            |prompt, _, model, _, _, _, _, _| match prompt {
+                "fail" => Err("boom".into()),
+                _ => Ok(ClaudeReply::new(&format!("{model}: {prompt}"), Some(0.6))),
+            },
+        );
+        let jobs = parse_jobs(
+            "{\"prompt\": \"fail\"}\n{\"prompt\": \"hi\", \"model\": \"haiku\"}\n{\"prompt\": \"late\"}",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        let summary = run_jobs(&claude, &jobs, &options(Some(0.5)), &mut out)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                ran: 2,
+                failed: 1,
+                skipped: 1,
+                cost_usd: 0.6,
+            }
+        );
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["ok"], false);
+        assert_eq!(lines[0]["error"], "boom");
+        assert_eq!(lines[1]["reply"], "haiku: hi");
+        assert_eq!(lines[2]["id"], "3");
+        assert!(lines[2]["error"].as_str().unwrap().contains("budget"));
+    }
+}
@@ -0,0 +1,406 @@
+//! Encrypted state bundles for moving a bridge between machines
+//! (`ccchat sessions export|import`).
+//!
+//! A bundle holds the per-sender memory databases (session history,
+//! summaries, pins, model preference), the allowed list with group bindings,
+//! scheduled reminders, rate-limit buckets and the spend ledger behind
+//! budgets, `/cost` and `/report`. The payload is JSON, sealed with
+//! ChaCha20-Poly1305 under a key derived from a passphrase with
+//! PBKDF2-HMAC-SHA256.
+
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::{Component, Path};
+
+use crate::error::AppError;
+
+const MAGIC: &[u8; 8] = b"CCCHATB1";
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 4 + SALT_LEN + NONCE_LEN;
+const BUNDLE_VERSION: u32 = 1;
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Files and directories (relative to the config dir) carried in a bundle.
+const BUNDLED_PATHS: &[&str] = &[
+    "allowed.json",
+    "schedule.db",
+    "rate_limits.db",
+    "usage.db",
+    "memories",
+];
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    created_at: i64,
+    /// Relative path -> base64 file contents.
+    files: BTreeMap<String, String>,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<LessSafeKey, AppError> {
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| AppError::from("Invalid bundle header"))?;
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let unbound = UnboundKey::new(&CHACHA20_POLY1305, &key)
+        .map_err(|_| AppError::from("Failed to initialise bundle cipher"))?;
+    Ok(LessSafeKey::new(unbound))
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), AppError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!("Passphrase must be at least {MIN_PASSPHRASE_LEN} characters").into());
+    }
+    Ok(())
+}
+
+/// Flush a SQLite WAL into the main file so a plain copy is complete.
+fn checkpoint(db: &Path) {
+    if let Ok(conn) = rusqlite::Connection::open(db) {
+        let _ = conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);");
+    }
+}
+
+fn add_file(
+    files: &mut BTreeMap<String, String>,
+    root: &Path,
+    path: &Path,
+) -> Result<(), AppError> {
+    let name = path.to_string_lossy();
+    if name.ends_with("-wal") || name.ends_with("-shm") {
+        return Ok(());
+    }
+    if path.extension().is_some_and(|e| e == "db") {
+        checkpoint(path);
+    }
+    let rel = path
+        .strip_prefix(root)
+        .map_err(|_| AppError::from("Bundle path escaped config dir"))?;
+    let contents = std::fs::read(path)?;
+    files.insert(
+        rel.to_string_lossy().replace('\\', "/"),
+        base64::engine::general_purpose::STANDARD.encode(contents),
+    );
+    Ok(())
+}
+
+fn collect_files(root: &Path) -> Result<BTreeMap<String, String>, AppError> {
+    let mut files = BTreeMap::new();
+    for entry in BUNDLED_PATHS {
+        let path = root.join(entry);
+        if path.is_file() {
+            add_file(&mut files, root, &path)?;
+        } else if path.is_dir() {
+            let mut children: Vec<_> = std::fs::read_dir(&path)?
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .collect();
+            children.sort();
+            for child in children {
+                add_file(&mut files, root, &child)?;
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// A key derived from a passphrase, kept to seal further payloads without
+/// running the KDF again. Each seal still gets a fresh nonce.
+pub(crate) struct SealKey {
+    key: LessSafeKey,
+    salt: [u8; SALT_LEN],
+    iterations: u32,
+}
+
+impl SealKey {
+    /// Derive a key from `passphrase` under a new random salt.
+    pub(crate) fn new(passphrase: &str, iterations: u32) -> Result<Self, AppError> {
+        check_passphrase(passphrase)?;
+        let mut salt = [0u8; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| AppError::from("Failed to generate random bundle salt"))?;
+        Ok(Self {
+            key: derive_key(passphrase, &salt, iterations)?,
+            salt,
+            iterations,
+        })
+    }
+}
+
+/// Seal `payload` under `passphrase`, behind a header starting with `magic`.
+pub(crate) fn seal(
+    magic: &[u8; 8],
+    payload: Vec<u8>,
+    passphrase: &str,
+    iterations: u32,
+) -> Result<Vec<u8>, AppError> {
+    seal_with(magic, payload, &SealKey::new(passphrase, iterations)?)
+}
+
+/// [`seal`] with a key already derived.
+pub(crate) fn seal_with(
+    magic: &[u8; 8],
+    mut payload: Vec<u8>,
+    key: &SealKey,
+) -> Result<Vec<u8>, AppError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| AppError::from("Failed to generate random bundle nonce"))?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(magic);
+    header.extend_from_slice(&key.iterations.to_be_bytes());
+    header.extend_from_slice(&key.salt);
+    header.extend_from_slice(&nonce);
+
+    key.key
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(&header),
+            &mut payload,
+        )
+        .map_err(|_| AppError::from("Failed to encrypt bundle"))?;
+
+    header.extend_from_slice(&payload);
+    Ok(header)
+}
+
+/// Whether `data` was sealed with `magic`.
+pub(crate) fn is_sealed(magic: &[u8; 8], data: &[u8]) -> bool {
+    data.len() >= HEADER_LEN && &data[..magic.len()] == magic
+}
+
+/// The payload [`seal`] sealed into `data`.
+pub(crate) fn open(magic: &[u8; 8], data: &[u8], passphrase: &str) -> Result<Vec<u8>, AppError> {
+    open_with_key(magic, data, passphrase).map(|(payload, _)| payload)
+}
+
+/// [`open`], also returning the derived key so the payload can be sealed
+/// again under the same passphrase with [`seal_with`].
+pub(crate) fn open_with_key(
+    magic: &[u8; 8],
+    data: &[u8],
+    passphrase: &str,
+) -> Result<(Vec<u8>, SealKey), AppError> {
+    if !is_sealed(magic, data) {
+        return Err("Not a ccchat bundle".into());
+    }
+    let (header, sealed) = data.split_at(HEADER_LEN);
+    let iterations = u32::from_be_bytes(header[8..12].try_into().unwrap_or_default());
+    let salt: [u8; SALT_LEN] = header[12..12 + SALT_LEN]
+        .try_into()
+        .map_err(|_| AppError::from("Invalid bundle header"))?;
+    let nonce: [u8; NONCE_LEN] = header[12 + SALT_LEN..]
+        .try_into()
+        .map_err(|_| AppError::from("Invalid bundle header"))?;
+
+    let key = derive_key(passphrase, &salt, iterations)?;
+    let mut payload = sealed.to_vec();
+    let plain = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(header),
+            &mut payload,
+        )
+        .map_err(|_| AppError::from("Wrong passphrase or corrupted bundle"))?;
+    let plain = plain.to_vec();
+    Ok((
+        plain,
+        SealKey {
+            key,
+            salt,
+            iterations,
+        },
+    ))
+}
+
+/// Build an encrypted bundle from the state under `root`.
+pub(crate) fn export_bundle(
+    root: &Path,
+    passphrase: &str,
+    iterations: u32,
+) -> Result<(Vec<u8>, usize), AppError> {
+    check_passphrase(passphrase)?;
+    let files = collect_files(root)?;
+    let count = files.len();
+    let manifest = Manifest {
+        version: BUNDLE_VERSION,
+        created_at: crate::helpers::epoch_now(),
+        files,
+    };
+    let payload = serde_json::to_vec(&manifest)?;
+    Ok((seal(MAGIC, payload, passphrase, iterations)?, count))
+}
+
+/// Only plain relative paths may be restored (no `..`, no absolute paths).
+fn is_safe_relative(rel: &str) -> bool {
+    let path = Path::new(rel);
+    !rel.is_empty() && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Decrypt `data` and restore its files under `root`. Existing files are only
+/// replaced with `force`. Returns the number of files written.
+pub(crate) fn import_bundle(
+    root: &Path,
+    data: &[u8],
+    passphrase: &str,
+    force: bool,
+) -> Result<usize, AppError> {
+    let plain = open(MAGIC, data, passphrase)?;
+    let manifest: Manifest = serde_json::from_slice(&plain)?;
+    if manifest.version != BUNDLE_VERSION {
+        return Err(format!("Unsupported bundle version {}", manifest.version).into());
+    }
+
+    let mut decoded = Vec::with_capacity(manifest.files.len());
+    for (rel, b64) in &manifest.files {
+        if !is_safe_relative(rel) {
+            return Err(format!("Refusing unsafe path in bundle: {rel}").into());
+        }
+        let contents = base64::engine::general_purpose::STANDARD
+            .decode(b64)
+            .map_err(|e| AppError::from(format!("Corrupted bundle entry {rel}: {e}")))?;
+        decoded.push((root.join(rel), contents));
+    }
+    if !force {
+        if let Some((path, _)) = decoded.iter().find(|(p, _)| p.exists()) {
+            return Err(format!(
+                "{} already exists; rerun with --force to overwrite",
+                path.display()
+            )
+            .into());
+        }
+    }
+    for (path, contents) in &decoded {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Stale WAL files would be replayed over the restored database.
+        for suffix in ["-wal", "-shm"] {
+            let mut side = path.clone().into_os_string();
+            side.push(suffix);
+            let _ = std::fs::remove_file(side);
+        }
+        std::fs::write(path, contents)?;
+    }
+    Ok(decoded.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_ITERATIONS: u32 = 1_000;
+
+    fn temp_root(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ccchat_bundle_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn seed(root: &Path) {
+        std::fs::create_dir_all(root.join("memories")).unwrap();
+        std::fs::write(root.join("allowed.json"), r#"{"allowed":[]}"#).unwrap();
+        std::fs::write(root.join("memories/abc.db"), b"sqlite-bytes").unwrap();
+        std::fs::write(root.join("memories/abc.db-wal"), b"wal").unwrap();
+        std::fs::write(root.join("usage.db"), b"ledger-bytes").unwrap();
+        std::fs::write(root.join("audit.db"), b"not bundled").unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let src = temp_root("src");
+        let dst = temp_root("dst");
+        seed(&src);
+
+        let (bundle, count) = export_bundle(&src, "correct horse", TEST_ITERATIONS).unwrap();
+        assert_eq!(count, 3);
+        assert!(bundle.starts_with(MAGIC));
+
+        let restored = import_bundle(&dst, &bundle, "correct horse", false).unwrap();
+        assert_eq!(restored, 3);
+        assert_eq!(
+            std::fs::read(dst.join("memories/abc.db")).unwrap(),
+            b"sqlite-bytes"
+        );
+        assert!(dst.join("allowed.json").exists());
+        assert_eq!(
+            std::fs::read(dst.join("usage.db")).unwrap(),
+            b"ledger-bytes"
+        );
+        assert!(!dst.join("audit.db").exists());
+        assert!(!dst.join("memories/abc.db-wal").exists());
+
+        let _ = std::fs::remove_dir_all(&src);
+        let _ = std::fs::remove_dir_all(&dst);
+    }
+
+    #[test]
+    fn test_wrong_passphrase_rejected() {
+        let src = temp_root("wrongpw");
+        seed(&src);
+        let (bundle, _) = export_bundle(&src, "correct horse", TEST_ITERATIONS).unwrap();
+        let err = import_bundle(&src, &bundle, "battery staple", true).unwrap_err();
+        assert!(err.to_string().contains("Wrong passphrase"));
+        let _ = std::fs::remove_dir_all(&src);
+    }
+
+    #[test]
+    fn test_import_refuses_overwrite_without_force() {
+        let src = temp_root("noforce");
+        seed(&src);
+        let (bundle, _) = export_bundle(&src, "correct horse", TEST_ITERATIONS).unwrap();
+        let err = import_bundle(&src, &bundle, "correct horse", false).unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        assert_eq!(
+            import_bundle(&src, &bundle, "correct horse", true).unwrap(),
+            3
+        );
+        let _ = std::fs::remove_dir_all(&src);
+    }
+
+    #[test]
+    fn test_tampered_bundle_rejected() {
+        let src = temp_root("tamper");
+        seed(&src);
+        let (mut bundle, _) = export_bundle(&src, "correct horse", TEST_ITERATIONS).unwrap();
+        let last = bundle.len() - 1;
+        bundle[last] ^= 0x01;
+        assert!(import_bundle(&src, &bundle, "correct horse", true).is_err());
+        assert!(import_bundle(&src, b"garbage", "correct horse", true)
+            .unwrap_err()
+            .to_string()
+            .contains("Not a ccchat bundle"));
+        let _ = std::fs::remove_dir_all(&src);
+    }
+
+    #[test]
+    fn test_short_passphrase_rejected() {
+        let src = temp_root("shortpw");
+        assert!(export_bundle(&src, "short", TEST_ITERATIONS).is_err());
+        let _ = std::fs::remove_dir_all(&src);
+    }
+
+    #[test]
+    fn test_is_safe_relative() {
+        assert!(is_safe_relative("memories/abc.db"));
+        assert!(!is_safe_relative("../etc/passwd"));
+        assert!(!is_safe_relative("/etc/passwd"));
+        assert!(!is_safe_relative(""));
+    }
+}
@@ -0,0 +1,162 @@
+//! Numbered choices.
+//!
+//! When a reply ends with a list of options, or the bridge asks for a
+//! decision, the options are shown numbered and a bare "1", "2", ... reply
+//! picks one, as if its text (or command) had been typed in full. The offer
+//! is per sender and lapses after [`CHOICE_TTL_SECS`] or when the next reply
+//! replaces it.
+
+use std::time::{Duration, Instant};
+
+use crate::constants::CHOICE_TTL_SECS;
+use crate::state::State;
+
+/// Most options a list can have and still be answered with one digit.
+const MAX_OPTIONS: usize = 9;
+
+/// Options offered to one sender.
+pub(crate) struct PendingChoice {
+    /// What each number stands for: a message for Claude or a command.
+    pub(crate) options: Vec<String>,
+    pub(crate) offered_at: Instant,
+}
+
+/// The item text if `line` is a list item: `1.`, `2)`, `a)`, `B.`, `-`,
+/// `*` or `•` followed by a space.
+fn list_item(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    for bullet in ["- ", "* ", "• "] {
+        if let Some(rest) = line.strip_prefix(bullet) {
+            return Some(rest.trim());
+        }
+    }
+    let marker_len = line
+        .find(|c: char| !c.is_ascii_digit())
+        .filter(|&n| n > 0 && n <= 2)
+        .or_else(|| {
+            line.starts_with(|c: char| c.is_ascii_alphabetic())
+                .then_some(1)
+        })?;
+    let rest = line[marker_len..]
+        .strip_prefix(". ")
+        .or_else(|| line[marker_len..].strip_prefix(") "))?;
+    (!rest.trim().is_empty()).then(|| rest.trim())
+}
+
+/// Whether the line introducing a list asks the reader to pick from it, as
+/// opposed to a list of findings or steps.
+fn asks_to_choose(intro: &str) -> bool {
+    let intro = intro.trim().to_lowercase();
+    intro.ends_with('?')
+        || (intro.ends_with(':')
+            && ["option", "choose", "pick", "prefer", "either", "which"]
+                .iter()
+                .any(|word| intro.contains(word)))
+}
+
+/// If `text` ends with a list of 2–9 options after a line asking for a
+/// choice, returns the text with the list renumbered `1.`, `2.`, ... and the
+/// options themselves.
+pub(crate) fn number_options(text: &str) -> Option<(String, Vec<String>)> {
+    let lines: Vec<&str> = text.trim_end().lines().collect();
+    let start = lines.iter().rposition(|line| list_item(line).is_none())? + 1;
+    let intro = lines[..start].iter().rev().find(|l| !l.trim().is_empty())?;
+    if !asks_to_choose(intro) {
+        return None;
+    }
+    let options: Vec<String> = lines[start..]
+        .iter()
+        .filter_map(|line| list_item(line))
+        .map(|item| item.trim_matches('*').trim().to_string())
+        .collect();
+    if !(2..=MAX_OPTIONS).contains(&options.len()) {
+        return None;
+    }
+    let mut out: Vec<String> = lines[..start].iter().map(|l| l.to_string()).collect();
+    out.extend(
+        options
+            .iter()
+            .enumerate()
+            .map(|(i, option)| format!("{}. {option}", i + 1)),
+    );
+    Some((out.join("\n"), options))
+}
+
+/// Remember `options` as the answers to a bare number from `sender`.
+pub(crate) fn offer(state: &State, sender: &str, options: Vec<String>) {
+    state.pending_choices.insert(
+        sender.to_string(),
+        PendingChoice {
+            options,
+            offered_at: Instant::now(),
+        },
+    );
+}
+
+pub(crate) fn clear(state: &State, sender: &str) {
+    state.pending_choices.remove(sender);
+}
+
+/// Whether `text` is a bare number and `sender` has an offer it could pick
+/// from.
+pub(crate) fn is_pick(state: &State, sender: &str, text: &str) -> bool {
+    text.trim().parse::<usize>().is_ok() && state.pending_choices.contains_key(sender)
+}
+
+/// The option a bare-number reply picks, if `sender` has a live offer. Any
+/// pick uses up the offer.
+pub(crate) fn take(state: &State, sender: &str, text: &str) -> Option<String> {
+    let n: usize = text.trim().parse().ok()?;
+    let (_, choice) = state.pending_choices.remove(sender)?;
+    if choice.offered_at.elapsed() > Duration::from_secs(CHOICE_TTL_SECS) {
+        return None;
+    }
+    match choice.options.get(n.checked_sub(1)?) {
+        Some(option) => Some(option.clone()),
+        None => {
+            // Out of range: keep the offer so they can try again.
+            state.pending_choices.insert(sender.to_string(), choice);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_options_renumbers_trailing_list() {
+        let (text, options) = number_options(
+            "Which approach?\n\n- **Rewrite the parser**\n- Patch the lexer\n* Leave it\n\n",
+        )
+        .unwrap();
+        assert_eq!(
+            text,
+            "Which approach?\n\n1. Rewrite the parser\n2. Patch the lexer\n3. Leave it"
+        );
+        assert_eq!(
+            options,
+            vec!["Rewrite the parser", "Patch the lexer", "Leave it"]
+        );
+
+        let (_, options) = number_options("Pick one:\nA) Red\nB) Blue").unwrap();
+        assert_eq!(options, vec!["Red", "Blue"]);
+        let (_, options) = number_options("Here are your options:\n1. First\n2) Second").unwrap();
+        assert_eq!(options, vec!["First", "Second"]);
+    }
+
+    #[test]
+    fn test_number_options_needs_a_trailing_list() {
+        // List not at the end
+        assert!(number_options("- one\n- two\n\nThat's all.").is_none());
+        // A single item isn't a choice
+        assert!(number_options("Which?\n1. Only one").is_none());
+        // Nor is a list of findings
+        assert!(number_options("What I changed:\n- Fixed X\n- Added Y").is_none());
+        assert!(number_options("Plain answer.").is_none());
+        let long: String =
+            (1..=10).fold("Which?\n".to_string(), |s, i| s + &format!("{i}. item\n"));
+        assert!(number_options(&long).is_none());
+    }
+}
@@ -0,0 +1,39 @@
+//! The bridge machine's clipboard for `/clip` (account owner only).
+//!
+//! One clipboard handle is kept for the life of the process: on X11 and
+//! Wayland the contents we set are only served while it exists.
+
+use std::sync::Mutex;
+
+use arboard::Clipboard;
+
+use crate::error::AppError;
+
+static CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+
+fn with_clipboard<T>(
+    f: impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>,
+) -> Result<T, AppError> {
+    let mut guard = CLIPBOARD
+        .lock()
+        .map_err(|_| AppError::Other("clipboard lock poisoned".to_string()))?;
+    if guard.is_none() {
+        *guard = Some(Clipboard::new().map_err(|e| AppError::Other(e.to_string()))?);
+    }
+    let clipboard = guard.as_mut().expect("clipboard initialized above");
+    f(clipboard).map_err(|e| AppError::Other(e.to_string()))
+}
+
+/// Text currently on the clipboard.
+pub(crate) async fn get_text() -> Result<String, AppError> {
+    tokio::task::spawn_blocking(|| with_clipboard(|c| c.get_text()))
+        .await
+        .map_err(|e| AppError::Other(e.to_string()))?
+}
+
+/// Replace the clipboard with `text`.
+pub(crate) async fn set_text(text: String) -> Result<(), AppError> {
+    tokio::task::spawn_blocking(move || with_clipboard(|c| c.set_text(text)))
+        .await
+        .map_err(|e| AppError::Other(e.to_string()))?
+}
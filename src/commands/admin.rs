@@ -1,48 +1,361 @@
+use clap::ValueEnum;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tracing::info;
 
-use crate::memory::{export_messages, format_epoch, persist_allow, persist_revoke};
-use crate::state::{PendingSender, State};
+use crate::constants::{TAIL_DEFAULT_MINUTES, TAIL_MAX_MINUTES};
+use crate::helpers::is_group_chat;
+use crate::maintenance::Task;
+use crate::memory::{
+    export_messages, format_epoch, persist_allow, persist_group_project, persist_group_settings,
+    persist_revoke, persist_setting,
+};
+use crate::settings::{Settings, KEYS};
+use crate::state::State;
 
-pub(super) fn cmd_status(state: &State, sender: &str) -> String {
+/// `/status`: how the bridge is doing and where the sender's session
+/// stands; `/status all` lists every active session for the admin.
+pub(super) fn cmd_status(state: &State, sender: &str, arg: &str) -> String {
+    match arg {
+        "" => {}
+        "all" if state.is_admin(sender) => return status_all(state),
+        "all" => return "Only the account owner can use /status all.".to_string(),
+        _ => return "Usage: /status [all]".to_string(),
+    }
     let uptime = state.metrics.start_time.elapsed();
     let hours = uptime.as_secs() / 3600;
     let mins = (uptime.as_secs() % 3600) / 60;
-    let count = state.metrics.message_count.load(Ordering::Relaxed);
+    let count = state.metrics.message_count.get();
     let cost = state.total_cost_usd();
     let sender_cost = state.sender_cost_usd(sender);
+    let context = state.session_tokens.get(sender).map(|t| *t).unwrap_or(0);
     let sessions = state.session_mgr.sessions.len();
-    let allowed = state.allowed_ids.len();
-    let errors = state.metrics.error_count.load(Ordering::Relaxed);
+    let allowed = state.access.allowed_ids.len();
+    let errors = state.metrics.error_count.get();
     let latency = state.avg_latency_ms();
+    let p50 = state.metrics.latency_percentile(0.50);
+    let p95 = state.metrics.latency_percentile(0.95);
+    let reply_p50 = state.metrics.reply_percentile(0.50);
+    let reply_p95 = state.metrics.reply_percentile(0.95);
+    let avg_cost = state.avg_cost_usd();
+    let reconnects = state.metrics.reconnects.get();
+    let rate_limited = state.metrics.rate_limited.get();
+    let runs = match state.run_slots.limit {
+        0 => format!("{} running", state.run_slots.busy()),
+        limit => format!(
+            "{}/{limit} running, {} queued",
+            state.run_slots.busy(),
+            state.run_slots.waiting.load(Ordering::Relaxed)
+        ),
+    };
+    let inbound = format!(
+        "{}/{} waiting (peak {}), {} shed",
+        state.inbox.depth(),
+        state.inbox.capacity(),
+        state.inbox.high_water(),
+        state.inbox.shed_count()
+    );
+    let failures = state.metrics.failure_counts();
+    let failures = if failures.is_empty() {
+        "none".to_string()
+    } else {
+        failures
+            .iter()
+            .map(|(category, count)| format!("{category}={count}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let connection = connection_status(state);
+    let models = crate::probe::status(state)
+        .map(|models| format!("Models: {models}\n"))
+        .unwrap_or_default();
+    let session = session_status(state, sender);
     format!(
         "ccchat status\n\
+         Connection: {connection}\n\
+         {models}\
          Uptime: {hours}h {mins}m\n\
          Messages: {count}\n\
          Active sessions: {sessions}\n\
+         Claude runs: {runs}\n\
+         Inbound queue: {inbound}\n\
          Allowed senders: {allowed}\n\
          Total cost: ${cost:.4}\n\
          Your cost: ${sender_cost:.4}\n\
+         Your context: {context} tokens\n\
          Errors: {errors}\n\
-         Avg latency: {latency:.0}ms"
+         Avg latency: {latency:.0}ms\n\
+         Latency p50/p95: {p50}ms / {p95}ms\n\
+         Reply time p50/p95: {reply_p50}ms / {reply_p95}ms\n\
+         Avg cost/message: ${avg_cost:.4}\n\
+         Failures: {failures}\n\
+         Reconnects: {reconnects}\n\
+         Rate-limited: {rate_limited}\n\
+         \n\
+         {session}"
+    )
+}
+
+fn connection_status(state: &State) -> String {
+    let time = |epoch: i64| format_epoch(epoch.max(0) as u64);
+    let transport = state.config.transport.as_str();
+    let mut status = match state.metrics.connected_at.load(Ordering::Relaxed) {
+        0 => format!("disconnected ({transport})"),
+        since => format!("connected ({transport}) since {}", time(since)),
+    };
+    match state.metrics.last_reconnect_at.load(Ordering::Relaxed) {
+        0 => status.push_str(", no reconnects"),
+        at => status.push_str(&format!(", last reconnect {}", time(at))),
+    }
+    status
+}
+
+/// Age, idle time and message count of a session.
+fn session_age(session: &crate::state::SenderState) -> String {
+    format!(
+        "{} old, {} message(s), last active {} ago",
+        crate::helpers::format_duration_human(session.started.elapsed().as_secs()),
+        session.message_count,
+        crate::helpers::format_duration_human(session.last_activity.elapsed().as_secs())
+    )
+}
+
+/// Whether a Claude run is going for `chat`, and what's waiting behind it.
+fn job_status(state: &State, chat: &str) -> String {
+    let running = if state.active_runs.contains_key(chat) {
+        "running"
+    } else {
+        "idle"
+    };
+    // Group chats buffer each speaker separately (`turns::buffer_key`).
+    let speaker_prefix = format!("{chat}\n");
+    let buffered: usize = state
+        .debounce
+        .buffers
+        .iter()
+        .filter(|e| e.key() == chat || e.key().starts_with(&speaker_prefix))
+        .map(|e| e.value().0.len())
+        .sum();
+    let confirm = if state.pending_confirms.contains_key(chat) {
+        ", 1 awaiting /confirm"
+    } else {
+        ""
+    };
+    format!("{running}, {buffered} message(s) queued{confirm}")
+}
+
+fn session_status(state: &State, sender: &str) -> String {
+    let prefs = state.prefs(sender);
+    let (model, age) = match state.session_mgr.sessions.get(sender) {
+        Some(session) => (session.model.clone(), session_age(&session)),
+        None => (state.settings().model, "no session yet".to_string()),
+    };
+    let project = state
+        .group_projects
+        .get(sender)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "none (private workspace)".to_string());
+    let persona = match (state.agents.get(sender), &prefs.persona) {
+        (Some(agent), _) => format!("agent {}", *agent),
+        (None, Some(persona)) => persona.clone(),
+        (None, None) => "none".to_string(),
+    };
+    // The ledger's days are UTC, as in `ccchat costs`.
+    let now = crate::helpers::epoch_now();
+    let midnight = now - now % crate::constants::SECS_PER_DAY;
+    let today = crate::ledger::open_ledger_db()
+        .map(|conn| {
+            crate::ledger::runs_since(&conn, midnight, Some(sender))
+                .iter()
+                .map(|run| run.cost_usd)
+                .sum::<f64>()
+        })
+        .unwrap_or(0.0);
+    let age = if crate::privacy::is_private(state, sender) {
+        format!("{age} (private)")
+    } else {
+        age
+    };
+    let named = crate::named_sessions::names(state, sender);
+    let named = if named.is_empty() {
+        String::new()
+    } else {
+        let names: Vec<String> = named.iter().map(|n| format!("@{n}")).collect();
+        format!("\nNamed sessions: {}", names.join(", "))
+    };
+    format!(
+        "Your session\n\
+         Model: {model}\n\
+         Project: {project}\n\
+         Persona: {persona}\n\
+         Session: {age}{named}\n\
+         Jobs: {}\n\
+         Spent today (UTC): ${today:.4} (budget ${:.2} per message)",
+        job_status(state, sender),
+        state.run_budget(sender),
     )
 }
 
-pub(super) fn cmd_pending(state: &State) -> String {
-    if state.pending_senders.is_empty() {
+/// `/status all`: one line per active session.
+fn status_all(state: &State) -> String {
+    let mut chats: Vec<(String, String, String)> = state
+        .session_mgr
+        .sessions
+        .iter()
+        .map(|e| (e.key().clone(), e.model.clone(), session_age(e.value())))
+        .collect();
+    if chats.is_empty() {
+        return "No active sessions.".to_string();
+    }
+    chats.sort();
+    let mut lines = vec![format!("Active sessions ({}):", chats.len())];
+    for (chat, model, age) in chats {
+        lines.push(format!(
+            "{chat}: {model}, {age}; {}; ${:.4} spent",
+            job_status(state, &chat),
+            state.sender_cost_usd(&chat)
+        ));
+    }
+    lines.join("\n")
+}
+
+/// `/maintenance` shows how housekeeping last went; `/maintenance run
+/// [task ...]` runs the configured tasks (or the named ones) now.
+pub(super) async fn handle_maintenance(
+    state: &State,
+    sender: &str,
+    args: &str,
+) -> Result<(), crate::error::AppError> {
+    if !state.is_admin(sender) {
+        return state
+            .send_message(sender, "Only the account owner can use /maintenance.")
+            .await;
+    }
+    let usage = format!(
+        "Usage: /maintenance [run [task ...]]\nTasks: {}",
+        crate::maintenance::task_list(Task::value_variants())
+    );
+    let mut words = args.split([' ', ',']).filter(|w| !w.is_empty());
+    let reply = match words.next() {
+        None => crate::maintenance::describe(state),
+        Some("run") => {
+            let names: Vec<&str> = words.collect();
+            let tasks: Option<Vec<Task>> = if names.is_empty() {
+                Some(state.config.maintenance_tasks.clone())
+            } else {
+                names
+                    .iter()
+                    .map(|name| Task::from_str(name, true).ok())
+                    .collect()
+            };
+            match tasks {
+                Some(tasks) => {
+                    crate::audit::log_action(
+                        "maintenance",
+                        sender,
+                        &crate::maintenance::task_list(&tasks),
+                    );
+                    crate::maintenance::run(state, &tasks)
+                        .await
+                        .iter()
+                        .map(|(task, run)| crate::maintenance::describe_run(*task, run))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+                None => usage,
+            }
+        }
+        Some(_) => usage,
+    };
+    state.send_message(sender, &reply).await
+}
+
+/// `/senders`: allowed senders, and any Signal can't currently deliver to.
+pub(super) fn cmd_senders(state: &State, sender: &str) -> String {
+    if !state.is_admin(sender) {
+        return "Only the admin can list senders.".to_string();
+    }
+    let mut numbers: Vec<String> = state
+        .access
+        .allowed_ids
+        .iter()
+        .map(|e| e.key().clone())
+        .collect();
+    let flagged = state
+        .suspended_recipients
+        .iter()
+        .map(|e| e.key().clone())
+        .chain(state.identity_changes.iter().map(|e| e.key().clone()))
+        .collect::<Vec<_>>();
+    for number in flagged {
+        if !numbers.contains(&number) {
+            numbers.push(number);
+        }
+    }
+    if numbers.is_empty() {
+        return "No allowed senders yet.".to_string();
+    }
+    numbers.sort();
+    let mut lines = vec![format!("Senders ({}):", numbers.len())];
+    for number in &numbers {
+        let mut status = Vec::new();
+        if let Some(since) = state.suspended_recipients.get(number) {
+            status.push(format!(
+                "suspended, not registered with Signal (since {})",
+                format_epoch(*since as u64)
+            ));
+        }
+        if let Some(change) = state.identity_changes.get(number) {
+            let untrusted = if change.untrusted {
+                ", not trusted yet"
+            } else {
+                ""
+            };
+            status.push(format!(
+                "safety number changed {}{untrusted}",
+                format_epoch(change.at as u64)
+            ));
+        }
+        if !state.is_allowed(number) {
+            status.push("no longer allowed".to_string());
+        }
+        let status = if status.is_empty() {
+            "ok".to_string()
+        } else {
+            status.join("; ")
+        };
+        lines.push(format!("  {number} - {status}"));
+    }
+    lines.join("\n")
+}
+
+pub(super) fn cmd_pending(state: &State, sender: &str) -> String {
+    if state.access.pending_senders.is_empty() {
         return "No pending senders.".to_string();
     }
     let mut entries: Vec<_> = state
+        .access
         .pending_senders
         .iter()
         .map(|e| (e.value().short_id, e.value().name.clone(), e.key().clone()))
         .collect();
     entries.sort_by_key(|(sid, _, _)| *sid);
     let mut lines = vec!["Pending senders:".to_string()];
-    for (sid, name, real_id) in &entries {
-        lines.push(format!("  #{sid} {name} ({real_id}) — /allow {sid}"));
+    for (i, (sid, name, real_id)) in entries.iter().enumerate() {
+        lines.push(format!(
+            "{}. #{sid} {name} ({real_id}) — /allow {sid}",
+            i + 1
+        ));
     }
+    lines.push("Reply with a number to allow that sender.".to_string());
+    crate::choices::offer(
+        state,
+        sender,
+        entries
+            .iter()
+            .map(|(sid, _, _)| format!("/allow {sid}"))
+            .collect(),
+    );
     lines.join("\n")
 }
 
@@ -52,13 +365,14 @@ pub(super) fn cmd_allow(state: &State, arg: &str) -> String {
     }
     let (real_id, name) = if let Ok(num) = arg.parse::<u64>() {
         let found = state
+            .access
             .pending_senders
             .iter()
             .find(|e| e.value().short_id == num)
             .map(|e| (e.key().clone(), e.value().name.clone()));
         match found {
             Some((rid, name)) => {
-                state.pending_senders.remove(&rid);
+                state.access.pending_senders.remove(&rid);
                 (rid, name)
             }
             None => return format!("No pending sender #{num}.\nUse /pending to see the list."),
@@ -66,14 +380,18 @@ pub(super) fn cmd_allow(state: &State, arg: &str) -> String {
     } else {
         let id = arg.to_string();
         let name = state
+            .access
             .pending_senders
             .remove(&id)
             .map(|(_, ps)| ps.name)
             .unwrap_or_default();
         (id, name)
     };
-    state.allowed_ids.insert(real_id.clone(), ());
+    state.access.allowed_ids.insert(real_id.clone(), ());
     persist_allow(&real_id, &name);
+    if state.access.guests.remove(&real_id).is_some() {
+        crate::memory::persist_guest(&real_id, false);
+    }
     crate::audit::log_action("allow", &real_id, &name);
     info!(sender = %real_id, sender_name = %name, "Sender approved");
     let display = if name.is_empty() {
@@ -84,18 +402,255 @@ pub(super) fn cmd_allow(state: &State, arg: &str) -> String {
     format!("Allowed: {display}\nSaved. They can now send messages.")
 }
 
+/// Make a one-time invite code (admin only).
+pub(super) fn cmd_invite(state: &State, sender: &str) -> String {
+    if !state.is_admin(sender) {
+        return "Only the admin can make invite codes.".to_string();
+    }
+    let code = crate::invites::create(&state.access);
+    info!("Invite code created");
+    format!(
+        "Invite code: {code}
+         Share it with someone you'd like to chat with Claude here. When they message          this number with it, they're let in as a guest (answers only, ${:.2} per reply).          It works once, within 7 days.",
+        crate::constants::GUEST_MAX_BUDGET_USD
+    )
+}
+
 pub(super) fn cmd_revoke(state: &State, id: &str) -> String {
     if id.is_empty() {
         return "Usage: /revoke <id>".to_string();
     }
-    state.allowed_ids.remove(id);
+    state.access.allowed_ids.remove(id);
     persist_revoke(id);
+    if state.access.guests.remove(id).is_some() {
+        crate::memory::persist_guest(id, false);
+    }
     crate::audit::log_action("revoke", id, "");
     state.session_mgr.sessions.remove(id);
     info!(sender = %id, "Sender revoked");
     format!("Revoked: {id}")
 }
 
+/// Bind the current group chat to a project directory (admin only, enforced at routing).
+pub(super) fn cmd_bind(state: &State, chat_id: &str, arg: &str) -> String {
+    if !is_group_chat(chat_id) {
+        return "Use /bind inside a group chat to bind it to a project directory.".to_string();
+    }
+    if arg.is_empty() {
+        return match state.group_projects.get(chat_id) {
+            Some(dir) => format!(
+                "This group is bound to {}\n{}",
+                dir.display(),
+                tool_policy_summary(state, &dir)
+            ),
+            None => "This group isn't bound to a project.\nUsage: /bind <path>".to_string(),
+        };
+    }
+    let dir = match std::path::Path::new(arg).canonicalize() {
+        Ok(d) if d.is_dir() => d,
+        _ => return format!("Not a directory: {arg}"),
+    };
+    state
+        .group_projects
+        .insert(chat_id.to_string(), dir.clone());
+    persist_group_project(chat_id, &dir.to_string_lossy());
+    crate::audit::log_action("bind", chat_id, &dir.to_string_lossy());
+    info!(group = %chat_id, project = %dir.display(), "Group bound to project");
+    format!(
+        "Bound this group ({chat_id}) to {}\nEveryone here now shares one Claude session working in that directory.\n{}",
+        dir.display(),
+        tool_policy_summary(state, &dir)
+    )
+}
+
+/// Show or change this group's settings. Changes are limited to group
+/// admins at routing.
+pub(super) fn cmd_group(state: &State, chat_id: &str, arg: &str) -> String {
+    use crate::group_settings::KEYS;
+    const USAGE: &str =
+        "Usage: /group status\n       /group set <key> <value>\n       /group reset <key>";
+    if !is_group_chat(chat_id) {
+        return "Use /group inside a group chat.".to_string();
+    }
+    let (action, rest) = match arg.split_once(' ') {
+        Some((action, rest)) => (action, rest.trim()),
+        None => (arg, ""),
+    };
+    let mut settings = state.group_settings(chat_id);
+    let key = match action {
+        "" | "status" => {
+            let project = state
+                .group_projects
+                .get(chat_id)
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_else(|| "none".to_string());
+            let mut lines = vec![format!("Group {chat_id}"), format!("  project: {project}")];
+            for key in KEYS {
+                lines.push(format!("  {key}: {}", settings.get(key)));
+            }
+            lines.push(USAGE.to_string());
+            return lines.join("\n");
+        }
+        "set" => {
+            let Some((key, value)) = rest.split_once(' ') else {
+                return USAGE.to_string();
+            };
+            let value = match key {
+                "model" => state
+                    .model_router
+                    .read()
+                    .map(|router| router.resolve(value.trim()))
+                    .unwrap_or_else(|_| value.trim().to_string()),
+                _ => value.trim().to_string(),
+            };
+            if let Err(e) = settings.set(key, &value) {
+                return e;
+            }
+            if let (Some(model), Some(mut session)) = (
+                settings.model.as_ref().filter(|_| key == "model"),
+                state.session_mgr.sessions.get_mut(chat_id),
+            ) {
+                session.model = model.clone();
+            }
+            key
+        }
+        "reset" if settings.reset(rest) => rest,
+        "reset" => return format!("Group settings: {}.", KEYS.join(", ")),
+        _ => return USAGE.to_string(),
+    };
+    persist_group_settings(chat_id, &settings);
+    state
+        .group_settings
+        .insert(chat_id.to_string(), settings.clone());
+    let value = settings.get(key);
+    crate::audit::log_action("group_set", chat_id, &format!("{key}={value}"));
+    info!(group = %chat_id, key, value = %value, "Group setting changed");
+    format!("{key} set to {value} for this group.")
+}
+
+/// (Re)load a bound project's `.ccchat.toml` and say what it allows.
+fn tool_policy_summary(state: &State, dir: &std::path::Path) -> String {
+    use crate::policy::POLICY_FILE;
+    match state.tool_policies.load(dir) {
+        Ok(Some(policy)) => format!("Tool policy from {POLICY_FILE}:\n{}", policy.describe()),
+        Ok(None) => format!("No {POLICY_FILE} tool policy; Claude Code's own settings apply."),
+        Err(e) => format!(
+            "⚠️ Claude won't run here until {POLICY_FILE} is fixed and you /bind again: {e}"
+        ),
+    }
+}
+
+/// Toggle forwarding of WARN/ERROR log events to the admin's chat.
+pub(super) fn cmd_tail(state: &State, sender: &str, arg: &str) -> String {
+    if !state.is_admin(sender) {
+        return "Only the admin can tail logs.".to_string();
+    }
+    let (action, minutes) = match arg.split_once(' ') {
+        Some((a, m)) => (a, Some(m.trim())),
+        None => (arg, None),
+    };
+    match action {
+        "on" => {
+            let minutes = match minutes.map(str::parse::<u64>) {
+                None => TAIL_DEFAULT_MINUTES,
+                Some(Ok(m)) if (1..=TAIL_MAX_MINUTES).contains(&m) => m,
+                _ => return format!("Minutes must be between 1 and {TAIL_MAX_MINUTES}."),
+            };
+            state.log_tail.enable_for(minutes);
+            info!(minutes, "Log tail enabled");
+            format!(
+                "Forwarding WARN/ERROR logs here for the next {minutes} min. /tail off to stop."
+            )
+        }
+        "off" => {
+            state.log_tail.disable();
+            "Log tail off.".to_string()
+        }
+        "" => match state.log_tail.remaining_secs() {
+            0 => "Log tail is off.\nUsage: /tail on [minutes]|off".to_string(),
+            secs => format!("Log tail is on ({} min left).", secs.div_ceil(60)),
+        },
+        _ => "Usage: /tail on [minutes]|off".to_string(),
+    }
+}
+
+/// Show or change runtime settings (`/config show|set|reset`).
+pub(super) fn cmd_config(state: &State, sender: &str, arg: &str) -> String {
+    const USAGE: &str =
+        "Usage: /config show\n       /config set <key> <value>\n       /config reset <key>";
+    if !state.is_admin(sender) {
+        return "Only the admin can change settings.".to_string();
+    }
+    let (action, rest) = match arg.split_once(' ') {
+        Some((action, rest)) => (action, rest.trim()),
+        None => (arg, ""),
+    };
+    let defaults = Settings::from_config(&state.config);
+    match action {
+        "" | "show" => {
+            let current = state.settings();
+            let mut lines = vec!["Settings:".to_string()];
+            for key in KEYS {
+                let value = current.get(key).unwrap_or_default();
+                if current.get(key) == defaults.get(key) {
+                    lines.push(format!("  {key}: {value}"));
+                } else {
+                    lines.push(format!(
+                        "  {key}: {value} (default {})",
+                        defaults.get(key).unwrap_or_default()
+                    ));
+                }
+            }
+            lines.push(USAGE.to_string());
+            lines.join("\n")
+        }
+        "set" => {
+            let Some((key, value)) = rest.split_once(' ') else {
+                return USAGE.to_string();
+            };
+            let value = value.trim();
+            let Ok(mut settings) = state.settings.write() else {
+                return "Settings are unavailable right now.".to_string();
+            };
+            let mut updated = settings.clone();
+            if let Err(e) = updated.set(key, value) {
+                return e;
+            }
+            *settings = updated;
+            let shown = settings.get(key).unwrap_or_default();
+            drop(settings);
+            persist_setting(key, Some(&shown));
+            crate::audit::log_action("config_set", sender, &format!("{key}={shown}"));
+            info!(key, value = %shown, "Setting changed");
+            format!("{key} set to {shown}.")
+        }
+        "reset" if KEYS.contains(&rest) => {
+            if let Ok(mut settings) = state.settings.write() {
+                settings.reset(rest, &defaults);
+            }
+            persist_setting(rest, None);
+            crate::audit::log_action("config_reset", sender, rest);
+            format!(
+                "{rest} reset to {}.",
+                defaults.get(rest).unwrap_or_default()
+            )
+        }
+        "reset" => format!("Settings: {}.", KEYS.join(", ")),
+        _ => USAGE.to_string(),
+    }
+}
+
+/// Which Anthropic credential is in use and which are resting (`/key status`).
+pub(super) fn cmd_key(state: &State, sender: &str, arg: &str) -> String {
+    if !state.is_admin(sender) {
+        return "Only the admin can see Claude accounts.".to_string();
+    }
+    match arg {
+        "" | "status" => state.claude_keys.status(crate::helpers::epoch_now() as u64),
+        _ => "Usage: /key status".to_string(),
+    }
+}
+
 pub(super) fn cmd_audit() -> String {
     let actions = crate::audit::get_recent_actions(20);
     if actions.is_empty() {
@@ -148,24 +703,115 @@ pub(super) fn cmd_usage(state: &State, sender: &str) -> String {
     )
 }
 
+/// `/cost`: where the sender's spend went, token by token, and what prompt
+/// caching saved.
+pub(super) fn cmd_cost(state: &State, sender: &str) -> String {
+    let cost = state.sender_cost_usd(sender);
+    let Some((usage, saved)) = state.token_usage.get(sender).map(|e| *e.value()) else {
+        return format!("Cost so far: ${cost:.4}\nNo token counts recorded yet.");
+    };
+    let tokens = crate::streaming::format_tokens;
+    format!(
+        "Cost so far: ${cost:.4}\n\
+         Input: {} fresh, {} read from cache, {} written to cache\n\
+         Output: {}\n\
+         Cache hit rate: {:.0}%\n\
+         Saved by prompt caching: ${saved:.4} (at list prices)",
+        tokens(usage.input),
+        tokens(usage.cache_read),
+        tokens(usage.cache_write),
+        tokens(usage.output),
+        usage.cache_hit_rate() * 100.0,
+    )
+}
+
+pub(super) fn cmd_stop(state: &State, sender: &str) -> String {
+    match state.active_runs.get(sender) {
+        Some(stop) => {
+            stop.notify_one();
+            "Stopping Claude...".to_string()
+        }
+        None => "Nothing is running.".to_string(),
+    }
+}
+
+/// Admin: cancel a chat's run and release its session lock (default: this chat).
+pub(super) fn cmd_unstick(state: &State, sender: &str, arg: &str) -> String {
+    if !state.is_admin(sender) {
+        return "Only the admin can unstick sessions.".to_string();
+    }
+    let target = if arg.is_empty() { sender } else { arg };
+    let cancelled = state.unstick(target);
+    crate::audit::log_action("unstick", target, "");
+    info!(sender = %target, cancelled, "Session unstuck");
+    if cancelled {
+        format!("Unstuck {target}: cancelled its run and released the session.")
+    } else {
+        format!("Released the session for {target} (nothing was running).")
+    }
+}
+
 pub(super) fn cmd_help() -> String {
     "ccchat commands:\n\
      /help - Show this help message\n\
-     /status - Show bot status (uptime, messages, cost)\n\
+     /status [all] - Show bot and session status (all: every session, admin)\n\
      /reset - End current session and start fresh\n\
+     /confirm - Run a message held back as expensive\n\
+     /carry - Bring a summary of the conversation before /reset into the next one\n\
      /more - Continue a truncated response\n\
+     /continue - Keep going after Claude hit the per-message budget\n\
+     /stop - Cancel the reply Claude is working on\n\
+     /retry - Send again what a crash or restart cut off\n\
+     @<name>: <message> - Ask a separate named session without leaving this one\n\
+     /compact - Summarize this session and continue in a fresh one\n\
+     /files [glob] - List files in your session workspace\n\
+     /attachments - List the files you've sent that are still kept\n\
+     /get <file> - Download a workspace file as an attachment\n\
+     /put <path> - Save the attached file into your workspace\n\
      /model <name> - Switch Claude model (e.g., haiku, sonnet, opus)\n\
+     /agent list - Show the custom agents you can pick\n\
+     /agent <name>|off - Use a custom agent for this session\n\
+     //<command> - Run one of Claude Code's slash commands (e.g., //review)\n\
+     /redaction on|off - Toggle masking of secrets before they reach Claude\n\
+     /private on|off - Keep nothing from this session: no memory, no logs, files wiped when it ends\n\
+     /prefs - Show your saved preferences\n\
+     /prefs set|reset <key> [value] - Change footer, diff, verbosity, locale, quiet-hours, persona, temperature or max-tokens\n\
+     /set <key> <value> - Short for /prefs set (e.g. /set temperature 0.2)\n\
+     /format tables auto|text|image|off - Choose how tables in replies are shown\n\
+     /format code image|text - Send long code blocks as images\n\
+     /voice on|only|off - Also (or only) send replies as voice notes\n\
      /memory - Show stored conversation memory\n\
+     /memory lock <passphrase> - Encrypt your memory with your passphrase\n\
+     /memory unlock <passphrase> - Unlock it after a restart (lock off: remove)\n\
      /forget - Clear all stored memory\n\
+     /remember <fact> - Keep a fact Claude should always know\n\
+     /memories - List remembered facts\n\
+     /forget <number> - Remove one remembered fact\n\
      /search <query> - Search conversation history\n\
      /export - Export conversation history\n\
+     /gh [question] - Ask about your GitHub notifications, reviews and assignments\n\
+     /ha <entity> [action] - Show or control a Home Assistant entity\n\
+     /clip - Show the machine's clipboard\n\
+     /clip set <text> - Put text on the machine's clipboard (or send clip: <text>)\n\
+     /screenshot - Capture the machine's screen (needs --allow-screenshot)\n\
      /usage - Show your personal usage stats\n\
+     /cost - Break down your spend by token type and prompt cache savings\n\
+     /report [all] [chart] - Usage report for the last 7 days\n\
+     /report weekly on [all]|off - Get the report every Monday\n\
      /pin <label> - Pin recent messages with a label\n\
      /pins - List saved pins\n\
+     /template add|remove <name> [text] - Save a prompt with {attachment}, {file} or {input}\n\
+     /templates - List saved templates\n\
+     /t <name> [text] - Send a saved template\n\
      /recall <label> - Recall a pinned conversation for context\n\
+     /context add|list|remove <file> - Keep workspace files in every prompt this session\n\
+     /good - Mark the last reply as a good one\n\
+     /bad [comment] - Mark the last reply as a bad one, optionally saying why\n\
      /remind <time> <msg> - Set a reminder (e.g., /remind 5m Check oven)\n\
      /reminders - List your pending reminders\n\
      /cancel <id> - Cancel a reminder\n\
+     /followup <time> <prompt> - Run a prompt later and report back (--note: just a message)\n\
+     /followups - List pending follow-ups (/followups cancel <id>)\n\
      /cron <pattern> <msg> - Create a cron job (e.g., /cron \"0 9 * * MON\" Standup)\n\
      /every <interval> <msg> - Repeat every N time (e.g., /every 1h Check status)\n\
      /daily <HH:MM> <msg> - Daily job at time UTC (e.g., /daily 09:00 Standup)\n\
@@ -173,37 +819,64 @@ pub(super) fn cmd_help() -> String {
      /cron-cancel <id> - Cancel a cron job\n\
      /cron-pause <id> - Pause a cron job\n\
      /cron-resume <id> - Resume a paused cron job\n\
+     /subscribe <url> [schedule] [summarize] - Get new items from an RSS/Atom feed\n\
+     /subscriptions - List feed subscriptions\n\
+     /unsubscribe <id> - Remove a feed subscription\n\
      /audit - View recent admin actions\n\
+     /unstick [id] - Cancel a stuck run and release the session (admin)\n\
      /pending - List blocked senders awaiting approval\n\
+     /senders - List allowed senders and any Signal can't deliver to (admin)\n\
      /allow <id> - Approve a pending sender\n\
      /revoke <id> - Remove a sender's access\n\
+     /invite - Make a one-time code that lets someone in as a guest (admin)\n\
+     /bind <path> - Bind this group chat to a project directory\n\
+     /group [status|set <key> <value>|reset <key>] - Group settings: trigger, model, budget, length, admins (group admins)\n\
+     /tail on [minutes]|off - Forward WARN/ERROR logs to you for a while\n\
+     /maintenance [run [task ...]] - Show or run nightly housekeeping\n\
+     /config show|set <key> <value>|reset <key> - View or change runtime settings\n\
+     /key status - Show which Claude account is in use\n\
      /export-config - Export allowed senders as JSON"
         .to_string()
 }
 
+/// With `--unknown-sender-reply`, tell a sender who isn't allowed that the
+/// owner has their request, within the auto-reply limits.
+pub(crate) fn reply_to_unknown(state: &Arc<State>, source: &str) {
+    if !state.config.unknown_sender_reply
+        || !state
+            .access
+            .take_auto_reply(source, crate::helpers::epoch_now())
+    {
+        return;
+    }
+    info!(sender = %source, "Auto-replied to unknown sender");
+    let state = Arc::clone(state);
+    let source = source.to_string();
+    tokio::spawn(async move {
+        let _ = state
+            .send_message(&source, crate::constants::UNKNOWN_SENDER_REPLY)
+            .await;
+    });
+}
+
 /// Handle an unauthorized sender: track as pending, notify admin.
 pub(crate) fn handle_unauthorized(state: &Arc<State>, source: &str, source_name: &str) {
     let id = source.to_string();
-    let is_new = !state.pending_senders.contains_key(&id);
-    let short_id = if is_new {
-        let sid = state.pending_counter.fetch_add(1, Ordering::Relaxed) + 1;
-        state.pending_senders.insert(
-            id.clone(),
-            PendingSender {
-                name: source_name.to_string(),
-                short_id: sid,
-            },
-        );
-        sid
-    } else {
-        state.pending_senders.get(&id).unwrap().short_id
-    };
+    let (short_id, is_new) = state.access.add_pending(&id, source_name);
     info!(sender = %id, sender_name = %source_name, short_id = short_id, "Blocked unauthorized sender");
 
     if is_new {
+        crate::webhook::notify(
+            state,
+            "approval_requested",
+            &id,
+            &format!("New sender {source_name} waits for /allow {short_id}"),
+        );
+        // Approval takes the explicit command: a stranger's message mustn't
+        // turn the admin's next bare number into an /allow.
         let notify = format!(
             "New sender blocked: {source_name} ({id})\n\
-             Reply /allow {short_id}"
+             Reply /allow {short_id} to let them in"
         );
         let state = Arc::clone(state);
         let account = state.config.account.clone();
@@ -0,0 +1,155 @@
+use tracing::warn;
+
+use crate::error::AppError;
+use crate::github::Digest;
+use crate::state::State;
+
+const GH_DEFAULT_QUESTION: &str = "What needs my attention on GitHub?";
+
+/// The cached GitHub digest if it's recent, otherwise a fresh one (which is
+/// cached for next time).
+async fn github_digest(state: &State) -> Result<Digest, AppError> {
+    let cached = state.github_digest.read().ok().and_then(|d| d.clone());
+    if let Some(digest) = cached.filter(|d| d.is_fresh(state.config.github.poll_mins)) {
+        return Ok(digest);
+    }
+    let digest = crate::github::fetch_digest(&state.http, &state.config.github).await?;
+    if let Ok(mut cached) = state.github_digest.write() {
+        *cached = Some(digest.clone());
+    }
+    Ok(digest)
+}
+
+/// `/gh [question]`: answer a question about the sender's GitHub activity,
+/// with the digest given to Claude as context.
+pub(super) async fn handle_gh(state: &State, sender: &str, question: &str) -> Result<(), AppError> {
+    if state.config.github.token.is_none() {
+        return state
+            .send_message(
+                sender,
+                "GitHub isn't set up on this server (start ccchat with --github-token).",
+            )
+            .await;
+    }
+    let _ = state.set_typing(sender, true).await;
+    let digest = match github_digest(state).await {
+        Ok(digest) => digest,
+        Err(e) => {
+            warn!(sender = %sender, "GitHub digest failed: {e}");
+            let _ = state.set_typing(sender, false).await;
+            return state
+                .send_message(sender, &format!("Couldn't reach GitHub: {e}"))
+                .await;
+        }
+    };
+    let question = if question.is_empty() {
+        GH_DEFAULT_QUESTION
+    } else {
+        question
+    };
+    let context = format!("[GitHub digest]\n{}", digest.text);
+    super::run_conversation_with(state, sender, question, &[], Some(&context), false).await
+}
+
+/// `/ha`: list allowed entities; `/ha <entity>`: show its state;
+/// `/ha <entity> <action>`: run an action. Account owner only.
+pub(super) async fn handle_ha(state: &State, sender: &str, args: &str) -> Result<(), AppError> {
+    let config = &state.config.ha;
+    if !config.enabled() {
+        return state
+            .send_message(
+                sender,
+                "Home Assistant isn't set up on this server (start ccchat with --ha-url and --ha-token).",
+            )
+            .await;
+    }
+    if !state.is_admin(sender) {
+        return state
+            .send_message(sender, "Only the account owner can use /ha.")
+            .await;
+    }
+    let mut words = args.split_whitespace();
+    let reply = match (words.next(), words.next()) {
+        (None, _) => format!(
+            "Usage: /ha <entity> [on|off|toggle|lock|unlock|open|close|stop|press|run|trigger]\nAllowed: {}",
+            config.entities.join(", ")
+        ),
+        (Some(entity), action) => {
+            match crate::homeassistant::run_action(
+                &state.http,
+                config,
+                entity,
+                action.unwrap_or("state"),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(e) => {
+                    warn!(sender = %sender, entity, "Home Assistant call failed: {e}");
+                    format!("Home Assistant: {e}")
+                }
+            }
+        }
+    };
+    state.send_message(sender, &reply).await
+}
+
+/// Whether an action Claude asks for waits for the owner's yes: always for
+/// locks and covers, and every change when `/ha` is in `--confirm-commands`.
+fn ha_needs_yes(state: &State, entity: &str, action: &str) -> bool {
+    action != "state"
+        && (matches!(entity.split('.').next(), Some("lock" | "cover"))
+            || crate::confirmation::needs_confirmation(state, "/ha"))
+}
+
+/// Run the `[[ha entity action]]` lines in Claude's reply and replace them
+/// with their outcomes. Actions that need the owner's yes are held as a
+/// pending `/ha` command instead, one at a time. Replies to anyone but the
+/// account owner are left alone; only the owner is told about the tool.
+pub(super) async fn apply_ha_actions(state: &State, sender: &str, response: String) -> String {
+    if !state.config.ha.enabled() || !state.is_admin(sender) {
+        return response;
+    }
+    let (actions, rest) = crate::homeassistant::extract_actions(&response);
+    if actions.is_empty() {
+        return response;
+    }
+    let mut results = Vec::new();
+    let mut asked = false;
+    for (entity, action) in &actions {
+        if ha_needs_yes(state, entity, action) {
+            let line = if asked {
+                format!(
+                    "✗ {entity} {action}: not run; ask again after answering the question above"
+                )
+            } else {
+                asked = true;
+                crate::confirmation::ask_for_claude(
+                    state,
+                    sender,
+                    &format!("/ha {entity} {action}"),
+                    &format!("{action} {entity}"),
+                )
+            };
+            results.push(line);
+            continue;
+        }
+        let line =
+            match crate::homeassistant::run_action(&state.http, &state.config.ha, entity, action)
+                .await
+            {
+                Ok(result) => format!("✓ {result}"),
+                Err(e) => {
+                    warn!(sender = %sender, entity = %entity, "Home Assistant action failed: {e}");
+                    format!("✗ {entity} {action}: {e}")
+                }
+            };
+        results.push(line);
+    }
+    let results = format!("Home Assistant:\n{}", results.join("\n"));
+    if rest.is_empty() {
+        results
+    } else {
+        format!("{results}\n\n{rest}")
+    }
+}
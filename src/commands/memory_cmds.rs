@@ -2,8 +2,10 @@ use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Mutex;
 
+use crate::format::{FormatPrefs, TableFormat};
 use crate::memory::{format_epoch, search_memory_formatted};
 use crate::state::{SenderState, State};
+use crate::tts::VoiceMode;
 
 pub(super) fn cmd_search(sender: &str, query: &str) -> String {
     if query.is_empty() {
@@ -30,7 +32,30 @@ pub(super) fn cmd_search(sender: &str, query: &str) -> String {
 }
 
 pub(super) fn cmd_model(state: &State, sender: &str, model: &str) -> String {
-    let model = model.trim().to_string();
+    let router = state
+        .model_router
+        .read()
+        .map(|r| r.clone())
+        .unwrap_or_default();
+    let name = model.trim();
+    if name.is_empty() {
+        let current = state
+            .session_mgr
+            .sessions
+            .get(sender)
+            .map(|s| s.model.clone())
+            .unwrap_or_else(|| state.settings().model);
+        let mut msg = format!(
+            "Model: {}\nUsage: /model <name or alias>",
+            router.resolve(&current)
+        );
+        let routing = router.describe();
+        if !routing.is_empty() {
+            msg.push_str(&format!("\n\n{routing}"));
+        }
+        return msg;
+    }
+    let model = router.resolve(name);
     let mut entry = state
         .session_mgr
         .sessions
@@ -40,22 +65,290 @@ pub(super) fn cmd_model(state: &State, sender: &str, model: &str) -> String {
             model: model.clone(),
             lock: Arc::new(Mutex::new(())),
             last_activity: Instant::now(),
+            started: Instant::now(),
             message_count: 0,
+            prefs: Default::default(),
         });
     entry.model = model.clone();
     // Persist preference so it survives session resets
     if let Ok(conn) = crate::memory::open_memory_db(sender) {
         crate::memory::save_model_preference(&conn, &model);
     }
-    format!("Model switched to: {model}")
+    if model == name {
+        format!("Model switched to: {model}")
+    } else {
+        format!("Model switched to: {model} ({name})")
+    }
+}
+
+pub(super) fn cmd_agent(state: &State, sender: &str, arg: &str) -> String {
+    let current = state.agents.get(sender).map(|name| name.clone());
+    match arg {
+        "" => match current {
+            Some(name) => format!("Agent: {name}. /agent off to go back to plain Claude."),
+            None => {
+                "No agent selected.\nUsage: /agent list | /agent <name> | /agent off".to_string()
+            }
+        },
+        "list" => {
+            let agents = crate::agents::list_agents(&state.agent_dirs(sender));
+            if agents.is_empty() {
+                return "No agents found. Add them to .claude/agents in the project or ~/.claude/agents."
+                    .to_string();
+            }
+            let mut lines = vec!["Agents:".to_string()];
+            for agent in agents {
+                let marker = if current.as_deref() == Some(agent.name.as_str()) {
+                    "*"
+                } else {
+                    "-"
+                };
+                if agent.description.is_empty() {
+                    lines.push(format!("{marker} {}", agent.name));
+                } else {
+                    lines.push(format!("{marker} {}: {}", agent.name, agent.description));
+                }
+            }
+            lines.join("\n")
+        }
+        "off" => match state.agents.remove(sender) {
+            Some((_, name)) => format!("Agent {name} off."),
+            None => "No agent selected.".to_string(),
+        },
+        name => match crate::agents::find_agent(&state.agent_dirs(sender), name) {
+            Some(agent) => {
+                state.agents.insert(sender.to_string(), agent.name.clone());
+                format!(
+                    "Agent switched to: {}. It lasts until /reset or /agent off.",
+                    agent.name
+                )
+            }
+            None => format!("No agent named {name}. /agent list shows the available ones."),
+        },
+    }
+}
+
+pub(super) fn cmd_prefs(state: &State, sender: &str, arg: &str) -> String {
+    const USAGE: &str =
+        "Usage: /prefs\n       /prefs set <key> <value>\n       /prefs reset <key>|all";
+    let (action, rest) = arg.split_once(' ').unwrap_or((arg, ""));
+    let (key, value) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+    match (action, key) {
+        ("", _) => {
+            let prefs = state.prefs(sender);
+            let format = state.format_prefs(sender);
+            let mut lines = vec![
+                "Your preferences:".to_string(),
+                format!("  tables: {} (/format)", format.tables.as_str()),
+                format!("  code: {} (/format)", code_setting(format)),
+            ];
+            for key in crate::preferences::KEYS {
+                lines.push(format!("  {key}: {}", prefs.get(key)));
+            }
+            lines.push(USAGE.to_string());
+            lines.join("\n")
+        }
+        ("set", key) if !value.trim().is_empty() => {
+            let mut result = Ok(());
+            let prefs = state.update_prefs(sender, |p| result = p.set(key, value.trim()));
+            match result {
+                Ok(()) => format!("{key} set to {}.", prefs.get(key)),
+                Err(e) => format!("{e}.\n{USAGE}"),
+            }
+        }
+        ("reset", "all") => {
+            state.update_prefs(sender, |p| *p = Default::default());
+            "Preferences reset to defaults.".to_string()
+        }
+        ("reset", key) if !key.is_empty() => {
+            let mut result = Ok(());
+            let prefs = state.update_prefs(sender, |p| result = p.reset(key));
+            match result {
+                Ok(()) => format!("{key} reset to {}.", prefs.get(key)),
+                Err(e) => format!("{e}.\n{USAGE}"),
+            }
+        }
+        _ => USAGE.to_string(),
+    }
+}
+
+pub(super) fn cmd_redaction(state: &State, sender: &str, arg: &str) -> String {
+    match arg {
+        "on" => {
+            state.redaction_off.remove(sender);
+            "Redaction on. Secrets are masked before messages reach Claude.".to_string()
+        }
+        "off" => {
+            state.redaction_off.insert(sender.to_string(), ());
+            "Redaction off. Messages are sent to Claude unmodified.".to_string()
+        }
+        "" => {
+            let status = if state.redaction_off.contains_key(sender) {
+                "off"
+            } else {
+                "on"
+            };
+            format!("Redaction is {status}.\nUsage: /redaction on|off")
+        }
+        _ => "Usage: /redaction on|off".to_string(),
+    }
+}
+
+pub(super) fn cmd_private(state: &State, sender: &str, arg: &str) -> String {
+    match arg {
+        "on" => {
+            crate::privacy::start(state, sender);
+            "Private mode on. Nothing from this session is kept: no memory, no summaries, no logs of what's said, and its files are wiped when it ends (/reset or expiry).".to_string()
+        }
+        "off" => {
+            if state.private_sessions.remove(sender).is_none() {
+                return "Private mode is already off.".to_string();
+            }
+            "Private mode off. Messages from now on are remembered as usual.".to_string()
+        }
+        "" => {
+            let status = if crate::privacy::is_private(state, sender) {
+                "on"
+            } else {
+                "off"
+            };
+            format!("Private mode is {status}.\nUsage: /private on|off")
+        }
+        _ => "Usage: /private on|off".to_string(),
+    }
+}
+
+pub(super) fn cmd_format(state: &State, sender: &str, arg: &str) -> String {
+    const USAGE: &str = "Usage: /format tables auto|text|image|off\n       /format code image|text";
+    let (setting, choice) = match arg.split_once(' ') {
+        Some((setting, choice)) => (setting, choice.trim()),
+        None => (arg, ""),
+    };
+    let mut prefs = state.format_prefs(sender);
+    match (setting, choice) {
+        ("", _) => {
+            return format!(
+                "Formatting:\n  tables: {}\n  code: {}\n{USAGE}",
+                prefs.tables.as_str(),
+                code_setting(prefs)
+            )
+        }
+        ("tables", choice) => match TableFormat::parse(choice) {
+            Some(tables) => prefs.tables = tables,
+            None => return USAGE.to_string(),
+        },
+        ("code", "image") if !cfg!(feature = "render") => {
+            return "This build can't draw code images (it needs the render feature).".to_string()
+        }
+        ("code", "image") => prefs.code_images = true,
+        ("code", "text") => prefs.code_images = false,
+        _ => return USAGE.to_string(),
+    }
+    let custom = (prefs != state.settings().format).then_some(prefs);
+    state.update_prefs(sender, |p| p.format = custom);
+    format!(
+        "Formatting updated. Tables: {}, code: {}.",
+        prefs.tables.as_str(),
+        code_setting(prefs)
+    )
+}
+
+pub(super) fn cmd_voice(state: &State, sender: &str, arg: &str) -> String {
+    const USAGE: &str = "Usage: /voice on|only|off";
+    if state.config.tts.engine.is_none() {
+        return "Voice replies aren't set up on this server (start ccchat with --tts).".to_string();
+    }
+    if arg.is_empty() {
+        return format!(
+            "Voice replies: {}.\n{USAGE}",
+            state.voice_mode(sender).as_str()
+        );
+    }
+    match VoiceMode::parse(arg) {
+        Some(VoiceMode::Off) => {
+            state.voice_modes.remove(sender);
+            "Voice replies off.".to_string()
+        }
+        Some(mode) => {
+            state.voice_modes.insert(sender.to_string(), mode);
+            if mode == VoiceMode::Only {
+                "Voice replies on. You'll get a voice note instead of text.".to_string()
+            } else {
+                "Voice replies on. You'll get a voice note after each text reply.".to_string()
+            }
+        }
+        None => USAGE.to_string(),
+    }
+}
+
+fn code_setting(prefs: FormatPrefs) -> &'static str {
+    if prefs.code_images && cfg!(feature = "render") {
+        "image"
+    } else {
+        "text"
+    }
+}
+
+/// `sender`'s memory, or what to tell them when it can't be opened.
+fn open_memory(sender: &str) -> Result<rusqlite::Connection, String> {
+    if crate::memory::lock::is_locked(sender) {
+        return Err(crate::memory::lock::LOCKED_NOTICE.to_string());
+    }
+    crate::memory::open_memory_db(sender).map_err(|_| "Failed to access memory.".to_string())
+}
+
+/// `/memory`: what's stored, or `lock <passphrase>`, `unlock <passphrase>`
+/// and `lock off` to keep it sealed under the sender's own passphrase.
+pub(super) fn cmd_memory(sender: &str, arg: &str) -> String {
+    const USAGE: &str = "Usage: /memory, /memory lock <passphrase>, /memory unlock <passphrase> or /memory lock off";
+    let (sub, phrase) = arg.split_once(' ').unwrap_or((arg, ""));
+    let phrase = phrase.trim();
+    if sub.is_empty() {
+        return crate::memory::memory_status(sender);
+    }
+    if crate::helpers::is_group_chat(sender) {
+        return "A group's memory can't be locked: everyone in it would need the passphrase."
+            .to_string();
+    }
+    match (sub, phrase) {
+        ("lock", "off") => match crate::memory::lock::remove(sender) {
+            Ok(()) => {
+                crate::audit::log_action("memory_unlock", sender, "lock removed");
+                "Lock removed. Your memory is stored without your passphrase again.".to_string()
+            }
+            Err(e) => e.to_string(),
+        },
+        ("lock", phrase) if !phrase.is_empty() => {
+            match crate::memory::lock::lock(sender, phrase, crate::constants::BUNDLE_KDF_ITERATIONS)
+            {
+                Ok(()) => {
+                    crate::audit::log_action("memory_lock", sender, "");
+                    "Memory locked. Your history, facts and pins are now sealed with your \
+                     passphrase, which isn't stored. After a restart, send /memory unlock \
+                     <passphrase> before chatting. If you lose it, your memory is gone; \
+                     /forget starts over."
+                        .to_string()
+                }
+                Err(e) => e.to_string(),
+            }
+        }
+        ("unlock", phrase) if !phrase.is_empty() => {
+            match crate::memory::lock::unlock(sender, phrase) {
+                Ok(()) => "Memory unlocked.".to_string(),
+                Err(e) => e.to_string(),
+            }
+        }
+        _ => USAGE.to_string(),
+    }
 }
 
 pub(super) fn cmd_pin(sender: &str, label: &str) -> String {
     if label.is_empty() {
         return "Usage: /pin <label>".to_string();
     }
-    let Ok(conn) = crate::memory::open_memory_db(sender) else {
-        return "Failed to access memory.".to_string();
+    let conn = match open_memory(sender) {
+        Ok(conn) => conn,
+        Err(msg) => return msg,
     };
     let messages =
         crate::memory::messages::get_recent_messages(&conn, crate::constants::PIN_MESSAGE_COUNT);
@@ -71,9 +364,83 @@ pub(super) fn cmd_pin(sender: &str, label: &str) -> String {
     format!("Pinned {} messages as '{label}'", messages.len())
 }
 
+/// `/remember <fact>`: keep a fact for every future prompt.
+pub(super) fn cmd_remember(sender: &str, fact: &str) -> String {
+    if fact.is_empty() {
+        return "Usage: /remember <fact>, e.g. /remember my staging server is athena.local"
+            .to_string();
+    }
+    let conn = match open_memory(sender) {
+        Ok(conn) => conn,
+        Err(msg) => return msg,
+    };
+    match crate::memory::messages::add_fact(&conn, fact) {
+        Some(n) => format!("Remembered ({n}). Claude will know this from now on."),
+        None => format!(
+            "You already have {} facts saved. Remove some with /forget <number> first.",
+            crate::constants::MAX_FACTS
+        ),
+    }
+}
+
+/// `/memories`: list remembered facts.
+pub(super) fn cmd_memories(sender: &str) -> String {
+    let conn = match open_memory(sender) {
+        Ok(conn) => conn,
+        Err(msg) => return msg,
+    };
+    let facts = crate::memory::messages::list_facts(&conn);
+    if facts.is_empty() {
+        return "Nothing remembered yet. Use /remember <fact> to add something.".to_string();
+    }
+    let mut lines = vec![format!("Remembered facts ({}):", facts.len())];
+    for (i, fact) in facts.iter().enumerate() {
+        lines.push(format!("  {}. {fact}", i + 1));
+    }
+    lines.push("Use /forget <number> to remove one.".to_string());
+    lines.join("\n")
+}
+
+/// `/forget <number>`: drop one remembered fact.
+pub(super) fn cmd_forget_fact(sender: &str, arg: &str) -> String {
+    let Ok(n) = arg.parse::<usize>() else {
+        return "Usage: /forget <number> (see /memories), or /forget to clear all memory"
+            .to_string();
+    };
+    let conn = match open_memory(sender) {
+        Ok(conn) => conn,
+        Err(msg) => return msg,
+    };
+    match crate::memory::messages::delete_fact(&conn, n) {
+        Some(fact) => format!("Forgot: {fact}"),
+        None => format!("No fact number {n}. Use /memories to see them."),
+    }
+}
+
+/// `/good` or `/bad [comment]`: rate the last reply.
+pub(super) fn cmd_feedback(sender: &str, rating: &str, comment: &str) -> String {
+    let conn = match open_memory(sender) {
+        Ok(conn) => conn,
+        Err(msg) => return msg,
+    };
+    match crate::memory::messages::rate_last_reply(&conn, rating, comment) {
+        Some(reply) => {
+            let preview = crate::helpers::truncate(&reply, 60);
+            let saved = if comment.is_empty() {
+                ""
+            } else {
+                " (with your comment)"
+            };
+            format!("Marked the last reply as {rating}{saved}: \"{preview}\"")
+        }
+        None => "No reply to rate yet.".to_string(),
+    }
+}
+
 pub(super) fn cmd_pins(sender: &str) -> String {
-    let Ok(conn) = crate::memory::open_memory_db(sender) else {
-        return "Failed to access memory.".to_string();
+    let conn = match open_memory(sender) {
+        Ok(conn) => conn,
+        Err(msg) => return msg,
     };
     let pins = crate::memory::messages::list_pins(&conn);
     if pins.is_empty() {
@@ -87,12 +454,57 @@ pub(super) fn cmd_pins(sender: &str) -> String {
     lines.join("\n")
 }
 
+/// `/template add <name> <text>`, `/template remove <name>`, or `/templates`
+/// to list them. `/t <name>` uses one.
+pub(super) fn cmd_template(sender: &str, arg: &str) -> String {
+    const USAGE: &str =
+        "Usage: /template add <name> <text>, /template remove <name> or /templates\n\
+         Placeholders: {attachment} (the file you send with /t, or your last one), \
+         {file} (the newest workspace file), {input} (text after the name)";
+    let (sub, rest) = arg.split_once(' ').unwrap_or((arg, ""));
+    let conn = match open_memory(sender) {
+        Ok(conn) => conn,
+        Err(msg) => return msg,
+    };
+    match (sub, rest.trim()) {
+        ("" | "list", "") => {
+            let templates = crate::memory::messages::list_templates(&conn);
+            if templates.is_empty() {
+                return format!("No templates yet.\n{USAGE}");
+            }
+            let mut lines = vec![format!("Templates ({}):", templates.len())];
+            for (name, body) in &templates {
+                let body = crate::helpers::truncate(&body.replace('\n', " "), 60);
+                lines.push(format!("  {name}: {body}"));
+            }
+            lines.push("Use one with /t <name> [text].".to_string());
+            lines.join("\n")
+        }
+        ("add", rest) => {
+            let Some((name, body)) = rest.split_once(char::is_whitespace) else {
+                return USAGE.to_string();
+            };
+            crate::memory::messages::save_template(&conn, name, body.trim());
+            format!("Saved template '{name}'. Use it with /t {name}.")
+        }
+        ("remove", name) if !name.is_empty() => {
+            if crate::memory::messages::delete_template(&conn, name) {
+                format!("Removed template '{name}'.")
+            } else {
+                format!("No template named '{name}'.")
+            }
+        }
+        _ => USAGE.to_string(),
+    }
+}
+
 pub(super) fn cmd_recall(state: &State, sender: &str, label: &str) -> String {
     if label.is_empty() {
         return "Usage: /recall <label>".to_string();
     }
-    let Ok(conn) = crate::memory::open_memory_db(sender) else {
-        return "Failed to access memory.".to_string();
+    let conn = match open_memory(sender) {
+        Ok(conn) => conn,
+        Err(msg) => return msg,
     };
     match crate::memory::messages::get_pin(&conn, label) {
         Some(content) => {
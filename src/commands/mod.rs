@@ -1,65 +1,63 @@
 mod admin;
+mod integrations;
 mod memory_cmds;
+mod reports;
 mod scheduling;
+mod workspace;
 
 use admin::*;
+use integrations::*;
 use memory_cmds::*;
+use reports::*;
 use scheduling::*;
+use workspace::*;
 
 // Re-export pub(crate) items so main.rs can access them via `commands::`
-pub(crate) use admin::handle_unauthorized;
-pub(crate) use scheduling::{deliver_due_cron_jobs, deliver_due_reminders};
+pub(crate) use admin::{handle_unauthorized, reply_to_unknown};
+pub(crate) use reports::deliver_due_reports;
+pub(crate) use scheduling::{
+    deliver_due_cron_jobs, deliver_due_feeds, deliver_due_followups, deliver_due_reminders,
+};
+pub(crate) use workspace::remove_session_workdir;
 
 use std::path::PathBuf;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
-use tracing::{error, info, warn};
+use tokio::sync::{mpsc, Mutex, Notify};
+use tracing::{debug, error, info, warn};
 
+use crate::confirmation::Answer;
+use crate::constants::{OVERLOAD_ALERT_COOLDOWN_SECS, STREAM_PROGRESS_INTERVAL_SECS};
 use crate::error::AppError;
-use crate::helpers::{looks_truncated, merge_messages};
+use crate::helpers::{is_command, looks_truncated, merge_messages, truncate};
 use crate::memory::{
-    export_config, forget_with_counts, inject_context, memory_status, save_memory,
-    store_message_pair,
+    export_config, forget_with_counts, inject_context, save_memory, store_message_pair,
 };
+use crate::preferences::Verbosity;
+use crate::ratelimit::RateClass;
+use crate::resources::Overload;
 use crate::signal::{classify_attachment, AttachmentType};
 use crate::state::{State, TokenBucket};
+use crate::stickers::StickerStatus;
+use crate::traits::ClaudeReply;
+use crate::tts::VoiceMode;
 
-/// Download and classify attachments, returning file paths and whether audio was found.
+/// Screen, download and classify attachments, returning file paths and
+/// whether audio was found. Refused attachments are reported to the sender.
 pub(crate) async fn download_attachments(
     state: &State,
     reply_to: &str,
     raw_attachments: &[crate::signal::AttachmentInfo],
 ) -> (Vec<PathBuf>, bool) {
+    let screening = &state.config.screening;
     let mut file_paths = Vec::new();
     let mut has_audio = false;
     for att in raw_attachments {
-        match classify_attachment(&att.content_type) {
-            AttachmentType::Image | AttachmentType::Document => {
-                match state.download_attachment(att).await {
-                    Ok(path) => file_paths.push(path),
-                    Err(e) => {
-                        error!("Failed to download attachment {}: {e}", att.id);
-                        let _ = state
-                            .send_message(reply_to, &format!("Failed to download attachment: {e}"))
-                            .await;
-                    }
-                }
-            }
-            AttachmentType::Audio => match state.download_attachment(att).await {
-                Ok(path) => {
-                    has_audio = true;
-                    file_paths.push(path);
-                }
-                Err(e) => {
-                    error!("Failed to download audio {}: {e}", att.id);
-                    let _ = state
-                        .send_message(reply_to, &format!("Failed to download voice message: {e}"))
-                        .await;
-                }
-            },
-            AttachmentType::Other => {
+        let kind = classify_attachment(&att.content_type);
+        let refused = match screening.check_declared(&kind, att) {
+            Err(refusal) => Some(refusal),
+            Ok(()) if kind == AttachmentType::Other => {
                 info!("Unsupported attachment type: {}", att.content_type);
                 let _ = state
                     .send_message(
@@ -67,99 +65,268 @@ pub(crate) async fn download_attachments(
                         &format!("Unsupported attachment type: {}", att.content_type),
                     )
                     .await;
+                continue;
             }
+            Ok(()) => match state.download_attachment(att).await {
+                Ok(path) => match screening.check_downloaded(&kind, att, &path).await {
+                    Ok(()) => {
+                        has_audio |= kind == AttachmentType::Audio;
+                        file_paths.push(crate::attachments::keep(state, reply_to, att, path));
+                        None
+                    }
+                    Err(refusal) => {
+                        let _ = std::fs::remove_file(&path);
+                        Some(refusal)
+                    }
+                },
+                Err(e) if kind == AttachmentType::Audio => {
+                    error!("Failed to download audio {}: {e}", att.id);
+                    let _ = state
+                        .send_message(reply_to, &format!("Failed to download voice message: {e}"))
+                        .await;
+                    None
+                }
+                Err(e) => {
+                    error!("Failed to download attachment {}: {e}", att.id);
+                    let _ = state
+                        .send_message(reply_to, &format!("Failed to download attachment: {e}"))
+                        .await;
+                    None
+                }
+            },
+        };
+        if let Some(refusal) = refused {
+            warn!(sender = %reply_to, attachment = %att.id, "{refusal}");
+            let _ = state.send_message(reply_to, &refusal).await;
         }
     }
     (file_paths, has_audio)
 }
 
-/// Buffer a message for debounce; spawn flush timer if needed.
-pub(crate) fn buffer_debounced(state: &Arc<State>, reply_to: &str, message_text: &str) {
+/// Buffer a message for debounce; spawn flush timer if needed. In a group,
+/// each `speaker` gets their own buffer and the flushed turn leaves the
+/// raise-hand queue once answered. Returns true if this message started a
+/// new burst.
+pub(crate) fn buffer_debounced(
+    state: &Arc<State>,
+    reply_to: &str,
+    message_text: &str,
+    sent_at: Option<i64>,
+    speaker: Option<&str>,
+) -> bool {
+    let key = crate::turns::buffer_key(reply_to, speaker);
     {
         let mut entry = state
             .debounce
             .buffers
-            .entry(reply_to.to_string())
+            .entry(key.clone())
             .or_insert_with(|| (Vec::new(), Instant::now()));
-        entry.0.push(message_text.to_string());
+        entry.0.push((message_text.to_string(), sent_at));
         entry.1 = Instant::now();
     }
-    if state
-        .debounce
-        .active
-        .insert(reply_to.to_string(), ())
-        .is_none()
-    {
-        let state = Arc::clone(state);
-        let reply_to = reply_to.to_string();
-        let debounce_ms = state.config.debounce_ms;
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_millis(debounce_ms)).await;
-                let should_flush = state
-                    .debounce
-                    .buffers
-                    .get(&reply_to)
-                    .map(|e| e.1.elapsed() >= Duration::from_millis(debounce_ms))
-                    .unwrap_or(true);
-                if should_flush {
-                    break;
-                }
-            }
-            state.debounce.active.remove(&reply_to);
-            let messages = state
+    if state.debounce.active.insert(key.clone(), ()).is_some() {
+        return false;
+    }
+    let state = Arc::clone(state);
+    let reply_to = reply_to.to_string();
+    let speaker = speaker.map(str::to_string);
+    let window = Duration::from_millis(state.settings().debounce_ms);
+    let wake = Arc::new(Notify::new());
+    state.debounce.wake.insert(key.clone(), Arc::clone(&wake));
+    // The first message of the burst shows how long is left.
+    let first = state.last_inbound.get(&reply_to).map(|t| t.clone());
+    tokio::spawn(async move {
+        let mut shown = None;
+        loop {
+            let left = state
                 .debounce
                 .buffers
-                .remove(&reply_to)
-                .map(|(_, (msgs, _))| msgs)
+                .get(&key)
+                .map(|e| window.saturating_sub(e.1.elapsed()))
                 .unwrap_or_default();
-            if messages.is_empty() {
-                return;
+            if left.is_zero() {
+                break;
+            }
+            let secs = left.as_millis().div_ceil(1000) as u64;
+            if let Some(first) = &first {
+                let emoji = countdown_emoji(secs);
+                if shown != Some(emoji) {
+                    state.react(&reply_to, first, emoji).await;
+                    shown = Some(emoji);
+                }
+            }
+            // Until the count drops, or the flush keyword arrives.
+            let tick = left.saturating_sub(Duration::from_secs(secs - 1));
+            tokio::select! {
+                _ = tokio::time::sleep(tick) => {}
+                _ = wake.notified() => break,
+            }
+        }
+        state.debounce.active.remove(&key);
+        state.debounce.wake.remove(&key);
+        if let (Some(first), Some(_)) = (&first, shown) {
+            state.react(&reply_to, first, "▶️").await;
+        }
+        let flushed = state.debounce.buffers.remove(&key);
+        match flushed.filter(|(_, (m, _))| !m.is_empty()) {
+            Some((_, (messages, last_received))) => {
+                let texts: Vec<String> = messages.into_iter().map(|(text, _)| text).collect();
+                info!(sender = %reply_to, count = texts.len(), "Debounced messages flushed");
+                // The burst waits for a worker like any other message; the
+                // worker lowers the speaker's hand once it's answered.
+                enqueue_inbound(
+                    &state,
+                    crate::inbox::Inbound {
+                        text: merge_messages(&texts),
+                        reply_to,
+                        attachments: Vec::new(),
+                        // Reply time counts from the last message of the burst.
+                        received: last_received,
+                        speaker,
+                        // Too late to delete one message out of a merged burst.
+                        sent_at: None,
+                    },
+                );
             }
-            let merged = merge_messages(&messages);
-            info!(sender = %reply_to, count = messages.len(), "Debounced messages flushed");
-            if let Err(e) = handle_message(&state, &reply_to, &merged, &[]).await {
-                error!("Error handling message from {reply_to}: {e}");
-                let _ = state.send_message(&reply_to, &format!("Error: {e}")).await;
+            None => {
+                if let Some(speaker) = speaker {
+                    crate::turns::lower(&state, &reply_to, &speaker);
+                }
             }
+        }
+    });
+    true
+}
+
+/// Queue `inbound` for the inbound workers. If the queue is full, the
+/// message shed to make room gives up its turn and its sender is told, at
+/// most once a minute.
+pub(crate) fn enqueue_inbound(state: &Arc<State>, inbound: crate::inbox::Inbound) {
+    let Some(shed) = state.inbox.push(inbound) else {
+        return;
+    };
+    warn!(sender = %shed.reply_to, depth = state.inbox.depth(), "Inbound queue full, shedding a message");
+    if let Some(speaker) = &shed.speaker {
+        crate::turns::lower(state, &shed.reply_to, speaker);
+    }
+    if state.inbox.should_notify(&shed.reply_to) {
+        let state = Arc::clone(state);
+        tokio::spawn(async move {
+            let _ = state
+                .send_message(
+                    &shed.reply_to,
+                    &format!(
+                        "I'm swamped with messages right now and had to skip yours (\"{}\"). Please send it again in a few minutes.",
+                        truncate(&shed.text, 40)
+                    ),
+                )
+                .await;
         });
     }
 }
 
+/// Seconds left in a debounce window, as a reaction.
+fn countdown_emoji(secs: u64) -> &'static str {
+    const KEYCAPS: [&str; 10] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣", "9️⃣", "🔟"];
+    KEYCAPS
+        .get((secs as usize).wrapping_sub(1))
+        .copied()
+        .unwrap_or("⏳")
+}
+
+/// A message that's just the `--flush-keyword` sends the burst waiting in
+/// the debounce window straight away. Returns true if there was one; the
+/// keyword itself isn't passed on.
+pub(crate) fn flush_debounced(
+    state: &State,
+    reply_to: &str,
+    message_text: &str,
+    speaker: Option<&str>,
+) -> bool {
+    let keyword = state.config.flush_keyword.as_str();
+    if keyword.is_empty() || !message_text.trim().eq_ignore_ascii_case(keyword) {
+        return false;
+    }
+    let key = crate::turns::buffer_key(reply_to, speaker);
+    let Some(wake) = state.debounce.wake.get(&key).map(|w| Arc::clone(&w)) else {
+        return false;
+    };
+    debug!(sender = %reply_to, "Debounce flushed by keyword");
+    wake.notify_one();
+    true
+}
+
 pub(crate) fn handle_command(state: &State, sender: &str, text: &str) -> Option<String> {
     let text = text.trim();
-    // /reset and /more are handled in handle_message (need async)
+    // /reset, /carry, /confirm, /more, /compact, /get, /put and /gh are handled in handle_message (need async)
     let (cmd, arg) = match text.split_once(' ') {
         Some((c, a)) => (c, a.trim()),
         None => (text, ""),
     };
+    if ADMIN_COMMANDS.contains(&cmd) && !state.is_admin(sender) {
+        return Some(format!("Only the account owner can use {cmd}."));
+    }
     match cmd {
         "/help" => Some(cmd_help()),
-        "/status" => Some(cmd_status(state, sender)),
-        "/pending" => Some(cmd_pending(state)),
+        "/status" => Some(cmd_status(state, sender, arg)),
+        "/pending" => Some(cmd_pending(state, sender)),
+        "/senders" => Some(cmd_senders(state, sender)),
         "/allow" => Some(cmd_allow(state, arg)),
         "/revoke" => Some(cmd_revoke(state, arg)),
+        "/invite" => Some(cmd_invite(state, sender)),
+        "/bind" => Some(cmd_bind(state, sender, arg)),
+        "/group" => Some(cmd_group(state, sender, arg)),
+        "/tail" => Some(cmd_tail(state, sender, arg)),
+        "/config" => Some(cmd_config(state, sender, arg)),
+        "/key" => Some(cmd_key(state, sender, arg)),
         "/model" => Some(cmd_model(state, sender, arg)),
-        "/memory" => Some(memory_status(sender)),
-        "/forget" => Some(forget_with_counts(sender)),
+        "/agent" => Some(cmd_agent(state, sender, arg)),
+        "/prefs" => Some(cmd_prefs(state, sender, arg)),
+        "/set" => Some(cmd_prefs(state, sender, &format!("set {arg}"))),
+        "/stop" => Some(cmd_stop(state, sender)),
+        "/unstick" => Some(cmd_unstick(state, sender, arg)),
+        "/redaction" => Some(cmd_redaction(state, sender, arg)),
+        "/private" => Some(cmd_private(state, sender, arg)),
+        "/format" => Some(cmd_format(state, sender, arg)),
+        "/voice" => Some(cmd_voice(state, sender, arg)),
+        "/memory" => Some(cmd_memory(sender, arg)),
+        "/forget" if arg.is_empty() => Some(forget_with_counts(sender)),
+        "/forget" => Some(cmd_forget_fact(sender, arg)),
+        "/remember" => Some(cmd_remember(sender, arg)),
+        "/memories" => Some(cmd_memories(sender)),
         "/search" => Some(cmd_search(sender, arg)),
-        "/export-config" => Some(export_config(&state.allowed_ids, &state.config.account)),
+        "/export-config" => Some(export_config(
+            &state.access.allowed_ids,
+            &state.config.account,
+        )),
         "/export" => Some(cmd_export(sender)),
         "/audit" => Some(cmd_audit()),
         "/usage" => Some(cmd_usage(state, sender)),
+        "/cost" => Some(cmd_cost(state, sender)),
         "/pin" => Some(cmd_pin(sender, arg)),
+        "/context" => Some(cmd_context(state, sender, arg)),
         "/pins" => Some(cmd_pins(sender)),
+        "/template" => Some(cmd_template(sender, arg)),
+        "/templates" | "/t" if arg.is_empty() => Some(cmd_template(sender, "")),
+        "/good" => Some(cmd_feedback(sender, "good", arg)),
+        "/bad" => Some(cmd_feedback(sender, "bad", arg)),
         "/recall" => Some(cmd_recall(state, sender, arg)),
         "/remind" => Some(cmd_remind(sender, arg)),
         "/reminders" => Some(cmd_reminders(sender)),
         "/cancel" => Some(cmd_cancel_reminder(sender, arg)),
+        "/followup" => Some(cmd_followup(sender, arg)),
+        "/followups" => Some(cmd_followups(sender, arg)),
         "/crons" => Some(cmd_crons(sender)),
         "/cron-cancel" => Some(cmd_cron_cancel(sender, arg)),
+        "/subscriptions" => Some(cmd_subscriptions(sender)),
+        "/unsubscribe" => Some(cmd_unsubscribe(sender, arg)),
         "/cron-pause" => Some(cmd_cron_pause(sender, arg)),
         "/cron-resume" => Some(cmd_cron_resume(sender, arg)),
         "/cron" => Some(cmd_cron(sender, arg)),
         "/every" => Some(cmd_every(sender, arg)),
         "/daily" => Some(cmd_daily(sender, arg)),
+        "/files" => Some(cmd_files(state, sender, arg)),
+        "/attachments" => Some(crate::attachments::describe(state, sender)),
         _ => None,
     }
 }
@@ -172,8 +339,34 @@ async fn handle_more(state: &State, sender: &str) -> Result<(), AppError> {
     Ok(())
 }
 
+/// `/continue`: give a run that stopped at the budget another
+/// `max_budget` on the same session.
+async fn handle_continue(state: &State, sender: &str) -> Result<(), AppError> {
+    if let Some((_, session_id)) = state.session_mgr.budget_hits.remove(sender) {
+        return handle_continuation(state, sender, &session_id).await;
+    }
+    state
+        .send_message(sender, "Nothing stopped at the budget.")
+        .await
+}
+
 async fn handle_reset(state: &State, sender: &str) -> Result<(), AppError> {
+    state.session_tokens.remove(sender);
+    state.compacted_context.remove(sender);
+    state.agents.remove(sender);
+    state.pinned_files.clear(sender);
+    let mut msg = "Session reset. Next message starts a fresh conversation.".to_string();
+    let mut summarized = false;
     if let Some((_, session)) = state.session_mgr.sessions.remove(sender) {
+        remove_session_workdir(sender, &session.session_id);
+        if crate::privacy::end(state, sender, &session.session_id) {
+            return state
+                .send_message(
+                    sender,
+                    "Private session ended; nothing from it was kept. Next message starts a fresh conversation.",
+                )
+                .await;
+        }
         let model = session.model.clone();
         if let Some(summary) = state
             .claude_runner
@@ -182,63 +375,492 @@ async fn handle_reset(state: &State, sender: &str) -> Result<(), AppError> {
         {
             save_memory(sender, &summary);
             info!(sender = %sender, "Saved memory on reset");
+            summarized = true;
         }
+        state.pending_carries.insert(
+            sender.to_string(),
+            (session.session_id, model, Instant::now()),
+        );
+        msg.push_str("\nReply /carry to bring over a summary of the previous conversation.");
     }
+    state.send_message(sender, &msg).await?;
+    if summarized {
+        crate::starters::offer(state, sender).await;
+    }
+    Ok(())
+}
+
+/// `/carry`: summarize the conversation ended by the last `/reset` and seed
+/// the next message with it, the same way compaction does.
+async fn handle_carry(state: &State, sender: &str) -> Result<(), AppError> {
+    let Some((_, (session_id, model, reset_at))) = state.pending_carries.remove(sender) else {
+        return state
+            .send_message(
+                sender,
+                "Nothing to carry over. /carry works just after /reset.",
+            )
+            .await;
+    };
+    if reset_at.elapsed() > Duration::from_secs(crate::constants::CARRY_TTL_SECS) {
+        return state
+            .send_message(
+                sender,
+                "The previous conversation is too old to carry over now.",
+            )
+            .await;
+    }
+    let _ = state.set_typing(sender, true).await;
+    let summary = state
+        .claude_runner
+        .summarize_session(&session_id, &model)
+        .await;
+    let _ = state.set_typing(sender, false).await;
+    let Some(summary) = summary else {
+        return state
+            .send_message(sender, "Couldn't summarize the previous conversation.")
+            .await;
+    };
+    info!(sender = %sender, old_session = %session_id, "Carried summary over after reset");
+    state
+        .compacted_context
+        .insert(sender.to_string(), summary.clone());
     state
         .send_message(
             sender,
-            "Session reset. Next message starts a fresh conversation.",
+            &format!("Carrying over:\n{summary}\n\nYour next message continues from here."),
         )
         .await
 }
 
-/// Returns true if the sender is rate-limited and should not proceed.
-async fn check_rate_limit(state: &State, sender: &str) -> Result<bool, AppError> {
-    if let Some((cap, rate)) = state.config.rate_limit_config {
+/// `/confirm`: run the message held back by [`hold_for_confirmation`].
+async fn handle_confirm(state: &State, sender: &str) -> Result<(), AppError> {
+    let Some((_, pending)) = state.pending_confirms.remove(sender) else {
+        return state.send_message(sender, "Nothing to confirm.").await;
+    };
+    if pending.asked_at.elapsed() > Duration::from_secs(crate::constants::CONFIRM_TTL_SECS) {
+        cleanup_attachments(&pending.attachments);
+        return state
+            .send_message(
+                sender,
+                "That request is too old to confirm now. Send it again.",
+            )
+            .await;
+    }
+    run_conversation_with(
+        state,
+        sender,
+        &pending.text,
+        &pending.attachments,
+        pending.context.as_deref(),
+        true,
+    )
+    .await
+}
+
+/// `/retry`: run again the prompts a crash or restart cut off.
+async fn handle_retry(state: &State, sender: &str) -> Result<(), AppError> {
+    let prompts = crate::journal::take_interrupted(sender);
+    if prompts.is_empty() {
+        return state.send_message(sender, "Nothing to retry.").await;
+    }
+    rerun(state, sender, prompts).await
+}
+
+/// Run interrupted prompts again, oldest first, as if just received.
+async fn rerun(state: &State, sender: &str, prompts: Vec<String>) -> Result<(), AppError> {
+    for prompt in prompts {
+        info!(sender = %sender, "Re-running interrupted prompt");
+        if check_rate_limit(state, sender, RateClass::Claude).await? {
+            return Ok(());
+        }
+        run_conversation(state, sender, &prompt, &[]).await?;
+    }
+    Ok(())
+}
+
+/// Startup: tell each sender whose prompt the last process died on, or with
+/// `--rerun-interrupted` run it again.
+pub(crate) async fn recover_interrupted(state: &State, lost: Vec<(String, usize)>) {
+    for (sender, count) in lost {
+        warn!(sender = %sender, count, "Prompt interrupted by the last shutdown");
+        let result = if state.config.rerun_interrupted {
+            let prompts = crate::journal::take_interrupted(&sender);
+            rerun(state, &sender, prompts).await
+        } else {
+            state
+                .send_message(&sender, &crate::journal::interrupted_notice(count))
+                .await
+        };
+        if let Err(e) = result {
+            warn!(sender = %sender, "Recovering interrupted prompt failed: {e}");
+        }
+    }
+}
+
+/// Returns true if the sender is rate-limited for `class` and should not proceed.
+async fn check_rate_limit(state: &State, sender: &str, class: RateClass) -> Result<bool, AppError> {
+    if let Some((cap, rate)) = state.rate_limit_for(sender, class) {
         let mut bucket = state
             .rate_limits
-            .entry(sender.to_string())
+            .entry((sender.to_string(), class))
             .or_insert_with(|| TokenBucket::new(cap, rate));
+        // Pick up limits changed by a config reload.
+        bucket.capacity = cap;
+        bucket.rate_per_sec = rate;
         if !bucket.try_consume() {
-            warn!(sender = %sender, "Rate limited");
+            drop(bucket);
+            warn!(sender = %sender, class = class.as_str(), "Rate limited");
+            state.metrics.rate_limited.incr();
             state
                 .send_message(
                     sender,
                     "Rate limited. Please wait before sending more messages.",
                 )
                 .await?;
+            state.send_sticker(sender, StickerStatus::RateLimited).await;
+            crate::receipts::rejected(state, sender).await;
             return Ok(true);
         }
     }
     Ok(false)
 }
 
+/// Commands that change or reveal who may use the bridge: the account
+/// owner's alone, whoever else is allowed.
+const ADMIN_COMMANDS: [&str; 5] = ["/allow", "/revoke", "/pending", "/audit", "/export-config"];
+
+/// Commands for getting out of trouble. They skip rate limiting and any
+/// pending choice or confirmation, so they answer even when the sender is
+/// rate limited or a run is going.
+const CONTROL_COMMANDS: [&str; 3] = ["/stop", "/unstick", "/status"];
+
+fn is_control_command(text: &str) -> bool {
+    let cmd = text.split_whitespace().next().unwrap_or_default();
+    CONTROL_COMMANDS.contains(&cmd)
+}
+
 pub(crate) async fn handle_message(
     state: &State,
     sender: &str,
     text: &str,
     attachments: &[PathBuf],
 ) -> Result<(), AppError> {
+    if is_control_command(text) {
+        if let Some(response) = handle_command(state, sender, text) {
+            return state.send_message(sender, &response).await;
+        }
+    }
+    let chosen = crate::choices::take(state, sender, text);
+    let text = chosen.as_deref().unwrap_or(text);
+    let confirmed = match crate::confirmation::take(state, sender, text) {
+        Some(Answer::Confirmed(command)) => Some(command),
+        Some(Answer::Declined(command)) => {
+            return state
+                .send_message(sender, &format!("OK, not running {command}."))
+                .await;
+        }
+        Some(Answer::Expired(command)) => {
+            return state
+                .send_message(
+                    sender,
+                    &format!("Too late to confirm {command}; send it again if you still want it."),
+                )
+                .await;
+        }
+        Some(Answer::Moved(command)) => {
+            info!(sender = %sender, command = %command, "Unconfirmed command dropped");
+            None
+        }
+        None => None,
+    };
+    let text = confirmed.as_deref().unwrap_or(text);
+    if is_command(text) && check_rate_limit(state, sender, RateClass::Command).await? {
+        return Ok(());
+    }
+    if confirmed.is_none() && crate::confirmation::needs_confirmation(state, text) {
+        let question = crate::confirmation::ask(state, sender, text);
+        return state.send_message(sender, &question).await;
+    }
     if text.trim() == "/more" {
         return handle_more(state, sender).await;
     }
+    if text.trim() == "/continue" {
+        return handle_continue(state, sender).await;
+    }
     if text.trim() == "/reset" {
         return handle_reset(state, sender).await;
     }
+    if text.trim() == "/carry" {
+        return handle_carry(state, sender).await;
+    }
+    if text.trim() == "/confirm" {
+        return handle_confirm(state, sender).await;
+    }
+    if text.trim() == "/compact" {
+        return handle_compact(state, sender).await;
+    }
+    if text.trim() == "/retry" {
+        return handle_retry(state, sender).await;
+    }
+    if let Some(arg) = text.trim().strip_prefix("/get") {
+        if arg.is_empty() || arg.starts_with(' ') {
+            return handle_get(state, sender, arg.trim()).await;
+        }
+    }
+    if let Some(arg) = text.trim().strip_prefix("/put") {
+        if arg.is_empty() || arg.starts_with(' ') {
+            return handle_put(state, sender, arg.trim(), attachments).await;
+        }
+    }
+    if text.trim() == "/screenshot" {
+        return handle_screenshot(state, sender).await;
+    }
+    if let Some(clip) = parse_clip(text) {
+        if state.is_admin(sender) {
+            return handle_clip(state, sender, clip).await;
+        }
+        // Someone else's "clip: ..." is just a message.
+        if text.trim_start().starts_with('/') {
+            return state
+                .send_message(sender, "Only the account owner can use /clip.")
+                .await;
+        }
+    }
     if let Some(response) = handle_command(state, sender, text) {
         state.send_message(sender, &response).await?;
         return Ok(());
     }
-    if check_rate_limit(state, sender).await? {
+    if let Some(arg) = text.trim().strip_prefix("/subscribe") {
+        if arg.is_empty() || arg.starts_with(' ') {
+            return handle_subscribe(state, sender, arg.trim()).await;
+        }
+    }
+    if let Some(arg) = text.trim().strip_prefix("/report") {
+        if arg.is_empty() || arg.starts_with(' ') {
+            return handle_report(state, sender, arg.trim()).await;
+        }
+    }
+    if let Some(args) = text.trim().strip_prefix("/maintenance") {
+        if args.is_empty() || args.starts_with(' ') {
+            return handle_maintenance(state, sender, args.trim()).await;
+        }
+    }
+    if let Some(args) = text.trim().strip_prefix("/ha") {
+        if args.is_empty() || args.starts_with(' ') {
+            return handle_ha(state, sender, args.trim()).await;
+        }
+    }
+    let expanded;
+    let (text, attachments) = match text.trim().strip_prefix("/t ") {
+        Some(args) => match crate::templates::expand(state, sender, args.trim(), attachments) {
+            Ok(filled) => {
+                expanded = filled;
+                (expanded.0.as_str(), expanded.1.as_slice())
+            }
+            Err(msg) => {
+                cleanup_attachments(attachments);
+                return state.send_message(sender, &msg).await;
+            }
+        },
+        None => (text, attachments),
+    };
+    if check_rate_limit(state, sender, RateClass::Claude).await? {
         return Ok(());
     }
     if check_injection_guard(state, sender, text).await? {
         return Ok(());
     }
 
+    if let Some(question) = text.trim().strip_prefix("/gh") {
+        if question.is_empty() || question.starts_with(' ') {
+            return handle_gh(state, sender, question.trim()).await;
+        }
+    }
+    if let Some(command) = claude_command(state, text) {
+        return run_claude_command(state, sender, &command).await;
+    }
+
     run_conversation(state, sender, text, attachments).await
 }
 
+/// The Claude slash command a message asks for: anything after the
+/// `--claude-command-prefix`, or (with `--slash-passthrough`) a slash command
+/// ccchat didn't handle itself.
+fn claude_command(state: &State, text: &str) -> Option<String> {
+    let text = text.trim();
+    if state
+        .model_router
+        .read()
+        .is_ok_and(|router| router.is_routing_command(text))
+    {
+        return None;
+    }
+    let prefix = state.config.claude_command_prefix.as_str();
+    let command = match text.strip_prefix(prefix) {
+        Some(rest) if !prefix.is_empty() => format!("/{}", rest.trim_start_matches('/')),
+        _ if state.config.slash_passthrough => text.to_string(),
+        _ => return None,
+    };
+    let name = command
+        .strip_prefix('/')?
+        .split(char::is_whitespace)
+        .next()?;
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':'));
+    valid.then_some(command)
+}
+
+/// Run one of Claude Code's slash commands (built in, or from
+/// `.claude/commands`) in the sender's session. The prompt goes to claude
+/// untouched: memory and other context would hide the leading slash.
+async fn run_claude_command(state: &State, sender: &str, command: &str) -> Result<(), AppError> {
+    info!(sender = %sender, command = %truncate(crate::privacy::loggable(state, sender, command), 40), "Claude slash command");
+    let _ = state.set_typing(sender, true).await;
+    let (session_id, model, lock, _is_new) = state.get_or_create_session(sender);
+    let system_prompt = state.get_system_prompt(sender);
+    let _guard = lock_session(state, sender, &lock).await?;
+    let slot = state.acquire_run_slot(sender).await?;
+    let result = call_claude(
+        state,
+        sender,
+        command,
+        &session_id,
+        &model,
+        &[],
+        &system_prompt,
+    )
+    .await;
+    drop(slot);
+    let _ = state.set_typing(sender, false).await;
+    if let Ok(ref reply) = result {
+        if !crate::privacy::is_private(state, sender) {
+            store_message_pair(sender, command, &reply.text, &session_id);
+        }
+    }
+    send_claude_response(state, sender, result, &session_id, command).await
+}
+
+/// The overload action and reason if the machine is over a
+/// `resource_limits` threshold.
+async fn overloaded(state: &State) -> Option<(Overload, String)> {
+    let limits = state.resource_limits.read().ok()?.clone();
+    if !limits.is_set() {
+        return None;
+    }
+    let snap = tokio::task::spawn_blocking(crate::resources::snapshot)
+        .await
+        .ok()?;
+    limits
+        .exceeded(&snap)
+        .map(|reason| (limits.when_exceeded, reason))
+}
+
+/// Returns true (and holds the run for `/confirm`) if the prompt's estimated
+/// input cost is over `--confirm-above-usd`.
+async fn hold_for_confirmation(
+    state: &State,
+    sender: &str,
+    text: &str,
+    attachments: &[PathBuf],
+    context: Option<&str>,
+    prompt: &str,
+    model: &str,
+) -> Result<bool, AppError> {
+    let threshold = state.config.confirm_above_usd;
+    if threshold <= 0.0 {
+        return Ok(false);
+    }
+    let (tokens, cost) = crate::estimate::estimate(prompt, attachments, model);
+    if cost <= threshold {
+        return Ok(false);
+    }
+    info!(sender = %sender, tokens, cost, "Holding expensive run for /confirm");
+    let pending = crate::estimate::PendingRun {
+        text: text.to_string(),
+        attachments: attachments.to_vec(),
+        context: context.map(str::to_string),
+        asked_at: Instant::now(),
+    };
+    if let Some(previous) = state.pending_confirms.insert(sender.to_string(), pending) {
+        cleanup_attachments(&previous.attachments);
+    }
+    let _ = state.set_typing(sender, false).await;
+    crate::webhook::notify(
+        state,
+        "approval_requested",
+        sender,
+        &format!("Run of about ${cost:.2} waits for /confirm"),
+    );
+    state
+        .send_message(
+            sender,
+            &format!(
+                "This will cost roughly ${cost:.2} ({} tokens in, on {model}) before Claude writes anything. Reply /confirm to proceed.",
+                crate::streaming::format_tokens(tokens)
+            ),
+        )
+        .await?;
+    Ok(true)
+}
+
+/// Returns true (and queues or turns away the request) if the machine is
+/// overloaded. Requests with attachments can't be queued, so they're
+/// turned away.
+async fn check_resources(
+    state: &State,
+    sender: &str,
+    prompt: &str,
+    has_attachments: bool,
+) -> Result<bool, AppError> {
+    let Some((action, reason)) = overloaded(state).await else {
+        return Ok(false);
+    };
+    warn!(sender = %sender, "Machine overloaded: {reason}");
+    // Private prompts can't wait on disk either.
+    let queueable = !has_attachments && !crate::privacy::is_private(state, sender);
+    let msg = if action == Overload::Queue && queueable {
+        if let Ok(qconn) = crate::queue::open_queue_db() {
+            crate::queue::enqueue(&qconn, sender, prompt, "[]");
+        }
+        state.metrics.overload_queued.incr();
+        OVERLOAD_QUEUED_MSG
+    } else {
+        state.metrics.overload_rejected.incr();
+        OVERLOAD_REJECTED_MSG
+    };
+    alert_overload(state, sender, &reason).await;
+    state.send_message(sender, msg).await?;
+    Ok(true)
+}
+
+/// Tell the account owner about an overload, at most once per cooldown.
+async fn alert_overload(state: &State, sender: &str, reason: &str) {
+    // The owner already got the overload message themselves.
+    let admin = &state.config.account;
+    if sender == admin {
+        return;
+    }
+    let now = crate::helpers::epoch_now() as u64;
+    let last = state.metrics.overload_alerted_at.load(Ordering::Relaxed);
+    if now.saturating_sub(last) < OVERLOAD_ALERT_COOLDOWN_SECS
+        || state
+            .metrics
+            .overload_alerted_at
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+    let _ = state
+        .send_message(
+            admin,
+            &format!("⚠️ Machine overloaded ({reason}); holding off on Claude runs."),
+        )
+        .await;
+}
+
 /// Returns true (and sends a block message) if the injection guard fires.
 async fn check_injection_guard(state: &State, sender: &str, text: &str) -> Result<bool, AppError> {
     if let Some(ref lakera_key) = state.config.lakera_api_key {
@@ -258,6 +880,12 @@ fn build_prompt(state: &State, sender: &str, text: &str, is_new_session: bool) -
     } else {
         text.to_string()
     };
+    let base_prompt = match state.compacted_context.remove(sender) {
+        Some((_, summary)) => format!(
+            "[Summary of earlier conversation]\n{summary}\n\n[Current message]\n{base_prompt}"
+        ),
+        None => base_prompt,
+    };
     let recalled = state.pending_recalls.remove(sender).map(|(_, v)| v);
     if let Some(ref pin_content) = recalled {
         format!("[Recalled context]\n{pin_content}\n\n[Current message]\n{base_prompt}")
@@ -266,15 +894,121 @@ fn build_prompt(state: &State, sender: &str, text: &str, is_new_session: bool) -
     }
 }
 
-/// Remove temporary attachment files after the Claude call.
+/// Mask secrets in the prompt and any text attachments, unless the sender opted out.
+fn redact_input(state: &State, sender: &str, text: &str, attachments: &[PathBuf]) -> String {
+    if state.redaction_off.contains_key(sender) {
+        return text.to_string();
+    }
+    let (redacted, count) = state.redactor.redact(text);
+    let count = count + state.redactor.redact_files(attachments);
+    if count > 0 {
+        info!(sender = %sender, count, "Redacted secrets before sending to Claude");
+    }
+    redacted
+}
+
+/// Remove temporary attachment files after the Claude call. Kept
+/// attachments stay until evicted.
 fn cleanup_attachments(attachments: &[PathBuf]) {
     for path in attachments {
+        if crate::attachments::is_kept(path) {
+            continue;
+        }
         if let Err(e) = std::fs::remove_file(path) {
             tracing::debug!("Failed to remove temp file {}: {e}", path.display());
         }
     }
 }
 
+/// Summarize the current session into memory and continue in a fresh session
+/// seeded with that summary. Workspace files move to the new session.
+/// Caller must hold the session lock. Returns false if there was nothing to
+/// compact or summarization failed.
+async fn compact_session(state: &State, sender: &str) -> bool {
+    let Some((old_id, model)) = state
+        .session_mgr
+        .sessions
+        .get(sender)
+        .map(|s| (s.session_id.clone(), s.model.clone()))
+    else {
+        return false;
+    };
+    let Some(summary) = state.claude_runner.summarize_session(&old_id, &model).await else {
+        warn!(sender = %sender, "Compaction skipped: summarization failed");
+        return false;
+    };
+    if !crate::privacy::is_private(state, sender) {
+        save_memory(sender, &summary);
+    }
+    let new_id = rotate_session(state, sender, &old_id);
+    state.compacted_context.insert(sender.to_string(), summary);
+    info!(sender = %sender, old_session = %old_id, new_session = %new_id, "Session compacted");
+    true
+}
+
+/// Move the sender onto a fresh session id, carrying the workspace over.
+/// Caller must hold the session lock. Returns the new id.
+fn rotate_session(state: &State, sender: &str, old_id: &str) -> String {
+    let new_id = uuid::Uuid::new_v4().to_string();
+    let old_dir = crate::helpers::session_workdir(sender, old_id);
+    if old_dir.exists() {
+        if let Err(e) = std::fs::rename(&old_dir, crate::helpers::session_workdir(sender, &new_id))
+        {
+            warn!(sender = %sender, "Failed to carry workspace to new session: {e}");
+        }
+    }
+    if let Some(mut session) = state.session_mgr.sessions.get_mut(sender) {
+        session.session_id = new_id.clone();
+        session.message_count = 0;
+    }
+    state.session_mgr.truncated_sessions.remove(sender);
+    state.session_mgr.budget_hits.remove(sender);
+    state.session_tokens.remove(sender);
+    new_id
+}
+
+/// Record the session's context size and compact once it nears the window.
+async fn maybe_auto_compact(state: &State, sender: &str, tokens: u64) -> Result<(), AppError> {
+    state.session_tokens.insert(sender.to_string(), tokens);
+    if tokens < crate::constants::AUTO_COMPACT_TOKENS {
+        return Ok(());
+    }
+    if compact_session(state, sender).await {
+        state
+            .send_message(
+                sender,
+                "Context compacted. Continuing in a fresh session seeded with a summary.",
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+async fn handle_compact(state: &State, sender: &str) -> Result<(), AppError> {
+    let Some(lock) = state
+        .session_mgr
+        .sessions
+        .get(sender)
+        .map(|s| s.lock.clone())
+    else {
+        return state
+            .send_message(sender, "No active session to compact.")
+            .await;
+    };
+    let _ = state.set_typing(sender, true).await;
+    let compacted = {
+        let _guard = lock_session(state, sender, &lock).await?;
+        compact_session(state, sender).await
+    };
+    let _ = state.set_typing(sender, false).await;
+    let msg = if compacted {
+        "Context compacted. Continuing in a fresh session seeded with a summary."
+    } else {
+        "Couldn't compact this session (summary failed). Try again or /reset."
+    };
+    state.send_message(sender, msg).await
+}
+
 /// Check if enough messages have passed to trigger an auto-summarize.
 async fn maybe_auto_summarize(state: &State, sender: &str, session_id: &str, model: &str) {
     let should_summarize = {
@@ -309,37 +1043,403 @@ async fn run_conversation(
     text: &str,
     attachments: &[PathBuf],
 ) -> Result<(), AppError> {
+    run_conversation_with(state, sender, text, attachments, None, false).await
+}
+
+/// [`run_conversation`] with extra context (e.g. the `/gh` digest) placed
+/// before the message. The context goes to Claude but not into history.
+/// `confirmed` skips the `/confirm` check for expensive prompts.
+async fn run_conversation_with(
+    state: &State,
+    sender: &str,
+    text: &str,
+    attachments: &[PathBuf],
+    context: Option<&str>,
+    confirmed: bool,
+) -> Result<(), AppError> {
+    let (received, extra) = (text, context);
+    if crate::memory::lock::is_locked(sender) {
+        cleanup_attachments(attachments);
+        return state
+            .send_message(sender, crate::memory::lock::LOCKED_NOTICE)
+            .await;
+    }
     let _ = state.set_typing(sender, true).await;
-    let (session_id, model, lock, is_new_session) = state.get_or_create_session(sender);
-    let prompt = build_prompt(state, sender, text, is_new_session);
+    let (named, text) = match crate::named_sessions::addressed(text) {
+        Some((name, rest)) => (Some(name), rest),
+        None => (None, text),
+    };
+    let (session_id, model, lock, is_new_session) = match &named {
+        Some(name) => crate::named_sessions::session(state, sender, name),
+        None => state.get_or_create_session(sender),
+    };
+    let route = state.route_model(sender, text, &model);
+    let (mut model, text) = (route.model, route.text);
+    if let Some(reason) = route.reason {
+        info!(sender = %sender, model = %model, "Routed by {reason}");
+        let verbose = state
+            .session_mgr
+            .sessions
+            .get(sender)
+            .is_some_and(|s| s.prefs.verbosity == Verbosity::Detailed);
+        if verbose {
+            let _ = state
+                .send_message(sender, &format!("Using {model} ({reason})."))
+                .await;
+        }
+    }
+    if let Some(msg) = workdir_quota_exceeded(state, sender, &session_id) {
+        let _ = state.set_typing(sender, false).await;
+        cleanup_attachments(attachments);
+        return state.send_message(sender, &msg).await;
+    }
+    let text = &redact_input(state, sender, text, attachments);
+    // Given context, pinned files, fetched pages and provider context reach
+    // Claude too, so they're masked alike.
+    let given = context.map(|c| redact_input(state, sender, c, &[]));
+    let pages = crate::fetch::fetch_context(
+        &state.config.fetch,
+        &state.config.net,
+        text,
+        crate::privacy::is_private(state, sender),
+    )
+    .await
+    .map(|pages| redact_input(state, sender, &pages, &[]));
+    let pinned =
+        crate::context::files::gather(state, sender, &state.workdir_for(sender, &session_id))
+            .await
+            .map(|pinned| redact_input(state, sender, &pinned, &[]));
+    let provided = crate::context::gather(state, sender, text)
+        .await
+        .map(|p| redact_input(state, sender, &p, &[]));
+    let ocr = if state.config.ocr {
+        crate::ocr::extract_text(attachments)
+            .await
+            .map(|ocr| redact_input(state, sender, &ocr, &[]))
+    } else {
+        None
+    };
+    let context: Vec<&str> = [
+        pinned.as_deref(),
+        given.as_deref(),
+        provided.as_deref(),
+        pages.as_deref(),
+        ocr.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let with_context = if context.is_empty() {
+        text.clone()
+    } else {
+        format!("{}\n\n[Current message]\n{text}", context.join("\n\n"))
+    };
+    if !confirmed
+        && hold_for_confirmation(
+            state,
+            sender,
+            received,
+            attachments,
+            extra,
+            &with_context,
+            &model,
+        )
+        .await?
+    {
+        return Ok(());
+    }
+    let mut prompt = build_prompt(state, sender, &with_context, is_new_session);
+    if check_resources(state, sender, &prompt, !attachments.is_empty()).await? {
+        let _ = state.set_typing(sender, false).await;
+        cleanup_attachments(attachments);
+        return Ok(());
+    }
 
-    let _guard = lock.lock().await;
+    let private = crate::privacy::is_private(state, sender);
+    // A locked memory's owner wouldn't want their prompt left on disk in the clear
+    let journaled = !private && !crate::memory::lock::has_lock(sender);
+    let _journal = journaled.then(|| crate::journal::Entry::begin(sender, received));
+    let _guard = lock_session(state, sender, &lock).await?;
+    let slot = state.acquire_run_slot(sender).await?;
     let system_prompt = state.get_system_prompt(sender);
-    let call_start = Instant::now();
-    let result = state
-        .claude_runner
-        .run_claude(
+    let mut session_id = session_id;
+    let mut result = call_claude(
+        state,
+        sender,
+        &prompt,
+        &session_id,
+        &model,
+        attachments,
+        &system_prompt,
+    )
+    .await;
+    if let Err(AppError::UnknownSession(_)) = result {
+        // claude's session store was pruned; start over from stored memory
+        // rather than letting the conversation silently restart.
+        warn!(sender = %sender, session = %session_id, "Claude lost the session, recreating it");
+        session_id = match &named {
+            Some(name) => crate::named_sessions::rotate(state, sender, name),
+            None => rotate_session(state, sender, &session_id),
+        };
+        prompt = build_prompt(state, sender, &with_context, true);
+        state.send_message(sender, SESSION_REPAIRED_MSG).await?;
+        result = call_claude(
+            state,
+            sender,
             &prompt,
             &session_id,
             &model,
             attachments,
-            sender,
-            state.config.max_budget,
             &system_prompt,
         )
         .await;
-    state.record_latency(call_start.elapsed().as_millis() as u64);
+    }
+    if let Err(e) = &result {
+        if let Some(fallback) = crate::probe::report_failure(state, &model, e).await {
+            warn!(sender = %sender, model = %model, "Model unavailable, retrying on {fallback}");
+            model = fallback;
+            result = call_claude(
+                state,
+                sender,
+                &prompt,
+                &session_id,
+                &model,
+                attachments,
+                &system_prompt,
+            )
+            .await;
+        }
+    }
+    drop(slot);
 
     cleanup_attachments(attachments);
     let _ = state.set_typing(sender, false).await;
 
-    if let Ok((ref response, _)) = result {
-        info!(sender = %sender, response_len = response.len(), "Reply sent");
-        store_message_pair(sender, text, response, &session_id);
-        maybe_auto_summarize(state, sender, &session_id, &model).await;
+    let mut context_tokens = None;
+    if let Ok(ref reply) = result {
+        info!(sender = %sender, response_len = reply.text.len(), "Reply sent");
+        if !private {
+            store_message_pair(sender, text, &reply.text, &session_id);
+        }
+        // Summaries and compaction follow the main session only.
+        if named.is_none() && !private {
+            maybe_auto_summarize(state, sender, &session_id, &model).await;
+            context_tokens = reply.context_tokens;
+        }
+    }
+    if let (Some(name), Ok(reply)) = (&named, &mut result) {
+        reply.text = format!("@{name}: {}", reply.text);
     }
 
-    send_claude_response(state, sender, result, &session_id, &prompt).await
+    send_claude_response(state, sender, result, &session_id, &prompt).await?;
+    if let Some(tokens) = context_tokens {
+        maybe_auto_compact(state, sender, tokens).await?;
+    }
+    Ok(())
+}
+
+/// Wait for the sender's session lock, giving up after `--stuck-run-mins` so
+/// a hung run can't hold every later message forever.
+async fn lock_session<'a>(
+    state: &State,
+    sender: &str,
+    lock: &'a Mutex<()>,
+) -> Result<tokio::sync::MutexGuard<'a, ()>, AppError> {
+    tokio::time::timeout(state.config.stuck_after, lock.lock())
+        .await
+        .map_err(|_| {
+            warn!(sender = %sender, "Timed out waiting for the session lock");
+            AppError::Other(STILL_BUSY_MSG.to_string())
+        })
+}
+
+/// One timed `claude` run in the sender's session workspace, which `/stop`
+/// can cancel.
+async fn call_claude(
+    state: &State,
+    sender: &str,
+    prompt: &str,
+    session_id: &str,
+    model: &str,
+    attachments: &[PathBuf],
+    system_prompt: &str,
+) -> Result<ClaudeReply, AppError> {
+    let call_start = Instant::now();
+    let stop = Arc::new(Notify::new());
+    state
+        .active_runs
+        .insert(sender.to_string(), Arc::clone(&stop));
+    state
+        .run_progress
+        .insert(sender.to_string(), Instant::now());
+    let work_dir = state.workdir_for(sender, session_id);
+    let max_budget = state.run_budget(sender);
+    let options = state.run_options(sender);
+    let result = if state.config.stream_progress {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let run = state.claude_runner.run_claude_streaming(
+            prompt,
+            session_id,
+            model,
+            attachments,
+            &work_dir,
+            max_budget,
+            system_prompt,
+            &options,
+            tx,
+        );
+        stream_with_progress(state, sender, run, rx, &stop).await
+    } else {
+        let run = state.claude_runner.run_claude(
+            prompt,
+            session_id,
+            model,
+            attachments,
+            &work_dir,
+            max_budget,
+            system_prompt,
+            &options,
+        );
+        tokio::select! {
+            result = run => result,
+            _ = stop.notified() => Err(AppError::Stopped),
+        }
+    };
+    if state
+        .active_runs
+        .remove_if(sender, |_, active| Arc::ptr_eq(active, &stop))
+        .is_some()
+    {
+        state.run_progress.remove(sender);
+    }
+    state.record_latency(call_start.elapsed().as_millis() as u64);
+    result
+}
+
+/// Drive a streaming run, passing the running totals on to the sender every
+/// [`STREAM_PROGRESS_INTERVAL_SECS`] and, if any were sent, the exact
+/// figure at the end.
+async fn stream_with_progress(
+    state: &State,
+    sender: &str,
+    run: impl std::future::Future<Output = Result<ClaudeReply, AppError>>,
+    mut progress: mpsc::UnboundedReceiver<crate::streaming::StreamProgress>,
+    stop: &Notify,
+) -> Result<ClaudeReply, AppError> {
+    let interval = Duration::from_secs(STREAM_PROGRESS_INTERVAL_SECS);
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    tokio::pin!(run);
+    let mut latest = None;
+    let mut reported = false;
+    let result = loop {
+        tokio::select! {
+            result = &mut run => break result,
+            _ = stop.notified() => break Err(AppError::Stopped),
+            Some(totals) = progress.recv() => {
+                if let Some(mut seen) = state.run_progress.get_mut(sender) {
+                    *seen = Instant::now();
+                }
+                latest = Some(totals);
+            }
+            _ = ticker.tick() => {
+                if let Some(totals) = latest.take() {
+                    let _ = state
+                        .send_message(sender, &crate::streaming::progress_message(totals))
+                        .await;
+                    let _ = state.set_typing(sender, true).await;
+                    reported = true;
+                }
+            }
+        }
+    };
+    if let (true, Ok(reply)) = (reported, &result) {
+        let _ = state
+            .send_message(
+                sender,
+                &crate::streaming::final_message(reply.usage.map(|u| u.tokens()), reply.cost_usd),
+            )
+            .await;
+    }
+    result
+}
+
+const OVERLOAD_QUEUED_MSG: &str = "The machine I run on is busy right now, so I've queued your message and will answer once it has room.";
+const OVERLOAD_REJECTED_MSG: &str =
+    "The machine I run on is busy right now. Please try again in a few minutes.";
+const STILL_BUSY_MSG: &str = "Your previous message is still being worked on, so I gave up on this one. Send /stop to cancel it, then try again.";
+const SESSION_REPAIRED_MSG: &str = "Claude no longer had this session, so I started a new one from your saved memory. Recent messages may need repeating.";
+
+/// Apply the sender's `/format` settings: code blocks and diagrams become
+/// images first (in `render` builds), then tables are redrawn.
+async fn format_response(state: &State, sender: &str, response: &str) -> crate::format::Formatted {
+    let prefs = state.format_prefs(sender);
+    #[cfg(feature = "render")]
+    let rendered = if prefs.code_images {
+        crate::render::render_blocks(response).await
+    } else {
+        crate::format::Formatted {
+            text: response.to_string(),
+            images: Vec::new(),
+        }
+    };
+    #[cfg(not(feature = "render"))]
+    let rendered = crate::format::Formatted {
+        text: response.to_string(),
+        images: Vec::new(),
+    };
+    let tables = crate::format::format_reply(
+        &rendered.text,
+        prefs.tables,
+        state.config.transport.renders_markdown(),
+    );
+    crate::format::Formatted {
+        text: tables.text,
+        images: rendered.images.into_iter().chain(tables.images).collect(),
+    }
+}
+
+/// Speak a reply and send it as audio. Returns false (after logging) when
+/// voice replies aren't configured or synthesis fails.
+async fn send_voice_note(state: &State, sender: &str, text: &str) -> bool {
+    let tts = &state.config.tts;
+    if tts.engine.is_none() {
+        return false;
+    }
+    let spoken = crate::tts::speakable(text);
+    if spoken.is_empty() {
+        return false;
+    }
+    let note = match crate::tts::synthesize(tts, &state.http, &spoken).await {
+        Ok(note) => note,
+        Err(e) => {
+            warn!(sender = %sender, "Speech synthesis failed: {e}");
+            return false;
+        }
+    };
+    match state
+        .signal_api
+        .send_attachment(sender, &note.data, note.content_type, note.filename)
+        .await
+    {
+        Ok(()) => true,
+        Err(e) => {
+            warn!(sender = %sender, "Voice note send failed: {e}");
+            false
+        }
+    }
+}
+
+/// Send the parts of a reply that were rendered as images.
+async fn send_rendered_images(state: &State, sender: &str, images: &[(String, Vec<u8>)]) {
+    for (filename, png) in images {
+        if let Err(e) = state
+            .signal_api
+            .send_attachment(sender, png, "image/png", filename)
+            .await
+        {
+            warn!(sender = %sender, file = %filename, "Image send failed: {e}");
+        }
+    }
 }
 
 /// Send any file references embedded in a Claude response as Signal attachments.
@@ -372,14 +1472,10 @@ async fn handle_claude_error(
     error: AppError,
     original_prompt: &str,
 ) -> Result<(), AppError> {
-    state.metrics.error_count.fetch_add(1, Ordering::Relaxed);
-    crate::webhook::fire_if_configured(
-        &state.config.webhook_url,
-        "error",
-        sender,
-        &error.to_string(),
-    );
-    if !original_prompt.is_empty() {
+    state.metrics.error_count.incr();
+    state.metrics.record_failure(error.category());
+    crate::webhook::notify(state, "error", sender, &error.to_string());
+    if !original_prompt.is_empty() && !crate::privacy::is_private(state, sender) {
         if let Ok(qconn) = crate::queue::open_queue_db() {
             crate::queue::enqueue(&qconn, sender, original_prompt, "[]");
         }
@@ -387,40 +1483,179 @@ async fn handle_claude_error(
     state
         .send_message(sender, &format!("Claude error: {error}"))
         .await?;
+    state
+        .send_sticker(sender, StickerStatus::from_failure(error.category()))
+        .await;
+    if error.category() == "budget" {
+        crate::receipts::rejected(state, sender).await;
+    }
     Ok(())
 }
 
+/// A run stopped at `--max-budget-usd`: send what it got so far and offer
+/// `/continue` instead of queueing the prompt to fail the same way again.
+async fn handle_budget_hit(
+    state: &State,
+    sender: &str,
+    partial: ClaudeReply,
+    session_id: &str,
+) -> Result<(), AppError> {
+    let cost = partial.cost_usd.unwrap_or(0.0);
+    info!(sender = %sender, cost_usd = cost, "Claude run stopped at budget");
+    state.metrics.record_failure("budget");
+    if cost > 0.0 {
+        state.add_sender_cost(sender, cost);
+    }
+    crate::ledger::record_run(sender, session_id, None, cost);
+    state
+        .session_mgr
+        .budget_hits
+        .insert(sender.to_string(), session_id.to_string());
+    let prompt = format!(
+        "(Budget hit — reply /continue to spend another ${:.2}.)",
+        state.run_budget(sender)
+    );
+    let msg = if partial.text.trim().is_empty() {
+        prompt
+    } else {
+        let formatted = format_response(state, sender, &partial.text).await;
+        format!("{}\n\n{prompt}", formatted.text)
+    };
+    state.send_long_message(sender, &msg).await?;
+    crate::receipts::rejected(state, sender).await;
+    crate::webhook::notify(
+        state,
+        "budget_threshold",
+        sender,
+        &format!(
+            "Run stopped at the ${:.2} per-message budget",
+            state.run_budget(sender)
+        ),
+    );
+    crate::webhook::check_daily_budget(state);
+    Ok(())
+}
+
+/// Model and cost line for senders who turned on `/prefs set footer on`,
+/// with what prompt caching saved when it saved anything.
+fn reply_footer(
+    state: &State,
+    sender: &str,
+    cost_usd: Option<f64>,
+    cache_saved: f64,
+) -> Option<String> {
+    let session = state.session_mgr.sessions.get(sender)?;
+    if !session.prefs.footer {
+        return None;
+    }
+    let cost = cost_usd.map(|c| format!(" · ${c:.4}")).unwrap_or_default();
+    let saved = if cache_saved > 0.00005 {
+        format!(" (cache saved ${cache_saved:.4})")
+    } else {
+        String::new()
+    };
+    Some(format!("\n\n— {}{cost}{saved}", session.model))
+}
+
 /// Send a Claude response: check truncation, store session for /more if needed, send to user.
 /// On error, enqueues the original prompt for background retry.
 async fn send_claude_response(
     state: &State,
     sender: &str,
-    result: Result<(String, Option<f64>), AppError>,
+    result: Result<ClaudeReply, AppError>,
     session_id: &str,
     original_prompt: &str,
 ) -> Result<(), AppError> {
     match result {
-        Ok((response, cost)) => {
-            if let Some(c) = cost {
+        Ok(ClaudeReply {
+            text: response,
+            cost_usd,
+            usage,
+            ..
+        }) => {
+            if let Some(c) = cost_usd {
                 state.add_cost(c);
                 state.add_sender_cost(sender, c);
                 info!(sender = %sender, cost_usd = c, total_cost_usd = state.total_cost_usd(), "Claude call completed");
             }
+            let cache_saved = usage.map_or(0.0, |usage| {
+                let model = state
+                    .session_mgr
+                    .sessions
+                    .get(sender)
+                    .map(|s| s.model.clone())
+                    .unwrap_or_default();
+                state.record_usage(sender, usage, &model)
+            });
+            let project = state.group_projects.get(sender).and_then(|dir| {
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            });
+            crate::ledger::record_run(sender, session_id, project, cost_usd.unwrap_or(0.0));
+            let mut response = apply_ha_actions(state, sender, response).await;
+            match crate::choices::number_options(&response) {
+                Some((numbered, options)) => {
+                    response = numbered;
+                    crate::choices::offer(state, sender, options);
+                }
+                None => crate::choices::clear(state, sender),
+            }
+            let mut formatted = format_response(state, sender, &response).await;
+            if let Some(diff) = crate::diff::for_reply(state, sender, original_prompt, &response) {
+                formatted.text = diff;
+            }
+            if let Some(max) = state.group_settings(sender).max_reply_chars {
+                formatted.text = crate::group_settings::cap_reply(&formatted.text, max);
+            }
+            if let Some(footer) = reply_footer(state, sender, cost_usd, cache_saved) {
+                formatted.text.push_str(&footer);
+            }
+            let voice = state.voice_mode(sender);
+            let spoken =
+                voice == VoiceMode::Only && send_voice_note(state, sender, &formatted.text).await;
             if looks_truncated(&response) {
                 state
                     .session_mgr
                     .truncated_sessions
                     .insert(sender.to_string(), session_id.to_string());
-                let msg =
-                    format!("{response}\n\n(Response may be truncated. Send /more to continue.)");
+                let msg = if spoken {
+                    "(Response may be truncated. Send /more to continue.)".to_string()
+                } else {
+                    format!(
+                        "{}\n\n(Response may be truncated. Send /more to continue.)",
+                        formatted.text
+                    )
+                };
                 state.send_long_message(sender, &msg).await?;
             } else {
                 state.session_mgr.truncated_sessions.remove(sender);
-                state.send_long_message(sender, &response).await?;
+                if !spoken {
+                    state.send_long_message(sender, &formatted.text).await?;
+                }
+            }
+            if voice == VoiceMode::On {
+                send_voice_note(state, sender, &formatted.text).await;
             }
+            send_rendered_images(state, sender, &formatted.images).await;
             send_file_attachments(state, sender, &response).await;
+            state.record_reply_latency();
+            state.send_sticker(sender, StickerStatus::Done).await;
+            crate::webhook::notify(
+                state,
+                "message_handled",
+                sender,
+                &format!("${:.4}", cost_usd.unwrap_or(0.0)),
+            );
+            crate::webhook::check_daily_budget(state);
             Ok(())
         }
+        Err(AppError::Stopped) => {
+            info!(sender = %sender, "Claude run stopped");
+            state.send_message(sender, "Stopped.").await
+        }
+        Err(AppError::BudgetExceeded(partial)) => {
+            handle_budget_hit(state, sender, partial, session_id).await
+        }
         Err(e) => handle_claude_error(state, sender, e, original_prompt).await,
     }
 }
@@ -437,7 +1672,7 @@ pub(crate) async fn handle_continuation(
         .sessions
         .get(sender)
         .map(|s| s.model.clone())
-        .unwrap_or_else(|| state.config.model.clone());
+        .unwrap_or_else(|| state.settings().model);
 
     let lock = state
         .session_mgr
@@ -447,7 +1682,8 @@ pub(crate) async fn handle_continuation(
         .unwrap_or_else(|| Arc::new(Mutex::new(())));
 
     let system_prompt = state.get_system_prompt(sender);
-    let _guard = lock.lock().await;
+    let _guard = lock_session(state, sender, &lock).await?;
+    let slot = state.acquire_run_slot(sender).await?;
     let result = state
         .claude_runner
         .run_claude(
@@ -455,11 +1691,13 @@ pub(crate) async fn handle_continuation(
             session_id,
             &model,
             &[],
-            sender,
-            state.config.max_budget,
+            &state.workdir_for(sender, session_id),
+            state.run_budget(sender),
             &system_prompt,
+            &state.run_options(sender),
         )
         .await;
+    drop(slot);
 
     let _ = state.set_typing(sender, false).await;
 
@@ -478,10 +1716,20 @@ pub(crate) async fn retry_pending_messages(state: &State) {
     let Ok(qconn) = crate::queue::open_queue_db() else {
         return;
     };
+    if let Some((_, reason)) = overloaded(state).await {
+        debug!("Retries held while overloaded: {reason}");
+        return;
+    }
     for (id, sender, content, _attachments) in crate::queue::get_pending(&qconn) {
+        // Retries only use idle slots; live messages come first.
+        let Some(_slot) = state.run_slots.try_acquire() else {
+            break;
+        };
         let (session_id, model, lock, _is_new) = state.get_or_create_session(&sender);
         let system_prompt = state.get_system_prompt(&sender);
-        let _guard = lock.lock().await;
+        let Ok(_guard) = lock_session(state, &sender, &lock).await else {
+            continue;
+        };
         match state
             .claude_runner
             .run_claude(
@@ -489,9 +1737,10 @@ pub(crate) async fn retry_pending_messages(state: &State) {
                 &session_id,
                 &model,
                 &[],
-                &sender,
-                state.config.max_budget,
+                &state.workdir_for(&sender, &session_id),
+                state.run_budget(&sender),
                 &system_prompt,
+                &state.run_options(&sender),
             )
             .await
         {
@@ -505,7 +1754,8 @@ pub(crate) async fn retry_pending_messages(state: &State) {
                     crate::queue::mark_completed(&qconn, id);
                 }
             }
-            Err(_) => {
+            Err(e) => {
+                state.metrics.record_failure(e.category());
                 crate::queue::increment_retry(&qconn, id);
             }
         }
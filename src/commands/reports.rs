@@ -0,0 +1,125 @@
+use tracing::warn;
+
+use crate::constants::{REPORT_DAYS, SECS_PER_DAY};
+use crate::error::AppError;
+use crate::report::Report;
+use crate::state::State;
+
+const REPORT_USAGE: &str = "Usage: /report [all] [chart]\n       /report weekly on [all]|off";
+
+/// What a `/report` message asks for.
+#[derive(Debug, PartialEq)]
+pub(super) enum ReportRequest {
+    /// Report now; `global` covers every sender.
+    Show {
+        global: bool,
+        chart: bool,
+    },
+    /// Weekly report on (everyone's if `global`).
+    WeeklyOn {
+        global: bool,
+    },
+    WeeklyOff,
+}
+
+pub(super) fn parse_report(arg: &str) -> Option<ReportRequest> {
+    let words: Vec<&str> = arg.split_whitespace().collect();
+    match words.as_slice() {
+        ["weekly", "on"] => Some(ReportRequest::WeeklyOn { global: false }),
+        ["weekly", "on", "all"] => Some(ReportRequest::WeeklyOn { global: true }),
+        ["weekly", "off"] => Some(ReportRequest::WeeklyOff),
+        words
+            if words
+                .iter()
+                .all(|w| matches!(*w, "all" | "chart" | "weekly")) =>
+        {
+            Some(ReportRequest::Show {
+                global: words.contains(&"all"),
+                chart: words.contains(&"chart"),
+            })
+        }
+        _ => None,
+    }
+}
+
+pub(super) async fn handle_report(state: &State, sender: &str, arg: &str) -> Result<(), AppError> {
+    let Some(request) = parse_report(arg) else {
+        return state.send_message(sender, REPORT_USAGE).await;
+    };
+    let global = matches!(
+        request,
+        ReportRequest::Show { global: true, .. } | ReportRequest::WeeklyOn { global: true }
+    );
+    if global && !state.is_admin(sender) {
+        return state
+            .send_message(sender, "Only the admin can see everyone's usage.")
+            .await;
+    }
+    match request {
+        ReportRequest::Show { global, chart } => send_report(state, sender, global, chart).await,
+        ReportRequest::WeeklyOn { global } => {
+            let conn = crate::schedule::open_schedule_db()?;
+            let reply = if crate::schedule::set_report_sub(&conn, sender, global) {
+                "Weekly report on. It arrives Mondays at 09:00 UTC; /report weekly off to stop."
+            } else {
+                "Couldn't schedule the weekly report."
+            };
+            state.send_message(sender, reply).await
+        }
+        ReportRequest::WeeklyOff => {
+            let conn = crate::schedule::open_schedule_db()?;
+            let reply = if crate::schedule::remove_report_sub(&conn, sender) {
+                "Weekly report off."
+            } else {
+                "You don't have a weekly report."
+            };
+            state.send_message(sender, reply).await
+        }
+    }
+}
+
+/// Send the last `REPORT_DAYS` of usage as text, plus a busiest-hours chart
+/// if asked.
+async fn send_report(
+    state: &State,
+    sender: &str,
+    global: bool,
+    chart: bool,
+) -> Result<(), AppError> {
+    let conn = crate::ledger::open_ledger_db()?;
+    let since = crate::helpers::epoch_now() - REPORT_DAYS * SECS_PER_DAY;
+    let runs = crate::ledger::runs_since(&conn, since, (!global).then_some(sender));
+    let report = Report::build(&runs, REPORT_DAYS);
+    state
+        .send_long_message(sender, &report.text(global))
+        .await?;
+    if chart && report.replies > 0 {
+        match report.hours_chart() {
+            Ok(png) => {
+                state
+                    .signal_api
+                    .send_attachment(sender, &png, "image/png", "report-hours.png")
+                    .await?
+            }
+            Err(e) => warn!(sender = %sender, "Report chart failed: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Send due weekly reports. Called periodically by the background loop.
+pub(crate) async fn deliver_due_reports(state: &State) {
+    let Ok(conn) = crate::schedule::open_schedule_db() else {
+        return;
+    };
+    for (sender, global) in crate::schedule::get_due_report_subs(&conn) {
+        if state.in_quiet_hours(&sender) {
+            continue;
+        }
+        crate::schedule::advance_report_sub(&conn, &sender);
+        let global = global && state.is_admin(&sender);
+        if let Err(e) = send_report(state, &sender, global, true).await {
+            warn!(sender = %sender, "Failed to deliver weekly report: {e}");
+        }
+    }
+}
@@ -1,3 +1,10 @@
+use crate::constants::{
+    FEED_DEFAULT_INTERVAL_SECS, FEED_MAX_ITEMS, FEED_MAX_PER_SENDER, FEED_MIN_INTERVAL_SECS,
+    FEED_SEEN_KEEP,
+};
+use crate::error::AppError;
+use crate::feeds::FeedItem;
+use crate::schedule::FeedSub;
 use crate::state::State;
 
 pub(super) fn cmd_remind(sender: &str, arg: &str) -> String {
@@ -52,6 +59,75 @@ pub(super) fn cmd_cancel_reminder(sender: &str, id_str: &str) -> String {
     }
 }
 
+const FOLLOWUP_USAGE: &str =
+    "Usage: /followup <time> <prompt>\n       /followup <time> --note <message>\n\
+     Examples: /followup 2h check whether the deploy finished\n          \
+     /followup 30m --note Move the car";
+
+pub(super) fn cmd_followup(sender: &str, arg: &str) -> String {
+    let Some((when, rest)) = arg.split_once(' ') else {
+        return FOLLOWUP_USAGE.to_string();
+    };
+    let Some(duration) = crate::helpers::parse_duration(when) else {
+        return format!("Invalid time format: '{when}'. Use 5m, 1h, 30s, 2d.");
+    };
+    let rest = rest.trim();
+    let (text, prompt) = match rest.strip_prefix("--note") {
+        Some(note) if note.is_empty() || note.starts_with(' ') => (note.trim(), false),
+        _ => (rest, true),
+    };
+    if text.is_empty() {
+        return FOLLOWUP_USAGE.to_string();
+    }
+    let Ok(conn) = crate::schedule::open_schedule_db() else {
+        return "Failed to access schedule database.".to_string();
+    };
+    let run_at = crate::helpers::epoch_now() + duration.as_secs() as i64;
+    let id = crate::schedule::add_followup(&conn, sender, text, prompt, run_at);
+    if id == 0 {
+        return "Failed to save the follow-up.".to_string();
+    }
+    let human = crate::helpers::format_duration_human(duration.as_secs());
+    if prompt {
+        format!("Follow-up #{id}: in {human} I'll run \"{text}\" and tell you what I find.")
+    } else {
+        format!("Follow-up #{id}: I'll message you in {human}: {text}")
+    }
+}
+
+/// `/followups` lists pending follow-ups; `/followups cancel <id>` drops one.
+pub(super) fn cmd_followups(sender: &str, arg: &str) -> String {
+    let Ok(conn) = crate::schedule::open_schedule_db() else {
+        return "Failed to access schedule database.".to_string();
+    };
+    if let Some(id) = arg.strip_prefix("cancel") {
+        let Ok(id) = id.trim().trim_start_matches('#').parse::<i64>() else {
+            return "Usage: /followups cancel <id>".to_string();
+        };
+        return if crate::schedule::remove_followup(&conn, id, sender) {
+            format!("Follow-up #{id} cancelled.")
+        } else {
+            format!("No pending follow-up #{id} found for you.")
+        };
+    }
+    if !arg.is_empty() {
+        return "Usage: /followups [cancel <id>]".to_string();
+    }
+    let followups = crate::schedule::get_followups(&conn, sender);
+    if followups.is_empty() {
+        return "No pending follow-ups.".to_string();
+    }
+    let now = crate::helpers::epoch_now();
+    let mut lines = vec![format!("Pending follow-ups ({}):", followups.len())];
+    for f in &followups {
+        let human = crate::helpers::format_duration_human((f.run_at - now).max(0) as u64);
+        let kind = if f.prompt { "run" } else { "note" };
+        lines.push(format!("  #{} - {kind}: {} (in {human})", f.id, f.text));
+    }
+    lines.push("Cancel one with /followups cancel <id>.".to_string());
+    lines.join("\n")
+}
+
 pub(super) fn cmd_cron(sender: &str, arg: &str) -> String {
     if arg.is_empty() {
         return "Usage: /cron \"0 9 * * MON\" <message>\n       /cron 0 9 * * MON <message>\nAll times are UTC.".to_string();
@@ -214,12 +290,269 @@ pub(super) fn cmd_cron_resume(sender: &str, id_str: &str) -> String {
     }
 }
 
+const SUBSCRIBE_USAGE: &str = "Usage: /subscribe <feed-url> [interval | \"cron\"] [summarize]\nExamples: /subscribe https://blog.rust-lang.org/feed.xml\n          /subscribe https://example.com/rss 6h summarize\n          /subscribe https://example.com/atom \"0 8 * * *\"\nChecked hourly by default; cron times are UTC.";
+
+/// `/subscribe` arguments: URL, schedule (cron pattern or interval) and
+/// whether to summarize.
+#[derive(Debug, PartialEq)]
+pub(super) struct Subscription {
+    pub(super) url: String,
+    pub(super) cron_pattern: Option<String>,
+    pub(super) interval_secs: Option<i64>,
+    pub(super) summarize: bool,
+}
+
+pub(super) fn parse_subscribe(arg: &str) -> Result<Subscription, String> {
+    let mut words: Vec<&str> = arg.split_whitespace().collect();
+    if words.is_empty() {
+        return Err(SUBSCRIBE_USAGE.to_string());
+    }
+    let url = words.remove(0).to_string();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("'{url}' isn't a feed URL.\n{SUBSCRIBE_USAGE}"));
+    }
+    let summarize = matches!(words.last(), Some(&"summarize" | &"--summarize"));
+    if summarize {
+        words.pop();
+    }
+    let (cron_pattern, interval_secs) = match words.as_slice() {
+        [] => (None, Some(FEED_DEFAULT_INTERVAL_SECS)),
+        [one] if crate::helpers::parse_interval_secs(one).is_some() => {
+            let secs = crate::helpers::parse_interval_secs(one).unwrap_or_default();
+            if secs < FEED_MIN_INTERVAL_SECS {
+                return Err(format!(
+                    "Feeds can be checked at most every {}.",
+                    crate::helpers::format_duration_human(FEED_MIN_INTERVAL_SECS as u64)
+                ));
+            }
+            (None, Some(secs))
+        }
+        _ => (Some(words.join(" ").trim_matches('"').to_string()), None),
+    };
+    Ok(Subscription {
+        url,
+        cron_pattern,
+        interval_secs,
+        summarize,
+    })
+}
+
+fn feed_schedule(cron_pattern: Option<&str>, interval_secs: Option<i64>) -> String {
+    match (cron_pattern, interval_secs) {
+        (Some(pattern), _) => crate::helpers::format_cron_human(pattern),
+        (None, Some(secs)) => format!(
+            "every {}",
+            crate::helpers::format_duration_human(secs as u64)
+        ),
+        (None, None) => String::new(),
+    }
+}
+
+/// `/subscribe <url> [schedule] [summarize]`: fetch the feed once to check
+/// it and mark what's already there as seen, then save the subscription.
+pub(super) async fn handle_subscribe(
+    state: &State,
+    sender: &str,
+    arg: &str,
+) -> Result<(), AppError> {
+    let reply = subscribe(state, sender, arg).await;
+    state.send_message(sender, &reply).await
+}
+
+async fn subscribe(state: &State, sender: &str, arg: &str) -> String {
+    let sub = match parse_subscribe(arg) {
+        Ok(sub) => sub,
+        Err(msg) => return msg,
+    };
+    let Ok(conn) = crate::schedule::open_schedule_db() else {
+        return "Failed to access schedule database.".to_string();
+    };
+    if crate::schedule::get_feeds(&conn, sender).len() >= FEED_MAX_PER_SENDER {
+        return format!(
+            "You already have {FEED_MAX_PER_SENDER} subscriptions. Remove one with /unsubscribe <id>."
+        );
+    }
+    let feed = match crate::fetch::fetch_feed(&state.config.fetch, &state.config.net, &sub.url)
+        .await
+        .and_then(|xml| crate::feeds::parse_feed(&xml))
+    {
+        Ok(feed) => feed,
+        Err(e) => return format!("Couldn't read that feed: {e}"),
+    };
+    let title = if feed.title.is_empty() {
+        sub.url.clone()
+    } else {
+        feed.title.clone()
+    };
+    let id = crate::schedule::add_feed(
+        &conn,
+        sender,
+        &sub.url,
+        &title,
+        sub.cron_pattern.as_deref(),
+        sub.interval_secs,
+        sub.summarize,
+    );
+    if id == 0 {
+        return format!(
+            "Invalid schedule: \"{}\"",
+            sub.cron_pattern.unwrap_or_default()
+        );
+    }
+    let ids: Vec<&str> = feed.items.iter().map(|i| i.id.as_str()).collect();
+    crate::schedule::mark_feed_items_seen(&conn, id, &ids, FEED_SEEN_KEEP);
+    crate::audit::log_action("feed_subscribe", sender, &format!("#{id} {}", sub.url));
+    let schedule = feed_schedule(sub.cron_pattern.as_deref(), sub.interval_secs);
+    let summarized = if sub.summarize {
+        ", summarized by Claude"
+    } else {
+        ""
+    };
+    format!(
+        "Subscribed #{id} to {title} ({} items now), checked {schedule}. New items will be sent here{summarized}.",
+        feed.items.len()
+    )
+}
+
+pub(super) fn cmd_subscriptions(sender: &str) -> String {
+    let Ok(conn) = crate::schedule::open_schedule_db() else {
+        return "Failed to access schedule database.".to_string();
+    };
+    let feeds = crate::schedule::get_feeds(&conn, sender);
+    if feeds.is_empty() {
+        return "No feed subscriptions. Add one with /subscribe <feed-url>.".to_string();
+    }
+    let mut lines = vec![format!("Feed subscriptions ({}):", feeds.len())];
+    for feed in &feeds {
+        let schedule = feed_schedule(feed.cron_pattern.as_deref(), feed.interval_secs);
+        let summarized = if feed.summarize { ", summarized" } else { "" };
+        lines.push(format!(
+            "  #{} {} — {} ({schedule}{summarized})",
+            feed.id, feed.title, feed.url
+        ));
+    }
+    lines.join("\n")
+}
+
+pub(super) fn cmd_unsubscribe(sender: &str, id_str: &str) -> String {
+    let id: i64 = match id_str.trim_start_matches('#').parse() {
+        Ok(id) => id,
+        Err(_) => return "Usage: /unsubscribe <id>".to_string(),
+    };
+    let Ok(conn) = crate::schedule::open_schedule_db() else {
+        return "Failed to access schedule database.".to_string();
+    };
+    if crate::schedule::remove_feed(&conn, id, sender) {
+        crate::audit::log_action("feed_unsubscribe", sender, &format!("#{id}"));
+        format!("Unsubscribed from feed #{id}.")
+    } else {
+        format!("No feed subscription #{id} found for you.")
+    }
+}
+
+/// Ask Claude for a digest of new feed items; None (plain list instead) if
+/// the run fails.
+async fn summarize_feed_items(
+    state: &State,
+    feed: &FeedSub,
+    items: &[&FeedItem],
+) -> Option<String> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let prompt = crate::feeds::summary_prompt(&feed.title, items);
+    let settings = state.settings();
+    let slot = state.acquire_run_slot(&feed.sender).await.ok()?;
+    let result = state
+        .claude_runner
+        .run_claude(
+            &prompt,
+            &session_id,
+            &settings.model,
+            &[],
+            &state.workdir_for(&feed.sender, &session_id),
+            settings.max_budget,
+            &state.get_system_prompt(&feed.sender),
+            &state.run_options(&feed.sender),
+        )
+        .await;
+    drop(slot);
+    super::remove_session_workdir(&feed.sender, &session_id);
+    match result {
+        Ok(reply) => {
+            if let Some(c) = reply.cost_usd {
+                state.add_cost(c);
+                state.add_sender_cost(&feed.sender, c);
+            }
+            Some(reply.text)
+        }
+        Err(e) => {
+            tracing::warn!(feed = feed.id, "Feed summary failed: {e}");
+            None
+        }
+    }
+}
+
+/// Check due feeds and send their new items. Called periodically by
+/// background loop.
+pub(crate) async fn deliver_due_feeds(state: &State) {
+    let Ok(conn) = crate::schedule::open_schedule_db() else {
+        return;
+    };
+    for feed in crate::schedule::get_due_feeds(&conn) {
+        if state.in_quiet_hours(&feed.sender) {
+            continue;
+        }
+        // Advance first so a broken feed waits for its next slot.
+        crate::schedule::advance_feed(&conn, &feed);
+        let parsed =
+            match crate::fetch::fetch_feed(&state.config.fetch, &state.config.net, &feed.url)
+                .await
+                .and_then(|xml| crate::feeds::parse_feed(&xml))
+            {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    tracing::warn!(feed = feed.id, url = %feed.url, "Feed check failed: {e}");
+                    continue;
+                }
+            };
+        let ids: Vec<&str> = parsed.items.iter().map(|i| i.id.as_str()).collect();
+        let new_ids = crate::schedule::mark_feed_items_seen(&conn, feed.id, &ids, FEED_SEEN_KEEP);
+        if new_ids.is_empty() {
+            continue;
+        }
+        let new: Vec<&FeedItem> = parsed
+            .items
+            .iter()
+            .filter(|i| new_ids.contains(&i.id))
+            .collect();
+        let shown = &new[..new.len().min(FEED_MAX_ITEMS)];
+        let more = new.len() - shown.len();
+        let summary = if feed.summarize {
+            summarize_feed_items(state, &feed, shown).await
+        } else {
+            None
+        };
+        let text = match summary {
+            Some(summary) if more > 0 => {
+                format!("New in {}:\n{summary}\n…and {more} more", feed.title)
+            }
+            Some(summary) => format!("New in {}:\n{summary}", feed.title),
+            None => crate::feeds::format_digest(&feed.title, shown, more),
+        };
+        if let Err(e) = state.send_long_message(&feed.sender, &text).await {
+            tracing::warn!(sender = %feed.sender, "Failed to deliver feed items: {e}");
+        }
+    }
+}
+
 /// Deliver due reminders. Called periodically by background loop.
 pub(crate) async fn deliver_due_reminders(state: &State) {
     let Ok(conn) = crate::schedule::open_schedule_db() else {
         return;
     };
     for (id, sender, message) in crate::schedule::get_due_reminders(&conn) {
+        if state.in_quiet_hours(&sender) {
+            continue;
+        }
         let text = format!("Reminder: {message}");
         if let Err(e) = state.send_message(&sender, &text).await {
             tracing::warn!(sender = %sender, "Failed to deliver reminder: {e}");
@@ -230,6 +563,44 @@ pub(crate) async fn deliver_due_reminders(state: &State) {
     crate::schedule::purge_delivered(&conn);
 }
 
+/// Run due follow-ups: prompts go through the sender's conversation, notes
+/// are sent as they are. Called periodically by background loop.
+pub(crate) async fn deliver_due_followups(state: &State) {
+    let Ok(conn) = crate::schedule::open_schedule_db() else {
+        return;
+    };
+    for followup in crate::schedule::get_due_followups(&conn) {
+        if state.in_quiet_hours(&followup.sender) {
+            continue;
+        }
+        // Removed before running, so one that fails isn't retried every tick
+        crate::schedule::remove_followup(&conn, followup.id, &followup.sender);
+        let result = if followup.prompt {
+            let ago = (crate::helpers::epoch_now() - followup.created_at).max(0) as u64;
+            let context = format!(
+                "[This is a follow-up the user scheduled {} ago with /followup. \
+                 Do what it asks now and report back.]",
+                crate::helpers::format_duration_human(ago)
+            );
+            super::run_conversation_with(
+                state,
+                &followup.sender,
+                &followup.text,
+                &[],
+                Some(&context),
+                false,
+            )
+            .await
+        } else {
+            let text = format!("Follow-up: {}", followup.text);
+            state.send_message(&followup.sender, &text).await
+        };
+        if let Err(e) = result {
+            tracing::warn!(sender = %followup.sender, id = followup.id, "Follow-up failed: {e}");
+        }
+    }
+}
+
 /// Deliver due cron jobs. Called periodically by background loop.
 pub(crate) async fn deliver_due_cron_jobs(state: &State) {
     let Ok(conn) = crate::schedule::open_schedule_db() else {
@@ -238,6 +609,9 @@ pub(crate) async fn deliver_due_cron_jobs(state: &State) {
     for (id, sender, message, cron_pattern, interval_secs) in
         crate::schedule::get_due_cron_jobs(&conn)
     {
+        if state.in_quiet_hours(&sender) {
+            continue;
+        }
         let text = format!("Scheduled: {message}");
         if let Err(e) = state.send_message(&sender, &text).await {
             tracing::warn!(sender = %sender, "Failed to deliver cron job: {e}");
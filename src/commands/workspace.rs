@@ -0,0 +1,372 @@
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::error::AppError;
+use crate::helpers::{
+    content_type_from_extension, dir_size, format_bytes, glob_match, list_files, new_path_in_dir,
+    resolve_in_dir, session_workdir,
+};
+use crate::state::State;
+
+const MAX_LISTED_FILES: usize = 50;
+/// Longest clipboard text sent back by `/clip`.
+const CLIP_MAX_CHARS: usize = 10_000;
+
+fn current_session_id(state: &State, sender: &str) -> Option<String> {
+    state
+        .session_mgr
+        .sessions
+        .get(sender)
+        .map(|s| s.session_id.clone())
+}
+
+pub(super) fn cmd_files(state: &State, sender: &str, pattern: &str) -> String {
+    let Some(session_id) = current_session_id(state, sender) else {
+        return "No active session.".to_string();
+    };
+    let mut files = list_files(&state.workdir_for(sender, &session_id));
+    let total: u64 = files.iter().map(|(_, size)| size).sum();
+    if !pattern.is_empty() {
+        files.retain(|(name, _)| glob_match(pattern, name));
+    }
+    if files.is_empty() {
+        return match pattern {
+            "" => "No files in your workspace.".to_string(),
+            _ => format!("No workspace files match {pattern}"),
+        };
+    }
+    let shown: u64 = files.iter().map(|(_, size)| size).sum();
+    let mut lines = vec![match pattern {
+        "" => format!(
+            "Workspace files ({}, {}):",
+            files.len(),
+            format_bytes(shown)
+        ),
+        _ => format!(
+            "Workspace files matching {pattern} ({}, {}):",
+            files.len(),
+            format_bytes(shown)
+        ),
+    }];
+    for (name, size) in files.iter().take(MAX_LISTED_FILES) {
+        lines.push(format!("  {name} ({})", format_bytes(*size)));
+    }
+    if files.len() > MAX_LISTED_FILES {
+        lines.push(format!("  ... and {} more", files.len() - MAX_LISTED_FILES));
+    }
+    let bound = state.group_projects.contains_key(sender);
+    if let Some(quota) = state.config.workdir_quota_bytes.filter(|_| !bound) {
+        lines.push(format!(
+            "Quota: {} of {}",
+            format_bytes(total),
+            format_bytes(quota)
+        ));
+    }
+    lines.push("Use /get <file> to download.".to_string());
+    lines.join("\n")
+}
+
+/// `/context add|list|remove`: files every prompt in the session includes.
+pub(super) fn cmd_context(state: &State, sender: &str, arg: &str) -> String {
+    const USAGE: &str =
+        "Usage: /context add <file> | /context list | /context remove <file or number>";
+    let Some(session_id) = current_session_id(state, sender) else {
+        return "No active session.".to_string();
+    };
+    let work_dir = state.workdir_for(sender, &session_id);
+    let name = |path: &std::path::Path| crate::context::files::display_name(&work_dir, path);
+    let (action, file) = match arg.split_once(' ') {
+        Some((action, file)) => (action, file.trim()),
+        None => (arg, ""),
+    };
+    match (action, file) {
+        ("" | "list", "") => {
+            let files = state.pinned_files.list(sender);
+            if files.is_empty() {
+                return "No files pinned. Use /context add <file> to keep one in every prompt."
+                    .to_string();
+            }
+            let mut lines = vec!["Pinned files:".to_string()];
+            for (i, path) in files.iter().enumerate() {
+                let size = std::fs::metadata(path).map(|m| m.len()).ok();
+                lines.push(match size {
+                    Some(size) => format!("{}. {} ({})", i + 1, name(path), format_bytes(size)),
+                    None => format!("{}. {} (missing)", i + 1, name(path)),
+                });
+            }
+            lines.join("\n")
+        }
+        ("add", file) if !file.is_empty() => {
+            let Some(path) = resolve_in_dir(&work_dir, file) else {
+                return format!("File not found in your workspace: {file}");
+            };
+            if !crate::context::files::is_text(&path) {
+                return format!("{file} isn't a text file.");
+            }
+            match state.pinned_files.add(sender, path) {
+                Ok(true) => {
+                    info!(sender = %sender, file = %file, "Pinned file to context");
+                    format!("Pinned {file}. Every prompt in this session will include it.")
+                }
+                Ok(false) => format!("{file} is already pinned."),
+                Err(e) => e,
+            }
+        }
+        ("remove", file) if !file.is_empty() => {
+            let index = file.parse::<usize>().ok();
+            let removed = state
+                .pinned_files
+                .remove(sender, |i, path| index == Some(i + 1) || name(path) == file);
+            match removed {
+                Some(path) => format!("Unpinned {}.", name(&path)),
+                None => format!("{file} isn't pinned. See /context list."),
+            }
+        }
+        _ => USAGE.to_string(),
+    }
+}
+
+/// Send a file from the sender's session workspace as an attachment.
+pub(super) async fn handle_get(state: &State, sender: &str, arg: &str) -> Result<(), AppError> {
+    if arg.is_empty() {
+        return state.send_message(sender, "Usage: /get <file>").await;
+    }
+    let Some(session_id) = current_session_id(state, sender) else {
+        return state.send_message(sender, "No active session.").await;
+    };
+    let Some(path) = resolve_in_dir(&state.workdir_for(sender, &session_id), arg) else {
+        return state
+            .send_message(sender, &format!("File not found: {arg}"))
+            .await;
+    };
+    let data = std::fs::read(&path)?;
+    let ct = content_type_from_extension(&path);
+    let fname = path.file_name().unwrap_or_default().to_string_lossy();
+    state
+        .signal_api
+        .send_attachment(sender, &data, ct, &fname)
+        .await
+}
+
+/// Save the attached file into the sender's session workspace at `arg`.
+pub(super) async fn handle_put(
+    state: &State,
+    sender: &str,
+    arg: &str,
+    attachments: &[PathBuf],
+) -> Result<(), AppError> {
+    let reply = put_file(state, sender, arg, attachments);
+    super::cleanup_attachments(attachments);
+    state.send_message(sender, &reply?).await
+}
+
+fn put_file(
+    state: &State,
+    sender: &str,
+    arg: &str,
+    attachments: &[PathBuf],
+) -> Result<String, AppError> {
+    let file = match attachments {
+        [] => return Ok("Attach a file to save it.\nUsage: /put <path>".to_string()),
+        [file] => file,
+        _ => return Ok("Attach one file at a time to /put.".to_string()),
+    };
+    if arg.is_empty() {
+        return Ok("Say where to save it.\nUsage: /put <path>".to_string());
+    }
+    let (session_id, ..) = state.get_or_create_session(sender);
+    let root = state.workdir_for(sender, &session_id);
+    let Some(dest) = new_path_in_dir(&root, arg) else {
+        return Ok(format!("Not a path inside your workspace: {arg}"));
+    };
+    let size = std::fs::metadata(file)?.len();
+    let bound = state.group_projects.contains_key(sender);
+    if let Some(quota) = state.config.workdir_quota_bytes.filter(|_| !bound) {
+        let used = dir_size(&session_workdir(sender, &session_id));
+        if used + size > quota {
+            return Ok(format!(
+                "That would take your workspace past its quota ({} used of {}).",
+                format_bytes(used),
+                format_bytes(quota)
+            ));
+        }
+    }
+    let parent = dest.parent().unwrap_or(&root);
+    // A symlink Claude left in the workspace mustn't lead the file, or the
+    // directories made for it, elsewhere: check what exists before creating
+    // the rest.
+    std::fs::create_dir_all(&root)?;
+    let existing = parent
+        .ancestors()
+        .find(|dir| dir.symlink_metadata().is_ok())
+        .unwrap_or(&root);
+    if !is_inside(existing, &root) {
+        return Ok(format!("Not a path inside your workspace: {arg}"));
+    }
+    std::fs::create_dir_all(parent)?;
+    if !is_inside(parent, &root) || dest.is_symlink() || dest.is_dir() {
+        return Ok(format!("Not a path inside your workspace: {arg}"));
+    }
+    std::fs::copy(file, &dest)?;
+    info!(sender = %sender, path = %arg, size, "Saved file to workspace");
+    Ok(format!("Saved {arg} ({}).", format_bytes(size)))
+}
+
+/// Whether `path` resolves, symlinks and all, to somewhere under `root`.
+fn is_inside(path: &Path, root: &Path) -> bool {
+    path.canonicalize()
+        .ok()
+        .zip(root.canonicalize().ok())
+        .is_some_and(|(path, root)| path.starts_with(root))
+}
+
+/// A clipboard request: `/clip`, or `/clip set <text>` / `clip: <text>`.
+#[derive(Debug, PartialEq)]
+pub(super) enum Clip<'a> {
+    Get,
+    Set(&'a str),
+}
+
+pub(super) fn parse_clip(text: &str) -> Option<Clip<'_>> {
+    let trimmed = text.trim();
+    if let Some(rest) = trimmed.strip_prefix("/clip") {
+        // "/clipboard" and the like aren't ours.
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Some(Clip::Get);
+        }
+        return match rest.strip_prefix("set") {
+            Some(value) if value.is_empty() || value.starts_with(char::is_whitespace) => {
+                Some(Clip::Set(value.trim()))
+            }
+            // Anything else gets the usage message.
+            _ => Some(Clip::Set("")),
+        };
+    }
+    // Phones capitalize the first letter, so "Clip:" counts too.
+    let prefix = trimmed.get(..5)?;
+    prefix
+        .eq_ignore_ascii_case("clip:")
+        .then(|| Clip::Set(trimmed[5..].trim()))
+}
+
+/// Read or set the bridge machine's clipboard.
+pub(super) async fn handle_clip(
+    state: &State,
+    sender: &str,
+    clip: Clip<'_>,
+) -> Result<(), AppError> {
+    let reply = match clip {
+        Clip::Get => match crate::clipboard::get_text().await {
+            Ok(text) if text.trim().is_empty() => "The clipboard is empty.".to_string(),
+            Ok(text) if text.chars().count() > CLIP_MAX_CHARS => format!(
+                "{}\n\n(Clipboard truncated to {CLIP_MAX_CHARS} characters.)",
+                text.chars().take(CLIP_MAX_CHARS).collect::<String>()
+            ),
+            Ok(text) => text,
+            Err(e) => {
+                warn!(sender = %sender, "Clipboard read failed: {e}");
+                format!("Couldn't read the clipboard: {e}")
+            }
+        },
+        Clip::Set("") => "Usage: /clip set <text> (or send clip: <text>)".to_string(),
+        Clip::Set(text) => {
+            let chars = text.chars().count();
+            match crate::clipboard::set_text(text.to_string()).await {
+                Ok(()) => {
+                    crate::audit::log_action("clip_set", sender, &format!("{chars} chars"));
+                    format!("Copied {chars} characters to the clipboard.")
+                }
+                Err(e) => {
+                    warn!(sender = %sender, "Clipboard write failed: {e}");
+                    format!("Couldn't set the clipboard: {e}")
+                }
+            }
+        }
+    };
+    state.send_long_message(sender, &reply).await
+}
+
+/// Send the account owner a capture of the bridge machine's screen.
+pub(super) async fn handle_screenshot(state: &State, sender: &str) -> Result<(), AppError> {
+    if !state.is_admin(sender) {
+        return state
+            .send_message(sender, "Only the account owner can use /screenshot.")
+            .await;
+    }
+    if !state.config.allow_screenshot {
+        return state
+            .send_message(
+                sender,
+                "Screenshots are off (start ccchat with --allow-screenshot, in a build with --features screenshot).",
+            )
+            .await;
+    }
+    capture_and_send(state, sender).await
+}
+
+#[cfg(feature = "screenshot")]
+async fn capture_and_send(state: &State, sender: &str) -> Result<(), AppError> {
+    match crate::screenshot::capture().await {
+        Ok(png) => {
+            crate::audit::log_action("screenshot", sender, "");
+            let name = format!(
+                "screenshot-{}.png",
+                chrono::Local::now().format("%Y%m%d-%H%M%S")
+            );
+            state
+                .signal_api
+                .send_attachment(sender, &png, "image/png", &name)
+                .await
+        }
+        Err(e) => {
+            warn!(sender = %sender, "Screenshot failed: {e}");
+            state
+                .send_message(sender, &format!("Couldn't take a screenshot: {e}"))
+                .await
+        }
+    }
+}
+
+#[cfg(not(feature = "screenshot"))]
+async fn capture_and_send(state: &State, sender: &str) -> Result<(), AppError> {
+    state
+        .send_message(sender, "This build doesn't include screenshots.")
+        .await
+}
+
+/// Returns a user-facing message if the session workspace is over its disk quota.
+pub(super) fn workdir_quota_exceeded(
+    state: &State,
+    sender: &str,
+    session_id: &str,
+) -> Option<String> {
+    // Bound project directories are the team's repo, not scratch space.
+    if state.group_projects.contains_key(sender) {
+        return None;
+    }
+    let quota = state.config.workdir_quota_bytes?;
+    let used = dir_size(&session_workdir(sender, session_id));
+    if used < quota {
+        return None;
+    }
+    warn!(sender = %sender, used, quota, "Workspace quota exceeded");
+    Some(format!(
+        "Workspace quota exceeded ({} of {}). Fetch what you need with /files and /get, then /reset to start a clean workspace.",
+        format_bytes(used),
+        format_bytes(quota)
+    ))
+}
+
+/// Delete a session's working directory once the session has ended.
+pub(crate) fn remove_session_workdir(sender: &str, session_id: &str) {
+    let dir = session_workdir(sender, session_id);
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            debug!("Failed to remove workdir {}: {e}", dir.display());
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! "Are you sure?" for destructive commands (`--confirm-commands`).
+//!
+//! A listed command (by default `/reset`, `/forget` and `/revoke`) doesn't
+//! act straight away: ccchat asks first and runs it only when the sender's
+//! next message is "yes" within [`CONFIRM_COMMAND_TTL_SECS`]. Anything else
+//! cancels it, so a fat-fingered `/reset` can't wipe a long session. Home
+//! Assistant actions Claude asks for wait the same way (see [`ask_for_claude`]).
+
+use std::time::{Duration, Instant};
+
+use crate::constants::CONFIRM_COMMAND_TTL_SECS;
+use crate::state::State;
+
+/// A command waiting for its sender to say yes.
+pub(crate) struct PendingCommand {
+    pub(crate) command: String,
+    pub(crate) asked_at: Instant,
+}
+
+/// What a message means for a command waiting to be confirmed.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Answer {
+    /// Run this command now.
+    Confirmed(String),
+    /// The sender said no; tell them and stop.
+    Declined(String),
+    /// Too late to say yes; tell them and stop.
+    Expired(String),
+    /// The message is something else: the command is dropped and the
+    /// message handled as usual.
+    Moved(String),
+}
+
+/// Whether the config says the command in `text` needs confirming.
+pub(crate) fn needs_confirmation(state: &State, text: &str) -> bool {
+    let cmd = text.split_whitespace().next().unwrap_or_default();
+    cmd.starts_with('/')
+        && state
+            .config
+            .confirm_commands
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(cmd))
+}
+
+/// Hold `text` until the sender confirms it; returns the question to send.
+pub(crate) fn ask(state: &State, sender: &str, text: &str) -> String {
+    let command = text.trim().to_string();
+    let question = format!(
+        "Are you sure you want to run {command}? Reply yes within {CONFIRM_COMMAND_TTL_SECS}s to go ahead."
+    );
+    hold(state, sender, command);
+    question
+}
+
+/// Hold `command`, which Claude wants to run as `sender`, until they confirm
+/// it; `what` says what it does ("unlock lock.front_door"). Returns the
+/// question to send.
+pub(crate) fn ask_for_claude(state: &State, sender: &str, command: &str, what: &str) -> String {
+    hold(state, sender, command.to_string());
+    format!("Claude wants to {what}. Reply yes within {CONFIRM_COMMAND_TTL_SECS}s to go ahead.")
+}
+
+fn hold(state: &State, sender: &str, command: String) {
+    state.pending_commands.insert(
+        sender.to_string(),
+        PendingCommand {
+            command,
+            asked_at: Instant::now(),
+        },
+    );
+}
+
+/// `text` as a yes/no reply: trimmed, lowercased, without a closing `.` or `!`.
+fn reply_word(text: &str) -> String {
+    text.trim().trim_end_matches(['.', '!']).to_lowercase()
+}
+
+/// Whether `text` is a yes or no to a command `sender` has pending. Group
+/// messages check this before they're attributed to their author.
+pub(crate) fn is_reply(state: &State, sender: &str, text: &str) -> bool {
+    state.pending_commands.contains_key(sender)
+        && matches!(reply_word(text).as_str(), "yes" | "y" | "no" | "n")
+}
+
+/// How `text` answers `sender`'s pending command, if there is one. Any
+/// answer uses it up.
+pub(crate) fn take(state: &State, sender: &str, text: &str) -> Option<Answer> {
+    let (_, pending) = state.pending_commands.remove(sender)?;
+    let command = pending.command;
+    let expired = pending.asked_at.elapsed() > Duration::from_secs(CONFIRM_COMMAND_TTL_SECS);
+    Some(match reply_word(text).as_str() {
+        "yes" | "y" if expired => Answer::Expired(command),
+        "yes" | "y" => Answer::Confirmed(command),
+        "no" | "n" => Answer::Declined(command),
+        _ => Answer::Moved(command),
+    })
+}
+
+/// `--confirm-commands` entries as command words (`reset` -> `/reset`).
+pub(crate) fn parse_commands(list: &[String]) -> Vec<String> {
+    list.iter()
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .map(|c| format!("/{}", c.trim_start_matches('/')))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_parse_commands() {
+        let list = vec!["/reset".to_string(), " forget ".to_string(), String::new()];
+        assert_eq!(parse_commands(&list), ["/reset", "/forget"]);
+    }
+
+    #[test]
+    fn test_take_answers() {
+        let mut state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.config_mut().confirm_commands = parse_commands(&["/reset".to_string()]);
+        assert!(needs_confirmation(&state, "/RESET"));
+        assert!(!needs_confirmation(&state, "/resetting"));
+        assert!(!needs_confirmation(&state, "reset the counter"));
+
+        assert_eq!(take(&state, "+1", "yes"), None);
+        ask(&state, "+1", "/reset");
+        assert_eq!(
+            take(&state, "+1", "Yes!"),
+            Some(Answer::Confirmed("/reset".into()))
+        );
+        assert_eq!(take(&state, "+1", "yes"), None);
+
+        ask(&state, "+1", "/reset");
+        assert_eq!(
+            take(&state, "+1", "what's 2+2"),
+            Some(Answer::Moved("/reset".into()))
+        );
+
+        ask(&state, "+1", "/reset");
+        state.pending_commands.get_mut("+1").unwrap().asked_at =
+            Instant::now() - Duration::from_secs(CONFIRM_COMMAND_TTL_SECS + 1);
+        assert_eq!(
+            take(&state, "+1", "yes"),
+            Some(Answer::Expired("/reset".into()))
+        );
+    }
+}
@@ -1,6 +1,22 @@
 /// Maximum Signal message length before splitting into multiple parts.
 pub(crate) const MAX_SIGNAL_MSG_LEN: usize = 4000;
 
+/// Email replies are sent whole; only absurdly long ones are split.
+pub(crate) const MAX_EMAIL_BODY_LEN: usize = 100_000;
+
+/// Discord rejects messages over 2000 characters.
+pub(crate) const MAX_DISCORD_MSG_LEN: usize = 2000;
+
+/// How often the iMessage transport checks the Messages database.
+#[cfg(feature = "macos-imessage")]
+pub(crate) const IMESSAGE_POLL_MS: u64 = 2000;
+
+/// Default seconds between IMAP inbox polls.
+pub(crate) const DEFAULT_EMAIL_POLL_SECS: u64 = 60;
+
+/// Unseen emails fetched per poll; the rest wait for the next one.
+pub(crate) const EMAIL_FETCH_BATCH: usize = 20;
+
 /// Response length threshold above which we check for truncation.
 pub(crate) const TRUNCATION_THRESHOLD: usize = 3500;
 
@@ -10,6 +26,13 @@ pub(crate) const TMP_DIR: &str = "/tmp/ccchat";
 /// Default debounce window in milliseconds.
 pub(crate) const DEFAULT_DEBOUNCE_MS: u64 = 3000;
 
+/// Default message that ends the debounce window early.
+pub(crate) const DEFAULT_FLUSH_KEYWORD: &str = "go";
+
+/// Default prefix that sends a message to Claude as one of its own slash
+/// commands (`//review` runs Claude's `/review`).
+pub(crate) const DEFAULT_CLAUDE_COMMAND_PREFIX: &str = "//";
+
 /// Default port for signal-cli-api.
 pub(crate) const DEFAULT_PORT: u16 = 8080;
 
@@ -22,6 +45,15 @@ pub(crate) const DEFAULT_MAX_BUDGET: f64 = 5.0;
 /// Budget for session summarization calls.
 pub(crate) const SUMMARIZE_BUDGET: f64 = 0.05;
 
+/// Most files `/context add` can pin to one session.
+pub(crate) const CONTEXT_FILES_MAX: usize = 5;
+
+/// Largest pinned file put in prompts as is; bigger ones are summarized.
+pub(crate) const CONTEXT_FILE_MAX_BYTES: usize = 16 * 1024;
+
+/// Most of a large pinned file given to Claude to summarize.
+pub(crate) const CONTEXT_FILE_SUMMARY_INPUT_BYTES: usize = 256 * 1024;
+
 /// Max number of memory search results to return.
 pub(crate) const MEMORY_SEARCH_LIMIT: usize = 5;
 
@@ -34,5 +66,309 @@ pub(crate) const SECS_PER_DAY: i64 = 86400;
 /// Number of recent messages to capture when pinning.
 pub(crate) const PIN_MESSAGE_COUNT: usize = 10;
 
+/// Max facts kept with /remember per sender (they go into every system prompt).
+pub(crate) const MAX_FACTS: usize = 50;
+
 /// Number of message pairs before triggering auto-summarization.
 pub(crate) const AUTO_SUMMARIZE_THRESHOLD: u64 = 20;
+
+/// Session context size (tokens) at which a session is auto-compacted,
+/// about 80% of Claude's 200k window.
+pub(crate) const AUTO_COMPACT_TOKENS: u64 = 160_000;
+
+/// Default disk quota per session working directory in MB.
+pub(crate) const DEFAULT_WORKDIR_QUOTA_MB: u64 = 500;
+
+/// Default size caps for incoming attachments, in MB.
+pub(crate) const DEFAULT_MAX_IMAGE_MB: u64 = 20;
+pub(crate) const DEFAULT_MAX_AUDIO_MB: u64 = 25;
+pub(crate) const DEFAULT_MAX_DOCUMENT_MB: u64 = 20;
+
+/// Default cap on `claude` processes running at once across all senders.
+pub(crate) const DEFAULT_MAX_CONCURRENT_RUNS: usize = 4;
+
+/// Number of recent Claude run durations (and end-to-end reply times) kept
+/// for p50/p95 latency.
+pub(crate) const LATENCY_SAMPLE_WINDOW: usize = 1000;
+
+/// PBKDF2 rounds used to derive the key for `ccchat sessions export`.
+pub(crate) const BUNDLE_KDF_ITERATIONS: u32 = 600_000;
+
+/// How often an unlocked `/memory lock` memory is sealed back to disk.
+pub(crate) const MEMORY_SEAL_SECS: u64 = 30;
+
+/// Minutes between model availability probes (`--probe-mins`).
+pub(crate) const DEFAULT_PROBE_MINS: u64 = 30;
+
+/// Budget for one availability probe.
+pub(crate) const PROBE_BUDGET: f64 = 0.05;
+
+/// A probe still going after this long counts as a failure.
+pub(crate) const PROBE_TIMEOUT_SECS: u64 = 120;
+
+/// Default duration of `/tail on` in minutes.
+pub(crate) const TAIL_DEFAULT_MINUTES: u64 = 10;
+
+/// Upper bound on `/tail on <minutes>`.
+pub(crate) const TAIL_MAX_MINUTES: u64 = 120;
+
+/// Log events buffered for the tail forwarder before new ones are dropped.
+pub(crate) const TAIL_CHANNEL_CAPACITY: usize = 256;
+
+/// Log lines shown per forwarded batch.
+pub(crate) const TAIL_MAX_LINES_PER_BATCH: usize = 20;
+
+/// Seconds between forwarded log batches.
+pub(crate) const TAIL_BATCH_INTERVAL_SECS: u64 = 15;
+
+/// Widest box-drawn table (in columns) sent as text before `/format tables
+/// auto` switches to an image; about a phone screen.
+pub(crate) const TABLE_TEXT_MAX_WIDTH: usize = 60;
+
+/// Longest cell drawn in a table image; longer cells are cut with `...`.
+pub(crate) const TABLE_IMAGE_MAX_CELL_CHARS: usize = 60;
+
+/// Shortest code block sent as an image when `/format code image` is on.
+#[cfg(feature = "render")]
+pub(crate) const CODE_IMAGE_MIN_LINES: usize = 15;
+
+/// Longest code block drawn as an image; longer ones stay text.
+#[cfg(feature = "render")]
+pub(crate) const CODE_IMAGE_MAX_LINES: usize = 300;
+
+/// Columns drawn per line in a code image; the rest of the line is cut.
+#[cfg(feature = "render")]
+pub(crate) const CODE_IMAGE_MAX_COLS: usize = 100;
+
+/// How long `mmdc` or `dot` may take to draw a diagram.
+#[cfg(feature = "render")]
+pub(crate) const RENDER_TIMEOUT_SECS: u64 = 30;
+
+/// Longest reply (in characters) read aloud by `/voice`; the rest is cut at
+/// the last full sentence.
+pub(crate) const TTS_MAX_CHARS: usize = 4000;
+
+/// How long speech synthesis may take per reply.
+pub(crate) const TTS_TIMEOUT_SECS: u64 = 120;
+
+/// Most links fetched from one message (`--fetch-urls`).
+pub(crate) const FETCH_MAX_URLS: usize = 3;
+
+/// Most bytes downloaded per fetched link.
+pub(crate) const FETCH_MAX_BYTES: usize = 2 * 1024 * 1024;
+
+/// Most bytes downloaded per file shared as a link (XMPP).
+pub(crate) const FETCH_MAX_FILE_BYTES: usize = 25 * 1024 * 1024;
+
+/// Most characters of page text added to the prompt per link.
+pub(crate) const FETCH_MAX_CHARS: usize = 20_000;
+
+/// Redirects followed per link.
+pub(crate) const FETCH_MAX_REDIRECTS: usize = 5;
+
+/// Timeout per link fetch.
+pub(crate) const FETCH_TIMEOUT_SECS: u64 = 15;
+
+/// Default minutes between `/gh` digest refreshes.
+pub(crate) const DEFAULT_GITHUB_POLL_MINS: u64 = 15;
+
+/// How long fetched calendar data is reused before asking the server again.
+pub(crate) const CALENDAR_CACHE_SECS: u64 = 300;
+
+/// Longest event description included in calendar context.
+pub(crate) const CALENDAR_NOTES_MAX_CHARS: usize = 300;
+
+/// How often a `/subscribe` feed is checked when no schedule is given.
+pub(crate) const FEED_DEFAULT_INTERVAL_SECS: i64 = 3600;
+
+/// Shortest `/subscribe` interval, to be polite to feed servers.
+pub(crate) const FEED_MIN_INTERVAL_SECS: i64 = 300;
+
+/// Most feeds one sender can subscribe to.
+pub(crate) const FEED_MAX_PER_SENDER: usize = 20;
+
+/// Most new items listed per feed digest; the rest are counted.
+pub(crate) const FEED_MAX_ITEMS: usize = 10;
+
+/// Item IDs remembered per feed for dedupe.
+pub(crate) const FEED_SEEN_KEEP: usize = 500;
+
+/// Longest item description passed to Claude for summarizing.
+pub(crate) const FEED_SUMMARY_MAX_CHARS: usize = 300;
+
+/// How long a `/screenshot` capture tool may run.
+#[cfg(feature = "screenshot")]
+pub(crate) const SCREENSHOT_TIMEOUT_SECS: u64 = 30;
+
+/// Least time between overload alerts to the admin.
+pub(crate) const OVERLOAD_ALERT_COOLDOWN_SECS: u64 = 900;
+
+/// How long a rate-limited or out-of-quota Claude account is skipped.
+pub(crate) const KEY_COOLDOWN_SECS: u64 = 900;
+
+/// When weekly `/report`s go out: Mondays 09:00 UTC.
+pub(crate) const WEEKLY_REPORT_CRON: &str = "0 9 * * MON";
+
+/// Days covered by a `/report`.
+pub(crate) const REPORT_DAYS: i64 = 7;
+
+/// How long after `/reset` the previous conversation can still be `/carry`-ed over.
+pub(crate) const CARRY_TTL_SECS: u64 = 900;
+
+/// How long a numbered choice can be answered with a bare number.
+pub(crate) const CHOICE_TTL_SECS: u64 = 600;
+
+/// Most follow-ups suggested after a session ends.
+pub(crate) const STARTERS_MAX: usize = 3;
+
+/// Default estimated input cost, in USD, above which a run waits for
+/// `/confirm` (`--confirm-above-usd`; 0 = never ask).
+pub(crate) const DEFAULT_CONFIRM_ABOVE_USD: f64 = 1.0;
+
+/// How long a run held for `/confirm` can still be confirmed.
+pub(crate) const CONFIRM_TTL_SECS: u64 = 600;
+
+/// Commands that ask "Are you sure?" before acting (`--confirm-commands`).
+pub(crate) const DEFAULT_CONFIRM_COMMANDS: &str = "/reset,/forget,/revoke";
+
+/// How long a destructive command waits for its "yes".
+pub(crate) const CONFIRM_COMMAND_TTL_SECS: u64 = 60;
+
+/// Default capacity of the inbound message queue (`--inbound-queue`).
+pub(crate) const DEFAULT_INBOUND_QUEUE: usize = 256;
+
+/// Default number of workers draining the inbound queue (`--inbound-workers`).
+pub(crate) const DEFAULT_INBOUND_WORKERS: usize = 32;
+
+/// Least time between "your message was dropped" notices to one sender.
+pub(crate) const SHED_NOTICE_COOLDOWN_SECS: u64 = 60;
+
+/// Default hours between archive uploads (`--archive-every-hours`).
+pub(crate) const DEFAULT_ARCHIVE_EVERY_HOURS: u64 = 24;
+
+/// Default number of archives kept in the bucket (`--archive-keep`).
+pub(crate) const DEFAULT_ARCHIVE_KEEP: usize = 30;
+
+/// Default days whose newest snapshot is kept (`--backup-keep-daily`).
+pub(crate) const DEFAULT_BACKUP_KEEP_DAILY: usize = 7;
+
+/// Default ISO weeks whose newest snapshot is kept (`--backup-keep-weekly`).
+pub(crate) const DEFAULT_BACKUP_KEEP_WEEKLY: usize = 4;
+
+/// Shards per metrics counter; more than the worker threads that bump them.
+pub(crate) const METRICS_SHARDS: usize = 16;
+
+/// Default local time housekeeping runs at (`--maintenance-at`).
+pub(crate) const DEFAULT_MAINTENANCE_AT: &str = "03:30";
+
+/// Default housekeeping tasks (`--maintenance-tasks`).
+pub(crate) const DEFAULT_MAINTENANCE_TASKS: &str =
+    "ttl,tmp,hashes,vacuum,summaries,attachments,backup";
+
+/// Default days kept attachments stay (`--attachment-retention-days`).
+pub(crate) const DEFAULT_ATTACHMENT_RETENTION_DAYS: u32 = 7;
+
+/// Default space for kept attachments in MB (`--attachment-max-mb`).
+pub(crate) const DEFAULT_ATTACHMENT_MAX_MB: u64 = 500;
+
+/// Days of conversation history kept in each memory database.
+pub(crate) const MESSAGE_RETENTION_DAYS: u32 = 30;
+
+/// Files in `TMP_DIR` older than this are removed by maintenance.
+pub(crate) const TMP_MAX_AGE_SECS: u64 = 24 * 3600;
+
+/// How long a sent message's hash is kept to recognise its echo.
+pub(crate) const SENT_HASH_TTL_SECS: u64 = 3600;
+
+/// Summaries kept per memory database by maintenance; older ones are deleted.
+pub(crate) const SUMMARIES_KEPT: usize = 50;
+
+/// Seconds between checks of reply p95 against `--reply-slo-ms`.
+pub(crate) const REPLY_SLO_CHECK_SECS: u64 = 300;
+
+/// Fewest replies in the window before the SLO is checked at all.
+pub(crate) const REPLY_SLO_MIN_SAMPLES: usize = 10;
+
+/// Seconds between running token/cost updates in `--stream-progress` mode.
+pub(crate) const STREAM_PROGRESS_INTERVAL_SECS: u64 = 30;
+
+/// Default minutes a Claude run may go without progress before it counts as
+/// stuck and is cancelled (`--stuck-run-mins`). Messages waiting on the
+/// session give up after as long.
+pub(crate) const DEFAULT_STUCK_RUN_MINS: u64 = 15;
+
+/// Default signal-cli-api sends per second (`--send-rate`).
+pub(crate) const DEFAULT_SEND_RATE: f64 = 5.0;
+
+/// Default random delay added to each paced send, in ms (`--send-jitter-ms`).
+pub(crate) const DEFAULT_SEND_JITTER_MS: u64 = 100;
+
+/// First extra gap between sends after a 429 or 5xx, in ms; doubles per error.
+pub(crate) const SEND_BACKOFF_BASE_MS: u64 = 500;
+
+/// Largest extra gap between sends, in ms.
+pub(crate) const SEND_BACKOFF_MAX_MS: u64 = 30_000;
+
+/// Times a send turned away with 429 is tried again.
+pub(crate) const SEND_RETRIES: u32 = 2;
+
+/// Times a failed webhook delivery is tried again, and the first wait
+/// before it (doubled each time).
+pub(crate) const WEBHOOK_RETRIES: u32 = 2;
+pub(crate) const WEBHOOK_RETRY_BASE_MS: u64 = 1000;
+
+/// How long an `/invite` code can be used, in seconds (7 days).
+pub(crate) const INVITE_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Wrong invite codes one sender may try per [`INVITE_MISS_WINDOW_SECS`].
+pub(crate) const INVITE_MAX_MISSES: u32 = 5;
+
+/// Window for [`INVITE_MAX_MISSES`], in seconds.
+pub(crate) const INVITE_MISS_WINDOW_SECS: i64 = 60 * 60;
+
+/// What `--unknown-sender-reply` tells someone who isn't allowed yet.
+pub(crate) const UNKNOWN_SENDER_REPLY: &str =
+    "This bot is private; your request was forwarded to the owner. You can chat once they approve it.";
+
+/// Seconds before the same unknown sender can get the auto-reply again (a day).
+pub(crate) const UNKNOWN_REPLY_INTERVAL_SECS: i64 = 24 * 60 * 60;
+
+/// Most unknown-sender auto-replies sent in any hour, across all senders.
+pub(crate) const UNKNOWN_REPLIES_PER_HOUR: usize = 5;
+
+/// Most an invited guest's Claude run may spend (USD), whatever `--max-budget` is.
+pub(crate) const GUEST_MAX_BUDGET_USD: f64 = 0.25;
+
+/// Tools an invited guest's runs can't use: they get answers, not actions.
+pub(crate) const ASK_ONLY_DENIED_TOOLS: [&str; 7] = [
+    "Bash",
+    "Write",
+    "Edit",
+    "MultiEdit",
+    "NotebookEdit",
+    "WebFetch",
+    "WebSearch",
+];
+
+/// Consecutive failures before signal-cli-api fails over to the next `--api-url`.
+pub(crate) const API_FAILOVER_THRESHOLD: u32 = 3;
+
+/// Seconds between refreshes of the running instance's lock file.
+pub(crate) const INSTANCE_LOCK_REFRESH_SECS: u64 = 60;
+
+/// An instance lock from another host not refreshed for this long is
+/// abandoned.
+pub(crate) const INSTANCE_LOCK_STALE_SECS: u64 = 300;
+
+/// Unread messages `ccchat mcp-serve` holds; the oldest go first.
+pub(crate) const MCP_INBOX_MAX: usize = 100;
+
+/// Longest `read_messages` may wait for a message, in seconds.
+pub(crate) const MCP_MAX_WAIT_SECS: u64 = 300;
+
+/// Start of messages sent with the MCP `notify` tool.
+pub(crate) const MCP_NOTIFY_PREFIX: &str = "🔔 Claude Code: ";
+
+/// Interrupted prompts older than this (seconds) are dropped at startup
+/// rather than offered for `/retry`.
+pub(crate) const JOURNAL_MAX_AGE_SECS: i64 = 24 * 3600;
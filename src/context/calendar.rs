@@ -0,0 +1,713 @@
+//! Calendar context (`--calendar-url`).
+//!
+//! Today's and tomorrow's events from an iCalendar feed (`.ics` / `webcal://`
+//! URL or local file) or a CalDAV collection are listed for prompts that
+//! mention the day, meetings or a time like "2pm". CalDAV servers expand
+//! recurring events themselves; for ICS feeds the common RRULEs (daily,
+//! weekly by day, monthly by date or nth weekday, yearly) are expanded here.
+
+use async_trait::async_trait;
+use chrono::{Datelike, Days, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use regex::Regex;
+use reqwest::Client;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use super::ContextProvider;
+use crate::constants::{CALENDAR_CACHE_SECS, CALENDAR_NOTES_MAX_CHARS};
+use crate::error::AppError;
+
+/// Longest span walked when counting `COUNT=` occurrences (about 20 years).
+const MAX_EXPAND_DAYS: i64 = 7300;
+
+enum Source {
+    /// iCalendar feed fetched with GET.
+    Ics(String),
+    /// CalDAV collection queried with REPORT.
+    CalDav(String),
+    File(PathBuf),
+}
+
+struct Cached {
+    at: Instant,
+    day: NaiveDate,
+    ics: String,
+}
+
+pub(crate) struct CalendarProvider {
+    source: Source,
+    username: Option<String>,
+    password: Option<String>,
+    cache: Mutex<Option<Cached>>,
+}
+
+impl CalendarProvider {
+    /// `url` is an `.ics`/`webcal://` feed, a CalDAV collection URL, or a
+    /// path to a local `.ics` file.
+    pub(crate) fn new(url: &str, username: Option<String>, password: Option<String>) -> Self {
+        let source = if let Some(rest) = url.strip_prefix("webcal://") {
+            Source::Ics(format!("https://{rest}"))
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            let path = url.split(['?', '#']).next().unwrap_or(url);
+            if path.to_ascii_lowercase().ends_with(".ics") {
+                Source::Ics(url.to_string())
+            } else {
+                Source::CalDav(url.to_string())
+            }
+        } else {
+            Source::File(PathBuf::from(url))
+        };
+        Self {
+            source,
+            username,
+            password,
+            cache: Mutex::new(None),
+        }
+    }
+
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.username {
+            Some(user) => builder.basic_auth(user, self.password.as_ref()),
+            None => builder,
+        }
+    }
+
+    /// Calendar data covering `today` and tomorrow, cached for a few minutes.
+    async fn load(&self, http: &Client, today: NaiveDate) -> Result<String, AppError> {
+        if let Ok(cache) = self.cache.lock() {
+            if let Some(c) = cache.as_ref() {
+                if c.day == today && c.at.elapsed() < Duration::from_secs(CALENDAR_CACHE_SECS) {
+                    return Ok(c.ics.clone());
+                }
+            }
+        }
+        let ics = match &self.source {
+            Source::File(path) => tokio::fs::read_to_string(path).await?,
+            Source::Ics(url) => {
+                let resp = self.request(http.get(url)).send().await?;
+                if !resp.status().is_success() {
+                    return Err(AppError::Other(format!(
+                        "Calendar returned {}",
+                        resp.status()
+                    )));
+                }
+                resp.text().await?
+            }
+            Source::CalDav(url) => self.report(http, url, today).await?,
+        };
+        if let Ok(mut cache) = self.cache.lock() {
+            *cache = Some(Cached {
+                at: Instant::now(),
+                day: today,
+                ics: ics.clone(),
+            });
+        }
+        Ok(ics)
+    }
+
+    /// CalDAV `calendar-query` for today and tomorrow, with recurrences
+    /// expanded by the server.
+    async fn report(&self, http: &Client, url: &str, today: NaiveDate) -> Result<String, AppError> {
+        let utc = |day: NaiveDate| {
+            Local
+                .from_local_datetime(&day.and_time(NaiveTime::MIN))
+                .earliest()
+                .map(|t| t.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string())
+                .unwrap_or_default()
+        };
+        let (start, end) = (utc(today), utc(today + Days::new(2)));
+        let body = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+  <D:prop><C:calendar-data><C:expand start="{start}" end="{end}"/></C:calendar-data></D:prop>
+  <C:filter><C:comp-filter name="VCALENDAR"><C:comp-filter name="VEVENT">
+    <C:time-range start="{start}" end="{end}"/>
+  </C:comp-filter></C:comp-filter></C:filter>
+</C:calendar-query>"#
+        );
+        let method = reqwest::Method::from_bytes(b"REPORT")
+            .map_err(|e| AppError::Other(format!("REPORT method: {e}")))?;
+        let resp = self
+            .request(http.request(method, url))
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(body)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(AppError::Other(format!(
+                "CalDAV returned {}",
+                resp.status()
+            )));
+        }
+        Ok(calendar_data(&resp.text().await?))
+    }
+}
+
+#[async_trait]
+impl ContextProvider for CalendarProvider {
+    fn name(&self) -> &'static str {
+        "Calendar"
+    }
+
+    fn wants(&self, text: &str) -> bool {
+        static RE: OnceLock<Regex> = OnceLock::new();
+        RE.get_or_init(|| {
+            Regex::new(
+                r"(?i)\b(calendar|schedule|agenda|meetings?|appointments?|events?|my day|today|tonight|tomorrow|this (morning|afternoon|evening)|\d{1,2}(:\d{2})?\s?[ap]m|\d{1,2}:\d{2})\b",
+            )
+            .unwrap()
+        })
+        .is_match(text)
+    }
+
+    async fn context(&self, http: &Client) -> Result<String, AppError> {
+        let today = Local::now().date_naive();
+        let ics = self.load(http, today).await?;
+        Ok(agenda(&parse_events(&ics), today))
+    }
+}
+
+/// The iCalendar text inside each `<calendar-data>` of a CalDAV multistatus.
+fn calendar_data(xml: &str) -> String {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r"(?s)<(?:[\w-]+:)?calendar-data[^>]*>(.*?)</(?:[\w-]+:)?calendar-data>")
+            .unwrap()
+    });
+    re.captures_iter(xml)
+        .map(|c| {
+            let data = c[1].trim();
+            data.strip_prefix("<![CDATA[")
+                .and_then(|d| d.strip_suffix("]]>"))
+                .map(str::to_string)
+                .unwrap_or_else(|| {
+                    quick_xml::escape::unescape(data)
+                        .map(|d| d.into_owned())
+                        .unwrap_or_else(|_| data.to_string())
+                })
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum When {
+    AllDay(NaiveDate),
+    /// Local wall-clock time.
+    At(NaiveDateTime),
+}
+
+impl When {
+    fn date(self) -> NaiveDate {
+        match self {
+            When::AllDay(d) => d,
+            When::At(t) => t.date(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    freq: Freq,
+    interval: u32,
+    until: Option<NaiveDate>,
+    count: Option<u32>,
+    /// (ordinal, weekday); ordinal 0 = every, 2 = second, -1 = last.
+    by_day: Vec<(i32, Weekday)>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Event {
+    summary: String,
+    location: Option<String>,
+    description: Option<String>,
+    start: Option<When>,
+    end: Option<When>,
+    rule: Option<Rule>,
+    exdates: Vec<NaiveDate>,
+}
+
+type Params<'a> = Vec<(&'a str, &'a str)>;
+
+/// Split `NAME;PARAM=V:value` into name, params and value, respecting quoted
+/// parameter values that contain `:`.
+fn split_property(line: &str) -> Option<(&str, Params<'_>, &str)> {
+    let mut in_quotes = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            in_quotes = !in_quotes;
+            None
+        }
+        ':' if !in_quotes => Some(i),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?;
+    let params = parts
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k, v.trim_matches('"')))
+        .collect();
+    Some((name, params, value))
+}
+
+fn unescape_text(value: &str) -> String {
+    value
+        .replace("\\n", "\n")
+        .replace("\\N", "\n")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
+
+fn parse_when(params: &[(&str, &str)], value: &str) -> Option<When> {
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| *v)
+    };
+    if param("VALUE") == Some("DATE") || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(When::AllDay);
+    }
+    let naive = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+    let local = if value.ends_with('Z') {
+        Utc.from_utc_datetime(&naive)
+            .with_timezone(&Local)
+            .naive_local()
+    } else if let Some(tz) = param("TZID").and_then(|t| t.parse::<chrono_tz::Tz>().ok()) {
+        tz.from_local_datetime(&naive)
+            .earliest()
+            .map(|t| t.with_timezone(&Local).naive_local())
+            .unwrap_or(naive)
+    } else {
+        // Floating time (or an unknown TZID): take it as local.
+        naive
+    };
+    Some(When::At(local))
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    Some(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn parse_rule(value: &str) -> Option<Rule> {
+    let mut rule = Rule {
+        freq: Freq::Daily,
+        interval: 1,
+        until: None,
+        count: None,
+        by_day: Vec::new(),
+    };
+    let mut freq = None;
+    for part in value.split(';') {
+        let Some((key, val)) = part.split_once('=') else {
+            continue;
+        };
+        match key {
+            "FREQ" => {
+                freq = Some(match val {
+                    "DAILY" => Freq::Daily,
+                    "WEEKLY" => Freq::Weekly,
+                    "MONTHLY" => Freq::Monthly,
+                    "YEARLY" => Freq::Yearly,
+                    // Hourly and finer rules aren't worth listing in an agenda.
+                    _ => return None,
+                })
+            }
+            "INTERVAL" => rule.interval = val.parse().ok().filter(|i| *i > 0)?,
+            "UNTIL" => {
+                rule.until = NaiveDate::parse_from_str(&val[..val.len().min(8)], "%Y%m%d").ok()
+            }
+            "COUNT" => rule.count = val.parse().ok(),
+            "BYDAY" => {
+                for day in val.split(',') {
+                    let (ord, wd) = day.split_at(day.len().saturating_sub(2));
+                    rule.by_day
+                        .push((ord.parse().unwrap_or(0), parse_weekday(wd)?));
+                }
+            }
+            _ => {}
+        }
+    }
+    rule.freq = freq?;
+    Some(rule)
+}
+
+fn parse_events(ics: &str) -> Vec<Event> {
+    // Unfold continuation lines (RFC 5545 3.1).
+    let mut lines: Vec<String> = Vec::new();
+    for raw in ics.lines() {
+        let raw = raw.trim_end_matches('\r');
+        match (
+            raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')),
+            lines.last_mut(),
+        ) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    let mut events = Vec::new();
+    let mut current: Option<Event> = None;
+    // Nested components (VALARM) have their own SUMMARY/DESCRIPTION.
+    let mut nested = 0;
+    for line in &lines {
+        let Some((name, params, value)) = split_property(line) else {
+            continue;
+        };
+        match (name, value) {
+            ("BEGIN", "VEVENT") => current = Some(Event::default()),
+            ("END", "VEVENT") => {
+                if let Some(event) = current.take().filter(|e| e.start.is_some()) {
+                    events.push(event);
+                }
+            }
+            ("BEGIN", _) if current.is_some() => nested += 1,
+            ("END", _) if current.is_some() => nested -= 1,
+            _ => {}
+        }
+        let Some(event) = current.as_mut().filter(|_| nested == 0) else {
+            continue;
+        };
+        match name {
+            "SUMMARY" => event.summary = unescape_text(value),
+            "LOCATION" if !value.is_empty() => event.location = Some(unescape_text(value)),
+            "DESCRIPTION" if !value.is_empty() => event.description = Some(unescape_text(value)),
+            "DTSTART" => event.start = parse_when(&params, value),
+            "DTEND" => event.end = parse_when(&params, value),
+            "RRULE" => event.rule = parse_rule(value),
+            "EXDATE" => event.exdates.extend(
+                value
+                    .split(',')
+                    .filter_map(|v| parse_when(&params, v))
+                    .map(When::date),
+            ),
+            _ => {}
+        }
+    }
+    events
+}
+
+fn months_between(from: NaiveDate, to: NaiveDate) -> i32 {
+    (to.year() - from.year()) * 12 + to.month() as i32 - from.month() as i32
+}
+
+fn days_in_month(date: NaiveDate) -> u32 {
+    let first = date.with_day(1).unwrap_or(date);
+    let next = first
+        .checked_add_months(chrono::Months::new(1))
+        .unwrap_or(first);
+    (next - first).num_days() as u32
+}
+
+impl Rule {
+    /// Whether the pattern (ignoring UNTIL, COUNT and EXDATE) lands on `day`.
+    fn matches(&self, start: NaiveDate, day: NaiveDate) -> bool {
+        let interval = self.interval as i64;
+        match self.freq {
+            Freq::Daily => (day - start).num_days() % interval == 0,
+            Freq::Weekly => {
+                let week = |d: NaiveDate| d - Days::new(d.weekday().num_days_from_monday() as u64);
+                let weeks = (week(day) - week(start)).num_days() / 7;
+                weeks % interval == 0
+                    && if self.by_day.is_empty() {
+                        day.weekday() == start.weekday()
+                    } else {
+                        self.by_day.iter().any(|(_, wd)| *wd == day.weekday())
+                    }
+            }
+            Freq::Monthly => {
+                months_between(start, day) as i64 % interval == 0
+                    && if self.by_day.is_empty() {
+                        day.day() == start.day()
+                    } else {
+                        self.by_day.iter().any(|(ord, wd)| {
+                            *wd == day.weekday()
+                                && match *ord {
+                                    0 => true,
+                                    n if n > 0 => ((day.day() - 1) / 7 + 1) as i32 == n,
+                                    n => ((days_in_month(day) - day.day()) / 7 + 1) as i32 == -n,
+                                }
+                        })
+                    }
+            }
+            Freq::Yearly => {
+                (day.year() - start.year()) as i64 % interval == 0
+                    && day.month() == start.month()
+                    && day.day() == start.day()
+            }
+        }
+    }
+}
+
+impl Event {
+    fn occurs_on(&self, day: NaiveDate) -> bool {
+        let Some(start) = self.start else {
+            return false;
+        };
+        let first = start.date();
+        if day < first {
+            return false;
+        }
+        let Some(rule) = &self.rule else {
+            return match (start, self.end) {
+                // DTEND of an all-day event is exclusive.
+                (When::AllDay(s), Some(When::AllDay(e))) => s <= day && day < e,
+                (When::At(s), Some(When::At(e))) => {
+                    s.date() == day
+                        || (s.date() < day
+                            && (e.date() > day || (e.date() == day && e.time() > NaiveTime::MIN)))
+                }
+                _ => first == day,
+            };
+        };
+        if self.exdates.contains(&day) || rule.until.is_some_and(|u| day > u) {
+            return false;
+        }
+        if !rule.matches(first, day) {
+            return false;
+        }
+        match rule.count {
+            None => true,
+            Some(count) => {
+                if (day - first).num_days() > MAX_EXPAND_DAYS {
+                    return false;
+                }
+                first
+                    .iter_days()
+                    .take_while(|d| *d <= day)
+                    .filter(|d| rule.matches(first, *d))
+                    .count()
+                    <= count as usize
+            }
+        }
+    }
+}
+
+fn event_line(event: &Event) -> String {
+    let when = match (event.start, event.end) {
+        (Some(When::At(s)), Some(When::At(e))) if e > s => {
+            format!("{}–{}", s.format("%H:%M"), e.format("%H:%M"))
+        }
+        (Some(When::At(s)), _) => s.format("%H:%M").to_string(),
+        _ => "All day".to_string(),
+    };
+    let title = if event.summary.is_empty() {
+        "(untitled)"
+    } else {
+        &event.summary
+    };
+    let mut line = format!("- {when} {title}");
+    if let Some(location) = &event.location {
+        line.push_str(&format!(" @ {location}"));
+    }
+    if let Some(notes) = &event.description {
+        let notes = notes.split_whitespace().collect::<Vec<_>>().join(" ");
+        let notes: String = notes.chars().take(CALENDAR_NOTES_MAX_CHARS).collect();
+        line.push_str(&format!("\n  Notes: {notes}"));
+    }
+    line
+}
+
+/// Today's and tomorrow's events, all-day first, then by start time.
+fn agenda(events: &[Event], today: NaiveDate) -> String {
+    let offset = Local::now().format("%:z");
+    let mut lines = vec![format!("Times are local (UTC{offset}).")];
+    for (label, day) in [("Today", today), ("Tomorrow", today + Days::new(1))] {
+        lines.push(format!("{label}, {}:", day.format("%a %-d %b %Y")));
+        let mut todays: Vec<&Event> = events.iter().filter(|e| e.occurs_on(day)).collect();
+        todays.sort_by_key(|e| match e.start {
+            Some(When::At(t)) => Some(t.time()),
+            _ => None,
+        });
+        if todays.is_empty() {
+            lines.push("- Nothing scheduled".to_string());
+        }
+        lines.extend(todays.into_iter().map(event_line));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const ICS: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+SUMMARY:Design review\r
+DTSTART:20261016T140000\r
+DTEND:20261016T150000\r
+LOCATION:Room 3\r
+DESCRIPTION:Bring the latency numbers\\, and the\r
+  Q3 plan.\r
+BEGIN:VALARM\r
+DESCRIPTION:Reminder\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Standup\r
+DTSTART:20260105T093000\r
+DTEND:20260105T094500\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\r
+EXDATE:20261019T093000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Offsite\r
+DTSTART;VALUE=DATE:20261016\r
+DTEND;VALUE=DATE:20261018\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Board meeting\r
+DTSTART:20260115T100000\r
+RRULE:FREQ=MONTHLY;BYDAY=3TH;COUNT=12\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_agenda_lists_today_and_tomorrow() {
+        let events = parse_events(ICS);
+        assert_eq!(events.len(), 4);
+        let text = agenda(&events, day("2026-10-16"));
+        let body: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(
+            body,
+            vec![
+                "Today, Fri 16 Oct 2026:",
+                "- All day Offsite",
+                "- 09:30–09:45 Standup",
+                "- 14:00–15:00 Design review @ Room 3",
+                "  Notes: Bring the latency numbers, and the Q3 plan.",
+                "Tomorrow, Sat 17 Oct 2026:",
+                "- All day Offsite",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recurrence_rules() {
+        let events = parse_events(ICS);
+        let standup = &events[1];
+        assert!(standup.occurs_on(day("2026-10-20")));
+        assert!(!standup.occurs_on(day("2026-10-19")), "EXDATE");
+        assert!(!standup.occurs_on(day("2026-10-18")), "Sunday");
+        assert!(!standup.occurs_on(day("2026-01-02")), "before start");
+
+        let board = &events[3];
+        assert!(board.occurs_on(day("2026-10-15")), "third Thursday");
+        assert!(!board.occurs_on(day("2026-10-08")));
+        assert!(board.occurs_on(day("2026-12-17")), "12th occurrence");
+        assert!(!board.occurs_on(day("2027-01-21")), "past COUNT");
+    }
+
+    #[test]
+    fn test_parse_rule_and_last_weekday() {
+        let rule = parse_rule("FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR;UNTIL=20271231T000000Z").unwrap();
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.by_day, vec![(-1, Weekday::Fri)]);
+        assert_eq!(rule.until, Some(day("2027-12-31")));
+        let start = day("2026-08-28");
+        assert!(rule.matches(start, day("2026-10-30")));
+        assert!(!rule.matches(start, day("2026-10-23")));
+        assert!(!rule.matches(start, day("2026-09-25")), "odd month");
+        assert!(parse_rule("FREQ=HOURLY").is_none());
+    }
+
+    #[test]
+    fn test_parse_when_timezones() {
+        let utc = parse_when(&[], "20261016T120000Z").unwrap();
+        let expected = Utc
+            .with_ymd_and_hms(2026, 10, 16, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&Local)
+            .naive_local();
+        assert_eq!(utc, When::At(expected));
+
+        let ny = parse_when(&[("TZID", "America/New_York")], "20261016T080000").unwrap();
+        assert_eq!(ny, When::At(expected), "08:00 EDT is 12:00 UTC");
+
+        let floating = parse_when(&[("TZID", "Not/AZone")], "20261016T080000").unwrap();
+        assert_eq!(floating.date(), day("2026-10-16"));
+    }
+
+    #[test]
+    fn test_wants_schedule_questions() {
+        let provider = CalendarProvider::new("/nonexistent.ics", None, None);
+        assert!(provider.wants("what's my day look like, and prep notes for the 2pm"));
+        assert!(provider.wants("Any meetings tomorrow?"));
+        assert!(provider.wants("move it to 14:30"));
+        assert!(!provider.wants("explain rust lifetimes"));
+    }
+
+    #[test]
+    fn test_source_detection() {
+        let src = |url: &str| CalendarProvider::new(url, None, None).source;
+        assert!(
+            matches!(src("webcal://example.com/cal"), Source::Ics(u) if u == "https://example.com/cal")
+        );
+        assert!(matches!(
+            src("https://example.com/basic.ics?token=1"),
+            Source::Ics(_)
+        ));
+        assert!(matches!(
+            src("https://dav.example.com/cal/me/"),
+            Source::CalDav(_)
+        ));
+        assert!(matches!(src("/home/me/cal.ics"), Source::File(_)));
+    }
+
+    #[tokio::test]
+    async fn test_caldav_report_extracts_calendar_data() {
+        let server = MockServer::start().await;
+        let escaped = quick_xml::escape::escape(ICS).into_owned();
+        let multistatus = format!(
+            "<?xml version=\"1.0\"?><d:multistatus xmlns:d=\"DAV:\" xmlns:cal=\"urn:ietf:params:xml:ns:caldav\">\
+             <d:response><d:propstat><d:prop><cal:calendar-data>{escaped}</cal:calendar-data></d:prop></d:propstat></d:response>\
+             </d:multistatus>"
+        );
+        Mock::given(method("REPORT"))
+            .and(header("depth", "1"))
+            .and(header("authorization", "Basic bWU6cHc="))
+            .respond_with(ResponseTemplate::new(207).set_body_string(multistatus))
+            .mount(&server)
+            .await;
+        let provider = CalendarProvider::new(
+            &format!("{}/cal/me/", server.uri()),
+            Some("me".to_string()),
+            Some("pw".to_string()),
+        );
+        let ics = provider
+            .load(&Client::new(), day("2026-10-16"))
+            .await
+            .unwrap();
+        assert_eq!(parse_events(&ics).len(), 4);
+    }
+}
@@ -0,0 +1,258 @@
+//! Files pinned to a session with `/context add`.
+//!
+//! Every prompt in the session starts with the pinned files' contents, read
+//! afresh so edits show up straight away. A file over
+//! [`CONTEXT_FILE_MAX_BYTES`] goes in as a summary Claude writes the first
+//! time it's needed, kept until the file changes. Files must be in the
+//! chat's workspace (or the project a group is bound to), and are dropped
+//! with the session on `/reset` or expiry.
+
+use dashmap::DashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+use crate::constants::{
+    CONTEXT_FILES_MAX, CONTEXT_FILE_MAX_BYTES, CONTEXT_FILE_SUMMARY_INPUT_BYTES,
+};
+use crate::state::State;
+
+#[derive(Default)]
+pub(crate) struct PinnedFiles {
+    /// Chat id -> files pinned to its current session, in the order added.
+    by_chat: DashMap<String, Vec<PathBuf>>,
+    /// Summaries of large files, with the modification time they describe.
+    summaries: DashMap<PathBuf, (SystemTime, String)>,
+}
+
+impl PinnedFiles {
+    pub(crate) fn list(&self, chat: &str) -> Vec<PathBuf> {
+        self.by_chat
+            .get(chat)
+            .map(|files| files.clone())
+            .unwrap_or_default()
+    }
+
+    /// Pin `path` for `chat`. False when it already is.
+    pub(crate) fn add(&self, chat: &str, path: PathBuf) -> Result<bool, String> {
+        let mut files = self.by_chat.entry(chat.to_string()).or_default();
+        if files.contains(&path) {
+            return Ok(false);
+        }
+        if files.len() >= CONTEXT_FILES_MAX {
+            return Err(format!(
+                "You can pin up to {CONTEXT_FILES_MAX} files. Remove one with /context remove first."
+            ));
+        }
+        files.push(path);
+        Ok(true)
+    }
+
+    /// Unpin the file `matches` picks. Returns it, or None when none matched.
+    pub(crate) fn remove(
+        &self,
+        chat: &str,
+        matches: impl Fn(usize, &Path) -> bool,
+    ) -> Option<PathBuf> {
+        let mut files = self.by_chat.get_mut(chat)?;
+        let index = files
+            .iter()
+            .enumerate()
+            .position(|(i, path)| matches(i, path))?;
+        let removed = files.remove(index);
+        let now_empty = files.is_empty();
+        drop(files);
+        if now_empty {
+            self.by_chat.remove(chat);
+        }
+        Some(removed)
+    }
+
+    /// Forget `chat`'s pins (its session ended).
+    pub(crate) fn clear(&self, chat: &str) {
+        self.by_chat.remove(chat);
+    }
+}
+
+/// Whether `path` looks like text worth putting in a prompt.
+pub(crate) fn is_text(path: &Path) -> bool {
+    std::fs::read(path)
+        .map(|bytes| !bytes[..bytes.len().min(8192)].contains(&0))
+        .unwrap_or(false)
+}
+
+/// The longest prefix of `text` no more than `max` bytes long.
+fn head(text: &str, max: usize) -> &str {
+    let end = (0..=max.min(text.len()))
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0);
+    &text[..end]
+}
+
+/// Ask Claude for a summary of a large file; None if the run fails.
+async fn summarize(state: &State, chat: &str, name: &str, text: &str) -> Option<String> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let prompt = format!(
+        "Summarize the file {name} below so it can stand in for the whole file in later \
+         conversations. Keep names, structure, decisions and anything someone would need \
+         to refer back to. Reply with the summary only.\n\n{}",
+        head(text, CONTEXT_FILE_SUMMARY_INPUT_BYTES)
+    );
+    let settings = state.settings();
+    let slot = state.acquire_run_slot(chat).await.ok()?;
+    let result = state
+        .claude_runner
+        .run_claude(
+            &prompt,
+            &session_id,
+            &settings.model,
+            &[],
+            &state.workdir_for(chat, &session_id),
+            crate::constants::SUMMARIZE_BUDGET,
+            crate::NO_MEMORY_PROMPT,
+            &state.run_options(chat),
+        )
+        .await;
+    drop(slot);
+    crate::commands::remove_session_workdir(chat, &session_id);
+    match result {
+        Ok(reply) => {
+            if let Some(c) = reply.cost_usd {
+                state.add_cost(c);
+                state.add_sender_cost(chat, c);
+            }
+            Some(reply.text)
+        }
+        Err(e) => {
+            warn!(file = name, "Pinned file summary failed: {e}");
+            None
+        }
+    }
+}
+
+/// One pinned file as prompt context: its text, or its summary when large.
+async fn file_block(state: &State, chat: &str, path: &Path, name: &str) -> String {
+    let (Ok(text), Ok(modified)) = (
+        std::fs::read_to_string(path),
+        std::fs::metadata(path).and_then(|m| m.modified()),
+    ) else {
+        return format!("[Pinned file {name}]\n(The file can't be read any more.)");
+    };
+    if text.len() <= CONTEXT_FILE_MAX_BYTES {
+        return format!("[Pinned file {name}]\n{text}");
+    }
+    let cached = state
+        .pinned_files
+        .summaries
+        .get(path)
+        .filter(|entry| entry.0 == modified)
+        .map(|entry| entry.1.clone());
+    let summary = match cached {
+        Some(summary) => Some(summary),
+        None => {
+            let summary = summarize(state, chat, name, &text).await;
+            if let Some(summary) = &summary {
+                debug!(file = name, "Summarized pinned file");
+                state
+                    .pinned_files
+                    .summaries
+                    .insert(path.to_path_buf(), (modified, summary.clone()));
+            }
+            summary
+        }
+    };
+    match summary {
+        Some(summary) => format!("[Pinned file {name}, summarized]\n{summary}"),
+        None => format!(
+            "[Pinned file {name}, first {} KB]\n{}",
+            CONTEXT_FILE_MAX_BYTES / 1024,
+            head(&text, CONTEXT_FILE_MAX_BYTES)
+        ),
+    }
+}
+
+/// Context from the files pinned to `chat`'s session, or None.
+pub(crate) async fn gather(state: &State, chat: &str, work_dir: &Path) -> Option<String> {
+    let files = state.pinned_files.list(chat);
+    if files.is_empty() {
+        return None;
+    }
+    let mut blocks = Vec::new();
+    for path in &files {
+        blocks.push(file_block(state, chat, path, &display_name(work_dir, path)).await);
+    }
+    Some(blocks.join("\n\n"))
+}
+
+/// `path` relative to the workspace, for messages and headings.
+pub(crate) fn display_name(work_dir: &Path, path: &Path) -> String {
+    let root = work_dir
+        .canonicalize()
+        .unwrap_or_else(|_| work_dir.to_path_buf());
+    path.strip_prefix(&root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{ClaudeReply, MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_pinned_files_add_and_remove() {
+        let pinned = PinnedFiles::default();
+        assert_eq!(pinned.add("+1", PathBuf::from("/w/a.md")), Ok(true));
+        assert_eq!(pinned.add("+1", PathBuf::from("/w/a.md")), Ok(false));
+        for i in 1..CONTEXT_FILES_MAX {
+            pinned.add("+1", PathBuf::from(format!("/w/{i}"))).unwrap();
+        }
+        assert!(pinned.add("+1", PathBuf::from("/w/extra")).is_err());
+        assert_eq!(
+            pinned.remove("+1", |_, p| p.ends_with("a.md")),
+            Some(PathBuf::from("/w/a.md"))
+        );
+        assert_eq!(pinned.remove("+1", |i, _| i == 99), None);
+        pinned.clear("+1");
+        assert!(pinned.list("+1").is_empty());
+    }
+
+    #[test]
+    fn test_head_keeps_char_boundaries() {
+        assert_eq!(head("héllo", 2), "h");
+        assert_eq!(head("hi", 10), "hi");
+    }
+
+    #[tokio::test]
+    async fn test_gather_reads_small_files_and_summarizes_large_ones() {
+        let mut claude = MockClaudeRunner::new();
+        // Summarized once, then cached until the file changes
+        claude
+            .expect_run_claude()
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _| Ok(ClaudeReply::new("It's long.", Some(0.01))));
+        let state = test_state_with(MockSignalApi::new(), claude);
+        let dir = std::env::temp_dir().join(format!("ccchat_pinned_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("design.md"), "Use SQLite.").unwrap();
+        std::fs::write(dir.join("big.txt"), "x".repeat(CONTEXT_FILE_MAX_BYTES + 1)).unwrap();
+        let root = dir.canonicalize().unwrap();
+        state
+            .pinned_files
+            .add("+1", root.join("design.md"))
+            .unwrap();
+        state.pinned_files.add("+1", root.join("big.txt")).unwrap();
+
+        let context = gather(&state, "+1", &dir).await.unwrap();
+        assert!(context.contains("[Pinned file design.md]\nUse SQLite."));
+        assert!(context.contains("[Pinned file big.txt, summarized]\nIt's long."));
+        std::fs::write(dir.join("design.md"), "Use Postgres.").unwrap();
+        let context = gather(&state, "+1", &dir).await.unwrap();
+        assert!(context.contains("Use Postgres."));
+        assert!(gather(&state, "+2", &dir).await.is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
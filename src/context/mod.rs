@@ -0,0 +1,129 @@
+//! Context providers: outside data added to prompts that ask for it.
+//!
+//! Each provider decides from the message text whether it's relevant (a
+//! calendar provider looks for "today", "meeting", "2pm" and the like), then
+//! fetches a short text block that is put in front of the message. Providers
+//! that expose personal data are limited to the account owner.
+
+pub(crate) mod calendar;
+pub(crate) mod files;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use tracing::{debug, warn};
+
+use crate::error::AppError;
+use crate::state::State;
+
+#[async_trait]
+pub(crate) trait ContextProvider: Send + Sync {
+    /// Short name for logs and the context heading.
+    fn name(&self) -> &'static str;
+
+    /// Only the account owner's prompts get this context.
+    fn admin_only(&self) -> bool {
+        true
+    }
+
+    /// Whether this message would benefit from the provider's context.
+    fn wants(&self, text: &str) -> bool;
+
+    /// Text to give Claude.
+    async fn context(&self, http: &Client) -> Result<String, AppError>;
+}
+
+/// Context from every provider that wants `text`, or None. A provider that
+/// fails is logged and skipped rather than holding up the reply.
+pub(crate) async fn gather(state: &State, sender: &str, text: &str) -> Option<String> {
+    let wanted = state
+        .context_providers
+        .iter()
+        .filter(|p| !p.admin_only() || state.is_admin(sender))
+        .filter(|p| p.wants(text));
+    let results = futures_util::future::join_all(
+        wanted.map(|p| async move { (p.name(), p.context(&state.http).await) }),
+    )
+    .await;
+    let blocks: Vec<String> = results
+        .into_iter()
+        .filter_map(|(name, result)| match result {
+            Ok(context) => {
+                debug!(provider = name, "Added context");
+                Some(format!("[{name}]\n{context}"))
+            }
+            Err(e) => {
+                warn!(provider = name, "Context provider failed: {e}");
+                None
+            }
+        })
+        .collect();
+    (!blocks.is_empty()).then(|| blocks.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    struct Fixed {
+        admin_only: bool,
+        result: Result<&'static str, &'static str>,
+    }
+
+    #[async_trait]
+    impl ContextProvider for Fixed {
+        fn name(&self) -> &'static str {
+            "Fixed"
+        }
+
+        fn admin_only(&self) -> bool {
+            self.admin_only
+        }
+
+        fn wants(&self, text: &str) -> bool {
+            text.contains("please")
+        }
+
+        async fn context(&self, _http: &Client) -> Result<String, AppError> {
+            self.result.map(str::to_string).map_err(AppError::from)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gather_respects_wants_and_admin_only() {
+        let mut state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.context_providers = vec![Box::new(Fixed {
+            admin_only: true,
+            result: Ok("secret schedule"),
+        })];
+        let owner = state.config.account.clone();
+        assert_eq!(
+            gather(&state, &owner, "please help").await.as_deref(),
+            Some("[Fixed]\nsecret schedule")
+        );
+        assert!(gather(&state, &owner, "help").await.is_none());
+        assert!(gather(&state, "+allowed_user", "please help")
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_gather_skips_failed_providers() {
+        let mut state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.context_providers = vec![
+            Box::new(Fixed {
+                admin_only: false,
+                result: Err("down"),
+            }),
+            Box::new(Fixed {
+                admin_only: false,
+                result: Ok("fine"),
+            }),
+        ];
+        assert_eq!(
+            gather(&state, "+allowed_user", "please").await.as_deref(),
+            Some("[Fixed]\nfine")
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Signal "delete for everyone" on messages Claude hasn't seen yet.
+//!
+//! A message deleted while it still sits in the debounce buffer, or in the
+//! inbound queue waiting for a worker, is dropped there, so Claude never
+//! answers something its sender took back. Messages are matched on the
+//! chat, the sender and the Signal timestamp. Once a run has started the
+//! deletion changes nothing; `/stop` cancels it.
+
+use tracing::info;
+
+use crate::signal::Deletion;
+use crate::state::State;
+
+/// Drop the deleted message wherever it's still waiting. Returns how many
+/// were dropped (0 when it was already handled or never buffered).
+pub(crate) fn honor(state: &State, deletion: &Deletion) -> usize {
+    let reply_to = match &deletion.group_id {
+        Some(group_id) => crate::helpers::group_chat_id(group_id),
+        None if deletion.is_sync => state.config.account.clone(),
+        None => deletion.source.clone(),
+    };
+    // Group messages are buffered and queued per speaker (`turns`).
+    let speaker = deletion.group_id.as_ref().map(|_| deletion.source.as_str());
+    let target = Some(deletion.target);
+
+    let key = crate::turns::buffer_key(&reply_to, speaker);
+    let mut dropped = 0;
+    if let Some(mut entry) = state.debounce.buffers.get_mut(&key) {
+        let before = entry.0.len();
+        entry.0.retain(|(_, sent_at)| *sent_at != target);
+        dropped += before - entry.0.len();
+    }
+
+    let queued = state.inbox.remove(|inbound| {
+        inbound.reply_to == reply_to
+            && inbound.speaker.as_deref() == speaker
+            && inbound.sent_at == target
+    });
+    for inbound in &queued {
+        if let Some(speaker) = &inbound.speaker {
+            crate::turns::lower(state, &inbound.reply_to, speaker);
+        }
+    }
+    dropped += queued.len();
+
+    if dropped > 0 {
+        info!(sender = %reply_to, count = dropped, "Dropped message deleted by its sender");
+    }
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+    use std::time::Instant;
+
+    fn deletion(source: &str, group_id: Option<&str>, target: i64) -> Deletion {
+        Deletion {
+            source: source.to_string(),
+            is_sync: false,
+            group_id: group_id.map(str::to_string),
+            target,
+        }
+    }
+
+    #[test]
+    fn test_drops_buffered_message() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.debounce.buffers.insert(
+            "+1".to_string(),
+            (
+                vec![("keep".into(), Some(100)), ("oops".into(), Some(200))],
+                Instant::now(),
+            ),
+        );
+        // Someone else's timestamp, or a group's, leaves the buffer alone.
+        assert_eq!(honor(&state, &deletion("+2", None, 200)), 0);
+        assert_eq!(honor(&state, &deletion("+1", Some("grp"), 200)), 0);
+
+        assert_eq!(honor(&state, &deletion("+1", None, 200)), 1);
+        let buffer = state.debounce.buffers.get("+1").unwrap();
+        assert_eq!(buffer.0, vec![("keep".to_string(), Some(100))]);
+    }
+
+    #[test]
+    fn test_drops_queued_message() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        let inbound = |reply_to: &str, speaker: Option<&str>, sent_at| crate::inbox::Inbound {
+            reply_to: reply_to.to_string(),
+            text: "hi".to_string(),
+            attachments: vec![],
+            received: Instant::now(),
+            speaker: speaker.map(str::to_string),
+            sent_at: Some(sent_at),
+        };
+        let group = crate::helpers::group_chat_id("grp");
+        state.inbox.push(inbound("+1", None, 100));
+        state.inbox.push(inbound(&group, Some("+1"), 100));
+        state.inbox.push(inbound(&group, Some("+2"), 100));
+
+        assert_eq!(honor(&state, &deletion("+1", Some("grp"), 100)), 1);
+        assert_eq!(state.inbox.depth(), 2);
+        assert_eq!(honor(&state, &deletion("+1", None, 100)), 1);
+        assert_eq!(honor(&state, &deletion("+1", None, 100)), 0);
+        assert_eq!(state.inbox.depth(), 1);
+    }
+}
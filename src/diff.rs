@@ -0,0 +1,268 @@
+//! Revised replies shown as a diff (`/prefs set diff on`).
+//!
+//! The last reply to each sender is kept in memory. When the next message
+//! asks for another go at it ("try again but shorter", "rewrite it more
+//! formally"), the new reply is sent as a word diff against that one:
+//! `[-removed-]` and `{+added+}`, with long unchanged or removed stretches
+//! cut down to their ends. A reply that shares less than half its words
+//! with the previous one is sent in full, since the diff wouldn't help.
+
+use crate::state::State;
+
+/// How a message starts when it asks for the last reply again, reworked.
+const REVISION_STARTS: &[&str] = &[
+    "try again",
+    "again but",
+    "again,",
+    "once more",
+    "redo",
+    "rewrite",
+    "rephrase",
+    "reword",
+    "make it",
+    "shorter",
+    "longer",
+    "more concise",
+    "simpler",
+    "same but",
+];
+
+/// Words kept either side of a collapsed stretch.
+const CONTEXT_WORDS: usize = 3;
+
+/// Largest comparison table (old words × new words) worth building.
+const MAX_CELLS: usize = 4_000_000;
+
+/// Whether `prompt` asks for the previous reply again, changed.
+pub(crate) fn is_revision(prompt: &str) -> bool {
+    let prompt = prompt.trim().to_lowercase();
+    REVISION_STARTS
+        .iter()
+        .any(|start| prompt.starts_with(start))
+}
+
+/// Keep `reply` as `sender`'s last one. When `prompt` asked to revise the
+/// one before and the sender has diffs on, returns the diff to send instead.
+pub(crate) fn for_reply(state: &State, sender: &str, prompt: &str, reply: &str) -> Option<String> {
+    let previous = state
+        .last_replies
+        .insert(sender.to_string(), reply.to_string())?;
+    if !state.prefs(sender).diff || !is_revision(prompt) {
+        return None;
+    }
+    render(&previous, reply)
+}
+
+/// Words and line breaks, in order.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            tokens.push("\n");
+        }
+        tokens.extend(line.split_whitespace());
+    }
+    tokens
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+/// Add `token` to the last run if it's of the same kind, else start one.
+fn push<'a>(runs: &mut Vec<(Op, Vec<&'a str>)>, op: Op, token: &'a str) {
+    match runs.last_mut() {
+        Some((last, tokens)) if *last == op => tokens.push(token),
+        _ => runs.push((op, vec![token])),
+    }
+}
+
+/// Word diff of `old` to `new` (longest common subsequence), or None when
+/// they're too long to compare.
+fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Option<Vec<(Op, Vec<&'a str>)>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    if (a.len() + 1).saturating_mul(b.len() + 1) > MAX_CELLS {
+        return None;
+    }
+    // lcs[i][j]: common subsequence length of a[i..] and b[j..].
+    let width = b.len() + 1;
+    let mut lcs = vec![0u32; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i * width + j] = if a[i] == b[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::new();
+    for token in &old[..prefix] {
+        push(&mut ops, Op::Same, token);
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            push(&mut ops, Op::Same, a[i]);
+            i += 1;
+            j += 1;
+        } else if j < b.len()
+            && (i == a.len() || lcs[i * width + j + 1] >= lcs[(i + 1) * width + j])
+        {
+            push(&mut ops, Op::Added, b[j]);
+            j += 1;
+        } else {
+            push(&mut ops, Op::Removed, a[i]);
+            i += 1;
+        }
+    }
+    for token in &old[old.len() - suffix..] {
+        push(&mut ops, Op::Same, token);
+    }
+    Some(ops)
+}
+
+/// Tokens joined back into text.
+fn join(tokens: &[&str]) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        if *token == "\n" {
+            text.push('\n');
+        } else {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push(' ');
+            }
+            text.push_str(token);
+        }
+    }
+    text
+}
+
+/// `tokens`, or its first and last few with `…` between when it's long.
+/// Only the ends that touch a change are kept (`head`, `tail`).
+fn shorten(tokens: &[&str], head: bool, tail: bool) -> String {
+    let keep = CONTEXT_WORDS * (usize::from(head) + usize::from(tail));
+    if tokens.len() <= keep + 1 {
+        return join(tokens);
+    }
+    let mut parts = Vec::new();
+    if head {
+        parts.push(join(&tokens[..CONTEXT_WORDS]));
+    }
+    parts.push("…".to_string());
+    if tail {
+        parts.push(join(&tokens[tokens.len() - CONTEXT_WORDS..]));
+    }
+    parts.join(" ")
+}
+
+/// The diff of `new` against `old` to send, or None when `new` should go
+/// out in full.
+pub(crate) fn render(old: &str, new: &str) -> Option<String> {
+    let (old, new) = (tokens(old), tokens(new));
+    let ops = diff(&old, &new)?;
+    let same: usize = ops
+        .iter()
+        .filter(|(op, _)| *op == Op::Same)
+        .map(|(_, words)| words.len())
+        .sum();
+    if same == new.len() && same == old.len() {
+        return Some("(Same as the last reply.)".to_string());
+    }
+    if same * 2 < new.len() {
+        return None;
+    }
+    let mut out = String::new();
+    let last = ops.len() - 1;
+    for (i, (op, words)) in ops.iter().enumerate() {
+        let piece = match op {
+            Op::Same => shorten(words, i > 0, i < last),
+            Op::Removed => format!("[-{}-]", shorten(words, true, true)),
+            Op::Added => format!("{{+{}+}}", join(words)),
+        };
+        if !out.is_empty() && !out.ends_with('\n') && !piece.starts_with('\n') {
+            out.push(' ');
+        }
+        out.push_str(&piece);
+    }
+    Some(format!("Changes from the last reply:\n\n{out}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_is_revision() {
+        assert!(is_revision("Try again but shorter"));
+        assert!(is_revision("  make it more formal"));
+        assert!(is_revision("rewrite that as bullet points"));
+        assert!(!is_revision("what's the weather"));
+        assert!(!is_revision("shortest path in a graph?"));
+    }
+
+    #[test]
+    fn test_diff_words() {
+        let old = tokens("the quick brown fox");
+        let new = tokens("the slow brown fox jumps");
+        assert_eq!(
+            diff(&old, &new).unwrap(),
+            vec![
+                (Op::Same, vec!["the"]),
+                (Op::Added, vec!["slow"]),
+                (Op::Removed, vec!["quick"]),
+                (Op::Same, vec!["brown", "fox"]),
+                (Op::Added, vec!["jumps"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_is_compact() {
+        let old = "Rust is a systems language. It is fast, safe and productive, and it has a \
+                   friendly compiler that explains its errors in detail with suggestions.";
+        let new = "Rust is a systems language. It is fast, safe and productive, and it has a \
+                   friendly compiler.";
+        let diff = render(old, new).unwrap();
+        assert_eq!(
+            diff,
+            "Changes from the last reply:\n\n… has a friendly {+compiler.+} [-compiler that explains … detail with suggestions.-]"
+        );
+        assert_eq!(render(new, new).unwrap(), "(Same as the last reply.)");
+        // Mostly rewritten: send it whole.
+        assert_eq!(render(old, "Rust: fast and safe."), None);
+    }
+
+    #[test]
+    fn test_for_reply_needs_pref_and_revision() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        let sender = format!("+diff_{}", uuid::Uuid::new_v4());
+        let first = "one two three four five six seven eight nine ten";
+        let second = "one two three four five six seven eight nine";
+        assert_eq!(for_reply(&state, &sender, "count to ten", first), None);
+        assert_eq!(for_reply(&state, &sender, "try again", second), None);
+
+        state.get_or_create_session(&sender);
+        state.update_prefs(&sender, |prefs| prefs.diff = true);
+        assert_eq!(for_reply(&state, &sender, "hello", first), None);
+        let diff = for_reply(&state, &sender, "again but shorter", second);
+        assert!(diff.unwrap().ends_with("[-ten-]"));
+        crate::memory::delete_memory(&sender);
+    }
+}
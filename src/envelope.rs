@@ -0,0 +1,312 @@
+//! Typed model of signal-cli envelopes as delivered over the receive WebSocket.
+//!
+//! Every field is optional or defaulted so unknown message kinds still parse.
+//! Parsing is lenient section by section: a malformed `dataMessage` or a single
+//! bad attachment is logged with a warning and dropped instead of discarding
+//! the whole envelope.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use tracing::warn;
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Envelope {
+    pub(crate) source: Option<String>,
+    pub(crate) source_number: Option<String>,
+    pub(crate) source_uuid: Option<String>,
+    pub(crate) source_name: Option<String>,
+    pub(crate) source_device: Option<u32>,
+    pub(crate) timestamp: Option<i64>,
+    pub(crate) data_message: Option<DataMessage>,
+    pub(crate) sync_message: Option<SyncMessage>,
+    pub(crate) receipt_message: Option<Value>,
+    pub(crate) typing_message: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DataMessage {
+    pub(crate) timestamp: Option<i64>,
+    pub(crate) message: Option<String>,
+    #[serde(default, deserialize_with = "lenient_vec")]
+    pub(crate) attachments: Vec<Attachment>,
+    pub(crate) group_info: Option<GroupInfo>,
+    pub(crate) reaction: Option<Reaction>,
+    pub(crate) remote_delete: Option<RemoteDelete>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SyncMessage {
+    pub(crate) sent_message: Option<SentMessage>,
+}
+
+/// A message the account owner sent from another device (e.g. Note to Self).
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SentMessage {
+    pub(crate) destination: Option<String>,
+    pub(crate) destination_number: Option<String>,
+    pub(crate) timestamp: Option<i64>,
+    pub(crate) message: Option<String>,
+    #[serde(default, deserialize_with = "lenient_vec")]
+    pub(crate) attachments: Vec<Attachment>,
+    pub(crate) group_info: Option<GroupInfo>,
+    pub(crate) remote_delete: Option<RemoteDelete>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct GroupInfo {
+    pub(crate) group_id: String,
+    #[serde(rename = "type")]
+    pub(crate) kind: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Reaction {
+    pub(crate) emoji: String,
+    pub(crate) target_author: Option<String>,
+    pub(crate) target_author_number: Option<String>,
+    pub(crate) target_sent_timestamp: Option<i64>,
+    #[serde(default)]
+    pub(crate) is_remove: bool,
+}
+
+/// "Delete for everyone" of an earlier message.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RemoteDelete {
+    /// Timestamp of the deleted message.
+    pub(crate) timestamp: i64,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Attachment {
+    pub(crate) id: String,
+    pub(crate) content_type: String,
+    pub(crate) filename: Option<String>,
+    pub(crate) size: Option<u64>,
+    #[serde(default)]
+    pub(crate) voice_note: bool,
+}
+
+/// Deserialize a list, skipping (and warning about) elements that don't fit `T`.
+fn lenient_vec<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let raw: Option<Vec<Value>> = Option::deserialize(deserializer)?;
+    Ok(raw
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|v| match serde_json::from_value(v) {
+            Ok(item) => Some(item),
+            Err(e) => {
+                warn!("Skipping malformed {}: {e}", short_type_name::<T>());
+                None
+            }
+        })
+        .collect())
+}
+
+fn short_type_name<T>() -> &'static str {
+    let full = std::any::type_name::<T>();
+    full.rsplit("::").next().unwrap_or(full)
+}
+
+/// Parse one optional section, warning (not failing) when it is present but malformed.
+fn parse_section<T: DeserializeOwned>(value: &Value, name: &str) -> Option<T> {
+    if value.is_null() {
+        return None;
+    }
+    match serde_json::from_value(value.clone()) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!("Ignoring malformed {name}: {e}");
+            None
+        }
+    }
+}
+
+impl Envelope {
+    /// Parse a receive payload of the form `{"envelope": {...}, "account": ...}`.
+    /// Returns None only if there is no envelope object at all.
+    pub(crate) fn from_value(payload: &Value) -> Option<Self> {
+        let raw = payload.get("envelope")?;
+        if !raw.is_object() {
+            warn!("Envelope is not an object: {raw}");
+            return None;
+        }
+        if let Ok(env) = serde_json::from_value::<Envelope>(raw.clone()) {
+            return Some(env);
+        }
+        // Fall back to field-by-field so one bad section doesn't lose the message.
+        Some(Envelope {
+            source: parse_section(&raw["source"], "source"),
+            source_number: parse_section(&raw["sourceNumber"], "sourceNumber"),
+            source_uuid: parse_section(&raw["sourceUuid"], "sourceUuid"),
+            source_name: parse_section(&raw["sourceName"], "sourceName"),
+            source_device: parse_section(&raw["sourceDevice"], "sourceDevice"),
+            timestamp: parse_section(&raw["timestamp"], "timestamp"),
+            data_message: parse_section(&raw["dataMessage"], "dataMessage"),
+            sync_message: parse_section(&raw["syncMessage"], "syncMessage"),
+            receipt_message: parse_section(&raw["receiptMessage"], "receiptMessage"),
+            typing_message: parse_section(&raw["typingMessage"], "typingMessage"),
+        })
+    }
+
+    /// The sender's phone number, falling back to the generic source (often a UUID).
+    pub(crate) fn sender(&self) -> Option<&str> {
+        self.source_number
+            .as_deref()
+            .or(self.source.as_deref())
+            .filter(|s| !s.is_empty())
+    }
+
+    pub(crate) fn sent_message(&self) -> Option<&SentMessage> {
+        self.sync_message.as_ref()?.sent_message.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Value {
+        let path = format!(
+            "{}/tests/fixtures/envelopes/{name}.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let contents = std::fs::read_to_string(&path).expect("fixture missing");
+        serde_json::from_str(&contents).expect("fixture is not valid JSON")
+    }
+
+    #[test]
+    fn test_fixture_data_message() {
+        let env = Envelope::from_value(&fixture("data_message")).unwrap();
+        assert_eq!(env.sender(), Some("+15551234567"));
+        assert_eq!(env.source_name.as_deref(), Some("Alice"));
+        assert_eq!(env.source_device, Some(1));
+        let data = env.data_message.unwrap();
+        assert_eq!(data.message.as_deref(), Some("Hello Claude"));
+        assert!(data.attachments.is_empty());
+        assert!(data.group_info.is_none());
+    }
+
+    #[test]
+    fn test_fixture_sync_note_to_self() {
+        let env = Envelope::from_value(&fixture("sync_message")).unwrap();
+        let sent = env.sent_message().unwrap();
+        assert_eq!(sent.message.as_deref(), Some("/status"));
+        assert_eq!(sent.destination_number.as_deref(), Some("+15550000000"));
+        assert!(env.data_message.is_none());
+    }
+
+    #[test]
+    fn test_fixture_group_message() {
+        let env = Envelope::from_value(&fixture("group_message")).unwrap();
+        let group = env.data_message.unwrap().group_info.unwrap();
+        assert_eq!(
+            group.group_id,
+            "kT3f0vQ9H1c2Yx8pL5mN4aRbZcWdEeFgHiJkLmNoPq0="
+        );
+        assert_eq!(group.kind.as_deref(), Some("DELIVER"));
+    }
+
+    #[test]
+    fn test_fixture_attachments() {
+        let env = Envelope::from_value(&fixture("attachments")).unwrap();
+        let data = env.data_message.unwrap();
+        assert_eq!(data.attachments.len(), 2);
+        assert_eq!(data.attachments[0].content_type, "image/jpeg");
+        assert_eq!(data.attachments[0].size, Some(123456));
+        assert!(data.attachments[1].voice_note);
+    }
+
+    #[test]
+    fn test_fixture_reaction() {
+        let env = Envelope::from_value(&fixture("reaction")).unwrap();
+        let data = env.data_message.unwrap();
+        assert!(data.message.is_none());
+        let reaction = data.reaction.unwrap();
+        assert_eq!(reaction.emoji, "👍");
+        assert_eq!(reaction.target_sent_timestamp, Some(1700000000000));
+        assert!(!reaction.is_remove);
+    }
+
+    #[test]
+    fn test_fixture_remote_delete() {
+        let env = Envelope::from_value(&fixture("remote_delete")).unwrap();
+        let data = env.data_message.unwrap();
+        assert!(data.message.is_none());
+        assert_eq!(data.remote_delete.unwrap().timestamp, 1700000000000);
+    }
+
+    #[test]
+    fn test_fixture_receipt_and_typing() {
+        let receipt = Envelope::from_value(&fixture("receipt")).unwrap();
+        assert!(receipt.receipt_message.is_some());
+        assert!(receipt.data_message.is_none());
+        let typing = Envelope::from_value(&fixture("typing")).unwrap();
+        assert!(typing.typing_message.is_some());
+    }
+
+    #[test]
+    fn test_malformed_attachment_skipped() {
+        let payload = serde_json::json!({
+            "envelope": {
+                "sourceNumber": "+1",
+                "dataMessage": {
+                    "message": "two files",
+                    "attachments": [
+                        {"contentType": "image/png"},
+                        {"id": "ok", "contentType": "text/plain"}
+                    ]
+                }
+            }
+        });
+        let data = Envelope::from_value(&payload)
+            .unwrap()
+            .data_message
+            .unwrap();
+        assert_eq!(data.attachments.len(), 1);
+        assert_eq!(data.attachments[0].id, "ok");
+    }
+
+    #[test]
+    fn test_malformed_section_keeps_rest() {
+        let payload = serde_json::json!({
+            "envelope": {
+                "sourceNumber": "+1",
+                "sourceDevice": "not-a-number",
+                "dataMessage": {"message": "still here"}
+            }
+        });
+        let env = Envelope::from_value(&payload).unwrap();
+        assert_eq!(env.sender(), Some("+1"));
+        assert!(env.source_device.is_none());
+        assert_eq!(
+            env.data_message.unwrap().message.as_deref(),
+            Some("still here")
+        );
+    }
+
+    #[test]
+    fn test_missing_envelope() {
+        assert!(Envelope::from_value(&serde_json::json!({"account": "+1"})).is_none());
+        assert!(Envelope::from_value(&serde_json::json!({"envelope": "oops"})).is_none());
+    }
+
+    #[test]
+    fn test_sender_falls_back_to_source() {
+        let payload = serde_json::json!({"envelope": {"source": "uuid-1", "sourceNumber": null}});
+        let env = Envelope::from_value(&payload).unwrap();
+        assert_eq!(env.sender(), Some("uuid-1"));
+    }
+}
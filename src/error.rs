@@ -4,8 +4,14 @@ use thiserror::Error;
 pub(crate) enum AppError {
     #[error("Signal API error: {0}")]
     Signal(String),
+    /// Failure talking to a non-Signal messaging bridge.
+    #[error("Transport error: {0}")]
+    Transport(String),
     #[error("Claude error: {0}")]
     Claude(String),
+    /// `claude` no longer has the session (its local store was pruned).
+    #[error("Claude session not found: {0}")]
+    UnknownSession(String),
     #[error("Database error: {0}")]
     Database(#[from] rusqlite::Error),
     #[error("IO error: {0}")]
@@ -14,10 +20,38 @@ pub(crate) enum AppError {
     Http(#[from] reqwest::Error),
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
+    /// `claude` stopped at `--max-budget-usd`; holds what it had so far.
+    #[error("Budget limit reached")]
+    BudgetExceeded(crate::traits::ClaudeReply),
+    /// The sender cancelled the run with `/stop`.
+    #[error("Stopped")]
+    Stopped,
     #[error("{0}")]
     Other(String),
 }
 
+impl AppError {
+    /// Coarse failure bucket for metrics (e.g. `ccchat_claude_failures_total{category=...}`).
+    pub(crate) fn category(&self) -> &'static str {
+        let msg = self.to_string().to_lowercase();
+        match self {
+            AppError::Io(_) => "spawn",
+            AppError::Http(_) | AppError::Signal(_) | AppError::Transport(_) => "network",
+            AppError::UnknownSession(_) => "session",
+            _ if msg.contains("budget") => "budget",
+            _ if msg.contains("timed out") || msg.contains("timeout") => "timeout",
+            _ if msg.contains("rate limit")
+                || msg.contains("overloaded")
+                || msg.contains("429") =>
+            {
+                "rate_limited"
+            }
+            _ if msg.contains("auth") || msg.contains("login") || msg.contains("401") => "auth",
+            _ => "other",
+        }
+    }
+}
+
 impl From<String> for AppError {
     fn from(s: String) -> Self {
         AppError::Other(s)
@@ -95,4 +129,33 @@ mod tests {
         let err = AppError::Claude("rate limited".to_string());
         assert_eq!(err.to_string(), "Claude error: rate limited");
     }
+
+    #[test]
+    fn test_category_buckets() {
+        let io = AppError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "claude"));
+        assert_eq!(io.category(), "spawn");
+        assert_eq!(
+            AppError::Claude("Exceeded max budget".into()).category(),
+            "budget"
+        );
+        assert_eq!(
+            AppError::Claude("request timed out".into()).category(),
+            "timeout"
+        );
+        assert_eq!(
+            AppError::Claude("API Error: 529 overloaded".into()).category(),
+            "rate_limited"
+        );
+        assert_eq!(
+            AppError::Claude("Invalid API key · Please run /login".into()).category(),
+            "auth"
+        );
+        assert_eq!(AppError::Signal("Send failed".into()).category(), "network");
+        assert_eq!(
+            AppError::Transport("Send failed".into()).category(),
+            "network"
+        );
+        assert_eq!(AppError::UnknownSession("abc".into()).category(), "session");
+        assert_eq!(AppError::Other("boom".into()).category(), "other");
+    }
 }
@@ -0,0 +1,121 @@
+//! Pre-flight cost estimates (`--confirm-above-usd`).
+//!
+//! Before a large prompt (a pasted log, a big attachment) goes to Claude, its
+//! input tokens are counted locally and priced at list rates. Above the
+//! threshold the run is held until the sender replies `/confirm`. The count
+//! follows how Claude's tokenizer splits text closely enough to catch the
+//! expensive cases; it doesn't try to be exact.
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Tokens an image costs Claude, whatever its size (it is scaled down first).
+const IMAGE_TOKENS: u64 = 1_600;
+/// Bytes per token for PDFs and other files that aren't text.
+const BINARY_BYTES_PER_TOKEN: u64 = 50;
+
+/// A run held back until the sender confirms it.
+pub(crate) struct PendingRun {
+    /// The message as received, before routing or redaction.
+    pub(crate) text: String,
+    pub(crate) attachments: Vec<PathBuf>,
+    /// Extra context the message came with (e.g. the `/gh` digest).
+    pub(crate) context: Option<String>,
+    pub(crate) asked_at: Instant,
+}
+
+/// Approximate Claude token count: words of up to eight letters are one
+/// token and longer ones one more per eight letters, digits go in threes, every punctuation mark
+/// and every non-Latin character is a token of its own, and whitespace is
+/// folded into the token after it.
+pub(crate) fn count_tokens(text: &str) -> u64 {
+    let mut tokens: u64 = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_ascii_alphabetic() {
+            let mut len: u64 = 1;
+            while chars.next_if(|c| c.is_ascii_alphabetic()).is_some() {
+                len += 1;
+            }
+            tokens += 1 + (len - 1) / 8;
+        } else if c.is_ascii_digit() {
+            let mut len: u64 = 1;
+            while chars.next_if(|c| c.is_ascii_digit()).is_some() {
+                len += 1;
+            }
+            tokens += len.div_ceil(3);
+        } else {
+            tokens += 1;
+        }
+    }
+    tokens
+}
+
+fn attachment_tokens(path: &Path) -> u64 {
+    if crate::ocr::is_image(path) {
+        return IMAGE_TOKENS;
+    }
+    match std::fs::read(path) {
+        Ok(bytes) => match std::str::from_utf8(&bytes) {
+            Ok(text) => count_tokens(text),
+            Err(_) => bytes.len() as u64 / BINARY_BYTES_PER_TOKEN,
+        },
+        Err(_) => 0,
+    }
+}
+
+/// Estimated input tokens and their list-price cost for one run.
+pub(crate) fn estimate(prompt: &str, attachments: &[PathBuf], model: &str) -> (u64, f64) {
+    let tokens = count_tokens(prompt)
+        + attachments
+            .iter()
+            .map(|p| attachment_tokens(p))
+            .sum::<u64>();
+    let (input_price, _) = crate::streaming::list_prices(model);
+    (tokens, tokens as f64 * input_price / 1_000_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("the cat sat"), 3);
+        // Long words split, numbers go in threes, punctuation counts
+        assert_eq!(count_tokens("internationalization"), 3);
+        assert_eq!(count_tokens("1234567"), 3);
+        assert_eq!(count_tokens("fn main() {}"), 6);
+        assert_eq!(count_tokens("日本語"), 3);
+        // A typical log line lands near chars / 4
+        let line = "2024-05-01T12:00:03Z ERROR db: connection refused (retry 3/5)\n";
+        let per_line = count_tokens(line) as usize;
+        assert!(
+            (line.len() / 5..=line.len() / 2).contains(&per_line),
+            "{per_line}"
+        );
+    }
+
+    #[test]
+    fn test_estimate_prices_input_at_list_rates() {
+        let log = "ERROR connection refused\n".repeat(100_000);
+        let (tokens, cost) = estimate(&log, &[], "opus");
+        assert_eq!(tokens, 400_000);
+        assert!((cost - 6.0).abs() < 1e-9, "{cost}");
+        let (_, cheap) = estimate(&log, &[], "haiku");
+        assert!(cheap < 0.5);
+
+        let dir = std::env::temp_dir().join(format!("ccchat_estimate_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("shot.png");
+        std::fs::write(&image, [0u8; 10]).unwrap();
+        let notes = dir.join("notes.txt");
+        std::fs::write(&notes, "the cat sat").unwrap();
+        assert_eq!(estimate("", &[image, notes], "sonnet").0, IMAGE_TOKENS + 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+//! signal-cli-api failover (`--api-url primary,secondary`).
+//!
+//! With more than one API URL, ccchat uses the first until it fails
+//! [`API_FAILOVER_THRESHOLD`] times in a row — WebSocket connects and drops,
+//! or sends that error or come back 5xx — then moves to the next, wrapping
+//! around after the last. Each switch is logged and reported to the account
+//! owner from the instance that took over.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::warn;
+
+use crate::constants::API_FAILOVER_THRESHOLD;
+
+pub(crate) struct ApiEndpoints {
+    urls: Vec<String>,
+    active: AtomicUsize,
+    /// Consecutive failures on the active URL.
+    failures: AtomicU32,
+    /// Where switch notices go (the admin alert task).
+    alerts: Option<UnboundedSender<String>>,
+}
+
+impl ApiEndpoints {
+    pub(crate) fn new(urls: Vec<String>, alerts: Option<UnboundedSender<String>>) -> Self {
+        Self {
+            urls,
+            active: AtomicUsize::new(0),
+            failures: AtomicU32::new(0),
+            alerts,
+        }
+    }
+
+    /// One URL, no failover.
+    pub(crate) fn single(url: impl Into<String>) -> Self {
+        Self::new(vec![url.into()], None)
+    }
+
+    /// Base URL to use right now.
+    pub(crate) fn current(&self) -> String {
+        self.urls
+            .get(self.active.load(Ordering::Relaxed))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Count a failure on `url`; switches to the next URL once the active
+    /// one has failed too often. Failures on a URL that's no longer active
+    /// (a request that started before a switch) don't count. Returns true
+    /// if this call switched.
+    pub(crate) fn record_failure(&self, url: &str) -> bool {
+        if self.urls.len() < 2 || url != self.current() {
+            return false;
+        }
+        if self.failures.fetch_add(1, Ordering::Relaxed) + 1 < API_FAILOVER_THRESHOLD {
+            return false;
+        }
+        let from = self.active.load(Ordering::Relaxed);
+        let to = (from + 1) % self.urls.len();
+        if self
+            .active
+            .compare_exchange(from, to, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        self.failures.store(0, Ordering::Relaxed);
+        let notice = format!(
+            "signal-cli-api at {} failed {API_FAILOVER_THRESHOLD} times in a row; switched to {}.",
+            self.urls[from], self.urls[to]
+        );
+        warn!("{notice}");
+        if let Some(alerts) = &self.alerts {
+            let _ = alerts.send(notice);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fails_over_after_threshold_and_wraps() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let api = ApiEndpoints::new(
+            vec!["http://a:8080".to_string(), "http://b:8080".to_string()],
+            Some(tx),
+        );
+        for _ in 1..API_FAILOVER_THRESHOLD {
+            assert!(!api.record_failure("http://a:8080"));
+        }
+        // A success in between starts the count again
+        api.record_success();
+        for _ in 1..API_FAILOVER_THRESHOLD {
+            assert!(!api.record_failure("http://a:8080"));
+        }
+        assert!(api.record_failure("http://a:8080"));
+        assert_eq!(api.current(), "http://b:8080");
+        assert!(rx
+            .try_recv()
+            .unwrap()
+            .ends_with("switched to http://b:8080."));
+
+        // Stragglers from the old URL don't count against the new one
+        for _ in 0..API_FAILOVER_THRESHOLD {
+            assert!(!api.record_failure("http://a:8080"));
+        }
+        for _ in 0..API_FAILOVER_THRESHOLD {
+            api.record_failure("http://b:8080");
+        }
+        assert_eq!(api.current(), "http://a:8080");
+    }
+
+    #[test]
+    fn test_single_url_never_switches() {
+        let api = ApiEndpoints::single("http://only:8080");
+        for _ in 0..10 {
+            assert!(!api.record_failure("http://only:8080"));
+        }
+        assert_eq!(api.current(), "http://only:8080");
+    }
+}
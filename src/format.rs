@@ -0,0 +1,518 @@
+//! Reply formatting applied before a reply is split and sent.
+//!
+//! Claude writes GitHub-flavoured Markdown, and most messengers show its
+//! tables as rows of pipes and dashes. Tables are redrawn with box-drawing
+//! characters, or rendered to a PNG attachment when they're too wide to read
+//! on a phone. Code blocks are left alone.
+
+use embedded_graphics::mono_font::iso_8859_1::FONT_10X20;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{Gray8, GrayColor};
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::AppError;
+
+/// How Markdown tables in replies are shown (`/format tables`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TableFormat {
+    /// Box-drawing text, or an image when wider than a phone screen.
+    #[default]
+    Auto,
+    /// Always box-drawing text.
+    Text,
+    /// Always an image.
+    Image,
+    /// Leave the Markdown as Claude wrote it.
+    Off,
+}
+
+impl TableFormat {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(Self::Auto),
+            "text" => Some(Self::Text),
+            "image" => Some(Self::Image),
+            "off" => Some(Self::Off),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Text => "text",
+            Self::Image => "image",
+            Self::Off => "off",
+        }
+    }
+}
+
+/// A sender's `/format` settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct FormatPrefs {
+    pub(crate) tables: TableFormat,
+    /// Send long code blocks and diagrams as images (`render` builds only).
+    pub(crate) code_images: bool,
+}
+
+impl Default for FormatPrefs {
+    fn default() -> Self {
+        Self {
+            tables: TableFormat::Auto,
+            code_images: true,
+        }
+    }
+}
+
+/// A reply ready to send: the text plus PNG attachments (filename, bytes)
+/// for the parts that were rendered as images.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Formatted {
+    pub(crate) text: String,
+    pub(crate) images: Vec<(String, Vec<u8>)>,
+}
+
+/// Stands in for a table that was sent as an image.
+const TABLE_IMAGE_NOTE: &str = "(Table sent as an image.)";
+
+/// Rewrite the Markdown tables in a reply. `fenced` wraps text tables in a
+/// code block for messengers that render Markdown, so they show monospaced.
+pub(crate) fn format_reply(text: &str, tables: TableFormat, fenced: bool) -> Formatted {
+    if tables == TableFormat::Off {
+        return Formatted {
+            text: text.to_string(),
+            images: Vec::new(),
+        };
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut images = Vec::new();
+    let mut in_fence = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        let table = if in_fence {
+            None
+        } else {
+            parse_table(&lines[i..])
+        };
+        let Some((table, consumed)) = table else {
+            out.push(line.to_string());
+            i += 1;
+            continue;
+        };
+        i += consumed;
+        let boxed = table.to_box_text();
+        let too_wide = boxed.lines().map(UnicodeWidthStr::width).max().unwrap_or(0)
+            > crate::constants::TABLE_TEXT_MAX_WIDTH;
+        if tables == TableFormat::Image || (tables == TableFormat::Auto && too_wide) {
+            match table.to_png() {
+                Ok(png) => {
+                    images.push((format!("table-{}.png", images.len() + 1), png));
+                    out.push(TABLE_IMAGE_NOTE.to_string());
+                    continue;
+                }
+                Err(e) => tracing::warn!("Table image failed, sending text: {e}"),
+            }
+        }
+        if fenced {
+            out.push(format!("```\n{boxed}\n```"));
+        } else {
+            out.push(boxed);
+        }
+    }
+    let mut text = out.join("\n");
+    if text.is_empty() && !images.is_empty() {
+        text = TABLE_IMAGE_NOTE.to_string();
+    }
+    Formatted { text, images }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, PartialEq)]
+struct Table {
+    header: Vec<String>,
+    aligns: Vec<Align>,
+    rows: Vec<Vec<String>>,
+}
+
+/// A table starting at the first line: header row, delimiter row, then body
+/// rows up to the first line without a pipe. Returns it with the line count.
+fn parse_table(lines: &[&str]) -> Option<(Table, usize)> {
+    let header = parse_row(lines.first()?)?;
+    let aligns = parse_delimiter(lines.get(1)?)?;
+    if aligns.len() != header.len() {
+        return None;
+    }
+    let columns = header.len();
+    let mut rows = Vec::new();
+    for line in &lines[2..] {
+        let Some(mut row) = parse_row(line) else {
+            break;
+        };
+        row.resize(columns, String::new());
+        rows.push(row);
+    }
+    let consumed = rows.len() + 2;
+    Some((
+        Table {
+            header,
+            aligns,
+            rows,
+        },
+        consumed,
+    ))
+}
+
+/// Cells of a `| a | b |` row, with inline emphasis and code marks dropped.
+fn parse_row(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    if !line.contains('|') {
+        return None;
+    }
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = match line.strip_suffix('|') {
+        Some(rest) if !rest.ends_with('\\') => rest,
+        _ => line,
+    };
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell)),
+            _ => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    Some(
+        cells
+            .iter()
+            .map(|c| c.replace("**", "").replace('`', "").trim().to_string())
+            .collect(),
+    )
+}
+
+fn parse_delimiter(line: &str) -> Option<Vec<Align>> {
+    parse_row(line)?
+        .iter()
+        .map(|cell| {
+            let left = cell.starts_with(':');
+            let right = cell.ends_with(':');
+            let dashes = cell.trim_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (left, right) {
+                (true, true) => Align::Center,
+                (false, true) => Align::Right,
+                _ => Align::Left,
+            })
+        })
+        .collect()
+}
+
+fn pad(text: &str, width: usize, align: Align) -> String {
+    let gap = width.saturating_sub(text.width());
+    let (before, after) = match align {
+        Align::Left => (0, gap),
+        Align::Right => (gap, 0),
+        Align::Center => (gap / 2, gap - gap / 2),
+    };
+    format!("{}{text}{}", " ".repeat(before), " ".repeat(after))
+}
+
+impl Table {
+    fn widths(&self, measure: impl Fn(&str) -> usize) -> Vec<usize> {
+        (0..self.header.len())
+            .map(|col| {
+                std::iter::once(&self.header)
+                    .chain(&self.rows)
+                    .map(|row| measure(&row[col]))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    fn to_box_text(&self) -> String {
+        let widths = self.widths(|s| s.width());
+        let rule = |left: &str, mid: &str, right: &str| {
+            let segments: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            format!("{left}{}{right}", segments.join(mid))
+        };
+        let row = |cells: &[String]| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .zip(&self.aligns)
+                .map(|((cell, w), align)| format!(" {} ", pad(cell, *w, *align)))
+                .collect();
+            format!("│{}│", padded.join("│"))
+        };
+        let mut lines = vec![rule("┌", "┬", "┐"), row(&self.header)];
+        lines.push(rule("├", "┼", "┤"));
+        lines.extend(self.rows.iter().map(|r| row(r)));
+        lines.push(rule("└", "┴", "┘"));
+        lines.join("\n")
+    }
+
+    /// Grayscale PNG of the table in a fixed-width font. Characters the font
+    /// lacks are drawn as `?`.
+    fn to_png(&self) -> Result<Vec<u8>, AppError> {
+        const PAD: u32 = 8;
+        let char_w = FONT_10X20.character_size.width;
+        let char_h = FONT_10X20.character_size.height;
+        let max_chars = crate::constants::TABLE_IMAGE_MAX_CELL_CHARS;
+        let clip = |s: &str| -> String {
+            if s.chars().count() > max_chars {
+                let cut: String = s.chars().take(max_chars - 3).collect();
+                format!("{cut}...")
+            } else {
+                s.to_string()
+            }
+        };
+        let header: Vec<String> = self.header.iter().map(|c| clip(c)).collect();
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|r| r.iter().map(|c| clip(c)).collect())
+            .collect();
+        let clipped = Table {
+            header,
+            aligns: self.aligns.clone(),
+            rows,
+        };
+        let widths = clipped.widths(|s| s.chars().count());
+        let col_px: Vec<u32> = widths
+            .iter()
+            .map(|w| *w as u32 * char_w + 2 * PAD)
+            .collect();
+        let row_h = char_h + PAD;
+        let width = col_px.iter().sum::<u32>() + col_px.len() as u32 + 1;
+        let height = (clipped.rows.len() as u32 + 1) * (row_h + 1) + 1;
+
+        let mut canvas = Canvas::new(width, height);
+        canvas.fill(0, 0, width, row_h + 1, 235);
+        let style = MonoTextStyle::new(&FONT_10X20, Gray8::BLACK);
+        let all_rows = std::iter::once(&clipped.header).chain(&clipped.rows);
+        for (r, row) in all_rows.enumerate() {
+            let top = r as u32 * (row_h + 1) + 1;
+            let mut left = 1;
+            for ((cell, px), (w, align)) in row
+                .iter()
+                .zip(&col_px)
+                .zip(widths.iter().zip(&clipped.aligns))
+            {
+                let gap = (*w - cell.chars().count()) as u32 * char_w;
+                let offset = match align {
+                    Align::Left => 0,
+                    Align::Right => gap,
+                    Align::Center => gap / 2,
+                };
+                let origin = Point::new((left + PAD + offset) as i32, (top + PAD / 2) as i32);
+                let _ = Text::with_baseline(cell, origin, style, Baseline::Top).draw(&mut canvas);
+                left += px + 1;
+            }
+        }
+        // Grid lines last so they sit on top of the header shading.
+        let mut x = 0;
+        canvas.fill(x, 0, 1, height, 150);
+        for px in &col_px {
+            x += px + 1;
+            canvas.fill(x, 0, 1, height, 150);
+        }
+        for r in 0..=clipped.rows.len() as u32 + 1 {
+            canvas.fill(0, r * (row_h + 1), width, 1, 150);
+        }
+        canvas.encode_png()
+    }
+}
+
+/// Bar chart as a PNG, one bar per value with its label underneath (empty
+/// labels are skipped). Used for `/report` busiest hours.
+pub(crate) fn bar_chart_png(
+    title: &str,
+    labels: &[String],
+    values: &[u64],
+) -> Result<Vec<u8>, AppError> {
+    const BAR_W: u32 = 20;
+    const GAP: u32 = 6;
+    const MARGIN: u32 = 16;
+    const PLOT_H: u32 = 200;
+    let char_h = FONT_10X20.character_size.height;
+    let width = (MARGIN * 2 + values.len() as u32 * (BAR_W + GAP))
+        .max(title.chars().count() as u32 * FONT_10X20.character_size.width + MARGIN * 2);
+    let top = MARGIN + char_h + MARGIN;
+    let base = top + PLOT_H;
+    let height = base + char_h + MARGIN;
+
+    let mut canvas = Canvas::new(width, height);
+    let style = MonoTextStyle::new(&FONT_10X20, Gray8::BLACK);
+    let _ = Text::with_baseline(
+        title,
+        Point::new(MARGIN as i32, MARGIN as i32),
+        style,
+        Baseline::Top,
+    )
+    .draw(&mut canvas);
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    for (i, value) in values.iter().enumerate() {
+        let bar_h = (value * PLOT_H as u64 / max) as u32;
+        let x = MARGIN + i as u32 * (BAR_W + GAP);
+        canvas.fill(x, base - bar_h, BAR_W, bar_h, 110);
+        if let Some(label) = labels.get(i).filter(|l| !l.is_empty()) {
+            let origin = Point::new(x as i32, (base + 2) as i32);
+            let _ = Text::with_baseline(label, origin, style, Baseline::Top).draw(&mut canvas);
+        }
+    }
+    canvas.fill(MARGIN / 2, base, width - MARGIN, 1, 0);
+    canvas.encode_png()
+}
+
+/// 8-bit grayscale pixel buffer that embedded-graphics draws text into.
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![255; (width * height) as usize],
+        }
+    }
+
+    fn fill(&mut self, x: u32, y: u32, w: u32, h: u32, luma: u8) {
+        for row in y..(y + h).min(self.height) {
+            for col in x..(x + w).min(self.width) {
+                self.pixels[(row * self.width + col) as usize] = luma;
+            }
+        }
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>, AppError> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| AppError::Other(format!("PNG encoding failed: {e}")))?;
+        Ok(png)
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Gray8;
+    type Error = std::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) {
+                if x < self.width && y < self.height {
+                    self.pixels[(y * self.width + x) as usize] = color.luma();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str =
+        "Results:\n\n| Name | Score |\n|:-----|------:|\n| **Ann** | 9 |\n| Bob | 10 |\n\nDone.";
+
+    #[test]
+    fn test_table_redrawn_as_box_text() {
+        let out = format_reply(TABLE, TableFormat::Text, false);
+        assert!(out.images.is_empty());
+        assert_eq!(
+            out.text,
+            "Results:\n\n\
+             ┌──────┬───────┐\n\
+             │ Name │ Score │\n\
+             ├──────┼───────┤\n\
+             │ Ann  │     9 │\n\
+             │ Bob  │    10 │\n\
+             └──────┴───────┘\n\nDone."
+        );
+    }
+
+    #[test]
+    fn test_fenced_for_markdown_messengers() {
+        let out = format_reply(TABLE, TableFormat::Text, true);
+        assert!(out.text.contains("```\n┌"));
+        assert!(out.text.contains("┘\n```"));
+    }
+
+    #[test]
+    fn test_off_and_code_blocks_left_alone() {
+        assert_eq!(format_reply(TABLE, TableFormat::Off, false).text, TABLE);
+        let code = "```\n| a | b |\n|---|---|\n| 1 | 2 |\n```";
+        assert_eq!(format_reply(code, TableFormat::Text, false).text, code);
+    }
+
+    #[test]
+    fn test_not_a_table_without_delimiter_row() {
+        let text = "a | b\nc | d";
+        assert_eq!(format_reply(text, TableFormat::Auto, false).text, text);
+    }
+
+    #[test]
+    fn test_wide_table_becomes_image_in_auto() {
+        let wide = format!(
+            "| Col | Description |\n|---|---|\n| x | {} |",
+            "long words ".repeat(10)
+        );
+        let out = format_reply(&wide, TableFormat::Auto, false);
+        assert_eq!(out.text, TABLE_IMAGE_NOTE);
+        assert_eq!(out.images.len(), 1);
+        assert_eq!(out.images[0].0, "table-1.png");
+        assert!(out.images[0].1.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_parse_row_handles_escaped_pipes_and_short_rows() {
+        assert_eq!(
+            parse_row("| a \\| b | `c` |"),
+            Some(vec!["a | b".to_string(), "c".to_string()])
+        );
+        let (table, consumed) = parse_table(&["| a | b |", "|---|:-:|", "| 1 |"]).unwrap();
+        assert_eq!(consumed, 3);
+        assert_eq!(table.aligns, vec![Align::Left, Align::Center]);
+        assert_eq!(table.rows, vec![vec!["1".to_string(), String::new()]]);
+    }
+}
@@ -0,0 +1,290 @@
+//! GitHub digest for `/gh` (`--github-token`).
+//!
+//! Unread notifications, open pull requests waiting on your review and open
+//! issues/PRs assigned to you are fetched from the REST API and condensed
+//! into a short text digest. A background loop refreshes it every
+//! `--github-poll-mins`, so `/gh what needs my attention?` usually answers
+//! from the cache; the digest is given to Claude as context for the question.
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+pub(crate) const API_URL: &str = "https://api.github.com";
+const API_VERSION: &str = "2022-11-28";
+const USER_AGENT: &str = concat!("ccchat/", env!("CARGO_PKG_VERSION"));
+/// Items fetched per list; the digest is context, not a full inbox.
+const PER_PAGE: u32 = 30;
+
+#[derive(Clone, Debug)]
+pub(crate) struct GitHubConfig {
+    /// None = `/gh` unavailable.
+    pub(crate) token: Option<String>,
+    pub(crate) api_url: String,
+    /// Minutes between background refreshes (0 = fetch on demand only).
+    pub(crate) poll_mins: u64,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            api_url: API_URL.to_string(),
+            poll_mins: 0,
+        }
+    }
+}
+
+/// A fetched digest and when it was taken.
+#[derive(Clone, Debug)]
+pub(crate) struct Digest {
+    pub(crate) fetched_at: DateTime<Utc>,
+    pub(crate) text: String,
+}
+
+impl Digest {
+    /// Young enough to answer `/gh` without refetching.
+    pub(crate) fn is_fresh(&self, poll_mins: u64) -> bool {
+        poll_mins > 0 && Utc::now() - self.fetched_at < chrono::Duration::minutes(poll_mins as i64)
+    }
+}
+
+#[derive(Deserialize)]
+struct Notification {
+    reason: String,
+    updated_at: String,
+    subject: Subject,
+    repository: Repository,
+}
+
+#[derive(Deserialize)]
+struct Subject {
+    title: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    full_name: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    total_count: u64,
+    items: Vec<Issue>,
+}
+
+#[derive(Deserialize)]
+struct Issue {
+    number: u64,
+    title: String,
+    html_url: String,
+    repository_url: String,
+    updated_at: String,
+}
+
+async fn get<T: serde::de::DeserializeOwned>(
+    http: &Client,
+    config: &GitHubConfig,
+    token: &str,
+    path: &str,
+) -> Result<T, AppError> {
+    let resp = http
+        .get(format!("{}{path}", config.api_url.trim_end_matches('/')))
+        .bearer_auth(token)
+        .header("Accept", "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", API_VERSION)
+        .header("User-Agent", USER_AGENT)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(AppError::Other(format!("GitHub returned {status}: {body}")));
+    }
+    Ok(resp.json().await?)
+}
+
+/// Fetch notifications, review requests and assignments.
+pub(crate) async fn fetch_digest(http: &Client, config: &GitHubConfig) -> Result<Digest, AppError> {
+    let token = config
+        .token
+        .as_deref()
+        .ok_or_else(|| AppError::Other("No GitHub token".to_string()))?;
+    let notifications: Vec<Notification> = get(
+        http,
+        config,
+        token,
+        &format!("/notifications?per_page={PER_PAGE}"),
+    )
+    .await?;
+    let reviews: SearchResult = get(
+        http,
+        config,
+        token,
+        &format!("/search/issues?q=is:open+is:pr+review-requested:@me&per_page={PER_PAGE}"),
+    )
+    .await?;
+    let assigned: SearchResult = get(
+        http,
+        config,
+        token,
+        &format!("/search/issues?q=is:open+assignee:@me&per_page={PER_PAGE}"),
+    )
+    .await?;
+    let fetched_at = Utc::now();
+    Ok(Digest {
+        fetched_at,
+        text: format_digest(fetched_at, &notifications, &reviews, &assigned),
+    })
+}
+
+fn format_digest(
+    fetched_at: DateTime<Utc>,
+    notifications: &[Notification],
+    reviews: &SearchResult,
+    assigned: &SearchResult,
+) -> String {
+    let mut lines = vec![format!(
+        "GitHub as of {}",
+        fetched_at.format("%Y-%m-%d %H:%M UTC")
+    )];
+    lines.push(format!("Unread notifications ({}):", notifications.len()));
+    for n in notifications {
+        lines.push(format!(
+            "- {}: {} [{}, {}] updated {}",
+            n.repository.full_name,
+            n.subject.title,
+            n.subject.kind,
+            n.reason,
+            day(&n.updated_at)
+        ));
+    }
+    for (heading, result) in [
+        ("Pull requests waiting on your review", reviews),
+        ("Open issues and pull requests assigned to you", assigned),
+    ] {
+        lines.push(format!("{heading} ({}):", result.total_count));
+        for issue in &result.items {
+            let repo = issue
+                .repository_url
+                .rsplit("/repos/")
+                .next()
+                .unwrap_or(&issue.repository_url);
+            lines.push(format!(
+                "- {repo}#{} {} — {} (updated {})",
+                issue.number,
+                issue.title,
+                issue.html_url,
+                day(&issue.updated_at)
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+/// The date part of an ISO 8601 timestamp.
+fn day(timestamp: &str) -> &str {
+    timestamp.split('T').next().unwrap_or(timestamp)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    pub(crate) async fn mock_github() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/notifications"))
+            .and(header("authorization", "Bearer ghp_test"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                    "reason": "review_requested",
+                    "updated_at": "2026-10-15T09:00:00Z",
+                    "subject": {"title": "Fix flaky test", "type": "PullRequest"},
+                    "repository": {"full_name": "acme/api"}
+                }])),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/search/issues"))
+            .and(query_param("q", "is:open is:pr review-requested:@me"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "total_count": 1,
+                "items": [{
+                    "number": 42,
+                    "title": "Fix flaky test",
+                    "html_url": "https://github.com/acme/api/pull/42",
+                    "repository_url": "https://api.github.com/repos/acme/api",
+                    "updated_at": "2026-10-15T09:00:00Z"
+                }]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/search/issues"))
+            .and(query_param("q", "is:open assignee:@me"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"total_count": 0, "items": []})),
+            )
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn config(api_url: String) -> GitHubConfig {
+        GitHubConfig {
+            token: Some("ghp_test".to_string()),
+            api_url,
+            poll_mins: 15,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_digest_lists_everything() {
+        let server = mock_github().await;
+        let digest = fetch_digest(&Client::new(), &config(server.uri()))
+            .await
+            .unwrap();
+        assert!(digest
+            .text
+            .contains("Unread notifications (1):\n- acme/api: Fix flaky test [PullRequest, review_requested] updated 2026-10-15"));
+        assert!(digest.text.contains(
+            "Pull requests waiting on your review (1):\n- acme/api#42 Fix flaky test — https://github.com/acme/api/pull/42 (updated 2026-10-15)"
+        ));
+        assert!(digest
+            .text
+            .ends_with("Open issues and pull requests assigned to you (0):"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_digest_reports_auth_failure() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401).set_body_string("Bad credentials"))
+            .mount(&server)
+            .await;
+        let err = fetch_digest(&Client::new(), &config(server.uri()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("401"));
+    }
+
+    #[test]
+    fn test_digest_freshness() {
+        let digest = Digest {
+            fetched_at: Utc::now() - chrono::Duration::minutes(10),
+            text: String::new(),
+        };
+        assert!(digest.is_fresh(15));
+        assert!(!digest.is_fresh(5));
+        assert!(!digest.is_fresh(0));
+    }
+}
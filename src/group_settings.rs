@@ -0,0 +1,193 @@
+//! Per-group settings (`/group set`), changed by the group's admins.
+//!
+//! A bound group can have its own trigger word (only messages containing
+//! it reach Claude), model, share of the per-message budget and reply
+//! length cap. The account owner is always a group admin and can name
+//! others with `/group set admins`. Settings are saved per group id in
+//! `allowed.json` and shown by `/group status`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::State;
+
+/// Settings `/group set` accepts, in the order `/group status` shows them.
+pub(crate) const KEYS: [&str; 5] = ["trigger", "model", "budget", "length", "admins"];
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct GroupSettings {
+    /// Word a message must contain to go to Claude (None: every message).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) trigger: Option<String>,
+    /// Model for the group's session, in place of the default and `/model`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) model: Option<String>,
+    /// Percent of `max_budget` each run in the group may spend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) budget_share: Option<u8>,
+    /// Longest reply sent, in characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_reply_chars: Option<usize>,
+    /// Members besides the account owner who may change these settings.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) admins: Vec<String>,
+}
+
+impl GroupSettings {
+    /// Change `key`. `model` is stored as given; resolve aliases first.
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("Usage: /group set {key} <value>"));
+        }
+        match key {
+            "trigger" => {
+                let word = normalize(value);
+                if word.is_empty() || value.contains(char::is_whitespace) {
+                    return Err("The trigger is a single word, like @claude.".to_string());
+                }
+                self.trigger = Some(value.to_string());
+            }
+            "model" => self.model = Some(value.to_string()),
+            "budget" => match value.trim_end_matches('%').parse::<u8>() {
+                Ok(share @ 1..=100) => self.budget_share = Some(share),
+                _ => return Err("The budget share is a percentage from 1 to 100.".to_string()),
+            },
+            "length" => match value.parse::<usize>() {
+                Ok(chars) if chars >= 50 => self.max_reply_chars = Some(chars),
+                _ => {
+                    return Err("The length cap is a number of characters, 50 or more.".to_string())
+                }
+            },
+            "admins" => {
+                self.admins = value
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            _ => return Err(format!("Group settings: {}.", KEYS.join(", "))),
+        }
+        Ok(())
+    }
+
+    /// Put `key` back to its default. False when there's no such setting.
+    pub(crate) fn reset(&mut self, key: &str) -> bool {
+        match key {
+            "trigger" => self.trigger = None,
+            "model" => self.model = None,
+            "budget" => self.budget_share = None,
+            "length" => self.max_reply_chars = None,
+            "admins" => self.admins.clear(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Text form of `key`, as `/group status` shows it.
+    pub(crate) fn get(&self, key: &str) -> String {
+        let or_default =
+            |value: Option<String>, default: &str| value.unwrap_or_else(|| default.to_string());
+        match key {
+            "trigger" => or_default(self.trigger.clone(), "none (every message)"),
+            "model" => or_default(self.model.clone(), "default"),
+            "budget" => or_default(self.budget_share.map(|share| format!("{share}%")), "100%"),
+            "length" => or_default(
+                self.max_reply_chars
+                    .map(|chars| format!("{chars} characters")),
+                "no limit",
+            ),
+            "admins" if self.admins.is_empty() => "account owner only".to_string(),
+            "admins" => format!("account owner, {}", self.admins.join(", ")),
+            _ => String::new(),
+        }
+    }
+
+    /// Whether `text` should go to Claude: it contains the trigger word, or
+    /// there isn't one.
+    pub(crate) fn triggered_by(&self, text: &str) -> bool {
+        let Some(trigger) = &self.trigger else {
+            return true;
+        };
+        let trigger = normalize(trigger);
+        text.split_whitespace()
+            .any(|word| normalize(word) == trigger)
+    }
+}
+
+/// A word lowercased, without the punctuation around it.
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| c.is_ascii_punctuation())
+        .to_lowercase()
+}
+
+/// Whether `member` may change `chat`'s settings.
+pub(crate) fn is_group_admin(state: &State, chat: &str, member: &str) -> bool {
+    state.is_admin(member)
+        || state
+            .group_settings
+            .get(chat)
+            .is_some_and(|settings| settings.admins.iter().any(|id| id == member))
+}
+
+/// `text` cut to `max` characters at a word break, saying so.
+pub(crate) fn cap_reply(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(i) if i > max / 2 => &cut[..i],
+        _ => cut.as_str(),
+    };
+    format!(
+        "{}…\n\n(Cut at this group's {max}-character limit.)",
+        cut.trim_end()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_reset() {
+        let mut settings = GroupSettings::default();
+        settings.set("trigger", "@Claude").unwrap();
+        settings.set("budget", "25%").unwrap();
+        settings.set("length", "500").unwrap();
+        settings.set("admins", "+1555, +1666").unwrap();
+        assert_eq!(settings.get("trigger"), "@Claude");
+        assert_eq!(settings.get("budget"), "25%");
+        assert_eq!(settings.get("length"), "500 characters");
+        assert_eq!(settings.admins, vec!["+1555", "+1666"]);
+
+        assert!(settings.set("budget", "150").is_err());
+        assert!(settings.set("length", "10").is_err());
+        assert!(settings.set("trigger", "hey you").is_err());
+        assert!(settings.set("colour", "red").is_err());
+
+        assert!(settings.reset("budget"));
+        assert_eq!(settings.get("budget"), "100%");
+        assert!(!settings.reset("colour"));
+    }
+
+    #[test]
+    fn test_triggered_by() {
+        let mut settings = GroupSettings::default();
+        assert!(settings.triggered_by("anything"));
+        settings.set("trigger", "@claude").unwrap();
+        assert!(settings.triggered_by("@Claude, what's the build status?"));
+        assert!(settings.triggered_by("what do you think claude?"));
+        assert!(!settings.triggered_by("claudette is here"));
+        assert!(!settings.triggered_by("lunch?"));
+    }
+
+    #[test]
+    fn test_cap_reply() {
+        assert_eq!(cap_reply("short", 50), "short");
+        let long = "word ".repeat(30);
+        let capped = cap_reply(&long, 52);
+        assert!(capped.starts_with("word word"));
+        assert!(capped.contains("word…\n\n(Cut at this group's 52-character limit.)"));
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::net::TcpListener;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 pub(crate) fn hash_message(text: &str) -> u64 {
@@ -24,11 +24,15 @@ pub(crate) fn split_message(text: &str, max_len: usize) -> Vec<String> {
             break;
         }
 
-        let chunk = &remaining[..max_len];
+        let mut end = max_len;
+        while !remaining.is_char_boundary(end) {
+            end -= 1;
+        }
+        let chunk = &remaining[..end];
         let split_at = chunk
             .rfind("\n\n")
             .or_else(|| chunk.rfind('\n'))
-            .unwrap_or(max_len);
+            .unwrap_or(end);
 
         let (part, rest) = remaining.split_at(split_at);
         parts.push(part.to_string());
@@ -38,6 +42,41 @@ pub(crate) fn split_message(text: &str, max_len: usize) -> Vec<String> {
     parts
 }
 
+/// Like [`split_message`], but a code block cut across parts is closed at the
+/// end of one part and reopened (with its language tag) at the start of the
+/// next, for clients that render Markdown.
+pub(crate) fn split_message_fenced(text: &str, max_len: usize) -> Vec<String> {
+    const FENCE_RESERVE: usize = 32;
+    if text.len() <= max_len {
+        return vec![text.to_string()];
+    }
+    let mut open: Option<String> = None;
+    split_message(text, max_len.saturating_sub(FENCE_RESERVE).max(1))
+        .into_iter()
+        .map(|part| {
+            let mut out = String::new();
+            if let Some(fence) = &open {
+                out.push_str(fence);
+                out.push('\n');
+            }
+            for line in part.lines() {
+                let line = line.trim_start();
+                if line.starts_with("```") {
+                    open = match open {
+                        Some(_) => None,
+                        None => Some(line.chars().take(FENCE_RESERVE - 8).collect()),
+                    };
+                }
+            }
+            out.push_str(&part);
+            if open.is_some() {
+                out.push_str("\n```");
+            }
+            out
+        })
+        .collect()
+}
+
 pub(crate) fn truncate(s: &str, max: usize) -> String {
     if s.len() <= max {
         s.to_string()
@@ -135,17 +174,18 @@ pub(crate) fn is_command(text: &str) -> bool {
     text.trim().starts_with('/')
 }
 
-pub(crate) fn find_free_port(preferred: u16) -> u16 {
+/// A port free on `ip`: `preferred` or one of the 100 after it, else any.
+pub(crate) fn find_free_port(ip: std::net::IpAddr, preferred: u16) -> u16 {
     if preferred == 0 {
-        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind ephemeral port");
+        let listener = TcpListener::bind((ip, 0)).expect("failed to bind ephemeral port");
         return listener.local_addr().unwrap().port();
     }
     for port in preferred..=preferred.saturating_add(100) {
-        if TcpListener::bind(("127.0.0.1", port)).is_ok() {
+        if TcpListener::bind((ip, port)).is_ok() {
             return port;
         }
     }
-    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind ephemeral port");
+    let listener = TcpListener::bind((ip, 0)).expect("failed to bind ephemeral port");
     listener.local_addr().unwrap().port()
 }
 
@@ -207,12 +247,140 @@ pub(crate) fn format_cron_human(pattern: &str) -> String {
     }
 }
 
+/// Recipient id used to address a Signal group: `group.<base64(group id)>`.
+pub(crate) fn group_chat_id(group_id: &str) -> String {
+    use base64::Engine;
+    format!(
+        "group.{}",
+        base64::engine::general_purpose::STANDARD.encode(group_id)
+    )
+}
+
+/// Reverse of [`group_chat_id`]: the transport's own group id, if `chat_id` is a group.
+pub(crate) fn group_id_from_chat(chat_id: &str) -> Option<String> {
+    use base64::Engine;
+    let encoded = chat_id.strip_prefix("group.")?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Email threads are addressed as `<address>#<thread>`; this is the address part
+/// (the id itself for every other kind of chat).
+pub(crate) fn thread_owner(chat_id: &str) -> &str {
+    chat_id.split_once('#').map_or(chat_id, |(owner, _)| owner)
+}
+
+pub(crate) fn is_group_chat(chat_id: &str) -> bool {
+    chat_id.starts_with("group.")
+}
+
 pub(crate) fn isolated_workdir(sender: &str) -> PathBuf {
     std::env::temp_dir()
         .join("ccchat")
         .join(crate::memory::hash_sender(sender))
 }
 
+/// Claude's working directory for one session: `<tmp>/ccchat/<sender-hash>/<session-id>/`.
+pub(crate) fn session_workdir(sender: &str, session_id: &str) -> PathBuf {
+    isolated_workdir(sender).join(session_id)
+}
+
+/// Total size in bytes of all files below `dir` (0 if it doesn't exist).
+pub(crate) fn dir_size(dir: &Path) -> u64 {
+    list_files(dir).iter().map(|(_, size)| size).sum()
+}
+
+/// Recursively list files below `dir` as (relative path, size), sorted by path.
+pub(crate) fn list_files(dir: &Path) -> Vec<(String, u64)> {
+    let mut files = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            let path = entry.path();
+            if meta.is_dir() {
+                stack.push(path);
+            } else if meta.is_file() {
+                if let Ok(rel) = path.strip_prefix(dir) {
+                    files.push((rel.to_string_lossy().to_string(), meta.len()));
+                }
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Resolve a user-supplied relative path inside `dir`, rejecting anything that escapes it.
+pub(crate) fn resolve_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    let root = dir.canonicalize().ok()?;
+    let path = root.join(name).canonicalize().ok()?;
+    (path.starts_with(&root) && path.is_file()).then_some(path)
+}
+
+/// Shell-style match of a relative path: `*` and `?` stay within one path
+/// segment, `**` spans any number of them. A pattern without `/` is matched
+/// against the file name alone, so `*.rs` finds Rust files at any depth.
+pub(crate) fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(p: &[u8], s: &[u8]) -> bool {
+        match p {
+            [] => s.is_empty(),
+            [b'*', b'*', rest @ ..] => match rest.strip_prefix(b"/") {
+                // "**/" is zero or more whole directories
+                Some(rest) => (0..=s.len())
+                    .filter(|&i| i == 0 || s[i - 1] == b'/')
+                    .any(|i| matches(rest, &s[i..])),
+                None => (0..=s.len()).any(|i| matches(rest, &s[i..])),
+            },
+            [b'*', rest @ ..] => (0..=s.len())
+                .take_while(|&i| i == 0 || s[i - 1] != b'/')
+                .any(|i| matches(rest, &s[i..])),
+            [b'?', rest @ ..] => matches!(s, [c, ..] if *c != b'/') && matches(rest, &s[1..]),
+            [c, rest @ ..] => s.first() == Some(c) && matches(rest, &s[1..]),
+        }
+    }
+    let target = if pattern.contains('/') {
+        path
+    } else {
+        path.rsplit('/').next().unwrap_or(path)
+    };
+    matches(pattern.as_bytes(), target.as_bytes())
+}
+
+/// Join a user-supplied relative path onto `dir` for a new file, rejecting
+/// absolute paths and `..` so it can't land outside.
+pub(crate) fn new_path_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    use std::path::Component;
+    let rel = Path::new(name.trim());
+    let safe = rel.file_name().is_some()
+        && rel
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    safe.then(|| dir.join(rel))
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+    if bytes >= GB {
+        format!("{:.1} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.1} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
+        format!("{:.1} KB", bytes as f64 / KB as f64)
+    } else {
+        format!("{bytes} B")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -276,6 +444,28 @@ mod tests {
         assert_eq!(parts[1].len(), 4000);
     }
 
+    #[test]
+    fn test_split_message_respects_char_boundaries() {
+        let msg = "é".repeat(3000);
+        let parts = split_message(&msg, 2001);
+        assert!(parts.iter().all(|p| p.len() <= 2001));
+        assert_eq!(parts.concat(), msg);
+    }
+
+    #[test]
+    fn test_split_message_fenced_reopens_code_block() {
+        let code: String = (0..200).map(|i| format!("let x{i} = {i};\n")).collect();
+        let msg = format!("Here you go:\n```rust\n{code}```\nDone.");
+        let parts = split_message_fenced(&msg, 2000);
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|p| p.len() <= 2000));
+        assert!(parts[0].ends_with("\n```"));
+        assert!(parts[1].starts_with("```rust\n"));
+        for part in &parts {
+            assert_eq!(part.matches("```").count() % 2, 0, "unbalanced: {part}");
+        }
+    }
+
     #[test]
     fn test_truncate_short() {
         assert_eq!(truncate("hello", 10), "hello");
@@ -401,17 +591,19 @@ mod tests {
         assert!(!prompt.contains("along with"));
     }
 
+    const LOCALHOST: std::net::IpAddr = std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
     #[test]
     fn test_find_free_port_returns_valid() {
-        let port = find_free_port(0);
+        let port = find_free_port(LOCALHOST, 0);
         assert!(port > 0);
         assert!(TcpListener::bind(("127.0.0.1", port)).is_ok());
     }
 
     #[test]
     fn test_find_free_port_preferred() {
-        let free = find_free_port(0);
-        let result = find_free_port(free);
+        let free = find_free_port(LOCALHOST, 0);
+        let result = find_free_port(LOCALHOST, free);
         assert_eq!(result, free);
     }
 
@@ -424,6 +616,98 @@ mod tests {
         assert!(dir_a.starts_with(std::env::temp_dir().join("ccchat")));
     }
 
+    #[test]
+    fn test_group_chat_id() {
+        let id = group_chat_id("abc");
+        assert_eq!(id, "group.YWJj");
+        assert!(is_group_chat(&id));
+        assert!(!is_group_chat("+1234567890"));
+    }
+
+    #[test]
+    fn test_group_id_from_chat_round_trip() {
+        assert_eq!(
+            group_id_from_chat(&group_chat_id("C0123ABC")).as_deref(),
+            Some("C0123ABC")
+        );
+        assert_eq!(group_id_from_chat("+1234567890"), None);
+        assert_eq!(group_id_from_chat("group.!!!"), None);
+    }
+
+    #[test]
+    fn test_thread_owner() {
+        assert_eq!(thread_owner("ann@example.com#1a2b"), "ann@example.com");
+        assert_eq!(thread_owner("+1234567890"), "+1234567890");
+    }
+
+    #[test]
+    fn test_session_workdir_nested_under_sender() {
+        let dir = session_workdir("+1234567890", "sess-1");
+        assert_eq!(dir, isolated_workdir("+1234567890").join("sess-1"));
+        assert_ne!(dir, session_workdir("+1234567890", "sess-2"));
+    }
+
+    #[test]
+    fn test_list_files_and_dir_size() {
+        let dir = std::env::temp_dir().join(format!("ccchat_list_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("b.txt"), "hello").unwrap();
+        std::fs::write(dir.join("sub").join("a.txt"), "abc").unwrap();
+        let files = list_files(&dir);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0], ("b.txt".to_string(), 5));
+        assert_eq!(files[1].1, 3);
+        assert!(files[1].0.ends_with("a.txt"));
+        assert_eq!(dir_size(&dir), 8);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dir_size(&dir), 0);
+    }
+
+    #[test]
+    fn test_resolve_in_dir_rejects_escape() {
+        let dir = std::env::temp_dir().join(format!("ccchat_resolve_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("out.txt"), "x").unwrap();
+        assert!(resolve_in_dir(&dir, "out.txt").is_some());
+        assert!(resolve_in_dir(&dir, "missing.txt").is_none());
+        assert!(resolve_in_dir(&dir, "../../etc/passwd").is_none());
+        assert!(resolve_in_dir(&dir, "/etc/passwd").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.rs", "src/commands/mod.rs"));
+        assert!(!glob_match("*.rs", "notes.md"));
+        assert!(glob_match("src/*.rs", "src/main.rs"));
+        assert!(!glob_match("src/*.rs", "src/commands/mod.rs"));
+        assert!(glob_match("src/**/*.rs", "src/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/commands/mod.rs"));
+        assert!(!glob_match("src/**/x.rs", "src/abx.rs"));
+        assert!(glob_match("report-?.pdf", "out/report-3.pdf"));
+        assert!(glob_match("**", "a/b/c"));
+    }
+
+    #[test]
+    fn test_new_path_in_dir() {
+        let dir = Path::new("/work");
+        assert_eq!(
+            new_path_in_dir(dir, "data/in.csv"),
+            Some(PathBuf::from("/work/data/in.csv"))
+        );
+        assert!(new_path_in_dir(dir, "../in.csv").is_none());
+        assert!(new_path_in_dir(dir, "/etc/passwd").is_none());
+        assert!(new_path_in_dir(dir, "").is_none());
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.0 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.0 MB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
     #[test]
     fn test_is_command() {
         assert!(is_command("/status"));
@@ -0,0 +1,308 @@
+//! Home Assistant control (`--ha-url`, `--ha-token`, `--ha-entity`).
+//!
+//! The account owner can run `/ha light.office off` directly, and Claude is
+//! told it may ask for the same actions by writing `[[ha <entity> <action>]]`
+//! lines in its reply; ccchat runs them through the REST API and replaces the
+//! lines with the outcome. Only allowlisted entities and a fixed set of
+//! services (on/off/toggle, locks, covers, buttons, scripts) are reachable,
+//! so Claude can't do anything `/ha` couldn't. Lock and cover actions from
+//! Claude only run once the owner replies yes.
+
+use regex::Regex;
+use reqwest::Client;
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+use crate::error::AppError;
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HaConfig {
+    /// Base URL, e.g. `http://homeassistant.local:8123` (None = disabled).
+    pub(crate) url: Option<String>,
+    /// Long-lived access token.
+    pub(crate) token: Option<String>,
+    /// Entity IDs that may be read or controlled; `light.*` allows a domain.
+    pub(crate) entities: Vec<String>,
+}
+
+impl HaConfig {
+    pub(crate) fn enabled(&self) -> bool {
+        self.url.is_some() && self.token.is_some()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match (&self.url, &self.token) {
+            (None, None) => Ok(()),
+            (Some(_), Some(_)) if self.entities.is_empty() => {
+                Err("--ha-url needs at least one --ha-entity to allow".to_string())
+            }
+            (Some(_), Some(_)) => Ok(()),
+            _ => Err("--ha-url and --ha-token must be given together".to_string()),
+        }
+    }
+
+    pub(crate) fn allows(&self, entity: &str) -> bool {
+        self.entities
+            .iter()
+            .any(|allowed| match allowed.strip_suffix(".*") {
+                Some(domain) => entity
+                    .split_once('.')
+                    .is_some_and(|(d, id)| d == domain && !id.is_empty()),
+                None => allowed == entity,
+            })
+    }
+
+    fn api(&self, path: &str) -> Result<(String, &str), AppError> {
+        match (&self.url, &self.token) {
+            (Some(url), Some(token)) => {
+                Ok((format!("{}/api/{path}", url.trim_end_matches('/')), token))
+            }
+            _ => Err(AppError::Other(
+                "Home Assistant is not configured".to_string(),
+            )),
+        }
+    }
+}
+
+/// The Home Assistant service for an action word, e.g. ("light.office", "off")
+/// -> ("light", "turn_off"). None for actions outside the allowed set.
+pub(crate) fn service_for(entity: &str, action: &str) -> Option<(String, &'static str)> {
+    let (domain, _) = entity.split_once('.')?;
+    let service = match (domain, action) {
+        (_, "on" | "turn_on") => "turn_on",
+        (_, "off" | "turn_off") => "turn_off",
+        (_, "toggle") => "toggle",
+        ("lock", "lock") => "lock",
+        ("lock", "unlock") => "unlock",
+        ("cover", "open" | "open_cover") => "open_cover",
+        ("cover", "close" | "close_cover") => "close_cover",
+        ("cover", "stop" | "stop_cover") => "stop_cover",
+        ("button" | "input_button", "press") => "press",
+        ("script", "run") => "turn_on",
+        ("automation", "trigger") => "trigger",
+        _ => return None,
+    };
+    Some((domain.to_string(), service))
+}
+
+#[derive(Deserialize)]
+struct EntityState {
+    state: String,
+    #[serde(default)]
+    attributes: serde_json::Value,
+}
+
+/// "Office lights (light.office): on".
+pub(crate) async fn get_state(
+    http: &Client,
+    config: &HaConfig,
+    entity: &str,
+) -> Result<String, AppError> {
+    if !config.allows(entity) {
+        return Err(AppError::Other(format!(
+            "{entity} is not on the Home Assistant allowlist"
+        )));
+    }
+    let (url, token) = config.api(&format!("states/{entity}"))?;
+    let resp = http.get(url).bearer_auth(token).send().await?;
+    if !resp.status().is_success() {
+        return Err(AppError::Other(format!(
+            "Home Assistant returned {} for {entity}",
+            resp.status()
+        )));
+    }
+    let entity_state: EntityState = resp.json().await?;
+    let name = entity_state.attributes["friendly_name"]
+        .as_str()
+        .map(|n| format!("{n} ({entity})"))
+        .unwrap_or_else(|| entity.to_string());
+    let unit = entity_state.attributes["unit_of_measurement"]
+        .as_str()
+        .map(|u| format!(" {u}"))
+        .unwrap_or_default();
+    Ok(format!("{name}: {}{unit}", entity_state.state))
+}
+
+/// Run an allowed action and report the entity's new state. `state` only
+/// reads it.
+pub(crate) async fn run_action(
+    http: &Client,
+    config: &HaConfig,
+    entity: &str,
+    action: &str,
+) -> Result<String, AppError> {
+    if action == "state" {
+        return get_state(http, config, entity).await;
+    }
+    if !config.allows(entity) {
+        return Err(AppError::Other(format!(
+            "{entity} is not on the Home Assistant allowlist"
+        )));
+    }
+    let (domain, service) = service_for(entity, action).ok_or_else(|| {
+        AppError::Other(format!("'{action}' isn't an allowed action for {entity}"))
+    })?;
+    let (url, token) = config.api(&format!("services/{domain}/{service}"))?;
+    let resp = http
+        .post(url)
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "entity_id": entity }))
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(AppError::Other(format!(
+            "Home Assistant returned {} for {domain}.{service}",
+            resp.status()
+        )));
+    }
+    // Report the new state; fall back to confirming the call.
+    Ok(get_state(http, config, entity)
+        .await
+        .unwrap_or_else(|_| format!("{domain}.{service} sent to {entity}")))
+}
+
+/// System prompt text describing the Home Assistant tool.
+pub(crate) fn tool_prompt(config: &HaConfig) -> Option<String> {
+    if !config.enabled() {
+        return None;
+    }
+    Some(format!(
+        "You can control the user's Home Assistant. To act, put a line of the form \
+         [[ha <entity_id> <action>]] in your reply, one per action; ccchat runs it and \
+         replaces the line with the result. Actions: on, off, toggle, lock/unlock (locks), \
+         open/close/stop (covers), press (buttons), run (scripts), trigger (automations), \
+         state (read the current state). Lock and cover actions wait for the user to reply \
+         yes. Allowed entities: {}. Only act when the user asks.",
+        config.entities.join(", ")
+    ))
+}
+
+/// `[[ha entity action]]` lines in a reply, with the reply minus those lines.
+pub(crate) fn extract_actions(reply: &str) -> (Vec<(String, String)>, String) {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"^\s*\[\[ha\s+([\w.*]+)\s+(\w+)\s*\]\]\s*$").unwrap());
+    let mut actions = Vec::new();
+    let mut kept = Vec::new();
+    for line in reply.lines() {
+        match re.captures(line) {
+            Some(c) => actions.push((c[1].to_string(), c[2].to_string())),
+            None => kept.push(line),
+        }
+    }
+    (actions, kept.join("\n").trim().to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    pub(crate) fn config(url: String) -> HaConfig {
+        HaConfig {
+            url: Some(url),
+            token: Some("ha_token".to_string()),
+            entities: vec!["light.*".to_string(), "lock.front_door".to_string()],
+        }
+    }
+
+    /// Home Assistant where `light.office` can be turned off.
+    pub(crate) async fn mock_ha() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/services/light/turn_off"))
+            .and(header("authorization", "Bearer ha_token"))
+            .and(body_json(serde_json::json!({"entity_id": "light.office"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/states/light.office"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "state": "off",
+                "attributes": {"friendly_name": "Office lights"}
+            })))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[test]
+    fn test_allowlist_and_validation() {
+        let config = config("http://ha.local:8123".to_string());
+        assert!(config.allows("light.office"));
+        assert!(config.allows("lock.front_door"));
+        assert!(!config.allows("lock.garage"));
+        assert!(!config.allows("light."));
+        assert!(!config.allows("switch.heater"));
+        assert!(config.validate().is_ok());
+        assert!(HaConfig::default().validate().is_ok());
+        let no_entities = HaConfig {
+            entities: Vec::new(),
+            ..config.clone()
+        };
+        assert!(no_entities.validate().is_err());
+        let no_token = HaConfig {
+            token: None,
+            ..config
+        };
+        assert!(no_token.validate().is_err());
+    }
+
+    #[test]
+    fn test_service_for_actions() {
+        assert_eq!(
+            service_for("light.office", "off"),
+            Some(("light".to_string(), "turn_off"))
+        );
+        assert_eq!(
+            service_for("cover.blinds", "open"),
+            Some(("cover".to_string(), "open_cover"))
+        );
+        assert_eq!(
+            service_for("lock.front_door", "unlock"),
+            Some(("lock".to_string(), "unlock"))
+        );
+        assert_eq!(service_for("light.office", "unlock"), None);
+        assert_eq!(service_for("light.office", "reload"), None);
+        assert_eq!(service_for("nodomain", "on"), None);
+    }
+
+    #[test]
+    fn test_extract_actions() {
+        let (actions, rest) = extract_actions(
+            "Turning off the lights.\n[[ha light.office off]]\n\nYour todo: ship it.",
+        );
+        assert_eq!(
+            actions,
+            vec![("light.office".to_string(), "off".to_string())]
+        );
+        assert_eq!(rest, "Turning off the lights.\n\nYour todo: ship it.");
+        let (none, same) = extract_actions("Mentioning [[ha light.x on]] inline isn't an action.");
+        assert!(none.is_empty());
+        assert_eq!(same, "Mentioning [[ha light.x on]] inline isn't an action.");
+    }
+
+    #[tokio::test]
+    async fn test_run_action_reports_new_state() {
+        let server = mock_ha().await;
+        let result = run_action(&Client::new(), &config(server.uri()), "light.office", "off")
+            .await
+            .unwrap();
+        assert_eq!(result, "Office lights (light.office): off");
+    }
+
+    #[tokio::test]
+    async fn test_run_action_refuses_unlisted_entities() {
+        let config = config("http://127.0.0.1:9".to_string());
+        let err = run_action(&Client::new(), &config, "switch.heater", "on")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("allowlist"));
+        let err = run_action(&Client::new(), &config, "light.office", "explode")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("isn't an allowed action"));
+    }
+}
@@ -0,0 +1,300 @@
+//! `ccchat import-claude <project>`: bring conversations from Claude Code's
+//! local history into a chat's memory.
+//!
+//! Claude Code keeps one JSONL transcript per session under
+//! `~/.claude/projects/<project path with non-alphanumerics as '-'>/`. Each
+//! session's text turns are stored as messages (so `/search` finds them) with
+//! their original times, and each session becomes a pin named
+//! `cc-<date>-<id>` holding a one-line summary and the end of the
+//! conversation, ready for `/recall`. Sessions already imported are skipped,
+//! so the command can be rerun as history grows.
+
+use rusqlite::Connection;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::error::AppError;
+
+/// Characters of conversation kept in a session's pin.
+const PIN_MAX_CHARS: usize = 8_000;
+/// Length of the summary line when it comes from the first prompt.
+const TITLE_MAX_CHARS: usize = 80;
+
+/// One Claude Code session, reduced to its text turns.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ClaudeSession {
+    pub(crate) id: String,
+    /// Claude Code's own title for the session, if it wrote one.
+    pub(crate) title: Option<String>,
+    /// (role, text, epoch seconds), consecutive turns by the same role merged.
+    pub(crate) turns: Vec<(String, String, i64)>,
+}
+
+impl ClaudeSession {
+    /// Claude Code's title, or the start of the first prompt.
+    pub(crate) fn summary(&self) -> String {
+        if let Some(title) = &self.title {
+            return title.clone();
+        }
+        let first = self
+            .turns
+            .iter()
+            .find(|(role, _, _)| role == "user")
+            .map(|(_, text, _)| text.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        if first.chars().count() > TITLE_MAX_CHARS {
+            let cut: String = first.chars().take(TITLE_MAX_CHARS).collect();
+            format!("{cut}...")
+        } else {
+            first
+        }
+    }
+}
+
+/// `~/.claude/projects/<encoded>` for `project`, which may be the project
+/// directory or the encoded name itself.
+pub(crate) fn history_dir(claude_home: &Path, project: &str) -> Result<PathBuf, AppError> {
+    let projects = claude_home.join("projects");
+    let as_named = projects.join(project);
+    if !project.contains('/') && as_named.is_dir() {
+        return Ok(as_named);
+    }
+    let path = std::fs::canonicalize(project).unwrap_or_else(|_| PathBuf::from(project));
+    let encoded: String = path
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let dir = projects.join(&encoded);
+    if dir.is_dir() {
+        Ok(dir)
+    } else {
+        Err(AppError::Other(format!(
+            "No Claude Code history for {project} (looked in {})",
+            dir.display()
+        )))
+    }
+}
+
+/// Text of a message's content: a plain string or the `text` blocks of a
+/// block list (tool calls and results are left out).
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.trim().to_string(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter(|b| b["type"] == "text")
+            .filter_map(|b| b["text"].as_str())
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        _ => String::new(),
+    }
+}
+
+/// Parse one session transcript. Meta entries, sub-agent side chains and
+/// slash-command bookkeeping are skipped.
+pub(crate) fn parse_session(id: &str, jsonl: &str) -> ClaudeSession {
+    let mut session = ClaudeSession {
+        id: id.to_string(),
+        title: None,
+        turns: Vec::new(),
+    };
+    for entry in jsonl
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+    {
+        let kind = entry["type"].as_str().unwrap_or_default();
+        if kind == "summary" {
+            session.title = entry["summary"].as_str().map(str::to_string);
+            continue;
+        }
+        if !matches!(kind, "user" | "assistant")
+            || entry["isMeta"] == true
+            || entry["isSidechain"] == true
+        {
+            continue;
+        }
+        let text = content_text(&entry["message"]["content"]);
+        if text.is_empty() || text.starts_with("<command-") || text.starts_with("<local-command-") {
+            continue;
+        }
+        let timestamp = entry["timestamp"]
+            .as_str()
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.timestamp())
+            .unwrap_or(0);
+        match session.turns.last_mut() {
+            Some((role, last, _)) if role == kind => {
+                last.push_str("\n\n");
+                last.push_str(&text);
+            }
+            _ => session.turns.push((kind.to_string(), text, timestamp)),
+        }
+    }
+    session
+}
+
+/// Pin text: the summary, then as much of the end of the conversation as
+/// fits in [`PIN_MAX_CHARS`].
+fn pin_content(project: &str, session: &ClaudeSession) -> String {
+    let started = session.turns.first().map(|t| t.2).unwrap_or(0);
+    let header = format!(
+        "Claude Code session in {project} ({}): {}",
+        crate::memory::format_epoch(started as u64),
+        session.summary()
+    );
+    let mut kept = Vec::new();
+    let mut used = 0;
+    for (role, text, _) in session.turns.iter().rev() {
+        let line = format!("{role}: {text}");
+        used += line.chars().count();
+        if used > PIN_MAX_CHARS && !kept.is_empty() {
+            break;
+        }
+        kept.push(line);
+    }
+    kept.reverse();
+    format!("{header}\n\n{}", kept.join("\n"))
+}
+
+fn already_imported(conn: &Connection, session_id: &str) -> bool {
+    conn.query_row(
+        "SELECT 1 FROM messages WHERE session_id = ?1 LIMIT 1",
+        rusqlite::params![session_id],
+        |_| Ok(()),
+    )
+    .is_ok()
+}
+
+/// What one import did.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ImportReport {
+    pub(crate) sessions: usize,
+    pub(crate) messages: usize,
+    pub(crate) skipped: usize,
+}
+
+/// Import every session transcript in `dir` into `chat`'s memory.
+pub(crate) fn import_history(dir: &Path, chat: &str) -> Result<ImportReport, AppError> {
+    let project = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().trim_start_matches('-').to_string())
+        .unwrap_or_default();
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    files.sort();
+
+    let conn = crate::memory::open_memory_db(chat)?;
+    let mut report = ImportReport::default();
+    for file in files {
+        let id = file
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let session_id = format!("claude-code:{id}");
+        let session = parse_session(&id, &std::fs::read_to_string(&file)?);
+        if session.turns.is_empty() {
+            continue;
+        }
+        if already_imported(&conn, &session_id) {
+            report.skipped += 1;
+            continue;
+        }
+        for (role, text, timestamp) in &session.turns {
+            crate::memory::messages::store_message_at(&conn, role, text, &session_id, *timestamp);
+        }
+        let date = crate::memory::format_epoch(session.turns[0].2 as u64);
+        let short_id: String = id.chars().take(8).collect();
+        crate::memory::messages::save_pin(
+            &conn,
+            &format!("cc-{date}-{short_id}"),
+            &pin_content(&project, &session),
+        );
+        report.sessions += 1;
+        report.messages += session.turns.len();
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = r#"{"type":"summary","summary":"Fix login redirect loop","leafUuid":"x"}
+{"type":"user","isMeta":true,"message":{"role":"user","content":"Caveat: local commands below"},"timestamp":"2025-06-01T09:00:00Z"}
+{"type":"user","message":{"role":"user","content":"<command-name>/clear</command-name>"},"timestamp":"2025-06-01T09:00:01Z"}
+{"type":"user","message":{"role":"user","content":"Why does login loop after the OAuth callback?"},"timestamp":"2025-06-01T09:00:05Z"}
+{"type":"assistant","message":{"id":"m1","content":[{"type":"text","text":"Let me look at the callback handler."},{"type":"tool_use","name":"Read","input":{}}]},"timestamp":"2025-06-01T09:00:09Z"}
+{"type":"user","message":{"role":"user","content":[{"type":"tool_result","content":"fn callback() {}"}]},"timestamp":"2025-06-01T09:00:10Z"}
+{"type":"assistant","message":{"id":"m2","content":[{"type":"text","text":"The session cookie is set after the redirect."}]},"timestamp":"2025-06-01T09:00:20Z"}
+{"type":"assistant","isSidechain":true,"message":{"content":[{"type":"text","text":"sub-agent chatter"}]},"timestamp":"2025-06-01T09:00:21Z"}
+not json
+"#;
+
+    #[test]
+    fn test_parse_session_keeps_text_turns() {
+        let session = parse_session("abc", TRANSCRIPT);
+        assert_eq!(session.summary(), "Fix login redirect loop");
+        assert_eq!(session.turns.len(), 2);
+        assert_eq!(
+            session.turns[0],
+            (
+                "user".to_string(),
+                "Why does login loop after the OAuth callback?".to_string(),
+                1748768405
+            )
+        );
+        assert_eq!(
+            session.turns[1].1,
+            "Let me look at the callback handler.\n\nThe session cookie is set after the redirect."
+        );
+
+        let untitled = parse_session(
+            "def",
+            &TRANSCRIPT.replacen("\"type\":\"summary\"", "\"type\":\"x\"", 1),
+        );
+        assert_eq!(
+            untitled.summary(),
+            "Why does login loop after the OAuth callback?"
+        );
+    }
+
+    #[test]
+    fn test_import_history_is_idempotent() {
+        let home = std::env::temp_dir().join(format!("ccchat_import_{}", std::process::id()));
+        let dir = home.join("projects").join("-work-shop");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("0a1b2c3d-4e5f.jsonl"), TRANSCRIPT).unwrap();
+        std::fs::write(dir.join("empty.jsonl"), "").unwrap();
+        assert_eq!(history_dir(&home, "-work-shop").unwrap(), dir);
+        assert_eq!(history_dir(&home, "/work/shop").unwrap(), dir);
+        assert!(history_dir(&home, "/work/other").is_err());
+
+        let chat = "+import_history";
+        crate::memory::delete_memory(chat);
+        let report = import_history(&dir, chat).unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                sessions: 1,
+                messages: 2,
+                skipped: 0,
+            }
+        );
+        assert_eq!(import_history(&dir, chat).unwrap().skipped, 1);
+
+        let conn = crate::memory::open_memory_db(chat).unwrap();
+        let pin = crate::memory::messages::get_pin(&conn, "cc-2025-06-01-0a1b2c3d").unwrap();
+        assert!(pin.starts_with(
+            "Claude Code session in work-shop (2025-06-01): Fix login redirect loop\n\nuser: Why"
+        ));
+        assert!(!crate::memory::search_memory_formatted(chat, "cookie", 5).is_empty());
+        crate::memory::delete_memory(chat);
+        let _ = std::fs::remove_dir_all(&home);
+    }
+}
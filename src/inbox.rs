@@ -0,0 +1,197 @@
+//! Bounded inbound pipeline (`--inbound-queue`, `--inbound-workers`).
+//!
+//! The receive loop only parses and routes; messages for Claude go into a
+//! bounded queue that a fixed pool of workers drains. A flood (a group's
+//! history syncing, signal-cli redelivering a backlog) then waits in the
+//! queue instead of starting a task per message, and once the queue is full
+//! messages are shed: the newest by default, or the oldest with
+//! `--inbound-overflow drop-oldest`. A sender whose message was shed is told
+//! so, at most once a minute.
+//!
+//! Debounced bursts join the queue as one message when their window ends.
+//! Commands skip the queue so `/stop` and `/status` still work while it is
+//! backed up.
+
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use crate::constants::SHED_NOTICE_COOLDOWN_SECS;
+
+/// What goes when the queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub(crate) enum Overflow {
+    /// Turn the new message away.
+    #[default]
+    DropNewest,
+    /// Make room by dropping the message that has waited longest.
+    DropOldest,
+}
+
+/// A routed message waiting for a worker.
+pub(crate) struct Inbound {
+    pub(crate) reply_to: String,
+    pub(crate) text: String,
+    pub(crate) attachments: Vec<crate::signal::AttachmentInfo>,
+    pub(crate) received: Instant,
+    /// Who sent it, when it's a turn in a group's raise-hand queue.
+    pub(crate) speaker: Option<String>,
+    /// Signal timestamp (ms), to match a later "delete for everyone".
+    pub(crate) sent_at: Option<i64>,
+}
+
+pub(crate) struct Inbox<T> {
+    queue: Mutex<VecDeque<T>>,
+    ready: Notify,
+    capacity: usize,
+    overflow: Overflow,
+    /// Workers currently handling a message.
+    pub(crate) busy: AtomicUsize,
+    shed: AtomicU64,
+    high_water: AtomicUsize,
+    /// Last time each sender was told a message of theirs was shed.
+    notified: DashMap<String, Instant>,
+}
+
+impl<T> Inbox<T> {
+    pub(crate) fn new(capacity: usize, overflow: Overflow) -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            ready: Notify::new(),
+            capacity: capacity.max(1),
+            overflow,
+            busy: AtomicUsize::new(0),
+            shed: AtomicU64::new(0),
+            high_water: AtomicUsize::new(0),
+            notified: DashMap::new(),
+        }
+    }
+
+    /// Queue `item`. Returns the item shed to stay within capacity, if any
+    /// (`item` itself under [`Overflow::DropNewest`]).
+    pub(crate) fn push(&self, item: T) -> Option<T> {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        let shed = if queue.len() < self.capacity {
+            queue.push_back(item);
+            None
+        } else {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            match self.overflow {
+                Overflow::DropNewest => Some(item),
+                Overflow::DropOldest => {
+                    let oldest = queue.pop_front();
+                    queue.push_back(item);
+                    oldest
+                }
+            }
+        };
+        self.high_water.fetch_max(queue.len(), Ordering::Relaxed);
+        drop(queue);
+        self.ready.notify_one();
+        shed
+    }
+
+    /// Wait for the next queued item.
+    pub(crate) async fn pop(&self) -> T {
+        loop {
+            {
+                let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(item) = queue.pop_front() {
+                    // One wakeup can stand for several pushes; pass it on.
+                    if !queue.is_empty() {
+                        self.ready.notify_one();
+                    }
+                    return item;
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    /// Take the queued items `matches` picks out of the queue.
+    pub(crate) fn remove(&self, matches: impl Fn(&T) -> bool) -> Vec<T> {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        let (removed, kept): (VecDeque<T>, VecDeque<T>) = queue.drain(..).partition(matches);
+        *queue = kept;
+        removed.into()
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.queue.lock().map(|q| q.len()).unwrap_or(0)
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn shed_count(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn high_water(&self) -> usize {
+        self.high_water.load(Ordering::Relaxed)
+    }
+
+    /// Whether `sender` should hear about a shed message now.
+    pub(crate) fn should_notify(&self, sender: &str) -> bool {
+        let cooldown = Duration::from_secs(SHED_NOTICE_COOLDOWN_SECS);
+        if self
+            .notified
+            .get(sender)
+            .is_some_and(|last| last.elapsed() < cooldown)
+        {
+            return false;
+        }
+        self.notified.insert(sender.to_string(), Instant::now());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overflow_policies() {
+        let newest = Inbox::new(2, Overflow::DropNewest);
+        assert_eq!(newest.push(1), None);
+        assert_eq!(newest.push(2), None);
+        assert_eq!(newest.push(3), Some(3));
+        assert_eq!((newest.depth(), newest.shed_count()), (2, 1));
+
+        let oldest = Inbox::new(2, Overflow::DropOldest);
+        oldest.push(1);
+        oldest.push(2);
+        assert_eq!(oldest.push(3), Some(1));
+        assert_eq!(oldest.high_water(), 2);
+
+        assert!(oldest.should_notify("+a"));
+        assert!(!oldest.should_notify("+a"));
+        assert!(oldest.should_notify("+b"));
+
+        assert_eq!(oldest.remove(|n| n % 2 == 0), vec![2]);
+        assert_eq!(oldest.depth(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_workers_drain_in_order() {
+        let inbox = std::sync::Arc::new(Inbox::new(8, Overflow::DropNewest));
+        let waiting = {
+            let inbox = std::sync::Arc::clone(&inbox);
+            tokio::spawn(async move { inbox.pop().await })
+        };
+        tokio::task::yield_now().await;
+        inbox.push("first");
+        inbox.push("second");
+        let got = tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(got, "first");
+        assert_eq!(inbox.pop().await, "second");
+        assert_eq!(inbox.depth(), 0);
+    }
+}
@@ -0,0 +1,419 @@
+//! `ccchat init`: first-run setup.
+//!
+//! Asks for the Signal account (offering to link signal-cli to the Signal
+//! app on a phone), who else may chat, the default model and budget, and
+//! where state is kept, then writes `~/.config/ccchat/ccchat.env` and the
+//! allowed list. ccchat reads `ccchat.env` at startup, so a plain `ccchat`
+//! picks the answers up; flags and variables already in the environment
+//! still win. Last, it offers to install a systemd user unit.
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::constants::{DEFAULT_MAX_BUDGET, DEFAULT_MODEL};
+use crate::error::AppError;
+use crate::memory::{default_config_dir, AllowedEntry, PersistedAllowed};
+
+pub(crate) const ENV_FILE: &str = "ccchat.env";
+const UNIT_NAME: &str = "ccchat.service";
+
+/// Export `ccchat.env`'s settings, leaving variables that are already set.
+pub(crate) fn load_env_file() {
+    let Ok(text) = std::fs::read_to_string(default_config_dir().join(ENV_FILE)) else {
+        return;
+    };
+    for (key, value) in parse_env(&text) {
+        if std::env::var_os(&key).is_none() {
+            std::env::set_var(key, value);
+        }
+    }
+}
+
+/// `KEY=value` lines; blank lines and `#` comments are skipped and values
+/// may be quoted.
+fn parse_env(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let unquoted = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            (key.trim().to_string(), unquoted.to_string())
+        })
+        .collect()
+}
+
+fn is_phone_number(s: &str) -> bool {
+    s.strip_prefix('+').is_some_and(|digits| {
+        (7..=15).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit())
+    })
+}
+
+/// Questions on `out`, answers from `input`.
+struct Prompter<R, W> {
+    input: R,
+    out: W,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    /// One answer, or `default` for an empty line.
+    fn ask(&mut self, question: &str, default: &str) -> Result<String, AppError> {
+        if default.is_empty() {
+            write!(self.out, "{question}: ")?;
+        } else {
+            write!(self.out, "{question} [{default}]: ")?;
+        }
+        self.out.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err(AppError::Other("Setup cancelled".to_string()));
+        }
+        let answer = line.trim();
+        Ok(if answer.is_empty() { default } else { answer }.to_string())
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool, AppError> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            match self
+                .ask(&format!("{question} ({hint})"), "")?
+                .to_lowercase()
+                .as_str()
+            {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => writeln!(self.out, "Please answer y or n.")?,
+            }
+        }
+    }
+
+    fn say(&mut self, text: &str) -> Result<(), AppError> {
+        writeln!(self.out, "{text}")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Answers {
+    account: String,
+    /// Link signal-cli to the account before finishing.
+    link: bool,
+    allowed: Vec<String>,
+    model: String,
+    max_budget: f64,
+    data_dir: PathBuf,
+    install_unit: bool,
+}
+
+fn ask_answers<R: BufRead, W: Write>(
+    p: &mut Prompter<R, W>,
+    config_dir: &Path,
+) -> Result<Answers, AppError> {
+    p.say("Your Signal number is the one ccchat answers on; messages from it have admin rights.")?;
+    let account = loop {
+        let answer = p.ask("Signal number (e.g. +447700900123)", "")?;
+        if is_phone_number(&answer) {
+            break answer;
+        }
+        p.say("Enter the number with + and country code.")?;
+    };
+    let link = !p.confirm(
+        &format!("Is {account} already registered or linked with signal-cli?"),
+        true,
+    )? && p.confirm("Link signal-cli to the Signal app on that phone now?", true)?;
+
+    let allowed = loop {
+        let answer = p.ask(
+            "Other numbers allowed to chat, comma-separated (blank for just you)",
+            "",
+        )?;
+        let numbers: Vec<String> = answer
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty() && *n != account)
+            .map(str::to_string)
+            .collect();
+        match numbers.iter().find(|n| !is_phone_number(n)) {
+            Some(bad) => p.say(&format!("{bad} isn't a phone number."))?,
+            None => break numbers,
+        }
+    };
+
+    let model = p.ask("Default Claude model (opus, sonnet, haiku)", DEFAULT_MODEL)?;
+    let max_budget = loop {
+        let answer = p.ask(
+            "Most a single reply may cost, in USD",
+            &DEFAULT_MAX_BUDGET.to_string(),
+        )?;
+        match answer.trim_start_matches('$').parse::<f64>() {
+            Ok(budget) if budget > 0.0 => break budget,
+            _ => p.say("Enter an amount above zero, e.g. 2.50.")?,
+        }
+    };
+
+    let data_dir = p.ask(
+        "Where to keep ccchat's data",
+        &config_dir.display().to_string(),
+    )?;
+    let data_dir = match (data_dir.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(data_dir),
+    };
+
+    let install_unit = cfg!(target_os = "linux")
+        && p.confirm(
+            "Install a systemd user service so ccchat starts at login?",
+            false,
+        )?;
+
+    Ok(Answers {
+        account,
+        link,
+        allowed,
+        model,
+        max_budget,
+        data_dir,
+        install_unit,
+    })
+}
+
+fn env_contents(answers: &Answers, config_dir: &Path) -> String {
+    let mut env = format!(
+        "# Written by `ccchat init`. Flags and environment variables override these.\n\
+         CCCHAT_ACCOUNT={}\n\
+         CCCHAT_MODEL={}\n\
+         CCCHAT_MAX_BUDGET={}\n",
+        answers.account, answers.model, answers.max_budget
+    );
+    if answers.data_dir != config_dir {
+        env.push_str(&format!("CCCHAT_DATA_DIR={}\n", answers.data_dir.display()));
+    }
+    env
+}
+
+/// Write `ccchat.env` into `config_dir` and add the allowed numbers to the
+/// allowed list in the data directory.
+fn write_config(answers: &Answers, config_dir: &Path) -> Result<PathBuf, AppError> {
+    std::fs::create_dir_all(config_dir)?;
+    std::fs::create_dir_all(&answers.data_dir)?;
+    let env_path = config_dir.join(ENV_FILE);
+    std::fs::write(&env_path, env_contents(answers, config_dir))?;
+
+    let allowed_path = answers.data_dir.join("allowed.json");
+    let mut data: PersistedAllowed = std::fs::read_to_string(&allowed_path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    for id in &answers.allowed {
+        if !data.allowed.iter().any(|e| &e.id == id) {
+            data.allowed.push(AllowedEntry {
+                id: id.clone(),
+                name: String::new(),
+            });
+        }
+    }
+    std::fs::write(&allowed_path, serde_json::to_string_pretty(&data)?)?;
+    Ok(env_path)
+}
+
+fn systemd_unit(exe: &Path, env_path: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=ccchat - Claude over Signal\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         EnvironmentFile={}\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=10\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        env_path.display(),
+        exe.display()
+    )
+}
+
+fn install_unit(env_path: &Path) -> Result<String, AppError> {
+    let unit_dir = std::env::var("HOME")
+        .map(PathBuf::from)
+        .map_err(|_| AppError::Other("HOME is not set".to_string()))?
+        .join(".config/systemd/user");
+    std::fs::create_dir_all(&unit_dir)?;
+    let unit_path = unit_dir.join(UNIT_NAME);
+    std::fs::write(
+        &unit_path,
+        systemd_unit(&std::env::current_exe()?, env_path),
+    )?;
+    for args in [
+        &["--user", "daemon-reload"][..],
+        &["--user", "enable", "--now", UNIT_NAME],
+    ] {
+        let status = Command::new("systemctl").args(args).status();
+        if !status.is_ok_and(|s| s.success()) {
+            return Ok(format!(
+                "Wrote {}, but `systemctl {}` failed; run it yourself once systemd is available.",
+                unit_path.display(),
+                args.join(" ")
+            ));
+        }
+    }
+    Ok(format!(
+        "Installed and started {UNIT_NAME}. Logs: journalctl --user -u ccchat -f"
+    ))
+}
+
+/// Run `signal-cli link`, showing its device link as a QR code when
+/// `qrencode` is installed.
+fn link_account<W: Write>(out: &mut W) -> Result<(), AppError> {
+    let mut child = Command::new("signal-cli")
+        .args(["link", "-n", "ccchat"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::Other(format!("Couldn't start signal-cli: {e}")))?;
+    let stdout = child.stdout.take().expect("piped stdout");
+    for line in std::io::BufReader::new(stdout).lines() {
+        let line = line?;
+        if line.starts_with("sgnl://") || line.starts_with("tsdevice:") {
+            writeln!(
+                out,
+                "In Signal on your phone, open Settings > Linked devices and scan:"
+            )?;
+            let shown = Command::new("qrencode")
+                .args(["-t", "ansiutf8", &line])
+                .status()
+                .is_ok_and(|s| s.success());
+            if !shown {
+                writeln!(out, "{line}\n(Install qrencode to see this as a QR code.)")?;
+            }
+        } else {
+            writeln!(out, "{line}")?;
+        }
+    }
+    if !child.wait()?.success() {
+        return Err(AppError::Other("signal-cli link failed".to_string()));
+    }
+    Ok(())
+}
+
+/// Ask the questions and write the config; returns what was done.
+fn run_with<R: BufRead, W: Write>(
+    p: &mut Prompter<R, W>,
+    config_dir: &Path,
+) -> Result<Vec<String>, AppError> {
+    let env_path = config_dir.join(ENV_FILE);
+    if env_path.exists()
+        && !p.confirm(
+            &format!("{} already exists. Replace it?", env_path.display()),
+            false,
+        )?
+    {
+        return Ok(vec![format!("Left {} as it was.", env_path.display())]);
+    }
+    let answers = ask_answers(p, config_dir)?;
+    if answers.link {
+        link_account(&mut p.out)?;
+    }
+    let env_path = write_config(&answers, config_dir)?;
+    let mut done = vec![format!("Wrote {}", env_path.display())];
+    if !answers.allowed.is_empty() {
+        done.push(format!(
+            "Allowed {} in {}",
+            answers.allowed.join(", "),
+            answers.data_dir.join("allowed.json").display()
+        ));
+    }
+    if answers.install_unit {
+        done.push(install_unit(&env_path)?);
+    } else {
+        done.push("Run `ccchat` to start.".to_string());
+    }
+    Ok(done)
+}
+
+pub(crate) fn run_init() -> Result<String, AppError> {
+    let stdin = std::io::stdin();
+    let mut prompter = Prompter {
+        input: stdin.lock(),
+        out: std::io::stdout(),
+    };
+    Ok(run_with(&mut prompter, &default_config_dir())?.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompter(answers: &str) -> Prompter<std::io::Cursor<Vec<u8>>, Vec<u8>> {
+        Prompter {
+            input: std::io::Cursor::new(answers.as_bytes().to_vec()),
+            out: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_env() {
+        let parsed = parse_env(
+            "# comment\n\nCCCHAT_ACCOUNT=+4477\nCCCHAT_MODEL = \"sonnet\"\nnot a setting\n",
+        );
+        assert_eq!(
+            parsed,
+            [
+                ("CCCHAT_ACCOUNT".to_string(), "+4477".to_string()),
+                ("CCCHAT_MODEL".to_string(), "sonnet".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wizard_writes_env_and_allowed_list() {
+        let root = std::env::temp_dir().join(format!("ccchat_init_{}", uuid::Uuid::new_v4()));
+        let config_dir = root.join("config");
+        let data_dir = root.join("data");
+        // Bad answers are asked again
+        let script = format!(
+            "07700\n+447700900123\n\n+447700900456, nope\n+447700900456\nsonnet\n-1\n2.5\n{}\nn\n",
+            data_dir.display()
+        );
+        let mut p = prompter(&script);
+        let done = run_with(&mut p, &config_dir).unwrap();
+        assert!(done[0].contains(ENV_FILE));
+
+        let env = std::fs::read_to_string(config_dir.join(ENV_FILE)).unwrap();
+        let env = parse_env(&env);
+        assert!(env.contains(&("CCCHAT_ACCOUNT".into(), "+447700900123".into())));
+        assert!(env.contains(&("CCCHAT_MODEL".into(), "sonnet".into())));
+        assert!(env.contains(&("CCCHAT_MAX_BUDGET".into(), "2.5".into())));
+        assert!(env.contains(&("CCCHAT_DATA_DIR".into(), data_dir.display().to_string())));
+        let allowed: PersistedAllowed =
+            serde_json::from_str(&std::fs::read_to_string(data_dir.join("allowed.json")).unwrap())
+                .unwrap();
+        assert_eq!(allowed.allowed[0].id, "+447700900456");
+        let asked = String::from_utf8(p.out).unwrap();
+        assert!(asked.contains("nope isn't a phone number"));
+
+        // A second run leaves the config alone unless told otherwise
+        let mut again = prompter("\n");
+        let done = run_with(&mut again, &config_dir).unwrap();
+        assert!(done[0].starts_with("Left"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_systemd_unit_uses_env_file() {
+        let unit = systemd_unit(
+            Path::new("/usr/local/bin/ccchat"),
+            Path::new("/home/me/.config/ccchat/ccchat.env"),
+        );
+        assert!(unit.contains("EnvironmentFile=/home/me/.config/ccchat/ccchat.env\n"));
+        assert!(unit.contains("ExecStart=/usr/local/bin/ccchat\n"));
+        assert!(unit.contains("WantedBy=default.target"));
+    }
+}
@@ -0,0 +1,217 @@
+//! One running ccchat per account (`--force` to start anyway).
+//!
+//! Two instances on the same account each answer every message, so replies
+//! arrive twice and cost twice. Before touching the bridge, ccchat takes a
+//! lock file for the account in the data directory and refuses to start
+//! while another live instance holds it. This has to come first: starting a
+//! managed signal-cli-api kills whatever signal-cli-api is already running,
+//! which would take the other instance's bridge down with it.
+//!
+//! The lock names the holder's pid and host. A lock whose process has exited
+//! is taken over; so is one from another host (a data directory shared over
+//! the network) that hasn't been refreshed for [`INSTANCE_LOCK_STALE_SECS`],
+//! since its pid means nothing here. signal-cli-api keeps nothing per account
+//! that an instance could claim, so the data directory is where the lock
+//! lives whichever bridge is used.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::constants::{INSTANCE_LOCK_REFRESH_SECS, INSTANCE_LOCK_STALE_SECS};
+
+/// Who holds an account's lock.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Holder {
+    pid: u32,
+    host: String,
+    account: String,
+    started: i64,
+    /// Last refresh, for telling a live remote holder from a dead one.
+    refreshed: i64,
+}
+
+impl Holder {
+    fn this_process(account: &str, now: i64) -> Self {
+        Self {
+            pid: std::process::id(),
+            host: host_name(),
+            account: account.to_string(),
+            started: now,
+            refreshed: now,
+        }
+    }
+
+    fn is_us(&self) -> bool {
+        self.pid == std::process::id() && self.host == host_name()
+    }
+
+    /// Whether this lock still keeps others out.
+    fn is_live(&self, now: i64) -> bool {
+        if self.host == host_name() {
+            process_running(self.pid)
+        } else {
+            now - self.refreshed < INSTANCE_LOCK_STALE_SECS as i64
+        }
+    }
+}
+
+/// The lock this process holds, released on shutdown.
+static HELD: OnceLock<PathBuf> = OnceLock::new();
+
+fn host_name() -> String {
+    sysinfo::System::host_name().unwrap_or_default()
+}
+
+/// Whether `pid` is a running ccchat (not just any process that reused it).
+fn process_running(pid: u32) -> bool {
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut system = sysinfo::System::new();
+    system.refresh_processes(sysinfo::ProcessesToUpdate::Some(&[pid]), true);
+    system
+        .process(pid)
+        .is_some_and(|p| p.name().to_string_lossy().contains("ccchat"))
+}
+
+pub(crate) fn lock_path(account: &str) -> PathBuf {
+    crate::memory::config_dir().join(format!(
+        "instance-{}.lock",
+        crate::memory::hash_sender(account)
+    ))
+}
+
+fn read(path: &Path) -> Option<Holder> {
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+fn write(path: &Path, holder: &Holder) -> std::io::Result<()> {
+    let tmp = path.with_extension("lock.tmp");
+    std::fs::write(&tmp, serde_json::to_string(holder)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+fn refused(holder: &Holder) -> String {
+    format!(
+        "Another ccchat is already running for {} (pid {} on {}, since {}). \
+         Two instances on one account answer every message twice. \
+         Stop the other one, or start with --force if you're sure it's gone.",
+        holder.account,
+        holder.pid,
+        holder.host,
+        crate::memory::format_epoch(holder.started.max(0) as u64)
+    )
+}
+
+/// Take the lock at `path`, unless someone else live holds it and `force`
+/// is off.
+fn acquire_at(path: &Path, account: &str, force: bool, now: i64) -> Result<(), String> {
+    if let Some(holder) = read(path) {
+        if !holder.is_us() && holder.is_live(now) {
+            if !force {
+                return Err(refused(&holder));
+            }
+            tracing::warn!(
+                "Starting anyway (--force) although pid {} on {} holds the lock for {account}",
+                holder.pid,
+                holder.host
+            );
+        }
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    }
+    write(path, &Holder::this_process(account, now))
+        .map_err(|e| format!("Cannot write {}: {e}", path.display()))
+}
+
+/// Take `account`'s lock, or say who has it.
+pub(crate) fn acquire(account: &str, force: bool) -> Result<(), String> {
+    let path = lock_path(account);
+    acquire_at(&path, account, force, crate::helpers::epoch_now())?;
+    let _ = HELD.set(path);
+    Ok(())
+}
+
+/// Keep the lock fresh so instances on other hosts sharing the data
+/// directory can tell it's still held.
+pub(crate) fn spawn_refresh_loop() {
+    let Some(path) = HELD.get() else {
+        return;
+    };
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(INSTANCE_LOCK_REFRESH_SECS)).await;
+            match read(path) {
+                Some(mut holder) if holder.is_us() => {
+                    holder.refreshed = crate::helpers::epoch_now();
+                    if let Err(e) = write(path, &holder) {
+                        tracing::warn!("Failed to refresh instance lock: {e}");
+                    }
+                }
+                // Someone started with --force and took it over.
+                Some(holder) => {
+                    tracing::warn!(
+                        "Instance lock taken over by pid {} on {}; this instance no longer holds it",
+                        holder.pid,
+                        holder.host
+                    );
+                    return;
+                }
+                None => return,
+            }
+        }
+    });
+}
+
+/// Give the lock up on the way out, if it's still ours.
+pub(crate) fn release() {
+    if let Some(path) = HELD.get() {
+        if read(path).is_some_and(|h| h.is_us()) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn other(pid: u32, host: &str, refreshed: i64) -> Holder {
+        Holder {
+            pid,
+            host: host.to_string(),
+            account: "+1".to_string(),
+            started: 0,
+            refreshed,
+        }
+    }
+
+    #[test]
+    fn test_acquire_refuses_live_holder() {
+        let dir = std::env::temp_dir().join(format!("ccchat-lock-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("instance.lock");
+        let now = 1_000_000;
+        acquire_at(&path, "+1", false, now).unwrap();
+        // Taking our own lock again is fine.
+        acquire_at(&path, "+1", false, now).unwrap();
+
+        // A remote holder refreshed recently keeps us out unless forced.
+        write(&path, &other(42, "elsewhere", now - 10)).unwrap();
+        let err = acquire_at(&path, "+1", false, now).unwrap_err();
+        assert!(err.contains("pid 42 on elsewhere"));
+        assert!(err.contains("--force"));
+        acquire_at(&path, "+1", true, now).unwrap();
+        assert!(read(&path).unwrap().is_us());
+
+        // One that stopped refreshing is abandoned.
+        let stale = now - INSTANCE_LOCK_STALE_SECS as i64;
+        write(&path, &other(42, "elsewhere", stale)).unwrap();
+        acquire_at(&path, "+1", false, now).unwrap();
+
+        // On this host, only a running ccchat counts.
+        write(&path, &other(u32::MAX - 1, &host_name(), now)).unwrap();
+        acquire_at(&path, "+1", false, now).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! Invite codes (`/invite`): planned sharing without the `/allow` round-trip.
+//!
+//! The admin makes a one-time code and passes it on. An unknown sender whose
+//! message contains an unused code younger than a week is allowed straight
+//! away as a guest: Claude answers their questions but can't run commands,
+//! edit files or browse, and each run may spend at most
+//! [`GUEST_MAX_BUDGET_USD`](crate::constants::GUEST_MAX_BUDGET_USD). `/allow`
+//! lifts the restrictions and `/revoke` ends access as for anyone else.
+//!
+//! So codes can't be guessed, only the first two words of a message that look
+//! like a code are tried, and a sender who gets [`INVITE_MAX_MISSES`] wrong
+//! within an hour isn't checked again until the hour is up.
+
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::access::Access;
+use crate::constants::{INVITE_MAX_MISSES, INVITE_MISS_WINDOW_SECS, INVITE_TTL_SECS};
+use crate::state::State;
+
+/// Code-shaped words tried per message.
+const GUESSES_PER_MESSAGE: usize = 2;
+
+/// Make a new code and remember it. Returned as shown to people (`ABCD-1234`).
+pub(crate) fn create(access: &Access) -> String {
+    let code = uuid::Uuid::new_v4().simple().to_string()[..8].to_uppercase();
+    access
+        .invites
+        .insert(code.clone(), crate::helpers::epoch_now());
+    crate::memory::persist_invites(&access.invites);
+    display(&code)
+}
+
+/// `ABCD1234` as `ABCD-1234`.
+fn display(code: &str) -> String {
+    format!("{}-{}", &code[..4], &code[4..])
+}
+
+/// A word as a stored code would look: no dashes or punctuation, uppercase.
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Whether a normalized word could be a code: eight hex digits, as
+/// [`create`] makes them.
+fn looks_like_code(word: &str) -> bool {
+    word.len() == 8 && word.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Forget codes older than [`INVITE_TTL_SECS`]. True when any were.
+fn prune(access: &Access, now: i64) -> bool {
+    let before = access.invites.len();
+    access
+        .invites
+        .retain(|_, created| now - *created < INVITE_TTL_SECS);
+    access.invites.len() != before
+}
+
+/// The unused code in `text`, if there is one; it's used up. A message with
+/// code-shaped words but no valid code counts as a miss for `sender`.
+fn take_code(access: &Access, sender: &str, text: &str) -> Option<String> {
+    let now = crate::helpers::epoch_now();
+    let pruned = prune(access, now);
+    access
+        .invite_misses
+        .retain(|_, (_, since)| now - *since < INVITE_MISS_WINDOW_SECS);
+    let guesses: Vec<String> = text
+        .split_whitespace()
+        .map(normalize)
+        .filter(|word| looks_like_code(word))
+        .take(GUESSES_PER_MESSAGE)
+        .collect();
+    let locked_out = access
+        .invite_misses
+        .get(sender)
+        .is_some_and(|misses| misses.0 >= INVITE_MAX_MISSES);
+    let code = if guesses.is_empty() || locked_out {
+        None
+    } else {
+        guesses
+            .iter()
+            .find_map(|word| access.invites.remove(word).map(|(code, _)| code))
+    };
+    if code.is_none() && !guesses.is_empty() && !locked_out {
+        let mut misses = access
+            .invite_misses
+            .entry(sender.to_string())
+            .or_insert((0, now));
+        misses.0 += 1;
+        if misses.0 >= INVITE_MAX_MISSES {
+            warn!(sender = %sender, "Too many wrong invite codes; ignoring codes for an hour");
+        }
+    }
+    if pruned || code.is_some() {
+        crate::memory::persist_invites(&access.invites);
+    }
+    code
+}
+
+/// Let `sender` in as a guest when `text` has an invite code, welcoming
+/// them and telling the admin. False when it has none.
+pub(crate) fn redeem(state: &Arc<State>, sender: &str, name: &str, text: &str) -> bool {
+    let Some(code) = take_code(&state.access, sender, text) else {
+        return false;
+    };
+    state.access.pending_senders.remove(sender);
+    state.access.allowed_ids.insert(sender.to_string(), ());
+    state.access.guests.insert(sender.to_string(), ());
+    crate::memory::persist_allow(sender, name);
+    crate::memory::persist_guest(sender, true);
+    crate::audit::log_action("invite", sender, name);
+    info!(sender = %sender, sender_name = %name, "Sender joined with an invite code");
+
+    let welcome = format!(
+        "Welcome! Your invite code worked, so you can chat with Claude here now.\n\
+         As a guest, Claude answers questions but won't run commands or change files, \
+         and each reply is capped at ${:.2}. Send /help to see what you can do.",
+        crate::constants::GUEST_MAX_BUDGET_USD
+    );
+    let notice = format!(
+        "{} ({sender}) joined with invite {} as a guest.\n\
+         /allow {sender} lifts the guest limits; /revoke {sender} removes them.",
+        if name.is_empty() { "Someone" } else { name },
+        display(&code)
+    );
+    let state = Arc::clone(state);
+    let sender = sender.to_string();
+    tokio::spawn(async move {
+        let _ = state.send_message(&sender, &welcome).await;
+        let account = state.config.account.clone();
+        let _ = state.send_message(&account, &notice).await;
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_single_use_and_expire() {
+        let access = Access::default();
+        let code = create(&access);
+        assert_eq!(code.len(), 9);
+        assert_eq!(take_code(&access, "+1", "hello"), None);
+        let lower = format!("hi, my code is {}!", code.to_lowercase());
+        assert_eq!(take_code(&access, "+1", &lower), Some(normalize(&code)));
+        assert_eq!(take_code(&access, "+1", &code), None);
+
+        let old = create(&access);
+        access.invites.insert(
+            normalize(&old),
+            crate::helpers::epoch_now() - INVITE_TTL_SECS,
+        );
+        assert_eq!(take_code(&access, "+1", &old), None);
+        assert!(access.invites.is_empty());
+    }
+
+    #[test]
+    fn test_guessing_is_limited() {
+        let access = Access::default();
+        let code = create(&access);
+        // Only the first two code-shaped words are tried.
+        let spray = format!("00000000 11111111 {code}");
+        assert_eq!(take_code(&access, "+guesser", &spray), None);
+        // Chatter without anything code-shaped isn't a miss.
+        for _ in 0..10 {
+            assert_eq!(take_code(&access, "+guesser", "hello there"), None);
+        }
+        for _ in 1..INVITE_MAX_MISSES {
+            assert_eq!(take_code(&access, "+guesser", "DEADBEEF"), None);
+        }
+        // Out of tries: even the right code is ignored for now.
+        assert_eq!(take_code(&access, "+guesser", &code), None);
+        assert_eq!(
+            access.invite_misses.get("+guesser").unwrap().0,
+            INVITE_MAX_MISSES
+        );
+        assert!(access.invites.contains_key(&normalize(&code)));
+        assert_eq!(take_code(&access, "+friend", &code), Some(normalize(&code)));
+    }
+}
@@ -0,0 +1,172 @@
+//! Prompts Claude was working on, kept so a crash doesn't swallow them.
+//!
+//! A conversation prompt is written to the `journal` table of the queue
+//! database before Claude runs and removed once the run is over, however it
+//! ended. Whatever is still there at startup was cut off by the process
+//! dying: its sender is asked whether to resend it (`/retry` does), or with
+//! `--rerun-interrupted` it is run again straight away. Attachments are not
+//! kept; they're cleaned up with the run.
+
+use rusqlite::Connection;
+use tracing::error;
+
+use crate::constants::JOURNAL_MAX_AGE_SECS;
+
+/// Sent for interrupted prompts when they aren't re-run automatically.
+pub(crate) const INTERRUPTED_MSG: &str = "Your last request was interrupted — resend? (/retry)";
+
+/// A prompt in flight; leaves the journal when dropped. A panicking run
+/// leaves it behind, like a crash would.
+pub(crate) struct Entry(Option<i64>);
+
+impl Entry {
+    pub(crate) fn begin(sender: &str, text: &str) -> Self {
+        let id = crate::queue::open_queue_db()
+            .ok()
+            .and_then(|conn| record(&conn, sender, text, crate::helpers::epoch_now()));
+        Self(id)
+    }
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        let Some(id) = self.0 else {
+            return;
+        };
+        if std::thread::panicking() {
+            return;
+        }
+        if let Ok(conn) = crate::queue::open_queue_db() {
+            let _ = conn.execute("DELETE FROM journal WHERE id = ?1", [id]);
+        }
+    }
+}
+
+fn record(conn: &Connection, sender: &str, text: &str, now: i64) -> Option<i64> {
+    match conn.execute(
+        "INSERT INTO journal (sender, content, started) VALUES (?1, ?2, ?3)",
+        rusqlite::params![sender, text, now],
+    ) {
+        Ok(_) => Some(conn.last_insert_rowid()),
+        Err(e) => {
+            error!("Failed to journal prompt: {e}");
+            None
+        }
+    }
+}
+
+/// Mark the prompts left running by the last process as interrupted, and
+/// return their senders with how many each lost. Entries older than
+/// [`JOURNAL_MAX_AGE_SECS`] are dropped instead.
+fn interrupt_unfinished(conn: &Connection, now: i64) -> Vec<(String, usize)> {
+    let _ = conn.execute(
+        "DELETE FROM journal WHERE started < ?1",
+        [now - JOURNAL_MAX_AGE_SECS],
+    );
+    let Ok(mut stmt) = conn.prepare(
+        "UPDATE journal SET status = 'interrupted' WHERE status = 'running' RETURNING sender",
+    ) else {
+        return Vec::new();
+    };
+    let senders: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .ok()
+        .map(|iter| iter.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default();
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for sender in senders {
+        match counts.iter_mut().find(|(s, _)| *s == sender) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((sender, 1)),
+        }
+    }
+    counts
+}
+
+/// Startup: find what the last process didn't finish.
+pub(crate) fn recover() -> Vec<(String, usize)> {
+    match crate::queue::open_queue_db() {
+        Ok(conn) => interrupt_unfinished(&conn, crate::helpers::epoch_now()),
+        Err(e) => {
+            error!("Failed to open journal: {e}");
+            Vec::new()
+        }
+    }
+}
+
+fn take_from(conn: &Connection, sender: &str) -> Vec<String> {
+    let Ok(mut stmt) = conn.prepare(
+        "DELETE FROM journal WHERE sender = ?1 AND status = 'interrupted' RETURNING id, content",
+    ) else {
+        return Vec::new();
+    };
+    let mut prompts: Vec<(i64, String)> = stmt
+        .query_map([sender], |row| Ok((row.get(0)?, row.get(1)?)))
+        .ok()
+        .map(|iter| iter.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default();
+    prompts.sort();
+    prompts.into_iter().map(|(_, content)| content).collect()
+}
+
+/// `sender`'s interrupted prompts, oldest first, removed from the journal.
+pub(crate) fn take_interrupted(sender: &str) -> Vec<String> {
+    crate::queue::open_queue_db()
+        .map(|conn| take_from(&conn, sender))
+        .unwrap_or_default()
+}
+
+/// What to tell a sender who lost `count` prompts.
+pub(crate) fn interrupted_notice(count: usize) -> String {
+    if count == 1 {
+        INTERRUPTED_MSG.to_string()
+    } else {
+        format!("Your last {count} requests were interrupted — resend? (/retry)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_journal_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE journal (
+                id INTEGER PRIMARY KEY,
+                sender TEXT NOT NULL,
+                content TEXT NOT NULL,
+                started INTEGER NOT NULL,
+                status TEXT NOT NULL DEFAULT 'running'
+            );",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_interrupt_and_take() {
+        let conn = test_journal_db();
+        let now = 1_000_000;
+        record(&conn, "+a", "first", now - 20).unwrap();
+        record(&conn, "+b", "other", now - 10).unwrap();
+        record(&conn, "+a", "second", now - 5).unwrap();
+        record(&conn, "+a", "ancient", now - JOURNAL_MAX_AGE_SECS - 1).unwrap();
+
+        let mut lost = interrupt_unfinished(&conn, now);
+        lost.sort();
+        assert_eq!(lost, vec![("+a".to_string(), 2), ("+b".to_string(), 1)]);
+        // Already interrupted: a second startup doesn't report them again.
+        assert!(interrupt_unfinished(&conn, now).is_empty());
+
+        assert_eq!(take_from(&conn, "+a"), vec!["first", "second"]);
+        assert!(take_from(&conn, "+a").is_empty());
+        assert_eq!(take_from(&conn, "+b"), vec!["other"]);
+    }
+
+    #[test]
+    fn test_interrupted_notice() {
+        assert_eq!(interrupted_notice(1), INTERRUPTED_MSG);
+        assert!(interrupted_notice(3).starts_with("Your last 3 requests"));
+    }
+}
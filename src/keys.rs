@@ -0,0 +1,270 @@
+//! Several Anthropic credentials for the `claude` process, with failover.
+//!
+//! Each credential is an API key (passed as `ANTHROPIC_API_KEY`) or a claude
+//! CLI profile directory (passed as `CLAUDE_CONFIG_DIR`), listed under
+//! `claude_accounts` in the `--config` file. Runs use the active one; when it
+//! hits a rate limit or usage quota it is rested for a while and the next
+//! available credential takes over.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::KEY_COOLDOWN_SECS;
+
+/// One `claude_accounts` entry. Exactly one of `api_key`, `api_key_env` and
+/// `config_dir` is set.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct AccountEntry {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) api_key: Option<String>,
+    /// Environment variable holding the key, to keep it out of the file.
+    #[serde(default)]
+    pub(crate) api_key_env: Option<String>,
+    /// claude CLI profile directory (logged in with `claude /login`).
+    #[serde(default)]
+    pub(crate) config_dir: Option<String>,
+}
+
+pub(crate) struct Credential {
+    pub(crate) name: String,
+    /// Environment for the `claude` process when using this credential.
+    pub(crate) env: Vec<(&'static str, String)>,
+}
+
+impl Credential {
+    pub(crate) fn from_entry(entry: &AccountEntry) -> Result<Self, String> {
+        if entry.name.is_empty() {
+            return Err("every claude_accounts entry needs a name".to_string());
+        }
+        let name = entry.name.clone();
+        let env = match (&entry.api_key, &entry.api_key_env, &entry.config_dir) {
+            (Some(key), None, None) => ("ANTHROPIC_API_KEY", key.clone()),
+            (None, Some(var), None) => match std::env::var(var) {
+                Ok(key) if !key.is_empty() => ("ANTHROPIC_API_KEY", key),
+                _ => return Err(format!("claude account {name}: ${var} is not set")),
+            },
+            (None, None, Some(dir)) => ("CLAUDE_CONFIG_DIR", expand_home(dir)),
+            _ => {
+                return Err(format!(
+                    "claude account {name}: set one of api_key, api_key_env or config_dir"
+                ))
+            }
+        };
+        Ok(Self {
+            name,
+            env: vec![env],
+        })
+    }
+}
+
+fn expand_home(dir: &str) -> String {
+    match (dir.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{home}/{rest}"),
+        _ => dir.to_string(),
+    }
+}
+
+/// Whether a `claude` failure means this credential is rate limited or out
+/// of quota (as opposed to a problem with the request itself).
+pub(crate) fn is_limit_error(message: &str) -> bool {
+    let lower = message.to_lowercase();
+    [
+        "rate limit",
+        "rate_limit",
+        "usage limit",
+        "429",
+        "quota",
+        "credit balance",
+    ]
+    .iter()
+    .any(|needle| lower.contains(needle))
+}
+
+/// The configured credentials and which one is in use.
+#[derive(Default)]
+pub(crate) struct KeyPool {
+    creds: Vec<Credential>,
+    active: AtomicUsize,
+    /// Epoch secs until which each credential is skipped.
+    resting_until: Vec<AtomicU64>,
+    /// Runs started with each credential.
+    runs: Vec<AtomicU64>,
+    pub(crate) failovers: AtomicU64,
+}
+
+impl KeyPool {
+    pub(crate) fn new(creds: Vec<Credential>) -> Self {
+        Self {
+            resting_until: creds.iter().map(|_| AtomicU64::new(0)).collect(),
+            runs: creds.iter().map(|_| AtomicU64::new(0)).collect(),
+            creds,
+            active: AtomicUsize::new(0),
+            failovers: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.creds.len()
+    }
+
+    /// Credential for the next run: the active one unless it is resting,
+    /// otherwise the first available after it, otherwise whichever is back
+    /// soonest. None when no credentials are configured.
+    pub(crate) fn current(&self, now: u64) -> Option<(usize, &Credential)> {
+        if self.creds.is_empty() {
+            return None;
+        }
+        let active = self.active.load(Ordering::Relaxed);
+        let n = self.creds.len();
+        let idx = (0..n)
+            .map(|i| (active + i) % n)
+            .find(|&i| self.resting_until[i].load(Ordering::Relaxed) <= now)
+            .unwrap_or_else(|| {
+                (0..n)
+                    .min_by_key(|&i| self.resting_until[i].load(Ordering::Relaxed))
+                    .unwrap_or(active)
+            });
+        self.active.store(idx, Ordering::Relaxed);
+        self.runs[idx].fetch_add(1, Ordering::Relaxed);
+        Some((idx, &self.creds[idx]))
+    }
+
+    /// Rest credential `idx` after a rate limit or quota error and move on
+    /// to the next one.
+    pub(crate) fn fail_over(&self, idx: usize, now: u64) {
+        self.resting_until[idx].store(now + KEY_COOLDOWN_SECS, Ordering::Relaxed);
+        let _ = self.active.compare_exchange(
+            idx,
+            (idx + 1) % self.creds.len(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+        self.failovers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// One line per credential for `/key status`.
+    pub(crate) fn status(&self, now: u64) -> String {
+        if self.creds.is_empty() {
+            return "Using claude's own login (no claude_accounts configured).".to_string();
+        }
+        let active = self.active.load(Ordering::Relaxed);
+        let mut lines = vec!["Claude accounts:".to_string()];
+        for (i, cred) in self.creds.iter().enumerate() {
+            let marker = if i == active { "*" } else { " " };
+            let kind = if cred.env[0].0 == "CLAUDE_CONFIG_DIR" {
+                "profile"
+            } else {
+                "API key"
+            };
+            let runs = self.runs[i].load(Ordering::Relaxed);
+            let resting = self.resting_until[i].load(Ordering::Relaxed);
+            let state = if resting > now {
+                format!(
+                    "rate limited, retry in {} min",
+                    (resting - now).div_ceil(60)
+                )
+            } else {
+                "ok".to_string()
+            };
+            lines.push(format!(
+                "{marker} {} ({kind}): {state}, {runs} runs",
+                cred.name
+            ));
+        }
+        lines.push(format!(
+            "Failovers since start: {}",
+            self.failovers.load(Ordering::Relaxed)
+        ));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(names: &[&str]) -> KeyPool {
+        KeyPool::new(
+            names
+                .iter()
+                .map(|name| {
+                    Credential::from_entry(&AccountEntry {
+                        name: name.to_string(),
+                        api_key: Some(format!("sk-{name}")),
+                        ..Default::default()
+                    })
+                    .unwrap()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_fail_over_rests_and_rotates() {
+        let pool = pool(&["personal", "team"]);
+        let (idx, cred) = pool.current(1000).unwrap();
+        assert_eq!((idx, cred.name.as_str()), (0, "personal"));
+        assert_eq!(
+            cred.env,
+            vec![("ANTHROPIC_API_KEY", "sk-personal".to_string())]
+        );
+
+        pool.fail_over(0, 1000);
+        assert_eq!(pool.current(1001).unwrap().1.name, "team");
+        // Both resting: use whichever comes back first.
+        pool.fail_over(1, 1002);
+        assert_eq!(pool.current(1003).unwrap().1.name, "personal");
+        // Once personal's rest is over it is picked again.
+        assert_eq!(
+            pool.current(1000 + KEY_COOLDOWN_SECS).unwrap().1.name,
+            "personal"
+        );
+        assert_eq!(pool.failovers.load(Ordering::Relaxed), 2);
+
+        let status = pool.status(1003);
+        assert!(status.contains("* personal (API key): rate limited, retry in"));
+        assert!(status.contains("  team (API key): rate limited"));
+        assert!(status.ends_with("Failovers since start: 2"));
+        assert!(KeyPool::default().current(0).is_none());
+    }
+
+    #[test]
+    fn test_entry_validation() {
+        let entry = |api_key: Option<&str>, config_dir: Option<&str>| AccountEntry {
+            name: "x".to_string(),
+            api_key: api_key.map(str::to_string),
+            api_key_env: None,
+            config_dir: config_dir.map(str::to_string),
+        };
+        assert!(Credential::from_entry(&entry(None, None)).is_err());
+        assert!(Credential::from_entry(&entry(Some("k"), Some("/d"))).is_err());
+        let cred = Credential::from_entry(&entry(None, Some("/opt/claude-team"))).unwrap();
+        assert_eq!(
+            cred.env,
+            vec![("CLAUDE_CONFIG_DIR", "/opt/claude-team".to_string())]
+        );
+        let missing = AccountEntry {
+            name: "y".to_string(),
+            api_key_env: Some("CCCHAT_TEST_UNSET_KEY_VAR".to_string()),
+            ..Default::default()
+        };
+        assert!(Credential::from_entry(&missing)
+            .err()
+            .unwrap()
+            .contains("$CCCHAT_TEST_UNSET_KEY_VAR is not set"));
+    }
+
+    #[test]
+    fn test_is_limit_error() {
+        assert!(is_limit_error(
+            "Claude error: Claude AI usage limit reached|1760000000"
+        ));
+        assert!(is_limit_error(
+            "API Error: 429 {\"type\":\"rate_limit_error\"}"
+        ));
+        assert!(is_limit_error("Credit balance is too low"));
+        assert!(!is_limit_error("claude exited with 1: invalid model"));
+    }
+}
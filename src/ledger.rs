@@ -0,0 +1,261 @@
+//! Usage ledger: one row per Claude reply, kept in
+//! `~/.config/ccchat/usage.db` for `/report` and cost exports
+//! (`/costs.csv`, `/costs.json`, `ccchat costs export`).
+
+use rusqlite::Connection;
+use tracing::error;
+
+use crate::error::AppError;
+
+const LEDGER_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        sender TEXT NOT NULL,
+        session_id TEXT NOT NULL,
+        project TEXT,
+        cost_usd REAL NOT NULL DEFAULT 0,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_timestamp ON runs (timestamp);";
+
+pub(crate) fn open_ledger_db() -> Result<Connection, AppError> {
+    let path = crate::memory::config_dir().join("usage.db");
+    let conn = Connection::open(&path)?;
+    conn.execute_batch("PRAGMA journal_mode=WAL;")?;
+    conn.execute_batch(LEDGER_SCHEMA)?;
+    Ok(conn)
+}
+
+/// One answered message.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Run {
+    pub(crate) sender: String,
+    pub(crate) session_id: String,
+    /// Bound project directory name, if the chat had one.
+    pub(crate) project: Option<String>,
+    pub(crate) cost_usd: f64,
+    pub(crate) timestamp: i64,
+}
+
+pub(crate) fn insert_run(conn: &Connection, run: &Run) {
+    if let Err(e) = conn.execute(
+        "INSERT INTO runs (sender, session_id, project, cost_usd, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![run.sender, run.session_id, run.project, run.cost_usd, run.timestamp],
+    ) {
+        error!("Ledger write failed: {e}");
+    }
+}
+
+/// Record a reply sent to `sender` now.
+pub(crate) fn record_run(sender: &str, session_id: &str, project: Option<String>, cost_usd: f64) {
+    let Ok(conn) = open_ledger_db() else { return };
+    insert_run(
+        &conn,
+        &Run {
+            sender: sender.to_string(),
+            session_id: session_id.to_string(),
+            project,
+            cost_usd,
+            timestamp: crate::helpers::epoch_now(),
+        },
+    );
+}
+
+/// Spent on Claude so far today (UTC, as the ledger counts days).
+pub(crate) fn spent_today() -> f64 {
+    let now = crate::helpers::epoch_now();
+    let midnight = now - now % crate::constants::SECS_PER_DAY;
+    open_ledger_db()
+        .map(|conn| {
+            runs_since(&conn, midnight, None)
+                .iter()
+                .map(|run| run.cost_usd)
+                .sum()
+        })
+        .unwrap_or(0.0)
+}
+
+/// The chat that `session_id` belonged to, from its first recorded run.
+pub(crate) fn chat_for_session(conn: &Connection, session_id: &str) -> Option<String> {
+    conn.query_row(
+        "SELECT sender FROM runs WHERE session_id = ?1 ORDER BY id LIMIT 1",
+        [session_id],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+/// Runs since `since` (epoch secs), oldest first; only `sender`'s if given.
+pub(crate) fn runs_since(conn: &Connection, since: i64, sender: Option<&str>) -> Vec<Run> {
+    let sql = "SELECT sender, session_id, project, cost_usd, timestamp FROM runs
+        WHERE timestamp >= ?1 AND (?2 IS NULL OR sender = ?2) ORDER BY timestamp, id";
+    let Ok(mut stmt) = conn.prepare(sql) else {
+        return Vec::new();
+    };
+    stmt.query_map(rusqlite::params![since, sender], |row| {
+        Ok(Run {
+            sender: row.get(0)?,
+            session_id: row.get(1)?,
+            project: row.get(2)?,
+            cost_usd: row.get(3)?,
+            timestamp: row.get(4)?,
+        })
+    })
+    .ok()
+    .map(|iter| iter.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+/// One sender's spend on one UTC day.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct DailyCost {
+    /// `YYYY-MM-DD`
+    pub(crate) date: String,
+    pub(crate) sender: String,
+    pub(crate) runs: u64,
+    pub(crate) cost_usd: f64,
+}
+
+/// Per-sender, per-day totals for runs in `[since, until)` (epoch secs).
+pub(crate) fn daily_costs(conn: &Connection, since: i64, until: i64) -> Vec<DailyCost> {
+    let sql = "SELECT date(timestamp, 'unixepoch') AS day, sender, COUNT(*), SUM(cost_usd)
+        FROM runs WHERE timestamp >= ?1 AND timestamp < ?2
+        GROUP BY day, sender ORDER BY day, sender";
+    let Ok(mut stmt) = conn.prepare(sql) else {
+        return Vec::new();
+    };
+    stmt.query_map(rusqlite::params![since, until], |row| {
+        Ok(DailyCost {
+            date: row.get(0)?,
+            sender: row.get(1)?,
+            runs: row.get(2)?,
+            cost_usd: row.get(3)?,
+        })
+    })
+    .ok()
+    .map(|iter| iter.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+/// Epoch range for an export between two `YYYY-MM-DD` dates, both
+/// inclusive; either end may be left open.
+pub(crate) fn date_range(since: Option<&str>, until: Option<&str>) -> Result<(i64, i64), String> {
+    let parse = |s: &str| {
+        chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map(|d| {
+                d.and_hms_opt(0, 0, 0)
+                    .unwrap_or_default()
+                    .and_utc()
+                    .timestamp()
+            })
+            .map_err(|_| format!("Invalid date '{s}' (expected YYYY-MM-DD)"))
+    };
+    let start = since.map(parse).transpose()?.unwrap_or(0);
+    let end = match until {
+        Some(s) => parse(s)? + crate::constants::SECS_PER_DAY,
+        None => i64::MAX,
+    };
+    Ok((start, end))
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub(crate) fn costs_csv(rows: &[DailyCost]) -> String {
+    let mut out = String::from("date,sender,runs,cost_usd\n");
+    for row in rows {
+        out.push_str(&format!(
+            "{},{},{},{:.6}\n",
+            row.date,
+            csv_field(&row.sender),
+            row.runs,
+            row.cost_usd
+        ));
+    }
+    out
+}
+
+pub(crate) fn costs_json(rows: &[DailyCost]) -> serde_json::Value {
+    serde_json::json!({
+        "rows": rows,
+        "total_cost_usd": rows.iter().map(|r| r.cost_usd).sum::<f64>(),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn test_ledger_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(LEDGER_SCHEMA).unwrap();
+        conn
+    }
+
+    pub(crate) fn run(sender: &str, session_id: &str, cost_usd: f64, timestamp: i64) -> Run {
+        Run {
+            sender: sender.to_string(),
+            session_id: session_id.to_string(),
+            project: None,
+            cost_usd,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_runs_since_filters_by_time_and_sender() {
+        let conn = test_ledger_db();
+        insert_run(&conn, &run("+a", "s1", 0.1, 100));
+        insert_run(&conn, &run("+b", "s2", 0.2, 200));
+        insert_run(
+            &conn,
+            &Run {
+                project: Some("api".to_string()),
+                ..run("+a", "s1", 0.3, 300)
+            },
+        );
+        let all = runs_since(&conn, 150, None);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].sender, "+b");
+        assert_eq!(all[1].project.as_deref(), Some("api"));
+        let mine = runs_since(&conn, 0, Some("+a"));
+        assert_eq!(mine.len(), 2);
+        assert!(mine.iter().all(|r| r.sender == "+a"));
+    }
+
+    #[test]
+    fn test_daily_costs_groups_by_day_and_sender() {
+        let conn = test_ledger_db();
+        // 2025-03-01 00:00:00 UTC
+        let day = 1740787200;
+        insert_run(&conn, &run("+a", "s1", 0.10, day + 60));
+        insert_run(&conn, &run("+a", "s1", 0.05, day + 3600));
+        insert_run(&conn, &run("+b", "s2", 0.20, day + 7200));
+        insert_run(&conn, &run("+a", "s3", 1.00, day + 86_400));
+
+        let (since, until) = date_range(Some("2025-03-01"), Some("2025-03-01")).unwrap();
+        let rows = daily_costs(&conn, since, until);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].date, "2025-03-01");
+        assert_eq!((rows[0].sender.as_str(), rows[0].runs), ("+a", 2));
+        assert!((rows[0].cost_usd - 0.15).abs() < 1e-9);
+
+        let (since, until) = date_range(None, None).unwrap();
+        let all = daily_costs(&conn, since, until);
+        assert_eq!(
+            costs_csv(&all),
+            "date,sender,runs,cost_usd\n\
+             2025-03-01,+a,2,0.150000\n\
+             2025-03-01,+b,1,0.200000\n\
+             2025-03-02,+a,1,1.000000\n"
+        );
+        let json = costs_json(&all);
+        assert_eq!(json["rows"][2]["date"], "2025-03-02");
+        assert!((json["total_cost_usd"].as_f64().unwrap() - 1.35).abs() < 1e-9);
+        assert!(date_range(Some("March"), None).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Admin notices when the bridge comes online or goes offline
+//! (`--notify-lifecycle`).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::state::State;
+
+/// Set once the panic hook has announced going offline, so a cascade of
+/// panics sends one message.
+static PANIC_NOTIFIED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn online_message(model: &str) -> String {
+    format!(
+        "ccchat online, version {}, model {model}",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+pub(crate) fn offline_message(reason: &str) -> String {
+    format!("ccchat going offline ({reason})")
+}
+
+pub(crate) fn task_panic_message(reason: &str) -> String {
+    format!("ccchat: a task panicked, still running ({reason})")
+}
+
+/// What to tell the admin about a panic. Only one on the main thread ends
+/// the process; elsewhere the runtime drops the task and the bridge carries
+/// on, so it isn't reported as going offline.
+fn panic_notice(on_main_thread: bool, reason: &str) -> Option<String> {
+    if !on_main_thread {
+        return Some(task_panic_message(reason));
+    }
+    (!PANIC_NOTIFIED.swap(true, Ordering::Relaxed)).then(|| offline_message(reason))
+}
+
+/// Tell the admin the bridge is up. Only the first successful connect is
+/// announced; WebSocket reconnects stay quiet. Every connect is recorded for
+/// `/status`.
+pub(crate) async fn announce_online(state: &State) {
+    state.metrics.record_connected();
+    if !state.config.notify_lifecycle || state.online_announced.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let msg = online_message(&state.settings().model);
+    if let Err(e) = state.send_message(&state.config.account, &msg).await {
+        debug!("Failed to send online notice: {e}");
+    }
+}
+
+/// Tell the admin the bridge is going away. Bounded so it can't hold up shutdown.
+pub(crate) async fn announce_offline(state: &State, reason: &str) {
+    if !state.config.notify_lifecycle {
+        return;
+    }
+    notify_bounded(state, &offline_message(reason)).await;
+}
+
+async fn notify_bounded(state: &State, msg: &str) {
+    match tokio::time::timeout(
+        Duration::from_secs(5),
+        state.send_message(&state.config.account, msg),
+    )
+    .await
+    {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => debug!("Failed to send lifecycle notice: {e}"),
+        Err(_) => debug!("Timed out sending lifecycle notice"),
+    }
+}
+
+/// Best-effort notice on panic. Runs the send on a fresh thread and runtime
+/// because the hook may fire on a runtime worker.
+pub(crate) fn install_panic_hook(state: &Arc<State>) {
+    if !state.config.notify_lifecycle {
+        return;
+    }
+    let state = Arc::clone(state);
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        // Char-based cut: a byte slice could itself panic inside the hook.
+        let reason: String = format!("panic: {info}").chars().take(300).collect();
+        let on_main_thread = std::thread::current().name() == Some("main");
+        let Some(msg) = panic_notice(on_main_thread, &reason) else {
+            return;
+        };
+        let state = Arc::clone(&state);
+        let _ = std::thread::spawn(move || {
+            if let Ok(rt) = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                rt.block_on(notify_bounded(&state, &msg));
+            }
+        })
+        .join();
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_online_message_has_version_and_model() {
+        let msg = online_message("sonnet");
+        assert!(msg.starts_with("ccchat online, version "));
+        assert!(msg.contains(env!("CARGO_PKG_VERSION")));
+        assert!(msg.ends_with("model sonnet"));
+    }
+
+    #[tokio::test]
+    async fn test_announce_online_once_when_enabled() {
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_send_msg()
+            .withf(|to, msg| to == "+1234567890" && msg.starts_with("ccchat online"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let mut state = test_state_with(signal, MockClaudeRunner::new());
+        state.config_mut().notify_lifecycle = true;
+        announce_online(&state).await;
+        // Reconnects don't re-announce.
+        announce_online(&state).await;
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_silent_when_disabled() {
+        let mut signal = MockSignalApi::new();
+        signal.expect_send_msg().never();
+        let state = test_state_with(signal, MockClaudeRunner::new());
+        announce_online(&state).await;
+        announce_offline(&state, "shutdown").await;
+    }
+
+    #[test]
+    fn test_only_main_thread_panic_goes_offline() {
+        assert_eq!(
+            panic_notice(false, "panic: boom").as_deref(),
+            Some("ccchat: a task panicked, still running (panic: boom)")
+        );
+        // A task panic doesn't use up the one offline notice.
+        assert_eq!(
+            panic_notice(true, "panic: crash").as_deref(),
+            Some("ccchat going offline (panic: crash)")
+        );
+        assert_eq!(panic_notice(true, "panic: again"), None);
+        assert!(panic_notice(false, "panic: later").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_announce_offline_sends_reason() {
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_send_msg()
+            .withf(|_, msg| msg == "ccchat going offline (SIGTERM)")
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let mut state = test_state_with(signal, MockClaudeRunner::new());
+        state.config_mut().notify_lifecycle = true;
+        announce_offline(&state, "SIGTERM").await;
+    }
+}
@@ -0,0 +1,91 @@
+//! Where the managed bridge and the stats server listen (`--bind-addr`).
+//!
+//! Both default to 127.0.0.1. `--bind-addr` takes any local address: `::1`
+//! for IPv6 loopback, one interface's address to serve just that network,
+//! or `0.0.0.0` / `::` for all of them. Off loopback, the stats server is
+//! refused without `--stats-token`, and a warning is logged for each
+//! listener, since signal-cli-api has no authentication of its own.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Address to connect to for a listener bound to `ip`: a wildcard bind is
+/// reached over loopback of the same family.
+pub(crate) fn connect_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) if v4.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(v6) if v6.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    }
+}
+
+/// Base URL of an HTTP listener bound to `ip`:`port`, IPv6 in brackets.
+pub(crate) fn base_url(ip: IpAddr, port: u16) -> String {
+    format!("http://{}", SocketAddr::new(connect_ip(ip), port))
+}
+
+/// Check that listening on `ip` is safe with these settings; returns the
+/// warnings to log. Off loopback the stats server needs a token.
+pub(crate) fn check(
+    ip: IpAddr,
+    managed_bridge: bool,
+    stats_port: u16,
+    stats_token: Option<&str>,
+) -> Result<Vec<String>, String> {
+    if ip.is_loopback() {
+        return Ok(Vec::new());
+    }
+    let stats = stats_port > 0;
+    if stats && stats_token.is_none_or(|t| t.trim().is_empty()) {
+        return Err(format!(
+            "--bind-addr {ip} exposes the stats server beyond this machine; set --stats-token"
+        ));
+    }
+    let mut warnings = Vec::new();
+    if managed_bridge {
+        warnings.push(format!(
+            "signal-cli-api will listen on {ip}; it has no authentication, so anyone who can reach it can send as this account. Firewall it."
+        ));
+    }
+    if stats {
+        warnings.push(format!(
+            "Stats server will listen on {ip}, port {stats_port}; requests need the stats token"
+        ));
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_url() {
+        let v4: IpAddr = "127.0.0.1".parse().unwrap();
+        assert_eq!(base_url(v4, 8080), "http://127.0.0.1:8080");
+        assert_eq!(base_url("::1".parse().unwrap(), 8080), "http://[::1]:8080");
+        assert_eq!(
+            base_url("0.0.0.0".parse().unwrap(), 80),
+            "http://127.0.0.1:80"
+        );
+        assert_eq!(base_url("::".parse().unwrap(), 80), "http://[::1]:80");
+        assert_eq!(
+            base_url("192.168.1.5".parse().unwrap(), 80),
+            "http://192.168.1.5:80"
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let loopback: IpAddr = "::1".parse().unwrap();
+        assert!(check(loopback, true, 8081, None).unwrap().is_empty());
+
+        let lan: IpAddr = "192.168.1.5".parse().unwrap();
+        assert!(check(lan, true, 8081, None)
+            .unwrap_err()
+            .contains("--stats-token"));
+        assert!(check(lan, true, 8081, Some(" ")).is_err());
+        assert_eq!(check(lan, true, 8081, Some("s3cret")).unwrap().len(), 2);
+        assert_eq!(check(lan, true, 0, None).unwrap().len(), 1);
+        assert!(check(lan, false, 0, None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+//! Live WARN/ERROR log forwarding to the admin's Signal chat (`/tail on`).
+//!
+//! `TailLayer` is a tracing layer that, while tailing is active, pushes
+//! formatted events into a bounded channel. A background forwarder drains the
+//! channel and sends them to the admin in rate-limited batches.
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// Shared on/off switch for tailing: active until the stored epoch second.
+#[derive(Default)]
+pub(crate) struct TailHandle {
+    until_epoch: AtomicI64,
+    dropped: AtomicU64,
+}
+
+impl TailHandle {
+    pub(crate) fn enable_for(&self, minutes: u64) {
+        self.until_epoch.store(
+            crate::helpers::epoch_now() + minutes as i64 * 60,
+            Ordering::Relaxed,
+        );
+    }
+
+    pub(crate) fn disable(&self) {
+        self.until_epoch.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        crate::helpers::epoch_now() < self.until_epoch.load(Ordering::Relaxed)
+    }
+
+    /// Seconds of tailing left (0 when off).
+    pub(crate) fn remaining_secs(&self) -> u64 {
+        (self.until_epoch.load(Ordering::Relaxed) - crate::helpers::epoch_now()).max(0) as u64
+    }
+
+    /// Number of events dropped because the channel was full, reset on read.
+    pub(crate) fn take_dropped(&self) -> u64 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+pub(crate) struct TailLayer {
+    handle: Arc<TailHandle>,
+    tx: mpsc::Sender<String>,
+}
+
+impl TailLayer {
+    pub(crate) fn new(handle: Arc<TailHandle>) -> (Self, mpsc::Receiver<String>) {
+        let (tx, rx) = mpsc::channel(crate::constants::TAIL_CHANNEL_CAPACITY);
+        (Self { handle, tx }, rx)
+    }
+}
+
+#[derive(Default)]
+struct LineVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for LineVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={value}", field.name());
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for TailLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        // Never forward our own forwarding errors back into the channel.
+        if *meta.level() > Level::WARN
+            || meta.target() == module_path!()
+            || !self.handle.is_active()
+        {
+            return;
+        }
+        let mut visitor = LineVisitor::default();
+        event.record(&mut visitor);
+        let line = format!(
+            "{} {}: {}{}",
+            meta.level(),
+            meta.target(),
+            visitor.message,
+            visitor.fields
+        );
+        if self.tx.try_send(line).is_err() {
+            self.handle.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Drain buffered events and send them to the admin as one batch.
+/// Returns the number of events reported (including dropped ones).
+pub(crate) async fn flush_batch(
+    state: &crate::state::State,
+    rx: &mut mpsc::Receiver<String>,
+) -> u64 {
+    let mut lines = Vec::new();
+    while let Ok(line) = rx.try_recv() {
+        lines.push(line);
+    }
+    let dropped = state.log_tail.take_dropped();
+    if lines.is_empty() && dropped == 0 {
+        return 0;
+    }
+    let msg = format_batch(&lines, dropped);
+    if let Err(e) = state.send_long_message(&state.config.account, &msg).await {
+        // Logged below WARN so a failing send can't feed itself back into the tail.
+        tracing::debug!("Failed to forward log tail batch: {e}");
+    }
+    lines.len() as u64 + dropped
+}
+
+/// Format one batch of log lines as a single Signal message.
+pub(crate) fn format_batch(lines: &[String], dropped: u64) -> String {
+    let max = crate::constants::TAIL_MAX_LINES_PER_BATCH;
+    let mut out = vec![format!("[tail] {} event(s)", lines.len() as u64 + dropped)];
+    out.extend(lines.iter().take(max).cloned());
+    let skipped = lines.len().saturating_sub(max) as u64 + dropped;
+    if skipped > 0 {
+        out.push(format!("(+{skipped} more not shown)"));
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    fn collect(active: bool, emit: impl FnOnce()) -> Vec<String> {
+        let handle = Arc::new(TailHandle::default());
+        if active {
+            handle.enable_for(5);
+        }
+        let (layer, mut rx) = TailLayer::new(handle);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, emit);
+        let mut lines = Vec::new();
+        while let Ok(line) = rx.try_recv() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[test]
+    fn test_layer_forwards_warn_and_error_when_active() {
+        let lines = collect(true, || {
+            tracing::info!("just info");
+            tracing::warn!(sender = "+1", "Rate limited");
+            tracing::error!("Signal send failed");
+        });
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("WARN"));
+        assert!(lines[0].contains("Rate limited sender=+1"));
+        assert!(lines[1].starts_with("ERROR"));
+    }
+
+    #[test]
+    fn test_layer_silent_when_inactive() {
+        let lines = collect(false, || tracing::error!("boom"));
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn test_handle_enable_disable() {
+        let handle = TailHandle::default();
+        assert!(!handle.is_active());
+        handle.enable_for(10);
+        assert!(handle.is_active());
+        assert!(handle.remaining_secs() > 590);
+        handle.disable();
+        assert!(!handle.is_active());
+        assert_eq!(handle.remaining_secs(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_flush_batch_sends_to_admin() {
+        use crate::traits::{MockClaudeRunner, MockSignalApi};
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_send_msg()
+            .withf(|to, msg| to == "+1234567890" && msg.contains("ERROR ccchat: boom"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let state = crate::state::tests::test_state_with(signal, MockClaudeRunner::new());
+        let (tx, mut rx) = mpsc::channel(4);
+        tx.try_send("ERROR ccchat: boom".to_string()).unwrap();
+        assert_eq!(flush_batch(&state, &mut rx).await, 1);
+        // Nothing buffered: no message.
+        assert_eq!(flush_batch(&state, &mut rx).await, 0);
+    }
+
+    #[test]
+    fn test_format_batch_truncates() {
+        let lines: Vec<String> = (0..25).map(|i| format!("WARN x: {i}")).collect();
+        let msg = format_batch(&lines, 3);
+        assert!(msg.starts_with("[tail] 28 event(s)"));
+        assert!(msg.contains("WARN x: 0"));
+        assert!(!msg.contains("WARN x: 24"));
+        assert!(msg.ends_with("(+8 more not shown)"));
+    }
+}
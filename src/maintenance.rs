@@ -0,0 +1,474 @@
+//! Nightly housekeeping (`--maintenance-at`, `--maintenance-tasks`).
+//!
+//! Once a day, at a quiet local time (03:30 by default), ccchat tidies up
+//! after itself: prompts nobody answered are dropped, conversation history
+//! past its retention is purged, stale files in the temp directory go, old
+//! sent-message hashes are forgotten, the databases are vacuumed, each
+//! sender's summaries are trimmed to the newest few, kept attachments past
+//! their retention are evicted and the data directory is snapshotted. The
+//! admin can run any of it on demand with `/maintenance run`; how each task
+//! last went shows in `/maintenance` and the stats endpoint.
+
+use chrono::{NaiveDateTime, NaiveTime};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+use crate::constants::{
+    CARRY_TTL_SECS, CHOICE_TTL_SECS, CONFIRM_COMMAND_TTL_SECS, CONFIRM_TTL_SECS,
+    MESSAGE_RETENTION_DAYS, SENT_HASH_TTL_SECS, SUMMARIES_KEPT, TMP_DIR, TMP_MAX_AGE_SECS,
+};
+use crate::state::State;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, clap::ValueEnum)]
+pub(crate) enum Task {
+    /// Drop expired prompts and purge history past its retention.
+    Ttl,
+    /// Remove stale files from the temp directory.
+    Tmp,
+    /// Forget hashes of long-sent messages.
+    Hashes,
+    /// VACUUM the databases.
+    Vacuum,
+    /// Keep only each sender's newest summaries.
+    Summaries,
+    /// Evict kept attachments past their retention or size limit.
+    Attachments,
+    /// Upload an encrypted archive (needs `--archive-bucket`).
+    Archive,
+    /// Snapshot the data directory into `--backup-dir`.
+    Backup,
+}
+
+impl Task {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Task::Ttl => "ttl",
+            Task::Tmp => "tmp",
+            Task::Hashes => "hashes",
+            Task::Vacuum => "vacuum",
+            Task::Summaries => "summaries",
+            Task::Attachments => "attachments",
+            Task::Archive => "archive",
+            Task::Backup => "backup",
+        }
+    }
+}
+
+/// How a task went the last time it ran.
+#[derive(Clone, Debug)]
+pub(crate) struct TaskRun {
+    /// Epoch seconds the run started.
+    pub(crate) at: i64,
+    pub(crate) took_ms: u64,
+    /// What was done, or what went wrong.
+    pub(crate) outcome: Result<String, String>,
+}
+
+/// `--maintenance-at`: a local `HH:MM`, or `off`.
+pub(crate) fn parse_at(s: &str) -> Result<Option<NaiveTime>, String> {
+    if s.eq_ignore_ascii_case("off") || s.is_empty() {
+        return Ok(None);
+    }
+    NaiveTime::parse_from_str(s, "%H:%M")
+        .map(Some)
+        .map_err(|_| format!("expected HH:MM or off, got {s:?}"))
+}
+
+/// Time from `now` (local) until the next `at`.
+pub(crate) fn until_next(at: NaiveTime, now: NaiveDateTime) -> Duration {
+    let mut next = now.date().and_time(at);
+    if next <= now {
+        next += chrono::TimeDelta::days(1);
+    }
+    (next - now).to_std().unwrap_or_default()
+}
+
+/// Run `tasks` in order, recording how each went.
+pub(crate) async fn run(state: &State, tasks: &[Task]) -> Vec<(Task, TaskRun)> {
+    let mut runs = Vec::new();
+    for &task in tasks {
+        let at = crate::helpers::epoch_now();
+        let started = Instant::now();
+        let outcome = run_task(state, task).await;
+        let run = TaskRun {
+            at,
+            took_ms: started.elapsed().as_millis() as u64,
+            outcome,
+        };
+        match &run.outcome {
+            Ok(detail) => info!(
+                task = task.as_str(),
+                took_ms = run.took_ms,
+                "Maintenance: {detail}"
+            ),
+            Err(e) => warn!(task = task.as_str(), "Maintenance failed: {e}"),
+        }
+        state.maintenance.insert(task, run.clone());
+        runs.push((task, run));
+    }
+    runs
+}
+
+async fn run_task(state: &State, task: Task) -> Result<String, String> {
+    match task {
+        Task::Ttl => {
+            let expired = expire_prompts(state);
+            let dir = crate::memory::memory_dir();
+            let purged = blocking(move || {
+                Ok(db_files(&dir)?
+                    .iter()
+                    .filter_map(|path| Connection::open(path).ok())
+                    .map(|conn| crate::memory::purge_old_messages(&conn, MESSAGE_RETENTION_DAYS))
+                    .sum::<usize>())
+            })
+            .await?;
+            Ok(format!(
+                "{expired} expired prompt(s) dropped, {purged} message(s) older than {MESSAGE_RETENTION_DAYS} days purged"
+            ))
+        }
+        Task::Tmp => {
+            let (removed, bytes) =
+                blocking(|| sweep_dir(Path::new(TMP_DIR), Duration::from_secs(TMP_MAX_AGE_SECS)))
+                    .await?;
+            Ok(format!(
+                "{removed} stale file(s) removed, {} KB freed",
+                bytes / 1024
+            ))
+        }
+        Task::Hashes => {
+            let before = state.sent_hashes.len();
+            let ttl = Duration::from_secs(SENT_HASH_TTL_SECS);
+            state.sent_hashes.retain(|_, sent| sent.elapsed() < ttl);
+            Ok(format!(
+                "{} old hash(es) pruned, {} kept",
+                before - state.sent_hashes.len(),
+                state.sent_hashes.len()
+            ))
+        }
+        Task::Vacuum => {
+            let root = crate::memory::config_dir();
+            let memories = crate::memory::memory_dir();
+            let (count, freed) = blocking(move || {
+                let mut count = 0;
+                let mut freed = 0;
+                for path in db_files(&root)?.into_iter().chain(db_files(&memories)?) {
+                    freed += vacuum(&path)?;
+                    count += 1;
+                }
+                Ok((count, freed))
+            })
+            .await?;
+            Ok(format!(
+                "{count} database(s) vacuumed, {} KB reclaimed",
+                freed / 1024
+            ))
+        }
+        Task::Summaries => {
+            let dir = crate::memory::memory_dir();
+            let deleted = blocking(move || {
+                Ok(db_files(&dir)?
+                    .iter()
+                    .map(|path| compact_summaries(path, SUMMARIES_KEPT))
+                    .sum::<usize>())
+            })
+            .await?;
+            Ok(format!(
+                "{deleted} old summar(ies) deleted, newest {SUMMARIES_KEPT} kept per sender"
+            ))
+        }
+        Task::Attachments => {
+            let config = Arc::clone(&state.config);
+            let (removed, bytes) =
+                blocking(move || crate::attachments::evict(&config.attachment_retention)).await?;
+            let (files, total) = crate::attachments::usage();
+            Ok(format!(
+                "{removed} attachment(s) evicted, {} KB freed; {files} kept ({} KB)",
+                bytes / 1024,
+                total / 1024
+            ))
+        }
+        Task::Archive => {
+            let Some(config) = &state.config.archive else {
+                return Err("no archive bucket configured (--archive-bucket)".to_string());
+            };
+            let root = crate::memory::config_dir();
+            crate::archive::push(
+                &state.http,
+                config,
+                &root,
+                crate::constants::BUNDLE_KDF_ITERATIONS,
+            )
+            .await
+            .map(|(key, files)| format!("uploaded {key} ({files} files)"))
+            .map_err(|e| e.to_string())
+        }
+        Task::Backup => {
+            let config = Arc::clone(&state.config);
+            let root = crate::memory::config_dir();
+            blocking(move || {
+                crate::backup::snapshot(
+                    &root,
+                    &config.backup,
+                    crate::constants::BUNDLE_KDF_ITERATIONS,
+                    chrono::Utc::now(),
+                )
+                .map(|(path, files)| format!("wrote {} ({files} files)", path.display()))
+                .map_err(|e| e.to_string())
+            })
+            .await
+        }
+    }
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("task panicked: {e}"))?
+}
+
+/// Drop prompts (`/confirm`, numbered choices, "are you sure?", `/carry`)
+/// that can no longer be answered; returns how many went.
+fn expire_prompts(state: &State) -> usize {
+    let alive = |at: &Instant, ttl: u64| at.elapsed() < Duration::from_secs(ttl);
+    let count = |state: &State| {
+        state.pending_confirms.len()
+            + state.pending_choices.len()
+            + state.pending_commands.len()
+            + state.pending_carries.len()
+    };
+    let before = count(state);
+    state
+        .pending_confirms
+        .retain(|_, p| alive(&p.asked_at, CONFIRM_TTL_SECS));
+    state
+        .pending_choices
+        .retain(|_, c| alive(&c.offered_at, CHOICE_TTL_SECS));
+    state
+        .pending_commands
+        .retain(|_, c| alive(&c.asked_at, CONFIRM_COMMAND_TTL_SECS));
+    state
+        .pending_carries
+        .retain(|_, (_, _, at)| alive(at, CARRY_TTL_SECS));
+    before - count(state)
+}
+
+/// SQLite databases directly in `dir`.
+fn db_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("{}: {e}", dir.display())),
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "db"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Remove entries in `dir` untouched for longer than `max_age`; returns how
+/// many went and their size in bytes.
+pub(crate) fn sweep_dir(dir: &Path, max_age: Duration) -> Result<(usize, u64), String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(e) => return Err(format!("{}: {e}", dir.display())),
+    };
+    let now = SystemTime::now();
+    let (mut removed, mut bytes) = (0, 0);
+    for entry in entries.filter_map(|e| e.ok()) {
+        let Ok(meta) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        let stale = meta
+            .modified()
+            .ok()
+            .and_then(|m| now.duration_since(m).ok())
+            .is_some_and(|age| age > max_age);
+        if !stale {
+            continue;
+        }
+        let gone = if meta.is_dir() {
+            std::fs::remove_dir_all(entry.path())
+        } else {
+            std::fs::remove_file(entry.path())
+        };
+        if gone.is_ok() {
+            removed += 1;
+            bytes += meta.len();
+        }
+    }
+    Ok((removed, bytes))
+}
+
+/// VACUUM the database at `path`; returns the bytes it shrank by.
+pub(crate) fn vacuum(path: &Path) -> Result<u64, String> {
+    let size = || std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let before = size();
+    let conn = Connection::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    conn.execute_batch("VACUUM;")
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    drop(conn);
+    Ok(before.saturating_sub(size()))
+}
+
+/// Delete all but the newest `keep` summaries in the memory database at
+/// `path`; returns how many went.
+pub(crate) fn compact_summaries(path: &Path, keep: usize) -> usize {
+    let Ok(conn) = Connection::open(path) else {
+        return 0;
+    };
+    conn.execute(
+        "DELETE FROM summaries WHERE id NOT IN
+            (SELECT id FROM summaries ORDER BY timestamp DESC, id DESC LIMIT ?1)",
+        rusqlite::params![keep as i64],
+    )
+    .unwrap_or(0)
+}
+
+/// The schedule and each task's last run, for `/maintenance`.
+pub(crate) fn describe(state: &State) -> String {
+    let schedule = match state.config.maintenance_at {
+        Some(at) => format!(
+            "Maintenance runs daily at {} ({})",
+            at.format("%H:%M"),
+            task_list(&state.config.maintenance_tasks)
+        ),
+        None => "Scheduled maintenance is off (--maintenance-at off)".to_string(),
+    };
+    let mut runs: Vec<_> = state
+        .maintenance
+        .iter()
+        .map(|e| (*e.key(), e.value().clone()))
+        .collect();
+    if runs.is_empty() {
+        return format!("{schedule}\nNo task has run yet.");
+    }
+    runs.sort_by_key(|(task, _)| *task);
+    let mut lines = vec![schedule];
+    lines.extend(runs.iter().map(|(task, run)| describe_run(*task, run)));
+    lines.join("\n")
+}
+
+pub(crate) fn describe_run(task: Task, run: &TaskRun) -> String {
+    let when = crate::memory::format_epoch(run.at.max(0) as u64);
+    match &run.outcome {
+        Ok(detail) => format!(
+            "{}: ok at {when} ({}ms) - {detail}",
+            task.as_str(),
+            run.took_ms
+        ),
+        Err(e) => format!("{}: FAILED at {when} - {e}", task.as_str()),
+    }
+}
+
+pub(crate) fn task_list(tasks: &[Task]) -> String {
+    tasks
+        .iter()
+        .map(|t| t.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Each task's last run, for the stats endpoint.
+pub(crate) fn stats_json(state: &State) -> serde_json::Value {
+    let tasks: serde_json::Map<String, serde_json::Value> = state
+        .maintenance
+        .iter()
+        .map(|e| {
+            let run = e.value();
+            (
+                e.key().as_str().to_string(),
+                serde_json::json!({
+                    "last_run": run.at,
+                    "took_ms": run.took_ms,
+                    "ok": run.outcome.is_ok(),
+                    "detail": match &run.outcome {
+                        Ok(detail) => detail,
+                        Err(e) => e,
+                    },
+                }),
+            )
+        })
+        .collect();
+    serde_json::json!({
+        "at": state.config.maintenance_at.map(|at| at.format("%H:%M").to_string()),
+        "tasks": tasks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ccchat_maint_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_schedule() {
+        assert_eq!(parse_at("off"), Ok(None));
+        assert!(parse_at("25:00").is_err());
+        let at = parse_at("03:30").unwrap().unwrap();
+        let day = chrono::NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let before = day.and_hms_opt(1, 0, 0).unwrap();
+        assert_eq!(until_next(at, before), Duration::from_secs(2 * 3600 + 1800));
+        let after = day.and_hms_opt(3, 30, 0).unwrap();
+        assert_eq!(until_next(at, after), Duration::from_secs(24 * 3600));
+    }
+
+    #[test]
+    fn test_sweep_dir_removes_only_stale_entries() {
+        let dir = scratch();
+        std::fs::write(dir.join("old.jpg"), b"12345").unwrap();
+        std::fs::create_dir(dir.join("olddir")).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        std::fs::write(dir.join("new.jpg"), b"1").unwrap();
+        let (removed, bytes) = sweep_dir(&dir, Duration::from_millis(25)).unwrap();
+        assert_eq!(removed, 2);
+        assert!(bytes >= 5);
+        assert!(dir.join("new.jpg").exists());
+        assert!(!dir.join("old.jpg").exists());
+        assert_eq!(sweep_dir(&dir.join("missing"), Duration::ZERO), Ok((0, 0)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compact_summaries_and_vacuum() {
+        let dir = scratch();
+        let path = dir.join("sender.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE summaries (id INTEGER PRIMARY KEY, summary TEXT, timestamp INTEGER)",
+        )
+        .unwrap();
+        for i in 0..10 {
+            conn.execute(
+                "INSERT INTO summaries (summary, timestamp) VALUES (?1, ?2)",
+                rusqlite::params![format!("summary {i} {}", "x".repeat(4000)), i],
+            )
+            .unwrap();
+        }
+        drop(conn);
+        assert_eq!(compact_summaries(&path, 3), 7);
+        let conn = Connection::open(&path).unwrap();
+        let kept = crate::memory::messages::get_recent_summaries(&conn, 10);
+        assert_eq!(
+            kept.iter().map(|(_, ts)| *ts).collect::<Vec<_>>(),
+            [9, 8, 7]
+        );
+        drop(conn);
+        assert!(vacuum(&path).unwrap() > 0);
+        assert_eq!(db_files(&dir).unwrap(), [path]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
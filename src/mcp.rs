@@ -0,0 +1,429 @@
+//! `ccchat mcp-serve`: ccchat as an MCP server, so Claude Code sessions on
+//! the desktop can reach you over Signal.
+//!
+//! Speaks the Model Context Protocol over stdio (JSON-RPC, one message per
+//! line) and offers three tools: `send_message` to any number or group,
+//! `notify` for a note to yourself, and `read_messages` for what has arrived
+//! since, which can wait for an answer. Messages are read from the account's
+//! signal-cli-api WebSocket for as long as the server runs; anything this
+//! server sent itself is left out when it echoes back.
+
+use dashmap::DashMap;
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::Notify;
+
+use crate::constants::{MCP_INBOX_MAX, MCP_MAX_WAIT_SECS, MCP_NOTIFY_PREFIX};
+use crate::error::AppError;
+use crate::signal::ParsedEnvelope;
+use crate::traits::SignalApi;
+use crate::transport::Transport;
+
+/// Protocol version offered when the client doesn't name one.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// A message that arrived while the server was running.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct Received {
+    /// Number or `group.<id>` to answer with `send_message`.
+    chat: String,
+    from: String,
+    name: String,
+    text: String,
+    /// Signal timestamp (ms).
+    sent_at: Option<i64>,
+}
+
+pub(crate) struct Server {
+    api: Box<dyn SignalApi>,
+    account: String,
+    /// Unread messages, oldest first.
+    inbox: Mutex<VecDeque<Received>>,
+    arrived: Notify,
+    /// Hashes of texts this server sent, to drop their sync echoes.
+    sent: DashMap<u64, ()>,
+    connected: AtomicBool,
+}
+
+impl Server {
+    pub(crate) fn new(api: Box<dyn SignalApi>, account: String) -> Self {
+        Self {
+            api,
+            account,
+            inbox: Mutex::new(VecDeque::new()),
+            arrived: Notify::new(),
+            sent: DashMap::new(),
+            connected: AtomicBool::new(false),
+        }
+    }
+
+    /// File an inbound message as unread, unless it's our own echo.
+    fn receive(&self, envelope: ParsedEnvelope) {
+        let hash = crate::helpers::hash_message(&envelope.message_text);
+        if envelope.is_sync && self.sent.remove(&hash).is_some() {
+            return;
+        }
+        if envelope.message_text.trim().is_empty() {
+            return;
+        }
+        let chat = match &envelope.group_id {
+            Some(group_id) => crate::helpers::group_chat_id(group_id),
+            None if envelope.is_sync => self.account.clone(),
+            None => envelope.source.clone(),
+        };
+        let mut inbox = self.inbox.lock().unwrap_or_else(|e| e.into_inner());
+        if inbox.len() >= MCP_INBOX_MAX {
+            inbox.pop_front();
+        }
+        inbox.push_back(Received {
+            chat,
+            from: envelope.source,
+            name: envelope.source_name,
+            text: envelope.message_text,
+            sent_at: envelope.sent_at,
+        });
+        drop(inbox);
+        self.arrived.notify_waiters();
+    }
+
+    /// Unread messages (optionally only from `from`), marking them read.
+    fn take(&self, from: Option<&str>, limit: usize) -> Vec<Received> {
+        let mut inbox = self.inbox.lock().unwrap_or_else(|e| e.into_inner());
+        let mut taken = Vec::new();
+        let mut kept = VecDeque::new();
+        for msg in inbox.drain(..) {
+            let wanted = from.is_none_or(|f| msg.from == f || msg.chat == f);
+            if wanted && taken.len() < limit {
+                taken.push(msg);
+            } else {
+                kept.push_back(msg);
+            }
+        }
+        *inbox = kept;
+        taken
+    }
+
+    async fn send(&self, recipient: &str, text: &str) -> Result<(), AppError> {
+        self.sent.insert(crate::helpers::hash_message(text), ());
+        self.api.send_msg(recipient, text).await
+    }
+
+    async fn read_messages(&self, args: &Value) -> Result<String, String> {
+        let from = args["from"].as_str().filter(|f| !f.is_empty());
+        let limit = args["limit"].as_u64().unwrap_or(10).max(1) as usize;
+        let wait = Duration::from_secs(
+            args["wait_seconds"]
+                .as_u64()
+                .unwrap_or(0)
+                .min(MCP_MAX_WAIT_SECS),
+        );
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            // Register before checking, so a message landing in between
+            // still wakes us.
+            let arrived = self.arrived.notified();
+            let messages = self.take(from, limit);
+            if !messages.is_empty() {
+                return serde_json::to_string_pretty(&messages).map_err(|e| e.to_string());
+            }
+            if tokio::time::timeout_at(deadline, arrived).await.is_err() {
+                break;
+            }
+        }
+        if !self.connected.load(Ordering::Relaxed) {
+            return Err("Not connected to signal-cli-api, so no messages can arrive. Is it running at the --api-url given?".to_string());
+        }
+        Ok("No new messages.".to_string())
+    }
+
+    async fn call_tool(&self, name: &str, args: &Value) -> Result<String, String> {
+        let text = |key: &str| {
+            args[key]
+                .as_str()
+                .filter(|s| !s.trim().is_empty())
+                .ok_or_else(|| format!("`{key}` is required"))
+        };
+        match name {
+            "send_message" => {
+                let recipient = text("recipient")?;
+                self.send(recipient, text("text")?)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok(format!("Sent to {recipient}."))
+            }
+            "notify" => {
+                let body = format!("{MCP_NOTIFY_PREFIX}{}", text("text")?);
+                self.send(&self.account, &body)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok("Notification sent.".to_string())
+            }
+            "read_messages" => self.read_messages(args).await,
+            _ => Err(format!("Unknown tool: {name}")),
+        }
+    }
+
+    /// Answer one JSON-RPC message; notifications get no answer.
+    pub(crate) async fn handle(&self, request: &Value) -> Option<Value> {
+        let id = request.get("id")?.clone();
+        let params = &request["params"];
+        let result = match request["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "protocolVersion": params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+                "capabilities": {"tools": {}},
+                "serverInfo": {"name": "ccchat", "version": env!("CARGO_PKG_VERSION")},
+            }),
+            "ping" => json!({}),
+            "tools/list" => json!({"tools": tools()}),
+            "tools/call" => {
+                let name = params["name"].as_str().unwrap_or_default();
+                let outcome = self.call_tool(name, &params["arguments"]).await;
+                let is_error = outcome.is_err();
+                let text = outcome.unwrap_or_else(|e| e);
+                json!({"content": [{"type": "text", "text": text}], "isError": is_error})
+            }
+            method => {
+                return Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {"code": -32601, "message": format!("Method not found: {method}")},
+                }))
+            }
+        };
+        Some(json!({"jsonrpc": "2.0", "id": id, "result": result}))
+    }
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "send_message",
+            "description": "Send a Signal message to a phone number (+E164) or a group (group.<id>, as shown by read_messages).",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "recipient": {"type": "string", "description": "Phone number or group.<id>"},
+                    "text": {"type": "string"},
+                },
+                "required": ["recipient", "text"],
+            },
+        },
+        {
+            "name": "notify",
+            "description": "Send the user a notification on their phone (Signal Note to Self), e.g. when a long task finishes or needs their attention.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {"text": {"type": "string"}},
+                "required": ["text"],
+            },
+        },
+        {
+            "name": "read_messages",
+            "description": "Read Signal messages received since the last read, oldest first. To ask the user something, notify them with the question, then call this with wait_seconds to wait for their answer.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "from": {"type": "string", "description": "Only messages from this number or group.<id>"},
+                    "limit": {"type": "integer", "description": "Most messages to return (default 10)"},
+                    "wait_seconds": {"type": "integer", "description": format!("If nothing is unread, wait this long for a message (at most {MCP_MAX_WAIT_SECS})")},
+                },
+            },
+        },
+    ])
+}
+
+/// Read messages from signal-cli-api into the inbox, reconnecting with
+/// backoff until the server exits.
+async fn listen(server: Arc<Server>, net: crate::net::NetConfig, api_url: String) {
+    let url = Transport::Signal.receive_url(&api_url, &server.account);
+    let mut backoff = 1u64;
+    loop {
+        match net.connect_ws(&url).await {
+            Ok(ws) => {
+                server.connected.store(true, Ordering::Relaxed);
+                backoff = 1;
+                let (_, mut read) = ws.split();
+                while let Some(Ok(msg)) = read.next().await {
+                    let Ok(text) = msg.into_text() else {
+                        continue;
+                    };
+                    let Ok(event) = serde_json::from_str::<Value>(&text) else {
+                        continue;
+                    };
+                    if let Some(envelope) = Transport::Signal.parse_event(&event, &server.account) {
+                        server.receive(envelope);
+                    }
+                }
+                server.connected.store(false, Ordering::Relaxed);
+            }
+            Err(e) => tracing::debug!("mcp-serve: cannot connect to {url}: {e}"),
+        }
+        tokio::time::sleep(Duration::from_secs(backoff)).await;
+        backoff = (backoff * 2).min(60);
+    }
+}
+
+/// Serve MCP on stdin/stdout until stdin closes.
+pub(crate) async fn serve(
+    server: Server,
+    net: crate::net::NetConfig,
+    api_url: String,
+) -> Result<(), AppError> {
+    let server = Arc::new(server);
+    tokio::spawn(listen(Arc::clone(&server), net, api_url));
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => server.handle(&request).await,
+            Err(e) => Some(json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {"code": -32700, "message": format!("Parse error: {e}")},
+            })),
+        };
+        if let Some(response) = response {
+            stdout.write_all(format!("{response}\n").as_bytes()).await?;
+            stdout.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::MockSignalApi;
+
+    fn envelope(source: &str, text: &str, is_sync: bool) -> ParsedEnvelope {
+        ParsedEnvelope {
+            source: source.to_string(),
+            message_text: text.to_string(),
+            is_sync,
+            source_uuid: String::new(),
+            source_name: "Alice".to_string(),
+            attachments: Vec::new(),
+            group_id: None,
+            thread_id: None,
+            sent_at: Some(7),
+        }
+    }
+
+    fn call(name: &str, arguments: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call",
+               "params": {"name": name, "arguments": arguments}})
+    }
+
+    #[tokio::test]
+    async fn test_initialize_and_list_tools() {
+        let server = Server::new(Box::new(MockSignalApi::new()), "+1".to_string());
+        let init = json!({"jsonrpc": "2.0", "id": 0, "method": "initialize",
+                          "params": {"protocolVersion": "2025-03-26"}});
+        let resp = server.handle(&init).await.unwrap();
+        assert_eq!(resp["result"]["protocolVersion"], "2025-03-26");
+        assert_eq!(resp["result"]["serverInfo"]["name"], "ccchat");
+
+        let list = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"});
+        let resp = server.handle(&list).await.unwrap();
+        let names: Vec<&str> = resp["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|t| t["name"].as_str())
+            .collect();
+        assert_eq!(names, ["send_message", "notify", "read_messages"]);
+
+        let note = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        assert!(server.handle(&note).await.is_none());
+        let unknown = json!({"jsonrpc": "2.0", "id": 2, "method": "resources/list"});
+        assert_eq!(
+            server.handle(&unknown).await.unwrap()["error"]["code"],
+            -32601
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notify_and_send() {
+        let mut mock = MockSignalApi::new();
+        mock.expect_send_msg()
+            .withf(|to, text| to == "+1" && text == "🔔 Claude Code: build finished")
+            .times(1)
+            .returning(|_, _| Ok(()));
+        mock.expect_send_msg()
+            .withf(|to, text| to == "+2" && text == "hi")
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let server = Server::new(Box::new(mock), "+1".to_string());
+
+        let resp = server
+            .handle(&call("notify", json!({"text": "build finished"})))
+            .await
+            .unwrap();
+        assert_eq!(resp["result"]["isError"], false);
+        server
+            .handle(&call(
+                "send_message",
+                json!({"recipient": "+2", "text": "hi"}),
+            ))
+            .await
+            .unwrap();
+        let resp = server
+            .handle(&call("send_message", json!({"recipient": "+2"})))
+            .await
+            .unwrap();
+        assert_eq!(resp["result"]["isError"], true);
+        assert_eq!(resp["result"]["content"][0]["text"], "`text` is required");
+
+        // Our own notification echoes back as a sync message and is dropped;
+        // the user's answer in Note to Self is kept.
+        server.receive(envelope("+1", "🔔 Claude Code: build finished", true));
+        server.receive(envelope("+1", "ship it", true));
+        let messages = server.take(None, 10);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].chat, "+1");
+        assert_eq!(messages[0].text, "ship it");
+    }
+
+    #[tokio::test]
+    async fn test_read_messages_marks_read_and_waits() {
+        let server = Arc::new(Server::new(
+            Box::new(MockSignalApi::new()),
+            "+1".to_string(),
+        ));
+        server.connected.store(true, Ordering::Relaxed);
+        server.receive(envelope("+2", "first", false));
+        server.receive(envelope("+3", "second", false));
+
+        let text = server.read_messages(&json!({"from": "+3"})).await.unwrap();
+        assert!(text.contains("second") && !text.contains("first"));
+        assert!(server
+            .read_messages(&json!({}))
+            .await
+            .unwrap()
+            .contains("first"));
+        assert_eq!(
+            server.read_messages(&json!({})).await.unwrap(),
+            "No new messages."
+        );
+
+        let waiter = {
+            let server = Arc::clone(&server);
+            tokio::spawn(async move { server.read_messages(&json!({"wait_seconds": 5})).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        server.receive(envelope("+2", "answer", false));
+        assert!(waiter.await.unwrap().unwrap().contains("answer"));
+
+        server.connected.store(false, Ordering::Relaxed);
+        assert!(server.read_messages(&json!({})).await.is_err());
+    }
+}
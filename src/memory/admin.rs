@@ -3,11 +3,14 @@ use tracing::error;
 use super::context::{delete_memory, format_epoch};
 use super::messages::{
     get_message_count, get_oldest_message_ts, get_recent_summaries, get_summary_count,
-    search_memory,
+    list_feedback, search_memory,
 };
-use super::schema::open_memory_db;
+use super::schema::{memory_dir, open_memory_db};
 
 pub(crate) fn memory_status(sender: &str) -> String {
+    if super::lock::is_locked(sender) {
+        return super::lock::LOCKED_NOTICE.to_string();
+    }
     let conn = match open_memory_db(sender) {
         Ok(c) => c,
         Err(_) => {
@@ -44,6 +47,9 @@ pub(crate) fn memory_status(sender: &str) -> String {
         }
     }
 
+    if super::lock::has_lock(sender) {
+        out.push_str("\n\nLocked with your passphrase; /memory lock off stores it plainly again.");
+    }
     out.push_str("\n\nUse /forget to clear all memory.");
     out
 }
@@ -63,6 +69,10 @@ pub(crate) fn search_memory_formatted(
 }
 
 pub(crate) fn forget_with_counts(sender: &str) -> String {
+    if super::lock::is_locked(sender) {
+        delete_memory(sender);
+        return "Locked memory deleted. Next conversation starts completely fresh.".to_string();
+    }
     let (msg_count, sum_count) = match open_memory_db(sender) {
         Ok(conn) => (get_message_count(&conn), get_summary_count(&conn)),
         Err(_) => (0, 0),
@@ -77,6 +87,44 @@ pub(crate) fn forget_with_counts(sender: &str) -> String {
     }
 }
 
+/// Every rated reply as JSON lines, for `ccchat feedback export`, and how
+/// many there are. Each line's `chat` is the memory file name (a hash of the
+/// chat id), so the export doesn't carry phone numbers.
+pub(crate) fn export_feedback() -> (String, usize) {
+    let Ok(entries) = std::fs::read_dir(memory_dir()) else {
+        return (String::new(), 0);
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "db"))
+        .collect();
+    paths.sort();
+    let mut out = String::new();
+    let mut count = 0;
+    for path in paths {
+        let Ok(conn) = rusqlite::Connection::open(&path) else {
+            continue;
+        };
+        let chat = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for feedback in list_feedback(&conn) {
+            let mut line = serde_json::json!({ "chat": chat });
+            if let (Some(obj), Ok(serde_json::Value::Object(fields))) =
+                (line.as_object_mut(), serde_json::to_value(&feedback))
+            {
+                obj.extend(fields);
+            }
+            out.push_str(&line.to_string());
+            out.push('\n');
+            count += 1;
+        }
+    }
+    (out, count)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
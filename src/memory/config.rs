@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use tracing::error;
 
+use crate::ratelimit::RateClass;
+
 #[derive(Serialize, Deserialize, Default)]
 pub(crate) struct PersistedAllowed {
     pub(crate) allowed: Vec<AllowedEntry>,
@@ -9,6 +11,54 @@ pub(crate) struct PersistedAllowed {
     pub(crate) system_prompt: Option<String>,
     #[serde(default)]
     pub(crate) sender_prompts: Option<std::collections::HashMap<String, String>>,
+    /// Group chat id (`group.<id>`) -> project directory.
+    #[serde(default)]
+    pub(crate) group_projects: Option<std::collections::HashMap<String, String>>,
+    /// Group chat id -> `/group set` settings.
+    #[serde(default)]
+    pub(crate) group_settings:
+        Option<std::collections::HashMap<String, crate::group_settings::GroupSettings>>,
+    /// Sender id -> per-class rate limits (e.g. `claude: 20/hour`).
+    #[serde(default)]
+    pub(crate) sender_rate_limits: Option<std::collections::HashMap<String, SenderRateLimits>>,
+    /// Load, memory and disk thresholds checked before each Claude run.
+    #[serde(default)]
+    pub(crate) resource_limits: Option<crate::resources::ResourceLimits>,
+    /// Anthropic credentials to rotate through when one hits a limit.
+    #[serde(default)]
+    pub(crate) claude_accounts: Option<Vec<crate::keys::AccountEntry>>,
+    /// Sticker pack and the sticker sent for each reply status.
+    #[serde(default)]
+    pub(crate) stickers: Option<crate::stickers::StickerConfig>,
+    /// `/config set` changes (setting key -> value).
+    #[serde(default)]
+    pub(crate) settings: Option<std::collections::BTreeMap<String, String>>,
+    /// Short model names (alias -> model id).
+    #[serde(default)]
+    pub(crate) model_aliases: Option<std::collections::BTreeMap<String, String>>,
+    /// Rules picking a model per message, first match wins.
+    #[serde(default)]
+    pub(crate) model_routes: Option<Vec<crate::router::RouteRule>>,
+    /// Outgoing webhooks beyond `--webhook-url`.
+    #[serde(default)]
+    pub(crate) webhooks: Option<Vec<crate::webhook::Hook>>,
+    /// Day's spend (USD) that fires the `budget_threshold` webhook.
+    #[serde(default)]
+    pub(crate) webhook_daily_budget_usd: Option<f64>,
+    /// Unused `/invite` codes -> when they were made (epoch secs).
+    #[serde(default)]
+    pub(crate) invites: Option<std::collections::BTreeMap<String, i64>>,
+    /// Allowed senders who came in with an invite code (restricted).
+    #[serde(default)]
+    pub(crate) guests: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub(crate) struct SenderRateLimits {
+    #[serde(default)]
+    pub(crate) claude: Option<String>,
+    #[serde(default)]
+    pub(crate) command: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -18,14 +68,23 @@ pub(crate) struct AllowedEntry {
     pub(crate) name: String,
 }
 
+/// Where ccchat keeps its state: `CCCHAT_DATA_DIR`, or `~/.config/ccchat`.
 pub(crate) fn config_dir() -> PathBuf {
-    let dir = std::env::var("HOME")
+    let dir = match std::env::var_os("CCCHAT_DATA_DIR").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => default_config_dir(),
+    };
+    let _ = std::fs::create_dir_all(&dir);
+    dir
+}
+
+/// `~/.config/ccchat`, where `ccchat.env` always lives.
+pub(crate) fn default_config_dir() -> PathBuf {
+    std::env::var("HOME")
         .map(PathBuf::from)
         .unwrap_or_else(|_| PathBuf::from("."))
         .join(".config")
-        .join("ccchat");
-    let _ = std::fs::create_dir_all(&dir);
-    dir
+        .join("ccchat")
 }
 
 pub(crate) fn allowed_file_path() -> PathBuf {
@@ -71,6 +130,223 @@ pub(crate) fn persist_revoke(id: &str) {
     save_persisted_allowed(&data);
 }
 
+pub(crate) fn persist_group_project(chat_id: &str, project_dir: &str) {
+    let mut data = load_persisted_allowed();
+    data.group_projects
+        .get_or_insert_with(Default::default)
+        .insert(chat_id.to_string(), project_dir.to_string());
+    save_persisted_allowed(&data);
+}
+
+/// Save the unused `/invite` codes.
+pub(crate) fn persist_invites(invites: &dashmap::DashMap<String, i64>) {
+    let mut data = load_persisted_allowed();
+    data.invites = Some(
+        invites
+            .iter()
+            .map(|e| (e.key().clone(), *e.value()))
+            .collect(),
+    );
+    save_persisted_allowed(&data);
+}
+
+/// Mark `id` as an invited guest, or lift that when `guest` is false.
+pub(crate) fn persist_guest(id: &str, guest: bool) {
+    let mut data = load_persisted_allowed();
+    let guests = data.guests.get_or_insert_with(Default::default);
+    guests.retain(|g| g != id);
+    if guest {
+        guests.push(id.to_string());
+    }
+    save_persisted_allowed(&data);
+}
+
+/// Save a group's `/group set` settings, or forget them when all are default.
+pub(crate) fn persist_group_settings(
+    chat_id: &str,
+    settings: &crate::group_settings::GroupSettings,
+) {
+    let mut data = load_persisted_allowed();
+    let saved = data.group_settings.get_or_insert_with(Default::default);
+    if *settings == Default::default() {
+        saved.remove(chat_id);
+    } else {
+        saved.insert(chat_id.to_string(), settings.clone());
+    }
+    save_persisted_allowed(&data);
+}
+
+/// Groups' settings saved by [`persist_group_settings`].
+pub(crate) fn load_group_settings(
+) -> std::collections::HashMap<String, crate::group_settings::GroupSettings> {
+    load_persisted_allowed().group_settings.unwrap_or_default()
+}
+
+/// Save a `/config set` change, or forget it when `value` is None.
+pub(crate) fn persist_setting(key: &str, value: Option<&str>) {
+    let mut data = load_persisted_allowed();
+    let settings = data.settings.get_or_insert_with(Default::default);
+    match value {
+        Some(value) => settings.insert(key.to_string(), value.to_string()),
+        None => settings.remove(key),
+    };
+    save_persisted_allowed(&data);
+}
+
+/// `/config set` changes saved by [`persist_setting`].
+pub(crate) fn load_saved_settings() -> std::collections::BTreeMap<String, String> {
+    load_persisted_allowed().settings.unwrap_or_default()
+}
+
+/// Group -> project bindings from the persisted list (`/bind`) and the config file.
+/// Config file entries win on conflict.
+pub(crate) fn load_group_projects(
+    config_path: Option<&str>,
+) -> std::collections::HashMap<String, PathBuf> {
+    let mut bindings: std::collections::HashMap<String, PathBuf> = load_persisted_allowed()
+        .group_projects
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(k, v)| (k, PathBuf::from(v)))
+        .collect();
+    if let Some(path) = config_path {
+        if let Ok(contents) = std::fs::read_to_string(path) {
+            let parsed: PersistedAllowed = if path.ends_with(".yaml") || path.ends_with(".yml") {
+                serde_yaml::from_str(&contents).unwrap_or_default()
+            } else {
+                serde_json::from_str(&contents).unwrap_or_default()
+            };
+            for (chat_id, dir) in parsed.group_projects.unwrap_or_default() {
+                bindings.insert(chat_id, PathBuf::from(dir));
+            }
+        }
+    }
+    bindings
+}
+
+/// Per-sender rate limit overrides from the config file, parsed into
+/// (capacity, refill/sec). Invalid entries are logged and skipped.
+pub(crate) fn load_sender_rate_limits(
+    config_path: Option<&str>,
+) -> std::collections::HashMap<(String, RateClass), (f64, f64)> {
+    let mut limits = std::collections::HashMap::new();
+    let Some(path) = config_path else {
+        return limits;
+    };
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return limits;
+    };
+    let parsed: PersistedAllowed = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&contents).unwrap_or_default()
+    } else {
+        serde_json::from_str(&contents).unwrap_or_default()
+    };
+    for (sender, entry) in parsed.sender_rate_limits.unwrap_or_default() {
+        for (class, spec) in [
+            (RateClass::Claude, entry.claude),
+            (RateClass::Command, entry.command),
+        ] {
+            let Some(spec) = spec else { continue };
+            match crate::helpers::parse_rate_limit(&spec) {
+                Some(limit) => {
+                    limits.insert((sender.clone(), class), limit);
+                }
+                None => error!(
+                    "Invalid {} rate limit for {sender}: {spec:?}",
+                    class.as_str()
+                ),
+            }
+        }
+    }
+    limits
+}
+
+/// Resource guardrails from the config file (none set if absent).
+pub(crate) fn load_resource_limits(config_path: Option<&str>) -> crate::resources::ResourceLimits {
+    let Some(path) = config_path else {
+        return Default::default();
+    };
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Default::default();
+    };
+    let parsed: PersistedAllowed = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&contents).unwrap_or_default()
+    } else {
+        serde_json::from_str(&contents).unwrap_or_default()
+    };
+    parsed.resource_limits.unwrap_or_default()
+}
+
+/// `claude_accounts` from the config file, in failover order.
+pub(crate) fn load_claude_accounts(config_path: Option<&str>) -> Vec<crate::keys::AccountEntry> {
+    let Some(path) = config_path else {
+        return Vec::new();
+    };
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let parsed: PersistedAllowed = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&contents).unwrap_or_default()
+    } else {
+        serde_json::from_str(&contents).unwrap_or_default()
+    };
+    parsed.claude_accounts.unwrap_or_default()
+}
+
+/// `stickers` from the config file (none configured if absent).
+pub(crate) fn load_stickers(config_path: Option<&str>) -> crate::stickers::StickerConfig {
+    let Some(path) = config_path else {
+        return Default::default();
+    };
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Default::default();
+    };
+    let parsed: PersistedAllowed = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&contents).unwrap_or_default()
+    } else {
+        serde_json::from_str(&contents).unwrap_or_default()
+    };
+    parsed.stickers.unwrap_or_default()
+}
+
+/// `webhooks` and `webhook_daily_budget_usd` from the config file.
+pub(crate) fn load_webhooks(config_path: Option<&str>) -> (Vec<crate::webhook::Hook>, Option<f64>) {
+    let Some(path) = config_path else {
+        return Default::default();
+    };
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Default::default();
+    };
+    let parsed: PersistedAllowed = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&contents).unwrap_or_default()
+    } else {
+        serde_json::from_str(&contents).unwrap_or_default()
+    };
+    (
+        parsed.webhooks.unwrap_or_default(),
+        parsed.webhook_daily_budget_usd,
+    )
+}
+
+/// `model_aliases` and `model_routes` from the config file.
+pub(crate) fn load_model_router(config_path: Option<&str>) -> crate::router::Router {
+    let Some(path) = config_path else {
+        return Default::default();
+    };
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Default::default();
+    };
+    let parsed: PersistedAllowed = if path.ends_with(".yaml") || path.ends_with(".yml") {
+        serde_yaml::from_str(&contents).unwrap_or_default()
+    } else {
+        serde_json::from_str(&contents).unwrap_or_default()
+    };
+    crate::router::Router {
+        aliases: parsed.model_aliases.unwrap_or_default(),
+        routes: parsed.model_routes.unwrap_or_default(),
+    }
+}
+
 pub(crate) fn reload_config(
     config_path: Option<&str>,
     account: &str,
@@ -564,6 +840,19 @@ mod tests {
             ],
             system_prompt: None,
             sender_prompts: None,
+            group_projects: None,
+            group_settings: None,
+            sender_rate_limits: None,
+            resource_limits: None,
+            claude_accounts: None,
+            stickers: None,
+            settings: None,
+            model_aliases: None,
+            model_routes: None,
+            webhooks: None,
+            webhook_daily_budget_usd: None,
+            invites: None,
+            guests: None,
         };
         let json = serde_json::to_string(&data).unwrap();
         let loaded: PersistedAllowed = serde_json::from_str(&json).unwrap();
@@ -605,6 +894,19 @@ mod tests {
             }],
             system_prompt: None,
             sender_prompts: None,
+            group_projects: None,
+            group_settings: None,
+            sender_rate_limits: None,
+            resource_limits: None,
+            claude_accounts: None,
+            stickers: None,
+            settings: None,
+            model_aliases: None,
+            model_routes: None,
+            webhooks: None,
+            webhook_daily_budget_usd: None,
+            invites: None,
+            guests: None,
         };
         let dir = std::env::temp_dir().join(format!("ccchat_test_merge_{}", std::process::id()));
         let _ = std::fs::create_dir_all(&dir);
@@ -628,6 +930,111 @@ mod tests {
         let _ = std::fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn test_load_group_projects_from_yaml_config() {
+        let dir = std::env::temp_dir().join(format!("ccchat_groups_{}", std::process::id()));
+        let _ = std::fs::create_dir_all(&dir);
+        let path = dir.join("config.yaml");
+        let yaml = "allowed: []\ngroup_projects:\n  \"group.abc=\": /srv/repo\n";
+        std::fs::write(&path, yaml).unwrap();
+
+        let bindings = load_group_projects(Some(path.to_str().unwrap()));
+        assert_eq!(
+            bindings.get("group.abc="),
+            Some(&PathBuf::from("/srv/repo"))
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_load_sender_rate_limits_from_yaml_config() {
+        let dir = std::env::temp_dir().join(format!("ccchat_rates_{}", std::process::id()));
+        let _ = std::fs::create_dir_all(&dir);
+        let path = dir.join("config.yaml");
+        let yaml = "allowed: []\nsender_rate_limits:\n  \"+111\":\n    claude: 20/hour\n    command: 2/sec\n  \"+222\":\n    claude: lots\n";
+        std::fs::write(&path, yaml).unwrap();
+
+        let limits = load_sender_rate_limits(Some(path.to_str().unwrap()));
+        assert_eq!(
+            limits.get(&("+111".to_string(), RateClass::Claude)),
+            Some(&(20.0, 20.0 / 3600.0))
+        );
+        assert_eq!(
+            limits.get(&("+111".to_string(), RateClass::Command)),
+            Some(&(2.0, 2.0))
+        );
+        assert!(!limits.contains_key(&("+222".to_string(), RateClass::Claude)));
+        assert!(load_sender_rate_limits(None).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_load_resource_limits_from_yaml_config() {
+        let dir = std::env::temp_dir().join(format!("ccchat_resources_{}", std::process::id()));
+        let _ = std::fs::create_dir_all(&dir);
+        let path = dir.join("config.yaml");
+        let yaml = "allowed: []\nresource_limits:\n  max_load: 8\n  min_free_disk_mb: 1024\n  when_exceeded: reject\n";
+        std::fs::write(&path, yaml).unwrap();
+
+        let limits = load_resource_limits(Some(path.to_str().unwrap()));
+        assert_eq!(limits.max_load, Some(8.0));
+        assert_eq!(limits.min_free_memory_mb, None);
+        assert_eq!(limits.min_free_disk_mb, Some(1024));
+        assert_eq!(limits.when_exceeded, crate::resources::Overload::Reject);
+        assert!(!load_resource_limits(None).is_set());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_load_claude_accounts_from_yaml_config() {
+        let dir = std::env::temp_dir().join(format!("ccchat_accounts_{}", std::process::id()));
+        let _ = std::fs::create_dir_all(&dir);
+        let path = dir.join("config.yaml");
+        let yaml = "allowed: []\nclaude_accounts:\n  - name: personal\n    config_dir: ~/.claude-personal\n  - name: team\n    api_key_env: TEAM_ANTHROPIC_KEY\n";
+        std::fs::write(&path, yaml).unwrap();
+
+        let accounts = load_claude_accounts(Some(path.to_str().unwrap()));
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].name, "personal");
+        assert_eq!(
+            accounts[0].config_dir.as_deref(),
+            Some("~/.claude-personal")
+        );
+        assert_eq!(
+            accounts[1].api_key_env.as_deref(),
+            Some("TEAM_ANTHROPIC_KEY")
+        );
+        assert!(load_claude_accounts(None).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_load_stickers_from_yaml_config() {
+        let dir = std::env::temp_dir().join(format!("ccchat_stickers_{}", std::process::id()));
+        let _ = std::fs::create_dir_all(&dir);
+        let path = dir.join("config.yaml");
+        let yaml = "allowed: []\nstickers:\n  pack_id: abc123\n  pack_key: def456\n  done: 0\n  rate_limited: 3\n";
+        std::fs::write(&path, yaml).unwrap();
+
+        let stickers = load_stickers(Some(path.to_str().unwrap()));
+        assert_eq!(stickers.pack_key.as_deref(), Some("def456"));
+        assert_eq!(
+            stickers
+                .sticker_for(crate::stickers::StickerStatus::RateLimited)
+                .as_deref(),
+            Some("abc123:3")
+        );
+        assert_eq!(stickers.error, None);
+        assert!(!load_stickers(None).is_set());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_persisted_allowed_without_group_projects_parses() {
+        let parsed: PersistedAllowed = serde_json::from_str(r#"{"allowed": []}"#).unwrap();
+        assert!(parsed.group_projects.is_none());
+    }
+
     #[test]
     fn test_reload_config_updates_system_prompt() {
         let dir = std::env::temp_dir().join(format!("ccchat_reload_prompt_{}", std::process::id()));
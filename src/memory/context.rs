@@ -1,6 +1,8 @@
 use tracing::error;
 
-use super::messages::{get_recent_summaries, search_memory, store_message, store_summary};
+use super::messages::{
+    get_recent_summaries, list_facts, search_memory, store_message, store_summary,
+};
 use super::schema::{memory_db_path, memory_json_path, open_memory_db};
 
 pub(crate) fn save_memory(sender: &str, summary: &str) {
@@ -16,6 +18,8 @@ pub(crate) fn delete_memory(sender: &str) {
     let _ = std::fs::remove_file(db_path.with_extension("db-wal"));
     let _ = std::fs::remove_file(db_path.with_extension("db-shm"));
     let _ = std::fs::remove_file(memory_json_path(sender));
+    let _ = std::fs::remove_dir_all(crate::attachments::sender_dir(sender));
+    super::lock::delete(sender);
 }
 
 pub(crate) fn inject_context(sender: &str, text: &str) -> String {
@@ -58,6 +62,20 @@ pub(crate) fn inject_context(sender: &str, text: &str) -> String {
     ctx
 }
 
+/// The sender's `/remember` facts as a system prompt section, if any.
+pub(crate) fn facts_prompt(sender: &str) -> Option<String> {
+    let facts = list_facts(&open_memory_db(sender).ok()?);
+    if facts.is_empty() {
+        return None;
+    }
+    let mut prompt = "Facts the user asked you to remember:".to_string();
+    for fact in &facts {
+        prompt.push_str("\n- ");
+        prompt.push_str(fact);
+    }
+    Some(prompt)
+}
+
 pub(crate) fn store_message_pair(
     sender: &str,
     user_msg: &str,
@@ -0,0 +1,289 @@
+//! `ccchat memory`: look through and prune the memory databases from the
+//! shell, without going through chat.
+//!
+//! Each chat's memory lives in its own database named after a hash of the
+//! chat id, so records are addressed as `<chat>:<kind><row>`, e.g.
+//! `3f2a9c0d1e4b5a68:m42` for message 42 (`s` for summaries, `f` for facts
+//! saved with `/remember`).
+
+use rusqlite::{Connection, OpenFlags};
+use std::path::PathBuf;
+
+use super::context::format_epoch;
+use super::messages::fts_query;
+use super::schema::{hash_sender, memory_dir, open_memory_db};
+use crate::error::AppError;
+
+/// What `ccchat memory list` shows.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum Kind {
+    Messages,
+    Summaries,
+    Facts,
+}
+
+impl Kind {
+    fn letter(self) -> char {
+        match self {
+            Kind::Messages => 'm',
+            Kind::Summaries => 's',
+            Kind::Facts => 'f',
+        }
+    }
+
+    fn from_letter(letter: char) -> Option<Self> {
+        match letter {
+            'm' => Some(Kind::Messages),
+            's' => Some(Kind::Summaries),
+            'f' => Some(Kind::Facts),
+            _ => None,
+        }
+    }
+
+    fn table(self) -> &'static str {
+        match self {
+            Kind::Messages => "messages",
+            Kind::Summaries => "summaries",
+            Kind::Facts => "facts",
+        }
+    }
+}
+
+/// One stored row, ready to print.
+#[derive(Debug, PartialEq)]
+struct Record {
+    chat: String,
+    kind: Kind,
+    row: i64,
+    label: String,
+    content: String,
+    timestamp: i64,
+}
+
+impl Record {
+    fn line(&self) -> String {
+        format!(
+            "{}:{}{}  {}  {}{}",
+            self.chat,
+            self.kind.letter(),
+            self.row,
+            format_epoch(self.timestamp.max(0) as u64),
+            self.label,
+            crate::helpers::truncate(&self.content.replace('\n', " "), 100)
+        )
+    }
+}
+
+/// Memory databases, as (chat hash, path), sorted.
+fn databases() -> Vec<(String, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(memory_dir()) else {
+        return Vec::new();
+    };
+    let mut dbs: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "db"))
+        .filter_map(|p| Some((p.file_stem()?.to_string_lossy().into_owned(), p)))
+        .collect();
+    dbs.sort();
+    dbs
+}
+
+fn search_db(conn: &Connection, chat: &str, fts: &str, limit: usize) -> Vec<Record> {
+    let sql = "SELECT m.id, m.role, m.content, m.timestamp FROM messages m
+               JOIN messages_fts f ON m.id = f.rowid
+               WHERE messages_fts MATCH ?1
+               ORDER BY f.rank
+               LIMIT ?2";
+    let Ok(mut stmt) = conn.prepare(sql) else {
+        return Vec::new();
+    };
+    stmt.query_map(rusqlite::params![fts, limit as i64], |row| {
+        Ok(Record {
+            chat: chat.to_string(),
+            kind: Kind::Messages,
+            row: row.get(0)?,
+            label: format!("[{}] ", row.get::<_, String>(1)?),
+            content: row.get(2)?,
+            timestamp: row.get(3)?,
+        })
+    })
+    .ok()
+    .map(|iter| iter.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+/// `ccchat memory search`: messages matching any word of `query`, in one
+/// sender's memory or everyone's.
+pub(crate) fn search(query: &str, sender: Option<&str>, limit: usize) -> Result<String, AppError> {
+    let fts = fts_query(query)
+        .ok_or_else(|| AppError::Other("Search for at least one word.".to_string()))?;
+    let dbs = match sender {
+        Some(sender) => vec![(hash_sender(sender), super::schema::memory_db_path(sender))],
+        None => databases(),
+    };
+    let mut records = Vec::new();
+    for (chat, path) in dbs {
+        let Ok(conn) = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY) else {
+            continue;
+        };
+        records.extend(search_db(&conn, &chat, &fts, limit));
+    }
+    records.truncate(limit);
+    if records.is_empty() {
+        return Ok(format!("No messages match \"{query}\"."));
+    }
+    Ok(records
+        .iter()
+        .map(Record::line)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn list_db(conn: &Connection, chat: &str, kind: Kind, limit: usize) -> Vec<Record> {
+    let sql = match kind {
+        Kind::Messages => "SELECT id, role, content, timestamp FROM messages",
+        Kind::Summaries => "SELECT id, '', summary, timestamp FROM summaries",
+        Kind::Facts => "SELECT id, '', content, timestamp FROM facts",
+    };
+    let sql = format!("{sql} ORDER BY id DESC LIMIT ?1");
+    let Ok(mut stmt) = conn.prepare(&sql) else {
+        return Vec::new();
+    };
+    let mut records: Vec<Record> = stmt
+        .query_map([limit as i64], |row| {
+            let role: String = row.get(1)?;
+            Ok(Record {
+                chat: chat.to_string(),
+                kind,
+                row: row.get(0)?,
+                label: if role.is_empty() {
+                    String::new()
+                } else {
+                    format!("[{role}] ")
+                },
+                content: row.get(2)?,
+                timestamp: row.get(3)?,
+            })
+        })
+        .ok()
+        .map(|iter| iter.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default();
+    records.reverse();
+    records
+}
+
+/// `ccchat memory list`: a sender's newest records of one kind, oldest first.
+pub(crate) fn list(sender: &str, kind: Kind, limit: usize) -> Result<String, AppError> {
+    if !super::schema::memory_db_path(sender).exists() {
+        return Ok(format!("No memory stored for {sender}."));
+    }
+    let conn = open_memory_db(sender)?;
+    let records = list_db(&conn, &hash_sender(sender), kind, limit);
+    if records.is_empty() {
+        return Ok(format!("No {} stored for {sender}.", kind.table()));
+    }
+    Ok(records
+        .iter()
+        .map(Record::line)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Parse `<chat>:<kind><row>`.
+fn parse_id(id: &str) -> Option<(String, Kind, i64)> {
+    let (chat, rest) = id.trim().split_once(':')?;
+    let mut chars = rest.chars();
+    let kind = Kind::from_letter(chars.next()?)?;
+    let row = chars.as_str().parse().ok()?;
+    let valid_chat = !chat.is_empty() && chat.chars().all(|c| c.is_ascii_hexdigit());
+    valid_chat.then(|| (chat.to_string(), kind, row))
+}
+
+/// `ccchat memory delete`: remove one record by the id `search` or `list`
+/// printed.
+pub(crate) fn delete(id: &str) -> Result<String, AppError> {
+    let (chat, kind, row) = parse_id(id).ok_or_else(|| {
+        AppError::Other(format!(
+            "Not a memory id: {id} (expected <chat>:<m|s|f><number>, as printed by search and list)"
+        ))
+    })?;
+    let path = memory_dir().join(format!("{chat}.db"));
+    if !path.exists() {
+        return Err(AppError::Other(format!(
+            "No memory database for chat {chat}"
+        )));
+    }
+    let conn = Connection::open(&path)?;
+    let deleted = conn.execute(
+        &format!("DELETE FROM {} WHERE id = ?1", kind.table()),
+        [row],
+    )?;
+    if deleted == 0 {
+        return Err(AppError::Other(format!("No record {id}")));
+    }
+    if kind == Kind::Messages {
+        conn.execute("DELETE FROM feedback WHERE message_id = ?1", [row])?;
+    }
+    Ok(format!("Deleted {id}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::context::delete_memory;
+    use crate::memory::messages::{add_fact, store_message};
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(
+            parse_id("3f2a9c0d1e4b5a68:m42"),
+            Some(("3f2a9c0d1e4b5a68".to_string(), Kind::Messages, 42))
+        );
+        assert_eq!(parse_id("abc:f1").map(|(_, k, _)| k), Some(Kind::Facts));
+        assert_eq!(parse_id("abc:x1"), None);
+        assert_eq!(parse_id("../etc:m1"), None);
+        assert_eq!(parse_id("abc:m"), None);
+        assert_eq!(parse_id("42"), None);
+    }
+
+    #[test]
+    fn test_search_list_delete() {
+        let sender = format!("inspect_test_{}", uuid::Uuid::new_v4());
+        let conn = open_memory_db(&sender).unwrap();
+        store_message(&conn, "user", "where is the staging database", "s1");
+        store_message(&conn, "assistant", "on athena", "s1");
+        add_fact(&conn, "staging is athena.local");
+        let chat = hash_sender(&sender);
+
+        let found = search("staging", Some(&sender), 10).unwrap();
+        assert!(found.starts_with(&format!("{chat}:m1  ")));
+        assert!(found.contains("[user] where is the staging database"));
+        assert!(search("!!", None, 10).is_err());
+
+        let listed = list(&sender, Kind::Messages, 10).unwrap();
+        assert_eq!(listed.lines().count(), 2);
+        assert!(listed
+            .lines()
+            .nth(1)
+            .unwrap()
+            .contains("[assistant] on athena"));
+        assert!(list(&sender, Kind::Facts, 10)
+            .unwrap()
+            .contains(&format!("{chat}:f1")));
+        assert_eq!(
+            list(&sender, Kind::Summaries, 10).unwrap(),
+            format!("No summaries stored for {sender}.")
+        );
+
+        assert_eq!(
+            delete(&format!("{chat}:m1")).unwrap(),
+            format!("Deleted {chat}:m1")
+        );
+        assert!(delete(&format!("{chat}:m1")).is_err());
+        assert!(search("staging", Some(&sender), 10)
+            .unwrap()
+            .starts_with("No messages match"));
+        delete_memory(&sender);
+    }
+}
@@ -0,0 +1,372 @@
+//! Memory locked with the sender's own passphrase (`/memory lock`).
+//!
+//! A locked sender's memory database (history, summaries, facts, pins,
+//! preferences) is kept on disk only sealed, as `<hash>.db.locked`, under a
+//! key derived from their passphrase, which the bridge never stores. Once
+//! unlocked it lives in this process's memory (SQLite's `memdb` VFS) and
+//! changes are sealed back to disk every [`MEMORY_SEAL_SECS`] and at
+//! shutdown. After a restart it can't be read until the sender sends
+//! `/memory unlock` again.
+//!
+//! [`MEMORY_SEAL_SECS`]: crate::constants::MEMORY_SEAL_SECS
+
+use base64::Engine;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tracing::{error, info};
+
+use super::schema::{hash_sender, memory_db_path, memory_dir, memory_json_path};
+use crate::bundle::SealKey;
+use crate::error::AppError;
+
+/// Start of a sealed memory file.
+const MAGIC: &[u8; 8] = b"CCCHATM1";
+
+/// Told to a sender whose memory is still locked.
+pub(crate) const LOCKED_NOTICE: &str =
+    "Your memory is locked. Send /memory unlock <passphrase> to use it.";
+
+/// Whether `text` (a message or a whole transport frame) may hold a
+/// passphrase: it has `/memory lock` or `/memory unlock` in it.
+pub(crate) fn mentions_passphrase(text: &str) -> bool {
+    text.match_indices("/memory")
+        .map(|(at, cmd)| text[at + cmd.len()..].trim_start())
+        .any(|rest| rest.starts_with("lock") || rest.starts_with("unlock"))
+}
+
+/// An unlocked memory: the connection that keeps the in-memory database
+/// alive, the key to seal it with and the `data_version` last sealed.
+struct Unlocked {
+    holder: Connection,
+    key: SealKey,
+    sealed_version: i64,
+}
+
+/// Sender hash -> their unlocked memory.
+fn unlocked() -> &'static Mutex<HashMap<String, Unlocked>> {
+    static UNLOCKED: OnceLock<Mutex<HashMap<String, Unlocked>>> = OnceLock::new();
+    UNLOCKED.get_or_init(Default::default)
+}
+
+fn locked_path(sender: &str) -> PathBuf {
+    memory_dir().join(format!("{}.db.locked", hash_sender(sender)))
+}
+
+/// Whether `sender` has locked their memory (unlocked now or not).
+pub(crate) fn has_lock(sender: &str) -> bool {
+    locked_path(sender).exists()
+}
+
+/// Whether `sender`'s memory is locked and waits for `/memory unlock`.
+pub(crate) fn is_locked(sender: &str) -> bool {
+    has_lock(sender)
+        && !unlocked()
+            .lock()
+            .is_ok_and(|open| open.contains_key(&hash_sender(sender)))
+}
+
+fn open_memdb(hash: &str) -> Result<Connection, AppError> {
+    let conn = Connection::open_with_flags(
+        format!("file:/ccchat-{hash}.db?vfs=memdb"),
+        OpenFlags::SQLITE_OPEN_READ_WRITE
+            | OpenFlags::SQLITE_OPEN_CREATE
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    Ok(conn)
+}
+
+/// A connection to `sender`'s memory when it's locked: None when it isn't,
+/// an error while it waits to be unlocked.
+pub(super) fn connection(sender: &str) -> Result<Option<Connection>, AppError> {
+    if !has_lock(sender) {
+        return Ok(None);
+    }
+    let hash = hash_sender(sender);
+    if !unlocked().lock().is_ok_and(|open| open.contains_key(&hash)) {
+        return Err(LOCKED_NOTICE.into());
+    }
+    open_memdb(&hash).map(Some)
+}
+
+/// Every row of every table, as JSON. The search index is left out; it's
+/// rebuilt as the messages are loaded.
+fn dump(conn: &Connection) -> Result<Vec<u8>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' \
+         AND name NOT LIKE 'sqlite_%' AND name NOT LIKE 'messages_fts%' ORDER BY name",
+    )?;
+    let tables: Vec<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let mut out = BTreeMap::new();
+    for table in tables {
+        let mut stmt = conn.prepare(&format!("SELECT * FROM \"{table}\""))?;
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let rows = stmt
+            .query_map([], |row| {
+                (0..columns.len())
+                    .map(|i| row.get::<_, Value>(i).map(to_json))
+                    .collect::<Result<Vec<_>, _>>()
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        out.insert(table, serde_json::json!({"columns": columns, "rows": rows}));
+    }
+    Ok(serde_json::to_vec(&out)?)
+}
+
+fn to_json(value: Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(i) => i.into(),
+        Value::Real(f) => f.into(),
+        Value::Text(s) => s.into(),
+        Value::Blob(b) => {
+            serde_json::json!({"blob": base64::engine::general_purpose::STANDARD.encode(b)})
+        }
+    }
+}
+
+fn from_json(value: &serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::Text(s.clone()),
+        serde_json::Value::Object(o) => o
+            .get("blob")
+            .and_then(|b| b.as_str())
+            .and_then(|b| base64::engine::general_purpose::STANDARD.decode(b).ok())
+            .map(Value::Blob)
+            .unwrap_or(Value::Null),
+        _ => Value::Null,
+    }
+}
+
+/// Insert a [`dump`] into `conn`, whose tables already exist.
+fn load(conn: &Connection, data: &[u8]) -> Result<(), AppError> {
+    #[derive(serde::Deserialize)]
+    struct Table {
+        columns: Vec<String>,
+        rows: Vec<Vec<serde_json::Value>>,
+    }
+    let tables: BTreeMap<String, Table> = serde_json::from_slice(data)?;
+    for (name, table) in &tables {
+        let columns = table
+            .columns
+            .iter()
+            .map(|c| format!("\"{c}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let params = vec!["?"; table.columns.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "INSERT OR REPLACE INTO \"{name}\" ({columns}) VALUES ({params})"
+        ))?;
+        for row in &table.rows {
+            stmt.execute(rusqlite::params_from_iter(row.iter().map(from_json)))?;
+        }
+    }
+    Ok(())
+}
+
+fn data_version(conn: &Connection) -> i64 {
+    conn.query_row("PRAGMA data_version", [], |row| row.get(0))
+        .unwrap_or_default()
+}
+
+/// Write `data` to the locked file, via a temporary file so a crash
+/// leaves the old one.
+fn write_sealed(sender_hash: &str, data: &[u8]) -> Result<(), AppError> {
+    let path = memory_dir().join(format!("{sender_hash}.db.locked"));
+    let partial = path.with_extension("locked.partial");
+    std::fs::write(&partial, data)?;
+    std::fs::rename(&partial, &path)?;
+    Ok(())
+}
+
+/// Put `dump` in memory as `sender`'s unlocked database.
+fn open_unlocked(sender: &str, dump: &[u8], key: SealKey) -> Result<(), AppError> {
+    let hash = hash_sender(sender);
+    let holder = open_memdb(&hash)?;
+    super::schema::create_tables(&holder)?;
+    load(&holder, dump)?;
+    let sealed_version = data_version(&holder);
+    let mut open = unlocked().lock().map_err(|_| "Memory lock poisoned")?;
+    open.insert(
+        hash,
+        Unlocked {
+            holder,
+            key,
+            sealed_version,
+        },
+    );
+    Ok(())
+}
+
+/// Seal `sender`'s memory under `passphrase` and delete the plain copy.
+/// It stays unlocked until the bridge restarts.
+pub(crate) fn lock(sender: &str, passphrase: &str, iterations: u32) -> Result<(), AppError> {
+    if has_lock(sender) {
+        return Err("Your memory is already locked.".into());
+    }
+    let key = SealKey::new(passphrase, iterations)?;
+    let data = dump(&super::open_memory_db(sender)?)?;
+    write_sealed(
+        &hash_sender(sender),
+        &crate::bundle::seal_with(MAGIC, data.clone(), &key)?,
+    )?;
+    open_unlocked(sender, &data, key)?;
+    let db = memory_db_path(sender);
+    for path in [
+        db.with_extension("db-wal"),
+        db.with_extension("db-shm"),
+        memory_json_path(sender),
+        db,
+    ] {
+        let _ = std::fs::remove_file(path);
+    }
+    info!(sender = %sender, "Memory locked");
+    Ok(())
+}
+
+/// Open `sender`'s locked memory with `passphrase`.
+pub(crate) fn unlock(sender: &str, passphrase: &str) -> Result<(), AppError> {
+    if !has_lock(sender) {
+        return Err("Your memory isn't locked.".into());
+    }
+    if !is_locked(sender) {
+        return Ok(());
+    }
+    let sealed = std::fs::read(locked_path(sender))?;
+    let (data, key) = crate::bundle::open_with_key(MAGIC, &sealed, passphrase)
+        .map_err(|_| AppError::from("Wrong passphrase."))?;
+    open_unlocked(sender, &data, key)?;
+    info!(sender = %sender, "Memory unlocked");
+    Ok(())
+}
+
+/// Turn the lock off: write unlocked memory back to a plain database.
+pub(crate) fn remove(sender: &str) -> Result<(), AppError> {
+    if !has_lock(sender) {
+        return Err("Your memory isn't locked.".into());
+    }
+    let hash = hash_sender(sender);
+    let data = {
+        let open = unlocked().lock().map_err(|_| "Memory lock poisoned")?;
+        let memory = open.get(&hash).ok_or(LOCKED_NOTICE)?;
+        dump(&memory.holder)?
+    };
+    let conn = Connection::open(memory_db_path(sender))?;
+    super::schema::create_tables(&conn)?;
+    load(&conn, &data)?;
+    drop(conn);
+    std::fs::remove_file(locked_path(sender))?;
+    if let Ok(mut open) = unlocked().lock() {
+        open.remove(&hash);
+    }
+    info!(sender = %sender, "Memory lock removed");
+    Ok(())
+}
+
+/// Forget `sender`'s locked memory entirely (`/forget`).
+pub(crate) fn delete(sender: &str) {
+    if let Ok(mut open) = unlocked().lock() {
+        open.remove(&hash_sender(sender));
+    }
+    let _ = std::fs::remove_file(locked_path(sender));
+}
+
+/// Seal every unlocked memory that changed since it was last sealed.
+/// Returns how many were written.
+pub(crate) fn flush() -> usize {
+    let Ok(mut open) = unlocked().lock() else {
+        return 0;
+    };
+    let mut written = 0;
+    for (hash, memory) in open.iter_mut() {
+        let version = data_version(&memory.holder);
+        if version == memory.sealed_version {
+            continue;
+        }
+        let sealed = dump(&memory.holder)
+            .and_then(|data| crate::bundle::seal_with(MAGIC, data, &memory.key))
+            .and_then(|sealed| write_sealed(hash, &sealed));
+        match sealed {
+            Ok(()) => {
+                memory.sealed_version = version;
+                written += 1;
+            }
+            Err(e) => error!(sender_hash = %hash, "Failed to seal locked memory: {e}"),
+        }
+    }
+    written
+}
+
+/// Drop `sender`'s key, as a restart would.
+#[cfg(test)]
+pub(crate) fn relock(sender: &str) {
+    if let Ok(mut open) = unlocked().lock() {
+        open.remove(&hash_sender(sender));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mentions_passphrase() {
+        let frame = r#"{"envelope":{"dataMessage":{"message":"/memory  unlock hunter2"}}}"#;
+        assert!(mentions_passphrase(frame));
+        assert!(mentions_passphrase("/memory lock hunter2"));
+        assert!(!mentions_passphrase("/memory status"));
+        assert!(!mentions_passphrase("how do I unlock my memory?"));
+    }
+
+    #[test]
+    fn test_lock_unlock_and_remove() {
+        let sender = format!("+lock_{}", uuid::Uuid::new_v4());
+        super::super::context::save_memory(&sender, "Talked about tea");
+        lock(&sender, "correct horse", 1_000).unwrap();
+        assert!(!memory_db_path(&sender).exists());
+        let sealed = std::fs::read(locked_path(&sender)).unwrap();
+        assert!(!String::from_utf8_lossy(&sealed).contains("tea"));
+        assert!(lock(&sender, "correct horse", 1_000).is_err());
+
+        // Unlocked after locking: memory works and changes get sealed
+        super::super::context::save_memory(&sender, "Then coffee");
+        assert_eq!(flush_one(&sender), 1);
+
+        relock(&sender);
+        assert!(is_locked(&sender));
+        assert!(super::super::open_memory_db(&sender).is_err());
+        assert!(unlock(&sender, "wrong horse").is_err());
+        unlock(&sender, "correct horse").unwrap();
+        let conn = super::super::open_memory_db(&sender).unwrap();
+        let summaries = super::super::messages::get_recent_summaries(&conn, 10);
+        assert_eq!(summaries.len(), 2);
+        drop(conn);
+
+        remove(&sender).unwrap();
+        assert!(!has_lock(&sender));
+        let conn = super::super::open_memory_db(&sender).unwrap();
+        assert_eq!(
+            super::super::messages::get_recent_summaries(&conn, 10).len(),
+            2
+        );
+        drop(conn);
+        super::super::context::delete_memory(&sender);
+    }
+
+    /// [`flush`] counting only `sender`, since other tests share the registry.
+    fn flush_one(sender: &str) -> usize {
+        let before = std::fs::read(locked_path(sender)).unwrap();
+        flush();
+        usize::from(std::fs::read(locked_path(sender)).unwrap() != before)
+    }
+}
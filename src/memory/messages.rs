@@ -1,8 +1,19 @@
 use rusqlite::Connection;
+use serde::Serialize;
 use tracing::error;
 
 pub(crate) fn store_message(conn: &Connection, role: &str, content: &str, session_id: &str) {
-    let timestamp = crate::helpers::epoch_now();
+    store_message_at(conn, role, content, session_id, crate::helpers::epoch_now());
+}
+
+/// Store a message with its original time (imported history).
+pub(crate) fn store_message_at(
+    conn: &Connection,
+    role: &str,
+    content: &str,
+    session_id: &str,
+    timestamp: i64,
+) {
     if let Err(e) = conn.execute(
         "INSERT INTO messages (role, content, timestamp, session_id) VALUES (?1, ?2, ?3, ?4)",
         rusqlite::params![role, content, timestamp, session_id],
@@ -28,11 +39,8 @@ pub(crate) fn store_summary(conn: &Connection, summary: &str) {
     );
 }
 
-pub(crate) fn search_memory(
-    conn: &Connection,
-    query: &str,
-    limit: usize,
-) -> Vec<(String, String, i64)> {
+/// `query` as an FTS5 match on any of its words, or `None` if it has none.
+pub(crate) fn fts_query(query: &str) -> Option<String> {
     let sanitized: String = query
         .chars()
         .map(|c| {
@@ -44,10 +52,17 @@ pub(crate) fn search_memory(
         })
         .collect();
     let terms: Vec<&str> = sanitized.split_whitespace().collect();
-    if terms.is_empty() {
+    (!terms.is_empty()).then(|| terms.join(" OR "))
+}
+
+pub(crate) fn search_memory(
+    conn: &Connection,
+    query: &str,
+    limit: usize,
+) -> Vec<(String, String, i64)> {
+    let Some(fts_query) = fts_query(query) else {
         return Vec::new();
-    }
-    let fts_query = terms.join(" OR ");
+    };
     let sql = "SELECT m.role, m.content, m.timestamp FROM messages m
                JOIN messages_fts f ON m.id = f.rowid
                WHERE messages_fts MATCH ?1
@@ -216,6 +231,150 @@ pub(crate) fn delete_pin(conn: &Connection, label: &str) {
     );
 }
 
+/// Save a `/template`, replacing one with the same name.
+pub(crate) fn save_template(conn: &Connection, name: &str, body: &str) {
+    let timestamp = crate::helpers::epoch_now();
+    if let Err(e) = conn.execute(
+        "INSERT INTO templates (name, body, timestamp) VALUES (?1, ?2, ?3)
+         ON CONFLICT(name) DO UPDATE SET body = excluded.body, timestamp = excluded.timestamp",
+        rusqlite::params![name, body, timestamp],
+    ) {
+        error!("Failed to save template: {e}");
+    }
+}
+
+/// Templates as (name, body), by name.
+pub(crate) fn list_templates(conn: &Connection) -> Vec<(String, String)> {
+    let sql = "SELECT name, body FROM templates ORDER BY name";
+    let Ok(mut stmt) = conn.prepare(sql) else {
+        return Vec::new();
+    };
+    stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })
+    .ok()
+    .map(|iter| iter.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+pub(crate) fn get_template(conn: &Connection, name: &str) -> Option<String> {
+    conn.query_row(
+        "SELECT body FROM templates WHERE name = ?1",
+        rusqlite::params![name],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+/// Returns whether there was a template called `name`.
+pub(crate) fn delete_template(conn: &Connection, name: &str) -> bool {
+    conn.execute(
+        "DELETE FROM templates WHERE name = ?1",
+        rusqlite::params![name],
+    )
+    .is_ok_and(|n| n > 0)
+}
+
+/// Store a fact from `/remember`. Returns its number in `/memories`, or
+/// `None` when [`MAX_FACTS`](crate::constants::MAX_FACTS) are already kept.
+pub(crate) fn add_fact(conn: &Connection, content: &str) -> Option<usize> {
+    let count = list_facts(conn).len();
+    if count >= crate::constants::MAX_FACTS {
+        return None;
+    }
+    if let Err(e) = conn.execute(
+        "INSERT INTO facts (content, timestamp) VALUES (?1, ?2)",
+        rusqlite::params![content, crate::helpers::epoch_now()],
+    ) {
+        error!("Failed to store fact: {e}");
+        return None;
+    }
+    Some(count + 1)
+}
+
+/// Remembered facts, oldest first; `/memories` numbers them from 1.
+pub(crate) fn list_facts(conn: &Connection) -> Vec<String> {
+    let Ok(mut stmt) = conn.prepare("SELECT content FROM facts ORDER BY id") else {
+        return Vec::new();
+    };
+    stmt.query_map([], |row| row.get(0))
+        .ok()
+        .map(|iter| iter.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Delete fact number `n` as listed by `/memories`. Returns what it said.
+pub(crate) fn delete_fact(conn: &Connection, n: usize) -> Option<String> {
+    let (id, content): (i64, String) = conn
+        .query_row(
+            "SELECT id, content FROM facts ORDER BY id LIMIT 1 OFFSET ?1",
+            rusqlite::params![n.checked_sub(1)? as i64],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .ok()?;
+    conn.execute("DELETE FROM facts WHERE id = ?1", rusqlite::params![id])
+        .ok()?;
+    Some(content)
+}
+
+/// Rate the latest reply `good` or `bad` (`/good`, `/bad`), replacing any
+/// earlier rating of it. Returns the reply, or `None` if there isn't one.
+pub(crate) fn rate_last_reply(conn: &Connection, rating: &str, comment: &str) -> Option<String> {
+    let (id, content): (i64, String) = conn
+        .query_row(
+            "SELECT id, content FROM messages WHERE role = 'assistant' ORDER BY id DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .ok()?;
+    conn.execute(
+        "INSERT INTO feedback (message_id, rating, comment, timestamp) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(message_id) DO UPDATE SET
+            rating = excluded.rating, comment = excluded.comment, timestamp = excluded.timestamp",
+        rusqlite::params![id, rating, comment, crate::helpers::epoch_now()],
+    )
+    .ok()?;
+    Some(content)
+}
+
+/// A rated reply with the message that prompted it.
+#[derive(Debug, Serialize)]
+pub(crate) struct Feedback {
+    pub(crate) rating: String,
+    pub(crate) comment: String,
+    pub(crate) rated_at: i64,
+    pub(crate) session_id: Option<String>,
+    pub(crate) prompt: Option<String>,
+    pub(crate) response: String,
+    pub(crate) replied_at: i64,
+}
+
+/// All rated replies, oldest rating first.
+pub(crate) fn list_feedback(conn: &Connection) -> Vec<Feedback> {
+    let sql = "SELECT f.rating, f.comment, f.timestamp, m.session_id, m.content, m.timestamp,
+            (SELECT u.content FROM messages u
+             WHERE u.id < m.id AND u.role = 'user' ORDER BY u.id DESC LIMIT 1)
+        FROM feedback f JOIN messages m ON m.id = f.message_id
+        ORDER BY f.timestamp, f.message_id";
+    let Ok(mut stmt) = conn.prepare(sql) else {
+        return Vec::new();
+    };
+    stmt.query_map([], |row| {
+        Ok(Feedback {
+            rating: row.get(0)?,
+            comment: row.get(1)?,
+            rated_at: row.get(2)?,
+            session_id: row.get(3)?,
+            response: row.get(4)?,
+            replied_at: row.get(5)?,
+            prompt: row.get(6)?,
+        })
+    })
+    .ok()
+    .map(|iter| iter.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
 pub(crate) fn get_recent_messages(conn: &Connection, limit: usize) -> Vec<(String, String, i64)> {
     let sql =
         "SELECT role, content, timestamp FROM messages ORDER BY timestamp DESC, id DESC LIMIT ?1";
@@ -233,12 +392,14 @@ pub(crate) fn get_recent_messages(conn: &Connection, limit: usize) -> Vec<(Strin
     rows
 }
 
-pub(crate) fn purge_old_messages(conn: &Connection, days: u32) {
+/// Delete messages older than `days`; returns how many went.
+pub(crate) fn purge_old_messages(conn: &Connection, days: u32) -> usize {
     let cutoff = crate::helpers::epoch_now() - (days as i64 * crate::constants::SECS_PER_DAY);
-    let _ = conn.execute(
+    conn.execute(
         "DELETE FROM messages WHERE timestamp < ?1",
         rusqlite::params![cutoff],
-    );
+    )
+    .unwrap_or(0)
 }
 
 #[cfg(test)]
@@ -340,6 +501,43 @@ mod tests {
         delete_memory(&sender);
     }
 
+    #[test]
+    fn test_rate_last_reply() {
+        let sender = format!("feedback_test_{}", std::process::id());
+        let conn = open_memory_db(&sender).unwrap();
+        assert_eq!(rate_last_reply(&conn, "good", ""), None);
+        store_message(&conn, "user", "what's 2+2", "s1");
+        store_message(&conn, "assistant", "5", "s1");
+        assert_eq!(rate_last_reply(&conn, "good", "").as_deref(), Some("5"));
+        // Rating again replaces the first rating
+        rate_last_reply(&conn, "bad", "arithmetic").unwrap();
+        let feedback = list_feedback(&conn);
+        assert_eq!(feedback.len(), 1);
+        assert_eq!(feedback[0].rating, "bad");
+        assert_eq!(feedback[0].comment, "arithmetic");
+        assert_eq!(feedback[0].prompt.as_deref(), Some("what's 2+2"));
+        assert_eq!(feedback[0].response, "5");
+        assert_eq!(feedback[0].session_id.as_deref(), Some("s1"));
+        delete_memory(&sender);
+    }
+
+    #[test]
+    fn test_facts() {
+        let sender = format!("facts_test_{}", std::process::id());
+        let conn = open_memory_db(&sender).unwrap();
+        assert_eq!(add_fact(&conn, "staging is athena.local"), Some(1));
+        assert_eq!(add_fact(&conn, "prefers fish shell"), Some(2));
+        assert_eq!(add_fact(&conn, "deploys on Fridays"), Some(3));
+        assert_eq!(delete_fact(&conn, 0), None);
+        assert_eq!(delete_fact(&conn, 4), None);
+        assert_eq!(delete_fact(&conn, 2).as_deref(), Some("prefers fish shell"));
+        assert_eq!(
+            list_facts(&conn),
+            ["staging is athena.local", "deploys on Fridays"]
+        );
+        delete_memory(&sender);
+    }
+
     #[test]
     fn test_get_oldest_message_ts_empty() {
         let sender = format!("oldest_empty_{}", std::process::id());
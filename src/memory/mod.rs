@@ -1,21 +1,32 @@
 mod admin;
 mod config;
 mod context;
+pub(crate) mod inspect;
+pub(crate) mod lock;
 pub(crate) mod messages;
 pub(crate) mod schema;
 
 // Re-export the public API so callers use `crate::memory::*` unchanged
-pub(crate) use admin::{forget_with_counts, memory_status, search_memory_formatted};
+pub(crate) use admin::{
+    export_feedback, forget_with_counts, memory_status, search_memory_formatted,
+};
 pub(crate) use config::{
-    allowed_file_path, export_config, load_config_file, load_persisted_allowed, persist_allow,
-    persist_revoke, reload_config_full, validate_config_entries,
+    allowed_file_path, config_dir, default_config_dir, export_config, load_claude_accounts,
+    load_config_file, load_group_projects, load_group_settings, load_model_router,
+    load_persisted_allowed, load_resource_limits, load_saved_settings, load_sender_rate_limits,
+    load_stickers, load_webhooks, persist_allow, persist_group_project, persist_group_settings,
+    persist_guest, persist_invites, persist_revoke, persist_setting, reload_config_full,
+    validate_config_entries,
+};
+pub(crate) use config::{AllowedEntry, PersistedAllowed};
+pub(crate) use context::{
+    facts_prompt, format_epoch, inject_context, save_memory, store_message_pair,
 };
-pub(crate) use context::{format_epoch, inject_context, save_memory, store_message_pair};
 pub(crate) use messages::{
     export_messages, get_message_count_by_role, load_model_preference, purge_old_messages,
     save_model_preference,
 };
-pub(crate) use schema::{hash_sender, open_memory_db};
+pub(crate) use schema::{hash_sender, memory_dir, open_memory_db};
 
 #[cfg(test)]
 pub(crate) use context::delete_memory;
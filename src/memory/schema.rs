@@ -26,9 +26,19 @@ pub(crate) fn memory_json_path(sender: &str) -> PathBuf {
 }
 
 pub(crate) fn open_memory_db(sender: &str) -> Result<Connection, AppError> {
+    // A locked memory only opens (in memory) once its owner unlocks it
+    if let Some(conn) = super::lock::connection(sender)? {
+        return Ok(conn);
+    }
     let path = memory_db_path(sender);
     let conn = Connection::open(&path)?;
     conn.execute_batch("PRAGMA journal_mode=WAL;")?;
+    create_tables(&conn)?;
+    migrate_json_to_sqlite(&conn, sender);
+    Ok(conn)
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<(), AppError> {
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS messages (
             id INTEGER PRIMARY KEY,
@@ -58,15 +68,44 @@ pub(crate) fn open_memory_db(sender: &str) -> Result<Connection, AppError> {
             model TEXT NOT NULL,
             updated_at INTEGER NOT NULL
         );
+        CREATE TABLE IF NOT EXISTS preferences (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            data TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
         CREATE TABLE IF NOT EXISTS pins (
             id INTEGER PRIMARY KEY,
             label TEXT NOT NULL UNIQUE,
             content TEXT NOT NULL,
             timestamp INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS templates (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            body TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS attachments (
+            id INTEGER PRIMARY KEY,
+            filename TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            path TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS facts (
+            id INTEGER PRIMARY KEY,
+            content TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS feedback (
+            message_id INTEGER PRIMARY KEY,
+            rating TEXT NOT NULL,
+            comment TEXT NOT NULL DEFAULT '',
+            timestamp INTEGER NOT NULL
         );",
     )?;
-    migrate_json_to_sqlite(&conn, sender);
-    Ok(conn)
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize)]
@@ -0,0 +1,247 @@
+//! Runtime metrics shown by `/status`, `/stats` and the stats server.
+//!
+//! Counters that every message bumps are [`Counter`]s: one atomic per shard,
+//! each on its own cache line, so worker threads counting at once don't
+//! bounce a single line between cores. Reads add the shards up, which is
+//! fine for the occasional stats request.
+
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use crate::constants::METRICS_SHARDS;
+
+#[derive(Default)]
+#[repr(align(64))]
+struct Shard(AtomicU64);
+
+/// A monotonically increasing count, sharded by thread.
+pub(crate) struct Counter {
+    shards: [Shard; METRICS_SHARDS],
+}
+
+/// Hands each thread the next shard in turn.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % METRICS_SHARDS;
+}
+
+impl Counter {
+    pub(crate) fn new() -> Self {
+        Self {
+            shards: std::array::from_fn(|_| Shard::default()),
+        }
+    }
+
+    pub(crate) fn add(&self, n: u64) {
+        let shard = SHARD.with(|shard| *shard);
+        self.shards[shard].0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn incr(&self) {
+        self.add(1);
+    }
+
+    pub(crate) fn get(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.load(Ordering::Relaxed))
+            .fold(0, u64::wrapping_add)
+    }
+
+    /// Replace the count (tests).
+    #[cfg(test)]
+    pub(crate) fn set(&self, n: u64) {
+        for shard in &self.shards[1..] {
+            shard.0.store(0, Ordering::Relaxed);
+        }
+        self.shards[0].0.store(n, Ordering::Relaxed);
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn percentile(samples: &std::sync::Mutex<VecDeque<u64>>, p: f64) -> u64 {
+    let mut samples: Vec<u64> = match samples.lock() {
+        Ok(guard) => guard.iter().copied().collect(),
+        Err(_) => return 0,
+    };
+    if samples.is_empty() {
+        return 0;
+    }
+    samples.sort_unstable();
+    let rank = ((p * samples.len() as f64).ceil() as usize).clamp(1, samples.len());
+    samples[rank - 1]
+}
+
+fn push_sample(samples: &std::sync::Mutex<VecDeque<u64>>, ms: u64) {
+    if let Ok(mut samples) = samples.lock() {
+        if samples.len() >= crate::constants::LATENCY_SAMPLE_WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(ms);
+    }
+}
+
+/// Runtime metrics. Counters bumped on every message are sharded; the
+/// rest are plain atomics.
+pub(crate) struct Metrics {
+    pub(crate) start_time: Instant,
+    pub(crate) message_count: Counter,
+    pub(crate) total_cost: Counter, // stored as microdollars
+    pub(crate) error_count: Counter,
+    pub(crate) latency_sum_ms: Counter,
+    pub(crate) latency_count: Counter,
+    /// Most recent Claude run durations (ms) for percentile reporting.
+    pub(crate) latency_samples: std::sync::Mutex<VecDeque<u64>>,
+    /// Most recent end-to-end reply times (ms): message received to last
+    /// part of the reply delivered.
+    pub(crate) reply_samples: std::sync::Mutex<VecDeque<u64>>,
+    /// Whether the admin has been told reply p95 is over `--reply-slo-ms`.
+    pub(crate) slo_breached: AtomicBool,
+    /// Claude failures keyed by `AppError::category()`.
+    pub(crate) failures: DashMap<&'static str, AtomicU64>,
+    /// Claude runs that reported a cost (denominator for average cost).
+    pub(crate) costed_runs: Counter,
+    pub(crate) reconnects: Counter,
+    /// When the current connection came up (epoch secs); 0 while disconnected.
+    pub(crate) connected_at: AtomicI64,
+    /// When a reconnect last succeeded (epoch secs); 0 if none has.
+    pub(crate) last_reconnect_at: AtomicI64,
+    pub(crate) rate_limited: Counter,
+    /// Requests held or turned away by `resource_limits`.
+    pub(crate) overload_queued: Counter,
+    pub(crate) overload_rejected: Counter,
+    /// When the admin was last told about an overload (epoch secs).
+    pub(crate) overload_alerted_at: AtomicU64,
+    /// Day (epoch days) the daily spend webhook last fired.
+    pub(crate) budget_alerted_day: AtomicI64,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        Self {
+            start_time: Instant::now(),
+            message_count: Counter::new(),
+            total_cost: Counter::new(),
+            error_count: Counter::new(),
+            latency_sum_ms: Counter::new(),
+            latency_count: Counter::new(),
+            latency_samples: std::sync::Mutex::new(VecDeque::new()),
+            reply_samples: std::sync::Mutex::new(VecDeque::new()),
+            slo_breached: AtomicBool::new(false),
+            failures: DashMap::new(),
+            costed_runs: Counter::new(),
+            reconnects: Counter::new(),
+            connected_at: AtomicI64::new(0),
+            last_reconnect_at: AtomicI64::new(0),
+            rate_limited: Counter::new(),
+            overload_queued: Counter::new(),
+            overload_rejected: Counter::new(),
+            overload_alerted_at: AtomicU64::new(0),
+            budget_alerted_day: AtomicI64::new(0),
+        }
+    }
+
+    /// A Claude run took `ms`.
+    pub(crate) fn record_latency(&self, ms: u64) {
+        self.latency_sum_ms.add(ms);
+        self.latency_count.incr();
+        push_sample(&self.latency_samples, ms);
+    }
+
+    /// A reply reached its chat `ms` after its message came in.
+    pub(crate) fn record_reply(&self, ms: u64) {
+        push_sample(&self.reply_samples, ms);
+    }
+
+    /// Latency percentile (0.0-1.0) over the recent sample window, nearest-rank.
+    pub(crate) fn latency_percentile(&self, p: f64) -> u64 {
+        percentile(&self.latency_samples, p)
+    }
+
+    /// End-to-end reply time percentile (0.0-1.0), nearest-rank.
+    pub(crate) fn reply_percentile(&self, p: f64) -> u64 {
+        percentile(&self.reply_samples, p)
+    }
+
+    /// Replies in the end-to-end sample window.
+    pub(crate) fn reply_sample_count(&self) -> usize {
+        self.reply_samples.lock().map(|s| s.len()).unwrap_or(0)
+    }
+
+    /// The transport connected: the first time, or after a reconnect.
+    pub(crate) fn record_connected(&self) {
+        let now = crate::helpers::epoch_now();
+        self.connected_at.store(now, Ordering::Relaxed);
+        if self.reconnects.get() > 0 {
+            self.last_reconnect_at.store(now, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_disconnected(&self) {
+        self.connected_at.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_failure(&self, category: &'static str) {
+        self.failures
+            .entry(category)
+            .or_insert_with(|| AtomicU64::new(0))
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Failure counts sorted by category name.
+    pub(crate) fn failure_counts(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<_> = self
+            .failures
+            .iter()
+            .map(|e| (*e.key(), e.value().load(Ordering::Relaxed)))
+            .collect();
+        counts.sort();
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_counter_sums_shards_across_threads() {
+        let counter = Arc::new(Counter::new());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        counter.incr();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(counter.get(), 8000);
+        counter.set(3);
+        assert_eq!(counter.get(), 3);
+    }
+
+    #[test]
+    fn test_record_latency() {
+        let metrics = Metrics::new();
+        for ms in 1..=100 {
+            metrics.record_latency(ms);
+        }
+        assert_eq!(metrics.latency_count.get(), 100);
+        assert_eq!(metrics.latency_sum_ms.get(), 5050);
+        assert_eq!(metrics.latency_percentile(0.95), 95);
+    }
+}
@@ -0,0 +1,136 @@
+//! Side sessions addressed by name: `@work: ...`.
+//!
+//! A message starting with `@<name>:` goes to a Claude session of that name
+//! instead of the sender's usual one, which stays active for everything
+//! else. The named session is created on first use and has its own Claude
+//! context, lock and workspace, so a question for it can run alongside the
+//! main conversation. Replies are marked with the name so it's clear which
+//! session answered. Named sessions expire with `--session-ttl` like the
+//! main one.
+
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+use crate::state::State;
+
+/// Longest session name `@name:` accepts.
+const MAX_NAME_LEN: usize = 32;
+
+/// One of a sender's named sessions.
+pub(crate) struct NamedSession {
+    pub(crate) session_id: String,
+    pub(crate) lock: Arc<Mutex<()>>,
+    pub(crate) last_activity: Instant,
+}
+
+/// The session name and the rest of a message starting `@name:`. Names are
+/// letters, digits, `-` and `_`, compared without case.
+pub(crate) fn addressed(text: &str) -> Option<(String, &str)> {
+    let (name, rest) = text.trim_start().strip_prefix('@')?.split_once(':')?;
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+    let rest = rest.trim();
+    (valid && !rest.is_empty()).then(|| (name.to_ascii_lowercase(), rest))
+}
+
+/// `sender`'s session called `name`, created if needed: (session id, model,
+/// lock, whether it's new). It uses the model of the sender's main session.
+pub(crate) fn session(
+    state: &State,
+    sender: &str,
+    name: &str,
+) -> (String, String, Arc<Mutex<()>>, bool) {
+    let model = state
+        .session_mgr
+        .sessions
+        .get(sender)
+        .map(|s| s.model.clone())
+        .unwrap_or_else(|| state.settings().model);
+    let key = (sender.to_string(), name.to_string());
+    let is_new = !state.named_sessions.contains_key(&key);
+    let mut entry = state.named_sessions.entry(key).or_insert_with(|| {
+        let session_id = uuid::Uuid::new_v4().to_string();
+        tracing::info!(sender = %sender, name = %name, session_id = %session_id, "New named session created");
+        NamedSession {
+            session_id,
+            lock: Arc::new(Mutex::new(())),
+            last_activity: Instant::now(),
+        }
+    });
+    entry.last_activity = Instant::now();
+    (entry.session_id.clone(), model, entry.lock.clone(), is_new)
+}
+
+/// Give `sender`'s session `name` a fresh Claude session id (claude lost the
+/// old one), keeping its workspace.
+pub(crate) fn rotate(state: &State, sender: &str, name: &str) -> String {
+    let new_id = uuid::Uuid::new_v4().to_string();
+    let key = (sender.to_string(), name.to_string());
+    if let Some(mut session) = state.named_sessions.get_mut(&key) {
+        let old_dir = crate::helpers::session_workdir(sender, &session.session_id);
+        if old_dir.exists() {
+            let _ = std::fs::rename(&old_dir, crate::helpers::session_workdir(sender, &new_id));
+        }
+        session.session_id = new_id.clone();
+    }
+    new_id
+}
+
+/// Names of `sender`'s named sessions, sorted.
+pub(crate) fn names(state: &State, sender: &str) -> Vec<String> {
+    let mut names: Vec<String> = state
+        .named_sessions
+        .iter()
+        .filter(|entry| entry.key().0 == sender)
+        .map(|entry| entry.key().1.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_addressed() {
+        assert_eq!(
+            addressed("@work: deploy staging"),
+            Some(("work".to_string(), "deploy staging"))
+        );
+        assert_eq!(
+            addressed("@Side-Project:hi"),
+            Some(("side-project".to_string(), "hi"))
+        );
+        assert_eq!(addressed("@work:"), None);
+        assert_eq!(addressed("@: hi"), None);
+        assert_eq!(addressed("@bob, see 10:30"), None);
+        assert_eq!(addressed("email me@example.com: later"), None);
+        assert_eq!(addressed("hi @work: there"), None);
+    }
+
+    #[test]
+    fn test_session_is_separate_and_reused() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        let (main_id, _, _, _) = state.get_or_create_session("+1");
+        let (work_id, model, _, is_new) = session(&state, "+1", "work");
+        assert!(is_new);
+        assert_ne!(work_id, main_id);
+        assert_eq!(model, "sonnet");
+        let (again, _, _, is_new) = session(&state, "+1", "work");
+        assert_eq!(again, work_id);
+        assert!(!is_new);
+
+        let rotated = rotate(&state, "+1", "work");
+        assert_eq!(session(&state, "+1", "work").0, rotated);
+        session(&state, "+1", "home");
+        assert_eq!(names(&state, "+1"), vec!["home", "work"]);
+        assert!(names(&state, "+2").is_empty());
+    }
+}
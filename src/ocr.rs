@@ -0,0 +1,135 @@
+//! OCR for image attachments (`--ocr`).
+//!
+//! Screenshots and photos of documents are run through `tesseract`, and
+//! the text it finds goes into the prompt next to the image, so questions
+//! like "what does this error say?" work with a model that can't see images
+//! or with a runner that drops them. Images that come back with only a
+//! few stray characters (photos of things rather than text) add nothing.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, warn};
+
+const IMAGE_EXTENSIONS: &[&str] = &["bmp", "gif", "jpeg", "jpg", "png", "tif", "tiff", "webp"];
+/// Fewest real words for an image to count as text.
+const MIN_WORDS: usize = 5;
+/// Most characters of recognised text kept per image.
+const MAX_CHARS: usize = 8_000;
+
+static MISSING_WARNED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn is_image(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Whether OCR output reads as text: enough words, and most tokens words
+/// rather than the symbol noise tesseract produces for pictures.
+pub(crate) fn looks_like_text(ocr: &str) -> bool {
+    let tokens: Vec<&str> = ocr.split_whitespace().collect();
+    let words = tokens
+        .iter()
+        .filter(|t| t.chars().filter(|c| c.is_alphanumeric()).count() >= 2)
+        .count();
+    words >= MIN_WORDS && words * 2 >= tokens.len()
+}
+
+/// Trim trailing space, squeeze runs of blank lines and cap the length.
+pub(crate) fn tidy(ocr: &str) -> String {
+    let mut out = String::new();
+    let mut blank = false;
+    for line in ocr.lines().map(str::trim_end) {
+        if line.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    let out = out.trim_end();
+    if out.chars().count() > MAX_CHARS {
+        let cut: String = out.chars().take(MAX_CHARS).collect();
+        format!("{cut}\n[...]")
+    } else {
+        out.to_string()
+    }
+}
+
+async fn run_tesseract(path: &Path) -> Option<String> {
+    let output = match tokio::process::Command::new("tesseract")
+        .arg(path)
+        .arg("-")
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if !MISSING_WARNED.swap(true, Ordering::Relaxed) {
+                warn!("--ocr is on but tesseract isn't installed; images go to Claude as-is");
+            }
+            return None;
+        }
+        Err(e) => {
+            warn!("tesseract failed to start: {e}");
+            return None;
+        }
+    };
+    if !output.status.success() {
+        debug!(
+            "tesseract failed on {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Prompt context with the text found in each image attachment that has
+/// any, or None.
+pub(crate) async fn extract_text(attachments: &[PathBuf]) -> Option<String> {
+    let mut sections = Vec::new();
+    for path in attachments.iter().filter(|p| is_image(p)) {
+        let Some(ocr) = run_tesseract(path).await else {
+            continue;
+        };
+        if !looks_like_text(&ocr) {
+            continue;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        sections.push(format!("[Text in image {name} (OCR)]\n{}", tidy(&ocr)));
+    }
+    (!sections.is_empty()).then(|| sections.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_like_text() {
+        assert!(looks_like_text(
+            "error[E0382]: borrow of moved value: `config`\n  --> src/main.rs:42:5"
+        ));
+        // What tesseract makes of a holiday photo
+        assert!(!looks_like_text("~ = . | ‘ ,\n_ \\ a . ~~ i"));
+        assert!(!looks_like_text("EXIT"));
+        assert!(!is_image(Path::new("/tmp/notes.pdf")));
+        assert!(is_image(Path::new("/tmp/shot.PNG")));
+    }
+
+    #[test]
+    fn test_tidy_squeezes_blank_lines() {
+        assert_eq!(
+            tidy("\n\nline one   \n\n\n\nline two\n\n"),
+            "line one\n\nline two"
+        );
+        let long = "word ".repeat(MAX_CHARS);
+        assert!(tidy(&long).ends_with("\n[...]"));
+    }
+}
@@ -0,0 +1,141 @@
+//! Outgoing send pacing for signal-cli-api (`--send-rate`, `--send-jitter-ms`).
+//!
+//! Signal quietly drops or delays messages sent in quick succession. Every
+//! request that makes Signal send something (messages, attachments,
+//! stickers, typing indicators, reactions) takes its turn on one shared
+//! schedule, spaced by the configured rate plus a little random jitter. A
+//! 429 or 5xx from signal-cli-api widens the spacing, doubling up to
+//! [`SEND_BACKOFF_MAX_MS`], and each success narrows it again.
+
+use reqwest::StatusCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::constants::{SEND_BACKOFF_BASE_MS, SEND_BACKOFF_MAX_MS};
+
+pub(crate) struct SendPacer {
+    /// Gap between sends at the configured rate (zero = unpaced).
+    interval: Duration,
+    jitter_ms: u64,
+    /// When the next send may go.
+    next_slot: Mutex<Instant>,
+    /// Extra gap after rate limiting or server errors.
+    backoff_ms: AtomicU64,
+}
+
+impl SendPacer {
+    /// `rate` sends per second (0 = no limit), each delayed by up to
+    /// `jitter_ms` more.
+    pub(crate) fn new(rate: f64, jitter_ms: u64) -> Self {
+        let interval = if rate > 0.0 {
+            Duration::from_secs_f64(1.0 / rate)
+        } else {
+            Duration::ZERO
+        };
+        Self {
+            interval,
+            jitter_ms,
+            next_slot: Mutex::new(Instant::now()),
+            backoff_ms: AtomicU64::new(0),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn unpaced() -> Self {
+        Self::new(0.0, 0)
+    }
+
+    /// Current extra gap from backoff.
+    pub(crate) fn backoff(&self) -> Duration {
+        Duration::from_millis(self.backoff_ms.load(Ordering::Relaxed))
+    }
+
+    fn gap(&self) -> Duration {
+        let jitter = if self.jitter_ms == 0 {
+            0
+        } else {
+            // Sub-second clock noise is random enough to spread sends out.
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .subsec_nanos() as u64;
+            nanos % (self.jitter_ms + 1)
+        };
+        self.interval + self.backoff() + Duration::from_millis(jitter)
+    }
+
+    /// Wait for this request's turn.
+    pub(crate) async fn wait(&self) {
+        let slot = {
+            let mut next = self.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.gap();
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+
+    /// Adjust the backoff for signal-cli-api's answer to a paced request.
+    pub(crate) fn record(&self, status: StatusCode) {
+        let backoff = self.backoff_ms.load(Ordering::Relaxed);
+        let next = if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            let next = (backoff * 2).clamp(SEND_BACKOFF_BASE_MS, SEND_BACKOFF_MAX_MS);
+            warn!("signal-cli-api answered {status}; spacing sends by an extra {next}ms");
+            // Hold off whatever goes next, including a retry of this request
+            let mut slot = self.next_slot.lock().unwrap_or_else(|e| e.into_inner());
+            *slot = (*slot).max(Instant::now() + Duration::from_millis(next));
+            next
+        } else if backoff / 2 < SEND_BACKOFF_BASE_MS {
+            0
+        } else {
+            backoff / 2
+        };
+        self.backoff_ms.store(next, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_spaces_sends_at_rate() {
+        let pacer = SendPacer::new(20.0, 0);
+        let start = Instant::now();
+        for _ in 0..3 {
+            pacer.wait().await;
+        }
+        // First goes straight away, then one every 50ms
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    }
+
+    #[test]
+    fn test_backoff_doubles_on_errors_and_decays() {
+        let pacer = SendPacer::unpaced();
+        pacer.record(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(pacer.backoff(), Duration::from_millis(SEND_BACKOFF_BASE_MS));
+        pacer.record(StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            pacer.backoff(),
+            Duration::from_millis(SEND_BACKOFF_BASE_MS * 2)
+        );
+        for _ in 0..20 {
+            pacer.record(StatusCode::SERVICE_UNAVAILABLE);
+        }
+        assert_eq!(pacer.backoff(), Duration::from_millis(SEND_BACKOFF_MAX_MS));
+        // A client error isn't Signal pushing back
+        pacer.record(StatusCode::BAD_REQUEST);
+        assert_eq!(
+            pacer.backoff(),
+            Duration::from_millis(SEND_BACKOFF_MAX_MS / 2)
+        );
+        for _ in 0..20 {
+            pacer.record(StatusCode::OK);
+        }
+        assert_eq!(pacer.backoff(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,210 @@
+//! Per-project tool policies (`.ccchat.toml`).
+//!
+//! A project directory can carry a `.ccchat.toml` saying what Claude may do
+//! there, whoever is asking:
+//!
+//! ```toml
+//! [tools]
+//! allow = ["Read", "Grep", "Glob"]
+//! deny = ["WebFetch"]
+//! bash = ["cargo", "git"]   # Bash, but only these commands
+//! write = ["src/**"]        # Write and Edit, but only under these paths
+//! ```
+//!
+//! The file is read when a group is bound to the project (and at startup
+//! and config reload), not on every run, so Claude editing it doesn't change
+//! its own permissions until an admin binds again. Each run in the project
+//! gets the policy as `--allowedTools`/`--disallowedTools` rules. A policy
+//! file that doesn't parse stops Claude running there at all rather than
+//! running it unrestricted.
+
+use dashmap::DashMap;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+pub(crate) const POLICY_FILE: &str = ".ccchat.toml";
+
+/// Tools that change files, limited together by `write`.
+const WRITE_TOOLS: [&str; 3] = ["Write", "Edit", "MultiEdit"];
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ToolPolicy {
+    /// Tools or Claude Code permission rules allowed as written.
+    #[serde(default)]
+    allow: Vec<String>,
+    /// Tools or rules that are always refused.
+    #[serde(default)]
+    deny: Vec<String>,
+    /// Commands Bash may run (`cargo` allows `cargo test`, `cargo build`, ...).
+    #[serde(default)]
+    bash: Vec<String>,
+    /// Paths (globs, relative to the project) file edits may touch.
+    #[serde(default)]
+    write: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    tools: ToolPolicy,
+}
+
+impl ToolPolicy {
+    fn allowed(&self) -> Vec<String> {
+        let mut rules = self.allow.clone();
+        rules.extend(self.bash.iter().map(|cmd| format!("Bash({cmd}:*)")));
+        for path in &self.write {
+            rules.extend(WRITE_TOOLS.iter().map(|tool| format!("{tool}({path})")));
+        }
+        rules
+    }
+
+    /// `claude` flags that apply this policy.
+    pub(crate) fn claude_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for rule in self.allowed() {
+            args.push("--allowedTools".to_string());
+            args.push(rule);
+        }
+        for rule in &self.deny {
+            args.push("--disallowedTools".to_string());
+            args.push(rule.clone());
+        }
+        args
+    }
+
+    /// One line per setting, for `/bind`.
+    pub(crate) fn describe(&self) -> String {
+        let list = |items: &[String]| items.join(", ");
+        let mut lines = Vec::new();
+        if !self.allow.is_empty() {
+            lines.push(format!("Allowed: {}", list(&self.allow)));
+        }
+        if !self.bash.is_empty() {
+            lines.push(format!("Bash only for: {}", list(&self.bash)));
+        }
+        if !self.write.is_empty() {
+            lines.push(format!("Edits only under: {}", list(&self.write)));
+        }
+        if !self.deny.is_empty() {
+            lines.push(format!("Denied: {}", list(&self.deny)));
+        }
+        if lines.is_empty() {
+            lines.push("No rules (Claude Code's own settings apply)".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+/// Read `dir`'s policy file; `None` when there isn't one.
+pub(crate) fn read(dir: &Path) -> Result<Option<ToolPolicy>, String> {
+    let path = dir.join(POLICY_FILE);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("{}: {e}", path.display())),
+    };
+    toml::from_str::<PolicyFile>(&text)
+        .map(|file| Some(file.tools))
+        .map_err(|e| format!("{}: {}", path.display(), e.message()))
+}
+
+/// Policies of the projects groups are bound to, by project directory.
+/// Shared between `State` and the Claude runner.
+#[derive(Default)]
+pub(crate) struct ToolPolicies {
+    by_dir: DashMap<PathBuf, Result<ToolPolicy, String>>,
+}
+
+impl ToolPolicies {
+    /// (Re)read `dir`'s policy file and use it for runs there from now on.
+    pub(crate) fn load(&self, dir: &Path) -> Result<Option<ToolPolicy>, String> {
+        let policy = read(dir);
+        match &policy {
+            Ok(None) => {
+                self.by_dir.remove(dir);
+            }
+            Ok(Some(p)) => {
+                self.by_dir.insert(dir.to_path_buf(), Ok(p.clone()));
+            }
+            Err(e) => {
+                self.by_dir.insert(dir.to_path_buf(), Err(e.clone()));
+            }
+        }
+        policy
+    }
+
+    /// `claude` flags for a run in `dir`, or why Claude mustn't run there.
+    pub(crate) fn claude_args(&self, dir: &Path) -> Result<Vec<String>, String> {
+        match self.by_dir.get(dir).as_deref() {
+            None => Ok(Vec::new()),
+            Some(Ok(policy)) => Ok(policy.claude_args()),
+            Some(Err(e)) => Err(format!(
+                "This project's {POLICY_FILE} is invalid, so Claude won't run here until it's fixed and the group is bound again ({e})"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(policy: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ccchat_policy_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(POLICY_FILE), policy).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_policy_becomes_permission_flags() {
+        let dir = project(
+            "[tools]\nallow = [\"Read\"]\ndeny = [\"WebFetch\"]\nbash = [\"cargo\", \"git\"]\nwrite = [\"src/**\"]\n",
+        );
+        let policy = read(&dir).unwrap().unwrap();
+        assert_eq!(
+            policy.claude_args(),
+            [
+                "--allowedTools",
+                "Read",
+                "--allowedTools",
+                "Bash(cargo:*)",
+                "--allowedTools",
+                "Bash(git:*)",
+                "--allowedTools",
+                "Write(src/**)",
+                "--allowedTools",
+                "Edit(src/**)",
+                "--allowedTools",
+                "MultiEdit(src/**)",
+                "--disallowedTools",
+                "WebFetch",
+            ]
+        );
+        assert!(policy.describe().contains("Bash only for: cargo, git"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_policies_load_on_bind_and_fail_closed() {
+        let policies = ToolPolicies::default();
+        let dir = project("[tools]\nbash = [\"git\"]\n");
+        assert!(policies.claude_args(&dir).unwrap().is_empty());
+        policies.load(&dir).unwrap();
+        // Edits to the file only count once it's loaded again
+        std::fs::write(dir.join(POLICY_FILE), "[tools]\nallow = [\"Bash\"]\n").unwrap();
+        assert_eq!(policies.claude_args(&dir).unwrap()[1], "Bash(git:*)");
+
+        std::fs::write(dir.join(POLICY_FILE), "[tools]\nbash = \"git\"\n").unwrap();
+        assert!(policies.load(&dir).is_err());
+        assert!(policies.claude_args(&dir).is_err());
+
+        std::fs::remove_file(dir.join(POLICY_FILE)).unwrap();
+        assert_eq!(policies.load(&dir), Ok(None));
+        assert!(policies.claude_args(&dir).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
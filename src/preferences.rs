@@ -0,0 +1,403 @@
+//! Per-sender preferences (`/prefs`, `/format`), kept as JSON in the
+//! sender's memory database and loaded into their session.
+
+use chrono::Timelike;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::format::FormatPrefs;
+
+/// How much detail replies should go into.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Verbosity {
+    Brief,
+    #[default]
+    Normal,
+    Detailed,
+}
+
+impl Verbosity {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "brief" => Some(Self::Brief),
+            "normal" => Some(Self::Normal),
+            "detailed" => Some(Self::Detailed),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Brief => "brief",
+            Self::Normal => "normal",
+            Self::Detailed => "detailed",
+        }
+    }
+}
+
+/// Daily window (server local time) when scheduled messages are held back.
+/// Minutes after midnight; `start > end` wraps past midnight.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct QuietHours {
+    pub(crate) start: u16,
+    pub(crate) end: u16,
+}
+
+impl QuietHours {
+    /// Parse `22:00-07:00` (or `22-7`).
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let (start, end) = s.split_once('-')?;
+        let minutes = |t: &str| -> Option<u16> {
+            let (h, m) = t.trim().split_once(':').unwrap_or((t.trim(), "0"));
+            let (h, m): (u16, u16) = (h.parse().ok()?, m.parse().ok()?);
+            (h < 24 && m < 60).then_some(h * 60 + m)
+        };
+        let (start, end) = (minutes(start)?, minutes(end)?);
+        (start != end).then_some(Self { start, end })
+    }
+
+    pub(crate) fn contains(self, minute_of_day: u16) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute_of_day)
+        } else {
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+
+    pub(crate) fn contains_now(self) -> bool {
+        let now = chrono::Local::now();
+        self.contains((now.hour() * 60 + now.minute()) as u16)
+    }
+}
+
+impl std::fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+/// Reaction standing in for typing indicators with `typing react`.
+pub(crate) const WORKING_EMOJI: &str = "👀";
+
+/// What a chat sees while Claude works on a message.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Typing {
+    /// Typing indicators.
+    #[default]
+    On,
+    Off,
+    /// One reaction with this emoji on the message being worked on.
+    React(String),
+}
+
+impl Typing {
+    /// Parse `on`, `off`, `react` or `react <emoji>`.
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s.split_once(' ').unwrap_or((s, "")) {
+            ("on", "") => Some(Self::On),
+            ("off", "") => Some(Self::Off),
+            ("react", "") => Some(Self::React(WORKING_EMOJI.to_string())),
+            ("react", emoji) => Some(Self::React(emoji.trim().to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Typing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::On => write!(f, "on"),
+            Self::Off => write!(f, "off"),
+            Self::React(emoji) => write!(f, "react {emoji}"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct Preferences {
+    /// `/format` choices; None follows the server's setting.
+    pub(crate) format: Option<FormatPrefs>,
+    /// Add the model and cost under each Claude reply.
+    pub(crate) footer: bool,
+    /// Send a revised reply ("try again but shorter") as a diff.
+    pub(crate) diff: bool,
+    pub(crate) verbosity: Verbosity,
+    /// Language to reply in, e.g. `de` or `pt-BR`.
+    pub(crate) locale: Option<String>,
+    pub(crate) quiet_hours: Option<QuietHours>,
+    /// How Claude should come across, e.g. "a patient maths tutor".
+    pub(crate) persona: Option<String>,
+    /// Sampling temperature, 0 to 1, for runners that support it.
+    pub(crate) temperature: Option<f64>,
+    /// Most tokens in one reply.
+    pub(crate) max_tokens: Option<u32>,
+    pub(crate) typing: Typing,
+}
+
+/// Keys `/prefs set` and `/prefs reset` accept.
+pub(crate) const KEYS: [&str; 9] = [
+    "footer",
+    "diff",
+    "verbosity",
+    "locale",
+    "quiet-hours",
+    "persona",
+    "temperature",
+    "max-tokens",
+    "typing",
+];
+
+impl Preferences {
+    /// Change one preference from `/prefs set <key> <value>`.
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "footer" => {
+                self.footer = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("footer is on or off".to_string()),
+                }
+            }
+            "diff" => {
+                self.diff = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("diff is on or off".to_string()),
+                }
+            }
+            "verbosity" => {
+                self.verbosity =
+                    Verbosity::parse(value).ok_or("verbosity is brief, normal or detailed")?
+            }
+            "locale" => self.locale = Some(value.to_string()),
+            "quiet-hours" if value == "off" => self.quiet_hours = None,
+            "quiet-hours" => {
+                self.quiet_hours = Some(
+                    QuietHours::parse(value).ok_or("quiet-hours looks like 22:00-07:00, or off")?,
+                )
+            }
+            "persona" => self.persona = Some(value.to_string()),
+            "temperature" => {
+                self.temperature = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|t: &f64| (0.0..=1.0).contains(t))
+                        .ok_or("temperature is a number from 0 to 1")?,
+                )
+            }
+            "max-tokens" => {
+                self.max_tokens = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|n: &u32| *n > 0)
+                        .ok_or("max-tokens is a whole number above 0")?,
+                )
+            }
+            "typing" => {
+                self.typing =
+                    Typing::parse(value).ok_or("typing is on, off, react or react <emoji>")?
+            }
+            _ => return Err(format!("Unknown preference {key}")),
+        }
+        Ok(())
+    }
+
+    /// Put one preference back to its default.
+    pub(crate) fn reset(&mut self, key: &str) -> Result<(), String> {
+        let defaults = Self::default();
+        match key {
+            "footer" => self.footer = defaults.footer,
+            "diff" => self.diff = defaults.diff,
+            "verbosity" => self.verbosity = defaults.verbosity,
+            "locale" => self.locale = None,
+            "quiet-hours" => self.quiet_hours = None,
+            "persona" => self.persona = None,
+            "temperature" => self.temperature = None,
+            "max-tokens" => self.max_tokens = None,
+            "typing" => self.typing = defaults.typing,
+            _ => return Err(format!("Unknown preference {key}")),
+        }
+        Ok(())
+    }
+
+    /// Current value of `key` for `/prefs`.
+    pub(crate) fn get(&self, key: &str) -> String {
+        match key {
+            "footer" => if self.footer { "on" } else { "off" }.to_string(),
+            "diff" => if self.diff { "on" } else { "off" }.to_string(),
+            "verbosity" => self.verbosity.as_str().to_string(),
+            "locale" => self.locale.clone().unwrap_or_else(|| "(any)".to_string()),
+            "quiet-hours" => self
+                .quiet_hours
+                .map(|q| q.to_string())
+                .unwrap_or_else(|| "off".to_string()),
+            "persona" => self.persona.clone().unwrap_or_else(|| "(none)".to_string()),
+            "temperature" => self
+                .temperature
+                .map(|t| t.to_string())
+                .unwrap_or_else(|| "(model default)".to_string()),
+            "max-tokens" => self
+                .max_tokens
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| "(model default)".to_string()),
+            "typing" => self.typing.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Generation settings for the Claude runner.
+    pub(crate) fn run_options(&self) -> crate::traits::RunOptions {
+        crate::traits::RunOptions {
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            ask_only: false,
+        }
+    }
+
+    /// System prompt lines for verbosity, locale and persona.
+    pub(crate) fn prompt_hints(&self) -> Option<String> {
+        let mut hints = Vec::new();
+        if let Some(persona) = &self.persona {
+            hints.push(format!("Persona: {persona}."));
+        }
+        match self.verbosity {
+            Verbosity::Brief => hints.push("Keep replies short: answer first, no preamble.".into()),
+            Verbosity::Detailed => {
+                hints.push("Give thorough replies with reasoning and examples.".into())
+            }
+            Verbosity::Normal => {}
+        }
+        if let Some(locale) = &self.locale {
+            hints.push(format!(
+                "Reply in the language of locale {locale} unless asked otherwise."
+            ));
+        }
+        (!hints.is_empty()).then(|| hints.join("\n"))
+    }
+}
+
+/// The sender's saved preferences (defaults if none).
+pub(crate) fn load(sender: &str) -> Preferences {
+    let Ok(conn) = crate::memory::open_memory_db(sender) else {
+        return Preferences::default();
+    };
+    conn.query_row("SELECT data FROM preferences WHERE id = 1", [], |row| {
+        row.get::<_, String>(0)
+    })
+    .optional()
+    .ok()
+    .flatten()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default()
+}
+
+pub(crate) fn save(sender: &str, prefs: &Preferences) {
+    let Ok(conn) = crate::memory::open_memory_db(sender) else {
+        return;
+    };
+    let Ok(json) = serde_json::to_string(prefs) else {
+        return;
+    };
+    if let Err(e) = conn.execute(
+        "INSERT INTO preferences (id, data, updated_at) VALUES (1, ?1, ?2)
+         ON CONFLICT(id) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+        rusqlite::params![json, crate::helpers::epoch_now()],
+    ) {
+        error!("Failed to save preferences: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quiet_hours_parse_and_wrap() {
+        let night = QuietHours::parse("22:00-07:30").unwrap();
+        assert_eq!(night.to_string(), "22:00-07:30");
+        assert!(night.contains(23 * 60));
+        assert!(night.contains(7 * 60 + 29));
+        assert!(!night.contains(12 * 60));
+        let lunch = QuietHours::parse("12-13").unwrap();
+        assert!(lunch.contains(12 * 60 + 30));
+        assert!(!lunch.contains(13 * 60));
+        assert!(QuietHours::parse("25:00-07:00").is_none());
+        assert!(QuietHours::parse("9-9").is_none());
+    }
+
+    #[test]
+    fn test_set_reset_and_prompt_hints() {
+        let mut prefs = Preferences::default();
+        assert_eq!(prefs.prompt_hints(), None);
+        prefs.set("verbosity", "brief").unwrap();
+        prefs.set("locale", "de").unwrap();
+        prefs.set("persona", "a patient maths tutor").unwrap();
+        assert!(prefs.set("footer", "maybe").is_err());
+        assert!(prefs.set("colour", "blue").is_err());
+        assert_eq!(
+            prefs.prompt_hints().unwrap(),
+            "Persona: a patient maths tutor.\nKeep replies short: answer first, no preamble.\nReply in the language of locale de unless asked otherwise."
+        );
+        prefs.reset("persona").unwrap();
+        assert_eq!(prefs.get("persona"), "(none)");
+        assert_eq!(prefs.get("verbosity"), "brief");
+    }
+
+    #[test]
+    fn test_generation_settings() {
+        let mut prefs = Preferences::default();
+        assert_eq!(prefs.run_options(), crate::traits::RunOptions::default());
+        assert!(prefs.set("temperature", "1.5").is_err());
+        assert!(prefs.set("max-tokens", "0").is_err());
+        prefs.set("temperature", "0.2").unwrap();
+        prefs.set("max-tokens", "2000").unwrap();
+        let options = prefs.run_options();
+        assert_eq!(options.temperature, Some(0.2));
+        assert_eq!(
+            options.cli_env(),
+            [("CLAUDE_CODE_MAX_OUTPUT_TOKENS", "2000".to_string())]
+        );
+        prefs.reset("temperature").unwrap();
+        assert_eq!(prefs.get("temperature"), "(model default)");
+    }
+
+    #[test]
+    fn test_typing() {
+        let mut prefs = Preferences::default();
+        assert_eq!(prefs.get("typing"), "on");
+        prefs.set("typing", "react").unwrap();
+        assert_eq!(prefs.typing, Typing::React(WORKING_EMOJI.to_string()));
+        prefs.set("typing", "react 🛠️").unwrap();
+        assert_eq!(prefs.get("typing"), "react 🛠️");
+        prefs.set("typing", "off").unwrap();
+        assert!(prefs.set("typing", "sometimes").is_err());
+        assert_eq!(prefs.typing, Typing::Off);
+    }
+
+    #[test]
+    fn test_round_trip_through_memory_db() {
+        let sender = "+prefs_round_trip";
+        let mut prefs = Preferences::default();
+        prefs.set("quiet-hours", "22-7").unwrap();
+        prefs.footer = true;
+        prefs.typing = Typing::React("🛠️".to_string());
+        save(sender, &prefs);
+        assert_eq!(load(sender), prefs);
+        crate::memory::delete_memory(sender);
+        assert_eq!(load(sender), Preferences::default());
+        crate::memory::delete_memory(sender);
+    }
+}
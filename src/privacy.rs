@@ -0,0 +1,93 @@
+//! Private sessions (`/private on`).
+//!
+//! While a chat's session is private, nothing from it is kept: messages
+//! aren't written to memory, no summary of it is saved (on compaction,
+//! `/reset`, expiry or shutdown), prompts aren't journaled or queued for
+//! retry, attachments are deleted once answered, and logs leave out what
+//! was said. `claude` itself already runs without session persistence.
+//! When the session ends, its workspace is wiped with it and the next
+//! session is an ordinary one again.
+
+use crate::state::State;
+
+/// Stands in for private text in logs.
+pub(crate) const REDACTED: &str = "[private]";
+
+pub(crate) fn is_private(state: &State, sender: &str) -> bool {
+    state.private_sessions.contains_key(sender)
+}
+
+/// `text` if it may be logged, [`REDACTED`] if it's from a private session.
+pub(crate) fn loggable<'a>(state: &State, sender: &'a str, text: &'a str) -> &'a str {
+    if is_private(state, sender) {
+        REDACTED
+    } else {
+        text
+    }
+}
+
+/// Mark `sender`'s current session private.
+pub(crate) fn start(state: &State, sender: &str) {
+    state.get_or_create_session(sender);
+    state.private_sessions.insert(sender.to_string(), ());
+    // Anything waiting to be carried into the next session came from this one.
+    state.session_mgr.truncated_sessions.remove(sender);
+    state.session_mgr.budget_hits.remove(sender);
+    state.pending_carries.remove(sender);
+}
+
+/// The private session of `sender` (id `session_id`) is over: drop what
+/// ccchat held for it and wipe its workspace. Returns false if the session
+/// wasn't private, leaving everything as it was.
+pub(crate) fn end(state: &State, sender: &str, session_id: &str) -> bool {
+    if state.private_sessions.remove(sender).is_none() {
+        return false;
+    }
+    state.compacted_context.remove(sender);
+    state.session_mgr.truncated_sessions.remove(sender);
+    state.session_mgr.budget_hits.remove(sender);
+    state.session_tokens.remove(sender);
+    state.pending_carries.remove(sender);
+    state.last_replies.remove(sender);
+    crate::commands::remove_session_workdir(sender, session_id);
+    tracing::info!(sender = %sender, "Private session ended and wiped");
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_start_and_end() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        let sender = format!("+private_{}", uuid::Uuid::new_v4());
+        assert_eq!(loggable(&state, &sender, "hello"), "hello");
+        start(&state, &sender);
+        assert!(is_private(&state, &sender));
+        assert_eq!(loggable(&state, &sender, "hello"), REDACTED);
+
+        let session_id = state
+            .session_mgr
+            .sessions
+            .get(&sender)
+            .unwrap()
+            .session_id
+            .clone();
+        let workdir = crate::helpers::session_workdir(&sender, &session_id);
+        std::fs::create_dir_all(&workdir).unwrap();
+        std::fs::write(workdir.join("notes.txt"), "secret").unwrap();
+        state
+            .compacted_context
+            .insert(sender.clone(), "summary".to_string());
+
+        assert!(end(&state, &sender, &session_id));
+        assert!(!is_private(&state, &sender));
+        assert!(!workdir.exists());
+        assert!(!state.compacted_context.contains_key(&sender));
+        // Ending an ordinary session touches nothing.
+        assert!(!end(&state, &sender, &session_id));
+    }
+}
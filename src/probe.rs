@@ -0,0 +1,334 @@
+//! Model availability probes (`--probe-mins`) and `--fallback-model`.
+//!
+//! At startup and every `--probe-mins` minutes, each model in use (the
+//! default, the fallback, `model_routes` targets and models picked with
+//! `/model`) gets a one-line prompt. A model whose probe fails, say because
+//! the login expired or the API is down, is marked down until a later probe
+//! succeeds, and runs that would use it go to the fallback model instead. A
+//! real run failing that way marks its model down too and is retried once on
+//! the fallback. The account owner hears when a model goes down and when it's
+//! back; `/status` and the stats endpoints show each model's state.
+
+use dashmap::DashMap;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::constants::{PROBE_BUDGET, PROBE_TIMEOUT_SECS};
+use crate::error::AppError;
+use crate::state::State;
+
+const PROBE_PROMPT: &str = "Reply with the single word OK.";
+
+/// What the last probe or run said about a model.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Health {
+    pub(crate) up: bool,
+    /// When it was last heard from (epoch secs).
+    pub(crate) checked: i64,
+    /// When it went up or down (epoch secs).
+    pub(crate) since: i64,
+    /// Why it's down.
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct ModelHealth {
+    models: DashMap<String, Health>,
+}
+
+impl ModelHealth {
+    pub(crate) fn is_down(&self, model: &str) -> bool {
+        self.models.get(model).is_some_and(|h| !h.up)
+    }
+
+    /// Record a probe or run outcome. True when the model went up or down.
+    pub(crate) fn record(&self, model: &str, error: Option<String>, now: i64) -> bool {
+        let up = error.is_none();
+        let mut entry = self.models.entry(model.to_string()).or_insert(Health {
+            up: true,
+            checked: now,
+            since: now,
+            error: None,
+        });
+        let changed = entry.up != up;
+        if changed {
+            entry.since = now;
+        }
+        entry.up = up;
+        entry.checked = now;
+        entry.error = error;
+        changed
+    }
+
+    /// Every model heard from, by name.
+    pub(crate) fn snapshot(&self) -> Vec<(String, Health)> {
+        let mut models: Vec<_> = self
+            .models
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect();
+        models.sort_by(|a, b| a.0.cmp(&b.0));
+        models
+    }
+}
+
+/// The model to use instead of `model` while it's down: the fallback, if
+/// one is set and isn't down too.
+pub(crate) fn fallback(state: &State, model: &str) -> Option<String> {
+    if !state.model_health.is_down(model) {
+        return None;
+    }
+    let fallback = resolve(state, state.config.fallback_model.as_deref()?);
+    (fallback != model && !state.model_health.is_down(&fallback)).then_some(fallback)
+}
+
+fn resolve(state: &State, name: &str) -> String {
+    state
+        .model_router
+        .read()
+        .map(|r| r.resolve(name))
+        .unwrap_or_else(|_| name.to_string())
+}
+
+/// Whether a failed run says more about the model or account than about
+/// the prompt: expired login, rate limits, overload, server errors.
+pub(crate) fn is_outage(e: &AppError) -> bool {
+    let AppError::Claude(msg) = e else {
+        return false;
+    };
+    let lower = msg.to_lowercase();
+    matches!(e.category(), "auth" | "rate_limited" | "timeout")
+        || ["api error", "not_found_error", "500", "502", "503", "529"]
+            .iter()
+            .any(|needle| lower.contains(needle))
+}
+
+/// Note that `model` is down or back, telling the account owner when that's news.
+async fn record(state: &State, model: &str, error: Option<String>) {
+    let now = crate::helpers::epoch_now();
+    if !state.model_health.record(model, error.clone(), now) {
+        return;
+    }
+    let notice = match &error {
+        Some(e) => {
+            warn!(model = %model, "Model unavailable: {e}");
+            match &state.config.fallback_model {
+                Some(fallback) => format!(
+                    "{model} is unavailable ({e}). Using {} until it's back.",
+                    resolve(state, fallback)
+                ),
+                None => format!("{model} is unavailable ({e})."),
+            }
+        }
+        None => {
+            info!(model = %model, "Model available again");
+            format!("{model} is available again.")
+        }
+    };
+    let _ = state.send_message(&state.config.account, &notice).await;
+}
+
+/// After a failed run on `model`: the model to retry on, if the failure
+/// looks like an outage and there's a fallback.
+pub(crate) async fn report_failure(state: &State, model: &str, e: &AppError) -> Option<String> {
+    if !is_outage(e) {
+        return None;
+    }
+    record(state, model, Some(first_line(&e.to_string()))).await;
+    fallback(state, model)
+}
+
+fn first_line(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    match line.char_indices().nth(200) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Models worth probing: the default, the fallback, routing targets and
+/// whatever sessions are using.
+fn models_in_use(state: &State) -> BTreeSet<String> {
+    let mut models = BTreeSet::from([resolve(state, &state.settings().model)]);
+    if let Some(fallback) = &state.config.fallback_model {
+        models.insert(resolve(state, fallback));
+    }
+    if let Ok(router) = state.model_router.read() {
+        models.extend(router.routes.iter().map(|r| router.resolve(&r.model)));
+    }
+    models.extend(
+        state
+            .session_mgr
+            .sessions
+            .iter()
+            .map(|s| resolve(state, &s.model)),
+    );
+    models
+}
+
+/// Send `model` a one-line prompt.
+async fn probe(state: &State, model: &str) -> Result<(), AppError> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let work_dir = crate::helpers::session_workdir("probe", &session_id);
+    let options = crate::traits::RunOptions::default();
+    let run = state.claude_runner.run_claude(
+        PROBE_PROMPT,
+        &session_id,
+        model,
+        &[],
+        &work_dir,
+        PROBE_BUDGET,
+        crate::NO_MEMORY_PROMPT,
+        &options,
+    );
+    let result = tokio::time::timeout(Duration::from_secs(PROBE_TIMEOUT_SECS), run).await;
+    crate::commands::remove_session_workdir("probe", &session_id);
+    let reply = result.map_err(|_| AppError::Claude("probe timed out".to_string()))??;
+    if let Some(cost) = reply.cost_usd {
+        state.add_cost(cost);
+    }
+    Ok(())
+}
+
+/// Probe every model in use. Returns how many are down.
+pub(crate) async fn probe_all(state: &State) -> usize {
+    let mut down = 0;
+    for model in models_in_use(state) {
+        let error = probe(state, &model)
+            .await
+            .err()
+            .map(|e| first_line(&e.to_string()));
+        down += usize::from(error.is_some());
+        record(state, &model, error).await;
+    }
+    down
+}
+
+/// `/status` line, or None before anything was probed.
+pub(crate) fn status(state: &State) -> Option<String> {
+    let models = state.model_health.snapshot();
+    if models.is_empty() {
+        return None;
+    }
+    let parts: Vec<String> = models
+        .iter()
+        .map(|(model, health)| match &health.error {
+            None => format!("{model} up"),
+            Some(e) => format!(
+                "{model} down since {} ({e})",
+                crate::memory::format_epoch(health.since.max(0) as u64)
+            ),
+        })
+        .collect();
+    Some(parts.join(", "))
+}
+
+pub(crate) fn stats_json(state: &State) -> serde_json::Value {
+    let models: serde_json::Map<String, serde_json::Value> = state
+        .model_health
+        .snapshot()
+        .into_iter()
+        .map(|(model, health)| {
+            (
+                model,
+                serde_json::json!({
+                    "up": health.up,
+                    "since": health.since,
+                    "checked": health.checked,
+                    "error": health.error,
+                }),
+            )
+        })
+        .collect();
+    serde_json::json!({
+        "fallback": state.config.fallback_model,
+        "models": models,
+    })
+}
+
+/// `ccchat_model_up` lines, empty before anything was probed.
+pub(crate) fn prometheus(state: &State) -> String {
+    let models = state.model_health.snapshot();
+    if models.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from(
+        "# HELP ccchat_model_up Whether the last probe or run of a model succeeded\n\
+         # TYPE ccchat_model_up gauge\n",
+    );
+    for (model, health) in models {
+        out.push_str(&format!(
+            "ccchat_model_up{{model=\"{model}\"}} {}\n",
+            u8::from(health.up)
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{ClaudeReply, MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_record_reports_changes_only() {
+        let health = ModelHealth::default();
+        assert!(!health.record("opus", None, 10));
+        assert!(health.record("opus", Some("401".to_string()), 20));
+        assert!(!health.record("opus", Some("401".to_string()), 30));
+        assert!(health.is_down("opus"));
+        let (_, opus) = &health.snapshot()[0];
+        assert_eq!((opus.since, opus.checked), (20, 30));
+        assert!(health.record("opus", None, 40));
+        assert!(!health.is_down("opus"));
+        assert!(!health.is_down("never-probed"));
+    }
+
+    #[test]
+    fn test_is_outage() {
+        assert!(is_outage(&AppError::Claude(
+            "Invalid API key · Please run /login".into()
+        )));
+        assert!(is_outage(&AppError::Claude(
+            "API Error: 529 overloaded".into()
+        )));
+        assert!(!is_outage(&AppError::Claude(
+            "claude exited with 1: bad flag".into()
+        )));
+        assert!(!is_outage(&AppError::Stopped));
+    }
+
+    #[tokio::test]
+    async fn test_probe_all_marks_models_and_routes_to_fallback() {
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_send_msg()
+            .withf(|_, msg| msg.starts_with("opus is unavailable") && msg.contains("Using sonnet"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let mut claude = MockClaudeRunner::new();
+        claude
+            .expect_run_claude()
+            .returning(|_, _, model, _, _, _, _, _| match model {
+                "opus" => Err(AppError::Claude("OAuth token has expired".into())),
+                _ => Ok(ClaudeReply::new("OK", Some(0.001))),
+            });
+        let mut state = test_state_with(signal, claude);
+        state.settings.write().unwrap().model = "opus".to_string();
+        state.config_mut().fallback_model = Some("sonnet".to_string());
+
+        assert_eq!(probe_all(&state).await, 1);
+        assert!(state.model_health.is_down("opus"));
+        assert_eq!(fallback(&state, "opus").as_deref(), Some("sonnet"));
+        assert_eq!(fallback(&state, "sonnet"), None);
+        let route = state.route_model("+1", "hi", "opus");
+        assert_eq!(route.model, "sonnet");
+        assert_eq!(route.reason.as_deref(), Some("opus is unavailable"));
+        let status = status(&state).unwrap();
+        assert!(status.contains("opus down since") && status.contains("sonnet up"));
+        assert!(prometheus(&state).contains("ccchat_model_up{model=\"opus\"} 0"));
+        assert_eq!(stats_json(&state)["models"]["sonnet"]["up"], true);
+    }
+}
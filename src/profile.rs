@@ -0,0 +1,116 @@
+//! Bridge status in the account's Signal profile (`--profile-status-mins`).
+//!
+//! The bot account's "about" text says whether the bridge is up and what it
+//! has spent today (`🟢 online · $1.20 today`), so anyone about to message it
+//! can tell at a glance whether a reply will come. It's refreshed on the
+//! interval but only sent when it changed, since every profile update is
+//! pushed to contacts, and set to `🔴 offline` on a clean shutdown. After a
+//! crash the last status stays until ccchat is back.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::state::State;
+
+/// About text while ccchat is shutting down or stopped.
+pub(crate) const OFFLINE_STATUS: &str = "🔴 offline";
+
+/// About text while ccchat is running.
+pub(crate) fn online_status(spent_today: f64) -> String {
+    format!("🟢 online · ${spent_today:.2} today")
+}
+
+/// Set `about` unless it's what `last` says was set already.
+async fn update(state: &State, about: String, last: &mut String) {
+    if about == *last {
+        return;
+    }
+    match state
+        .signal_api
+        .set_profile(&state.config.profile_name, &about)
+        .await
+    {
+        Ok(()) => *last = about,
+        Err(e) => debug!("Profile status update failed: {e}"),
+    }
+}
+
+pub(crate) fn spawn_status_loop(state: &Arc<State>) {
+    let Some(every) = state.config.profile_status else {
+        return;
+    };
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let mut last = String::new();
+        loop {
+            update(
+                &state,
+                online_status(crate::ledger::spent_today()),
+                &mut last,
+            )
+            .await;
+            tokio::time::sleep(every).await;
+        }
+    });
+}
+
+/// Show the bridge as offline. Bounded so it can't hold up shutdown.
+pub(crate) async fn mark_offline(state: &State) {
+    if state.config.profile_status.is_none() {
+        return;
+    }
+    let mut last = String::new();
+    let update = update(state, OFFLINE_STATUS.to_string(), &mut last);
+    if tokio::time::timeout(Duration::from_secs(5), update)
+        .await
+        .is_err()
+    {
+        debug!("Timed out setting offline profile status");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_online_status() {
+        assert_eq!(online_status(1.2), "🟢 online · $1.20 today");
+        assert_eq!(online_status(0.0), "🟢 online · $0.00 today");
+    }
+
+    #[tokio::test]
+    async fn test_update_only_sends_changes() {
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_set_profile()
+            .withf(|name, about| name.is_empty() && about.starts_with("🟢"))
+            .times(2)
+            .returning(|_, _| Ok(()));
+        let state = test_state_with(signal, MockClaudeRunner::new());
+        let mut last = String::new();
+        update(&state, online_status(1.0), &mut last).await;
+        update(&state, online_status(1.0), &mut last).await;
+        update(&state, online_status(1.5), &mut last).await;
+        assert_eq!(last, online_status(1.5));
+    }
+
+    #[tokio::test]
+    async fn test_mark_offline() {
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_set_profile()
+            .withf(|name, about| name == "ccchat" && about == OFFLINE_STATUS)
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let mut state = test_state_with(signal, MockClaudeRunner::new());
+        // Off by default: nothing is sent.
+        mark_offline(&state).await;
+        state.config_mut().profile_status = Some(Duration::from_secs(300));
+        state.config_mut().profile_name = "ccchat".to_string();
+        mark_offline(&state).await;
+    }
+}
@@ -5,18 +5,8 @@ use crate::error::AppError;
 
 pub(crate) const MAX_RETRIES: i64 = 5;
 
-fn config_dir() -> std::path::PathBuf {
-    let dir = std::env::var("HOME")
-        .map(std::path::PathBuf::from)
-        .unwrap_or_else(|_| std::path::PathBuf::from("."))
-        .join(".config")
-        .join("ccchat");
-    let _ = std::fs::create_dir_all(&dir);
-    dir
-}
-
 pub(crate) fn open_queue_db() -> Result<Connection, AppError> {
-    let path = config_dir().join("queue.db");
+    let path = crate::memory::config_dir().join("queue.db");
     let conn = Connection::open(&path)?;
     conn.execute_batch("PRAGMA journal_mode=WAL;")?;
     conn.execute_batch(
@@ -28,6 +18,13 @@ pub(crate) fn open_queue_db() -> Result<Connection, AppError> {
             timestamp INTEGER NOT NULL,
             status TEXT NOT NULL DEFAULT 'pending',
             retry_count INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE TABLE IF NOT EXISTS journal (
+            id INTEGER PRIMARY KEY,
+            sender TEXT NOT NULL,
+            content TEXT NOT NULL,
+            started INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'running'
         );",
     )?;
     Ok(conn)
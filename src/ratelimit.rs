@@ -0,0 +1,193 @@
+//! Rate classes and persistence for the per-sender token buckets.
+//!
+//! Claude runs and cheap slash commands draw from separate buckets so a burst
+//! of `/status` can't lock someone out of chatting (and vice versa). Bucket
+//! levels are saved to `rate_limits.db` so limits survive a restart.
+
+use rusqlite::Connection;
+use std::time::Duration;
+use tracing::error;
+
+use crate::error::AppError;
+use crate::state::{State, TokenBucket};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum RateClass {
+    /// Messages that start a Claude run.
+    Claude,
+    /// Slash commands answered locally.
+    Command,
+}
+
+impl RateClass {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            RateClass::Claude => "claude",
+            RateClass::Command => "command",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "claude" => Some(RateClass::Claude),
+            "command" => Some(RateClass::Command),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn open_ratelimit_db() -> Result<Connection, AppError> {
+    let path = crate::memory::config_dir().join("rate_limits.db");
+    let conn = Connection::open(&path)?;
+    conn.execute_batch("PRAGMA journal_mode=WAL;")?;
+    init_schema(&conn)?;
+    Ok(conn)
+}
+
+fn init_schema(conn: &Connection) -> Result<(), AppError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS token_buckets (
+            sender TEXT NOT NULL,
+            class TEXT NOT NULL,
+            tokens REAL NOT NULL,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY (sender, class)
+        );",
+    )?;
+    Ok(())
+}
+
+/// Save every in-memory bucket. Returns the number of rows written.
+pub(crate) fn save_buckets(conn: &Connection, state: &State) -> usize {
+    let now = crate::helpers::epoch_now();
+    let rows: Vec<(String, RateClass, f64, i64)> = state
+        .rate_limits
+        .iter()
+        .map(|e| {
+            let (sender, class) = e.key();
+            let age = e.value().last_refill.elapsed().as_secs() as i64;
+            (sender.clone(), *class, e.value().tokens, now - age)
+        })
+        .collect();
+    let mut saved = 0;
+    for (sender, class, tokens, updated_at) in rows {
+        match conn.execute(
+            "INSERT INTO token_buckets (sender, class, tokens, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(sender, class) DO UPDATE SET tokens = ?3, updated_at = ?4",
+            rusqlite::params![sender, class.as_str(), tokens, updated_at],
+        ) {
+            Ok(_) => saved += 1,
+            Err(e) => error!("Failed to save rate limit bucket: {e}"),
+        }
+    }
+    saved
+}
+
+/// Rebuild buckets from the last saved levels, refilled for the time the
+/// bridge was down. Buckets for classes that are no longer limited are skipped.
+pub(crate) fn restore_buckets(conn: &Connection, state: &State) -> usize {
+    let Ok(mut stmt) = conn.prepare("SELECT sender, class, tokens, updated_at FROM token_buckets")
+    else {
+        return 0;
+    };
+    let rows: Vec<(String, String, f64, i64)> = stmt
+        .query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .map(|rows| rows.flatten().collect())
+        .unwrap_or_default();
+    let now = crate::helpers::epoch_now();
+    let mut restored = 0;
+    for (sender, class, tokens, updated_at) in rows {
+        let Some(class) = RateClass::parse(&class) else {
+            continue;
+        };
+        let Some((cap, rate)) = state.rate_limit_for(&sender, class) else {
+            continue;
+        };
+        let down_for = Duration::from_secs((now - updated_at).max(0) as u64);
+        state.rate_limits.insert(
+            (sender, class),
+            TokenBucket::restore(cap, rate, tokens, down_for),
+        );
+        restored += 1;
+    }
+    restored
+}
+
+/// Persist buckets, logging (not failing) if the database can't be opened.
+pub(crate) fn persist(state: &State) {
+    match open_ratelimit_db() {
+        Ok(conn) => {
+            save_buckets(&conn, state);
+        }
+        Err(e) => error!("Failed to open rate limit db: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    fn memory_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_rate_class_round_trip() {
+        for class in [RateClass::Claude, RateClass::Command] {
+            assert_eq!(RateClass::parse(class.as_str()), Some(class));
+        }
+        assert_eq!(RateClass::parse("other"), None);
+    }
+
+    #[test]
+    fn test_save_and_restore_buckets() {
+        let conn = memory_db();
+        let mut state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.config_mut().rate_limit_config = Some((5.0, 0.0));
+        let mut bucket = TokenBucket::new(5.0, 0.0);
+        bucket.tokens = 1.0;
+        state
+            .rate_limits
+            .insert(("+user".to_string(), RateClass::Claude), bucket);
+        // No command limit configured: this one is saved but not restored.
+        state.rate_limits.insert(
+            ("+user".to_string(), RateClass::Command),
+            TokenBucket::new(3.0, 0.0),
+        );
+        assert_eq!(save_buckets(&conn, &state), 2);
+
+        state.rate_limits.clear();
+        assert_eq!(restore_buckets(&conn, &state), 1);
+        let bucket = state
+            .rate_limits
+            .get(&("+user".to_string(), RateClass::Claude))
+            .unwrap();
+        assert!((bucket.tokens - 1.0).abs() < 0.01);
+        assert!((bucket.capacity - 5.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_restore_refills_for_downtime() {
+        let conn = memory_db();
+        let mut state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.config_mut().rate_limit_config = Some((10.0, 0.1));
+        conn.execute(
+            "INSERT INTO token_buckets VALUES ('+user', 'claude', 0.0, ?1)",
+            rusqlite::params![crate::helpers::epoch_now() - 30],
+        )
+        .unwrap();
+        restore_buckets(&conn, &state);
+        let bucket = state
+            .rate_limits
+            .get(&("+user".to_string(), RateClass::Claude))
+            .unwrap();
+        assert!(bucket.tokens >= 2.9 && bucket.tokens <= 3.2);
+    }
+}
@@ -0,0 +1,88 @@
+//! Read receipts and rejection marks (`--send-receipts`).
+//!
+//! With `--send-receipts`, a message from an allowed sender gets a read
+//! receipt as soon as ccchat takes it on, and one it turns away (sender not
+//! allowed, rate limited, over budget) gets a 🚫 reaction, so the sender can
+//! tell "working on it" from "refused" before any reply arrives.
+
+use std::sync::Arc;
+
+use crate::signal::ParsedEnvelope;
+use crate::state::State;
+
+/// Reaction on a message ccchat won't handle.
+pub(crate) const REJECTED_EMOJI: &str = "🚫";
+
+/// Send a read receipt for an accepted message, in the background.
+pub(crate) fn accepted(state: &Arc<State>, envelope: &ParsedEnvelope) {
+    let (true, Some(timestamp)) = (state.config.send_receipts, envelope.sent_at) else {
+        return;
+    };
+    let state = Arc::clone(state);
+    let author = envelope.source.clone();
+    tokio::spawn(async move {
+        if let Err(e) = state.signal_api.send_receipt(&author, timestamp).await {
+            tracing::debug!(sender = %author, "Read receipt failed: {e}");
+        }
+    });
+}
+
+/// Mark a message from someone who isn't allowed, in the background.
+pub(crate) fn rejected_envelope(state: &Arc<State>, envelope: &ParsedEnvelope) {
+    let (true, Some(timestamp)) = (state.config.send_receipts, envelope.sent_at) else {
+        return;
+    };
+    let state = Arc::clone(state);
+    let author = envelope.source.clone();
+    tokio::spawn(async move {
+        react(&state, &author, &author, timestamp).await;
+    });
+}
+
+/// Mark the latest message in `chat` as refused (rate limit, budget).
+pub(crate) async fn rejected(state: &State, chat: &str) {
+    if !state.config.send_receipts {
+        return;
+    }
+    let Some((author, timestamp)) = state.last_inbound.get(chat).map(|e| e.clone()) else {
+        return;
+    };
+    react(state, chat, &author, timestamp).await;
+}
+
+async fn react(state: &State, chat: &str, author: &str, timestamp: i64) {
+    if let Err(e) = state
+        .signal_api
+        .send_reaction(chat, REJECTED_EMOJI, author, timestamp)
+        .await
+    {
+        tracing::debug!(chat = %chat, "Rejection reaction failed: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    #[tokio::test]
+    async fn test_rejected_reacts_to_last_message() {
+        let mut mock = MockSignalApi::new();
+        mock.expect_send_reaction()
+            .withf(|chat, emoji, author, ts| {
+                chat == "group:abc" && emoji == REJECTED_EMOJI && author == "+1" && *ts == 42
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        let mut state = test_state_with(mock, MockClaudeRunner::new());
+        state
+            .last_inbound
+            .insert("group:abc".to_string(), ("+1".to_string(), 42));
+
+        rejected(&state, "group:abc").await;
+        state.config_mut().send_receipts = true;
+        rejected(&state, "+2").await;
+        rejected(&state, "group:abc").await;
+    }
+}
@@ -0,0 +1,200 @@
+//! Recipients Signal won't deliver to as things stand.
+//!
+//! Two send failures get more than a "Send failed" log line:
+//!
+//! - **Identity changed.** The recipient's safety number is new: they
+//!   reinstalled Signal or moved to a new phone, or someone took over their
+//!   number (a SIM swap). The admin is told either way, once per change:
+//!   failures before it's trusted are the same change, and one after it is
+//!   a new one. With
+//!   `--trust-new-identities` the new identity is trusted and the message
+//!   sent again; otherwise sends fail until it's trusted by hand.
+//! - **Unregistered.** The number no longer has Signal. Sending to it is
+//!   suspended, so replies, reminders and reports stop piling up errors,
+//!   until a message arrives from it again.
+//!
+//! `/senders` shows who is in either state.
+
+use crate::error::AppError;
+use crate::state::State;
+
+/// The latest safety-number change seen for a recipient.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct IdentityChange {
+    /// When it was noticed (epoch secs).
+    pub(crate) at: i64,
+    /// Not trusted yet, so sends still fail; those failures are this change
+    /// again, not a new one.
+    pub(crate) untrusted: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum SendProblem {
+    IdentityChanged,
+    Unregistered,
+}
+
+/// What a failed send says about its recipient, if anything.
+pub(crate) fn classify(error: &AppError) -> Option<SendProblem> {
+    let msg = error.to_string().to_lowercase();
+    if msg.contains("untrusted identity") || msg.contains("untrustedidentity") {
+        Some(SendProblem::IdentityChanged)
+    } else if msg.contains("unregistered") || msg.contains("not registered") {
+        Some(SendProblem::Unregistered)
+    } else {
+        None
+    }
+}
+
+/// Error for a send to a suspended recipient, without trying it.
+pub(crate) fn check_suspended(state: &State, recipient: &str) -> Result<(), AppError> {
+    if state.suspended_recipients.contains_key(recipient) {
+        return Err(AppError::Signal(format!(
+            "Not sending to {recipient}: the number isn't registered with Signal"
+        )));
+    }
+    Ok(())
+}
+
+/// Deal with a failed send of `message` to `recipient`. Returns the result
+/// of a retry when one was made, or the original error.
+pub(crate) async fn handle_send_error(
+    state: &State,
+    recipient: &str,
+    message: &str,
+    error: AppError,
+) -> Result<(), AppError> {
+    match classify(&error) {
+        Some(SendProblem::IdentityChanged) => {
+            identity_changed(state, recipient, message, error).await
+        }
+        Some(SendProblem::Unregistered) => {
+            let now = crate::helpers::epoch_now();
+            if state
+                .suspended_recipients
+                .insert(recipient.to_string(), now)
+                .is_none()
+            {
+                tracing::warn!(recipient = %recipient, "Recipient not registered with Signal; suspending sends");
+                notify_admin(
+                    state,
+                    recipient,
+                    &format!(
+                        "{recipient} is no longer registered with Signal, so ccchat has stopped sending to them. Sending resumes if they message again."
+                    ),
+                )
+                .await;
+            }
+            Err(error)
+        }
+        None => Err(error),
+    }
+}
+
+async fn identity_changed(
+    state: &State,
+    recipient: &str,
+    message: &str,
+    error: AppError,
+) -> Result<(), AppError> {
+    // Once a change is trusted, the next failure is a new change (another
+    // reinstall, or a SIM swap after all) and is reported again.
+    let first = !state
+        .identity_changes
+        .get(recipient)
+        .is_some_and(|c| c.untrusted);
+    if first {
+        state.identity_changes.insert(
+            recipient.to_string(),
+            IdentityChange {
+                at: crate::helpers::epoch_now(),
+                untrusted: true,
+            },
+        );
+    }
+    tracing::warn!(recipient = %recipient, "Recipient's safety number changed");
+    let trusted = if state.config.trust_new_identities {
+        match state.signal_api.trust_identity(recipient).await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!(recipient = %recipient, "Could not trust new identity: {e}");
+                false
+            }
+        }
+    } else {
+        false
+    };
+    if first {
+        let action = if trusted {
+            "The new identity was trusted automatically (--trust-new-identities)."
+        } else {
+            "Messages to them will fail until the new identity is trusted (signal-cli trust, or restart with --trust-new-identities)."
+        };
+        notify_admin(
+            state,
+            recipient,
+            &format!(
+                "⚠️ The safety number of {recipient} has changed. If they didn't reinstall Signal or get a new phone, their number may have been taken over (SIM swap). {action}"
+            ),
+        )
+        .await;
+    }
+    if !trusted {
+        return Err(error);
+    }
+    let resent = state.signal_api.send_msg(recipient, message).await;
+    if resent.is_ok() {
+        delivered(state, recipient);
+    }
+    resent
+}
+
+/// A send to `recipient` went through, so any change of theirs has been
+/// trusted, by `--trust-new-identities` or by hand.
+pub(crate) fn delivered(state: &State, recipient: &str) {
+    if let Some(mut change) = state.identity_changes.get_mut(recipient) {
+        change.untrusted = false;
+    }
+}
+
+/// Tell the admin, unless the problem is with the admin's own number.
+async fn notify_admin(state: &State, recipient: &str, notice: &str) {
+    if recipient == state.config.account {
+        return;
+    }
+    if let Err(e) = state
+        .signal_api
+        .send_msg(&state.config.account, notice)
+        .await
+    {
+        tracing::warn!("Failed to notify admin about {recipient}: {e}");
+    }
+}
+
+/// A message arrived from `sender`, so they're reachable again.
+pub(crate) fn heard_from(state: &State, sender: &str) {
+    if state.suspended_recipients.remove(sender).is_some() {
+        tracing::info!(sender = %sender, "Sender is back; resuming sends");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let err = |body: &str| AppError::Signal(format!("Send failed: 400 Bad Request: {body}"));
+        assert_eq!(
+            classify(&err(
+                r#"{"error":"Failed to send message: Untrusted Identity for \"+44\""}"#
+            )),
+            Some(SendProblem::IdentityChanged)
+        );
+        assert_eq!(
+            classify(&err(r#"{"error":"Unregistered user \"+44\""}"#)),
+            Some(SendProblem::Unregistered)
+        );
+        assert_eq!(classify(&err("Internal error")), None);
+    }
+}
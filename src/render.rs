@@ -0,0 +1,299 @@
+//! Code and diagram images (`--features render`).
+//!
+//! A phone screen shows about forty columns, so long code blocks arrive as
+//! syntax-highlighted PNGs instead of a wall of wrapped text. `mermaid` and
+//! `dot` blocks are drawn as diagrams by the mermaid CLI (`mmdc`) and Graphviz
+//! (`dot`) when they're installed. Any block that can't be rendered is sent
+//! as text.
+
+use embedded_graphics::mono_font::iso_8859_1::FONT_10X20;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+use image::{Rgb, RgbImage};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::constants::{CODE_IMAGE_MAX_COLS, CODE_IMAGE_MAX_LINES, CODE_IMAGE_MIN_LINES};
+use crate::error::AppError;
+use crate::format::Formatted;
+
+const CODE_NOTE: &str = "(Code sent as an image.)";
+const DIAGRAM_NOTE: &str = "(Diagram sent as an image.)";
+const THEME: &str = "InspiredGitHub";
+const MARGIN: u32 = 12;
+
+/// Replace long code blocks and diagram blocks with image attachments.
+pub(crate) async fn render_blocks(text: &str) -> Formatted {
+    let mut out: Vec<&str> = Vec::new();
+    let mut notes: Vec<(usize, &str)> = Vec::new();
+    let mut images = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let Some(lang) = line.trim_start().strip_prefix("```") else {
+            out.push(line);
+            continue;
+        };
+        let lang = lang.trim();
+        let body: Vec<&str> = lines.by_ref().take_while(|l| l.trim() != "```").collect();
+        let code = body.join("\n");
+        let rendered = match lang {
+            "mermaid" => render_mermaid(&code).await.map(|png| ("diagram", png)),
+            "dot" | "graphviz" => render_dot(&code).await.map(|png| ("diagram", png)),
+            _ if (CODE_IMAGE_MIN_LINES..=CODE_IMAGE_MAX_LINES).contains(&body.len()) => {
+                highlight_png(&code, lang).map(|png| ("code", png))
+            }
+            _ => Err(AppError::Other("left as text".to_string())),
+        };
+        match rendered {
+            Ok((kind, png)) => {
+                images.push((format!("{kind}-{}.png", images.len() + 1), png));
+                let note = if kind == "code" {
+                    CODE_NOTE
+                } else {
+                    DIAGRAM_NOTE
+                };
+                notes.push((out.len(), note));
+                out.push("");
+            }
+            Err(e) => {
+                debug!("Block not rendered ({lang}): {e}");
+                out.push(line);
+                out.extend(&body);
+                out.push("```");
+            }
+        }
+    }
+    let mut text: Vec<String> = out.iter().map(|l| l.to_string()).collect();
+    for (i, note) in notes {
+        text[i] = note.to_string();
+    }
+    Formatted {
+        text: text.join("\n"),
+        images,
+    }
+}
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    &THEMES.get_or_init(ThemeSet::load_defaults).themes[THEME]
+}
+
+/// Syntax-highlighted PNG of a code block, with line numbers. Lines longer
+/// than [`CODE_IMAGE_MAX_COLS`] are cut.
+fn highlight_png(code: &str, lang: &str) -> Result<Vec<u8>, AppError> {
+    let syntaxes = syntaxes();
+    let theme = theme();
+    let syntax = syntaxes
+        .find_syntax_by_token(lang)
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, theme);
+
+    let code = code.replace('\t', "    ");
+    let line_count = code.lines().count().max(1);
+    let gutter = line_count.to_string().len() + 1;
+    let cols = code
+        .lines()
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(CODE_IMAGE_MAX_COLS)
+        + gutter;
+    let char_w = FONT_10X20.character_size.width;
+    let char_h = FONT_10X20.character_size.height;
+    let width = cols as u32 * char_w + 2 * MARGIN;
+    let height = line_count as u32 * char_h + 2 * MARGIN;
+
+    let background = theme
+        .settings
+        .background
+        .map(|c| Rgb([c.r, c.g, c.b]))
+        .unwrap_or(Rgb([255, 255, 255]));
+    let mut canvas = Canvas(RgbImage::from_pixel(width, height, background));
+    let gutter_style = MonoTextStyle::new(&FONT_10X20, Rgb888::new(150, 150, 150));
+
+    for (n, line) in LinesWithEndings::from(&code).enumerate() {
+        let y = (MARGIN + n as u32 * char_h) as i32;
+        let number = format!("{:>width$}", n + 1, width = gutter - 1);
+        let _ = Text::with_baseline(
+            &number,
+            Point::new(MARGIN as i32, y),
+            gutter_style,
+            Baseline::Top,
+        )
+        .draw(&mut canvas);
+
+        let regions = highlighter
+            .highlight_line(line, syntaxes)
+            .map_err(|e| AppError::Other(format!("Highlighting failed: {e}")))?;
+        let mut col = gutter;
+        for (style, piece) in regions {
+            let piece: String = piece
+                .trim_end_matches(['\n', '\r'])
+                .chars()
+                .take((CODE_IMAGE_MAX_COLS + gutter).saturating_sub(col))
+                .collect();
+            if piece.is_empty() {
+                continue;
+            }
+            let color = style.foreground;
+            let text_style =
+                MonoTextStyle::new(&FONT_10X20, Rgb888::new(color.r, color.g, color.b));
+            let x = (MARGIN + col as u32 * char_w) as i32;
+            let _ = Text::with_baseline(&piece, Point::new(x, y), text_style, Baseline::Top)
+                .draw(&mut canvas);
+            col += piece.chars().count();
+        }
+    }
+
+    let mut png = std::io::Cursor::new(Vec::new());
+    canvas
+        .0
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|e| AppError::Other(format!("PNG encoding failed: {e}")))?;
+    Ok(png.into_inner())
+}
+
+/// `dot -Tpng`, reading the graph on stdin.
+async fn render_dot(source: &str) -> Result<Vec<u8>, AppError> {
+    let mut child = Command::new("dot")
+        .arg("-Tpng")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(source.as_bytes()).await?;
+    }
+    let output = tokio::time::timeout(render_timeout(), child.wait_with_output())
+        .await
+        .map_err(|_| AppError::Other("dot timed out".to_string()))??;
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(AppError::Other(format!(
+            "dot exited with {}",
+            output.status
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// `mmdc`, which only works on files.
+async fn render_mermaid(source: &str) -> Result<Vec<u8>, AppError> {
+    let dir = PathBuf::from(crate::constants::TMP_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let stem = dir.join(format!("mermaid-{}", uuid::Uuid::new_v4()));
+    let input = stem.with_extension("mmd");
+    let output = stem.with_extension("png");
+    std::fs::write(&input, source)?;
+    let result = tokio::time::timeout(
+        render_timeout(),
+        Command::new("mmdc")
+            .arg("-i")
+            .arg(&input)
+            .arg("-o")
+            .arg(&output)
+            .args(["-b", "white"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .status(),
+    )
+    .await;
+    let png = match result {
+        Ok(Ok(status)) if status.success() => std::fs::read(&output).map_err(AppError::from),
+        Ok(Ok(status)) => Err(AppError::Other(format!("mmdc exited with {status}"))),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err(AppError::Other("mmdc timed out".to_string())),
+    };
+    let _ = std::fs::remove_file(&input);
+    let _ = std::fs::remove_file(&output);
+    png
+}
+
+fn render_timeout() -> Duration {
+    Duration::from_secs(crate::constants::RENDER_TIMEOUT_SECS)
+}
+
+/// Lets embedded-graphics draw text into an `RgbImage`.
+struct Canvas(RgbImage);
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(self.0.width(), self.0.height())
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Rgb888;
+    type Error = std::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) {
+                if x < self.0.width() && y < self.0.height() {
+                    self.0
+                        .put_pixel(x, y, Rgb([color.r(), color.g(), color.b()]));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_block(lines: usize) -> String {
+        let body: Vec<String> = (0..lines).map(|i| format!("let x{i} = {i};")).collect();
+        format!(
+            "Here you go:\n```rust\n{}\n```\nThat's it.",
+            body.join("\n")
+        )
+    }
+
+    #[tokio::test]
+    async fn test_long_code_block_becomes_image() {
+        let out = render_blocks(&code_block(CODE_IMAGE_MIN_LINES)).await;
+        assert_eq!(out.text, format!("Here you go:\n{CODE_NOTE}\nThat's it."));
+        assert_eq!(out.images.len(), 1);
+        assert_eq!(out.images[0].0, "code-1.png");
+        assert!(out.images[0].1.starts_with(b"\x89PNG"));
+    }
+
+    #[tokio::test]
+    async fn test_short_code_block_stays_text() {
+        let text = code_block(3);
+        let out = render_blocks(&text).await;
+        assert_eq!(out.text, text);
+        assert!(out.images.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_language_still_renders() {
+        let png = highlight_png("some\tplain text\nline two", "no-such-lang").unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        // Gutter "1 " plus "some    plain text" (tab expanded).
+        assert_eq!(image.width(), 20 * 10 + 2 * MARGIN);
+        assert_eq!(image.height(), 2 * 20 + 2 * MARGIN);
+    }
+}
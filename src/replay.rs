@@ -0,0 +1,317 @@
+//! `ccchat replay`: a recorded session's prompts run again, for comparing
+//! models without chatting.
+//!
+//! The session's messages come from its chat's memory database (the chat is
+//! found in the usage ledger unless `--chat` names it). Its prompts are sent
+//! in order to one fresh session, so later prompts see the replayed answers
+//! to earlier ones, and the report puts each recorded reply next to the new
+//! one in a Markdown table. `--runner stub` echoes prompts back at no cost
+//! to check the pipeline; `--runner model` runs `claude` with `--model`.
+//! Model runs are capped by `--max-budget` each and `--budget` in all, and
+//! recorded in the ledger as [`LEDGER_SENDER`].
+
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+use crate::error::AppError;
+use crate::traits::{ClaudeRunner, RunOptions};
+
+/// Who replay runs are charged to in the usage ledger.
+pub(crate) const LEDGER_SENDER: &str = "replay";
+
+/// What a replay runs prompts with.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum Runner {
+    /// Echo each prompt back without starting `claude`.
+    Stub,
+    /// Run `claude` with `--model`.
+    Model,
+}
+
+/// How a replay runs.
+pub(crate) struct Options {
+    pub(crate) model: String,
+    /// Cap per prompt (USD).
+    pub(crate) max_budget: f64,
+    /// Cap for the whole replay (USD); prompts after it's spent are skipped.
+    pub(crate) budget: Option<f64>,
+    pub(crate) work_dir: PathBuf,
+}
+
+/// One recorded exchange.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Turn {
+    pub(crate) prompt: String,
+    /// None when no reply was recorded.
+    pub(crate) reply: Option<String>,
+}
+
+/// The exchanges recorded for `session_id`, oldest first.
+pub(crate) fn load_turns(conn: &Connection, session_id: &str) -> Vec<Turn> {
+    let Ok(mut stmt) =
+        conn.prepare("SELECT role, content FROM messages WHERE session_id = ?1 ORDER BY id")
+    else {
+        return Vec::new();
+    };
+    let rows: Vec<(String, String)> = stmt
+        .query_map([session_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default();
+    let mut turns: Vec<Turn> = Vec::new();
+    for (role, content) in rows {
+        match (role.as_str(), turns.last_mut()) {
+            ("user", _) => turns.push(Turn {
+                prompt: content,
+                reply: None,
+            }),
+            ("assistant", Some(turn)) if turn.reply.is_none() => turn.reply = Some(content),
+            _ => {}
+        }
+    }
+    turns
+}
+
+/// The session of the newest message in a chat's memory.
+fn latest_session(conn: &Connection) -> Option<String> {
+    conn.query_row(
+        "SELECT session_id FROM messages WHERE session_id IS NOT NULL ORDER BY id DESC LIMIT 1",
+        [],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+/// What the replay got for one turn.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Reply { text: String, cost_usd: f64 },
+    Failed(String),
+    Skipped,
+}
+
+async fn replay_turns(
+    runner: &dyn ClaudeRunner,
+    turns: &[Turn],
+    options: &Options,
+) -> (Vec<Outcome>, f64) {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let mut spent = 0.0;
+    let mut outcomes = Vec::new();
+    for (i, turn) in turns.iter().enumerate() {
+        if options.budget.is_some_and(|b| spent >= b) {
+            outcomes.push(Outcome::Skipped);
+            continue;
+        }
+        tracing::info!(turn = i + 1, "Replaying prompt");
+        let result = runner
+            .run_claude(
+                &turn.prompt,
+                &session_id,
+                &options.model,
+                &[],
+                &options.work_dir,
+                options.max_budget,
+                "",
+                &RunOptions::default(),
+            )
+            .await;
+        outcomes.push(match result {
+            Ok(reply) => {
+                let cost = reply.cost_usd.unwrap_or(0.0);
+                spent += cost;
+                crate::ledger::record_run(LEDGER_SENDER, &session_id, None, cost);
+                Outcome::Reply {
+                    text: reply.text,
+                    cost_usd: cost,
+                }
+            }
+            Err(e) => Outcome::Failed(e.to_string()),
+        });
+    }
+    (outcomes, spent)
+}
+
+/// Text for one Markdown table cell.
+fn cell(text: &str) -> String {
+    text.trim()
+        .replace('|', "\\|")
+        .replace("\r\n", "\n")
+        .replace('\n', "<br>")
+}
+
+fn render_report(
+    session_id: &str,
+    chat: &str,
+    label: &str,
+    turns: &[Turn],
+    outcomes: &[Outcome],
+    spent: f64,
+) -> String {
+    let mut report = format!(
+        "# Replay of session {session_id}\n\nChat: {chat}  \nReplayed with: {label}  \nTurns: {}  \nReplay cost: ${spent:.4}\n",
+        turns.len()
+    );
+    for (i, (turn, outcome)) in turns.iter().zip(outcomes).enumerate() {
+        let replayed = match outcome {
+            Outcome::Reply { text, cost_usd } => format!("{}<br><br>*${cost_usd:.4}*", cell(text)),
+            Outcome::Failed(e) => format!("*Failed: {}*", cell(e)),
+            Outcome::Skipped => "*Skipped: replay budget spent*".to_string(),
+        };
+        let recorded = turn
+            .reply
+            .as_deref()
+            .map_or_else(|| "*No reply recorded*".to_string(), cell);
+        report.push_str(&format!(
+            "\n## Turn {}\n\n> {}\n\n| Recorded | {label} |\n| --- | --- |\n| {recorded} | {replayed} |\n",
+            i + 1,
+            turn.prompt.trim().replace('\n', "\n> "),
+        ));
+    }
+    report
+}
+
+/// Report file for `session_id` when `--output` isn't given.
+fn default_output(session_id: &str) -> PathBuf {
+    PathBuf::from(format!("replay-{session_id}.md"))
+}
+
+/// Replay `session_id` (`last`: the chat's newest) from `chat` (looked up
+/// in the ledger when None) and write the report to `output`, or to
+/// [`default_output`] when None.
+pub(crate) async fn run(
+    runner: &dyn ClaudeRunner,
+    label: &str,
+    session_id: &str,
+    chat: Option<String>,
+    output: Option<PathBuf>,
+    options: &Options,
+) -> Result<String, AppError> {
+    let chat = match chat {
+        Some(chat) => chat,
+        None if session_id == "last" => {
+            return Err(AppError::Other(
+                "Replaying the last session needs --chat".to_string(),
+            ))
+        }
+        None => crate::ledger::open_ledger_db()
+            .ok()
+            .and_then(|conn| crate::ledger::chat_for_session(&conn, session_id))
+            .ok_or_else(|| {
+                AppError::Other(format!(
+                    "Session {session_id} isn't in the usage ledger; name its chat with --chat"
+                ))
+            })?,
+    };
+    let conn = crate::memory::open_memory_db(&chat)?;
+    let session_id = match session_id {
+        "last" => latest_session(&conn)
+            .ok_or_else(|| AppError::Other(format!("No sessions recorded in {chat}")))?,
+        id => id.to_string(),
+    };
+    let session_id = session_id.as_str();
+    let turns = load_turns(&conn, session_id);
+    if turns.is_empty() {
+        return Err(AppError::Other(format!(
+            "No messages recorded for session {session_id} in {chat}"
+        )));
+    }
+    let (outcomes, spent) = replay_turns(runner, &turns, options).await;
+    let report = render_report(session_id, &chat, label, &turns, &outcomes, spent);
+    let output = output.unwrap_or_else(|| default_output(session_id));
+    std::fs::write(&output, report)
+        .map_err(|e| AppError::Other(format!("{}: {e}", output.display())))?;
+    let failed = outcomes
+        .iter()
+        .filter(|o| !matches!(o, Outcome::Reply { .. }))
+        .count();
+    Ok(format!(
+        "Replayed {} prompt(s) with {label}, {failed} failed or skipped, ${spent:.4}. Report in {}",
+        turns.len(),
+        output.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::{ClaudeReply, MockClaudeRunner};
+
+    fn options(budget: Option<f64>) -> Options {
+        Options {
+            model: "haiku".to_string(),
+            max_budget: 1.0,
+            budget,
+            work_dir: std::env::temp_dir(),
+        }
+    }
+
+    #[test]
+    fn test_load_turns() {
+        let chat = format!("+replay_{}", uuid::Uuid::new_v4());
+        let conn = crate::memory::open_memory_db(&chat).unwrap();
+        crate::memory::store_message_pair(&chat, "first", "one", "s1");
+        crate::memory::store_message_pair(&chat, "other session", "x", "s2");
+        crate::memory::store_message_pair(&chat, "second", "two", "s1");
+        crate::memory::messages::store_message(&conn, "user", "unanswered", "s1");
+        assert_eq!(latest_session(&conn).as_deref(), Some("s1"));
+        assert_eq!(
+            load_turns(&conn, "s1"),
+            vec![
+                Turn {
+                    prompt: "first".to_string(),
+                    reply: Some("one".to_string())
+                },
+                Turn {
+                    prompt: "second".to_string(),
+                    reply: Some("two".to_string())
+                },
+                Turn {
+                    prompt: "unanswered".to_string(),
+                    reply: None
+                },
+            ]
+        );
+        crate::memory::delete_memory(&chat);
+    }
+
+    #[tokio::test]
+    async fn test_replay_shares_session_and_stops_at_budget() {
+        let session = std::sync::Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let seen = std::sync::Arc::clone(&session);
+        let mut claude = MockClaudeRunner::new();
+        claude.expect_run_claude().times(2).returning(
+            move |prompt, session_id, model, _, _, _, _, _| {
+                seen.lock().unwrap().push(session_id.to_string());
+                match prompt {
+                    "fail" => Err("boom".into()),
+                    _ => Ok(ClaudeReply::new(&format!("{model}: {prompt}"), Some(0.6))),
+                }
+            },
+        );
+        let turn = |prompt: &str| Turn {
+            prompt: prompt.to_string(),
+            reply: Some("old | reply\nline two".to_string()),
+        };
+        let turns = vec![turn("fail"), turn("hi"), turn("late")];
+        let (outcomes, spent) = replay_turns(&claude, &turns, &options(Some(0.5))).await;
+        assert_eq!(spent, 0.6);
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Failed("boom".to_string()),
+                Outcome::Reply {
+                    text: "haiku: hi".to_string(),
+                    cost_usd: 0.6
+                },
+                Outcome::Skipped,
+            ]
+        );
+        let sessions = session.lock().unwrap();
+        assert_eq!(sessions[0], sessions[1]);
+
+        let report = render_report("s1", "+1", "haiku", &turns, &outcomes, spent);
+        assert!(report.contains("| Recorded | haiku |"));
+        assert!(report.contains("| old \\| reply<br>line two | haiku: hi<br><br>*$0.6000* |"));
+        assert!(report.contains("*Failed: boom*"));
+    }
+}
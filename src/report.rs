@@ -0,0 +1,227 @@
+//! Usage reports for `/report`, built from the ledger: replies, cost,
+//! busiest hours, top projects, longest sessions and (for the owner's
+//! global report) top senders.
+
+use std::collections::HashMap;
+
+use chrono::{TimeZone, Utc};
+
+use crate::error::AppError;
+use crate::ledger::Run;
+
+/// Rows listed per section.
+const TOP_N: usize = 5;
+
+/// One conversation session's span in the report window.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SessionSpan {
+    pub(crate) sender: String,
+    pub(crate) started: i64,
+    pub(crate) secs: i64,
+    pub(crate) replies: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Report {
+    pub(crate) days: i64,
+    pub(crate) replies: usize,
+    pub(crate) cost_usd: f64,
+    /// Replies per hour of day (UTC).
+    pub(crate) hours: [u64; 24],
+    /// (project, replies, cost), most replies first.
+    pub(crate) projects: Vec<(String, usize, f64)>,
+    pub(crate) sessions: Vec<SessionSpan>,
+    /// (sender, replies, cost), highest cost first.
+    pub(crate) senders: Vec<(String, usize, f64)>,
+}
+
+impl Report {
+    pub(crate) fn build(runs: &[Run], days: i64) -> Self {
+        let mut hours = [0u64; 24];
+        let mut projects: HashMap<&str, (usize, f64)> = HashMap::new();
+        let mut sessions: HashMap<&str, SessionSpan> = HashMap::new();
+        let mut senders: HashMap<&str, (usize, f64)> = HashMap::new();
+        for run in runs {
+            hours[(run.timestamp.rem_euclid(86_400) / 3600) as usize] += 1;
+            let project = projects
+                .entry(run.project.as_deref().unwrap_or("(no project)"))
+                .or_default();
+            project.0 += 1;
+            project.1 += run.cost_usd;
+            let sender = senders.entry(&run.sender).or_default();
+            sender.0 += 1;
+            sender.1 += run.cost_usd;
+            let span = sessions
+                .entry(&run.session_id)
+                .or_insert_with(|| SessionSpan {
+                    sender: run.sender.clone(),
+                    started: run.timestamp,
+                    secs: 0,
+                    replies: 0,
+                });
+            span.secs = run.timestamp - span.started;
+            span.replies += 1;
+        }
+
+        let mut projects: Vec<_> = projects
+            .into_iter()
+            .map(|(name, (replies, cost))| (name.to_string(), replies, cost))
+            .collect();
+        projects.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        projects.truncate(TOP_N);
+        let mut sessions: Vec<_> = sessions.into_values().collect();
+        sessions
+            .sort_by(|a, b| (b.secs, b.replies, a.started).cmp(&(a.secs, a.replies, b.started)));
+        sessions.truncate(3);
+        let mut senders: Vec<_> = senders
+            .into_iter()
+            .map(|(id, (replies, cost))| (id.to_string(), replies, cost))
+            .collect();
+        senders.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+        senders.truncate(TOP_N);
+
+        Self {
+            days,
+            replies: runs.len(),
+            cost_usd: runs.iter().map(|r| r.cost_usd).sum(),
+            hours,
+            projects,
+            sessions,
+            senders,
+        }
+    }
+
+    /// Text summary; `global` adds the top senders.
+    pub(crate) fn text(&self, global: bool) -> String {
+        let scope = if global { "everyone" } else { "you" };
+        if self.replies == 0 {
+            return format!(
+                "Usage report ({scope}): no Claude replies in the last {} days.",
+                self.days
+            );
+        }
+        let mut lines = vec![
+            format!("Usage report, last {} days ({scope}):", self.days),
+            format!("Replies: {}", self.replies),
+            format!("Cost: ${:.2}", self.cost_usd),
+        ];
+        let mut busiest: Vec<(usize, u64)> = self
+            .hours
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, n)| *n > 0)
+            .collect();
+        busiest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let busiest: Vec<String> = busiest
+            .iter()
+            .take(3)
+            .map(|(hour, n)| format!("{hour:02}:00 ({n})"))
+            .collect();
+        lines.push(format!("Busiest hours (UTC): {}", busiest.join(", ")));
+        lines.push("Top projects:".to_string());
+        for (name, replies, cost) in &self.projects {
+            lines.push(format!("  {name}: {replies} replies, ${cost:.2}"));
+        }
+        lines.push("Longest sessions:".to_string());
+        for span in &self.sessions {
+            let started = Utc
+                .timestamp_opt(span.started, 0)
+                .single()
+                .map(|t| t.format("%a %d %b %H:%M").to_string())
+                .unwrap_or_default();
+            let who = if global {
+                format!("{} ", span.sender)
+            } else {
+                String::new()
+            };
+            lines.push(format!(
+                "  {who}{started}: {}, {} replies",
+                format_span(span.secs),
+                span.replies
+            ));
+        }
+        if global {
+            lines.push("Top senders:".to_string());
+            for (sender, replies, cost) in &self.senders {
+                lines.push(format!("  {sender}: {replies} replies, ${cost:.2}"));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Replies per hour of day as a bar chart.
+    pub(crate) fn hours_chart(&self) -> Result<Vec<u8>, AppError> {
+        let labels: Vec<String> = (0..24)
+            .map(|h| {
+                if h % 3 == 0 {
+                    format!("{h:02}")
+                } else {
+                    String::new()
+                }
+            })
+            .collect();
+        crate::format::bar_chart_png(
+            &format!("Replies by hour (UTC), last {} days", self.days),
+            &labels,
+            &self.hours,
+        )
+    }
+}
+
+fn format_span(secs: i64) -> String {
+    match secs / 60 {
+        0 => "under a minute".to_string(),
+        mins if mins < 60 => format!("{mins}m"),
+        mins => format!("{}h {}m", mins / 60, mins % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::tests::run;
+
+    #[test]
+    fn test_build_and_text() {
+        // 1970-01-05 is a Monday; 09:00 and 14:00 UTC.
+        let day = 4 * 86_400;
+        let runs = vec![
+            Run {
+                project: Some("api".to_string()),
+                ..run("+a", "s1", 0.50, day + 9 * 3600)
+            },
+            Run {
+                project: Some("api".to_string()),
+                ..run("+a", "s1", 0.25, day + 9 * 3600 + 7_800)
+            },
+            run("+b", "s2", 1.00, day + 14 * 3600),
+        ];
+        let report = Report::build(&runs, 7);
+        assert_eq!(report.replies, 3);
+        assert_eq!(report.hours[9], 1);
+        assert_eq!(report.hours[11], 1);
+        assert_eq!(report.hours[14], 1);
+        assert_eq!(report.projects[0], ("api".to_string(), 2, 0.75));
+        assert_eq!(report.sessions[0].secs, 7_800);
+        assert_eq!(report.senders[0].0, "+b");
+
+        let text = report.text(true);
+        assert!(text.starts_with("Usage report, last 7 days (everyone):\nReplies: 3\nCost: $1.75"));
+        assert!(text.contains("Busiest hours (UTC): 09:00 (1), 11:00 (1), 14:00 (1)"));
+        assert!(text.contains("  api: 2 replies, $0.75\n  (no project): 1 replies, $1.00"));
+        assert!(text.contains("  +a Mon 05 Jan 09:00: 2h 10m, 2 replies"));
+        assert!(text.ends_with("Top senders:\n  +b: 1 replies, $1.00\n  +a: 2 replies, $0.75"));
+        assert!(!report.text(false).contains("Top senders"));
+        assert!(report.hours_chart().unwrap().starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn test_empty_report() {
+        let report = Report::build(&[], 7);
+        assert_eq!(
+            report.text(false),
+            "Usage report (you): no Claude replies in the last 7 days."
+        );
+    }
+}
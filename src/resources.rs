@@ -0,0 +1,158 @@
+//! System resource guardrails checked before each Claude run.
+//!
+//! Thresholds come from the `resource_limits` section of the `--config` file
+//! (reloaded on SIGHUP). When the machine is over one, new requests are
+//! queued for the retry loop or turned away, and the account owner is told.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sysinfo::{Disks, System};
+
+use crate::constants::TMP_DIR;
+
+/// What to do with a request that arrives while the machine is overloaded.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Overload {
+    /// Hold the request and answer once the machine recovers.
+    #[default]
+    Queue,
+    /// Ask the sender to try again later.
+    Reject,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct ResourceLimits {
+    /// Highest 1-minute load average.
+    #[serde(default)]
+    pub(crate) max_load: Option<f64>,
+    /// Least available memory, in MB.
+    #[serde(default)]
+    pub(crate) min_free_memory_mb: Option<u64>,
+    /// Least free disk space on the filesystem holding `TMP_DIR`, in MB.
+    #[serde(default)]
+    pub(crate) min_free_disk_mb: Option<u64>,
+    #[serde(default)]
+    pub(crate) when_exceeded: Overload,
+}
+
+/// Current load, available memory and free disk under `TMP_DIR`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Snapshot {
+    pub(crate) load_1m: f64,
+    pub(crate) free_memory_mb: u64,
+    /// None if the filesystem couldn't be found.
+    pub(crate) free_disk_mb: Option<u64>,
+}
+
+pub(crate) fn snapshot() -> Snapshot {
+    let mut system = System::new();
+    system.refresh_memory();
+    Snapshot {
+        load_1m: System::load_average().one,
+        free_memory_mb: system.available_memory() / (1024 * 1024),
+        free_disk_mb: free_disk_mb(Path::new(TMP_DIR)),
+    }
+}
+
+/// Free space on the mount that holds `path` (the longest matching mount point).
+fn free_disk_mb(path: &Path) -> Option<u64> {
+    let _ = std::fs::create_dir_all(path);
+    let path = path.canonicalize().ok()?;
+    let disks = Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|d| path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+        .map(|d| d.available_space() / (1024 * 1024))
+}
+
+impl ResourceLimits {
+    pub(crate) fn is_set(&self) -> bool {
+        self.max_load.is_some()
+            || self.min_free_memory_mb.is_some()
+            || self.min_free_disk_mb.is_some()
+    }
+
+    /// Which limits `snap` breaks, e.g. "load 12.3 (max 8)", or None.
+    pub(crate) fn exceeded(&self, snap: &Snapshot) -> Option<String> {
+        let mut over = Vec::new();
+        if let Some(max) = self.max_load.filter(|max| snap.load_1m > *max) {
+            over.push(format!("load {:.1} (max {max})", snap.load_1m));
+        }
+        if let Some(min) = self
+            .min_free_memory_mb
+            .filter(|min| snap.free_memory_mb < *min)
+        {
+            over.push(format!(
+                "free memory {} MB (min {min} MB)",
+                snap.free_memory_mb
+            ));
+        }
+        if let (Some(min), Some(free)) = (self.min_free_disk_mb, snap.free_disk_mb) {
+            if free < min {
+                over.push(format!("free disk {free} MB (min {min} MB)"));
+            }
+        }
+        (!over.is_empty()).then(|| over.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(load_1m: f64, free_memory_mb: u64, free_disk_mb: Option<u64>) -> Snapshot {
+        Snapshot {
+            load_1m,
+            free_memory_mb,
+            free_disk_mb,
+        }
+    }
+
+    #[test]
+    fn test_exceeded_lists_each_breach() {
+        let limits = ResourceLimits {
+            max_load: Some(8.0),
+            min_free_memory_mb: Some(512),
+            min_free_disk_mb: Some(1024),
+            when_exceeded: Overload::Reject,
+        };
+        assert!(limits.is_set());
+        assert_eq!(limits.exceeded(&snap(2.0, 4096, Some(50_000))), None);
+        assert_eq!(
+            limits.exceeded(&snap(12.34, 100, Some(10))).as_deref(),
+            Some(
+                "load 12.3 (max 8), free memory 100 MB (min 512 MB), free disk 10 MB (min 1024 MB)"
+            )
+        );
+        // An unknown filesystem isn't treated as full.
+        assert_eq!(limits.exceeded(&snap(1.0, 4096, None)), None);
+        assert!(!ResourceLimits::default().is_set());
+        assert_eq!(
+            ResourceLimits::default().exceeded(&snap(99.0, 0, Some(0))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_limits_from_yaml() {
+        let limits: ResourceLimits =
+            serde_yaml::from_str("max_load: 6\nmin_free_disk_mb: 2048\nwhen_exceeded: reject\n")
+                .unwrap();
+        assert_eq!(limits.max_load, Some(6.0));
+        assert_eq!(limits.min_free_memory_mb, None);
+        assert_eq!(limits.when_exceeded, Overload::Reject);
+        let limits: ResourceLimits = serde_yaml::from_str("max_load: 4.5\n").unwrap();
+        assert_eq!(limits.when_exceeded, Overload::Queue);
+    }
+
+    #[test]
+    fn test_snapshot_reads_the_machine() {
+        let snap = snapshot();
+        assert!(snap.load_1m >= 0.0);
+        assert!(snap.free_memory_mb > 0);
+    }
+}
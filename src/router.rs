@@ -0,0 +1,223 @@
+//! Model aliases and routing rules.
+//!
+//! Both come from the `--config` file (reloaded on SIGHUP). `model_aliases`
+//! maps short names to model ids and works anywhere a model is named.
+//! `model_routes` is checked in order before each Claude run; the first rule
+//! whose conditions all hold picks the model:
+//!
+//! ```yaml
+//! model_aliases:
+//!   fast: haiku
+//!   deep: opus
+//! model_routes:
+//!   - command: /deep        # "/deep <question>" always goes to opus
+//!     model: deep
+//!   - sender: "+447711111111"
+//!     model: sonnet
+//!   - shorter_than: 200     # quick questions
+//!     model: fast
+//! ```
+//!
+//! Command rules apply to every message that starts with the command. The
+//! other rules only apply while the sender is on the default model; once
+//! they pick one with `/model`, that choice wins.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct RouteRule {
+    /// Message starts with this word (e.g. `/deep`), which is removed.
+    #[serde(default)]
+    pub(crate) command: Option<String>,
+    /// Message is from this sender or group.
+    #[serde(default)]
+    pub(crate) sender: Option<String>,
+    /// Message has fewer characters than this.
+    #[serde(default)]
+    pub(crate) shorter_than: Option<usize>,
+    /// Model or alias to use.
+    pub(crate) model: String,
+}
+
+impl RouteRule {
+    /// The message with the rule's command removed, if the rule matches.
+    fn matches<'a>(&self, sender: &str, text: &'a str) -> Option<&'a str> {
+        let text = match &self.command {
+            Some(command) => strip_command(text, command)?,
+            None => text,
+        };
+        if self.sender.as_deref().is_some_and(|s| s != sender) {
+            return None;
+        }
+        if self
+            .shorter_than
+            .is_some_and(|limit| text.chars().count() >= limit)
+        {
+            return None;
+        }
+        Some(text)
+    }
+
+    fn describe(&self) -> String {
+        let mut conditions = Vec::new();
+        if let Some(command) = &self.command {
+            conditions.push(command.clone());
+        }
+        if let Some(sender) = &self.sender {
+            conditions.push(format!("from {sender}"));
+        }
+        if let Some(limit) = self.shorter_than {
+            conditions.push(format!("under {limit} chars"));
+        }
+        if conditions.is_empty() {
+            conditions.push("every message".to_string());
+        }
+        conditions.join(", ")
+    }
+}
+
+fn strip_command<'a>(text: &'a str, command: &str) -> Option<&'a str> {
+    let rest = text.trim_start().strip_prefix(command)?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim_start())
+}
+
+/// The model chosen for one message.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Route<'a> {
+    pub(crate) model: String,
+    /// The message to send, without a routing command.
+    pub(crate) text: &'a str,
+    /// Which rule picked the model, if one did.
+    pub(crate) reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Router {
+    pub(crate) aliases: BTreeMap<String, String>,
+    pub(crate) routes: Vec<RouteRule>,
+}
+
+impl Router {
+    /// The model an alias stands for, or `name` itself.
+    pub(crate) fn resolve(&self, name: &str) -> String {
+        self.aliases
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// Whether `text` starts with a routing rule's command.
+    pub(crate) fn is_routing_command(&self, text: &str) -> bool {
+        self.routes.iter().any(|rule| {
+            rule.command
+                .as_deref()
+                .is_some_and(|command| strip_command(text, command).is_some())
+        })
+    }
+
+    /// Pick the model for `text`. `model` is the sender's session model and
+    /// `chosen` whether they picked it with `/model`.
+    pub(crate) fn route<'a>(
+        &self,
+        sender: &str,
+        text: &'a str,
+        model: &str,
+        chosen: bool,
+    ) -> Route<'a> {
+        for (i, rule) in self.routes.iter().enumerate() {
+            if chosen && rule.command.is_none() {
+                continue;
+            }
+            if let Some(text) = rule.matches(sender, text) {
+                return Route {
+                    model: self.resolve(&rule.model),
+                    text,
+                    reason: Some(format!("rule {}: {}", i + 1, rule.describe())),
+                };
+            }
+        }
+        Route {
+            model: self.resolve(model),
+            text,
+            reason: None,
+        }
+    }
+
+    /// `/model` listing of aliases and rules.
+    pub(crate) fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        if !self.aliases.is_empty() {
+            lines.push("Aliases:".to_string());
+            for (alias, model) in &self.aliases {
+                lines.push(format!("  {alias} → {model}"));
+            }
+        }
+        if !self.routes.is_empty() {
+            lines.push("Routing:".to_string());
+            for (i, rule) in self.routes.iter().enumerate() {
+                lines.push(format!("  {}. {} → {}", i + 1, rule.describe(), rule.model));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> Router {
+        Router {
+            aliases: [("deep", "opus"), ("fast", "haiku")]
+                .into_iter()
+                .map(|(a, m)| (a.to_string(), m.to_string()))
+                .collect(),
+            routes: vec![
+                RouteRule {
+                    command: Some("/deep".to_string()),
+                    model: "deep".to_string(),
+                    ..Default::default()
+                },
+                RouteRule {
+                    sender: Some("+vip".to_string()),
+                    model: "sonnet".to_string(),
+                    ..Default::default()
+                },
+                RouteRule {
+                    shorter_than: Some(20),
+                    model: "fast".to_string(),
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let router = router();
+        let route = router.route("+a", "/deep why is the sky blue", "sonnet", false);
+        assert_eq!(route.model, "opus");
+        assert_eq!(route.text, "why is the sky blue");
+        assert_eq!(route.reason.as_deref(), Some("rule 1: /deep"));
+        // "/deeper" isn't "/deep"
+        assert!(!router.is_routing_command("/deeper thoughts"));
+
+        assert_eq!(router.route("+vip", "hi", "sonnet", false).model, "sonnet");
+        let short = router.route("+a", "hi", "sonnet", false);
+        assert_eq!(short.model, "haiku");
+        assert_eq!(short.reason.as_deref(), Some("rule 3: under 20 chars"));
+        let long = router.route("+a", "a message of well over twenty chars", "deep", false);
+        assert_eq!((long.model.as_str(), long.reason), ("opus", None));
+    }
+
+    #[test]
+    fn test_chosen_model_only_yields_to_commands() {
+        let router = router();
+        assert_eq!(router.route("+a", "hi", "opus", true).model, "opus");
+        assert_eq!(router.route("+a", "/deep hi", "sonnet", true).model, "opus");
+        assert_eq!(router.resolve("fast"), "haiku");
+        assert_eq!(router.resolve("claude-sonnet-4-5"), "claude-sonnet-4-5");
+    }
+}
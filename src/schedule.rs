@@ -7,18 +7,8 @@ use tracing::error;
 
 use crate::error::AppError;
 
-fn config_dir() -> std::path::PathBuf {
-    let dir = std::env::var("HOME")
-        .map(std::path::PathBuf::from)
-        .unwrap_or_else(|_| std::path::PathBuf::from("."))
-        .join(".config")
-        .join("ccchat");
-    let _ = std::fs::create_dir_all(&dir);
-    dir
-}
-
 pub(crate) fn open_schedule_db() -> Result<Connection, AppError> {
-    let path = config_dir().join("schedule.db");
+    let path = crate::memory::config_dir().join("schedule.db");
     let conn = Connection::open(&path)?;
     conn.execute_batch("PRAGMA journal_mode=WAL;")?;
     conn.execute_batch(
@@ -45,9 +35,48 @@ pub(crate) fn open_schedule_db() -> Result<Connection, AppError> {
             created_at INTEGER NOT NULL
         );",
     )?;
+    conn.execute_batch(FEEDS_SCHEMA)?;
+    conn.execute_batch(REPORTS_SCHEMA)?;
+    conn.execute_batch(FOLLOWUPS_SCHEMA)?;
     Ok(conn)
 }
 
+/// Feed subscriptions and the item IDs already delivered for each.
+const FEEDS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS feeds (
+        id INTEGER PRIMARY KEY,
+        sender TEXT NOT NULL,
+        url TEXT NOT NULL,
+        title TEXT NOT NULL,
+        cron_pattern TEXT,
+        interval_secs INTEGER,
+        summarize INTEGER NOT NULL DEFAULT 0,
+        next_check_at INTEGER NOT NULL,
+        last_checked_at INTEGER,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS feed_items (
+        feed_id INTEGER NOT NULL,
+        item_id TEXT NOT NULL,
+        seen_at INTEGER NOT NULL,
+        PRIMARY KEY (feed_id, item_id)
+    );";
+
+const REPORTS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS report_subs (
+        sender TEXT PRIMARY KEY,
+        global INTEGER NOT NULL DEFAULT 0,
+        next_at INTEGER NOT NULL
+    );";
+
+/// One-shot `/followup`s: a prompt run (or, with `prompt = 0`, a message).
+const FOLLOWUPS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS followups (
+        id INTEGER PRIMARY KEY,
+        sender TEXT NOT NULL,
+        text TEXT NOT NULL,
+        prompt INTEGER NOT NULL DEFAULT 1,
+        run_at INTEGER NOT NULL,
+        created_at INTEGER NOT NULL
+    );";
+
 pub(crate) fn add_reminder(conn: &Connection, sender: &str, message: &str, deliver_at: i64) -> i64 {
     let now = crate::helpers::epoch_now();
     if let Err(e) = conn.execute(
@@ -282,6 +311,264 @@ pub(crate) fn resume_cron_job(conn: &Connection, id: i64, sender: &str) -> bool
     rows > 0
 }
 
+// --- Feed subscriptions ---
+
+/// A `/subscribe` feed.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FeedSub {
+    pub(crate) id: i64,
+    pub(crate) sender: String,
+    pub(crate) url: String,
+    pub(crate) title: String,
+    pub(crate) cron_pattern: Option<String>,
+    pub(crate) interval_secs: Option<i64>,
+    pub(crate) summarize: bool,
+}
+
+fn next_check(cron_pattern: Option<&str>, interval_secs: Option<i64>) -> Option<i64> {
+    match (cron_pattern, interval_secs) {
+        (Some(pattern), _) => compute_next_cron_delivery(pattern),
+        (None, Some(secs)) => Some(crate::helpers::epoch_now() + secs),
+        (None, None) => None,
+    }
+}
+
+/// Returns the new feed's ID, or 0 if the schedule is invalid.
+pub(crate) fn add_feed(
+    conn: &Connection,
+    sender: &str,
+    url: &str,
+    title: &str,
+    cron_pattern: Option<&str>,
+    interval_secs: Option<i64>,
+    summarize: bool,
+) -> i64 {
+    let Some(next) = next_check(cron_pattern, interval_secs) else {
+        return 0;
+    };
+    let now = crate::helpers::epoch_now();
+    if let Err(e) = conn.execute(
+        "INSERT INTO feeds (sender, url, title, cron_pattern, interval_secs, summarize, next_check_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        rusqlite::params![sender, url, title, cron_pattern, interval_secs, summarize, next, now],
+    ) {
+        error!("Failed to add feed: {e}");
+        return 0;
+    }
+    conn.last_insert_rowid()
+}
+
+fn query_feeds(conn: &Connection, filter: &str, param: &dyn rusqlite::ToSql) -> Vec<FeedSub> {
+    let sql = format!(
+        "SELECT id, sender, url, title, cron_pattern, interval_secs, summarize FROM feeds WHERE {filter} ORDER BY id"
+    );
+    let Ok(mut stmt) = conn.prepare(&sql) else {
+        return Vec::new();
+    };
+    stmt.query_map([param], |row| {
+        Ok(FeedSub {
+            id: row.get(0)?,
+            sender: row.get(1)?,
+            url: row.get(2)?,
+            title: row.get(3)?,
+            cron_pattern: row.get(4)?,
+            interval_secs: row.get(5)?,
+            summarize: row.get(6)?,
+        })
+    })
+    .ok()
+    .map(|iter| iter.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+pub(crate) fn get_feeds(conn: &Connection, sender: &str) -> Vec<FeedSub> {
+    query_feeds(conn, "sender = ?1", &sender)
+}
+
+pub(crate) fn get_due_feeds(conn: &Connection) -> Vec<FeedSub> {
+    query_feeds(conn, "next_check_at <= ?1", &crate::helpers::epoch_now())
+}
+
+pub(crate) fn advance_feed(conn: &Connection, feed: &FeedSub) {
+    let now = crate::helpers::epoch_now();
+    let next = next_check(feed.cron_pattern.as_deref(), feed.interval_secs).unwrap_or(now + 3600);
+    let _ = conn.execute(
+        "UPDATE feeds SET next_check_at = ?1, last_checked_at = ?2 WHERE id = ?3",
+        rusqlite::params![next, now, feed.id],
+    );
+}
+
+pub(crate) fn remove_feed(conn: &Connection, id: i64, sender: &str) -> bool {
+    let rows = conn
+        .execute(
+            "DELETE FROM feeds WHERE id = ?1 AND sender = ?2",
+            rusqlite::params![id, sender],
+        )
+        .unwrap_or(0);
+    if rows > 0 {
+        let _ = conn.execute(
+            "DELETE FROM feed_items WHERE feed_id = ?1",
+            rusqlite::params![id],
+        );
+    }
+    rows > 0
+}
+
+/// Record item IDs as seen; returns the ones that weren't seen before, in
+/// the order given. Old IDs beyond the newest `keep` are forgotten.
+pub(crate) fn mark_feed_items_seen(
+    conn: &Connection,
+    feed_id: i64,
+    item_ids: &[&str],
+    keep: usize,
+) -> Vec<String> {
+    let now = crate::helpers::epoch_now();
+    let mut new = Vec::new();
+    for item_id in item_ids {
+        let inserted = conn
+            .execute(
+                "INSERT OR IGNORE INTO feed_items (feed_id, item_id, seen_at) VALUES (?1, ?2, ?3)",
+                rusqlite::params![feed_id, item_id, now],
+            )
+            .unwrap_or(0);
+        if inserted > 0 {
+            new.push(item_id.to_string());
+        } else {
+            // Still in the feed, so keep it from being pruned.
+            let _ = conn.execute(
+                "UPDATE feed_items SET seen_at = ?1 WHERE feed_id = ?2 AND item_id = ?3",
+                rusqlite::params![now, feed_id, item_id],
+            );
+        }
+    }
+    let _ = conn.execute(
+        "DELETE FROM feed_items WHERE feed_id = ?1 AND rowid NOT IN
+            (SELECT rowid FROM feed_items WHERE feed_id = ?1 ORDER BY seen_at DESC, rowid DESC LIMIT ?2)",
+        rusqlite::params![feed_id, keep as i64],
+    );
+    new
+}
+
+// --- Weekly reports ---
+
+/// Send `sender` the weekly `/report` (everyone's if `global`). Replaces
+/// any earlier choice.
+pub(crate) fn set_report_sub(conn: &Connection, sender: &str, global: bool) -> bool {
+    let Some(next) = compute_next_cron_delivery(crate::constants::WEEKLY_REPORT_CRON) else {
+        return false;
+    };
+    conn.execute(
+        "INSERT OR REPLACE INTO report_subs (sender, global, next_at) VALUES (?1, ?2, ?3)",
+        rusqlite::params![sender, global, next],
+    )
+    .map_err(|e| error!("Failed to save report subscription: {e}"))
+    .is_ok()
+}
+
+pub(crate) fn remove_report_sub(conn: &Connection, sender: &str) -> bool {
+    conn.execute(
+        "DELETE FROM report_subs WHERE sender = ?1",
+        rusqlite::params![sender],
+    )
+    .unwrap_or(0)
+        > 0
+}
+
+/// Due weekly reports: (sender, global).
+pub(crate) fn get_due_report_subs(conn: &Connection) -> Vec<(String, bool)> {
+    let Ok(mut stmt) = conn.prepare("SELECT sender, global FROM report_subs WHERE next_at <= ?1")
+    else {
+        return Vec::new();
+    };
+    stmt.query_map(rusqlite::params![crate::helpers::epoch_now()], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })
+    .ok()
+    .map(|iter| iter.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+pub(crate) fn advance_report_sub(conn: &Connection, sender: &str) {
+    let next = compute_next_cron_delivery(crate::constants::WEEKLY_REPORT_CRON)
+        .unwrap_or_else(|| crate::helpers::epoch_now() + 7 * crate::constants::SECS_PER_DAY);
+    let _ = conn.execute(
+        "UPDATE report_subs SET next_at = ?1 WHERE sender = ?2",
+        rusqlite::params![next, sender],
+    );
+}
+
+// --- Follow-ups ---
+
+/// A `/followup`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Followup {
+    pub(crate) id: i64,
+    pub(crate) sender: String,
+    pub(crate) text: String,
+    /// Run `text` as a prompt; otherwise just send it.
+    pub(crate) prompt: bool,
+    pub(crate) run_at: i64,
+    pub(crate) created_at: i64,
+}
+
+/// Returns the new follow-up's ID, or 0 on failure.
+pub(crate) fn add_followup(
+    conn: &Connection,
+    sender: &str,
+    text: &str,
+    prompt: bool,
+    run_at: i64,
+) -> i64 {
+    let now = crate::helpers::epoch_now();
+    if let Err(e) = conn.execute(
+        "INSERT INTO followups (sender, text, prompt, run_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![sender, text, prompt, run_at, now],
+    ) {
+        error!("Failed to add follow-up: {e}");
+        return 0;
+    }
+    conn.last_insert_rowid()
+}
+
+fn query_followups(conn: &Connection, filter: &str, param: &dyn rusqlite::ToSql) -> Vec<Followup> {
+    let sql = format!(
+        "SELECT id, sender, text, prompt, run_at, created_at FROM followups WHERE {filter} ORDER BY run_at, id"
+    );
+    let Ok(mut stmt) = conn.prepare(&sql) else {
+        return Vec::new();
+    };
+    stmt.query_map([param], |row| {
+        Ok(Followup {
+            id: row.get(0)?,
+            sender: row.get(1)?,
+            text: row.get(2)?,
+            prompt: row.get(3)?,
+            run_at: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    })
+    .ok()
+    .map(|iter| iter.filter_map(|r| r.ok()).collect())
+    .unwrap_or_default()
+}
+
+pub(crate) fn get_followups(conn: &Connection, sender: &str) -> Vec<Followup> {
+    query_followups(conn, "sender = ?1", &sender)
+}
+
+pub(crate) fn get_due_followups(conn: &Connection) -> Vec<Followup> {
+    query_followups(conn, "run_at <= ?1", &crate::helpers::epoch_now())
+}
+
+/// Delete a follow-up; false when `sender` has none with that ID.
+pub(crate) fn remove_followup(conn: &Connection, id: i64, sender: &str) -> bool {
+    conn.execute(
+        "DELETE FROM followups WHERE id = ?1 AND sender = ?2",
+        rusqlite::params![id, sender],
+    )
+    .unwrap_or(0)
+        > 0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -314,6 +601,9 @@ mod tests {
             );",
         )
         .unwrap();
+        conn.execute_batch(FEEDS_SCHEMA).unwrap();
+        conn.execute_batch(REPORTS_SCHEMA).unwrap();
+        conn.execute_batch(FOLLOWUPS_SCHEMA).unwrap();
         conn
     }
 
@@ -596,4 +886,110 @@ mod tests {
         assert_eq!(alice_jobs.len(), 1);
         assert_eq!(alice_jobs[0].1, "alice job");
     }
+
+    // --- Feed tests ---
+
+    #[test]
+    fn test_add_feed_and_remove() {
+        let conn = test_schedule_db();
+        let id = add_feed(
+            &conn,
+            "+user",
+            "https://example.com/feed.xml",
+            "Example",
+            None,
+            Some(3600),
+            true,
+        );
+        assert!(id > 0);
+        let feeds = get_feeds(&conn, "+user");
+        assert_eq!(feeds.len(), 1);
+        assert_eq!(feeds[0].title, "Example");
+        assert!(feeds[0].summarize);
+        assert!(get_due_feeds(&conn).is_empty());
+        assert_eq!(
+            add_feed(&conn, "+user", "u", "t", Some("not a cron"), None, false),
+            0
+        );
+        assert!(!remove_feed(&conn, id, "+other"));
+        assert!(remove_feed(&conn, id, "+user"));
+        assert!(get_feeds(&conn, "+user").is_empty());
+    }
+
+    #[test]
+    fn test_mark_feed_items_seen_dedupes_and_prunes() {
+        let conn = test_schedule_db();
+        assert_eq!(
+            mark_feed_items_seen(&conn, 1, &["a", "b"], 3),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            mark_feed_items_seen(&conn, 1, &["c", "a", "b"], 3),
+            vec!["c"]
+        );
+        assert!(mark_feed_items_seen(&conn, 1, &["a"], 3).is_empty());
+        // Other feeds have their own history.
+        assert_eq!(mark_feed_items_seen(&conn, 2, &["a"], 3), vec!["a"]);
+        let kept: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM feed_items WHERE feed_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(kept, 3);
+        mark_feed_items_seen(&conn, 1, &["d", "e"], 3);
+        let kept: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM feed_items WHERE feed_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(kept, 3);
+    }
+
+    // --- Report subscription tests ---
+
+    #[test]
+    fn test_report_subs_due_and_advance() {
+        let conn = test_schedule_db();
+        assert!(set_report_sub(&conn, "+user", false));
+        assert!(set_report_sub(&conn, "+user", true));
+        assert!(get_due_report_subs(&conn).is_empty());
+        conn.execute("UPDATE report_subs SET next_at = 0", [])
+            .unwrap();
+        assert_eq!(
+            get_due_report_subs(&conn),
+            vec![("+user".to_string(), true)]
+        );
+        advance_report_sub(&conn, "+user");
+        assert!(get_due_report_subs(&conn).is_empty());
+        assert!(remove_report_sub(&conn, "+user"));
+        assert!(!remove_report_sub(&conn, "+user"));
+    }
+
+    #[test]
+    fn test_followups_due_and_remove() {
+        let conn = test_schedule_db();
+        let now = crate::helpers::epoch_now();
+        let later = add_followup(&conn, "+user", "check the deploy", true, now + 7200);
+        let due = add_followup(&conn, "+user", "stretch", false, now - 5);
+        assert!(later > 0 && due > 0);
+        let listed = get_followups(&conn, "+user");
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].text, "stretch");
+        assert!(!listed[0].prompt);
+        assert!(listed[1].prompt);
+        assert_eq!(
+            get_due_followups(&conn)
+                .iter()
+                .map(|f| f.id)
+                .collect::<Vec<_>>(),
+            vec![due]
+        );
+        assert!(!remove_followup(&conn, later, "+other"));
+        assert!(remove_followup(&conn, later, "+user"));
+        assert!(get_followups(&conn, "+other").is_empty());
+    }
 }
@@ -0,0 +1,286 @@
+//! Attachment screening.
+//!
+//! Attachments are checked twice: before download, against the size and
+//! file name the messenger reports, and after, against the file itself.
+//! Files over the per-type size cap and executables are refused, and with
+//! `--clamscan` every download is also virus-scanned. A refused file is
+//! deleted and the sender is told why, so a less-trusted sender can't use
+//! the bridge to drop programs on the machine.
+
+use std::path::Path;
+use tracing::warn;
+
+use crate::signal::{AttachmentInfo, AttachmentType};
+
+/// File extensions that are refused whatever content type they claim.
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "apk", "app", "bat", "bin", "cmd", "com", "deb", "dll", "dmg", "dylib", "exe", "jar", "msi",
+    "pkg", "ps1", "rpm", "scr", "so", "vbs",
+];
+
+const EXECUTABLE_TYPES: &[&str] = &[
+    "application/java-archive",
+    "application/vnd.android.package-archive",
+    "application/vnd.microsoft.portable-executable",
+    "application/x-dosexec",
+    "application/x-executable",
+    "application/x-mach-binary",
+    "application/x-msdownload",
+    "application/x-msi",
+    "application/x-sharedlib",
+];
+
+/// Leading bytes of Windows (MZ), ELF, Mach-O and fat/universal binaries.
+const EXECUTABLE_MAGIC: &[&[u8]] = &[
+    b"MZ",
+    b"\x7fELF",
+    &[0xfe, 0xed, 0xfa, 0xce],
+    &[0xfe, 0xed, 0xfa, 0xcf],
+    &[0xce, 0xfa, 0xed, 0xfe],
+    &[0xcf, 0xfa, 0xed, 0xfe],
+    &[0xca, 0xfe, 0xba, 0xbe],
+];
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ScreeningConfig {
+    /// Size caps in bytes (None = unlimited)
+    pub(crate) max_image_bytes: Option<u64>,
+    pub(crate) max_audio_bytes: Option<u64>,
+    pub(crate) max_document_bytes: Option<u64>,
+    /// Virus-scan downloads with `clamscan` if it's installed
+    pub(crate) clamscan: bool,
+}
+
+impl ScreeningConfig {
+    fn limit(&self, kind: &AttachmentType) -> Option<(u64, &'static str)> {
+        match kind {
+            AttachmentType::Image => self.max_image_bytes.map(|b| (b, "images")),
+            AttachmentType::Audio => self.max_audio_bytes.map(|b| (b, "voice messages")),
+            AttachmentType::Document => self.max_document_bytes.map(|b| (b, "documents")),
+            AttachmentType::Other => None,
+        }
+    }
+
+    /// Checks that need no download: declared size, file name and type.
+    /// `Err` is the refusal to send back.
+    pub(crate) fn check_declared(
+        &self,
+        kind: &AttachmentType,
+        att: &AttachmentInfo,
+    ) -> Result<(), String> {
+        let extension = att
+            .filename
+            .as_deref()
+            .and_then(|name| Path::new(name).extension())
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        if extension.is_some_and(|ext| EXECUTABLE_EXTENSIONS.contains(&ext.as_str()))
+            || EXECUTABLE_TYPES.contains(&att.content_type.as_str())
+        {
+            return Err(refusal(att, "executable files aren't accepted"));
+        }
+        self.check_size(kind, att, att.size)
+    }
+
+    /// Checks on the downloaded file: real size, executable headers and,
+    /// with `clamscan` on, a virus scan.
+    pub(crate) async fn check_downloaded(
+        &self,
+        kind: &AttachmentType,
+        att: &AttachmentInfo,
+        path: &Path,
+    ) -> Result<(), String> {
+        let size = tokio::fs::metadata(path).await.ok().map(|m| m.len());
+        self.check_size(kind, att, size)?;
+        if is_executable(path).await {
+            return Err(refusal(att, "executable files aren't accepted"));
+        }
+        if self.clamscan {
+            scan(att, path).await?;
+        }
+        Ok(())
+    }
+
+    fn check_size(
+        &self,
+        kind: &AttachmentType,
+        att: &AttachmentInfo,
+        size: Option<u64>,
+    ) -> Result<(), String> {
+        match (size, self.limit(kind)) {
+            (Some(size), Some((limit, what))) if size > limit => Err(refusal(
+                att,
+                &format!("{} is over the {} limit for {what}", mb(size), mb(limit)),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn refusal(att: &AttachmentInfo, reason: &str) -> String {
+    let name = att.filename.as_deref().unwrap_or(&att.content_type);
+    format!("Refused {name}: {reason}.")
+}
+
+fn mb(bytes: u64) -> String {
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    if mb.fract() == 0.0 {
+        format!("{mb:.0} MB")
+    } else {
+        format!("{mb:.1} MB")
+    }
+}
+
+async fn is_executable(path: &Path) -> bool {
+    use tokio::io::AsyncReadExt;
+    let Ok(mut file) = tokio::fs::File::open(path).await else {
+        return false;
+    };
+    let mut head = [0u8; 4];
+    let n = file.read(&mut head).await.unwrap_or(0);
+    EXECUTABLE_MAGIC
+        .iter()
+        .any(|magic| head[..n].starts_with(magic))
+}
+
+/// Run `clamscan` on one file. Exit 0 is clean and 1 is infected; anything
+/// else means the scan itself failed, and the file is refused rather than
+/// let through unscanned. A missing `clamscan` only logs a warning.
+async fn scan(att: &AttachmentInfo, path: &Path) -> Result<(), String> {
+    let output = match tokio::process::Command::new("clamscan")
+        .args(["--no-summary", "--infected"])
+        .arg(path)
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            warn!("--clamscan is on but clamscan isn't installed; attachment not scanned");
+            return Ok(());
+        }
+        Err(e) => {
+            warn!("clamscan failed to start: {e}");
+            return Err(refusal(att, "it couldn't be virus-scanned"));
+        }
+    };
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(1) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let signature = stdout
+                .lines()
+                .find_map(|line| line.strip_suffix(" FOUND")?.rsplit(": ").next())
+                .unwrap_or("malware");
+            warn!(attachment = %att.id, "clamscan flagged attachment: {signature}");
+            Err(refusal(
+                att,
+                &format!("the virus scanner flagged it ({signature})"),
+            ))
+        }
+        _ => {
+            warn!(
+                "clamscan error: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            Err(refusal(att, "it couldn't be virus-scanned"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn att(content_type: &str, filename: &str, size: Option<u64>) -> AttachmentInfo {
+        AttachmentInfo {
+            id: "att1".to_string(),
+            content_type: content_type.to_string(),
+            filename: Some(filename.to_string()),
+            size,
+            voice_note: false,
+        }
+    }
+
+    #[test]
+    fn test_check_declared_refuses_executables_and_oversize() {
+        let config = ScreeningConfig {
+            max_image_bytes: Some(20 * 1024 * 1024),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.check_declared(
+                &AttachmentType::Document,
+                &att("text/plain", "setup.EXE", Some(10))
+            ),
+            Err("Refused setup.EXE: executable files aren't accepted.".to_string())
+        );
+        assert!(config
+            .check_declared(
+                &AttachmentType::Other,
+                &att("application/x-msdownload", "tool", None)
+            )
+            .is_err());
+        assert_eq!(
+            config.check_declared(
+                &AttachmentType::Image,
+                &att("image/png", "big.png", Some(31 * 1024 * 1024 + 512 * 1024))
+            ),
+            Err("Refused big.png: 31.5 MB is over the 20 MB limit for images.".to_string())
+        );
+        assert!(config
+            .check_declared(
+                &AttachmentType::Image,
+                &att("image/png", "ok.png", Some(1024))
+            )
+            .is_ok());
+        // No cap configured for documents, and unknown sizes pass until downloaded
+        assert!(config
+            .check_declared(
+                &AttachmentType::Document,
+                &att("application/pdf", "big.pdf", Some(u64::MAX))
+            )
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_check_downloaded_spots_disguised_binaries() {
+        let dir = std::env::temp_dir().join(format!("ccchat_screening_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = ScreeningConfig {
+            max_document_bytes: Some(8),
+            ..Default::default()
+        };
+        let elf = dir.join("notes.txt");
+        std::fs::write(&elf, b"\x7fELF\x02").unwrap();
+        assert!(config
+            .check_downloaded(
+                &AttachmentType::Document,
+                &att("text/plain", "notes.txt", None),
+                &elf
+            )
+            .await
+            .unwrap_err()
+            .contains("executable"));
+        let long = dir.join("long.txt");
+        std::fs::write(&long, b"more than eight bytes").unwrap();
+        assert!(config
+            .check_downloaded(
+                &AttachmentType::Document,
+                &att("text/plain", "long.txt", None),
+                &long
+            )
+            .await
+            .unwrap_err()
+            .contains("over the"));
+        let fine = dir.join("fine.txt");
+        std::fs::write(&fine, b"hello").unwrap();
+        assert!(config
+            .check_downloaded(
+                &AttachmentType::Document,
+                &att("text/plain", "fine.txt", None),
+                &fine
+            )
+            .await
+            .is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,79 @@
+//! Screen capture for `/screenshot` (`--features screenshot`, then
+//! `--allow-screenshot`).
+//!
+//! Uses the platform's own tool: `screencapture` on macOS; on Linux `grim`
+//! under Wayland, otherwise the first of `gnome-screenshot`, `spectacle`,
+//! `scrot` and ImageMagick's `import` that is installed.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use crate::constants::{SCREENSHOT_TIMEOUT_SECS, TMP_DIR};
+use crate::error::AppError;
+
+/// Capture tools to try, as (program, args before the output path).
+fn tools() -> Vec<(&'static str, &'static [&'static str])> {
+    if cfg!(target_os = "macos") {
+        return vec![("screencapture", &["-x", "-t", "png"])];
+    }
+    let mut tools: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(("grim", &[]));
+    }
+    tools.extend([
+        ("gnome-screenshot", &["-f"][..]),
+        ("spectacle", &["-b", "-n", "-o"][..]),
+        ("scrot", &["-o"][..]),
+        ("import", &["-window", "root"][..]),
+    ]);
+    tools
+}
+
+/// PNG of the whole screen.
+pub(crate) async fn capture() -> Result<Vec<u8>, AppError> {
+    let dir = PathBuf::from(TMP_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("screenshot-{}.png", uuid::Uuid::new_v4()));
+    let result = capture_to(&path).await;
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+async fn capture_to(path: &Path) -> Result<Vec<u8>, AppError> {
+    for (program, args) in tools() {
+        let child = Command::new(program)
+            .args(args)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        let child = match child {
+            Ok(child) => child,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let output = tokio::time::timeout(
+            Duration::from_secs(SCREENSHOT_TIMEOUT_SECS),
+            child.wait_with_output(),
+        )
+        .await
+        .map_err(|_| AppError::Other(format!("{program} timed out")))??;
+        if !output.status.success() {
+            return Err(AppError::Other(format!(
+                "{program} exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        return Ok(std::fs::read(path)?);
+    }
+    Err(AppError::Other(
+        "no screenshot tool found (install grim, gnome-screenshot, spectacle, scrot or ImageMagick)"
+            .to_string(),
+    ))
+}
@@ -0,0 +1,212 @@
+//! Settings the account owner can change from chat with `/config set`.
+//!
+//! They start from the command-line values. Changes apply to the next
+//! message and are saved in `~/.config/ccchat/allowed.json`, where they
+//! override the command line on restart until `/config reset`.
+
+use std::collections::BTreeMap;
+
+use tracing::warn;
+
+use crate::format::{FormatPrefs, TableFormat};
+use crate::state::Config;
+
+/// Keys accepted by `/config set`, in `/config show` order.
+pub(crate) const KEYS: &[&str] = &[
+    "model",
+    "max_budget",
+    "debounce_ms",
+    "format.tables",
+    "format.code",
+    "signature",
+];
+
+/// Longest signature `/config set` accepts.
+const MAX_SIGNATURE_LEN: usize = 200;
+
+/// Longest debounce window `/config set` accepts.
+const MAX_DEBOUNCE_MS: u64 = 60_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Settings {
+    /// Model for new sessions without a `/model` preference.
+    pub(crate) model: String,
+    /// Budget per Claude run in USD.
+    pub(crate) max_budget: f64,
+    /// Debounce window for merging bursts of messages (0 = off).
+    pub(crate) debounce_ms: u64,
+    /// Formatting for senders who haven't chosen their own with `/format`.
+    pub(crate) format: FormatPrefs,
+    /// Appended to every message sent (empty = none).
+    pub(crate) signature: String,
+}
+
+impl Settings {
+    pub(crate) fn from_config(config: &Config) -> Self {
+        Self {
+            model: config.model.clone(),
+            max_budget: config.max_budget,
+            debounce_ms: config.debounce_ms,
+            format: FormatPrefs::default(),
+            signature: config.signature.clone(),
+        }
+    }
+
+    /// Command-line values with saved `/config set` changes applied on top.
+    /// Saved values that no longer parse are logged and skipped.
+    pub(crate) fn load(config: &Config, saved: &BTreeMap<String, String>) -> Self {
+        let mut settings = Self::from_config(config);
+        for (key, value) in saved {
+            if let Err(e) = settings.set(key, value) {
+                warn!("Ignoring saved setting {key}={value}: {e}");
+            }
+        }
+        settings
+    }
+
+    /// Text form of `key`, as shown by `/config show`.
+    pub(crate) fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "model" => Some(self.model.clone()),
+            "max_budget" => Some(format!("{:.2}", self.max_budget)),
+            "debounce_ms" => Some(self.debounce_ms.to_string()),
+            "format.tables" => Some(self.format.tables.as_str().to_string()),
+            "format.code" => Some(
+                if self.format.code_images {
+                    "image"
+                } else {
+                    "text"
+                }
+                .to_string(),
+            ),
+            "signature" if self.signature.is_empty() => Some("off".to_string()),
+            "signature" => Some(self.signature.clone()),
+            _ => None,
+        }
+    }
+
+    /// Set `key` from its text form. The error is shown to the user.
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "model" => {
+                if value.is_empty() || value.contains(char::is_whitespace) {
+                    return Err("Model must be a single name, e.g. sonnet.".to_string());
+                }
+                self.model = value.to_string();
+            }
+            "max_budget" => match value.trim_start_matches('$').parse::<f64>() {
+                Ok(budget) if budget.is_finite() && budget > 0.0 => self.max_budget = budget,
+                _ => return Err("Budget must be a positive amount in USD, e.g. 2.50.".to_string()),
+            },
+            "debounce_ms" => match value.parse::<u64>() {
+                Ok(ms) if ms <= MAX_DEBOUNCE_MS => self.debounce_ms = ms,
+                _ => {
+                    return Err(format!(
+                        "Debounce must be milliseconds between 0 and {MAX_DEBOUNCE_MS}."
+                    ))
+                }
+            },
+            "format.tables" => match TableFormat::parse(value) {
+                Some(tables) => self.format.tables = tables,
+                None => return Err("Tables must be auto, text, image or off.".to_string()),
+            },
+            "format.code" => match value {
+                "image" => self.format.code_images = true,
+                "text" => self.format.code_images = false,
+                _ => return Err("Code must be image or text.".to_string()),
+            },
+            "signature" => match value {
+                "off" | "" => self.signature.clear(),
+                _ if value.chars().count() > MAX_SIGNATURE_LEN => {
+                    return Err(format!(
+                        "Signature must be at most {MAX_SIGNATURE_LEN} characters."
+                    ))
+                }
+                _ => self.signature = value.to_string(),
+            },
+            _ => {
+                return Err(format!(
+                    "Unknown setting \"{key}\". Settings: {}.",
+                    KEYS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Put `key` back to its value in `defaults`.
+    pub(crate) fn reset(&mut self, key: &str, defaults: &Settings) {
+        match key {
+            "model" => self.model = defaults.model.clone(),
+            "max_budget" => self.max_budget = defaults.max_budget,
+            "debounce_ms" => self.debounce_ms = defaults.debounce_ms,
+            "format.tables" => self.format.tables = defaults.format.tables,
+            "format.code" => self.format.code_images = defaults.format.code_images,
+            "signature" => self.signature = defaults.signature.clone(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        Settings {
+            model: "opus".to_string(),
+            max_budget: 5.0,
+            debounce_ms: 3000,
+            format: FormatPrefs::default(),
+            signature: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_set_parses_and_validates() {
+        let mut s = settings();
+        s.set("model", "sonnet").unwrap();
+        s.set("max_budget", "$2.5").unwrap();
+        s.set("debounce_ms", "0").unwrap();
+        s.set("format.tables", "image").unwrap();
+        s.set("format.code", "text").unwrap();
+        s.set("signature", "— ccchat on homeserver").unwrap();
+        assert_eq!(s.get("model").as_deref(), Some("sonnet"));
+        assert_eq!(s.get("max_budget").as_deref(), Some("2.50"));
+        assert_eq!(s.get("debounce_ms").as_deref(), Some("0"));
+        assert_eq!(s.get("format.tables").as_deref(), Some("image"));
+        assert_eq!(s.get("format.code").as_deref(), Some("text"));
+        assert_eq!(
+            s.get("signature").as_deref(),
+            Some("— ccchat on homeserver")
+        );
+        s.set("signature", "off").unwrap();
+        assert_eq!(s.get("signature").as_deref(), Some("off"));
+        assert!(s.set("signature", &"x".repeat(201)).is_err());
+
+        assert!(s.set("max_budget", "-1").is_err());
+        assert!(s.set("max_budget", "NaN").is_err());
+        assert!(s.set("debounce_ms", "999999").is_err());
+        assert!(s.set("model", "two words").is_err());
+        assert!(s.set("format.tables", "huge").is_err());
+        assert!(s
+            .set("colour", "red")
+            .unwrap_err()
+            .contains("model, max_budget"));
+        assert_eq!(s.max_budget, 2.5);
+        assert!(s.get("colour").is_none());
+    }
+
+    #[test]
+    fn test_reset_restores_default() {
+        let defaults = settings();
+        let mut s = settings();
+        s.set("model", "haiku").unwrap();
+        s.set("format.tables", "off").unwrap();
+        s.reset("model", &defaults);
+        assert_eq!(s.model, "opus");
+        assert_eq!(s.format.tables, TableFormat::Off);
+        s.reset("format.tables", &defaults);
+        assert_eq!(s, defaults);
+    }
+}
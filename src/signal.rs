@@ -1,5 +1,7 @@
 use serde_json::Value;
 
+use crate::envelope::{Attachment, Envelope};
+
 pub(crate) struct ParsedEnvelope {
     pub(crate) source: String,
     pub(crate) message_text: String,
@@ -7,34 +9,31 @@ pub(crate) struct ParsedEnvelope {
     pub(crate) source_uuid: String,
     pub(crate) source_name: String,
     pub(crate) attachments: Vec<AttachmentInfo>,
+    /// Signal group id when the message was posted in a group chat.
+    pub(crate) group_id: Option<String>,
+    /// Conversation id to reply to instead of the sender (email threads).
+    pub(crate) thread_id: Option<String>,
+    /// Signal timestamp of the message (ms), for reacting to it.
+    pub(crate) sent_at: Option<i64>,
 }
 
 /// Parse a Signal envelope JSON into structured fields.
 /// Returns None if the envelope should be skipped (no source, no text/attachments, etc.)
 pub(crate) fn parse_envelope(envelope: &Value) -> Option<ParsedEnvelope> {
-    let source = envelope["envelope"]["sourceNumber"]
-        .as_str()
-        .or_else(|| envelope["envelope"]["source"].as_str());
-    let source = match source {
-        Some(s) if !s.is_empty() => s.to_string(),
-        _ => return None,
-    };
+    let env = Envelope::from_value(envelope)?;
+    let source = env.sender()?.to_string();
 
-    let has_data_attachments = envelope["envelope"]["dataMessage"]["attachments"]
-        .as_array()
-        .is_some_and(|a| !a.is_empty());
-    let has_sync_attachments = envelope["envelope"]["syncMessage"]["sentMessage"]["attachments"]
-        .as_array()
-        .is_some_and(|a| !a.is_empty());
+    let data = env.data_message.as_ref();
+    let sent = env.sent_message();
+    let has_data_attachments = data.is_some_and(|d| !d.attachments.is_empty());
+    let has_sync_attachments = sent.is_some_and(|s| !s.attachments.is_empty());
 
-    let (message_text, is_sync) = if let Some(m) =
-        envelope["envelope"]["dataMessage"]["message"].as_str()
-    {
+    let (message_text, is_sync) = if let Some(m) = data.and_then(|d| d.message.as_deref()) {
         if m.is_empty() && !has_data_attachments {
             return None;
         }
         (m.to_string(), false)
-    } else if let Some(m) = envelope["envelope"]["syncMessage"]["sentMessage"]["message"].as_str() {
+    } else if let Some(m) = sent.and_then(|s| s.message.as_deref()) {
         if m.is_empty() && !has_sync_attachments {
             return None;
         }
@@ -47,24 +46,60 @@ pub(crate) fn parse_envelope(envelope: &Value) -> Option<ParsedEnvelope> {
         return None;
     };
 
-    let source_uuid = envelope["envelope"]["sourceUuid"]
-        .as_str()
-        .unwrap_or_default()
-        .to_string();
-    let source_name = envelope["envelope"]["sourceName"]
-        .as_str()
-        .unwrap_or("unknown")
-        .to_string();
-
-    let attachments = extract_attachments(envelope);
+    let group_id = data
+        .and_then(|d| d.group_info.as_ref())
+        .or_else(|| sent.and_then(|s| s.group_info.as_ref()))
+        .map(|g| g.group_id.clone())
+        .filter(|g| !g.is_empty());
 
     Some(ParsedEnvelope {
         source,
         message_text,
         is_sync,
-        source_uuid,
-        source_name,
-        attachments,
+        source_uuid: env.source_uuid.clone().unwrap_or_default(),
+        source_name: env
+            .source_name
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string()),
+        attachments: attachments_of(&env),
+        group_id,
+        thread_id: None,
+        sent_at: data
+            .and_then(|d| d.timestamp)
+            .or_else(|| sent.and_then(|s| s.timestamp))
+            .or(env.timestamp),
+    })
+}
+
+/// A message its sender deleted for everyone.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Deletion {
+    pub(crate) source: String,
+    /// Deleted from another device of the account owner (Note to Self).
+    pub(crate) is_sync: bool,
+    pub(crate) group_id: Option<String>,
+    /// Signal timestamp of the deleted message (ms).
+    pub(crate) target: i64,
+}
+
+/// The deletion an envelope carries, if it's a remote delete.
+pub(crate) fn parse_remote_delete(envelope: &Value) -> Option<Deletion> {
+    let env = Envelope::from_value(envelope)?;
+    let source = env.sender()?.to_string();
+    let data = env.data_message.as_ref();
+    let sent = env.sent_message();
+    let (target, is_sync, group) = match (data, sent) {
+        (Some(d), _) if d.remote_delete.is_some() => {
+            (d.remote_delete.as_ref()?, false, d.group_info.as_ref())
+        }
+        (_, Some(s)) => (s.remote_delete.as_ref()?, true, s.group_info.as_ref()),
+        _ => return None,
+    };
+    Some(Deletion {
+        source,
+        is_sync,
+        group_id: group.map(|g| g.group_id.clone()).filter(|g| !g.is_empty()),
+        target: target.timestamp,
     })
 }
 
@@ -93,34 +128,37 @@ pub(crate) struct AttachmentInfo {
     pub(crate) id: String,
     pub(crate) content_type: String,
     pub(crate) filename: Option<String>,
+    /// Size in bytes, if the messenger says
+    pub(crate) size: Option<u64>,
     pub(crate) voice_note: bool,
 }
 
-pub(crate) fn extract_attachments(envelope: &Value) -> Vec<AttachmentInfo> {
-    let arrays = [
-        &envelope["envelope"]["dataMessage"]["attachments"],
-        &envelope["envelope"]["syncMessage"]["sentMessage"]["attachments"],
-    ];
-    for arr in arrays {
-        if let Some(list) = arr.as_array() {
-            return list
-                .iter()
-                .filter_map(|a| {
-                    let id = a["id"].as_str()?.to_string();
-                    let content_type = a["contentType"].as_str()?.to_string();
-                    let filename = a["filename"].as_str().map(|s| s.to_string());
-                    let voice_note = a["voiceNote"].as_bool().unwrap_or(false);
-                    Some(AttachmentInfo {
-                        id,
-                        content_type,
-                        filename,
-                        voice_note,
-                    })
-                })
-                .collect();
+impl From<&Attachment> for AttachmentInfo {
+    fn from(a: &Attachment) -> Self {
+        Self {
+            id: a.id.clone(),
+            content_type: a.content_type.clone(),
+            filename: a.filename.clone(),
+            size: a.size,
+            voice_note: a.voice_note,
         }
     }
-    Vec::new()
+}
+
+fn attachments_of(env: &Envelope) -> Vec<AttachmentInfo> {
+    let data = env.data_message.as_ref().map(|d| &d.attachments);
+    let sent = env.sent_message().map(|s| &s.attachments);
+    data.filter(|a| !a.is_empty())
+        .or(sent)
+        .map(|list| list.iter().map(AttachmentInfo::from).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+pub(crate) fn extract_attachments(envelope: &Value) -> Vec<AttachmentInfo> {
+    Envelope::from_value(envelope)
+        .map(|env| attachments_of(&env))
+        .unwrap_or_default()
 }
 
 #[cfg(test)]
@@ -311,6 +349,7 @@ mod tests {
                 "sourceUuid": "uuid-1",
                 "sourceName": "Alice",
                 "dataMessage": {
+                    "timestamp": 1700000000123_i64,
                     "message": "Hello world"
                 }
             }
@@ -320,6 +359,7 @@ mod tests {
         assert_eq!(parsed.message_text, "Hello world");
         assert!(!parsed.is_sync);
         assert_eq!(parsed.source_name, "Alice");
+        assert_eq!(parsed.sent_at, Some(1700000000123));
     }
 
     #[test]
@@ -419,4 +459,64 @@ mod tests {
         assert_eq!(parsed.attachments.len(), 1);
         assert_eq!(parsed.attachments[0].content_type, "application/pdf");
     }
+
+    #[test]
+    fn test_parse_envelope_group_message() {
+        let env: Value = serde_json::json!({
+            "envelope": {
+                "sourceNumber": "+1111111111",
+                "dataMessage": {
+                    "message": "Hi team",
+                    "groupInfo": {"groupId": "abc123==", "type": "DELIVER"}
+                }
+            }
+        });
+        let parsed = parse_envelope(&env).unwrap();
+        assert_eq!(parsed.group_id.as_deref(), Some("abc123=="));
+    }
+
+    #[test]
+    fn test_parse_envelope_direct_message_has_no_group() {
+        let env: Value = serde_json::json!({
+            "envelope": {
+                "sourceNumber": "+1111111111",
+                "dataMessage": {"message": "Hi"}
+            }
+        });
+        assert!(parse_envelope(&env).unwrap().group_id.is_none());
+    }
+
+    #[test]
+    fn test_parse_envelope_fixtures() {
+        let load = |name: &str| -> Value {
+            let path = format!(
+                "{}/tests/fixtures/envelopes/{name}.json",
+                env!("CARGO_MANIFEST_DIR")
+            );
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+        };
+        let parsed = parse_envelope(&load("attachments")).unwrap();
+        assert_eq!(parsed.attachments.len(), 2);
+        assert!(parsed.attachments[1].voice_note);
+        let parsed = parse_envelope(&load("sync_message")).unwrap();
+        assert!(parsed.is_sync);
+        assert_eq!(parsed.message_text, "/status");
+        // Reactions, receipts and typing indicators carry no text to route.
+        assert!(parse_envelope(&load("reaction")).is_none());
+        assert!(parse_envelope(&load("receipt")).is_none());
+        assert!(parse_envelope(&load("typing")).is_none());
+        assert!(parse_envelope(&load("remote_delete")).is_none());
+
+        assert_eq!(
+            parse_remote_delete(&load("remote_delete")),
+            Some(Deletion {
+                source: "+15551234567".to_string(),
+                is_sync: false,
+                group_id: None,
+                target: 1700000000000,
+            })
+        );
+        assert!(parse_remote_delete(&load("data_message")).is_none());
+        assert!(parse_remote_delete(&load("sync_message")).is_none());
+    }
 }
@@ -0,0 +1,136 @@
+//! Ways to pick up where a conversation left off.
+//!
+//! When `/reset` or expiry ends a session and its summary has been stored,
+//! Claude suggests two or three follow-ups from that summary. They're sent
+//! as numbered choices, with "Start fresh" last, so resuming from a phone is
+//! one digit: "You were debugging the flaky CI test. Continue? 1. ...".
+
+use tracing::{debug, warn};
+
+use crate::constants::{STARTERS_MAX, SUMMARIZE_BUDGET};
+use crate::state::State;
+
+/// Option that just confirms the next message starts over.
+const START_FRESH: &str = "Start fresh";
+
+fn prompt(summary: &str) -> String {
+    format!(
+        "This is a summary of a chat that just ended:\n\n{summary}\n\n\
+         Write a one-line question reminding the user what they were doing and asking \
+         whether to continue, e.g. \"You were debugging the flaky CI test. Continue?\". \
+         Then, one per line, two or three short messages (under 80 characters) the user \
+         could send to pick it up again, written as the user. Reply with those lines only."
+    )
+}
+
+/// The question and suggestions in Claude's reply, or None if it doesn't
+/// look like one.
+fn parse(reply: &str) -> Option<(String, Vec<String>)> {
+    let mut lines = reply.lines().map(str::trim).filter(|l| !l.is_empty());
+    let question = lines.next()?.to_string();
+    let suggestions: Vec<String> = lines
+        .map(|line| {
+            line.trim_start_matches(|c: char| c.is_ascii_digit() || "-*•.) ".contains(c))
+                .trim_matches('"')
+                .to_string()
+        })
+        .filter(|line| !line.is_empty())
+        .take(STARTERS_MAX)
+        .collect();
+    (!suggestions.is_empty()).then_some((question, suggestions))
+}
+
+/// The latest stored summary for `sender`.
+fn latest_summary(sender: &str) -> Option<String> {
+    let conn = crate::memory::open_memory_db(sender).ok()?;
+    crate::memory::messages::get_recent_summaries(&conn, 1)
+        .into_iter()
+        .next()
+        .map(|(summary, _)| summary)
+}
+
+/// Ask Claude for follow-ups to `summary`.
+async fn suggest(state: &State, sender: &str, summary: &str) -> Option<(String, Vec<String>)> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let slot = state.acquire_run_slot(sender).await.ok()?;
+    let result = state
+        .claude_runner
+        .run_claude(
+            &prompt(summary),
+            &session_id,
+            &state.settings().model,
+            &[],
+            &crate::helpers::session_workdir(sender, &session_id),
+            SUMMARIZE_BUDGET,
+            crate::NO_MEMORY_PROMPT,
+            &state.run_options(sender),
+        )
+        .await;
+    drop(slot);
+    crate::commands::remove_session_workdir(sender, &session_id);
+    match result {
+        Ok(reply) => {
+            if let Some(c) = reply.cost_usd {
+                state.add_cost(c);
+                state.add_sender_cost(sender, c);
+            }
+            parse(&reply.text)
+        }
+        Err(e) => {
+            warn!(sender = %sender, "Conversation starters failed: {e}");
+            None
+        }
+    }
+}
+
+/// Send `sender` follow-ups to their last stored summary as numbered
+/// choices. Does nothing when there's no summary or no usable suggestion.
+pub(crate) async fn offer(state: &State, sender: &str) {
+    let Some(summary) = latest_summary(sender) else {
+        return;
+    };
+    let Some((question, mut options)) = suggest(state, sender, &summary).await else {
+        return;
+    };
+    debug!(sender = %sender, count = options.len(), "Offering conversation starters");
+    let mut lines = vec![question];
+    lines.extend(
+        options
+            .iter()
+            .map(String::as_str)
+            .chain([START_FRESH])
+            .enumerate()
+            .map(|(i, option)| format!("{}. {option}", i + 1)),
+    );
+    // The session is already gone; /reset just says the next message starts over.
+    options.push("/reset".to_string());
+    crate::choices::offer(state, sender, options);
+    let _ = state.send_message(sender, &lines.join("\n")).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_question_and_suggestions() {
+        let (question, suggestions) = parse(
+            "You were debugging the flaky CI test. Continue?\n\n\
+             1. Let's keep going on the CI flake\n\
+             - \"Show me the last failing run\"\n\
+             3) Try the retry fix\n4. One too many",
+        )
+        .unwrap();
+        assert_eq!(question, "You were debugging the flaky CI test. Continue?");
+        assert_eq!(
+            suggestions,
+            vec![
+                "Let's keep going on the CI flake",
+                "Show me the last failing run",
+                "Try the retry fix"
+            ]
+        );
+        assert!(parse("Just a question?").is_none());
+        assert!(parse("").is_none());
+    }
+}
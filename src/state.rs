@@ -1,27 +1,35 @@
 use dashmap::DashMap;
 use reqwest::Client;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify, Semaphore, SemaphorePermit};
 
 use crate::error::AppError;
-use crate::helpers::{hash_message, split_message};
+use crate::helpers::hash_message;
+pub(crate) use crate::metrics::Metrics;
+use crate::preferences::Typing;
+use crate::ratelimit::RateClass;
 use crate::signal::AttachmentInfo;
+use crate::stickers::StickerStatus;
 use crate::traits::{ClaudeRunner, SignalApi};
 
-pub(crate) struct PendingSender {
-    pub(crate) name: String,
-    pub(crate) short_id: u64,
+/// Keycap emoji for `n` parts delivered (📨 past ten).
+fn parts_delivered_emoji(n: usize) -> &'static str {
+    const KEYCAPS: [&str; 10] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣", "9️⃣", "🔟"];
+    KEYCAPS.get(n.wrapping_sub(1)).copied().unwrap_or("📨")
 }
 
 pub(crate) struct SenderState {
     pub(crate) session_id: String,
     pub(crate) model: String,
     pub(crate) lock: Arc<Mutex<()>>,
+    /// When the session began, for its age in `/status`.
+    pub(crate) started: Instant,
     pub(crate) last_activity: Instant,
     pub(crate) message_count: u64,
+    pub(crate) prefs: crate::preferences::Preferences,
 }
 
 pub(crate) struct TokenBucket {
@@ -41,6 +49,21 @@ impl TokenBucket {
         }
     }
 
+    /// Rebuild a saved bucket, refilling for the time since it was saved.
+    pub(crate) fn restore(
+        capacity: f64,
+        rate_per_sec: f64,
+        tokens: f64,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            tokens: (tokens + elapsed.as_secs_f64() * rate_per_sec).min(capacity),
+            last_refill: Instant::now(),
+            capacity,
+            rate_per_sec,
+        }
+    }
+
     pub(crate) fn try_consume(&mut self) -> bool {
         let now = Instant::now();
         let elapsed = now.duration_since(self.last_refill).as_secs_f64();
@@ -55,58 +78,261 @@ impl TokenBucket {
     }
 }
 
-/// Immutable configuration set at startup from CLI args.
+/// Immutable configuration set at startup from CLI args, shared as
+/// `Arc<Config>` so tasks that only read settings needn't hold the whole
+/// [`State`].
+#[derive(Clone)]
 pub(crate) struct Config {
     pub(crate) model: String,
     pub(crate) max_budget: f64,
     pub(crate) rate_limit_config: Option<(f64, f64)>,
+    /// Global (capacity, refill/sec) for slash commands (None = unlimited)
+    pub(crate) command_rate_limit: Option<(f64, f64)>,
     pub(crate) session_ttl: Option<Duration>,
     pub(crate) debounce_ms: u64,
     pub(crate) account: String,
-    pub(crate) api_url: String,
+    /// Bridge URL(s); Signal fails over between them.
+    pub(crate) api: Arc<crate::failover::ApiEndpoints>,
     pub(crate) config_path: Option<String>,
     pub(crate) system_prompt: Option<String>,
-    pub(crate) webhook_url: Option<String>,
+    /// Outgoing webhooks (`--webhook-url` and the config file's `webhooks`).
+    pub(crate) webhooks: crate::webhook::Webhooks,
     /// Lakera Guard API key for prompt injection detection (None = guard disabled)
     pub(crate) lakera_api_key: Option<String>,
+    /// Disk quota per session working directory in bytes (None = unlimited)
+    pub(crate) workdir_quota_bytes: Option<u64>,
+    /// Message the admin on startup and shutdown
+    pub(crate) notify_lifecycle: bool,
+    /// How often the profile's about text is refreshed with the bridge's
+    /// status (`--profile-status-mins`; `None` = never)
+    pub(crate) profile_status: Option<Duration>,
+    /// Profile name sent along with it (`--profile-name`; empty = unchanged)
+    pub(crate) profile_name: String,
+    /// `/screenshot` enabled (`--allow-screenshot`, screenshot builds only)
+    pub(crate) allow_screenshot: bool,
+    /// Prefix that sends a message to Claude as a slash command ("" = off)
+    pub(crate) claude_command_prefix: String,
+    /// Message that sends a debounced burst straight away ("" = off)
+    pub(crate) flush_keyword: String,
+    /// Forward slash commands ccchat doesn't know to Claude as-is
+    pub(crate) slash_passthrough: bool,
+    /// Proxy and CA settings shared by every outbound connection
+    pub(crate) net: crate::net::NetConfig,
+    /// Which messenger the bridge talks to
+    pub(crate) transport: crate::transport::Transport,
+    /// Speech engine for `/voice` replies
+    pub(crate) tts: crate::tts::TtsConfig,
+    /// Link fetching for prompts (`--fetch-urls`)
+    pub(crate) fetch: crate::fetch::FetchConfig,
+    /// GitHub access for `/gh`
+    pub(crate) github: crate::github::GitHubConfig,
+    /// Home Assistant access for `/ha` and Claude's `[[ha ...]]` actions
+    pub(crate) ha: crate::homeassistant::HaConfig,
+    /// Stickers sent with done, error, budget and rate-limit replies
+    pub(crate) stickers: crate::stickers::StickerConfig,
+    /// Size caps, executable refusal and virus scanning for attachments
+    pub(crate) screening: crate::screening::ScreeningConfig,
+    /// Run claude with stream-json and report token/cost totals while it works
+    pub(crate) stream_progress: bool,
+    /// OCR image attachments (`--ocr`)
+    pub(crate) ocr: bool,
+    /// A run with no progress for this long is cancelled, and a message
+    /// waiting this long for the session lock gives up.
+    pub(crate) stuck_after: Duration,
+    /// Estimated input cost above which a run waits for `/confirm` (0 = off)
+    pub(crate) confirm_above_usd: f64,
+    /// Commands that wait for a "yes" before acting (`--confirm-commands`)
+    pub(crate) confirm_commands: Vec<String>,
+    /// Local time housekeeping runs each day (`--maintenance-at`; `None` = off)
+    pub(crate) maintenance_at: Option<chrono::NaiveTime>,
+    /// Housekeeping tasks run at that time (`--maintenance-tasks`)
+    pub(crate) maintenance_tasks: Vec<crate::maintenance::Task>,
+    /// Trust a recipient's new safety number and resend (`--trust-new-identities`)
+    pub(crate) trust_new_identities: bool,
+    /// Read receipts for accepted messages, 🚫 for refused ones (`--send-receipts`)
+    pub(crate) send_receipts: bool,
+    /// Tell unknown senders their request went to the owner (`--unknown-sender-reply`)
+    pub(crate) unknown_sender_reply: bool,
+    /// Line appended to every message sent (`--signature`; empty = none)
+    pub(crate) signature: String,
+    /// Re-run interrupted prompts at startup instead of offering /retry
+    /// (`--rerun-interrupted`)
+    pub(crate) rerun_interrupted: bool,
+    /// How long files people send are kept, and in how much space
+    pub(crate) attachment_retention: crate::attachments::Retention,
+    /// Alert the admin when p95 reply time goes over this (`--reply-slo-ms`)
+    pub(crate) reply_slo_ms: Option<u64>,
+    /// How often to check each model answers (`--probe-mins`; None = never)
+    pub(crate) probe_every: Option<Duration>,
+    /// Model to use while the chosen one is down (`--fallback-model`)
+    pub(crate) fallback_model: Option<String>,
+    /// Periodic encrypted uploads to object storage (`--archive-bucket`)
+    pub(crate) archive: Option<crate::archive::ArchiveConfig>,
+    /// Nightly local snapshots of the data directory (`--backup-dir`)
+    pub(crate) backup: crate::backup::BackupConfig,
 }
 
-/// Runtime metrics (atomic counters).
-pub(crate) struct Metrics {
-    pub(crate) start_time: Instant,
-    pub(crate) message_count: AtomicU64,
-    pub(crate) total_cost: AtomicU64, // stored as microdollars
-    pub(crate) error_count: AtomicU64,
-    pub(crate) latency_sum_ms: AtomicU64,
-    pub(crate) latency_count: AtomicU64,
+tokio::task_local! {
+    /// When the message being handled by this task was received.
+    pub(crate) static RECEIVED_AT: Instant;
 }
 
 /// Per-sender session tracking.
 pub(crate) struct SessionManager {
     pub(crate) sessions: DashMap<String, SenderState>,
     pub(crate) truncated_sessions: DashMap<String, String>,
+    /// Sessions whose last run stopped at the budget, for `/continue`.
+    pub(crate) budget_hits: DashMap<String, String>,
 }
 
 /// Debounce state for merging burst messages.
+/// A debounced message and its Signal timestamp.
+pub(crate) type Buffered = (String, Option<i64>);
+
 pub(crate) struct DebounceState {
-    pub(crate) buffers: DashMap<String, (Vec<String>, Instant)>,
+    /// Buffered messages, and when the last one arrived.
+    pub(crate) buffers: DashMap<String, (Vec<Buffered>, Instant)>,
     pub(crate) active: DashMap<String, ()>,
+    /// Wakes a waiting burst so it's sent before its window is up.
+    pub(crate) wake: DashMap<String, Arc<tokio::sync::Notify>>,
+}
+
+/// Global cap on concurrent `claude` runs (`--max-concurrent-runs`). Each
+/// sender is already limited to one run at a time by their session lock.
+pub(crate) struct RunSlots {
+    semaphore: Semaphore,
+    /// 0 = unlimited
+    pub(crate) limit: usize,
+    /// Runs currently waiting for a slot.
+    pub(crate) waiting: AtomicUsize,
+}
+
+impl RunSlots {
+    pub(crate) fn new(limit: usize) -> Self {
+        let permits = if limit == 0 {
+            Semaphore::MAX_PERMITS
+        } else {
+            limit
+        };
+        Self {
+            semaphore: Semaphore::new(permits),
+            limit,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Runs in progress right now.
+    pub(crate) fn busy(&self) -> usize {
+        if self.limit == 0 {
+            Semaphore::MAX_PERMITS - self.semaphore.available_permits()
+        } else {
+            self.limit - self.semaphore.available_permits()
+        }
+    }
+
+    pub(crate) fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.semaphore.try_acquire().ok()
+    }
+
+    /// Wait for a slot, counting this run as queued until one frees up.
+    pub(crate) async fn acquire(&self) -> Result<SemaphorePermit<'_>, AppError> {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = self.semaphore.acquire().await;
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        permit.map_err(|e| AppError::Other(format!("Run slots closed: {e}")))
+    }
 }
 
 pub(crate) struct State {
-    pub(crate) config: Config,
+    pub(crate) config: Arc<Config>,
+    /// Runtime-tunable settings (`/config set`), seeded from `config`.
+    pub(crate) settings: RwLock<crate::settings::Settings>,
     pub(crate) metrics: Metrics,
     pub(crate) session_mgr: SessionManager,
     pub(crate) debounce: DebounceState,
-    pub(crate) allowed_ids: DashMap<String, ()>,
-    pub(crate) pending_senders: DashMap<String, PendingSender>,
-    pub(crate) pending_counter: AtomicU64,
-    pub(crate) sent_hashes: Arc<DashMap<u64, ()>>,
-    pub(crate) rate_limits: DashMap<String, TokenBucket>,
+    pub(crate) run_slots: RunSlots,
+    /// Allowed and pending senders, invite codes and guests.
+    pub(crate) access: crate::access::Access,
+    /// Hashes of recently sent messages and when they went, to spot their echo.
+    pub(crate) sent_hashes: Arc<DashMap<u64, Instant>>,
+    /// Chat id -> (author, Signal timestamp) of the latest incoming message,
+    /// which multi-part replies react to as their parts are delivered.
+    pub(crate) last_inbound: DashMap<String, (String, i64)>,
+    pub(crate) rate_limits: DashMap<(String, RateClass), TokenBucket>,
+    /// Per-sender rate overrides from the config file.
+    pub(crate) sender_rate_limits: DashMap<(String, RateClass), (f64, f64)>,
+    /// Load/memory/disk thresholds from the config file.
+    pub(crate) resource_limits: RwLock<crate::resources::ResourceLimits>,
+    /// Model aliases and routing rules from the config file.
+    pub(crate) model_router: RwLock<crate::router::Router>,
     pub(crate) sender_costs: DashMap<String, AtomicU64>,
+    /// Tokens each sender's replies used, and what prompt caching saved (USD).
+    pub(crate) token_usage: DashMap<String, (crate::streaming::Usage, f64)>,
+    /// Approximate context size (tokens) of each sender's current session.
+    pub(crate) session_tokens: DashMap<String, u64>,
+    /// Summary to seed the next prompt with after a compaction.
+    pub(crate) compacted_context: DashMap<String, String>,
     pub(crate) sender_prompts: DashMap<String, String>,
     pub(crate) pending_recalls: DashMap<String, String>,
+    /// Session ended by `/reset` that `/carry` can still summarize: (session id, model, reset at).
+    pub(crate) pending_carries: DashMap<String, (String, String, Instant)>,
+    /// Expensive runs waiting for `/confirm`, per sender.
+    pub(crate) pending_confirms: DashMap<String, crate::estimate::PendingRun>,
+    /// Group chat id (`group.<id>`) -> bound project directory.
+    pub(crate) group_projects: DashMap<String, PathBuf>,
+    /// Group chat id -> its `/group set` settings.
+    pub(crate) group_settings: DashMap<String, crate::group_settings::GroupSettings>,
+    /// Files pinned to each chat's session with `/context add`.
+    pub(crate) pinned_files: crate::context::files::PinnedFiles,
+    /// Which models answered their last probe or run.
+    pub(crate) model_health: crate::probe::ModelHealth,
+    /// Sender -> custom agent picked with `/agent` for the current session.
+    pub(crate) agents: DashMap<String, String>,
+    /// Chat id -> signal that stops its running `claude` call (`/stop`).
+    pub(crate) active_runs: DashMap<String, Arc<Notify>>,
+    /// Chat id -> last sign of life from its running `claude` call.
+    pub(crate) run_progress: DashMap<String, Instant>,
+    /// Messages waiting for an inbound worker.
+    pub(crate) inbox: crate::inbox::Inbox<crate::inbox::Inbound>,
+    /// Numbered options a bare "1", "2", ... reply picks from, per sender.
+    pub(crate) pending_choices: DashMap<String, crate::choices::PendingChoice>,
+    /// Each sender's last Claude reply, to diff a revision against.
+    pub(crate) last_replies: DashMap<String, String>,
+    /// Destructive commands waiting for their sender's "yes", per sender.
+    pub(crate) pending_commands: DashMap<String, crate::confirmation::PendingCommand>,
+    /// How each housekeeping task went the last time it ran.
+    pub(crate) maintenance: DashMap<crate::maintenance::Task, crate::maintenance::TaskRun>,
+    /// Numbers Signal says aren't registered -> when sending to them stopped.
+    pub(crate) suspended_recipients: DashMap<String, i64>,
+    /// Numbers whose safety number changed -> the latest change.
+    pub(crate) identity_changes: DashMap<String, crate::recipients::IdentityChange>,
+    /// Group chat -> speakers whose turns are waiting or being answered, in order.
+    pub(crate) raised_hands: DashMap<String, std::collections::VecDeque<String>>,
+    /// Chat id -> Signal timestamp of the message last given the
+    /// `typing react` reaction, so it's only sent once.
+    pub(crate) working_reactions: DashMap<String, i64>,
+    /// (sender, name) -> side sessions addressed with `@name:`.
+    pub(crate) named_sessions: DashMap<(String, String), crate::named_sessions::NamedSession>,
+    pub(crate) redactor: crate::redact::Redactor,
+    /// Senders who turned secret redaction off with `/redaction off`.
+    pub(crate) redaction_off: DashMap<String, ()>,
+    /// Chats whose current session is private (`/private on`).
+    pub(crate) private_sessions: DashMap<String, ()>,
+    /// Per-sender `/voice` setting (absent = off).
+    pub(crate) voice_modes: DashMap<String, crate::tts::VoiceMode>,
+    /// Admin log tail switch shared with the tracing layer (`/tail on`).
+    pub(crate) log_tail: Arc<crate::logtail::TailHandle>,
+    /// Set after the `--notify-lifecycle` online notice has been sent.
+    pub(crate) online_announced: std::sync::atomic::AtomicBool,
     pub(crate) runtime_system_prompt: RwLock<Option<String>>,
+    /// Latest `/gh` digest, refreshed by the GitHub poll loop.
+    pub(crate) github_digest: RwLock<Option<crate::github::Digest>>,
+    /// Sources of extra prompt context (calendar, ...).
+    pub(crate) context_providers: Vec<Box<dyn crate::context::ContextProvider>>,
+    /// Anthropic credentials shared with the Claude runner (`/key status`).
+    pub(crate) claude_keys: Arc<crate::keys::KeyPool>,
+    /// Tool policies of bound projects, shared with the Claude runner.
+    pub(crate) tool_policies: Arc<crate::policy::ToolPolicies>,
     pub(crate) http: Client,
     pub(crate) signal_api: Box<dyn SignalApi>,
     pub(crate) claude_runner: Box<dyn ClaudeRunner>,
@@ -114,16 +340,157 @@ pub(crate) struct State {
 
 impl State {
     pub(crate) fn is_allowed(&self, sender: &str) -> bool {
-        !sender.is_empty() && self.allowed_ids.contains_key(sender)
+        self.access.is_allowed(sender)
+    }
+
+    pub(crate) fn is_admin(&self, sender: &str) -> bool {
+        crate::helpers::thread_owner(sender) == self.config.account
+    }
+
+    /// Current `/config` settings.
+    pub(crate) fn settings(&self) -> crate::settings::Settings {
+        match self.settings.read() {
+            Ok(settings) => settings.clone(),
+            Err(_) => crate::settings::Settings::from_config(&self.config),
+        }
+    }
+
+    pub(crate) fn format_prefs(&self, sender: &str) -> crate::format::FormatPrefs {
+        self.prefs(sender)
+            .format
+            .unwrap_or_else(|| self.settings().format)
+    }
+
+    /// The sender's preferences: their session's copy, or the saved ones.
+    pub(crate) fn prefs(&self, sender: &str) -> crate::preferences::Preferences {
+        match self.session_mgr.sessions.get(sender) {
+            Some(session) => session.prefs.clone(),
+            None => crate::preferences::load(sender),
+        }
+    }
+
+    /// Change the sender's preferences, save them and update their session.
+    pub(crate) fn update_prefs(
+        &self,
+        sender: &str,
+        change: impl FnOnce(&mut crate::preferences::Preferences),
+    ) -> crate::preferences::Preferences {
+        let mut prefs = self.prefs(sender);
+        change(&mut prefs);
+        crate::preferences::save(sender, &prefs);
+        if let Some(mut session) = self.session_mgr.sessions.get_mut(sender) {
+            session.prefs = prefs.clone();
+        }
+        prefs
+    }
+
+    /// `chat`'s `/group set` settings (all default outside groups).
+    pub(crate) fn group_settings(&self, chat: &str) -> crate::group_settings::GroupSettings {
+        self.group_settings
+            .get(chat)
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Most a Claude run for `sender` may spend: `max_budget`, or the
+    /// group's share of it.
+    pub(crate) fn run_budget(&self, sender: &str) -> f64 {
+        let max_budget = self.settings().max_budget;
+        let budget = match self.group_settings(sender).budget_share {
+            Some(share) => max_budget * f64::from(share) / 100.0,
+            None => max_budget,
+        };
+        if self.is_guest(sender) {
+            budget.min(crate::constants::GUEST_MAX_BUDGET_USD)
+        } else {
+            budget
+        }
+    }
+
+    /// See [`Access::is_guest`](crate::access::Access::is_guest).
+    pub(crate) fn is_guest(&self, sender: &str) -> bool {
+        self.access.is_guest(sender)
+    }
+
+    /// Settings for `sender`'s Claude runs: their preferences, limited to
+    /// answers for guests.
+    pub(crate) fn run_options(&self, sender: &str) -> crate::traits::RunOptions {
+        crate::traits::RunOptions {
+            ask_only: self.is_guest(sender),
+            ..self.prefs(sender).run_options()
+        }
+    }
+
+    /// Whether scheduled messages to `sender` should wait (`/prefs set quiet-hours`).
+    pub(crate) fn in_quiet_hours(&self, sender: &str) -> bool {
+        self.prefs(sender)
+            .quiet_hours
+            .is_some_and(|quiet| quiet.contains_now())
+    }
+
+    pub(crate) fn voice_mode(&self, sender: &str) -> crate::tts::VoiceMode {
+        self.voice_modes.get(sender).map(|m| *m).unwrap_or_default()
+    }
+
+    /// Take a `claude` run slot, telling the sender where they are in line
+    /// when every slot is busy.
+    pub(crate) async fn acquire_run_slot(
+        &self,
+        sender: &str,
+    ) -> Result<SemaphorePermit<'_>, AppError> {
+        if let Some(permit) = self.run_slots.try_acquire() {
+            return Ok(permit);
+        }
+        let position = self.run_slots.waiting.load(Ordering::Relaxed) + 1;
+        let _ = self
+            .send_message(
+                sender,
+                &format!(
+                    "All {} Claude slots are busy. You're #{position} in line.",
+                    self.run_slots.limit
+                ),
+            )
+            .await;
+        self.run_slots.acquire().await
+    }
+
+    /// Effective (capacity, refill/sec) for a sender and class: the per-sender
+    /// override if configured, otherwise the global setting.
+    pub(crate) fn rate_limit_for(&self, sender: &str, class: RateClass) -> Option<(f64, f64)> {
+        if let Some(limit) = self.sender_rate_limits.get(&(sender.to_string(), class)) {
+            return Some(*limit);
+        }
+        match class {
+            RateClass::Claude => self.config.rate_limit_config,
+            RateClass::Command => self.config.command_rate_limit,
+        }
+    }
+
+    /// Working directory for a Claude run: the bound project for a group room,
+    /// otherwise the isolated per-session workdir.
+    pub(crate) fn workdir_for(&self, chat_id: &str, session_id: &str) -> PathBuf {
+        match self.group_projects.get(chat_id) {
+            Some(dir) => dir.clone(),
+            None => crate::helpers::session_workdir(chat_id, session_id),
+        }
     }
 
     pub(crate) fn add_cost(&self, cost: f64) {
         let micros = (cost * 1_000_000.0) as u64;
-        self.metrics.total_cost.fetch_add(micros, Ordering::Relaxed);
+        self.metrics.total_cost.add(micros);
+        self.metrics.costed_runs.incr();
+    }
+
+    pub(crate) fn avg_cost_usd(&self) -> f64 {
+        let runs = self.metrics.costed_runs.get();
+        if runs == 0 {
+            return 0.0;
+        }
+        self.total_cost_usd() / runs as f64
     }
 
     pub(crate) fn total_cost_usd(&self) -> f64 {
-        self.metrics.total_cost.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        self.metrics.total_cost.get() as f64 / 1_000_000.0
     }
 
     pub(crate) fn add_sender_cost(&self, sender: &str, cost: f64) {
@@ -134,6 +501,26 @@ impl State {
             .fetch_add(micros, Ordering::Relaxed);
     }
 
+    /// Add a reply's token usage to the sender's totals; returns what prompt
+    /// caching saved on it.
+    pub(crate) fn record_usage(
+        &self,
+        sender: &str,
+        usage: crate::streaming::Usage,
+        model: &str,
+    ) -> f64 {
+        let saved = usage.cache_savings_usd(model);
+        let mut totals = self.token_usage.entry(sender.to_string()).or_default();
+        totals.0.add(usage);
+        totals.1 += saved;
+        saved
+    }
+
+    /// What prompt caching has saved across all senders.
+    pub(crate) fn cache_savings_usd(&self) -> f64 {
+        self.token_usage.iter().map(|e| e.value().1).sum()
+    }
+
     pub(crate) fn sender_cost_usd(&self, sender: &str) -> f64 {
         self.sender_costs
             .get(sender)
@@ -142,38 +529,89 @@ impl State {
     }
 
     pub(crate) fn record_latency(&self, duration_ms: u64) {
-        self.metrics
-            .latency_sum_ms
-            .fetch_add(duration_ms, Ordering::Relaxed);
-        self.metrics.latency_count.fetch_add(1, Ordering::Relaxed);
+        self.metrics.record_latency(duration_ms);
+    }
+
+    /// Record the end-to-end time of a reply just delivered, if the task
+    /// handling it knows when its message came in.
+    pub(crate) fn record_reply_latency(&self) {
+        if let Ok(received) = RECEIVED_AT.try_with(|at| *at) {
+            self.metrics
+                .record_reply(received.elapsed().as_millis() as u64);
+        }
     }
 
     /// Get the system prompt for a sender. Priority: per-sender > runtime global > config global > default.
-    /// Always appends the NO_MEMORY_PROMPT safety directive.
+    /// Always appends the NO_MEMORY_PROMPT safety directive, then the group,
+    /// Home Assistant, agent, preference and remembered-fact sections that apply.
     pub(crate) fn get_system_prompt(&self, sender: &str) -> String {
         let base = if let Some(per_sender) = self.sender_prompts.get(sender) {
-            per_sender.clone()
+            Some(per_sender.clone())
         } else if let Some(runtime) = self
             .runtime_system_prompt
             .read()
             .ok()
             .and_then(|g| g.clone())
         {
-            runtime
-        } else if let Some(ref global) = self.config.system_prompt {
-            global.clone()
+            Some(runtime)
         } else {
-            return crate::NO_MEMORY_PROMPT.to_string();
+            self.config.system_prompt.clone()
+        };
+        let mut prompt = match base {
+            Some(base) => format!("{base}\n\n{}", crate::NO_MEMORY_PROMPT),
+            None => crate::NO_MEMORY_PROMPT.to_string(),
         };
-        format!("{base}\n\n{}", crate::NO_MEMORY_PROMPT)
+        // Parts that change least come first: the prompt is cached as a
+        // prefix, so editing a preference or a fact only re-sends what
+        // follows it.
+        if crate::helpers::is_group_chat(sender) {
+            prompt.push_str("\n\n");
+            prompt.push_str(crate::turns::GROUP_PROMPT);
+            if let Some(max) = self.group_settings(sender).max_reply_chars {
+                prompt.push_str(&format!(
+                    "\n\nKeep each reply under {max} characters; longer ones are cut off."
+                ));
+            }
+        }
+        if self.is_admin(sender) {
+            if let Some(tool) = crate::homeassistant::tool_prompt(&self.config.ha) {
+                prompt.push_str("\n\n");
+                prompt.push_str(&tool);
+            }
+        }
+        if let Some(agent) = self.agent_for(sender) {
+            prompt.push_str("\n\n");
+            prompt.push_str(&agent.system_prompt());
+        }
+        if let Some(hints) = self.prefs(sender).prompt_hints() {
+            prompt.push_str("\n\n");
+            prompt.push_str(&hints);
+        }
+        if let Some(facts) = crate::memory::facts_prompt(sender) {
+            prompt.push_str("\n\n");
+            prompt.push_str(&facts);
+        }
+        prompt
+    }
+
+    /// Directories `/agent` reads agents from for this chat.
+    pub(crate) fn agent_dirs(&self, chat_id: &str) -> Vec<PathBuf> {
+        let project = self.group_projects.get(chat_id).map(|dir| dir.clone());
+        crate::agents::agent_dirs(project.as_deref())
+    }
+
+    /// The agent picked with `/agent`, if its file is still there.
+    pub(crate) fn agent_for(&self, sender: &str) -> Option<crate::agents::Agent> {
+        let name = self.agents.get(sender)?.clone();
+        crate::agents::find_agent(&self.agent_dirs(sender), &name)
     }
 
     pub(crate) fn avg_latency_ms(&self) -> f64 {
-        let count = self.metrics.latency_count.load(Ordering::Relaxed);
+        let count = self.metrics.latency_count.get();
         if count == 0 {
             return 0.0;
         }
-        self.metrics.latency_sum_ms.load(Ordering::Relaxed) as f64 / count as f64
+        self.metrics.latency_sum_ms.get() as f64 / count as f64
     }
 
     pub(crate) async fn send_message(
@@ -181,8 +619,41 @@ impl State {
         recipient: &str,
         message: &str,
     ) -> Result<(), AppError> {
-        self.sent_hashes.insert(hash_message(message), ());
-        self.signal_api.send_msg(recipient, message).await
+        crate::recipients::check_suspended(self, recipient)?;
+        let signed;
+        let signature = self.settings().signature;
+        let message = if signature.is_empty() {
+            message
+        } else {
+            signed = format!("{message}\n\n{signature}");
+            &signed
+        };
+        // Styled text arrives in our own echoes without its markers, so the
+        // plain text is what's hashed.
+        let styled = self
+            .config
+            .transport
+            .has_text_styles()
+            .then(|| crate::styles::parse(message))
+            .filter(|(_, styles)| !styles.is_empty());
+        let sent = match &styled {
+            Some((plain, styles)) => {
+                self.sent_hashes.insert(hash_message(plain), Instant::now());
+                self.signal_api.send_styled(recipient, plain, styles).await
+            }
+            None => {
+                self.sent_hashes
+                    .insert(hash_message(message), Instant::now());
+                self.signal_api.send_msg(recipient, message).await
+            }
+        };
+        match sent {
+            Err(e) => crate::recipients::handle_send_error(self, recipient, message, e).await,
+            Ok(()) => {
+                crate::recipients::delivered(self, recipient);
+                Ok(())
+            }
+        }
     }
 
     pub(crate) async fn send_long_message(
@@ -190,18 +661,90 @@ impl State {
         recipient: &str,
         message: &str,
     ) -> Result<(), AppError> {
-        let parts = split_message(message, crate::constants::MAX_SIGNAL_MSG_LEN);
+        let parts = self.config.transport.split_reply(message);
+        // Multi-part replies mark the message they answer: ✉️ while sending,
+        // then the number of parts delivered, then ✅.
+        let target = self
+            .last_inbound
+            .remove(recipient)
+            .map(|(_, target)| target)
+            .filter(|_| parts.len() > 1);
+        if let Some(target) = &target {
+            self.react(recipient, target, "✉️").await;
+        }
         for (i, part) in parts.iter().enumerate() {
-            if i > 0 {
+            // signal-cli-api sends are spaced by the pacer; other bridges get a fixed gap
+            if i > 0 && self.config.transport != crate::transport::Transport::Signal {
                 tokio::time::sleep(Duration::from_millis(200)).await;
             }
             self.send_message(recipient, part).await?;
+            if let Some(target) = &target {
+                self.react(recipient, target, parts_delivered_emoji(i + 1))
+                    .await;
+            }
+        }
+        if let Some(target) = &target {
+            self.react(recipient, target, "✅").await;
         }
         Ok(())
     }
 
+    /// Best-effort reaction to an incoming message.
+    pub(crate) async fn react(
+        &self,
+        recipient: &str,
+        (author, timestamp): &(String, i64),
+        emoji: &str,
+    ) {
+        if let Err(e) = self
+            .signal_api
+            .send_reaction(recipient, emoji, author, *timestamp)
+            .await
+        {
+            tracing::debug!(recipient = %recipient, "Reaction failed: {e}");
+        }
+    }
+
+    /// Show or stop the typing indicator, or whatever the chat chose in its
+    /// place with `/prefs set typing`.
     pub(crate) async fn set_typing(&self, recipient: &str, typing: bool) -> Result<(), AppError> {
-        self.signal_api.set_typing(recipient, typing).await
+        match self.prefs(recipient).typing {
+            Typing::On => self.signal_api.set_typing(recipient, typing).await,
+            Typing::Off => Ok(()),
+            Typing::React(emoji) => {
+                if typing {
+                    self.react_working(recipient, &emoji).await;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// React to the message being worked on, once however often the
+    /// indicator is refreshed.
+    async fn react_working(&self, recipient: &str, emoji: &str) {
+        let Some(target) = self.last_inbound.get(recipient).map(|t| t.clone()) else {
+            return;
+        };
+        if self
+            .working_reactions
+            .insert(recipient.to_string(), target.1)
+            == Some(target.1)
+        {
+            return;
+        }
+        self.react(recipient, &target, emoji).await;
+    }
+
+    /// Send the configured sticker for `status`, if any. Best effort: the
+    /// text reply has already gone out.
+    pub(crate) async fn send_sticker(&self, recipient: &str, status: StickerStatus) {
+        let Some(sticker) = self.config.stickers.sticker_for(status) else {
+            return;
+        };
+        if let Err(e) = self.signal_api.send_sticker(recipient, &sticker).await {
+            tracing::debug!(recipient = %recipient, "Sticker send failed: {e}");
+        }
     }
 
     pub(crate) async fn download_attachment(
@@ -221,6 +764,9 @@ impl State {
             .collect();
 
         for (sender, session_id, model) in &entries {
+            if crate::privacy::end(self, sender, session_id) {
+                continue;
+            }
             match self
                 .claude_runner
                 .summarize_session(session_id, model)
@@ -239,13 +785,101 @@ impl State {
         self.session_mgr.sessions.clear();
     }
 
+    /// Archive a session the TTL reaper found idle: summarize it, keep the
+    /// summary as memory and send it to the sender so the dropped context is
+    /// on record. Returns true if a summary was produced.
+    pub(crate) async fn archive_expired_session(&self, sender: &str, session_id: &str) -> bool {
+        if crate::privacy::end(self, sender, session_id) {
+            self.session_mgr.sessions.remove(sender);
+            self.agents.remove(sender);
+            self.pinned_files.clear(sender);
+            let notice = "Private session ended after inactivity; nothing from it was kept.";
+            if let Err(e) = self.send_message(sender, notice).await {
+                tracing::warn!(sender = %sender, "Failed to send archive notice: {e}");
+            }
+            return false;
+        }
+        let model = self
+            .session_mgr
+            .sessions
+            .get(sender)
+            .map(|s| s.model.clone())
+            .unwrap_or_else(|| self.settings().model);
+        let summary = self
+            .claude_runner
+            .summarize_session(session_id, &model)
+            .await;
+        let notice = match &summary {
+            Some(summary) => {
+                crate::memory::save_memory(sender, summary);
+                tracing::info!(sender = %sender, "Saved memory on expiry");
+                format!("Session archived. Summary: {summary}")
+            }
+            None => "Session archived after inactivity (no summary available).".to_string(),
+        };
+        if let Ok(conn) = crate::memory::open_memory_db(sender) {
+            crate::memory::purge_old_messages(&conn, crate::constants::MESSAGE_RETENTION_DAYS);
+        }
+        self.session_mgr.sessions.remove(sender);
+        self.session_tokens.remove(sender);
+        self.compacted_context.remove(sender);
+        self.agents.remove(sender);
+        self.pinned_files.clear(sender);
+        if let Err(e) = self.send_long_message(sender, &notice).await {
+            tracing::warn!(sender = %sender, "Failed to send archive notice: {e}");
+        }
+        summary.is_some()
+    }
+
+    /// Model for one message from `sender` on session model `model`, after
+    /// aliases and routing rules. Rules that aren't commands only apply while
+    /// the sender is on the default model.
+    pub(crate) fn route_model<'a>(
+        &self,
+        sender: &str,
+        text: &'a str,
+        model: &str,
+    ) -> crate::router::Route<'a> {
+        let chosen = model != self.settings().model;
+        let router = self
+            .model_router
+            .read()
+            .map(|r| r.clone())
+            .unwrap_or_default();
+        let mut route = router.route(sender, text, model, chosen);
+        if let Some(fallback) = crate::probe::fallback(self, &route.model) {
+            route.reason = Some(format!("{} is unavailable", route.model));
+            route.model = fallback;
+        }
+        route
+    }
+
+    /// Cancel `sender`'s running `claude` call, if any, and give their session
+    /// a fresh lock so messages stop queueing behind a hung task. Returns
+    /// whether a run was cancelled.
+    pub(crate) fn unstick(&self, sender: &str) -> bool {
+        let cancelled = match self.active_runs.remove(sender) {
+            Some((_, stop)) => {
+                stop.notify_one();
+                true
+            }
+            None => false,
+        };
+        self.run_progress.remove(sender);
+        if let Some(mut session) = self.session_mgr.sessions.get_mut(sender) {
+            session.lock = Arc::new(Mutex::new(()));
+        }
+        cancelled
+    }
+
     /// Get or create a session for a sender. Returns (session_id, model, lock, is_new).
     pub(crate) fn get_or_create_session(
         &self,
         sender: &str,
     ) -> (String, String, Arc<Mutex<()>>, bool) {
         let is_new = !self.session_mgr.sessions.contains_key(sender);
-        let default_model = self.config.model.clone();
+        let default_model = self.settings().model;
+        let group_model = self.group_settings(sender).model;
         let mut entry = self
             .session_mgr
             .sessions
@@ -253,17 +887,23 @@ impl State {
             .or_insert_with(|| {
                 let session_id = uuid::Uuid::new_v4().to_string();
                 tracing::info!(sender = %sender, session_id = %session_id, "New session created");
-                // Check for persisted model preference
-                let model = crate::memory::open_memory_db(sender)
-                    .ok()
-                    .and_then(|conn| crate::memory::load_model_preference(&conn))
+                // The group's own model, then the sender's saved `/model`
+                // choice, then the default.
+                let model = group_model
+                    .or_else(|| {
+                        crate::memory::open_memory_db(sender)
+                            .ok()
+                            .and_then(|conn| crate::memory::load_model_preference(&conn))
+                    })
                     .unwrap_or(default_model);
                 SenderState {
                     session_id,
                     model,
                     lock: Arc::new(Mutex::new(())),
                     last_activity: Instant::now(),
+                    started: Instant::now(),
                     message_count: 0,
+                    prefs: crate::preferences::load(sender),
                 }
             });
         entry.last_activity = Instant::now();
@@ -281,57 +921,240 @@ pub(crate) mod tests {
     use super::*;
     use crate::traits::{MockClaudeRunner, MockSignalApi};
 
+    impl State {
+        /// The config, for a test to change before using the state.
+        pub(crate) fn config_mut(&mut self) -> &mut Config {
+            Arc::make_mut(&mut self.config)
+        }
+    }
+
     pub(crate) fn test_state_with(signal: MockSignalApi, claude: MockClaudeRunner) -> State {
         State {
-            config: Config {
+            config: Arc::new(Config {
                 model: "sonnet".to_string(),
                 max_budget: 5.0,
                 rate_limit_config: None,
+                command_rate_limit: None,
                 session_ttl: None,
                 debounce_ms: 0,
                 account: "+1234567890".to_string(),
-                api_url: "http://127.0.0.1:9999".to_string(),
+                api: Arc::new(crate::failover::ApiEndpoints::single(
+                    "http://127.0.0.1:9999",
+                )),
                 config_path: None,
                 system_prompt: None,
-                webhook_url: None,
+                webhooks: Default::default(),
                 lakera_api_key: None,
-            },
-            metrics: Metrics {
-                start_time: Instant::now(),
-                message_count: AtomicU64::new(0),
-                total_cost: AtomicU64::new(0),
-                error_count: AtomicU64::new(0),
-                latency_sum_ms: AtomicU64::new(0),
-                latency_count: AtomicU64::new(0),
-            },
+                workdir_quota_bytes: None,
+                notify_lifecycle: false,
+                profile_status: None,
+                profile_name: String::new(),
+                allow_screenshot: false,
+                claude_command_prefix: crate::constants::DEFAULT_CLAUDE_COMMAND_PREFIX.to_string(),
+                flush_keyword: crate::constants::DEFAULT_FLUSH_KEYWORD.to_string(),
+                slash_passthrough: false,
+                net: crate::net::NetConfig::default(),
+                transport: crate::transport::Transport::Signal,
+                tts: crate::tts::TtsConfig::default(),
+                fetch: crate::fetch::FetchConfig::default(),
+                github: crate::github::GitHubConfig::default(),
+                ha: crate::homeassistant::HaConfig::default(),
+                stickers: Default::default(),
+                screening: Default::default(),
+                stream_progress: false,
+                confirm_above_usd: 0.0,
+                confirm_commands: Vec::new(),
+                maintenance_at: None,
+                maintenance_tasks: Vec::new(),
+                trust_new_identities: false,
+                send_receipts: false,
+                unknown_sender_reply: false,
+                signature: String::new(),
+                rerun_interrupted: false,
+                attachment_retention: Default::default(),
+                reply_slo_ms: None,
+                probe_every: None,
+                fallback_model: None,
+                archive: None,
+                backup: crate::backup::BackupConfig {
+                    dir: std::env::temp_dir().join("ccchat_test_backups"),
+                    passphrase: None,
+                    keep_daily: 1,
+                    keep_weekly: 0,
+                },
+                stuck_after: Duration::from_secs(crate::constants::DEFAULT_STUCK_RUN_MINS * 60),
+                ocr: false,
+            }),
+            settings: RwLock::new(crate::settings::Settings {
+                model: "sonnet".to_string(),
+                max_budget: 5.0,
+                debounce_ms: 0,
+                format: Default::default(),
+                signature: String::new(),
+            }),
+            metrics: Metrics::new(),
             session_mgr: SessionManager {
                 sessions: DashMap::new(),
                 truncated_sessions: DashMap::new(),
+                budget_hits: DashMap::new(),
             },
             debounce: DebounceState {
                 buffers: DashMap::new(),
                 active: DashMap::new(),
+                wake: DashMap::new(),
             },
-            allowed_ids: {
-                let m = DashMap::new();
-                m.insert("+1234567890".to_string(), ());
-                m.insert("+allowed_user".to_string(), ());
-                m
-            },
-            pending_senders: DashMap::new(),
-            pending_counter: AtomicU64::new(0),
+            run_slots: RunSlots::new(0),
+            access: crate::access::Access::new(
+                ["+1234567890", "+allowed_user"]
+                    .into_iter()
+                    .map(|id| (id.to_string(), ()))
+                    .collect(),
+                DashMap::new(),
+                DashMap::new(),
+            ),
             sent_hashes: Arc::new(DashMap::new()),
+            last_inbound: DashMap::new(),
             rate_limits: DashMap::new(),
+            sender_rate_limits: DashMap::new(),
+            resource_limits: RwLock::new(Default::default()),
+            model_router: RwLock::new(Default::default()),
             sender_costs: DashMap::new(),
+            token_usage: DashMap::new(),
+            session_tokens: DashMap::new(),
+            compacted_context: DashMap::new(),
             sender_prompts: DashMap::new(),
             pending_recalls: DashMap::new(),
+            pending_carries: DashMap::new(),
+            group_projects: DashMap::new(),
+            group_settings: DashMap::new(),
+            pinned_files: Default::default(),
+            model_health: Default::default(),
+            agents: DashMap::new(),
+            active_runs: DashMap::new(),
+            inbox: crate::inbox::Inbox::new(
+                crate::constants::DEFAULT_INBOUND_QUEUE,
+                crate::inbox::Overflow::DropNewest,
+            ),
+            pending_confirms: DashMap::new(),
+            run_progress: DashMap::new(),
+            pending_choices: DashMap::new(),
+            last_replies: DashMap::new(),
+            pending_commands: DashMap::new(),
+            maintenance: DashMap::new(),
+            suspended_recipients: DashMap::new(),
+            identity_changes: DashMap::new(),
+            raised_hands: DashMap::new(),
+            working_reactions: DashMap::new(),
+            named_sessions: DashMap::new(),
+            private_sessions: DashMap::new(),
+            redactor: crate::redact::Redactor::new(&[]).unwrap(),
+            redaction_off: DashMap::new(),
+            voice_modes: DashMap::new(),
+            log_tail: Arc::new(crate::logtail::TailHandle::default()),
+            online_announced: std::sync::atomic::AtomicBool::new(false),
             runtime_system_prompt: RwLock::new(None),
+            github_digest: RwLock::new(None),
+            context_providers: Vec::new(),
+            claude_keys: Default::default(),
+            tool_policies: Default::default(),
             http: Client::new(),
             signal_api: Box::new(signal),
             claude_runner: Box::new(claude),
         }
     }
 
+    #[tokio::test]
+    async fn test_send_message_appends_signature() {
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_send_msg()
+            .withf(|_, msg| msg == "hi\n\n— ccchat on homeserver")
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let state = test_state_with(signal, MockClaudeRunner::new());
+        state
+            .settings
+            .write()
+            .unwrap()
+            .set("signature", "— ccchat on homeserver")
+            .unwrap();
+        state.send_message("+1", "hi").await.unwrap();
+        // The echo check matches what was actually sent.
+        assert!(state
+            .sent_hashes
+            .contains_key(&hash_message("hi\n\n— ccchat on homeserver")));
+    }
+
+    #[tokio::test]
+    async fn test_send_message_styles_markdown() {
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_send_styled()
+            .withf(|_, msg, styles| {
+                msg == "Run cargo test"
+                    && styles.len() == 1
+                    && styles[0].to_string() == "4:10:MONOSPACE"
+            })
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        signal
+            .expect_send_msg()
+            .withf(|_, msg| msg == "2*3*4")
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let state = test_state_with(signal, MockClaudeRunner::new());
+        state.send_message("+1", "Run `cargo test`").await.unwrap();
+        state.send_message("+1", "2*3*4").await.unwrap();
+        // Our own echo comes back without the markers.
+        assert!(state
+            .sent_hashes
+            .contains_key(&hash_message("Run cargo test")));
+    }
+
+    #[tokio::test]
+    async fn test_run_slot_queue_position_feedback() {
+        let sent = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let sent_clone = Arc::clone(&sent);
+        let mut signal = MockSignalApi::new();
+        signal.expect_send_msg().returning(move |_, msg| {
+            sent_clone.lock().unwrap().push(msg.to_string());
+            Ok(())
+        });
+        let mut state = test_state_with(signal, MockClaudeRunner::new());
+        state.run_slots = RunSlots::new(1);
+        let state = Arc::new(state);
+
+        let held = state.acquire_run_slot("+a").await.unwrap();
+        assert!(sent.lock().unwrap().is_empty());
+        assert_eq!(state.run_slots.busy(), 1);
+
+        let waiter = {
+            let state = Arc::clone(&state);
+            tokio::spawn(async move {
+                let _slot = state.acquire_run_slot("+b").await.unwrap();
+            })
+        };
+        while state.run_slots.waiting.load(Ordering::Relaxed) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            sent.lock().unwrap().as_slice(),
+            ["All 1 Claude slots are busy. You're #1 in line."]
+        );
+        drop(held);
+        waiter.await.unwrap();
+        assert_eq!(state.run_slots.busy(), 0);
+        assert_eq!(state.run_slots.waiting.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_unlimited_run_slots_never_block() {
+        let slots = RunSlots::new(0);
+        let permits: Vec<_> = (0..100).filter_map(|_| slots.try_acquire()).collect();
+        assert_eq!(permits.len(), 100);
+        assert_eq!(slots.busy(), 100);
+    }
+
     #[test]
     fn test_token_bucket_basic() {
         let mut bucket = TokenBucket::new(3.0, 1.0);
@@ -351,6 +1174,33 @@ pub(crate) mod tests {
         assert!(bucket.try_consume());
     }
 
+    #[test]
+    fn test_token_bucket_restore_caps_at_capacity() {
+        let bucket = TokenBucket::restore(5.0, 1.0, 2.0, Duration::from_secs(2));
+        assert!((bucket.tokens - 4.0).abs() < f64::EPSILON);
+        let bucket = TokenBucket::restore(5.0, 1.0, 2.0, Duration::from_secs(3600));
+        assert!((bucket.tokens - 5.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_rate_limit_for_prefers_sender_override() {
+        let mut state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.config_mut().rate_limit_config = Some((5.0, 1.0));
+        assert_eq!(
+            state.rate_limit_for("+a", RateClass::Claude),
+            Some((5.0, 1.0))
+        );
+        assert_eq!(state.rate_limit_for("+a", RateClass::Command), None);
+        state
+            .sender_rate_limits
+            .insert(("+a".to_string(), RateClass::Command), (30.0, 0.5));
+        assert_eq!(
+            state.rate_limit_for("+a", RateClass::Command),
+            Some((30.0, 0.5))
+        );
+        assert_eq!(state.rate_limit_for("+b", RateClass::Command), None);
+    }
+
     #[test]
     fn test_rate_limit_blocks() {
         let mut bucket = TokenBucket::new(1.0, 0.0);
@@ -450,7 +1300,7 @@ pub(crate) mod tests {
     #[test]
     fn test_get_system_prompt_custom_global() {
         let mut state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
-        state.config.system_prompt = Some("You are a helpful math tutor.".to_string());
+        state.config_mut().system_prompt = Some("You are a helpful math tutor.".to_string());
         let prompt = state.get_system_prompt("+user");
         assert!(prompt.contains("You are a helpful math tutor."));
         assert!(prompt.contains(crate::NO_MEMORY_PROMPT));
@@ -459,7 +1309,7 @@ pub(crate) mod tests {
     #[test]
     fn test_get_system_prompt_per_sender_override() {
         let mut state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
-        state.config.system_prompt = Some("Global prompt.".to_string());
+        state.config_mut().system_prompt = Some("Global prompt.".to_string());
         state
             .sender_prompts
             .insert("+special".to_string(), "VIP prompt.".to_string());
@@ -476,15 +1326,57 @@ pub(crate) mod tests {
     #[test]
     fn test_metrics_error_count_initial_zero() {
         let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
-        assert_eq!(state.metrics.error_count.load(Ordering::Relaxed), 0);
+        assert_eq!(state.metrics.error_count.get(), 0);
     }
 
     #[test]
     fn test_metrics_error_count_increment() {
         let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
-        state.metrics.error_count.fetch_add(1, Ordering::Relaxed);
-        state.metrics.error_count.fetch_add(1, Ordering::Relaxed);
-        assert_eq!(state.metrics.error_count.load(Ordering::Relaxed), 2);
+        state.metrics.error_count.incr();
+        state.metrics.error_count.incr();
+        assert_eq!(state.metrics.error_count.get(), 2);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        assert_eq!(state.metrics.latency_percentile(0.95), 0);
+        for ms in 1..=100 {
+            state.record_latency(ms);
+        }
+        assert_eq!(state.metrics.latency_percentile(0.50), 50);
+        assert_eq!(state.metrics.latency_percentile(0.95), 95);
+    }
+
+    #[test]
+    fn test_latency_samples_bounded() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        for _ in 0..crate::constants::LATENCY_SAMPLE_WINDOW + 10 {
+            state.record_latency(5);
+        }
+        let len = state.metrics.latency_samples.lock().unwrap().len();
+        assert_eq!(len, crate::constants::LATENCY_SAMPLE_WINDOW);
+    }
+
+    #[test]
+    fn test_avg_cost_per_message() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        assert_eq!(state.avg_cost_usd(), 0.0);
+        state.add_cost(0.10);
+        state.add_cost(0.30);
+        assert!((state.avg_cost_usd() - 0.20).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_failure_counts_by_category() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.metrics.record_failure("timeout");
+        state.metrics.record_failure("budget");
+        state.metrics.record_failure("timeout");
+        assert_eq!(
+            state.metrics.failure_counts(),
+            vec![("budget", 1), ("timeout", 2)]
+        );
     }
 
     #[test]
@@ -515,6 +1407,63 @@ pub(crate) mod tests {
         assert_eq!(call_count.load(Ordering::Relaxed), 2);
     }
 
+    #[tokio::test]
+    async fn test_send_long_message_reacts_as_parts_are_delivered() {
+        let mut signal = MockSignalApi::new();
+        signal.expect_send_msg().returning(|_, _| Ok(()));
+        let reactions = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&reactions);
+        signal
+            .expect_send_reaction()
+            .withf(|to, _, author, ts| {
+                to == "+allowed_user" && author == "+allowed_user" && *ts == 1700000000123
+            })
+            .returning(move |_, emoji, _, _| {
+                seen.lock().unwrap().push(emoji.to_string());
+                Ok(())
+            });
+
+        let state = test_state_with(signal, MockClaudeRunner::new());
+        state.last_inbound.insert(
+            "+allowed_user".to_string(),
+            ("+allowed_user".to_string(), 1700000000123),
+        );
+        state
+            .send_long_message("+allowed_user", &"a".repeat(6000))
+            .await
+            .unwrap();
+        assert_eq!(*reactions.lock().unwrap(), ["✉️", "1️⃣", "2️⃣", "✅"]);
+        // Consumed: the next reply doesn't react to the same message again.
+        assert!(state.last_inbound.is_empty());
+        assert_eq!(parts_delivered_emoji(11), "📨");
+    }
+
+    #[tokio::test]
+    async fn test_set_typing_follows_preference() {
+        let sender = format!("+typing_{}", uuid::Uuid::new_v4());
+        let mut signal = MockSignalApi::new();
+        signal.expect_set_typing().times(1).returning(|_, _| Ok(()));
+        signal
+            .expect_send_reaction()
+            .withf(|_, emoji, _, ts| emoji == "🛠️" && *ts == 42)
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+        let state = test_state_with(signal, MockClaudeRunner::new());
+        state
+            .last_inbound
+            .insert(sender.clone(), (sender.clone(), 42));
+
+        state.set_typing(&sender, true).await.unwrap();
+        state.update_prefs(&sender, |p| p.typing = Typing::Off);
+        state.set_typing(&sender, true).await.unwrap();
+        state.update_prefs(&sender, |p| p.typing = Typing::React("🛠️".to_string()));
+        state.set_typing(&sender, true).await.unwrap();
+        // Refreshes and the final "stopped typing" don't react again.
+        state.set_typing(&sender, true).await.unwrap();
+        state.set_typing(&sender, false).await.unwrap();
+        crate::memory::delete_memory(&sender);
+    }
+
     // --- shutdown tests ---
 
     #[tokio::test]
@@ -536,7 +1485,9 @@ pub(crate) mod tests {
                 model: "sonnet".to_string(),
                 lock: Arc::new(Mutex::new(())),
                 last_activity: Instant::now(),
+                started: Instant::now(),
                 message_count: 0,
+                prefs: Default::default(),
             },
         );
         state.session_mgr.sessions.insert(
@@ -546,7 +1497,9 @@ pub(crate) mod tests {
                 model: "sonnet".to_string(),
                 lock: Arc::new(Mutex::new(())),
                 last_activity: Instant::now(),
+                started: Instant::now(),
                 message_count: 0,
+                prefs: Default::default(),
             },
         );
 
@@ -555,6 +1508,62 @@ pub(crate) mod tests {
         assert!(state.session_mgr.sessions.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_archive_expired_session_sends_summary() {
+        let mut claude = MockClaudeRunner::new();
+        claude
+            .expect_summarize_session()
+            .withf(|sid, model| sid == "s1" && model == "haiku")
+            .returning(|_, _| Some("Talked about Rust lifetimes.".to_string()));
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_send_msg()
+            .withf(|to, msg| {
+                to == "+archive_user"
+                    && msg == "Session archived. Summary: Talked about Rust lifetimes."
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let state = test_state_with(signal, claude);
+        state.session_mgr.sessions.insert(
+            "+archive_user".to_string(),
+            SenderState {
+                session_id: "s1".to_string(),
+                model: "haiku".to_string(),
+                lock: Arc::new(Mutex::new(())),
+                last_activity: Instant::now(),
+                started: Instant::now(),
+                message_count: 3,
+                prefs: Default::default(),
+            },
+        );
+        state
+            .session_tokens
+            .insert("+archive_user".to_string(), 1234);
+
+        assert!(state.archive_expired_session("+archive_user", "s1").await);
+        assert!(!state.session_mgr.sessions.contains_key("+archive_user"));
+        assert!(!state.session_tokens.contains_key("+archive_user"));
+        crate::memory::delete_memory("+archive_user");
+    }
+
+    #[tokio::test]
+    async fn test_archive_expired_session_without_summary_still_notifies() {
+        let mut claude = MockClaudeRunner::new();
+        claude.expect_summarize_session().returning(|_, _| None);
+        let mut signal = MockSignalApi::new();
+        signal
+            .expect_send_msg()
+            .withf(|_, msg| msg.starts_with("Session archived after inactivity"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let state = test_state_with(signal, claude);
+        assert!(!state.archive_expired_session("+archive_none", "s1").await);
+        crate::memory::delete_memory("+archive_none");
+    }
+
     #[tokio::test]
     async fn test_shutdown_save_sessions_empty_is_noop() {
         let mut claude = MockClaudeRunner::new();
@@ -578,7 +1587,9 @@ pub(crate) mod tests {
                 model: "sonnet".to_string(),
                 lock: Arc::new(Mutex::new(())),
                 last_activity: Instant::now(),
+                started: Instant::now(),
                 message_count: 0,
+                prefs: Default::default(),
             },
         );
 
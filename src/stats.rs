@@ -23,27 +23,107 @@ pub(crate) fn build_stats_json(state: &State) -> serde_json::Value {
             (e.key().clone(), serde_json::json!(cost))
         })
         .collect();
+    let failures: serde_json::Map<String, serde_json::Value> = state
+        .metrics
+        .failure_counts()
+        .into_iter()
+        .map(|(category, count)| (category.to_string(), serde_json::json!(count)))
+        .collect();
     serde_json::json!({
         "uptime_secs": uptime.as_secs(),
-        "messages": state.metrics.message_count.load(Ordering::Relaxed),
+        "messages": state.metrics.message_count.get(),
         "active_sessions": state.session_mgr.sessions.len(),
-        "allowed_senders": state.allowed_ids.len(),
+        "allowed_senders": state.access.allowed_ids.len(),
         "total_cost_usd": state.total_cost_usd(),
-        "error_count": state.metrics.error_count.load(Ordering::Relaxed),
+        "cache_savings_usd": state.cache_savings_usd(),
+        "error_count": state.metrics.error_count.get(),
         "avg_latency_ms": state.avg_latency_ms(),
+        "latency_p50_ms": state.metrics.latency_percentile(0.50),
+        "latency_p95_ms": state.metrics.latency_percentile(0.95),
+        "reply_p50_ms": state.metrics.reply_percentile(0.50),
+        "reply_p95_ms": state.metrics.reply_percentile(0.95),
+        "reply_slo_ms": state.config.reply_slo_ms,
+        "failures": failures,
+        "avg_cost_per_message_usd": state.avg_cost_usd(),
+        "reconnects": state.metrics.reconnects.get(),
+        "rate_limited": state.metrics.rate_limited.get(),
         "sender_costs": sender_costs,
-        "model": state.config.model,
+        "resources": build_resources_json(state),
+        "inbound": {
+            "depth": state.inbox.depth(),
+            "capacity": state.inbox.capacity(),
+            "busy": state.inbox.busy.load(Ordering::Relaxed),
+            "high_water": state.inbox.high_water(),
+            "shed": state.inbox.shed_count(),
+        },
+        "maintenance": crate::maintenance::stats_json(state),
+        "attachments": crate::attachments::stats_json(state),
+        "availability": crate::probe::stats_json(state),
+        "model": state.settings().model,
         "version": env!("CARGO_PKG_VERSION"),
     })
 }
 
+fn build_resources_json(state: &State) -> serde_json::Value {
+    let snap = crate::resources::snapshot();
+    let limits = state
+        .resource_limits
+        .read()
+        .map(|l| l.clone())
+        .unwrap_or_default();
+    serde_json::json!({
+        "load_1m": snap.load_1m,
+        "free_memory_mb": snap.free_memory_mb,
+        "free_disk_mb": snap.free_disk_mb,
+        "limits": limits,
+        "overloaded": limits.exceeded(&snap),
+        "queued": state.metrics.overload_queued.get(),
+        "rejected": state.metrics.overload_rejected.get(),
+    })
+}
+
 pub(crate) fn build_prometheus_metrics(state: &State) -> String {
     let uptime = state.metrics.start_time.elapsed().as_secs();
-    let messages = state.metrics.message_count.load(Ordering::Relaxed);
-    let errors = state.metrics.error_count.load(Ordering::Relaxed);
+    let messages = state.metrics.message_count.get();
+    let errors = state.metrics.error_count.get();
     let cost = state.total_cost_usd();
     let sessions = state.session_mgr.sessions.len();
     let latency = state.avg_latency_ms();
+    let p50 = state.metrics.latency_percentile(0.50);
+    let p95 = state.metrics.latency_percentile(0.95);
+    let reply_p50 = state.metrics.reply_percentile(0.50);
+    let reply_p95 = state.metrics.reply_percentile(0.95);
+    let avg_cost = state.avg_cost_usd();
+    let reconnects = state.metrics.reconnects.get();
+    let rate_limited = state.metrics.rate_limited.get();
+    let snap = crate::resources::snapshot();
+    let load = snap.load_1m;
+    let free_memory = snap.free_memory_mb * 1024 * 1024;
+    let free_disk = snap
+        .free_disk_mb
+        .map(|mb| {
+            format!(
+                "# HELP ccchat_free_disk_bytes Free disk space under the temp directory\n\
+                 # TYPE ccchat_free_disk_bytes gauge\n\
+                 ccchat_free_disk_bytes {}\n",
+                mb * 1024 * 1024
+            )
+        })
+        .unwrap_or_default();
+    let queued = state.metrics.overload_queued.get();
+    let rejected = state.metrics.overload_rejected.get();
+    let inbound_depth = state.inbox.depth();
+    let inbound_shed = state.inbox.shed_count();
+    let models = crate::probe::prometheus(state);
+    let mut failures = String::from(
+        "# HELP ccchat_claude_failures_total Claude run failures by category\n\
+         # TYPE ccchat_claude_failures_total counter\n",
+    );
+    for (category, count) in state.metrics.failure_counts() {
+        failures.push_str(&format!(
+            "ccchat_claude_failures_total{{category=\"{category}\"}} {count}\n"
+        ));
+    }
     format!(
         "# HELP ccchat_uptime_seconds Bot uptime in seconds\n\
          # TYPE ccchat_uptime_seconds gauge\n\
@@ -62,12 +142,90 @@ pub(crate) fn build_prometheus_metrics(state: &State) -> String {
          ccchat_active_sessions {sessions}\n\
          # HELP ccchat_avg_latency_ms Average response latency\n\
          # TYPE ccchat_avg_latency_ms gauge\n\
-         ccchat_avg_latency_ms {latency}\n"
+         ccchat_avg_latency_ms {latency}\n\
+         # HELP ccchat_claude_latency_ms Claude run duration percentiles over recent runs\n\
+         # TYPE ccchat_claude_latency_ms gauge\n\
+         ccchat_claude_latency_ms{{quantile=\"0.5\"}} {p50}\n\
+         ccchat_claude_latency_ms{{quantile=\"0.95\"}} {p95}\n\
+         # HELP ccchat_reply_latency_ms Message received to reply delivered, percentiles over recent replies\n\
+         # TYPE ccchat_reply_latency_ms gauge\n\
+         ccchat_reply_latency_ms{{quantile=\"0.5\"}} {reply_p50}\n\
+         ccchat_reply_latency_ms{{quantile=\"0.95\"}} {reply_p95}\n\
+         {failures}\
+         # HELP ccchat_avg_cost_per_message_usd Average cost per Claude reply\n\
+         # TYPE ccchat_avg_cost_per_message_usd gauge\n\
+         ccchat_avg_cost_per_message_usd {avg_cost}\n\
+         # HELP ccchat_reconnects_total WebSocket reconnects\n\
+         # TYPE ccchat_reconnects_total counter\n\
+         ccchat_reconnects_total {reconnects}\n\
+         # HELP ccchat_rate_limited_total Messages dropped by rate limiting\n\
+         # TYPE ccchat_rate_limited_total counter\n\
+         ccchat_rate_limited_total {rate_limited}\n\
+         # HELP ccchat_load_1m One-minute load average\n\
+         # TYPE ccchat_load_1m gauge\n\
+         ccchat_load_1m {load}\n\
+         # HELP ccchat_free_memory_bytes Available memory\n\
+         # TYPE ccchat_free_memory_bytes gauge\n\
+         ccchat_free_memory_bytes {free_memory}\n\
+         {free_disk}\
+         # HELP ccchat_overload_total Requests held or turned away by resource limits\n\
+         # TYPE ccchat_overload_total counter\n\
+         ccchat_overload_total{{action=\"queued\"}} {queued}\n\
+         ccchat_overload_total{{action=\"rejected\"}} {rejected}\n\
+         # HELP ccchat_inbound_queue_depth Messages waiting for a worker\n\
+         # TYPE ccchat_inbound_queue_depth gauge\n\
+         ccchat_inbound_queue_depth {inbound_depth}\n\
+         # HELP ccchat_inbound_shed_total Messages shed because the inbound queue was full\n\
+         # TYPE ccchat_inbound_shed_total counter\n\
+         ccchat_inbound_shed_total {inbound_shed}\n\
+         {models}"
     )
 }
 
-pub(crate) async fn run_stats_server(listener: TcpListener, state: Arc<State>) {
+/// `/costs.csv` or `/costs.json` body for an optional `?since=&until=`
+/// date range. `Err` is a bad query.
+fn build_costs(path: &str, query: &str) -> Result<(String, &'static str), String> {
+    let param = |key: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    };
+    let (since, until) = crate::ledger::date_range(param("since"), param("until"))?;
+    let conn = crate::ledger::open_ledger_db().map_err(|e| e.to_string())?;
+    let rows = crate::ledger::daily_costs(&conn, since, until);
+    Ok(if path == "/costs.csv" {
+        (crate::ledger::costs_csv(&rows), "text/csv; charset=utf-8")
+    } else {
+        (
+            crate::ledger::costs_json(&rows).to_string(),
+            "application/json",
+        )
+    })
+}
+
+/// Whether `request` carries `Authorization: Bearer <token>`; anything
+/// goes when no token is set.
+fn authorized(request: &str, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    request.lines().skip(1).any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("authorization")
+                && value.trim().strip_prefix("Bearer ") == Some(token)
+        })
+    })
+}
+
+pub(crate) async fn run_stats_server(
+    listener: TcpListener,
+    state: Arc<State>,
+    token: Option<String>,
+) {
     info!(addr = %listener.local_addr().unwrap(), "Stats server listening");
+    let token: Option<Arc<str>> = token.map(Into::into);
     loop {
         let (mut stream, addr) = match listener.accept().await {
             Ok(conn) => conn,
@@ -78,12 +236,23 @@ pub(crate) async fn run_stats_server(listener: TcpListener, state: Arc<State>) {
         };
         debug!(peer = %addr, "Stats connection");
         let state = state.clone();
+        let token = token.clone();
         tokio::spawn(async move {
             let mut buf = [0u8; 1024];
             let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut buf).await;
             let request = String::from_utf8_lossy(&buf);
-            let path = request.split_whitespace().nth(1).unwrap_or("/");
-            let (body, content_type) = if path == "/healthz" {
+            let target = request.split_whitespace().nth(1).unwrap_or("/");
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let mut status = "200 OK";
+            let (body, content_type) = if !authorized(&request, token.as_deref()) {
+                status = "401 Unauthorized";
+                ("Unauthorized".to_string(), "text/plain; charset=utf-8")
+            } else if path == "/costs.csv" || path == "/costs.json" {
+                build_costs(path, query).unwrap_or_else(|e| {
+                    status = "400 Bad Request";
+                    (e, "text/plain; charset=utf-8")
+                })
+            } else if path == "/healthz" {
                 (build_health_json(&state).to_string(), "application/json")
             } else if path == "/metrics" {
                 (
@@ -94,7 +263,7 @@ pub(crate) async fn run_stats_server(listener: TcpListener, state: Arc<State>) {
                 (build_stats_json(&state).to_string(), "application/json")
             };
             let response = format!(
-                "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
                 body.len(),
                 body
             );
@@ -143,6 +312,120 @@ mod tests {
         assert_eq!(json["total_cost_usd"], 0.0);
     }
 
+    #[test]
+    fn test_stats_json_degradation_metrics() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.record_latency(100);
+        state.record_latency(300);
+        state.metrics.record_failure("timeout");
+        state.metrics.reconnects.add(2);
+        state.metrics.rate_limited.add(4);
+        state.add_cost(0.5);
+        let json = build_stats_json(&state);
+        assert_eq!(json["latency_p50_ms"], 100);
+        assert_eq!(json["latency_p95_ms"], 300);
+        assert_eq!(json["failures"]["timeout"], 1);
+        assert_eq!(json["reconnects"], 2);
+        assert_eq!(json["rate_limited"], 4);
+        assert!((json["avg_cost_per_message_usd"].as_f64().unwrap() - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_prometheus_degradation_metrics() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.record_latency(250);
+        state.metrics.record_failure("budget");
+        state.metrics.rate_limited.add(3);
+        let metrics = build_prometheus_metrics(&state);
+        assert!(
+            metrics.contains("ccchat_claude_latency_ms{quantile=\"0.95\"} 250"),
+            "got: {metrics}"
+        );
+        assert!(
+            metrics.contains("ccchat_claude_failures_total{category=\"budget\"} 1"),
+            "got: {metrics}"
+        );
+        assert!(metrics.contains("ccchat_rate_limited_total 3"));
+        assert!(metrics.contains("ccchat_reconnects_total 0"));
+    }
+
+    #[test]
+    fn test_inbound_queue_metrics() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        let inbound = |text: &str| crate::inbox::Inbound {
+            reply_to: "+1".to_string(),
+            text: text.to_string(),
+            attachments: vec![],
+            received: std::time::Instant::now(),
+            speaker: None,
+            sent_at: None,
+        };
+        for i in 0..state.inbox.capacity() + 2 {
+            state.inbox.push(inbound(&i.to_string()));
+        }
+        let json = build_stats_json(&state);
+        assert_eq!(json["inbound"]["depth"], state.inbox.capacity());
+        assert_eq!(json["inbound"]["shed"], 2);
+        let metrics = build_prometheus_metrics(&state);
+        assert!(
+            metrics.contains("ccchat_inbound_shed_total 2"),
+            "got: {metrics}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_json_maintenance_status() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        assert!(build_stats_json(&state)["maintenance"]["tasks"]
+            .as_object()
+            .unwrap()
+            .is_empty());
+        crate::maintenance::run(
+            &state,
+            &[
+                crate::maintenance::Task::Hashes,
+                crate::maintenance::Task::Archive,
+            ],
+        )
+        .await;
+        let json = build_stats_json(&state);
+        assert_eq!(json["maintenance"]["tasks"]["hashes"]["ok"], true);
+        assert_eq!(json["maintenance"]["tasks"]["archive"]["ok"], false);
+        assert!(
+            json["maintenance"]["tasks"]["archive"]["last_run"]
+                .as_i64()
+                .unwrap()
+                > 0
+        );
+    }
+
+    #[test]
+    fn test_reply_latency_and_slo_alerts() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        // Outside a message task there's nothing to measure from
+        state.record_reply_latency();
+        assert_eq!(state.metrics.reply_sample_count(), 0);
+
+        let received = std::time::Instant::now() - std::time::Duration::from_secs(3);
+        for _ in 0..crate::constants::REPLY_SLO_MIN_SAMPLES {
+            crate::state::RECEIVED_AT.sync_scope(received, || state.record_reply_latency());
+        }
+        let json = build_stats_json(&state);
+        assert!(json["reply_p95_ms"].as_u64().unwrap() >= 3000);
+        assert!(build_prometheus_metrics(&state)
+            .contains("ccchat_reply_latency_ms{quantile=\"0.95\"} 3"));
+
+        let alert = crate::background::check_reply_slo(&state, 2000).unwrap();
+        assert!(
+            alert.starts_with("⚠️ Replies are slow: p95 is 3.0s"),
+            "{alert}"
+        );
+        // Only once per breach
+        assert!(crate::background::check_reply_slo(&state, 2000).is_none());
+        let recovered = crate::background::check_reply_slo(&state, 5000).unwrap();
+        assert!(recovered.contains("back within the SLO"));
+    }
+
     #[test]
     fn test_stats_includes_all_fields() {
         let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
@@ -193,7 +476,7 @@ mod tests {
     #[test]
     fn test_stats_includes_error_count() {
         let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
-        state.metrics.error_count.fetch_add(3, Ordering::Relaxed);
+        state.metrics.error_count.add(3);
         let json = build_stats_json(&state);
         assert_eq!(json["error_count"], 3);
     }
@@ -221,7 +504,7 @@ mod tests {
         assert_eq!(json["active_sessions"], 0);
 
         // Mutate: add messages
-        state.metrics.message_count.fetch_add(42, Ordering::Relaxed);
+        state.metrics.message_count.add(42);
         let json = build_stats_json(&state);
         assert_eq!(json["messages"], 42);
 
@@ -238,7 +521,9 @@ mod tests {
                 model: "sonnet".to_string(),
                 lock: Arc::new(tokio::sync::Mutex::new(())),
                 last_activity: std::time::Instant::now(),
+                started: std::time::Instant::now(),
                 message_count: 0,
+                prefs: Default::default(),
             },
         );
         let json = build_stats_json(&state);
@@ -248,7 +533,7 @@ mod tests {
     #[tokio::test]
     async fn test_stats_server_responds_with_json() {
         let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
-        state.metrics.message_count.store(7, Ordering::Relaxed);
+        state.metrics.message_count.set(7);
         state.add_cost(0.42);
 
         let state = Arc::new(state);
@@ -257,7 +542,7 @@ mod tests {
 
         // Spawn the stats server
         let server_state = state.clone();
-        tokio::spawn(run_stats_server(listener, server_state));
+        tokio::spawn(run_stats_server(listener, server_state, None));
 
         // Connect and send a minimal HTTP request
         let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
@@ -308,7 +593,7 @@ mod tests {
     #[test]
     fn test_prometheus_metrics_has_messages() {
         let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
-        state.metrics.message_count.store(42, Ordering::Relaxed);
+        state.metrics.message_count.set(42);
         let metrics = build_prometheus_metrics(&state);
         assert!(
             metrics.contains("ccchat_messages_total 42"),
@@ -319,7 +604,7 @@ mod tests {
     #[test]
     fn test_prometheus_metrics_has_errors() {
         let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
-        state.metrics.error_count.store(3, Ordering::Relaxed);
+        state.metrics.error_count.set(3);
         let metrics = build_prometheus_metrics(&state);
         assert!(metrics.contains("ccchat_errors_total 3"), "got: {metrics}");
     }
@@ -358,7 +643,7 @@ mod tests {
         let addr = listener.local_addr().unwrap();
 
         let server_state = state.clone();
-        tokio::spawn(run_stats_server(listener, server_state));
+        tokio::spawn(run_stats_server(listener, server_state, None));
 
         let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
         tokio::io::AsyncWriteExt::write_all(
@@ -389,7 +674,7 @@ mod tests {
         let addr = listener.local_addr().unwrap();
 
         let server_state = state.clone();
-        tokio::spawn(run_stats_server(listener, server_state));
+        tokio::spawn(run_stats_server(listener, server_state, None));
 
         let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
         tokio::io::AsyncWriteExt::write_all(
@@ -414,4 +699,74 @@ mod tests {
         // Should NOT contain full stats fields
         assert!(json.get("messages").is_none());
     }
+
+    #[tokio::test]
+    async fn test_costs_endpoint_serves_csv_and_rejects_bad_dates() {
+        let state = Arc::new(test_state_with(
+            MockSignalApi::new(),
+            MockClaudeRunner::new(),
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(run_stats_server(listener, state, None));
+
+        let get = |target: &'static str| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+            tokio::io::AsyncWriteExt::write_all(&mut stream, request.as_bytes())
+                .await
+                .unwrap();
+            let mut response = Vec::new();
+            tokio::io::AsyncReadExt::read_to_end(&mut stream, &mut response)
+                .await
+                .unwrap();
+            String::from_utf8(response).unwrap()
+        };
+
+        let csv = get("/costs.csv?since=2020-01-01&until=2020-01-01").await;
+        assert!(csv.starts_with("HTTP/1.1 200 OK"));
+        assert!(csv.contains("Content-Type: text/csv"));
+        assert!(csv.ends_with("\r\n\r\ndate,sender,runs,cost_usd\n"));
+        let bad = get("/costs.json?since=yesterday").await;
+        assert!(bad.starts_with("HTTP/1.1 400 Bad Request"));
+        assert!(bad.contains("Invalid date 'yesterday'"));
+    }
+
+    #[test]
+    fn test_authorized() {
+        let request = "GET / HTTP/1.1\r\nHost: x\r\nauthorization: Bearer s3cret\r\n\r\n";
+        assert!(authorized(request, None));
+        assert!(authorized(request, Some("s3cret")));
+        assert!(!authorized(request, Some("other")));
+        assert!(!authorized("GET / HTTP/1.1\r\n\r\n", Some("s3cret")));
+    }
+
+    #[tokio::test]
+    async fn test_stats_server_requires_token() {
+        let state = Arc::new(test_state_with(
+            MockSignalApi::new(),
+            MockClaudeRunner::new(),
+        ));
+        let listener = TcpListener::bind("[::1]:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(run_stats_server(listener, state, Some("s3cret".into())));
+
+        let get = |auth: &'static str| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET /healthz HTTP/1.1\r\nHost: localhost\r\n{auth}\r\n");
+            tokio::io::AsyncWriteExt::write_all(&mut stream, request.as_bytes())
+                .await
+                .unwrap();
+            let mut response = Vec::new();
+            tokio::io::AsyncReadExt::read_to_end(&mut stream, &mut response)
+                .await
+                .unwrap();
+            String::from_utf8(response).unwrap()
+        };
+
+        assert!(get("").await.starts_with("HTTP/1.1 401 Unauthorized"));
+        let ok = get("Authorization: Bearer s3cret\r\n").await;
+        assert!(ok.starts_with("HTTP/1.1 200 OK"));
+        assert!(ok.contains("\"status\":\"ok\""));
+    }
 }
@@ -0,0 +1,130 @@
+//! Status stickers: an optional Signal sticker sent alongside the text when
+//! a request finishes, fails, runs out of budget or is rate limited.
+//!
+//! Configured under `stickers` in the `--config` file with the pack's id and
+//! key (both from the pack's `signal.art` link) and a sticker number per
+//! status. Statuses without a number get text only.
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// Outcomes that can carry a sticker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StickerStatus {
+    Done,
+    Error,
+    Budget,
+    RateLimited,
+}
+
+impl StickerStatus {
+    /// Status for a failed Claude run, from `AppError::category()`.
+    pub(crate) fn from_failure(category: &str) -> Self {
+        match category {
+            "budget" => Self::Budget,
+            "rate_limited" => Self::RateLimited,
+            _ => Self::Error,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct StickerConfig {
+    #[serde(default)]
+    pub(crate) pack_id: String,
+    /// Needed to install the pack on the bridge's account at startup.
+    #[serde(default)]
+    pub(crate) pack_key: Option<String>,
+    #[serde(default)]
+    pub(crate) done: Option<u32>,
+    #[serde(default)]
+    pub(crate) error: Option<u32>,
+    #[serde(default)]
+    pub(crate) budget: Option<u32>,
+    #[serde(default)]
+    pub(crate) rate_limited: Option<u32>,
+}
+
+impl StickerConfig {
+    pub(crate) fn is_set(&self) -> bool {
+        !self.pack_id.is_empty()
+    }
+
+    /// `<pack_id>:<sticker_id>` for `status`, as signal-cli expects it.
+    pub(crate) fn sticker_for(&self, status: StickerStatus) -> Option<String> {
+        if !self.is_set() {
+            return None;
+        }
+        let id = match status {
+            StickerStatus::Done => self.done,
+            StickerStatus::Error => self.error,
+            StickerStatus::Budget => self.budget,
+            StickerStatus::RateLimited => self.rate_limited,
+        }?;
+        Some(format!("{}:{id}", self.pack_id))
+    }
+}
+
+/// Install the configured pack on the bridge's Signal account so its
+/// stickers can be sent. Failures are logged; replies still go out as text.
+pub(crate) async fn install_pack(
+    http: &reqwest::Client,
+    api_url: &str,
+    account: &str,
+    config: &StickerConfig,
+) {
+    let Some(pack_key) = config.pack_key.as_deref().filter(|_| config.is_set()) else {
+        return;
+    };
+    let body = serde_json::json!({ "pack_id": config.pack_id, "pack_key": pack_key });
+    match http
+        .post(format!("{api_url}/v1/sticker-packs/{account}"))
+        .json(&body)
+        .send()
+        .await
+    {
+        Ok(resp) if resp.status().is_success() => {
+            info!(pack_id = %config.pack_id, "Installed sticker pack");
+        }
+        Ok(resp) => warn!(status = %resp.status(), "Sticker pack install failed"),
+        Err(e) => warn!("Sticker pack install failed: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sticker_for_status() {
+        let config = StickerConfig {
+            pack_id: "abc123".to_string(),
+            done: Some(0),
+            budget: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.sticker_for(StickerStatus::Done).as_deref(),
+            Some("abc123:0")
+        );
+        assert_eq!(
+            config.sticker_for(StickerStatus::Budget).as_deref(),
+            Some("abc123:4")
+        );
+        assert_eq!(config.sticker_for(StickerStatus::Error), None);
+        assert_eq!(
+            StickerConfig::default().sticker_for(StickerStatus::Done),
+            None
+        );
+    }
+
+    #[test]
+    fn test_status_from_failure_category() {
+        assert_eq!(StickerStatus::from_failure("budget"), StickerStatus::Budget);
+        assert_eq!(
+            StickerStatus::from_failure("rate_limited"),
+            StickerStatus::RateLimited
+        );
+        assert_eq!(StickerStatus::from_failure("timeout"), StickerStatus::Error);
+    }
+}
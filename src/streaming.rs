@@ -0,0 +1,299 @@
+//! Streaming runs (`--stream-progress`).
+//!
+//! `claude --output-format stream-json` prints one JSON event per line as it
+//! works. Each assistant message carries its token usage, so the running
+//! total can be shown while the run is still going ("…1.2k tokens, ~$0.08
+//! so far") and an expensive run stopped with `/stop`. The cost shown while
+//! running is estimated from list prices; the final `result` event has the
+//! exact figure.
+//!
+//! Every run streams, progress or not: the `result` event's usage adds up
+//! all of a run's tool turns, and only the last assistant message tells how
+//! big the session's context has grown.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::process::{Output, Stdio};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Running totals of a streaming run.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct StreamProgress {
+    pub(crate) tokens: u64,
+    /// Estimated from list prices.
+    pub(crate) cost_usd: f64,
+}
+
+/// Token counts from a `usage` block. Input is split three ways: fresh
+/// tokens, tokens written to the prompt cache, and tokens read back from it.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Usage {
+    pub(crate) input: u64,
+    pub(crate) cache_write: u64,
+    pub(crate) cache_read: u64,
+    pub(crate) output: u64,
+}
+
+impl Usage {
+    pub(crate) fn from_json(usage: &Value) -> Self {
+        let get = |key: &str| usage[key].as_u64().unwrap_or(0);
+        Self {
+            input: get("input_tokens"),
+            cache_write: get("cache_creation_input_tokens"),
+            cache_read: get("cache_read_input_tokens"),
+            output: get("output_tokens"),
+        }
+    }
+
+    pub(crate) fn add(&mut self, other: Usage) {
+        self.input += other.input;
+        self.cache_write += other.cache_write;
+        self.cache_read += other.cache_read;
+        self.output += other.output;
+    }
+
+    /// Share of input tokens served from the prompt cache.
+    pub(crate) fn cache_hit_rate(self) -> f64 {
+        let input = self.input + self.cache_write + self.cache_read;
+        if input == 0 {
+            return 0.0;
+        }
+        self.cache_read as f64 / input as f64
+    }
+
+    pub(crate) fn tokens(self) -> u64 {
+        self.input + self.cache_write + self.cache_read + self.output
+    }
+
+    /// What prompt caching saved against sending all input fresh, at list
+    /// prices: reads cost 0.9x input less, writes 0.25x more. Negative when
+    /// a run only wrote to the cache.
+    pub(crate) fn cache_savings_usd(self, model: &str) -> f64 {
+        let (input_price, _) = list_prices(model);
+        (self.cache_read as f64 * 0.9 - self.cache_write as f64 * 0.25) * input_price / 1_000_000.0
+    }
+}
+
+/// USD per million (input, output) tokens by model family. Cache writes
+/// cost 1.25x input and cache reads 0.1x.
+pub(crate) fn list_prices(model: &str) -> (f64, f64) {
+    let model = model.to_ascii_lowercase();
+    if model.contains("opus") {
+        (15.0, 75.0)
+    } else if model.contains("haiku") {
+        (0.8, 4.0)
+    } else {
+        (3.0, 15.0)
+    }
+}
+
+/// Token usage seen so far, per assistant message. claude repeats a
+/// message's usage on every content block it streams, so later sightings of
+/// the same message id replace earlier ones rather than adding to them.
+pub(crate) struct StreamTally {
+    model: String,
+    messages: HashMap<String, Usage>,
+    last: Option<Usage>,
+}
+
+impl StreamTally {
+    pub(crate) fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            messages: HashMap::new(),
+            last: None,
+        }
+    }
+
+    /// The latest assistant message's usage: the whole context it was
+    /// given, plus what it wrote.
+    pub(crate) fn last_turn(&self) -> Option<Usage> {
+        self.last
+    }
+
+    /// Take in one stream-json event; returns the new totals if it changed them.
+    pub(crate) fn observe(&mut self, event: &Value) -> Option<StreamProgress> {
+        if event["type"] != "assistant" {
+            return None;
+        }
+        let message = &event["message"];
+        let usage = Usage::from_json(message.get("usage")?);
+        let id = message["id"].as_str().unwrap_or_default().to_string();
+        self.last = Some(usage);
+        if self.messages.insert(id, usage) == Some(usage) {
+            return None;
+        }
+        Some(self.progress())
+    }
+
+    pub(crate) fn progress(&self) -> StreamProgress {
+        let (input_price, output_price) = list_prices(&self.model);
+        let mut progress = StreamProgress::default();
+        for usage in self.messages.values() {
+            progress.tokens += usage.tokens();
+            let input = usage.input as f64
+                + usage.cache_write as f64 * 1.25
+                + usage.cache_read as f64 * 0.1;
+            progress.cost_usd +=
+                (input * input_price + usage.output as f64 * output_price) / 1_000_000.0;
+        }
+        progress
+    }
+}
+
+/// `1234` -> `1.2k`, `2500000` -> `2.5M`.
+pub(crate) fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..=999 => tokens.to_string(),
+        1_000..=999_999 => format!("{:.1}k", tokens as f64 / 1_000.0),
+        _ => format!("{:.1}M", tokens as f64 / 1_000_000.0),
+    }
+}
+
+pub(crate) fn progress_message(progress: StreamProgress) -> String {
+    format!(
+        "…{} tokens, ~${:.2} so far. Send /stop to cancel.",
+        format_tokens(progress.tokens),
+        progress.cost_usd
+    )
+}
+
+/// Exact totals from claude's `result` event, once the run is over.
+pub(crate) fn final_message(tokens: Option<u64>, cost_usd: Option<f64>) -> String {
+    match (tokens, cost_usd) {
+        (Some(tokens), Some(cost)) => {
+            format!("Done: {} tokens, ${cost:.2}.", format_tokens(tokens))
+        }
+        (Some(tokens), None) => format!("Done: {} tokens.", format_tokens(tokens)),
+        (None, Some(cost)) => format!("Done: ${cost:.2}."),
+        (None, None) => "Done.".to_string(),
+    }
+}
+
+/// Run `cmd` (already set up for `--output-format stream-json`), sending
+/// running totals to `progress` if given. The returned stdout is the final
+/// `result` event, which reads like `--output-format json` output; if there
+/// is none, it's whatever non-JSON text claude printed. Also returns the last
+/// assistant message's usage.
+pub(crate) async fn run(
+    cmd: &mut Command,
+    model: &str,
+    progress: Option<&UnboundedSender<StreamProgress>>,
+) -> std::io::Result<(Output, Option<Usage>)> {
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_task = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf).await;
+        buf
+    });
+
+    let mut tally = StreamTally::new(model);
+    let mut result = None;
+    let mut other = String::new();
+    let mut lines = BufReader::new(stdout).lines();
+    while let Some(line) = lines.next_line().await? {
+        match serde_json::from_str::<Value>(&line) {
+            Ok(event) if event["type"] == "result" => result = Some(line),
+            Ok(event) => {
+                if let (Some(totals), Some(progress)) = (tally.observe(&event), progress) {
+                    let _ = progress.send(totals);
+                }
+            }
+            Err(_) => {
+                other.push_str(&line);
+                other.push('\n');
+            }
+        }
+    }
+    let status = child.wait().await?;
+    let output = Output {
+        status,
+        stdout: result.unwrap_or(other).into_bytes(),
+        stderr: stderr_task.await.unwrap_or_default(),
+    };
+    Ok((output, tally.last_turn()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assistant(id: &str, input: u64, cache_read: u64, output: u64) -> Value {
+        serde_json::json!({
+            "type": "assistant",
+            "message": {
+                "id": id,
+                "usage": {
+                    "input_tokens": input,
+                    "cache_read_input_tokens": cache_read,
+                    "output_tokens": output,
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn test_tally_counts_each_message_once() {
+        let mut tally = StreamTally::new("claude-sonnet-4-5");
+        assert_eq!(
+            tally.observe(&serde_json::json!({"type": "system", "subtype": "init"})),
+            None
+        );
+        let first = tally.observe(&assistant("msg_1", 1_000, 0, 200)).unwrap();
+        assert_eq!(first.tokens, 1_200);
+        // Same message again (next content block): replaced, not added
+        assert_eq!(tally.observe(&assistant("msg_1", 1_000, 0, 200)), None);
+        let second = tally
+            .observe(&assistant("msg_2", 100, 10_000, 300))
+            .unwrap();
+        assert_eq!(second.tokens, 11_600);
+        // The context is the last message's, not the sum of every turn.
+        assert_eq!(tally.last_turn().unwrap().tokens(), 10_400);
+        // 1,100 input plus 10,000 cache reads at a tenth of $3/M, 500 output at $15/M
+        assert!((second.cost_usd - 0.0138).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cache_savings_and_hit_rate() {
+        let usage = Usage {
+            input: 1_000,
+            cache_write: 4_000,
+            cache_read: 0,
+            output: 100,
+        };
+        // A run that only writes to the cache costs more than no caching.
+        assert!(usage.cache_savings_usd("sonnet") < 0.0);
+        let mut total = usage;
+        total.add(Usage {
+            cache_read: 5_000,
+            ..Usage::default()
+        });
+        assert_eq!(total.cache_hit_rate(), 0.5);
+        // 5k reads save 4.5k tokens' worth, 4k writes cost 1k tokens' worth.
+        assert!((total.cache_savings_usd("opus") - 3_500.0 * 15.0 / 1e6).abs() < 1e-9);
+        assert_eq!(Usage::default().cache_hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_progress_and_final_messages() {
+        assert_eq!(format_tokens(950), "950");
+        assert_eq!(format_tokens(1_234), "1.2k");
+        assert_eq!(format_tokens(2_500_000), "2.5M");
+        assert_eq!(
+            progress_message(StreamProgress {
+                tokens: 1_200,
+                cost_usd: 0.0812,
+            }),
+            "…1.2k tokens, ~$0.08 so far. Send /stop to cancel."
+        );
+        assert_eq!(
+            final_message(Some(12_345), Some(0.4109)),
+            "Done: 12.3k tokens, $0.41."
+        );
+        assert_eq!(final_message(None, None), "Done.");
+    }
+}
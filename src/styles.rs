@@ -0,0 +1,228 @@
+//! Markdown emphasis sent as Signal text styles.
+//!
+//! Signal shows bold, italic, strikethrough, monospace and spoiler text from
+//! style ranges sent alongside the message, not from markup. Outgoing
+//! messages have `**bold**`, `*italic*`, `~~struck~~`, `||spoiler||`,
+//! `` `code` `` and fenced code blocks turned into ranges over the text
+//! without the markers. Asterisks and underscores only count at word
+//! boundaries, so `2*3*4` and `snake_case_names` are left alone.
+
+/// A Signal text style.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Style {
+    Bold,
+    Italic,
+    Strikethrough,
+    Monospace,
+    Spoiler,
+}
+
+impl Style {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Bold => "BOLD",
+            Self::Italic => "ITALIC",
+            Self::Strikethrough => "STRIKETHROUGH",
+            Self::Monospace => "MONOSPACE",
+            Self::Spoiler => "SPOILER",
+        }
+    }
+}
+
+/// A styled range. Offsets count UTF-16 code units, as Signal's do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct TextStyle {
+    pub(crate) start: usize,
+    pub(crate) length: usize,
+    pub(crate) style: Style,
+}
+
+impl std::fmt::Display for TextStyle {
+    /// signal-cli's `start:length:STYLE`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.start, self.length, self.style.as_str())
+    }
+}
+
+/// Markers, longest first so `**` wins over `*`.
+const MARKERS: [(&str, Style); 7] = [
+    ("**", Style::Bold),
+    ("__", Style::Bold),
+    ("~~", Style::Strikethrough),
+    ("||", Style::Spoiler),
+    ("`", Style::Monospace),
+    ("*", Style::Italic),
+    ("_", Style::Italic),
+];
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// Whether `c` may sit just outside a marker.
+fn is_boundary(c: Option<char>) -> bool {
+    c.is_none_or(|c| c.is_whitespace() || c.is_ascii_punctuation())
+}
+
+/// Where the span opened by `marker` at the start of `rest` closes: the
+/// byte offset of its closing marker. Spans don't cross lines.
+fn closing(rest: &str, marker: &str) -> Option<usize> {
+    let body = &rest[marker.len()..];
+    let line = &body[..body.find('\n').unwrap_or(body.len())];
+    if line.starts_with(char::is_whitespace) || line.starts_with(marker) {
+        return None;
+    }
+    let code = marker == "`";
+    let mut from = 0;
+    while let Some(i) = line[from..].find(marker).map(|i| i + from) {
+        let before = line[..i].chars().next_back();
+        let after = line[i + marker.len()..].chars().next();
+        let closes = i > 0
+            && (code || before.is_some_and(|c| !c.is_whitespace()))
+            && (code || is_boundary(after))
+            && !line[i + marker.len()..].starts_with(marker);
+        if closes {
+            return Some(marker.len() + i);
+        }
+        from = i + marker.len();
+    }
+    None
+}
+
+/// A fenced code block at the start of `rest`: its contents and the length
+/// of the whole block, closing fence included.
+fn fenced_block(rest: &str) -> Option<(&str, usize)> {
+    let after_open = rest.strip_prefix("```")?;
+    let body_start = after_open.find('\n')? + 1;
+    let body = &after_open[body_start..];
+    let close = if body.starts_with("```") {
+        0
+    } else {
+        body.find("\n```")? + 1
+    };
+    let contents = body[..close].strip_suffix('\n').unwrap_or(&body[..close]);
+    let fence_end = body[close..].find('\n').unwrap_or(body.len() - close);
+    Some((contents, 3 + body_start + close + fence_end))
+}
+
+fn parse_into(text: &str, out: &mut String, styles: &mut Vec<TextStyle>) {
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let line_start = out.is_empty() || out.ends_with('\n');
+        if line_start {
+            if let Some((contents, len)) = fenced_block(rest) {
+                let start = utf16_len(out);
+                out.push_str(contents);
+                styles.push(TextStyle {
+                    start,
+                    length: utf16_len(contents),
+                    style: Style::Monospace,
+                });
+                rest = &rest[len..];
+                continue;
+            }
+        }
+        let opens = MARKERS.iter().find_map(|&(marker, style)| {
+            let may_open = marker == "`" || is_boundary(out.chars().next_back());
+            (rest.starts_with(marker) && may_open)
+                .then(|| closing(rest, marker).map(|end| (marker, style, end)))
+                .flatten()
+        });
+        match opens {
+            Some((marker, style, end)) => {
+                let inner = &rest[marker.len()..end];
+                let start = utf16_len(out);
+                if style == Style::Monospace {
+                    out.push_str(inner);
+                } else {
+                    parse_into(inner, out, styles);
+                }
+                styles.push(TextStyle {
+                    start,
+                    length: utf16_len(out) - start,
+                    style,
+                });
+                rest = &rest[end + marker.len()..];
+            }
+            None => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// `markdown` without its emphasis markers, and the styles they stood for.
+pub(crate) fn parse(markdown: &str) -> (String, Vec<TextStyle>) {
+    let mut text = String::new();
+    let mut styles = Vec::new();
+    parse_into(markdown, &mut text, &mut styles);
+    styles.sort_by_key(|s| s.start);
+    (text, styles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styled(markdown: &str) -> (String, Vec<String>) {
+        let (text, styles) = parse(markdown);
+        (text, styles.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn test_inline_styles() {
+        assert_eq!(
+            styled("Run `cargo test` **now**, *please*."),
+            (
+                "Run cargo test now, please.".to_string(),
+                vec![
+                    "4:10:MONOSPACE".to_string(),
+                    "15:3:BOLD".to_string(),
+                    "20:6:ITALIC".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            styled("The killer is ||the butler|| ~~not~~ me"),
+            (
+                "The killer is the butler not me".to_string(),
+                vec![
+                    "14:10:SPOILER".to_string(),
+                    "25:3:STRIKETHROUGH".to_string()
+                ]
+            )
+        );
+        // Nested, and offsets in UTF-16 units past an emoji.
+        assert_eq!(
+            styled("🎉 **bold *and* italic**").1,
+            vec!["3:15:BOLD".to_string(), "8:3:ITALIC".to_string()]
+        );
+        // Markup inside code stays as written.
+        assert_eq!(styled("`a*b*c`").0, "a*b*c");
+    }
+
+    #[test]
+    fn test_leaves_plain_text_alone() {
+        for text in [
+            "2*3*4 = 24",
+            "snake_case_name",
+            "* a bullet\n* another",
+            "a ** b",
+            "price: $5 * 2",
+            "unclosed **bold",
+            "**across\nlines**",
+        ] {
+            assert_eq!(styled(text), (text.to_string(), vec![]), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_fenced_block() {
+        let (text, styles) = styled("Try:\n```rust\nfn main() {}\n```\nDone.");
+        assert_eq!(text, "Try:\nfn main() {}\nDone.");
+        assert_eq!(styles, vec!["5:12:MONOSPACE".to_string()]);
+        // An unclosed fence is left as it is.
+        assert_eq!(styled("```\ncode").0, "```\ncode");
+    }
+}
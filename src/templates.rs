@@ -0,0 +1,155 @@
+//! Prompt templates (`/template`, `/t`).
+//!
+//! `/template add review Review the attached diff: {attachment}` saves a
+//! prompt; `/t review` sends it. Placeholders are filled in when the template
+//! is used, not when it's saved:
+//!
+//! - `{attachment}`: the file sent with the `/t` message, else the newest kept
+//!   attachment (`--attachment-retention-days`), which goes to Claude as well
+//! - `{file}`: the most recently changed file in the session's workspace
+//! - `{input}`: whatever follows the name; without it, that text is appended
+//!
+//! Templates live in the sender's memory database, next to their pins.
+
+use std::path::{Path, PathBuf};
+
+use crate::state::State;
+
+const ATTACHMENT: &str = "{attachment}";
+const FILE: &str = "{file}";
+const INPUT: &str = "{input}";
+
+/// What a template's placeholders stand for when it's used.
+#[derive(Default)]
+struct Values {
+    input: String,
+    attachment: Option<String>,
+    file: Option<String>,
+}
+
+/// `body` with its placeholders replaced, in one pass so a filename that
+/// looks like a placeholder stays as it is.
+fn fill(body: &str, values: &Values) -> Result<String, String> {
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let (value, len) = if tail.starts_with(ATTACHMENT) {
+            let name = values.attachment.as_deref().ok_or(
+                "This template needs an attachment. Send a file with /t, or send one first.",
+            )?;
+            (name, ATTACHMENT.len())
+        } else if tail.starts_with(FILE) {
+            let name = values
+                .file
+                .as_deref()
+                .ok_or("This template needs a workspace file, and there are none yet.")?;
+            (name, FILE.len())
+        } else if tail.starts_with(INPUT) {
+            (values.input.as_str(), INPUT.len())
+        } else {
+            ("{", 1)
+        };
+        out.push_str(value);
+        rest = &tail[len..];
+    }
+    out.push_str(rest);
+    if !body.contains(INPUT) && !values.input.is_empty() {
+        out.push_str("\n\n");
+        out.push_str(&values.input);
+    }
+    Ok(out)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// The workspace file changed last, relative to the workspace.
+fn newest_file(state: &State, sender: &str) -> Option<String> {
+    let session_id = state
+        .session_mgr
+        .sessions
+        .get(sender)
+        .map(|s| s.session_id.clone())?;
+    let dir = state.workdir_for(sender, &session_id);
+    crate::helpers::list_files(&dir)
+        .into_iter()
+        .filter_map(|(name, _)| {
+            let modified = std::fs::metadata(dir.join(&name)).ok()?.modified().ok()?;
+            Some((modified, name))
+        })
+        .max()
+        .map(|(_, name)| name)
+}
+
+/// Expand `/t <name> [text]` for `sender`: the prompt to run and the files
+/// to send with it, or what to tell them instead.
+pub(crate) fn expand(
+    state: &State,
+    sender: &str,
+    args: &str,
+    attachments: &[PathBuf],
+) -> Result<(String, Vec<PathBuf>), String> {
+    let (name, input) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    if crate::memory::lock::is_locked(sender) {
+        return Err(crate::memory::lock::LOCKED_NOTICE.to_string());
+    }
+    let conn = crate::memory::open_memory_db(sender)
+        .map_err(|_| "Failed to access memory.".to_string())?;
+    let body = crate::memory::messages::get_template(&conn, name)
+        .ok_or_else(|| format!("No template named '{name}'. Use /templates to see yours."))?;
+    let mut files = attachments.to_vec();
+    let attachment = if !body.contains(ATTACHMENT) {
+        None
+    } else if let Some(path) = attachments.first() {
+        Some(file_name(path))
+    } else {
+        crate::attachments::latest(sender).map(|(filename, path)| {
+            files.push(path);
+            filename
+        })
+    };
+    let values = Values {
+        input: input.trim().to_string(),
+        attachment,
+        file: body
+            .contains(FILE)
+            .then(|| newest_file(state, sender))
+            .flatten(),
+    };
+    fill(&body, &values).map(|prompt| (prompt, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_placeholders() {
+        let values = Values {
+            input: "focus on errors".to_string(),
+            attachment: Some("fix.diff".to_string()),
+            file: Some("src/{input}.rs".to_string()),
+        };
+        assert_eq!(
+            fill("Review the attached diff: {attachment}", &values).unwrap(),
+            "Review the attached diff: fix.diff\n\nfocus on errors"
+        );
+        assert_eq!(
+            fill("Check {file}, {input}. {braces}", &values).unwrap(),
+            "Check src/{input}.rs, focus on errors. {braces}"
+        );
+        let none = Values::default();
+        assert_eq!(fill("Summarize {input}", &none).unwrap(), "Summarize ");
+        assert!(fill("Review {attachment}", &none)
+            .unwrap_err()
+            .contains("needs an attachment"));
+        assert!(fill("Explain {file}", &none)
+            .unwrap_err()
+            .contains("workspace file"));
+    }
+}
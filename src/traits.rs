@@ -1,17 +1,33 @@
 use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::Value;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::process::Command;
-use tracing::{debug, error};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, error, warn};
 
 use crate::error::AppError;
+use crate::failover::ApiEndpoints;
+use crate::keys::{is_limit_error, KeyPool};
+use crate::pacer::SendPacer;
 use crate::signal::AttachmentInfo;
+use crate::streaming::StreamProgress;
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub(crate) trait SignalApi: Send + Sync {
     async fn send_msg(&self, recipient: &str, message: &str) -> Result<(), AppError>;
+    /// Send `message` with bold, italic, monospace and similar ranges over
+    /// it. Only Signal has text styles; other transports send it plain.
+    async fn send_styled(
+        &self,
+        recipient: &str,
+        message: &str,
+        _styles: &[crate::styles::TextStyle],
+    ) -> Result<(), AppError> {
+        self.send_msg(recipient, message).await
+    }
     async fn set_typing(&self, recipient: &str, typing: bool) -> Result<(), AppError>;
     async fn download_attachment(&self, attachment: &AttachmentInfo) -> Result<PathBuf, AppError>;
     async fn send_attachment(
@@ -21,6 +37,136 @@ pub(crate) trait SignalApi: Send + Sync {
         content_type: &str,
         filename: &str,
     ) -> Result<(), AppError>;
+    /// Send a sticker (`<pack_id>:<sticker_id>`). Only Signal has stickers;
+    /// other transports skip them.
+    async fn send_sticker(&self, _recipient: &str, _sticker: &str) -> Result<(), AppError> {
+        Ok(())
+    }
+    /// React with `emoji` to the message `target_author` sent at
+    /// `target_timestamp`, replacing any earlier reaction. Signal only.
+    async fn send_reaction(
+        &self,
+        _recipient: &str,
+        _emoji: &str,
+        _target_author: &str,
+        _target_timestamp: i64,
+    ) -> Result<(), AppError> {
+        Ok(())
+    }
+    /// Mark the message `sender` sent at `timestamp` as read. Signal only.
+    async fn send_receipt(&self, _sender: &str, _timestamp: i64) -> Result<(), AppError> {
+        Ok(())
+    }
+    /// Trust `number`'s current identity after its safety number changed.
+    /// Signal only.
+    async fn trust_identity(&self, _number: &str) -> Result<(), AppError> {
+        Err(AppError::Transport(
+            "Identities can only be trusted over Signal".to_string(),
+        ))
+    }
+    /// Set the account's profile "about" text, and its name unless `name`
+    /// is empty. Signal only.
+    async fn set_profile(&self, _name: &str, _about: &str) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+/// Output of one `claude -p` run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ClaudeReply {
+    pub(crate) text: String,
+    pub(crate) cost_usd: Option<f64>,
+    /// Approximate session context size after this turn, from the last
+    /// assistant message's usage.
+    pub(crate) context_tokens: Option<u64>,
+    /// Token counts, including prompt cache reads and writes.
+    pub(crate) usage: Option<crate::streaming::Usage>,
+}
+
+#[cfg(test)]
+impl ClaudeReply {
+    pub(crate) fn new(text: &str, cost_usd: Option<f64>) -> Self {
+        Self {
+            text: text.to_string(),
+            cost_usd,
+            context_tokens: None,
+            usage: None,
+        }
+    }
+}
+
+/// Settings for one run (`/set temperature`, `/set max-tokens`, guest
+/// restrictions). Runners apply what they support and ignore the rest.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct RunOptions {
+    pub(crate) temperature: Option<f64>,
+    pub(crate) max_tokens: Option<u32>,
+    /// Answer only: no commands, file edits or web fetches (invited guests).
+    pub(crate) ask_only: bool,
+}
+
+impl RunOptions {
+    /// Environment for a `claude` CLI run. The CLI can cap output tokens
+    /// but has no temperature setting.
+    pub(crate) fn cli_env(&self) -> Vec<(&'static str, String)> {
+        self.max_tokens
+            .map(|n| ("CLAUDE_CODE_MAX_OUTPUT_TOKENS", n.to_string()))
+            .into_iter()
+            .collect()
+    }
+
+    /// Extra `claude` flags for a run.
+    pub(crate) fn cli_args(&self) -> Vec<String> {
+        if !self.ask_only {
+            return Vec::new();
+        }
+        crate::constants::ASK_ONLY_DENIED_TOOLS
+            .iter()
+            .flat_map(|tool| ["--disallowedTools".to_string(), tool.to_string()])
+            .collect()
+    }
+}
+
+/// Parse `claude --output-format json` stdout, falling back to plain text.
+pub(crate) fn parse_claude_output(stdout: &str) -> ClaudeReply {
+    let parsed: Value = serde_json::from_str(stdout)
+        .unwrap_or_else(|_| serde_json::json!({"result": stdout.trim()}));
+    let text = parsed["result"]
+        .as_str()
+        .unwrap_or_else(|| stdout.trim())
+        .to_string();
+    let cost_usd = parsed["cost_usd"]
+        .as_f64()
+        .or_else(|| parsed["total_cost_usd"].as_f64());
+    let usage = parsed["usage"]
+        .is_object()
+        .then(|| crate::streaming::Usage::from_json(&parsed["usage"]));
+    // The result's usage adds up every turn of the run, so it's the
+    // context's size only when there was a single turn.
+    let single_turn = parsed["num_turns"].as_u64().is_none_or(|n| n <= 1);
+    ClaudeReply {
+        text,
+        cost_usd,
+        context_tokens: usage.filter(|_| single_turn).map(|u| u.tokens()),
+        usage,
+    }
+}
+
+/// Whether `claude` output says it has no record of the requested session.
+pub(crate) fn is_unknown_session(output: &str) -> bool {
+    let lower = output.to_lowercase();
+    lower.contains("no conversation found")
+        || (lower.contains("session")
+            && (lower.contains("not found") || lower.contains("does not exist")))
+}
+
+/// A successful run that reports a different session id started from
+/// scratch, so the conversation so far is gone.
+fn switched_session(stdout: &str, session_id: &str) -> bool {
+    serde_json::from_str::<Value>(stdout)
+        .ok()
+        .and_then(|v| v["session_id"].as_str().map(|id| id != session_id))
+        .unwrap_or(false)
 }
 
 #[cfg_attr(test, mockall::automock)]
@@ -33,48 +179,179 @@ pub(crate) trait ClaudeRunner: Send + Sync {
         session_id: &str,
         model: &str,
         files: &[PathBuf],
-        sender: &str,
+        work_dir: &Path,
         max_budget: f64,
         system_prompt: &str,
-    ) -> Result<(String, Option<f64>), AppError>;
+        options: &RunOptions,
+    ) -> Result<ClaudeReply, AppError>;
+
+    /// `run_claude`, reporting running token and cost totals on `progress`
+    /// while claude works. Runners that can't stream just run.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_claude_streaming(
+        &self,
+        prompt: &str,
+        session_id: &str,
+        model: &str,
+        files: &[PathBuf],
+        work_dir: &Path,
+        max_budget: f64,
+        system_prompt: &str,
+        options: &RunOptions,
+        _progress: UnboundedSender<StreamProgress>,
+    ) -> Result<ClaudeReply, AppError> {
+        self.run_claude(
+            prompt,
+            session_id,
+            model,
+            files,
+            work_dir,
+            max_budget,
+            system_prompt,
+            options,
+        )
+        .await
+    }
 
     async fn summarize_session(&self, session_id: &str, model: &str) -> Option<String>;
 }
 
 pub(crate) struct SignalApiImpl {
     pub(crate) http: Client,
-    pub(crate) api_url: String,
+    /// signal-cli-api base URL(s); switches to a standby on repeated failures.
+    pub(crate) api: Arc<ApiEndpoints>,
     pub(crate) account: String,
+    /// Spaces out everything that makes Signal send.
+    pub(crate) pacer: Arc<SendPacer>,
+}
+
+impl SignalApiImpl {
+    /// Send `request` in its turn, letting the pacer back off on 429/5xx.
+    async fn paced(&self, request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+        self.pacer.wait().await;
+        let result = request.send().await;
+        if let Ok(resp) = &result {
+            self.pacer.record(resp.status());
+        }
+        result
+    }
+
+    /// POST a `/v2/send` body, counting the outcome towards failover. A 429
+    /// means nothing was sent, so it's tried again after the backoff.
+    async fn post_send(&self, body: &Value) -> Result<reqwest::Response, AppError> {
+        let mut attempt = 0;
+        loop {
+            let base = self.api.current();
+            let result = self
+                .paced(self.http.post(format!("{base}/v2/send")).json(body))
+                .await;
+            match &result {
+                Ok(resp) if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    if attempt < crate::constants::SEND_RETRIES {
+                        attempt += 1;
+                        continue;
+                    }
+                }
+                Ok(resp) if !resp.status().is_server_error() => self.api.record_success(),
+                _ => {
+                    self.api.record_failure(&base);
+                }
+            }
+            return Ok(result?);
+        }
+    }
+}
+
+/// Fresh temp path for a downloaded attachment, with an extension taken from
+/// the filename or, failing that, the content type. An id that isn't a plain
+/// name (iMessage ids are file paths) is hashed so it can't leave `TMP_DIR`.
+pub(crate) fn attachment_tmp_path(attachment: &AttachmentInfo) -> PathBuf {
+    let tmp_dir = PathBuf::from(crate::constants::TMP_DIR);
+    let _ = std::fs::create_dir_all(&tmp_dir);
+
+    let plain = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with('.')
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    let stem = if plain(&attachment.id) {
+        attachment.id.clone()
+    } else {
+        format!("{:016x}", crate::helpers::hash_message(&attachment.id))
+    };
+    let ext = attachment
+        .filename
+        .as_deref()
+        .and_then(|f| f.rsplit_once('.'))
+        .map(|(_, ext)| ext)
+        .filter(|ext| plain(ext))
+        .or(match attachment.content_type.as_str() {
+            "image/jpeg" => Some("jpg"),
+            "image/png" => Some("png"),
+            "image/gif" => Some("gif"),
+            "image/webp" => Some("webp"),
+            "application/pdf" => Some("pdf"),
+            "text/plain" => Some("txt"),
+            "audio/aac" => Some("aac"),
+            "audio/ogg" => Some("ogg"),
+            "audio/mpeg" => Some("mp3"),
+            "audio/mp4" => Some("m4a"),
+            "audio/x-caf" => Some("caf"),
+            _ => None,
+        })
+        .unwrap_or("bin");
+
+    tmp_dir.join(format!("{}_{}.{}", stem, uuid::Uuid::new_v4(), ext))
 }
 
 #[async_trait]
 impl SignalApi for SignalApiImpl {
     async fn send_msg(&self, recipient: &str, message: &str) -> Result<(), AppError> {
-        let url = format!("{}/v2/send", self.api_url);
-        let body = serde_json::json!({
+        self.send_styled(recipient, message, &[]).await
+    }
+
+    async fn send_styled(
+        &self,
+        recipient: &str,
+        message: &str,
+        styles: &[crate::styles::TextStyle],
+    ) -> Result<(), AppError> {
+        let mut body = serde_json::json!({
             "message": message,
             "number": self.account,
             "recipients": [recipient],
         });
+        if !styles.is_empty() {
+            let styles: Vec<String> = styles.iter().map(|s| s.to_string()).collect();
+            body["text_style"] = serde_json::json!(styles);
+        }
 
-        let resp = self.http.post(&url).json(&body).send().await?;
+        let resp = self.post_send(&body).await?;
         if !resp.status().is_success() {
             let status = resp.status();
             let body = resp.text().await.unwrap_or_default();
             error!(status = %status, body = %body, "Signal send failed");
-            return Err(AppError::Signal(format!("Send failed: {status}")));
+            return Err(AppError::Signal(format!(
+                "Send failed: {status}: {}",
+                crate::helpers::truncate(body.trim(), 200)
+            )));
         }
         Ok(())
     }
 
     async fn set_typing(&self, recipient: &str, typing: bool) -> Result<(), AppError> {
-        let url = format!("{}/v1/typing-indicator/{}", self.api_url, self.account);
+        let url = format!(
+            "{}/v1/typing-indicator/{}",
+            self.api.current(),
+            self.account
+        );
         let body = serde_json::json!({ "recipient": recipient });
 
         let resp = if typing {
-            self.http.put(&url).json(&body).send().await?
+            self.paced(self.http.put(&url).json(&body)).await?
         } else {
-            self.http.delete(&url).json(&body).send().await?
+            self.paced(self.http.delete(&url).json(&body)).await?
         };
 
         if !resp.status().is_success() {
@@ -84,37 +361,8 @@ impl SignalApi for SignalApiImpl {
     }
 
     async fn download_attachment(&self, attachment: &AttachmentInfo) -> Result<PathBuf, AppError> {
-        let tmp_dir = PathBuf::from(crate::constants::TMP_DIR);
-        let _ = std::fs::create_dir_all(&tmp_dir);
-
-        let ext = attachment
-            .filename
-            .as_deref()
-            .and_then(|f| f.rsplit('.').next())
-            .or(match attachment.content_type.as_str() {
-                "image/jpeg" => Some("jpg"),
-                "image/png" => Some("png"),
-                "image/gif" => Some("gif"),
-                "image/webp" => Some("webp"),
-                "application/pdf" => Some("pdf"),
-                "text/plain" => Some("txt"),
-                "audio/aac" => Some("aac"),
-                "audio/ogg" => Some("ogg"),
-                "audio/mpeg" => Some("mp3"),
-                "audio/mp4" => Some("m4a"),
-                "audio/x-caf" => Some("caf"),
-                _ => None,
-            })
-            .unwrap_or("bin");
-
-        let path = tmp_dir.join(format!(
-            "{}_{}.{}",
-            attachment.id,
-            uuid::Uuid::new_v4(),
-            ext
-        ));
-
-        let url = format!("{}/v1/attachments/{}", self.api_url, attachment.id);
+        let path = attachment_tmp_path(attachment);
+        let url = format!("{}/v1/attachments/{}", self.api.current(), attachment.id);
         let resp = self.http.get(&url).send().await?;
         if !resp.status().is_success() {
             return Err(format!(
@@ -143,7 +391,6 @@ impl SignalApi for SignalApiImpl {
     ) -> Result<(), AppError> {
         use base64::Engine;
         let b64 = base64::engine::general_purpose::STANDARD.encode(data);
-        let url = format!("{}/v2/send", self.api_url);
         let body = serde_json::json!({
             "message": "",
             "number": self.account,
@@ -153,7 +400,7 @@ impl SignalApi for SignalApiImpl {
             ],
         });
 
-        let resp = self.http.post(&url).json(&body).send().await?;
+        let resp = self.post_send(&body).await?;
         if !resp.status().is_success() {
             let status = resp.status();
             let body_text = resp.text().await.unwrap_or_default();
@@ -164,33 +411,148 @@ impl SignalApi for SignalApiImpl {
         }
         Ok(())
     }
+
+    async fn send_sticker(&self, recipient: &str, sticker: &str) -> Result<(), AppError> {
+        let body = serde_json::json!({
+            "message": "",
+            "number": self.account,
+            "recipients": [recipient],
+            "sticker": sticker,
+        });
+
+        let resp = self.post_send(&body).await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body_text = resp.text().await.unwrap_or_default();
+            error!(status = %status, body = %body_text, "Sticker send failed");
+            return Err(AppError::Signal(format!("Sticker send failed: {status}")));
+        }
+        Ok(())
+    }
+
+    async fn send_reaction(
+        &self,
+        recipient: &str,
+        emoji: &str,
+        target_author: &str,
+        target_timestamp: i64,
+    ) -> Result<(), AppError> {
+        let url = format!("{}/v1/reactions/{}", self.api.current(), self.account);
+        let body = serde_json::json!({
+            "reaction": emoji,
+            "recipient": recipient,
+            "target_author": target_author,
+            "timestamp": target_timestamp,
+        });
+
+        let resp = self.paced(self.http.post(&url).json(&body)).await?;
+        if !resp.status().is_success() {
+            return Err(AppError::Signal(format!(
+                "Reaction failed: {}",
+                resp.status()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn send_receipt(&self, sender: &str, timestamp: i64) -> Result<(), AppError> {
+        let url = format!("{}/v1/receipts/{}", self.api.current(), self.account);
+        let body = serde_json::json!({
+            "receipt_type": "read",
+            "recipient": sender,
+            "timestamp": timestamp,
+        });
+        let resp = self.paced(self.http.post(&url).json(&body)).await?;
+        if !resp.status().is_success() {
+            return Err(AppError::Signal(format!(
+                "Receipt failed: {}",
+                resp.status()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn trust_identity(&self, number: &str) -> Result<(), AppError> {
+        let url = format!(
+            "{}/v1/identities/{}/trust/{number}",
+            self.api.current(),
+            self.account
+        );
+        let body = serde_json::json!({ "trust_all_known_keys": true });
+        let resp = self.http.put(&url).json(&body).send().await?;
+        if !resp.status().is_success() {
+            return Err(AppError::Signal(format!("Trust failed: {}", resp.status())));
+        }
+        Ok(())
+    }
+
+    async fn set_profile(&self, name: &str, about: &str) -> Result<(), AppError> {
+        let url = format!("{}/v1/profiles/{}", self.api.current(), self.account);
+        let mut body = serde_json::json!({ "about": about });
+        if !name.is_empty() {
+            body["name"] = Value::from(name);
+        }
+        let resp = self.http.put(&url).json(&body).send().await?;
+        if !resp.status().is_success() {
+            return Err(AppError::Signal(format!(
+                "Profile update failed: {}",
+                resp.status()
+            )));
+        }
+        Ok(())
+    }
 }
 
-pub(crate) struct ClaudeRunnerImpl;
+pub(crate) struct ClaudeRunnerImpl {
+    /// Extra environment for the `claude` process (proxy, CA bundle).
+    pub(crate) env: Vec<(&'static str, String)>,
+    /// Anthropic credentials to rotate through (empty = claude's own login).
+    pub(crate) keys: Arc<KeyPool>,
+    /// Tool policies of bound projects (`.ccchat.toml`).
+    pub(crate) policies: Arc<crate::policy::ToolPolicies>,
+}
+
+impl ClaudeRunnerImpl {
+    /// Environment for the next run, and the credential it uses.
+    fn run_env(&self) -> (Option<usize>, Vec<(&'static str, String)>) {
+        let mut env = self.env.clone();
+        match self.keys.current(crate::helpers::epoch_now() as u64) {
+            Some((idx, cred)) => {
+                env.extend(cred.env.iter().cloned());
+                (Some(idx), env)
+            }
+            None => (None, env),
+        }
+    }
 
-#[async_trait]
-impl ClaudeRunner for ClaudeRunnerImpl {
     #[allow(clippy::too_many_arguments)]
-    async fn run_claude(
+    async fn run_once(
         &self,
         prompt: &str,
         session_id: &str,
         model: &str,
         files: &[PathBuf],
-        sender: &str,
+        work_dir: &Path,
         max_budget: f64,
         system_prompt: &str,
-    ) -> Result<(String, Option<f64>), AppError> {
-        let work_dir = crate::helpers::isolated_workdir(sender);
-        std::fs::create_dir_all(&work_dir)?;
-
+        options: &RunOptions,
+        env: Vec<(&'static str, String)>,
+        progress: Option<&UnboundedSender<StreamProgress>>,
+    ) -> Result<ClaudeReply, AppError> {
+        let mut tool_rules = self
+            .policies
+            .claude_args(work_dir)
+            .map_err(AppError::Other)?;
+        tool_rules.extend(options.cli_args());
         let mut cmd = Command::new("claude");
         cmd.arg("-p")
             .arg(prompt)
             .arg("--session-id")
             .arg(session_id)
+            // stream-json needs --verbose in print mode
             .arg("--output-format")
-            .arg("json")
+            .arg("stream-json")
+            .arg("--verbose")
             .arg("--model")
             .arg(model)
             .arg("--max-budget-usd")
@@ -198,35 +560,179 @@ impl ClaudeRunner for ClaudeRunnerImpl {
             .arg("--append-system-prompt")
             .arg(system_prompt)
             .arg("--no-session-persistence")
-            .current_dir(&work_dir)
-            .env_remove("CLAUDE_CODE_ENTRYPOINT");
+            .args(tool_rules)
+            .current_dir(work_dir)
+            .env_remove("CLAUDE_CODE_ENTRYPOINT")
+            .envs(env)
+            // A run dropped by /stop takes claude down with it
+            .kill_on_drop(true);
         for file in files {
             cmd.arg("--file").arg(file);
         }
-        let output = cmd.output().await?;
+        let (output, last_turn) = crate::streaming::run(&mut cmd, model, progress).await?;
 
+        if let Some(partial) = budget_exceeded(&String::from_utf8_lossy(&output.stdout)) {
+            return Err(AppError::BudgetExceeded(partial));
+        }
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            if is_unknown_session(&stderr) || is_unknown_session(&stdout) {
+                return Err(AppError::UnknownSession(session_id.to_string()));
+            }
+            let detail = if stderr.trim().is_empty() {
+                parse_claude_output(&stdout).text
+            } else {
+                stderr.to_string()
+            };
             return Err(AppError::Claude(format!(
-                "claude exited with {}: {stderr}",
+                "claude exited with {}: {detail}",
                 output.status
             )));
         }
 
         let stdout = String::from_utf8(output.stdout)?;
-        let parsed: Value = serde_json::from_str(&stdout)
-            .unwrap_or_else(|_| serde_json::json!({"result": stdout.trim()}));
+        if switched_session(&stdout, session_id) {
+            return Err(AppError::UnknownSession(session_id.to_string()));
+        }
+        if let Some(limit) = limit_error(&stdout) {
+            return Err(AppError::Claude(limit));
+        }
+        let mut reply = parse_claude_output(&stdout);
+        if let Some(turn) = last_turn {
+            reply.context_tokens = Some(turn.tokens());
+        }
+        Ok(reply)
+    }
+}
 
-        let result = parsed["result"]
-            .as_str()
-            .unwrap_or_else(|| stdout.trim())
-            .to_string();
+/// Error text of a run that "succeeded" only to report a rate limit or
+/// usage quota (`is_error` set in the JSON result).
+fn limit_error(stdout: &str) -> Option<String> {
+    let parsed: Value = serde_json::from_str(stdout).ok()?;
+    let result = parsed["result"].as_str()?;
+    (parsed["is_error"].as_bool() == Some(true) && is_limit_error(result))
+        .then(|| result.to_string())
+}
+
+/// The partial reply of a run that stopped at `--max-budget-usd`. The
+/// result may have no text when the budget ran out mid tool use.
+fn budget_exceeded(stdout: &str) -> Option<ClaudeReply> {
+    let parsed: Value = serde_json::from_str(stdout).ok()?;
+    if parsed["subtype"].as_str() != Some("error_max_budget_usd") {
+        return None;
+    }
+    let mut reply = parse_claude_output(stdout);
+    if parsed["result"].as_str().is_none() {
+        reply.text.clear();
+    }
+    Some(reply)
+}
+
+impl ClaudeRunnerImpl {
+    #[allow(clippy::too_many_arguments)]
+    async fn run_with_failover(
+        &self,
+        prompt: &str,
+        session_id: &str,
+        model: &str,
+        files: &[PathBuf],
+        work_dir: &Path,
+        max_budget: f64,
+        system_prompt: &str,
+        options: &RunOptions,
+        progress: Option<&UnboundedSender<StreamProgress>>,
+    ) -> Result<ClaudeReply, AppError> {
+        std::fs::create_dir_all(work_dir)?;
 
-        let cost = parsed["cost_usd"]
-            .as_f64()
-            .or_else(|| parsed["total_cost_usd"].as_f64());
+        // Each configured account gets one try before the error is returned.
+        let attempts = self.keys.len().max(1);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let (idx, mut env) = self.run_env();
+            env.extend(options.cli_env());
+            let result = self
+                .run_once(
+                    prompt,
+                    session_id,
+                    model,
+                    files,
+                    work_dir,
+                    max_budget,
+                    system_prompt,
+                    options,
+                    env,
+                    progress,
+                )
+                .await;
+            match (&result, idx) {
+                (Err(e), Some(idx)) if is_limit_error(&e.to_string()) => {
+                    warn!("Claude account hit a limit, failing over: {e}");
+                    self.keys.fail_over(idx, crate::helpers::epoch_now() as u64);
+                    if attempt == attempts {
+                        return result;
+                    }
+                }
+                _ => return result,
+            }
+        }
+    }
+}
 
-        Ok((result, cost))
+#[async_trait]
+impl ClaudeRunner for ClaudeRunnerImpl {
+    #[allow(clippy::too_many_arguments)]
+    async fn run_claude(
+        &self,
+        prompt: &str,
+        session_id: &str,
+        model: &str,
+        files: &[PathBuf],
+        work_dir: &Path,
+        max_budget: f64,
+        system_prompt: &str,
+        options: &RunOptions,
+    ) -> Result<ClaudeReply, AppError> {
+        self.run_with_failover(
+            prompt,
+            session_id,
+            model,
+            files,
+            work_dir,
+            max_budget,
+            system_prompt,
+            options,
+            None,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_claude_streaming(
+        &self,
+        prompt: &str,
+        session_id: &str,
+        model: &str,
+        files: &[PathBuf],
+        work_dir: &Path,
+        max_budget: f64,
+        system_prompt: &str,
+        options: &RunOptions,
+        progress: UnboundedSender<StreamProgress>,
+    ) -> Result<ClaudeReply, AppError> {
+        self.run_with_failover(
+            prompt,
+            session_id,
+            model,
+            files,
+            work_dir,
+            max_budget,
+            system_prompt,
+            options,
+            Some(&progress),
+        )
+        .await
     }
 
     async fn summarize_session(&self, session_id: &str, model: &str) -> Option<String> {
@@ -245,6 +751,7 @@ impl ClaudeRunner for ClaudeRunnerImpl {
             .arg(crate::NO_MEMORY_PROMPT)
             .arg("--no-session-persistence")
             .env_remove("CLAUDE_CODE_ENTRYPOINT")
+            .envs(self.run_env().1)
             .output()
             .await
             .ok()?;
@@ -259,6 +766,39 @@ impl ClaudeRunner for ClaudeRunnerImpl {
     }
 }
 
+/// Stand-in runner for `--dry-run`: echoes the prompt back without starting
+/// `claude`, so the Signal side can be exercised end-to-end at no cost.
+pub(crate) struct EchoRunner;
+
+#[async_trait]
+impl ClaudeRunner for EchoRunner {
+    async fn run_claude(
+        &self,
+        prompt: &str,
+        _session_id: &str,
+        model: &str,
+        files: &[PathBuf],
+        _work_dir: &Path,
+        _max_budget: f64,
+        _system_prompt: &str,
+        _options: &RunOptions,
+    ) -> Result<ClaudeReply, AppError> {
+        Ok(ClaudeReply {
+            text: format!(
+                "[dry run] model {model}, {} attachment(s)\n\n{prompt}",
+                files.len()
+            ),
+            cost_usd: Some(0.0),
+            context_tokens: None,
+            usage: None,
+        })
+    }
+
+    async fn summarize_session(&self, _session_id: &str, _model: &str) -> Option<String> {
+        Some("[dry run] session summary".to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -276,8 +816,9 @@ mod tests {
             .await;
         let api = SignalApiImpl {
             http: Client::new(),
-            api_url: server.uri(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
             account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
         };
         (server, api)
     }
@@ -298,12 +839,65 @@ mod tests {
             .await;
         let api = SignalApiImpl {
             http: Client::new(),
-            api_url: server.uri(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
             account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
         };
         assert!(api.send_msg("+recipient", "hello").await.is_err());
     }
 
+    #[tokio::test]
+    async fn test_signal_api_send_styled() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/v2/send"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "message": "bold move",
+                "text_style": ["0:4:BOLD"],
+            })))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let api = SignalApiImpl {
+            http: Client::new(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
+            account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
+        };
+        let (text, styles) = crate::styles::parse("**bold** move");
+        assert!(api.send_styled("+recipient", &text, &styles).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_signal_api_send_retries_after_429() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/v2/send"))
+            .respond_with(wiremock::ResponseTemplate::new(429))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/v2/send"))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let api = SignalApiImpl {
+            http: Client::new(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
+            account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
+        };
+        let start = std::time::Instant::now();
+        assert!(api.send_msg("+recipient", "hello").await.is_ok());
+        assert!(start.elapsed() >= std::time::Duration::from_millis(500));
+        // The 429 widened the spacing; the success after it narrowed it again
+        assert!(api.pacer.backoff() < std::time::Duration::from_millis(500));
+    }
+
     #[tokio::test]
     async fn test_signal_api_set_typing_on() {
         let (_server, api) = setup_wiremock("PUT", "/v1/typing-indicator/+1234567890", 204).await;
@@ -329,14 +923,16 @@ mod tests {
             .await;
         let api = SignalApiImpl {
             http: Client::new(),
-            api_url: server.uri(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
             account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
         };
 
         let att = AttachmentInfo {
             id: "att123".to_string(),
             content_type: "image/png".to_string(),
             filename: Some("photo.png".to_string()),
+            size: None,
             voice_note: false,
         };
 
@@ -353,6 +949,7 @@ mod tests {
             id: "att404".to_string(),
             content_type: "image/jpeg".to_string(),
             filename: None,
+            size: None,
             voice_note: false,
         };
         assert!(api.download_attachment(&att).await.is_err());
@@ -368,8 +965,9 @@ mod tests {
             .await;
         let api = SignalApiImpl {
             http: Client::new(),
-            api_url: server.uri(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
             account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
         };
         let err = api.send_msg("+recipient", "hello").await.unwrap_err();
         assert!(matches!(err, AppError::Signal(_)));
@@ -402,8 +1000,9 @@ mod tests {
             .await;
         let api = SignalApiImpl {
             http: Client::new(),
-            api_url: server.uri(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
             account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
         };
 
         for i in 1..=3 {
@@ -424,6 +1023,48 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_signal_api_send_sticker() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::path("/v2/send"))
+            .and(wiremock::matchers::body_partial_json(
+                serde_json::json!({ "recipients": ["+recipient"], "sticker": "abc123:2" }),
+            ))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let api = SignalApiImpl {
+            http: Client::new(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
+            account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
+        };
+        assert!(api.send_sticker("+recipient", "abc123:2").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_signal_api_set_profile() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("PUT"))
+            .and(wiremock::matchers::path("/v1/profiles/+1234567890"))
+            .and(wiremock::matchers::body_json(
+                serde_json::json!({ "about": "🔴 offline" }),
+            ))
+            .respond_with(wiremock::ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let api = SignalApiImpl {
+            http: Client::new(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
+            account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
+        };
+        assert!(api.set_profile("", "🔴 offline").await.is_ok());
+    }
+
     #[tokio::test]
     async fn test_signal_api_send_attachment_failure() {
         let server = wiremock::MockServer::start().await;
@@ -434,12 +1075,100 @@ mod tests {
             .await;
         let api = SignalApiImpl {
             http: Client::new(),
-            api_url: server.uri(),
+            api: Arc::new(ApiEndpoints::single(server.uri())),
             account: "+1234567890".to_string(),
+            pacer: Arc::new(SendPacer::unpaced()),
         };
         let result = api
             .send_attachment("+recipient", b"data", "image/png", "out.png")
             .await;
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_claude_output_with_usage() {
+        let stdout = r#"{"result":"hi","total_cost_usd":0.02,"usage":{"input_tokens":10,"cache_creation_input_tokens":200,"cache_read_input_tokens":5000,"output_tokens":40}}"#;
+        let reply = parse_claude_output(stdout);
+        assert_eq!(reply.text, "hi");
+        assert_eq!(reply.cost_usd, Some(0.02));
+        assert_eq!(reply.context_tokens, Some(5250));
+        let usage = reply.usage.unwrap();
+        assert_eq!((usage.cache_read, usage.cache_write), (5000, 200));
+    }
+
+    #[test]
+    fn test_multi_turn_usage_isnt_context_size() {
+        let stdout = r#"{"result":"done","num_turns":10,"usage":{"input_tokens":50,"cache_read_input_tokens":180000,"output_tokens":900}}"#;
+        let reply = parse_claude_output(stdout);
+        assert_eq!(reply.context_tokens, None);
+        assert_eq!(reply.usage.unwrap().tokens(), 180_950);
+    }
+
+    #[test]
+    fn test_budget_exceeded() {
+        let stdout = r#"{"subtype":"error_max_budget_usd","is_error":true,"result":"Half done","total_cost_usd":0.51}"#;
+        let partial = budget_exceeded(stdout).unwrap();
+        assert_eq!(partial.text, "Half done");
+        assert_eq!(partial.cost_usd, Some(0.51));
+        let no_text = budget_exceeded(r#"{"subtype":"error_max_budget_usd","total_cost_usd":0.5}"#);
+        assert_eq!(no_text.unwrap().text, "");
+        assert!(budget_exceeded(r#"{"subtype":"success","result":"hi"}"#).is_none());
+        assert!(budget_exceeded("plain text").is_none());
+        assert_eq!(
+            AppError::BudgetExceeded(ClaudeReply::default()).category(),
+            "budget"
+        );
+    }
+
+    #[test]
+    fn test_parse_claude_output_plain_text() {
+        let reply = parse_claude_output("  just text \n");
+        assert_eq!(reply.text, "just text");
+        assert_eq!(reply.cost_usd, None);
+        assert_eq!(reply.context_tokens, None);
+    }
+
+    #[test]
+    fn test_detects_unknown_session() {
+        assert!(is_unknown_session(
+            "Error: No conversation found with session ID: 1234"
+        ));
+        assert!(is_unknown_session("session abc does not exist"));
+        assert!(!is_unknown_session("API Error: 529 overloaded"));
+        assert!(switched_session(
+            r#"{"result":"hi","session_id":"new"}"#,
+            "old"
+        ));
+        assert!(!switched_session(
+            r#"{"result":"hi","session_id":"old"}"#,
+            "old"
+        ));
+        assert!(!switched_session("plain text", "old"));
+    }
+
+    #[tokio::test]
+    async fn test_echo_runner_echoes_prompt_for_free() {
+        let reply = EchoRunner
+            .run_claude(
+                "hello there",
+                "sid",
+                "sonnet",
+                &[PathBuf::from("/tmp/a.png")],
+                Path::new("/nonexistent"),
+                5.0,
+                "",
+                &RunOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert!(reply
+            .text
+            .starts_with("[dry run] model sonnet, 1 attachment(s)"));
+        assert!(reply.text.ends_with("hello there"));
+        assert_eq!(reply.cost_usd, Some(0.0));
+        assert!(EchoRunner
+            .summarize_session("sid", "sonnet")
+            .await
+            .is_some());
+    }
 }
@@ -0,0 +1,766 @@
+//! Discord bot transport.
+//!
+//! Inbound messages arrive over the Discord gateway (a WebSocket that needs
+//! an IDENTIFY and a steady heartbeat, driven by [`Gateway`]); replies,
+//! typing and uploads go through the REST API with the bot token. Both use
+//! the shared proxy/CA settings. A dropped connection RESUMEs the gateway
+//! session where it can, so Discord replays what was missed instead of the
+//! bot starting over.
+//!
+//! The gateway is spoken directly rather than through serenity or twilight:
+//! the bridge needs only MESSAGE_CREATE, and their own HTTP and WebSocket
+//! stacks wouldn't go through `--proxy` and `--ca-bundle`.
+//!
+//! Users are addressed by their Discord user id (a snowflake), which is what
+//! goes in the allowed list and `--account`. DMs are per-user sessions.
+//! Server channels are either one shared session per channel (a group, which
+//! needs `/bind` like any other) or, with `--discord-sessions user`, one
+//! session per user per channel, addressed as `<user>#<channel>`.
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tracing::{debug, error, info, warn};
+
+use crate::error::AppError;
+use crate::helpers::group_id_from_chat;
+use crate::signal::{AttachmentInfo, ParsedEnvelope};
+use crate::state::State;
+use crate::traits::{attachment_tmp_path, SignalApi};
+
+pub(crate) const API_URL: &str = "https://discord.com/api/v10";
+const GATEWAY_URL: &str = "wss://gateway.discord.gg";
+const GATEWAY_QUERY: &str = "/?v=10&encoding=json";
+
+/// GUILDS | GUILD_MESSAGES | DIRECT_MESSAGES | MESSAGE_CONTENT.
+const INTENTS: u64 = (1 << 0) | (1 << 9) | (1 << 12) | (1 << 15);
+/// Message flag set on voice messages.
+const IS_VOICE_MESSAGE: u64 = 1 << 13;
+
+const OP_DISPATCH: u64 = 0;
+const OP_HEARTBEAT: u64 = 1;
+const OP_IDENTIFY: u64 = 2;
+const OP_RESUME: u64 = 6;
+const OP_RECONNECT: u64 = 7;
+const OP_INVALID_SESSION: u64 = 9;
+const OP_HELLO: u64 = 10;
+const OP_HEARTBEAT_ACK: u64 = 11;
+
+/// How server-channel conversations map onto sessions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum SessionMode {
+    /// One shared session per channel (a group room).
+    #[default]
+    Channel,
+    /// One session per user in each channel.
+    User,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct DiscordConfig {
+    pub(crate) bot_token: String,
+    pub(crate) sessions: SessionMode,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    id: String,
+    channel_id: String,
+    #[serde(default)]
+    guild_id: Option<String>,
+    author: Author,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    #[serde(default)]
+    flags: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Author {
+    id: String,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    global_name: Option<String>,
+    #[serde(default)]
+    bot: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Attachment {
+    id: String,
+    #[serde(default)]
+    filename: Option<String>,
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    url: String,
+}
+
+/// What to do after one gateway frame.
+#[derive(Default)]
+pub(crate) struct Step {
+    /// Payload to write back (IDENTIFY, or a heartbeat Discord asked for).
+    pub(crate) reply: Option<String>,
+    /// Heartbeat period, once HELLO has arrived.
+    pub(crate) heartbeat: Option<Duration>,
+    pub(crate) envelope: Option<ParsedEnvelope>,
+    /// Discord asked us to drop the connection and start again.
+    pub(crate) reconnect: bool,
+}
+
+/// A gateway session a new connection can pick up with RESUME.
+#[derive(Clone, Debug, PartialEq)]
+struct Resumable {
+    session_id: String,
+    /// `resume_gateway_url` from READY.
+    url: String,
+}
+
+/// Gateway protocol state. It outlives each connection so the next one can
+/// resume the session.
+pub(crate) struct Gateway {
+    config: DiscordConfig,
+    seq: Option<u64>,
+    awaiting_ack: bool,
+    session: Option<Resumable>,
+}
+
+impl Gateway {
+    pub(crate) fn new(config: &DiscordConfig) -> Self {
+        Self {
+            config: config.clone(),
+            seq: None,
+            awaiting_ack: false,
+            session: None,
+        }
+    }
+
+    /// Where to connect next: the session's own gateway when resuming.
+    pub(crate) fn url(&self) -> String {
+        let base = self
+            .session
+            .as_ref()
+            .map_or(GATEWAY_URL, |s| s.url.trim_end_matches('/'));
+        format!("{base}{GATEWAY_QUERY}")
+    }
+
+    /// Start of a new connection: nothing is waiting on an ack yet.
+    pub(crate) fn connecting(&mut self) {
+        self.awaiting_ack = false;
+    }
+
+    /// The connection closed with `code`. Some codes mean the session can't
+    /// be resumed and the next connection must IDENTIFY.
+    pub(crate) fn on_close(&mut self, code: u16) {
+        // 4007: invalid seq, 4009: session timed out.
+        if matches!(code, 4007 | 4009) {
+            self.forget_session();
+        }
+    }
+
+    fn forget_session(&mut self) {
+        self.session = None;
+        self.seq = None;
+    }
+
+    pub(crate) fn on_frame(&mut self, frame: &Value) -> Step {
+        if let Some(seq) = frame["s"].as_u64() {
+            self.seq = Some(seq);
+        }
+        let mut step = Step::default();
+        match frame["op"].as_u64() {
+            Some(OP_HELLO) => {
+                let interval = frame["d"]["heartbeat_interval"].as_u64().unwrap_or(41_250);
+                step.heartbeat = Some(Duration::from_millis(interval));
+                step.reply = Some(self.resume().unwrap_or_else(|| self.identify()));
+            }
+            Some(OP_HEARTBEAT) => step.reply = Some(self.heartbeat_payload()),
+            Some(OP_HEARTBEAT_ACK) => self.awaiting_ack = false,
+            Some(OP_RECONNECT) => step.reconnect = true,
+            Some(OP_INVALID_SESSION) => {
+                // `d` says whether the session can still be resumed.
+                if frame["d"].as_bool() != Some(true) {
+                    self.forget_session();
+                }
+                step.reconnect = true;
+            }
+            Some(OP_DISPATCH) => match frame["t"].as_str() {
+                Some("MESSAGE_CREATE") => {
+                    step.envelope = parse_message(&frame["d"], self.config.sessions);
+                }
+                Some("READY") => {
+                    let data = &frame["d"];
+                    let user = &data["user"];
+                    debug!(
+                        "Discord gateway ready as {} ({})",
+                        user["username"].as_str().unwrap_or("?"),
+                        user["id"].as_str().unwrap_or("?")
+                    );
+                    self.session = data["session_id"].as_str().map(|id| Resumable {
+                        session_id: id.to_string(),
+                        url: data["resume_gateway_url"]
+                            .as_str()
+                            .unwrap_or(GATEWAY_URL)
+                            .to_string(),
+                    });
+                }
+                Some("RESUMED") => debug!("Discord gateway session resumed"),
+                _ => {}
+            },
+            _ => {}
+        }
+        step
+    }
+
+    /// Next periodic heartbeat. Errors if the previous one was never
+    /// acknowledged, which means the connection is dead.
+    pub(crate) fn heartbeat(&mut self) -> Result<String, AppError> {
+        if self.awaiting_ack {
+            return Err(AppError::Transport(
+                "Discord gateway stopped acknowledging heartbeats".into(),
+            ));
+        }
+        self.awaiting_ack = true;
+        Ok(self.heartbeat_payload())
+    }
+
+    fn heartbeat_payload(&self) -> String {
+        json!({ "op": OP_HEARTBEAT, "d": self.seq }).to_string()
+    }
+
+    /// RESUME for the session READY gave us, if there is one.
+    fn resume(&self) -> Option<String> {
+        let session = self.session.as_ref()?;
+        Some(
+            json!({
+                "op": OP_RESUME,
+                "d": {
+                    "token": self.config.bot_token,
+                    "session_id": session.session_id,
+                    "seq": self.seq,
+                }
+            })
+            .to_string(),
+        )
+    }
+
+    fn identify(&self) -> String {
+        json!({
+            "op": OP_IDENTIFY,
+            "d": {
+                "token": self.config.bot_token,
+                "intents": INTENTS,
+                "properties": { "os": std::env::consts::OS, "browser": "ccchat", "device": "ccchat" },
+            }
+        })
+        .to_string()
+    }
+}
+
+/// The bot's gateway side, kept for the life of the process.
+pub(crate) struct Link {
+    pub(crate) config: DiscordConfig,
+    gateway: tokio::sync::Mutex<Gateway>,
+}
+
+impl Link {
+    pub(crate) fn new(config: DiscordConfig) -> Self {
+        Self {
+            gateway: tokio::sync::Mutex::new(Gateway::new(&config)),
+            config,
+        }
+    }
+
+    /// Hold a gateway connection until it drops or Discord asks for a
+    /// reconnect, heartbeating on the interval HELLO gives us.
+    pub(crate) async fn listen(&self, state: &Arc<State>) -> Result<(), AppError> {
+        let mut gateway = self.gateway.lock().await;
+        gateway.connecting();
+        info!("Connecting to Discord gateway");
+        let ws = state.config.net.connect_ws(&gateway.url()).await?;
+        let (mut write, mut read) = ws.split();
+        let mut heartbeat: Option<tokio::time::Interval> = None;
+
+        loop {
+            let tick = async {
+                match heartbeat.as_mut() {
+                    Some(interval) => interval.tick().await,
+                    None => std::future::pending().await,
+                }
+            };
+            let msg = tokio::select! {
+                _ = tick => {
+                    write.send(WsMessage::Text(gateway.heartbeat()?)).await?;
+                    continue;
+                }
+                msg = read.next() => match msg {
+                    Some(msg) => msg?,
+                    None => return Ok(()),
+                },
+            };
+            let text = match msg {
+                WsMessage::Text(text) => text,
+                WsMessage::Close(frame) => {
+                    if let Some(frame) = frame {
+                        info!(code = u16::from(frame.code), "Discord closed the gateway");
+                        gateway.on_close(frame.code.into());
+                    }
+                    return Ok(());
+                }
+                _ => continue,
+            };
+            let frame: Value = match serde_json::from_str(&text) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Failed to parse gateway frame: {e}");
+                    continue;
+                }
+            };
+
+            let step = gateway.on_frame(&frame);
+            if let Some(reply) = step.reply {
+                write.send(WsMessage::Text(reply)).await?;
+            }
+            if let Some(period) = step.heartbeat {
+                let mut interval = tokio::time::interval(period);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                // The first tick fires immediately; IDENTIFY has just gone out.
+                interval.tick().await;
+                heartbeat = Some(interval);
+                info!("Discord gateway connected");
+                crate::lifecycle::announce_online(state).await;
+            }
+            if step.reconnect {
+                info!("Discord asked for a reconnect");
+                return Ok(());
+            }
+            if let Some(envelope) = step.envelope {
+                crate::dispatch_envelope(state, envelope);
+            }
+        }
+    }
+}
+
+/// Normalise a MESSAGE_CREATE payload. Bot messages (including our own) are skipped.
+fn parse_message(data: &Value, sessions: SessionMode) -> Option<ParsedEnvelope> {
+    let msg: Message = match serde_json::from_value(data.clone()) {
+        Ok(msg) => msg,
+        Err(e) => {
+            debug!("Skipping unparseable Discord message: {e}");
+            return None;
+        }
+    };
+    if msg.author.bot {
+        return None;
+    }
+
+    let text = strip_mentions(&msg.content);
+    let message_text = if !text.is_empty() {
+        text
+    } else if !msg.attachments.is_empty() {
+        "Describe this attachment.".to_string()
+    } else {
+        return None;
+    };
+
+    let voice_note = msg.flags & IS_VOICE_MESSAGE != 0;
+    let attachments = msg
+        .attachments
+        .into_iter()
+        .map(|a| AttachmentInfo {
+            id: attachment_ref(&msg.channel_id, &msg.id, &a.id),
+            content_type: a.content_type.unwrap_or_default(),
+            filename: a.filename,
+            size: a.size,
+            voice_note,
+        })
+        .collect();
+
+    let in_server = msg.guild_id.is_some();
+    let (group_id, thread_id) = match (in_server, sessions) {
+        (false, _) => (None, None),
+        (true, SessionMode::Channel) => (Some(msg.channel_id), None),
+        (true, SessionMode::User) => (None, Some(format!("{}#{}", msg.author.id, msg.channel_id))),
+    };
+    Some(ParsedEnvelope {
+        source: msg.author.id,
+        message_text,
+        is_sync: false,
+        source_uuid: String::new(),
+        source_name: msg.author.global_name.unwrap_or(msg.author.username),
+        attachments,
+        group_id,
+        thread_id,
+        sent_at: None,
+    })
+}
+
+/// Attachment URLs are signed and expire, so the id records where to look it
+/// up again: `<channel>:<message>:<attachment>`.
+fn attachment_ref(channel: &str, message: &str, attachment: &str) -> String {
+    format!("{channel}:{message}:{attachment}")
+}
+
+/// Drop leading `<@id>` / `<@!id>` mentions so "@ccchat hello" reads as "hello".
+fn strip_mentions(text: &str) -> String {
+    let mut rest = text.trim_start();
+    while let Some(tail) = rest.strip_prefix("<@") {
+        match tail.find('>') {
+            Some(end) => rest = tail[end + 1..].trim_start(),
+            None => break,
+        }
+    }
+    rest.trim_end().to_string()
+}
+
+pub(crate) struct DiscordApi {
+    http: Client,
+    api_url: String,
+    bot_token: String,
+    /// User id -> DM channel id.
+    dm_channels: DashMap<String, String>,
+}
+
+impl DiscordApi {
+    pub(crate) fn new(http: Client, api_url: String, config: &DiscordConfig) -> Self {
+        Self {
+            http,
+            api_url,
+            bot_token: config.bot_token.clone(),
+            dm_channels: DashMap::new(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .request(method, format!("{}{path}", self.api_url))
+            .header("Authorization", format!("Bot {}", self.bot_token))
+    }
+
+    async fn check(resp: reqwest::Response, what: &str) -> Result<reqwest::Response, AppError> {
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+        let body = resp.text().await.unwrap_or_default();
+        error!(status = %status, body = %body, "Discord {what} failed");
+        Err(AppError::Transport(format!(
+            "Discord {what} failed: {status}"
+        )))
+    }
+
+    /// Channel to post into: the server channel for groups and per-user
+    /// channel sessions, or the DM channel for a user id.
+    async fn channel_for(&self, recipient: &str) -> Result<String, AppError> {
+        if let Some(channel) = group_id_from_chat(recipient) {
+            return Ok(channel);
+        }
+        if let Some((_, channel)) = recipient.split_once('#') {
+            return Ok(channel.to_string());
+        }
+        if let Some(channel) = self.dm_channels.get(recipient) {
+            return Ok(channel.clone());
+        }
+        let resp = self
+            .request(reqwest::Method::POST, "/users/@me/channels")
+            .json(&json!({ "recipient_id": recipient }))
+            .send()
+            .await?;
+        let dm: Value = Self::check(resp, "DM open").await?.json().await?;
+        let channel = dm["id"]
+            .as_str()
+            .ok_or_else(|| AppError::Transport("Discord DM open returned no channel".into()))?
+            .to_string();
+        self.dm_channels
+            .insert(recipient.to_string(), channel.clone());
+        Ok(channel)
+    }
+}
+
+#[async_trait]
+impl SignalApi for DiscordApi {
+    async fn send_msg(&self, recipient: &str, message: &str) -> Result<(), AppError> {
+        let channel = self.channel_for(recipient).await?;
+        // Never let model output ping @everyone, roles or users.
+        let body = json!({ "content": message, "allowed_mentions": { "parse": [] } });
+        let resp = self
+            .request(
+                reqwest::Method::POST,
+                &format!("/channels/{channel}/messages"),
+            )
+            .json(&body)
+            .send()
+            .await?;
+        Self::check(resp, "send").await?;
+        Ok(())
+    }
+
+    async fn set_typing(&self, recipient: &str, typing: bool) -> Result<(), AppError> {
+        // The indicator clears itself after ~10s or when we post.
+        if !typing {
+            return Ok(());
+        }
+        let result = async {
+            let channel = self.channel_for(recipient).await?;
+            let resp = self
+                .request(
+                    reqwest::Method::POST,
+                    &format!("/channels/{channel}/typing"),
+                )
+                .send()
+                .await?;
+            Self::check(resp, "typing").await.map(|_| ())
+        }
+        .await;
+        if let Err(e) = result {
+            debug!("Typing indicator failed: {e}");
+        }
+        Ok(())
+    }
+
+    async fn download_attachment(&self, attachment: &AttachmentInfo) -> Result<PathBuf, AppError> {
+        let mut parts = attachment.id.splitn(3, ':');
+        let (Some(channel), Some(message), Some(id)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(AppError::Transport(format!(
+                "Malformed Discord attachment id {}",
+                attachment.id
+            )));
+        };
+        let resp = self
+            .request(
+                reqwest::Method::GET,
+                &format!("/channels/{channel}/messages/{message}"),
+            )
+            .send()
+            .await?;
+        let msg: Message = Self::check(resp, "message fetch").await?.json().await?;
+        let url = msg
+            .attachments
+            .into_iter()
+            .find(|a| a.id == id)
+            .map(|a| a.url)
+            .ok_or_else(|| AppError::Transport(format!("Discord attachment {id} not found")))?;
+
+        let resp = self.http.get(&url).send().await?;
+        let bytes = Self::check(resp, "attachment download")
+            .await?
+            .bytes()
+            .await?;
+        let path = attachment_tmp_path(attachment);
+        std::fs::write(&path, &bytes)?;
+        debug!("Downloaded attachment {id} to {}", path.display());
+        Ok(path)
+    }
+
+    async fn send_attachment(
+        &self,
+        recipient: &str,
+        data: &[u8],
+        content_type: &str,
+        filename: &str,
+    ) -> Result<(), AppError> {
+        let channel = self.channel_for(recipient).await?;
+        let file = reqwest::multipart::Part::bytes(data.to_vec())
+            .file_name(filename.to_string())
+            .mime_str(content_type)
+            .map_err(|e| AppError::Transport(format!("Bad content type {content_type}: {e}")))?;
+        let form = reqwest::multipart::Form::new()
+            .text(
+                "payload_json",
+                json!({ "allowed_mentions": { "parse": [] } }).to_string(),
+            )
+            .part("files[0]", file);
+        let resp = self
+            .request(
+                reqwest::Method::POST,
+                &format!("/channels/{channel}/messages"),
+            )
+            .multipart(form)
+            .send()
+            .await?;
+        if let Err(e) = Self::check(resp, "upload").await {
+            warn!("Attachment {filename} not sent: {e}");
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn config(sessions: SessionMode) -> DiscordConfig {
+        DiscordConfig {
+            bot_token: "tok".into(),
+            sessions,
+        }
+    }
+
+    fn message_create(data: Value) -> Value {
+        json!({ "op": 0, "t": "MESSAGE_CREATE", "s": 7, "d": data })
+    }
+
+    #[test]
+    fn test_hello_identifies_and_sets_heartbeat() {
+        let mut gw = Gateway::new(&config(SessionMode::Channel));
+        let step = gw.on_frame(&json!({"op": 10, "d": {"heartbeat_interval": 45000}}));
+        assert_eq!(step.heartbeat, Some(Duration::from_millis(45000)));
+        let identify: Value = serde_json::from_str(&step.reply.unwrap()).unwrap();
+        assert_eq!(identify["op"], 2);
+        assert_eq!(identify["d"]["token"], "tok");
+        assert_eq!(identify["d"]["intents"], INTENTS);
+    }
+
+    #[test]
+    fn test_heartbeat_tracks_sequence_and_acks() {
+        let mut gw = Gateway::new(&config(SessionMode::Channel));
+        gw.on_frame(&json!({"op": 0, "t": "GUILD_CREATE", "s": 3, "d": {}}));
+        assert_eq!(gw.heartbeat().unwrap(), r#"{"d":3,"op":1}"#);
+        assert!(
+            gw.heartbeat().is_err(),
+            "unacked heartbeat means a dead link"
+        );
+        gw.on_frame(&json!({"op": 11}));
+        assert!(gw.heartbeat().is_ok());
+    }
+
+    #[test]
+    fn test_reconnect_requests() {
+        let mut gw = Gateway::new(&config(SessionMode::Channel));
+        assert!(gw.on_frame(&json!({"op": 7})).reconnect);
+        assert!(gw.on_frame(&json!({"op": 9, "d": false})).reconnect);
+    }
+
+    #[test]
+    fn test_reconnect_resumes_session() {
+        let mut gw = Gateway::new(&config(SessionMode::Channel));
+        assert_eq!(gw.url(), "wss://gateway.discord.gg/?v=10&encoding=json");
+        gw.on_frame(&json!({"op": 0, "t": "READY", "s": 1, "d": {
+            "session_id": "sess-1",
+            "resume_gateway_url": "wss://gateway-us-east1-b.discord.gg",
+            "user": {"id": "1", "username": "ccchat"},
+        }}));
+        gw.on_frame(&message_create(json!({})));
+        assert!(gw.on_frame(&json!({"op": 7})).reconnect);
+
+        assert_eq!(
+            gw.url(),
+            "wss://gateway-us-east1-b.discord.gg/?v=10&encoding=json"
+        );
+        let hello = json!({"op": 10, "d": {"heartbeat_interval": 45000}});
+        let resume: Value = serde_json::from_str(&gw.on_frame(&hello).reply.unwrap()).unwrap();
+        assert_eq!(resume["op"], 6);
+        assert_eq!(resume["d"]["session_id"], "sess-1");
+        assert_eq!(resume["d"]["seq"], 7);
+
+        // A resumable invalid session keeps it; otherwise start over.
+        gw.on_frame(&json!({"op": 9, "d": true}));
+        assert_eq!(gw.on_frame(&hello).reply.unwrap(), gw.resume().unwrap());
+        gw.on_close(4009);
+        let identify: Value = serde_json::from_str(&gw.on_frame(&hello).reply.unwrap()).unwrap();
+        assert_eq!(identify["op"], 2);
+        assert_eq!(gw.url(), "wss://gateway.discord.gg/?v=10&encoding=json");
+    }
+
+    #[test]
+    fn test_parse_dm() {
+        let mut gw = Gateway::new(&config(SessionMode::Channel));
+        let step = gw.on_frame(&message_create(json!({
+            "id": "m1", "channel_id": "c1",
+            "author": {"id": "u1", "username": "ann", "global_name": "Ann"},
+            "content": "hello"
+        })));
+        let env = step.envelope.unwrap();
+        assert_eq!(env.source, "u1");
+        assert_eq!(env.source_name, "Ann");
+        assert_eq!(env.message_text, "hello");
+        assert!(env.group_id.is_none() && env.thread_id.is_none());
+    }
+
+    #[test]
+    fn test_parse_server_message_per_channel_and_per_user() {
+        let data = json!({
+            "id": "m1", "channel_id": "c9", "guild_id": "g1",
+            "author": {"id": "u1", "username": "ann"},
+            "content": "<@!42> <@43> what's up",
+            "attachments": [{"id": "a1", "filename": "voice.ogg", "content_type": "audio/ogg", "url": "https://cdn/x"}],
+            "flags": IS_VOICE_MESSAGE
+        });
+        let env = parse_message(&data, SessionMode::Channel).unwrap();
+        assert_eq!(env.group_id.as_deref(), Some("c9"));
+        assert_eq!(env.message_text, "what's up");
+        assert_eq!(env.attachments[0].id, "c9:m1:a1");
+        assert!(env.attachments[0].voice_note);
+
+        let env = parse_message(&data, SessionMode::User).unwrap();
+        assert!(env.group_id.is_none());
+        assert_eq!(env.thread_id.as_deref(), Some("u1#c9"));
+    }
+
+    #[test]
+    fn test_parse_skips_bots_and_empty() {
+        let bot = json!({
+            "id": "m1", "channel_id": "c1",
+            "author": {"id": "b1", "username": "ccchat", "bot": true},
+            "content": "reply"
+        });
+        assert!(parse_message(&bot, SessionMode::Channel).is_none());
+        let empty = json!({"id": "m1", "channel_id": "c1", "author": {"id": "u1"}, "content": ""});
+        assert!(parse_message(&empty, SessionMode::Channel).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_msg_opens_dm_and_disables_mentions() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/users/@me/channels"))
+            .and(body_json(json!({"recipient_id": "u1"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "dm1"})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/channels/dm1/messages"))
+            .and(header("authorization", "Bot tok"))
+            .and(body_json(
+                json!({"content": "hi @everyone", "allowed_mentions": {"parse": []}}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "m2"})))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let api = DiscordApi::new(Client::new(), server.uri(), &config(SessionMode::Channel));
+        api.send_msg("u1", "hi @everyone").await.unwrap();
+        api.send_msg("u1", "hi @everyone").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_to_per_user_channel_session() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/channels/c9/messages"))
+            .respond_with(ResponseTemplate::new(403))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let api = DiscordApi::new(Client::new(), server.uri(), &config(SessionMode::User));
+        let err = api.send_msg("u1#c9", "hi").await.unwrap_err();
+        assert!(matches!(err, AppError::Transport(_)));
+    }
+}
@@ -0,0 +1,1005 @@
+//! Email transport: poll an IMAP inbox, reply over SMTP.
+//!
+//! Each email thread is its own conversation. Its chat id is
+//! `<address>#<thread>`, where `<thread>` hashes the thread's root
+//! Message-ID, so replies land in the same Claude session and go back out
+//! with `In-Reply-To`/`References` set. Quoted text below a reply is
+//! stripped before it reaches Claude.
+//!
+//! `From:` can say anything, so mail only counts when the operator's own
+//! mail server (`--email-auth-server`) vouches for it in an
+//! `Authentication-Results` header: DMARC passed, or DKIM or SPF passed for
+//! the From domain. Anything else is dropped unread.
+//!
+//! The IMAP side is a minimal client (LOGIN, SELECT, UID SEARCH/FETCH/STORE)
+//! over TLS; SMTP goes through lettre. Both connect through `--proxy` and
+//! trust the `--ca-bundle` roots.
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{
+    AsyncSmtpConnection, AsyncTokioStream, Certificate, TlsParameters,
+};
+use lettre::transport::smtp::extension::ClientId;
+use mail_parser::{Message, MessageParser, MimeHeaders};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf,
+};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tracing::{debug, warn};
+
+use crate::error::AppError;
+use crate::net::NetConfig;
+use crate::signal::{AttachmentInfo, ParsedEnvelope};
+use crate::state::State;
+use crate::traits::{attachment_tmp_path, SignalApi};
+
+/// Subject used for mail that doesn't belong to a thread (admin notices, reminders).
+const DEFAULT_SUBJECT: &str = "ccchat";
+
+#[derive(Clone, Debug)]
+pub(crate) struct EmailConfig {
+    pub(crate) imap_host: String,
+    pub(crate) imap_port: u16,
+    pub(crate) smtp_host: String,
+    /// 465 uses implicit TLS; any other port uses STARTTLS.
+    pub(crate) smtp_port: u16,
+    pub(crate) username: String,
+    pub(crate) password: String,
+    /// Address replies are sent from.
+    pub(crate) address: String,
+    /// authserv-id of the mail server whose `Authentication-Results` are
+    /// trusted.
+    pub(crate) auth_server: String,
+    pub(crate) poll_interval: Duration,
+}
+
+/// Reply headers for one thread, updated with every inbound message.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct MailThread {
+    pub(crate) to: String,
+    pub(crate) subject: String,
+    pub(crate) last_message_id: Option<String>,
+    pub(crate) references: Vec<String>,
+}
+
+/// Shared between the inbox poller and [`EmailApi`].
+pub(crate) struct Mailbox {
+    pub(crate) config: EmailConfig,
+    net: NetConfig,
+    threads: DashMap<String, MailThread>,
+    /// Attachment bytes from the latest poll, keyed by attachment id.
+    attachments: DashMap<String, (Vec<u8>, String)>,
+}
+
+impl Mailbox {
+    pub(crate) fn new(config: EmailConfig, net: NetConfig) -> Self {
+        Self {
+            config,
+            net,
+            threads: DashMap::new(),
+            attachments: DashMap::new(),
+        }
+    }
+
+    /// A TLS connection to `host:port`, through the proxy if there is one.
+    async fn connect_tls(&self, host: &str, port: u16) -> Result<TlsStream<TcpStream>, AppError> {
+        let tcp = self.net.connect_tcp(host, port).await?;
+        let server_name = rustls_pki_types::ServerName::try_from(host.to_string())
+            .map_err(|e| AppError::Transport(format!("Invalid mail server {host}: {e}")))?;
+        Ok(tokio_rustls::TlsConnector::from(self.net.tls_config()?)
+            .connect(server_name, tcp)
+            .await?)
+    }
+
+    /// Poll the inbox until a connection fails.
+    pub(crate) async fn poll(&self, state: &Arc<State>) -> Result<(), AppError> {
+        loop {
+            let envelopes = self.fetch_new().await?;
+            crate::lifecycle::announce_online(state).await;
+            for envelope in envelopes {
+                crate::dispatch_envelope(state, envelope);
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Fetch unseen mail, mark it seen and normalise it into envelopes.
+    pub(crate) async fn fetch_new(&self) -> Result<Vec<ParsedEnvelope>, AppError> {
+        let tls = self
+            .connect_tls(&self.config.imap_host, self.config.imap_port)
+            .await?;
+        let mut imap = Imap::new(tls);
+        let raw = fetch_unseen(&mut imap, &self.config.username, &self.config.password).await?;
+
+        self.attachments.clear();
+        let mut envelopes = Vec::new();
+        for (uid, bytes) in raw {
+            match self.ingest(uid, &bytes) {
+                Some(env) => envelopes.push(env),
+                None => debug!(uid, "Skipped email"),
+            }
+        }
+        Ok(envelopes)
+    }
+
+    /// Parse one raw message, remember its thread and stash its attachments.
+    fn ingest(&self, uid: u32, raw: &[u8]) -> Option<ParsedEnvelope> {
+        let mail = parse_mail(raw, &self.config.auth_server)?;
+        let chat_id = format!("{}#{}", mail.from, thread_key(&mail.thread_root));
+        self.threads.insert(chat_id.clone(), mail.thread.clone());
+
+        let mut attachments = Vec::new();
+        for (i, (filename, content_type, data)) in mail.attachments.into_iter().enumerate() {
+            let id = format!("{uid}-{i}");
+            self.attachments
+                .insert(id.clone(), (data, content_type.clone()));
+            attachments.push(AttachmentInfo {
+                id,
+                content_type,
+                filename,
+                size: None,
+                voice_note: false,
+            });
+        }
+        let message_text = if !mail.text.is_empty() {
+            mail.text
+        } else if !attachments.is_empty() {
+            "Describe this attachment.".to_string()
+        } else {
+            return None;
+        };
+        Some(ParsedEnvelope {
+            source: mail.from,
+            message_text,
+            is_sync: false,
+            source_uuid: String::new(),
+            source_name: mail.from_name.unwrap_or_else(|| "unknown".to_string()),
+            attachments,
+            group_id: None,
+            thread_id: Some(chat_id),
+            sent_at: None,
+        })
+    }
+
+    /// Headers for a reply to `recipient`, a thread chat id or a bare address.
+    fn reply_to(&self, recipient: &str) -> MailThread {
+        self.threads
+            .get(recipient)
+            .map(|t| t.clone())
+            .unwrap_or_else(|| MailThread {
+                to: crate::helpers::thread_owner(recipient).to_string(),
+                subject: DEFAULT_SUBJECT.to_string(),
+                ..Default::default()
+            })
+    }
+}
+
+/// Chat ids embed a short, stable hash of the thread's root Message-ID.
+fn thread_key(root: &str) -> String {
+    format!("{:x}", crate::helpers::hash_message(root))
+}
+
+#[derive(Debug)]
+struct ParsedMail {
+    from: String,
+    from_name: Option<String>,
+    text: String,
+    thread_root: String,
+    thread: MailThread,
+    /// (filename, content type, bytes)
+    attachments: Vec<(Option<String>, String, Vec<u8>)>,
+}
+
+/// One method's result in an `Authentication-Results` header, such as
+/// `dkim=pass header.d=example.com`.
+#[derive(Debug, PartialEq)]
+struct AuthResult {
+    method: String,
+    result: String,
+    /// `ptype.property` -> value, e.g. `header.d` -> `example.com`.
+    props: Vec<(String, String)>,
+}
+
+/// `value` without its (possibly nested) parenthesised comments.
+fn strip_comments(value: &str) -> String {
+    let mut depth = 0usize;
+    value
+        .chars()
+        .filter(|&c| match c {
+            '(' => {
+                depth += 1;
+                false
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            _ => depth == 0,
+        })
+        .collect()
+}
+
+/// The authserv-id and results of one `Authentication-Results` value.
+fn parse_auth_results(value: &str) -> Option<(String, Vec<AuthResult>)> {
+    let value = strip_comments(value);
+    let mut parts = value.split(';');
+    let server = parts
+        .next()?
+        .split_whitespace()
+        .next()?
+        .to_ascii_lowercase();
+    let results = parts
+        .filter_map(|part| {
+            let mut words = part.split_whitespace();
+            let (method, result) = words.next()?.split_once('=')?;
+            let props = words
+                .filter_map(|w| w.split_once('='))
+                .map(|(k, v)| {
+                    (
+                        k.to_ascii_lowercase(),
+                        v.trim_matches('"').to_ascii_lowercase(),
+                    )
+                })
+                .collect();
+            Some(AuthResult {
+                method: method.split('/').next()?.to_ascii_lowercase(),
+                result: result.to_ascii_lowercase(),
+                props,
+            })
+        })
+        .collect();
+    Some((server, results))
+}
+
+/// Whether `a` and `b` are the same domain or one is a subdomain of the
+/// other (DMARC's relaxed alignment).
+fn aligned(a: &str, b: &str) -> bool {
+    a == b || a.ends_with(&format!(".{b}")) || b.ends_with(&format!(".{a}"))
+}
+
+/// Whether `auth_server`'s `Authentication-Results` vouch for `from`. Only
+/// the topmost header with its authserv-id counts: that's the one it added
+/// on delivery, and servers strip copies of their own id that arrive with
+/// the mail.
+fn sender_authenticated(msg: &Message, auth_server: &str, from: &str) -> bool {
+    let Some((_, domain)) = from.rsplit_once('@') else {
+        return false;
+    };
+    let Some(results) = msg
+        .headers_raw()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Authentication-Results"))
+        .filter_map(|(_, value)| parse_auth_results(value))
+        .find(|(server, _)| server.eq_ignore_ascii_case(auth_server))
+        .map(|(_, results)| results)
+    else {
+        return false;
+    };
+    let prop = |r: &AuthResult, key: &str| {
+        r.props
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.rsplit('@').next().unwrap_or(v).to_string())
+    };
+    results
+        .iter()
+        .filter(|r| r.result == "pass")
+        .any(|r| match r.method.as_str() {
+            "dmarc" => prop(r, "header.from").is_none_or(|d| d == domain),
+            "dkim" => prop(r, "header.d").is_some_and(|d| aligned(&d, domain)),
+            "spf" => prop(r, "smtp.mailfrom").is_some_and(|d| aligned(&d, domain)),
+            _ => false,
+        })
+}
+
+fn parse_mail(raw: &[u8], auth_server: &str) -> Option<ParsedMail> {
+    let msg = MessageParser::default().parse(raw)?;
+    // Vacation responders and bounces would otherwise loop with our replies.
+    if msg
+        .header("Auto-Submitted")
+        .and_then(|h| h.as_text())
+        .is_some_and(|v| !v.eq_ignore_ascii_case("no"))
+    {
+        return None;
+    }
+    let from_addr = msg.from()?.first()?;
+    let from = from_addr.address()?.to_ascii_lowercase();
+    let from_name = from_addr.name().map(str::to_string);
+    if !sender_authenticated(&msg, auth_server, &from) {
+        warn!(from = %from, "Dropped email that {auth_server} didn't authenticate");
+        return None;
+    }
+
+    let message_id = msg.message_id().map(str::to_string);
+    let mut references: Vec<String> = msg
+        .references()
+        .as_text_list()
+        .map(|l| l.iter().map(|s| s.to_string()).collect())
+        .unwrap_or_default();
+    let in_reply_to = msg.in_reply_to().as_text().map(str::to_string);
+    let thread_root = references
+        .first()
+        .cloned()
+        .or_else(|| in_reply_to.clone())
+        .or_else(|| message_id.clone())
+        .unwrap_or_else(|| from.clone());
+    if let Some(parent) = &in_reply_to {
+        if !references.contains(parent) {
+            references.push(parent.clone());
+        }
+    }
+    if let Some(id) = &message_id {
+        references.push(id.clone());
+    }
+
+    let subject = msg.subject().unwrap_or("").trim().to_string();
+    let text = msg
+        .body_text(0)
+        .map(|t| strip_quoted(&t))
+        .unwrap_or_default();
+    let attachments = msg
+        .attachments()
+        .map(|part| {
+            let content_type = part
+                .content_type()
+                .map(|ct| match ct.subtype() {
+                    Some(sub) => format!("{}/{sub}", ct.ctype()),
+                    None => ct.ctype().to_string(),
+                })
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            (
+                part.attachment_name().map(str::to_string),
+                content_type,
+                part.contents().to_vec(),
+            )
+        })
+        .collect();
+
+    Some(ParsedMail {
+        thread: MailThread {
+            to: from.clone(),
+            subject,
+            last_message_id: message_id,
+            references,
+        },
+        from,
+        from_name,
+        text,
+        thread_root,
+        attachments,
+    })
+}
+
+/// Drop the quoted history below a reply ("On ... wrote:" and `>` lines).
+pub(crate) fn strip_quoted(body: &str) -> String {
+    let mut kept = Vec::new();
+    for line in body.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("On ") && trimmed.ends_with("wrote:") {
+            break;
+        }
+        if trimmed == "-----Original Message-----" {
+            break;
+        }
+        if trimmed.starts_with('>') {
+            continue;
+        }
+        kept.push(line);
+    }
+    kept.join("\n").trim().to_string()
+}
+
+fn reply_subject(subject: &str) -> String {
+    if subject.is_empty() {
+        DEFAULT_SUBJECT.to_string()
+    } else if subject.len() >= 3 && subject[..3].eq_ignore_ascii_case("re:") {
+        subject.to_string()
+    } else {
+        format!("Re: {subject}")
+    }
+}
+
+// --- IMAP ---
+
+/// Untagged response lines plus any literal payloads, in order.
+#[derive(Debug, Default)]
+struct ImapResponse {
+    lines: Vec<String>,
+    literals: Vec<Vec<u8>>,
+}
+
+struct Imap<S> {
+    stream: BufReader<S>,
+    tag: u32,
+    greeted: bool,
+}
+
+/// `* 1 FETCH (BODY[] {42}` -> Some(42)
+fn literal_len(line: &str) -> Option<usize> {
+    let body = line.trim_end().strip_suffix('}')?;
+    let open = body.rfind('{')?;
+    body[open + 1..].trim_end_matches('+').parse().ok()
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Imap<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            tag: 0,
+            greeted: false,
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String, AppError> {
+        let mut line = Vec::new();
+        if self.stream.read_until(b'\n', &mut line).await? == 0 {
+            return Err(AppError::Transport("IMAP connection closed".into()));
+        }
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    async fn command(&mut self, cmd: &str) -> Result<ImapResponse, AppError> {
+        if !self.greeted {
+            let greeting = self.read_line().await?;
+            if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+                return Err(AppError::Transport(format!(
+                    "Unexpected IMAP greeting: {}",
+                    greeting.trim()
+                )));
+            }
+            self.greeted = true;
+        }
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        let verb = cmd.split_whitespace().next().unwrap_or(cmd).to_string();
+        let io = self.stream.get_mut();
+        io.write_all(format!("{tag} {cmd}\r\n").as_bytes()).await?;
+        io.flush().await?;
+
+        let mut resp = ImapResponse::default();
+        loop {
+            let line = self.read_line().await?;
+            if let Some(status) = line
+                .strip_prefix(tag.as_str())
+                .and_then(|r| r.strip_prefix(' '))
+            {
+                if status.starts_with("OK") {
+                    return Ok(resp);
+                }
+                return Err(AppError::Transport(format!(
+                    "IMAP {verb} failed: {}",
+                    status.trim()
+                )));
+            }
+            if let Some(n) = literal_len(&line) {
+                let mut buf = vec![0u8; n];
+                self.stream.read_exact(&mut buf).await?;
+                resp.literals.push(buf);
+            }
+            resp.lines.push(line);
+        }
+    }
+}
+
+/// Log in, fetch up to `EMAIL_FETCH_BATCH` unseen messages from INBOX and
+/// mark them seen. Returns (uid, raw message) pairs.
+async fn fetch_unseen<S: AsyncRead + AsyncWrite + Unpin>(
+    imap: &mut Imap<S>,
+    username: &str,
+    password: &str,
+) -> Result<Vec<(u32, Vec<u8>)>, AppError> {
+    imap.command(&format!("LOGIN {} {}", quote(username), quote(password)))
+        .await?;
+    imap.command("SELECT INBOX").await?;
+    let search = imap.command("UID SEARCH UNSEEN").await?;
+    let uids: Vec<u32> = search
+        .lines
+        .iter()
+        .filter_map(|l| l.strip_prefix("* SEARCH"))
+        .flat_map(|rest| rest.split_whitespace().filter_map(|n| n.parse().ok()))
+        .take(crate::constants::EMAIL_FETCH_BATCH)
+        .collect();
+
+    let mut messages = Vec::with_capacity(uids.len());
+    for uid in uids {
+        let fetched = imap
+            .command(&format!("UID FETCH {uid} (BODY.PEEK[])"))
+            .await?;
+        if let Some(raw) = fetched.literals.into_iter().next() {
+            messages.push((uid, raw));
+        }
+        imap.command(&format!("UID STORE {uid} +FLAGS (\\Seen)"))
+            .await?;
+    }
+    if let Err(e) = imap.command("LOGOUT").await {
+        debug!("IMAP logout: {e}");
+    }
+    Ok(messages)
+}
+
+// --- SMTP ---
+
+/// Implicit-TLS SMTP (port 465) over a stream lettre didn't open itself.
+#[derive(Debug)]
+struct SmtpsStream(TlsStream<TcpStream>);
+
+impl AsyncRead for SmtpsStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for SmtpsStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl AsyncTokioStream for SmtpsStream {
+    fn peer_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.0.get_ref().0.peer_addr()
+    }
+}
+
+pub(crate) struct EmailApi {
+    mailbox: Arc<Mailbox>,
+    /// For STARTTLS; implicit TLS goes through [`Mailbox::connect_tls`].
+    tls: TlsParameters,
+}
+
+impl EmailApi {
+    pub(crate) fn new(mailbox: Arc<Mailbox>) -> Result<Self, AppError> {
+        use rustls_pki_types::pem::PemObject;
+        use rustls_pki_types::CertificateDer;
+
+        let cfg = &mailbox.config;
+        let mut tls = TlsParameters::builder(cfg.smtp_host.clone());
+        if let Some(path) = &mailbox.net.ca_bundle {
+            let pem = std::fs::read(path)?;
+            for cert in CertificateDer::pem_slice_iter(&pem).flatten() {
+                let cert = Certificate::from_der(cert.to_vec())
+                    .map_err(|e| AppError::Transport(format!("Invalid CA certificate: {e}")))?;
+                tls = tls.add_root_certificate(cert);
+            }
+        }
+        let tls = tls
+            .build_rustls()
+            .map_err(|e| AppError::Transport(format!("SMTP TLS setup failed: {e}")))?;
+        Ok(Self { mailbox, tls })
+    }
+
+    /// An authenticated SMTP session: implicit TLS on 465, STARTTLS
+    /// (required) on any other port.
+    async fn smtp_session(&self) -> Result<AsyncSmtpConnection, AppError> {
+        let cfg = &self.mailbox.config;
+        let smtp_error = |e: lettre::transport::smtp::Error| {
+            AppError::Transport(format!("SMTP send failed: {e}"))
+        };
+        let hello = ClientId::default();
+        let mut conn = if cfg.smtp_port == 465 {
+            let tls = self
+                .mailbox
+                .connect_tls(&cfg.smtp_host, cfg.smtp_port)
+                .await?;
+            AsyncSmtpConnection::connect_with_transport(Box::new(SmtpsStream(tls)), &hello)
+                .await
+                .map_err(smtp_error)?
+        } else {
+            let tcp = self
+                .mailbox
+                .net
+                .connect_tcp(&cfg.smtp_host, cfg.smtp_port)
+                .await?;
+            let mut conn = AsyncSmtpConnection::connect_with_transport(Box::new(tcp), &hello)
+                .await
+                .map_err(smtp_error)?;
+            conn.starttls(self.tls.clone(), &hello)
+                .await
+                .map_err(smtp_error)?;
+            conn
+        };
+        let credentials = Credentials::new(cfg.username.clone(), cfg.password.clone());
+        conn.auth(&[Mechanism::Plain, Mechanism::Login], &credentials)
+            .await
+            .map_err(smtp_error)?;
+        Ok(conn)
+    }
+
+    fn builder(&self, recipient: &str) -> Result<lettre::message::MessageBuilder, AppError> {
+        let thread = self.mailbox.reply_to(recipient);
+        let parse = |addr: &str| {
+            addr.parse::<lettre::message::Mailbox>()
+                .map_err(|e| AppError::Transport(format!("Invalid email address {addr}: {e}")))
+        };
+        let mut builder = lettre::Message::builder()
+            .from(parse(&self.mailbox.config.address)?)
+            .to(parse(&thread.to)?)
+            .subject(reply_subject(&thread.subject));
+        if let Some(parent) = thread.last_message_id {
+            builder = builder.in_reply_to(format!("<{parent}>"));
+        }
+        if !thread.references.is_empty() {
+            let refs: Vec<String> = thread.references.iter().map(|r| format!("<{r}>")).collect();
+            builder = builder.references(refs.join(" "));
+        }
+        Ok(builder)
+    }
+
+    async fn deliver(&self, message: lettre::Message) -> Result<(), AppError> {
+        let mut conn = self.smtp_session().await?;
+        conn.send(message.envelope(), &message.formatted())
+            .await
+            .map_err(|e| AppError::Transport(format!("SMTP send failed: {e}")))?;
+        let _ = conn.quit().await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SignalApi for EmailApi {
+    async fn send_msg(&self, recipient: &str, message: &str) -> Result<(), AppError> {
+        let email = self
+            .builder(recipient)?
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.to_string())
+            .map_err(|e| AppError::Transport(format!("Failed to build email: {e}")))?;
+        self.deliver(email).await
+    }
+
+    async fn set_typing(&self, _recipient: &str, _typing: bool) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn download_attachment(&self, attachment: &AttachmentInfo) -> Result<PathBuf, AppError> {
+        let (_, (data, _)) = self
+            .mailbox
+            .attachments
+            .remove(&attachment.id)
+            .ok_or_else(|| {
+                AppError::Transport(format!("Email attachment {} expired", attachment.id))
+            })?;
+        let path = attachment_tmp_path(attachment);
+        std::fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    async fn send_attachment(
+        &self,
+        recipient: &str,
+        data: &[u8],
+        content_type: &str,
+        filename: &str,
+    ) -> Result<(), AppError> {
+        let ct = ContentType::parse(content_type).unwrap_or(ContentType::TEXT_PLAIN);
+        let email = self
+            .builder(recipient)?
+            .multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain(format!("Attached: {filename}")))
+                    .singlepart(Attachment::new(filename.to_string()).body(data.to_vec(), ct)),
+            )
+            .map_err(|e| AppError::Transport(format!("Failed to build email: {e}")))?;
+        if let Err(e) = self.deliver(email).await {
+            warn!("Email attachment send failed: {e}");
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_mailbox() -> Mailbox {
+        Mailbox::new(
+            EmailConfig {
+                imap_host: "imap.example.com".to_string(),
+                imap_port: 993,
+                smtp_host: "smtp.example.com".to_string(),
+                smtp_port: 465,
+                username: "bot@example.com".to_string(),
+                password: "pw".to_string(),
+                address: "bot@example.com".to_string(),
+                auth_server: "mx.example.com".to_string(),
+                poll_interval: Duration::from_secs(60),
+            },
+            NetConfig::default(),
+        )
+    }
+
+    const FIRST: &str = "Authentication-Results: mx.example.com;\r\n\
+        \tdkim=pass (2048-bit key) header.d=example.com header.s=s1;\r\n\
+        \tdmarc=pass (p=REJECT) header.from=example.com\r\n\
+        From: Ann <Ann@Example.com>\r\n\
+        To: bot@example.com\r\n\
+        Subject: Trip plans\r\n\
+        Message-ID: <m1@example.com>\r\n\
+        \r\n\
+        Where should we go?\r\n";
+
+    const REPLY: &str =
+        "Authentication-Results: mx.example.com; spf=pass smtp.mailfrom=ann@mail.example.com\r\n\
+        From: ann@example.com\r\n\
+        To: bot@example.com\r\n\
+        Subject: Re: Trip plans\r\n\
+        Message-ID: <m3@example.com>\r\n\
+        In-Reply-To: <m2@bot.example.com>\r\n\
+        References: <m1@example.com> <m2@bot.example.com>\r\n\
+        \r\n\
+        Somewhere warm.\r\n\
+        \r\n\
+        On Mon, 1 Jan 2024, ccchat wrote:\r\n\
+        > Lisbon?\r\n";
+
+    #[test]
+    fn test_thread_replies_share_a_chat_id() {
+        let mailbox = test_mailbox();
+        let first = mailbox.ingest(1, FIRST.as_bytes()).unwrap();
+        let reply = mailbox.ingest(2, REPLY.as_bytes()).unwrap();
+        assert_eq!(first.source, "ann@example.com");
+        assert_eq!(first.source_name, "Ann");
+        assert_eq!(first.thread_id, reply.thread_id);
+        assert!(first
+            .thread_id
+            .as_deref()
+            .unwrap()
+            .starts_with("ann@example.com#"));
+        assert_eq!(reply.message_text, "Somewhere warm.");
+    }
+
+    #[test]
+    fn test_reply_headers_follow_thread() {
+        let mailbox = test_mailbox();
+        let env = mailbox.ingest(2, REPLY.as_bytes()).unwrap();
+        let thread = mailbox.reply_to(env.thread_id.as_deref().unwrap());
+        assert_eq!(thread.to, "ann@example.com");
+        assert_eq!(reply_subject(&thread.subject), "Re: Trip plans");
+        assert_eq!(thread.last_message_id.as_deref(), Some("m3@example.com"));
+        assert_eq!(
+            thread.references,
+            vec!["m1@example.com", "m2@bot.example.com", "m3@example.com"]
+        );
+        // Unknown recipients get a fresh mail to the address part.
+        let fresh = mailbox.reply_to("admin@example.com");
+        assert_eq!(fresh.to, "admin@example.com");
+        assert_eq!(fresh.subject, DEFAULT_SUBJECT);
+    }
+
+    #[test]
+    fn test_auto_submitted_mail_skipped() {
+        let mailbox = test_mailbox();
+        let auto = format!("Auto-Submitted: auto-replied\r\n{FIRST}");
+        assert!(mailbox.ingest(1, auto.as_bytes()).is_none());
+    }
+
+    /// A mail from the admin's address with `results` at the top.
+    fn claiming_admin(results: &str) -> String {
+        format!(
+            "{results}From: you@example.com\r\n\
+             To: bot@example.com\r\n\
+             Subject: hi\r\n\
+             Message-ID: <f1@evil.test>\r\n\
+             \r\n\
+             /allow +15550001111\r\n"
+        )
+    }
+
+    #[test]
+    fn test_unauthenticated_mail_dropped() {
+        let mailbox = test_mailbox();
+        // No results at all, or results from a server that isn't ours.
+        for results in [
+            "",
+            "Authentication-Results: mx.evil.test; dmarc=pass header.from=example.com\r\n",
+        ] {
+            assert!(mailbox
+                .ingest(1, claiming_admin(results).as_bytes())
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_forged_from_dropped() {
+        let mailbox = test_mailbox();
+        for results in [
+            "Authentication-Results: mx.example.com; dmarc=fail header.from=example.com\r\n",
+            "Authentication-Results: mx.example.com; dkim=pass header.d=evil.test\r\n",
+            "Authentication-Results: mx.example.com; spf=pass smtp.mailfrom=x@evil.test; dkim=none\r\n",
+            "Authentication-Results: mx.example.com; dmarc=pass header.from=evil.test\r\n",
+            // A pass the sender wrote in themselves, below the server's own verdict.
+            "Authentication-Results: mx.example.com; dmarc=fail header.from=example.com\r\n\
+             Authentication-Results: mx.example.com; dmarc=pass header.from=example.com\r\n",
+        ] {
+            assert!(
+                mailbox.ingest(1, claiming_admin(results).as_bytes()).is_none(),
+                "{results}"
+            );
+        }
+        let genuine =
+            "Authentication-Results: MX.example.com; dmarc=pass header.from=example.com\r\n";
+        let env = mailbox
+            .ingest(1, claiming_admin(genuine).as_bytes())
+            .unwrap();
+        assert_eq!(env.source, "you@example.com");
+    }
+
+    #[test]
+    fn test_parse_auth_results() {
+        let (server, results) = parse_auth_results(
+            "mx.example.com 1; dkim=pass (good (nested) sig) header.d=Example.com; spf=softfail",
+        )
+        .unwrap();
+        assert_eq!(server, "mx.example.com");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].method, "dkim");
+        assert_eq!(
+            results[0].props,
+            vec![("header.d".to_string(), "example.com".to_string())]
+        );
+        assert_eq!(results[1].result, "softfail");
+        assert!(aligned("mail.example.com", "example.com"));
+        assert!(!aligned("badexample.com", "example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_smtp_goes_through_proxy_and_needs_starttls() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        // A proxy that lets the tunnel through to a server without STARTTLS.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut seen = Vec::new();
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 2 {
+                seen.push(line.trim_end().to_string());
+                line.clear();
+            }
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\n\r\n220 smtp.example.com ESMTP\r\n")
+                .await
+                .unwrap();
+            loop {
+                line.clear();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                seen.push(line.trim_end().to_string());
+                let reply: &[u8] = if line.starts_with("EHLO") {
+                    b"250 smtp.example.com\r\n"
+                } else {
+                    b"221 bye\r\n"
+                };
+                stream.get_mut().write_all(reply).await.unwrap();
+            }
+            seen
+        });
+        let mut config = test_mailbox().config;
+        config.smtp_port = 587;
+        let net = NetConfig {
+            proxy: Some(proxy),
+            ..Default::default()
+        };
+        let api = EmailApi::new(Arc::new(Mailbox::new(config, net))).unwrap();
+        let err = api.send_msg("ann@example.com", "hi").await.unwrap_err();
+        assert!(err.to_string().contains("STARTTLS"), "{err}");
+        let seen = server.await.unwrap();
+        assert!(seen[0].starts_with("CONNECT smtp.example.com:587 "));
+        // The password never goes out in the clear.
+        assert!(!seen.iter().any(|line| line.starts_with("AUTH")));
+    }
+
+    #[test]
+    fn test_strip_quoted() {
+        let body = "Sounds good.\n\n> earlier\n> text\nThanks\n\nOn Tue, Bob wrote:\n> more";
+        assert_eq!(strip_quoted(body), "Sounds good.\n\nThanks");
+    }
+
+    #[test]
+    fn test_literal_len_and_quote() {
+        assert_eq!(literal_len("* 1 FETCH (UID 7 BODY[] {42}\r\n"), Some(42));
+        assert_eq!(literal_len("* OK done\r\n"), None);
+        assert_eq!(quote(r#"pa"ss\"#), r#""pa\"ss\\""#);
+    }
+
+    /// Scripted IMAP server on an in-memory pipe.
+    async fn run_fake_server(
+        server: tokio::io::DuplexStream,
+        message: &'static str,
+    ) -> Vec<String> {
+        let mut server = BufReader::new(server);
+        let mut seen = Vec::new();
+        server.get_mut().write_all(b"* OK ready\r\n").await.unwrap();
+        loop {
+            let mut line = String::new();
+            if server.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let (tag, cmd) = line.trim_end().split_once(' ').unwrap();
+            seen.push(cmd.to_string());
+            let body = if cmd == "UID SEARCH UNSEEN" {
+                "* SEARCH 7\r\n".to_string()
+            } else if cmd.starts_with("UID FETCH 7") {
+                format!(
+                    "* 1 FETCH (UID 7 BODY[] {{{}}}\r\n{message})\r\n",
+                    message.len()
+                )
+            } else {
+                String::new()
+            };
+            let reply = format!("{body}{tag} OK done\r\n");
+            server.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            if cmd == "LOGOUT" {
+                break;
+            }
+        }
+        seen
+    }
+
+    #[tokio::test]
+    async fn test_fetch_unseen_against_fake_server() {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server_task = tokio::spawn(run_fake_server(server, FIRST));
+        let mut imap = Imap::new(client);
+        let messages = fetch_unseen(&mut imap, "bot@example.com", "pw")
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, 7);
+        assert_eq!(messages[0].1, FIRST.as_bytes());
+
+        let seen = server_task.await.unwrap();
+        assert_eq!(seen[0], r#"LOGIN "bot@example.com" "pw""#);
+        assert!(seen.contains(&"UID STORE 7 +FLAGS (\\Seen)".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_imap_command_failure() {
+        let (client, server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            server.get_mut().write_all(b"* OK ready\r\n").await.unwrap();
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            let tag = line.split(' ').next().unwrap().to_string();
+            let reply = format!("{tag} NO [AUTHENTICATIONFAILED] bad credentials\r\n");
+            server.get_mut().write_all(reply.as_bytes()).await.unwrap();
+        });
+        let mut imap = Imap::new(client);
+        let err = fetch_unseen(&mut imap, "bot@example.com", "wrong")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("IMAP LOGIN failed"));
+    }
+}
@@ -0,0 +1,474 @@
+//! iMessage transport for macOS (`--features macos-imessage`).
+//!
+//! Messages.app keeps every conversation in `~/Library/Messages/chat.db`.
+//! ccchat polls that database read-only for rows newer than the last one it
+//! saw and sends replies by driving Messages.app through `osascript`. The
+//! terminal running ccchat needs Full Disk Access (to read the database) and
+//! Automation access to Messages (to send).
+//!
+//! People are addressed by the handle Messages uses: a `+E164` number or an
+//! Apple ID email. Group chats map to `group.<base64(chat guid)>`. Messages
+//! you type into your own conversation count as Note to Self.
+
+use async_trait::async_trait;
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::error::AppError;
+use crate::helpers::group_id_from_chat;
+use crate::signal::{AttachmentInfo, ParsedEnvelope};
+use crate::state::State;
+use crate::traits::{attachment_tmp_path, SignalApi};
+
+/// Messages.app marks group chats with this `chat.style`.
+const GROUP_STYLE: i64 = 43;
+const FETCH_BATCH: i64 = 100;
+
+pub(crate) fn default_db_path() -> PathBuf {
+    std::env::var("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join("Library/Messages/chat.db")
+}
+
+/// Read-only view of the Messages database, remembering how far it has read.
+pub(crate) struct ChatDb {
+    pub(crate) path: PathBuf,
+    pub(crate) poll_interval: Duration,
+    account: String,
+    last_rowid: AtomicI64,
+}
+
+#[derive(Debug)]
+struct Row {
+    rowid: i64,
+    text: Option<String>,
+    attributed_body: Option<Vec<u8>>,
+    handle: Option<String>,
+    is_from_me: bool,
+    chat_identifier: Option<String>,
+    chat_style: Option<i64>,
+    chat_guid: Option<String>,
+}
+
+impl ChatDb {
+    /// Open the database and start after its newest message, so history
+    /// isn't replayed on startup.
+    pub(crate) fn open(
+        path: PathBuf,
+        account: &str,
+        poll_interval: Duration,
+    ) -> Result<Self, AppError> {
+        let conn = Self::connect(&path)?;
+        let last: i64 = conn.query_row("SELECT IFNULL(MAX(ROWID), 0) FROM message", [], |r| {
+            r.get(0)
+        })?;
+        Ok(Self {
+            path,
+            poll_interval,
+            account: account.to_string(),
+            last_rowid: AtomicI64::new(last),
+        })
+    }
+
+    fn connect(path: &Path) -> Result<Connection, AppError> {
+        Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(|e| {
+            AppError::Transport(format!(
+                "Cannot open {} (does this terminal have Full Disk Access?): {e}",
+                path.display()
+            ))
+        })
+    }
+
+    /// Poll the database until it can't be read.
+    pub(crate) async fn poll(self: &Arc<Self>, state: &Arc<State>) -> Result<(), AppError> {
+        crate::lifecycle::announce_online(state).await;
+        loop {
+            let db = Arc::clone(self);
+            let envelopes = tokio::task::spawn_blocking(move || db.fetch_new())
+                .await
+                .map_err(|e| AppError::Transport(format!("iMessage poll panicked: {e}")))??;
+            for envelope in envelopes {
+                crate::dispatch_envelope(state, envelope);
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Messages that arrived since the last call, normalised into envelopes.
+    pub(crate) fn fetch_new(&self) -> Result<Vec<ParsedEnvelope>, AppError> {
+        let conn = Self::connect(&self.path)?;
+        let rows = Self::rows_after(&conn, self.last_rowid.load(Ordering::Relaxed))?;
+        let mut envelopes = Vec::new();
+        for row in rows {
+            self.last_rowid.store(row.rowid, Ordering::Relaxed);
+            let attachments = Self::attachments(&conn, row.rowid)?;
+            if let Some(env) = to_envelope(row, attachments, &self.account) {
+                envelopes.push(env);
+            }
+        }
+        Ok(envelopes)
+    }
+
+    fn rows_after(conn: &Connection, after: i64) -> Result<Vec<Row>, AppError> {
+        let mut stmt = conn.prepare(
+            "SELECT m.ROWID, m.text, m.attributedBody, h.id, m.is_from_me,
+                    c.chat_identifier, c.style, c.guid
+             FROM message m
+             LEFT JOIN handle h ON h.ROWID = m.handle_id
+             LEFT JOIN chat_message_join cmj ON cmj.message_id = m.ROWID
+             LEFT JOIN chat c ON c.ROWID = cmj.chat_id
+             WHERE m.ROWID > ?1
+             ORDER BY m.ROWID
+             LIMIT ?2",
+        )?;
+        let rows = stmt
+            .query_map(rusqlite::params![after, FETCH_BATCH], |r| {
+                Ok(Row {
+                    rowid: r.get(0)?,
+                    text: r.get(1)?,
+                    attributed_body: r.get(2)?,
+                    handle: r.get(3)?,
+                    is_from_me: r.get::<_, i64>(4)? != 0,
+                    chat_identifier: r.get(5)?,
+                    chat_style: r.get(6)?,
+                    chat_guid: r.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    fn attachments(conn: &Connection, rowid: i64) -> Result<Vec<AttachmentInfo>, AppError> {
+        let mut stmt = conn.prepare(
+            "SELECT a.filename, a.mime_type, a.transfer_name
+             FROM attachment a
+             JOIN message_attachment_join maj ON maj.attachment_id = a.ROWID
+             WHERE maj.message_id = ?1",
+        )?;
+        let attachments = stmt
+            .query_map([rowid], |r| {
+                let path: Option<String> = r.get(0)?;
+                let mime: Option<String> = r.get(1)?;
+                let name: Option<String> = r.get(2)?;
+                Ok((path, mime, name))
+            })?
+            .filter_map(|row| match row {
+                Ok((Some(path), mime, name)) => Some(Ok(AttachmentInfo {
+                    size: None,
+                    voice_note: path.ends_with(".caf"),
+                    id: path,
+                    content_type: mime.unwrap_or_default(),
+                    filename: name,
+                })),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(attachments)
+    }
+}
+
+fn to_envelope(
+    row: Row,
+    attachments: Vec<AttachmentInfo>,
+    account: &str,
+) -> Option<ParsedEnvelope> {
+    let is_group = row.chat_style == Some(GROUP_STYLE);
+    let is_self_chat = !is_group && row.chat_identifier.as_deref() == Some(account);
+    // Our replies show up as is_from_me rows; in the self chat Messages also
+    // mirrors each one back as an incoming row from our own handle.
+    if row.is_from_me && !is_self_chat {
+        return None;
+    }
+    if !row.is_from_me && row.handle.as_deref() == Some(account) {
+        return None;
+    }
+
+    let text = row
+        .text
+        .filter(|t| !t.is_empty())
+        .or_else(|| {
+            row.attributed_body
+                .as_deref()
+                .and_then(attributed_body_text)
+        })
+        .map(|t| t.replace('\u{fffc}', "").trim().to_string())
+        .unwrap_or_default();
+    let message_text = if !text.is_empty() {
+        text
+    } else if !attachments.is_empty() {
+        "Describe this attachment.".to_string()
+    } else {
+        return None;
+    };
+
+    let source = if row.is_from_me {
+        account.to_string()
+    } else {
+        row.handle?
+    };
+    Some(ParsedEnvelope {
+        source,
+        message_text,
+        is_sync: row.is_from_me,
+        source_uuid: String::new(),
+        source_name: "unknown".to_string(),
+        attachments,
+        group_id: if is_group { row.chat_guid } else { None },
+        thread_id: None,
+        sent_at: None,
+    })
+}
+
+/// Recent macOS versions leave `message.text` empty and store the text in
+/// `attributedBody`, an NSKeyedArchiver "typedstream". The string follows the
+/// first `NSString` class marker as a length-prefixed UTF-8 run.
+pub(crate) fn attributed_body_text(blob: &[u8]) -> Option<String> {
+    const MARKER: &[u8] = b"NSString";
+    let start = blob.windows(MARKER.len()).position(|w| w == MARKER)? + MARKER.len();
+    // Class version and the `+` type tag precede the length.
+    let rest = blob.get(start + 5..)?;
+    let (len, body) = match *rest.first()? {
+        0x81 => (
+            u16::from_le_bytes([*rest.get(1)?, *rest.get(2)?]) as usize,
+            rest.get(3..)?,
+        ),
+        0x82 => (
+            u32::from_le_bytes([*rest.get(1)?, *rest.get(2)?, *rest.get(3)?, *rest.get(4)?])
+                as usize,
+            rest.get(5..)?,
+        ),
+        n => (n as usize, rest.get(1..)?),
+    };
+    String::from_utf8(body.get(..len)?.to_vec()).ok()
+}
+
+/// AppleScript that sends `item 1 of argv` (text, or a file path when
+/// `as_file`) to `item 2 of argv` (a handle, or a group chat guid).
+fn send_script(group: bool, as_file: bool) -> String {
+    let payload = if as_file {
+        "POSIX file (item 1 of argv)"
+    } else {
+        "item 1 of argv"
+    };
+    let target = if group {
+        "chat id (item 2 of argv)".to_string()
+    } else {
+        "participant (item 2 of argv) of (1st account whose service type = iMessage)".to_string()
+    };
+    format!("on run argv\ntell application \"Messages\" to send {payload} to {target}\nend run")
+}
+
+pub(crate) struct IMessageApi;
+
+impl IMessageApi {
+    async fn osascript(
+        &self,
+        recipient: &str,
+        payload: &str,
+        as_file: bool,
+    ) -> Result<(), AppError> {
+        let (target, group) = match group_id_from_chat(recipient) {
+            Some(guid) => (guid, true),
+            None => (recipient.to_string(), false),
+        };
+        let output = Command::new("osascript")
+            .arg("-e")
+            .arg(send_script(group, as_file))
+            .arg(payload)
+            .arg(&target)
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(AppError::Transport(format!(
+                "Messages.app send failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SignalApi for IMessageApi {
+    async fn send_msg(&self, recipient: &str, message: &str) -> Result<(), AppError> {
+        self.osascript(recipient, message, false).await
+    }
+
+    async fn set_typing(&self, _recipient: &str, _typing: bool) -> Result<(), AppError> {
+        // Messages.app has no scriptable typing indicator.
+        Ok(())
+    }
+
+    async fn download_attachment(&self, attachment: &AttachmentInfo) -> Result<PathBuf, AppError> {
+        // Attachments are already on disk; copy so cleanup can't touch the original.
+        let source = match attachment.id.strip_prefix("~/") {
+            Some(rest) => PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(rest),
+            None => PathBuf::from(&attachment.id),
+        };
+        let path = attachment_tmp_path(attachment);
+        std::fs::copy(&source, &path)?;
+        debug!(
+            "Copied attachment {} to {}",
+            source.display(),
+            path.display()
+        );
+        Ok(path)
+    }
+
+    async fn send_attachment(
+        &self,
+        recipient: &str,
+        data: &[u8],
+        _content_type: &str,
+        filename: &str,
+    ) -> Result<(), AppError> {
+        let dir = PathBuf::from(crate::constants::TMP_DIR).join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(filename);
+        std::fs::write(&path, data)?;
+        let result = self
+            .osascript(recipient, &path.to_string_lossy(), true)
+            .await;
+        // Messages copies the file when it accepts the send; give it a moment.
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let _ = std::fs::remove_dir_all(&dir);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "+1234567890";
+
+    fn test_db() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ccchat_imessage_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+             CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, chat_identifier TEXT, style INTEGER, guid TEXT);
+             CREATE TABLE message (ROWID INTEGER PRIMARY KEY, text TEXT, attributedBody BLOB,
+                                   handle_id INTEGER, is_from_me INTEGER);
+             CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+             CREATE TABLE attachment (ROWID INTEGER PRIMARY KEY, filename TEXT, mime_type TEXT, transfer_name TEXT);
+             CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);
+             INSERT INTO handle VALUES (1, '+15550001111'), (2, '+1234567890');
+             INSERT INTO chat VALUES (1, '+15550001111', 45, 'iMessage;-;+15550001111'),
+                                     (2, 'chat99', 43, 'iMessage;+;chat99'),
+                                     (3, '+1234567890', 45, 'iMessage;-;+1234567890');
+             INSERT INTO message VALUES (1, 'old history', NULL, 1, 0);
+             INSERT INTO chat_message_join VALUES (1, 1);",
+        )
+        .unwrap();
+        path
+    }
+
+    fn insert(path: &Path, rowid: i64, text: Option<&str>, handle: i64, from_me: bool, chat: i64) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute(
+            "INSERT INTO message VALUES (?1, ?2, NULL, ?3, ?4)",
+            rusqlite::params![rowid, text, handle, from_me as i64],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO chat_message_join VALUES (?1, ?2)",
+            rusqlite::params![chat, rowid],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_fetch_new_skips_history_and_own_replies() {
+        let path = test_db();
+        let db = ChatDb::open(path.clone(), ACCOUNT, Duration::from_secs(1)).unwrap();
+        insert(&path, 2, Some("hello"), 1, false, 1);
+        insert(&path, 3, Some("our reply"), 1, true, 1);
+        insert(&path, 4, Some("in the group"), 1, false, 2);
+
+        let envs = db.fetch_new().unwrap();
+        assert_eq!(envs.len(), 2);
+        assert_eq!(envs[0].source, "+15550001111");
+        assert_eq!(envs[0].message_text, "hello");
+        assert_eq!(envs[1].group_id.as_deref(), Some("iMessage;+;chat99"));
+        assert!(
+            db.fetch_new().unwrap().is_empty(),
+            "rows are only read once"
+        );
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_self_chat_is_note_to_self_without_mirror() {
+        let path = test_db();
+        let db = ChatDb::open(path.clone(), ACCOUNT, Duration::from_secs(1)).unwrap();
+        insert(&path, 2, Some("/status"), 2, true, 3);
+        insert(&path, 3, Some("/status"), 2, false, 3);
+        let envs = db.fetch_new().unwrap();
+        assert_eq!(envs.len(), 1);
+        assert!(envs[0].is_sync);
+        assert_eq!(envs[0].source, ACCOUNT);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_attributed_body_text() {
+        let mut blob = b"\x04\x0bstreamtyped\x81\xe8\x03\x84\x01@\x84\x84\x84\x12NSAttributedString\x00\x84\x84\x08NSObject\x00\x85\x92\x84\x84\x84\x08NSString".to_vec();
+        blob.extend_from_slice(b"\x01\x94\x84\x01+");
+        blob.push(5);
+        blob.extend_from_slice(b"hello\x86\x84");
+        assert_eq!(attributed_body_text(&blob).as_deref(), Some("hello"));
+
+        let long = "x".repeat(300);
+        let mut blob = b"NSString\x01\x94\x84\x01+\x81".to_vec();
+        blob.extend_from_slice(&300u16.to_le_bytes());
+        blob.extend_from_slice(long.as_bytes());
+        assert_eq!(attributed_body_text(&blob), Some(long));
+
+        assert_eq!(attributed_body_text(b"no marker"), None);
+    }
+
+    #[tokio::test]
+    async fn test_download_copies_into_tmp_dir() {
+        let dir = std::env::temp_dir().join(format!("ccchat_imessage_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("IMG_0001.heic");
+        std::fs::write(&source, b"image bytes").unwrap();
+        let attachment = AttachmentInfo {
+            id: source.to_string_lossy().into_owned(),
+            content_type: "image/heic".to_string(),
+            filename: Some("IMG_0001.heic".to_string()),
+            size: None,
+            voice_note: false,
+        };
+
+        let path = IMessageApi.download_attachment(&attachment).await.unwrap();
+        assert_eq!(path.parent(), Some(Path::new(crate::constants::TMP_DIR)));
+        assert!(path.to_string_lossy().ends_with(".heic"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"image bytes");
+        assert!(source.exists(), "the original is left alone");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_send_script_targets() {
+        let direct = send_script(false, false);
+        assert!(direct.contains("send item 1 of argv to participant (item 2 of argv)"));
+        let group_file = send_script(true, true);
+        assert!(group_file.contains("send POSIX file (item 1 of argv) to chat id (item 2 of argv)"));
+    }
+}
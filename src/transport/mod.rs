@@ -0,0 +1,298 @@
+//! Messaging transports (`--transport`).
+//!
+//! Every transport feeds the same core: inbound events are normalised into a
+//! [`ParsedEnvelope`] and replies go out through the [`SignalApi`] trait, so
+//! sessions, budgets and commands don't care which messenger is on the other
+//! end. Sender ids stay in `+E164` form wherever the network allows, which
+//! keeps the allowed list and admin account transport-agnostic.
+
+pub(crate) mod discord;
+pub(crate) mod email;
+#[cfg(feature = "macos-imessage")]
+pub(crate) mod imessage;
+pub(crate) mod slack;
+pub(crate) mod stdin;
+pub(crate) mod whatsapp;
+pub(crate) mod xmpp;
+
+use reqwest::Client;
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::failover::ApiEndpoints;
+use crate::helpers::{split_message, split_message_fenced};
+use crate::pacer::SendPacer;
+use crate::signal::{parse_envelope, parse_remote_delete, Deletion, ParsedEnvelope};
+use crate::traits::{SignalApi, SignalApiImpl};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Transport {
+    /// Signal via signal-cli-api.
+    #[default]
+    Signal,
+    /// WhatsApp via a whatsmeow-based bridge.
+    #[value(name = "whatsapp")]
+    WhatsApp,
+    /// Email: IMAP inbox polling, SMTP replies.
+    Email,
+    /// Slack app over Socket Mode.
+    Slack,
+    /// Discord bot over the gateway.
+    Discord,
+    /// XMPP (Jabber) account.
+    Xmpp,
+    /// Terminal REPL for local testing: prompts on stdin, replies on stdout.
+    Stdin,
+    /// iMessage via the local Messages.app database (macOS only).
+    #[cfg(feature = "macos-imessage")]
+    #[value(name = "imessage")]
+    IMessage,
+}
+
+/// Settings some transports need beyond the API URL, checked at startup.
+#[derive(Default)]
+pub(crate) struct Settings {
+    /// Shared by the IMAP poller and the SMTP sender.
+    pub(crate) mailbox: Option<Arc<email::Mailbox>>,
+    pub(crate) slack: Option<slack::SlackConfig>,
+    pub(crate) discord: Option<Arc<discord::Link>>,
+    /// Shared by the XMPP listener and sender.
+    pub(crate) xmpp: Option<Arc<xmpp::Link>>,
+    #[cfg(feature = "macos-imessage")]
+    pub(crate) imessage: Option<Arc<imessage::ChatDb>>,
+}
+
+impl Settings {
+    /// Where a transport that reads locally or polls gets its messages from,
+    /// shown in place of a bridge URL.
+    pub(crate) fn local_api_url(&self) -> Option<String> {
+        if let Some(mailbox) = &self.mailbox {
+            return Some(format!(
+                "imaps://{}:{}",
+                mailbox.config.imap_host, mailbox.config.imap_port
+            ));
+        }
+        if let Some(link) = &self.xmpp {
+            let (host, port) = link.config.server();
+            return Some(format!("xmpp://{host}:{port}"));
+        }
+        #[cfg(feature = "macos-imessage")]
+        if let Some(chat_db) = &self.imessage {
+            return Some(format!("file://{}", chat_db.path.display()));
+        }
+        None
+    }
+}
+
+impl Transport {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Transport::Signal => "signal",
+            Transport::WhatsApp => "whatsapp",
+            Transport::Email => "email",
+            Transport::Slack => "slack",
+            Transport::Discord => "discord",
+            Transport::Xmpp => "xmpp",
+            Transport::Stdin => "stdin",
+            #[cfg(feature = "macos-imessage")]
+            Transport::IMessage => "imessage",
+        }
+    }
+
+    /// Executable of the bridge process ccchat manages for this transport
+    /// (`None` when it talks to the network directly).
+    pub(crate) fn bridge_binary(self) -> Option<&'static str> {
+        match self {
+            Transport::Signal => Some("signal-cli-api"),
+            Transport::WhatsApp => Some(whatsapp::BRIDGE_BINARY),
+            _ => None,
+        }
+    }
+
+    /// API used when `--api-url` isn't given and there is no bridge.
+    pub(crate) fn default_api_url(self) -> Option<&'static str> {
+        match self {
+            Transport::Slack => Some(slack::API_URL),
+            Transport::Discord => Some(discord::API_URL),
+            Transport::Stdin => Some(stdin::API_URL),
+            _ => None,
+        }
+    }
+
+    /// Longest single outbound message before replies are split.
+    pub(crate) fn max_message_len(self) -> usize {
+        match self {
+            Transport::Email => crate::constants::MAX_EMAIL_BODY_LEN,
+            Transport::Discord => crate::constants::MAX_DISCORD_MSG_LEN,
+            _ => crate::constants::MAX_SIGNAL_MSG_LEN,
+        }
+    }
+
+    /// Split a reply into messages this transport will accept. Discord
+    /// renders Markdown, so code blocks cut between parts are kept intact.
+    pub(crate) fn split_reply(self, text: &str) -> Vec<String> {
+        match self {
+            Transport::Discord => split_message_fenced(text, self.max_message_len()),
+            _ => split_message(text, self.max_message_len()),
+        }
+    }
+
+    /// Whether the messenger renders Markdown code blocks in monospace.
+    pub(crate) fn renders_markdown(self) -> bool {
+        matches!(self, Transport::Slack | Transport::Discord)
+    }
+
+    /// Whether emphasis and inline code go out as text style ranges
+    /// (see [`crate::styles`]) instead of Markdown markers.
+    pub(crate) fn has_text_styles(self) -> bool {
+        self == Transport::Signal
+    }
+
+    /// WebSocket URL the bridge streams inbound messages on. Slack hands out
+    /// a fresh URL per connection instead (see [`slack::open_socket`]), and
+    /// Discord runs its own gateway protocol.
+    pub(crate) fn receive_url(self, api_url: &str, account: &str) -> String {
+        let ws_base = api_url.replacen("http", "ws", 1);
+        match self {
+            Transport::Signal => format!("{ws_base}/v1/receive/{account}"),
+            Transport::WhatsApp => format!("{ws_base}/v1/events"),
+            _ => String::new(),
+        }
+    }
+
+    /// Normalise one inbound WebSocket frame. `None` means skip it.
+    pub(crate) fn parse_event(self, event: &Value, account: &str) -> Option<ParsedEnvelope> {
+        match self {
+            Transport::Signal => {
+                // JSON-RPC mode wraps the envelope in `params`.
+                let envelope = if event.get("params").is_some() {
+                    &event["params"]
+                } else {
+                    event
+                };
+                parse_envelope(envelope)
+            }
+            Transport::WhatsApp => whatsapp::parse_event(event, account),
+            Transport::Slack => slack::parse_event(event),
+            _ => None,
+        }
+    }
+
+    /// A "delete for everyone" in an inbound frame. Only Signal has them.
+    pub(crate) fn parse_deletion(self, event: &Value) -> Option<Deletion> {
+        if self != Transport::Signal {
+            return None;
+        }
+        let envelope = if event.get("params").is_some() {
+            &event["params"]
+        } else {
+            event
+        };
+        parse_remote_delete(envelope)
+    }
+
+    /// Reply the transport expects on the socket for an inbound frame.
+    pub(crate) fn ack(self, event: &Value) -> Option<String> {
+        match self {
+            Transport::Slack => slack::ack(event),
+            _ => None,
+        }
+    }
+
+    /// Outbound API for replies, typing and attachments.
+    pub(crate) fn messaging_api(
+        self,
+        http: Client,
+        api: Arc<ApiEndpoints>,
+        pacer: Arc<SendPacer>,
+        account: String,
+        settings: &Settings,
+    ) -> Result<Box<dyn SignalApi>, AppError> {
+        // Only signal-cli-api fails over; other bridges stay on the first URL
+        let api_url = api.current();
+        Ok(match self {
+            Transport::Signal => Box::new(SignalApiImpl {
+                http,
+                api,
+                account,
+                pacer,
+            }),
+            Transport::WhatsApp => Box::new(whatsapp::WhatsAppApi { http, api_url }),
+            Transport::Email => {
+                let mailbox = settings
+                    .mailbox
+                    .as_ref()
+                    .ok_or_else(|| AppError::from("Email transport is not configured"))?;
+                Box::new(email::EmailApi::new(Arc::clone(mailbox))?)
+            }
+            Transport::Slack => {
+                let config = settings
+                    .slack
+                    .as_ref()
+                    .ok_or_else(|| AppError::from("Slack transport is not configured"))?;
+                Box::new(slack::SlackApi::new(http, api_url, config))
+            }
+            Transport::Discord => {
+                let link = settings
+                    .discord
+                    .as_ref()
+                    .ok_or_else(|| AppError::from("Discord transport is not configured"))?;
+                Box::new(discord::DiscordApi::new(http, api_url, &link.config))
+            }
+            Transport::Xmpp => {
+                let link = settings
+                    .xmpp
+                    .as_ref()
+                    .ok_or_else(|| AppError::from("XMPP transport is not configured"))?;
+                Box::new(xmpp::XmppApi {
+                    link: Arc::clone(link),
+                })
+            }
+            Transport::Stdin => Box::new(stdin::StdinApi),
+            #[cfg(feature = "macos-imessage")]
+            Transport::IMessage => Box::new(imessage::IMessageApi),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receive_url_per_transport() {
+        assert_eq!(
+            Transport::Signal.receive_url("http://127.0.0.1:8080", "+1234567890"),
+            "ws://127.0.0.1:8080/v1/receive/+1234567890"
+        );
+        assert_eq!(
+            Transport::WhatsApp.receive_url("https://wa.example", "+1234567890"),
+            "wss://wa.example/v1/events"
+        );
+    }
+
+    #[test]
+    fn test_only_slack_acks_frames() {
+        let frame = serde_json::json!({"envelope_id": "e1", "type": "hello"});
+        assert!(Transport::Slack.ack(&frame).is_some());
+        assert!(Transport::Signal.ack(&frame).is_none());
+    }
+
+    #[test]
+    fn test_signal_parse_event_unwraps_jsonrpc_params() {
+        let event = serde_json::json!({
+            "params": {
+                "envelope": {
+                    "source": "+15550001111",
+                    "dataMessage": {"message": "hi"}
+                }
+            }
+        });
+        let parsed = Transport::Signal
+            .parse_event(&event, "+1234567890")
+            .expect("parsed");
+        assert_eq!(parsed.source, "+15550001111");
+        assert_eq!(parsed.message_text, "hi");
+    }
+}
@@ -0,0 +1,584 @@
+//! Slack transport over Socket Mode.
+//!
+//! ccchat opens a WebSocket with the app-level token (`xapp-…`) via
+//! `apps.connections.open`, so no public URL is needed. Every frame Slack
+//! pushes carries an `envelope_id` that must be acknowledged within three
+//! seconds or it is redelivered. Replies, uploads and file lookups use the
+//! Web API with the bot token (`xoxb-…`).
+//!
+//! People are addressed by Slack user id (`U…`), which is what goes in the
+//! allowed list and `--account`. Channels the bot is in are groups
+//! (`group.<base64(channel id)>`) and follow the usual `/bind` rules.
+//!
+//! Slash commands are mapped onto ccchat commands: `/ccchat model sonnet`
+//! becomes `/model sonnet`, and any other command registered for the app
+//! (e.g. `/reset`) is passed through as typed.
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use tracing::{debug, error};
+
+use crate::error::AppError;
+use crate::helpers::group_id_from_chat;
+use crate::signal::{AttachmentInfo, ParsedEnvelope};
+use crate::traits::{attachment_tmp_path, SignalApi};
+
+pub(crate) const API_URL: &str = "https://slack.com/api";
+
+/// Umbrella slash command: its first word names the ccchat command.
+const APP_COMMAND: &str = "/ccchat";
+
+#[derive(Clone, Debug)]
+pub(crate) struct SlackConfig {
+    /// App-level token with `connections:write`, used for Socket Mode.
+    pub(crate) app_token: String,
+    /// Bot token used for the Web API.
+    pub(crate) bot_token: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MessageEvent {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    subtype: Option<String>,
+    #[serde(default)]
+    channel: String,
+    #[serde(default)]
+    channel_type: String,
+    #[serde(default)]
+    user: String,
+    #[serde(default)]
+    bot_id: Option<String>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    files: Vec<File>,
+}
+
+#[derive(Debug, Deserialize)]
+struct File {
+    id: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    mimetype: String,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    subtype: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SlashCommand {
+    #[serde(default)]
+    command: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    user_id: String,
+    #[serde(default)]
+    user_name: Option<String>,
+    #[serde(default)]
+    channel_id: String,
+}
+
+/// Ask Slack for a fresh Socket Mode URL (they are single-use).
+pub(crate) async fn open_socket(
+    http: &Client,
+    api_url: &str,
+    app_token: &str,
+) -> Result<String, AppError> {
+    let resp = call(http, api_url, "apps.connections.open", app_token, None).await?;
+    resp["url"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| AppError::Transport("apps.connections.open returned no url".into()))
+}
+
+/// Acknowledgement to write back for a Socket Mode frame, if it needs one.
+pub(crate) fn ack(frame: &Value) -> Option<String> {
+    let id = frame["envelope_id"].as_str()?;
+    Some(serde_json::json!({ "envelope_id": id }).to_string())
+}
+
+/// Normalise one Socket Mode frame. Bot messages (including our own replies),
+/// edits and other event types are skipped.
+pub(crate) fn parse_event(frame: &Value) -> Option<ParsedEnvelope> {
+    match frame["type"].as_str()? {
+        "events_api" => parse_message(&frame["payload"]["event"]),
+        "slash_commands" => parse_slash_command(&frame["payload"]),
+        _ => None,
+    }
+}
+
+fn parse_message(event: &Value) -> Option<ParsedEnvelope> {
+    let ev: MessageEvent = match serde_json::from_value(event.clone()) {
+        Ok(ev) => ev,
+        Err(e) => {
+            debug!("Skipping unparseable Slack event: {e}");
+            return None;
+        }
+    };
+    if ev.kind != "message" || ev.bot_id.is_some() || ev.user.is_empty() {
+        return None;
+    }
+    if ev.subtype.as_deref().is_some_and(|s| s != "file_share") {
+        return None;
+    }
+
+    let text = strip_mentions(&unescape(&ev.text));
+    let message_text = if !text.is_empty() {
+        text
+    } else if !ev.files.is_empty() {
+        "Describe this attachment.".to_string()
+    } else {
+        return None;
+    };
+
+    Some(ParsedEnvelope {
+        source: ev.user,
+        message_text,
+        is_sync: false,
+        source_uuid: String::new(),
+        source_name: "unknown".to_string(),
+        attachments: ev
+            .files
+            .into_iter()
+            .map(|f| AttachmentInfo {
+                id: f.id,
+                content_type: f.mimetype,
+                filename: f.name,
+                size: f.size,
+                voice_note: f.subtype.as_deref() == Some("slack_audio"),
+            })
+            .collect(),
+        group_id: (ev.channel_type != "im").then_some(ev.channel),
+        thread_id: None,
+        sent_at: None,
+    })
+}
+
+fn parse_slash_command(payload: &Value) -> Option<ParsedEnvelope> {
+    let cmd: SlashCommand = serde_json::from_value(payload.clone()).ok()?;
+    if cmd.user_id.is_empty() || cmd.command.is_empty() {
+        return None;
+    }
+    Some(ParsedEnvelope {
+        source: cmd.user_id,
+        message_text: map_slash_command(&cmd.command, &unescape(&cmd.text)),
+        is_sync: false,
+        source_uuid: String::new(),
+        source_name: cmd.user_name.unwrap_or_else(|| "unknown".to_string()),
+        attachments: Vec::new(),
+        // DM channel ids start with `D`; anything else is a shared channel.
+        group_id: (!cmd.channel_id.starts_with('D')).then_some(cmd.channel_id),
+        thread_id: None,
+        sent_at: None,
+    })
+}
+
+/// `/ccchat model sonnet` -> `/model sonnet`; `/reset` -> `/reset`.
+pub(crate) fn map_slash_command(command: &str, text: &str) -> String {
+    let text = text.trim();
+    if command == APP_COMMAND {
+        return match text.split_once(char::is_whitespace) {
+            _ if text.is_empty() => "/help".to_string(),
+            Some((name, rest)) => format!("/{} {}", name.trim_start_matches('/'), rest.trim()),
+            None => format!("/{}", text.trim_start_matches('/')),
+        };
+    }
+    if text.is_empty() {
+        command.to_string()
+    } else {
+        format!("{command} {text}")
+    }
+}
+
+/// Drop leading `<@U…>` mentions so "@ccchat hello" reads as "hello".
+fn strip_mentions(text: &str) -> String {
+    let mut rest = text.trim_start();
+    while let Some(tail) = rest.strip_prefix("<@") {
+        match tail.find('>') {
+            Some(end) => rest = tail[end + 1..].trim_start(),
+            None => break,
+        }
+    }
+    rest.trim_end().to_string()
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// One Web API call with a JSON body (or none).
+async fn call(
+    http: &Client,
+    api_url: &str,
+    method: &str,
+    token: &str,
+    body: Option<Value>,
+) -> Result<Value, AppError> {
+    let mut req = http.post(format!("{api_url}/{method}")).bearer_auth(token);
+    if let Some(body) = body {
+        req = req.json(&body);
+    }
+    check_response(req.send().await?, method).await
+}
+
+/// Slack reports failures as `{"ok": false, "error": …}` with a 200 status,
+/// so both layers are checked.
+async fn check_response(resp: reqwest::Response, method: &str) -> Result<Value, AppError> {
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(AppError::Transport(format!(
+            "Slack {method} failed: {status}"
+        )));
+    }
+    let json: Value = resp.json().await?;
+    if json["ok"].as_bool() != Some(true) {
+        let reason = json["error"].as_str().unwrap_or("unknown error");
+        error!(method, reason, "Slack API call failed");
+        return Err(AppError::Transport(format!(
+            "Slack {method} failed: {reason}"
+        )));
+    }
+    Ok(json)
+}
+
+pub(crate) struct SlackApi {
+    http: Client,
+    api_url: String,
+    bot_token: String,
+    /// User id -> DM channel id, from `conversations.open`.
+    dm_channels: DashMap<String, String>,
+}
+
+impl SlackApi {
+    pub(crate) fn new(http: Client, api_url: String, config: &SlackConfig) -> Self {
+        Self {
+            http,
+            api_url,
+            bot_token: config.bot_token.clone(),
+            dm_channels: DashMap::new(),
+        }
+    }
+
+    async fn call(&self, method: &str, body: Value) -> Result<Value, AppError> {
+        call(
+            &self.http,
+            &self.api_url,
+            method,
+            &self.bot_token,
+            Some(body),
+        )
+        .await
+    }
+
+    /// For methods that only take form arguments (file lookups and uploads).
+    async fn call_query(&self, method: &str, args: &[(&str, &str)]) -> Result<Value, AppError> {
+        let resp = self
+            .http
+            .post(format!("{}/{method}", self.api_url))
+            .bearer_auth(&self.bot_token)
+            .query(args)
+            .send()
+            .await?;
+        check_response(resp, method).await
+    }
+
+    /// Channel id to post into: the group's channel, or the DM with a user.
+    async fn channel_for(&self, recipient: &str) -> Result<String, AppError> {
+        if let Some(channel) = group_id_from_chat(recipient) {
+            return Ok(channel);
+        }
+        if let Some(channel) = self.dm_channels.get(recipient) {
+            return Ok(channel.clone());
+        }
+        let resp = self
+            .call(
+                "conversations.open",
+                serde_json::json!({ "users": recipient }),
+            )
+            .await?;
+        let channel = resp["channel"]["id"]
+            .as_str()
+            .ok_or_else(|| AppError::Transport("conversations.open returned no channel".into()))?
+            .to_string();
+        self.dm_channels
+            .insert(recipient.to_string(), channel.clone());
+        Ok(channel)
+    }
+}
+
+#[async_trait]
+impl SignalApi for SlackApi {
+    async fn send_msg(&self, recipient: &str, message: &str) -> Result<(), AppError> {
+        let channel = self.channel_for(recipient).await?;
+        self.call(
+            "chat.postMessage",
+            serde_json::json!({ "channel": channel, "text": escape(message) }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn set_typing(&self, _recipient: &str, _typing: bool) -> Result<(), AppError> {
+        // Slack has no typing indicator for bots outside the legacy RTM API.
+        Ok(())
+    }
+
+    async fn download_attachment(&self, attachment: &AttachmentInfo) -> Result<PathBuf, AppError> {
+        let info = self
+            .call_query("files.info", &[("file", &attachment.id)])
+            .await?;
+        let url = info["file"]["url_private_download"]
+            .as_str()
+            .ok_or_else(|| {
+                AppError::Transport(format!("Slack file {} has no download url", attachment.id))
+            })?;
+        let resp = self
+            .http
+            .get(url)
+            .bearer_auth(&self.bot_token)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(AppError::Transport(format!(
+                "Failed to download Slack file {}: {}",
+                attachment.id,
+                resp.status()
+            )));
+        }
+        let path = attachment_tmp_path(attachment);
+        let bytes = resp.bytes().await?;
+        std::fs::write(&path, &bytes)?;
+        debug!("Downloaded file {} to {}", attachment.id, path.display());
+        Ok(path)
+    }
+
+    async fn send_attachment(
+        &self,
+        recipient: &str,
+        data: &[u8],
+        _content_type: &str,
+        filename: &str,
+    ) -> Result<(), AppError> {
+        let channel = self.channel_for(recipient).await?;
+        let upload = self
+            .call_query(
+                "files.getUploadURLExternal",
+                &[("filename", filename), ("length", &data.len().to_string())],
+            )
+            .await?;
+        let (Some(upload_url), Some(file_id)) =
+            (upload["upload_url"].as_str(), upload["file_id"].as_str())
+        else {
+            return Err(AppError::Transport(
+                "files.getUploadURLExternal returned no upload url".into(),
+            ));
+        };
+        let resp = self
+            .http
+            .post(upload_url)
+            .body(data.to_vec())
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(AppError::Transport(format!(
+                "Slack file upload failed: {}",
+                resp.status()
+            )));
+        }
+        self.call(
+            "files.completeUploadExternal",
+            serde_json::json!({
+                "files": [{ "id": file_id, "title": filename }],
+                "channel_id": channel,
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn events_api(event: Value) -> Value {
+        json!({
+            "envelope_id": "env-1",
+            "type": "events_api",
+            "payload": { "type": "event_callback", "event": event }
+        })
+    }
+
+    fn api(server: &MockServer) -> SlackApi {
+        SlackApi::new(
+            Client::new(),
+            server.uri(),
+            &SlackConfig {
+                app_token: "xapp-test".into(),
+                bot_token: "xoxb-test".into(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_ack_echoes_envelope_id() {
+        let frame = events_api(json!({}));
+        assert_eq!(ack(&frame).as_deref(), Some(r#"{"envelope_id":"env-1"}"#));
+        assert!(ack(&json!({"type": "hello"})).is_none());
+    }
+
+    #[test]
+    fn test_parse_direct_message() {
+        let frame = events_api(json!({
+            "type": "message",
+            "channel": "D0123",
+            "channel_type": "im",
+            "user": "U0AAA",
+            "text": "is 1 &lt; 2?"
+        }));
+        let parsed = parse_event(&frame).unwrap();
+        assert_eq!(parsed.source, "U0AAA");
+        assert_eq!(parsed.message_text, "is 1 < 2?");
+        assert!(parsed.group_id.is_none());
+    }
+
+    #[test]
+    fn test_parse_channel_message_strips_mention_and_keeps_files() {
+        let frame = events_api(json!({
+            "type": "message",
+            "subtype": "file_share",
+            "channel": "C0123",
+            "channel_type": "channel",
+            "user": "U0AAA",
+            "text": "<@U0BOT> ",
+            "files": [{"id": "F1", "name": "clip.m4a", "mimetype": "audio/mp4", "subtype": "slack_audio"}]
+        }));
+        let parsed = parse_event(&frame).unwrap();
+        assert_eq!(parsed.group_id.as_deref(), Some("C0123"));
+        assert_eq!(parsed.message_text, "Describe this attachment.");
+        assert!(parsed.attachments[0].voice_note);
+    }
+
+    #[test]
+    fn test_parse_skips_bots_and_edits() {
+        let bot = events_api(json!({
+            "type": "message", "channel": "D1", "channel_type": "im",
+            "user": "U0BOT", "bot_id": "B1", "text": "reply"
+        }));
+        assert!(parse_event(&bot).is_none());
+        let edit = events_api(json!({
+            "type": "message", "subtype": "message_changed", "channel": "D1",
+            "channel_type": "im", "user": "U0AAA", "text": "x"
+        }));
+        assert!(parse_event(&edit).is_none());
+        assert!(parse_event(&json!({"type": "hello"})).is_none());
+    }
+
+    #[test]
+    fn test_parse_slash_command_in_dm() {
+        let frame = json!({
+            "envelope_id": "env-2",
+            "type": "slash_commands",
+            "payload": {
+                "command": "/ccchat",
+                "text": "model sonnet",
+                "user_id": "U0AAA",
+                "user_name": "ann",
+                "channel_id": "D0123"
+            }
+        });
+        let parsed = parse_event(&frame).unwrap();
+        assert_eq!(parsed.message_text, "/model sonnet");
+        assert_eq!(parsed.source_name, "ann");
+        assert!(parsed.group_id.is_none());
+    }
+
+    #[test]
+    fn test_map_slash_command() {
+        assert_eq!(map_slash_command("/ccchat", ""), "/help");
+        assert_eq!(map_slash_command("/ccchat", "reset"), "/reset");
+        assert_eq!(map_slash_command("/ccchat", "/model  opus"), "/model opus");
+        assert_eq!(map_slash_command("/reset", ""), "/reset");
+        assert_eq!(map_slash_command("/model", " haiku "), "/model haiku");
+    }
+
+    #[tokio::test]
+    async fn test_open_socket_uses_app_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/apps.connections.open"))
+            .and(header("authorization", "Bearer xapp-test"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"ok": true, "url": "wss://wss.slack.test/link"})),
+            )
+            .mount(&server)
+            .await;
+        let url = open_socket(&Client::new(), &server.uri(), "xapp-test")
+            .await
+            .unwrap();
+        assert_eq!(url, "wss://wss.slack.test/link");
+    }
+
+    #[tokio::test]
+    async fn test_send_msg_opens_dm_once() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/conversations.open"))
+            .and(body_json(json!({"users": "U0AAA"})))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"ok": true, "channel": {"id": "D0123"}})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat.postMessage"))
+            .and(header("authorization", "Bearer xoxb-test"))
+            .and(body_json(json!({"channel": "D0123", "text": "a &lt; b"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"ok": true})))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let api = api(&server);
+        api.send_msg("U0AAA", "a < b").await.unwrap();
+        api.send_msg("U0AAA", "a < b").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_error_in_body_is_reported() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat.postMessage"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"ok": false, "error": "not_in_channel"})),
+            )
+            .mount(&server)
+            .await;
+        let group = crate::helpers::group_chat_id("C0123");
+        let err = api(&server).send_msg(&group, "hi").await.unwrap_err();
+        assert!(err.to_string().contains("not_in_channel"));
+    }
+}
@@ -0,0 +1,148 @@
+//! Terminal REPL transport (`--transport stdin`) for local testing.
+//!
+//! Each line read from stdin is a message from the account owner, so admin
+//! commands work and no allowed list is needed. Replies are printed to
+//! stdout (logs go to stderr) and attachments ccchat sends are written to the
+//! temp dir. Everything past the envelope — sessions, budgets, commands,
+//! chunking — is the same code path the messengers use.
+
+use async_trait::async_trait;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::error::AppError;
+use crate::signal::{AttachmentInfo, ParsedEnvelope};
+use crate::state::State;
+use crate::traits::SignalApi;
+
+/// Shown in place of a bridge URL.
+pub(crate) const API_URL: &str = "stdin://";
+
+const PROMPT: &str = "> ";
+
+/// One input line as a message from the account owner. Blank lines are skipped.
+pub(crate) fn envelope(line: &str, account: &str) -> Option<ParsedEnvelope> {
+    let text = line.trim();
+    if text.is_empty() {
+        return None;
+    }
+    Some(ParsedEnvelope {
+        source: account.to_string(),
+        message_text: text.to_string(),
+        is_sync: false,
+        source_uuid: String::new(),
+        source_name: "stdin".to_string(),
+        attachments: Vec::new(),
+        group_id: None,
+        thread_id: None,
+        sent_at: None,
+    })
+}
+
+/// Print the input prompt (only when a person is typing).
+/// Read prompts from stdin until EOF, one line per message, finishing each
+/// reply before prompting again.
+pub(crate) async fn repl(state: &Arc<State>) -> Result<(), AppError> {
+    use std::io::IsTerminal;
+    use tokio::io::AsyncBufReadExt;
+
+    crate::lifecycle::announce_online(state).await;
+    let interactive = std::io::stdin().is_terminal();
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        if interactive {
+            prompt();
+        }
+        let Some(line) = lines.next_line().await? else {
+            return Ok(());
+        };
+        let Some(envelope) = envelope(&line, &state.config.account) else {
+            continue;
+        };
+        // Nothing echoes back on a terminal, so a prompt that repeats an
+        // earlier reply must not be mistaken for one.
+        state.sent_hashes.clear();
+        if let Some(inbound) = crate::route_envelope(state, envelope) {
+            crate::handle_inbound(state, inbound).await;
+        }
+    }
+}
+
+fn prompt() {
+    let mut out = std::io::stdout().lock();
+    let _ = out.write_all(PROMPT.as_bytes());
+    let _ = out.flush();
+}
+
+fn print(text: &str) -> Result<(), AppError> {
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "{text}")?;
+    out.flush()?;
+    Ok(())
+}
+
+pub(crate) struct StdinApi;
+
+#[async_trait]
+impl SignalApi for StdinApi {
+    async fn send_msg(&self, _recipient: &str, message: &str) -> Result<(), AppError> {
+        print(message)
+    }
+
+    async fn set_typing(&self, _recipient: &str, _typing: bool) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn download_attachment(&self, _attachment: &AttachmentInfo) -> Result<PathBuf, AppError> {
+        Err(AppError::Transport(
+            "The stdin transport has no attachments".to_string(),
+        ))
+    }
+
+    async fn send_attachment(
+        &self,
+        _recipient: &str,
+        data: &[u8],
+        content_type: &str,
+        filename: &str,
+    ) -> Result<(), AppError> {
+        let dir = PathBuf::from(crate::constants::TMP_DIR);
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}-{filename}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, data)?;
+        print(&format!("[attachment {content_type}: {}]", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_is_from_account_owner() {
+        let env = envelope("  /status \n", "+1234567890").expect("envelope");
+        assert_eq!(env.source, "+1234567890");
+        assert_eq!(env.message_text, "/status");
+        assert!(env.group_id.is_none());
+        assert!(env.thread_id.is_none());
+    }
+
+    #[test]
+    fn test_envelope_skips_blank_lines() {
+        assert!(envelope("", "+1234567890").is_none());
+        assert!(envelope("   \t", "+1234567890").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_inbound_attachments_unsupported() {
+        let attachment = AttachmentInfo {
+            id: "a".to_string(),
+            content_type: "image/png".to_string(),
+            filename: None,
+            size: None,
+            voice_note: false,
+        };
+        assert!(StdinApi.download_attachment(&attachment).await.is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! WhatsApp transport, talking to a whatsmeow-based bridge (`whatsmeow-api`).
+//!
+//! The bridge mirrors the shape of signal-cli-api: it links to a phone as a
+//! companion device, streams inbound messages as JSON over `GET /v1/events`
+//! (WebSocket) and accepts sends over REST:
+//!
+//! - `POST /v1/send` `{to, text}` or `{to, media: {mime, filename, data}}`
+//! - `POST /v1/presence` `{to, state: "composing" | "paused"}`
+//! - `GET /v1/media/{id}` returns the raw attachment bytes
+//!
+//! WhatsApp addresses people by JID (`447700000000@s.whatsapp.net`) and groups
+//! by `<id>@g.us`. JIDs are mapped to `+E164` and `group.<base64>` ids on the
+//! way in and back again on the way out.
+
+use async_trait::async_trait;
+use base64::Engine;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use tracing::{debug, error};
+
+use crate::error::AppError;
+use crate::helpers::group_id_from_chat;
+use crate::signal::{AttachmentInfo, ParsedEnvelope};
+use crate::traits::{attachment_tmp_path, SignalApi};
+
+pub(crate) const BRIDGE_BINARY: &str = "whatsmeow-api";
+
+const USER_SERVER: &str = "s.whatsapp.net";
+const GROUP_SERVER: &str = "g.us";
+
+#[derive(Debug, Default, Deserialize)]
+struct Event {
+    #[serde(rename = "type", default)]
+    kind: String,
+    /// Chat the message belongs to (the other person, or a group).
+    #[serde(default)]
+    chat: String,
+    /// Author of the message (differs from `chat` in groups).
+    #[serde(default)]
+    sender: String,
+    #[serde(default)]
+    push_name: Option<String>,
+    #[serde(default)]
+    from_me: bool,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    media: Vec<Media>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Media {
+    id: String,
+    #[serde(default)]
+    mime: String,
+    #[serde(default)]
+    filename: Option<String>,
+    /// Push-to-talk voice note.
+    #[serde(default)]
+    ptt: bool,
+}
+
+/// `447700000000:3@s.whatsapp.net` -> `+447700000000`. Non-phone JIDs are kept as-is.
+pub(crate) fn jid_to_id(jid: &str) -> String {
+    match jid.split_once('@') {
+        Some((user, USER_SERVER)) => {
+            let number = user.split(':').next().unwrap_or(user);
+            format!("+{number}")
+        }
+        _ => jid.to_string(),
+    }
+}
+
+/// Reverse of the inbound mapping: `+E164` -> user JID, `group.<b64>` -> group JID.
+pub(crate) fn recipient_jid(recipient: &str) -> String {
+    if let Some(group) = group_id_from_chat(recipient) {
+        return group;
+    }
+    if recipient.contains('@') {
+        return recipient.to_string();
+    }
+    format!("{}@{USER_SERVER}", recipient.trim_start_matches('+'))
+}
+
+/// Normalise one bridge event. Messages the account owner sends to other
+/// people are skipped; those in the owner's own chat count as Note to Self.
+pub(crate) fn parse_event(event: &Value, account: &str) -> Option<ParsedEnvelope> {
+    let ev: Event = match serde_json::from_value(event.clone()) {
+        Ok(ev) => ev,
+        Err(e) => {
+            debug!("Skipping unparseable WhatsApp event: {e}");
+            return None;
+        }
+    };
+    if ev.kind != "message" || ev.chat.is_empty() {
+        return None;
+    }
+    let is_group = ev.chat.ends_with(&format!("@{GROUP_SERVER}"));
+    let is_self_chat = !is_group && jid_to_id(&ev.chat) == account;
+    if ev.from_me && !is_self_chat {
+        return None;
+    }
+
+    let text = ev.text.unwrap_or_default();
+    let message_text = if !text.is_empty() {
+        text
+    } else if !ev.media.is_empty() {
+        "Describe this attachment.".to_string()
+    } else {
+        return None;
+    };
+
+    let author = if ev.sender.is_empty() {
+        &ev.chat
+    } else {
+        &ev.sender
+    };
+    Some(ParsedEnvelope {
+        source: if ev.from_me {
+            account.to_string()
+        } else {
+            jid_to_id(author)
+        },
+        message_text,
+        is_sync: ev.from_me,
+        source_uuid: String::new(),
+        source_name: ev.push_name.unwrap_or_else(|| "unknown".to_string()),
+        attachments: ev
+            .media
+            .into_iter()
+            .map(|m| AttachmentInfo {
+                id: m.id,
+                content_type: m.mime,
+                filename: m.filename,
+                size: None,
+                voice_note: m.ptt,
+            })
+            .collect(),
+        group_id: is_group.then_some(ev.chat),
+        thread_id: None,
+        sent_at: None,
+    })
+}
+
+pub(crate) struct WhatsAppApi {
+    pub(crate) http: Client,
+    pub(crate) api_url: String,
+}
+
+impl WhatsAppApi {
+    async fn post(&self, path: &str, body: Value, what: &str) -> Result<(), AppError> {
+        let url = format!("{}{path}", self.api_url);
+        let resp = self.http.post(&url).json(&body).send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            error!(status = %status, body = %body, "WhatsApp {what} failed");
+            return Err(AppError::Transport(format!(
+                "WhatsApp {what} failed: {status}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SignalApi for WhatsAppApi {
+    async fn send_msg(&self, recipient: &str, message: &str) -> Result<(), AppError> {
+        let body = serde_json::json!({
+            "to": recipient_jid(recipient),
+            "text": message,
+        });
+        self.post("/v1/send", body, "send").await
+    }
+
+    async fn set_typing(&self, recipient: &str, typing: bool) -> Result<(), AppError> {
+        let body = serde_json::json!({
+            "to": recipient_jid(recipient),
+            "state": if typing { "composing" } else { "paused" },
+        });
+        if let Err(e) = self.post("/v1/presence", body, "presence").await {
+            debug!("Typing indicator failed: {e}");
+        }
+        Ok(())
+    }
+
+    async fn download_attachment(&self, attachment: &AttachmentInfo) -> Result<PathBuf, AppError> {
+        let path = attachment_tmp_path(attachment);
+        let url = format!("{}/v1/media/{}", self.api_url, attachment.id);
+        let resp = self.http.get(&url).send().await?;
+        if !resp.status().is_success() {
+            return Err(AppError::Transport(format!(
+                "Failed to download WhatsApp media {}: {}",
+                attachment.id,
+                resp.status()
+            )));
+        }
+        let bytes = resp.bytes().await?;
+        std::fs::write(&path, &bytes)?;
+        debug!("Downloaded media {} to {}", attachment.id, path.display());
+        Ok(path)
+    }
+
+    async fn send_attachment(
+        &self,
+        recipient: &str,
+        data: &[u8],
+        content_type: &str,
+        filename: &str,
+    ) -> Result<(), AppError> {
+        let body = serde_json::json!({
+            "to": recipient_jid(recipient),
+            "media": {
+                "mime": content_type,
+                "filename": filename,
+                "data": base64::engine::general_purpose::STANDARD.encode(data),
+            },
+        });
+        self.post("/v1/send", body, "media send").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::group_chat_id;
+    use serde_json::json;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const ACCOUNT: &str = "+1234567890";
+
+    #[test]
+    fn test_jid_mapping_round_trip() {
+        assert_eq!(jid_to_id("447700000000@s.whatsapp.net"), "+447700000000");
+        assert_eq!(jid_to_id("447700000000:12@s.whatsapp.net"), "+447700000000");
+        assert_eq!(jid_to_id("12345@lid"), "12345@lid");
+        assert_eq!(
+            recipient_jid("+447700000000"),
+            "447700000000@s.whatsapp.net"
+        );
+        let group = "120363000000000000@g.us";
+        assert_eq!(recipient_jid(&group_chat_id(group)), group);
+    }
+
+    #[test]
+    fn test_parse_direct_message() {
+        let ev = json!({
+            "type": "message",
+            "chat": "447700000000@s.whatsapp.net",
+            "sender": "447700000000@s.whatsapp.net",
+            "push_name": "Ann",
+            "text": "hello"
+        });
+        let parsed = parse_event(&ev, ACCOUNT).unwrap();
+        assert_eq!(parsed.source, "+447700000000");
+        assert_eq!(parsed.source_name, "Ann");
+        assert_eq!(parsed.message_text, "hello");
+        assert!(!parsed.is_sync);
+        assert!(parsed.group_id.is_none());
+    }
+
+    #[test]
+    fn test_parse_group_message_with_media() {
+        let ev = json!({
+            "type": "message",
+            "chat": "120363000000000000@g.us",
+            "sender": "447700000000@s.whatsapp.net",
+            "media": [{"id": "m1", "mime": "audio/ogg", "ptt": true}]
+        });
+        let parsed = parse_event(&ev, ACCOUNT).unwrap();
+        assert_eq!(parsed.source, "+447700000000");
+        assert_eq!(parsed.group_id.as_deref(), Some("120363000000000000@g.us"));
+        assert_eq!(parsed.message_text, "Describe this attachment.");
+        assert!(parsed.attachments[0].voice_note);
+    }
+
+    #[test]
+    fn test_parse_note_to_self_is_sync() {
+        let ev = json!({
+            "type": "message",
+            "chat": "1234567890@s.whatsapp.net",
+            "from_me": true,
+            "text": "/status"
+        });
+        let parsed = parse_event(&ev, ACCOUNT).unwrap();
+        assert!(parsed.is_sync);
+        assert_eq!(parsed.source, ACCOUNT);
+    }
+
+    #[test]
+    fn test_parse_skips_own_messages_to_others_and_non_messages() {
+        let outgoing = json!({
+            "type": "message",
+            "chat": "447700000000@s.whatsapp.net",
+            "from_me": true,
+            "text": "hi"
+        });
+        assert!(parse_event(&outgoing, ACCOUNT).is_none());
+        assert!(parse_event(&json!({"type": "receipt", "chat": "x"}), ACCOUNT).is_none());
+        let empty = json!({"type": "message", "chat": "447700000000@s.whatsapp.net"});
+        assert!(parse_event(&empty, ACCOUNT).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_msg_posts_jid_and_text() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/send"))
+            .and(body_json(
+                json!({"to": "447700000000@s.whatsapp.net", "text": "hi"}),
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let api = WhatsAppApi {
+            http: Client::new(),
+            api_url: server.uri(),
+        };
+        api.send_msg("+447700000000", "hi").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_msg_error_status() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/send"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let api = WhatsAppApi {
+            http: Client::new(),
+            api_url: server.uri(),
+        };
+        let err = api.send_msg("+447700000000", "hi").await.unwrap_err();
+        assert!(matches!(err, AppError::Transport(_)));
+    }
+}
@@ -0,0 +1,828 @@
+//! XMPP transport: a small client for personal Jabber servers.
+//!
+//! ccchat logs in as its own account (`--jid`/`--password`), connecting
+//! through `--proxy` like every other transport and negotiating STARTTLS
+//! (required, trusting the `--ca-bundle` roots), SASL PLAIN and resource
+//! binding, then listens for `chat` messages. Each correspondent's bare JID
+//! is their session and their entry in the allowed list; `--account` is the
+//! admin's JID.
+//!
+//! Typing is shown with chat states (XEP-0085) and server pings (XEP-0199)
+//! are answered. Files people share by link (XEP-0066, which is how
+//! HTTP-upload clients send them) are downloaded as attachments; sending
+//! files isn't supported yet.
+//!
+//! OMEMO isn't implemented: message bodies are only protected by TLS to the
+//! server, and OMEMO-encrypted messages are dropped with a warning rather
+//! than passing their "your client doesn't support OMEMO" fallback text to
+//! Claude. The protocol is spoken directly with quick-xml, not xmpp-rs, so
+//! the connection goes through the shared proxy and CA settings.
+
+use async_trait::async_trait;
+use base64::Engine;
+use dashmap::DashMap;
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::error::AppError;
+use crate::net::NetConfig;
+use crate::signal::{AttachmentInfo, ParsedEnvelope};
+use crate::state::State;
+use crate::traits::{attachment_tmp_path, SignalApi};
+
+pub(crate) const DEFAULT_PORT: u16 = 5222;
+const RESOURCE: &str = "ccchat";
+/// Whitespace keepalive so NATs and servers don't drop an idle stream.
+const KEEPALIVE: Duration = Duration::from_secs(60);
+
+const NS_TLS: &str = "urn:ietf:params:xml:ns:xmpp-tls";
+const NS_SASL: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const NS_BIND: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+const NS_CHATSTATES: &str = "http://jabber.org/protocol/chatstates";
+const NS_OOB: &str = "jabber:x:oob";
+const NS_PING: &str = "urn:xmpp:ping";
+/// OMEMO payloads: the legacy namespace most clients still send, and OMEMO 2.
+const NS_OMEMO: [&str; 2] = ["eu.siacs.conversations.axolotl", "urn:xmpp:omemo:2"];
+
+#[derive(Clone, Debug)]
+pub(crate) struct XmppConfig {
+    /// Bare JID of the bot account.
+    pub(crate) jid: String,
+    pub(crate) password: String,
+    /// Server to connect to; defaults to the JID's domain on port 5222.
+    pub(crate) server: Option<(String, u16)>,
+}
+
+impl XmppConfig {
+    pub(crate) fn domain(&self) -> &str {
+        self.jid.split_once('@').map_or(&self.jid, |(_, d)| d)
+    }
+
+    fn local(&self) -> &str {
+        self.jid.split_once('@').map_or("", |(l, _)| l)
+    }
+
+    pub(crate) fn server(&self) -> (String, u16) {
+        self.server
+            .clone()
+            .unwrap_or_else(|| (self.domain().to_string(), DEFAULT_PORT))
+    }
+}
+
+/// One parsed stanza (or stream-level element), namespaces kept as `xmlns` attributes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn from_start(start: &BytesStart) -> Result<Self, AppError> {
+        let mut attrs = Vec::new();
+        for attr in start.attributes() {
+            let attr = attr.map_err(xml_error)?;
+            let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+            let value = attr.unescape_value().map_err(xml_error)?.into_owned();
+            attrs.push((key, value));
+        }
+        Ok(Self {
+            name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+            attrs,
+            ..Default::default()
+        })
+    }
+
+    pub(crate) fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn child_ns(&self, name: &str, ns: &str) -> Option<&Element> {
+        self.children
+            .iter()
+            .find(|c| c.name == name && c.attr("xmlns") == Some(ns))
+    }
+}
+
+fn xml_error(e: impl std::fmt::Display) -> AppError {
+    AppError::Transport(format!("XMPP stream error: {e}"))
+}
+
+/// Reads top-level elements off an XMPP stream. Stream headers are skipped,
+/// so the same reader carries on across stream restarts.
+pub(crate) struct StanzaReader<R> {
+    reader: quick_xml::Reader<R>,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncBufRead + Unpin> StanzaReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        let mut reader = quick_xml::Reader::from_reader(inner);
+        reader.config_mut().check_end_names = false;
+        Self {
+            reader,
+            buf: Vec::new(),
+        }
+    }
+
+    fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Next complete stanza, or `None` once the server closes the stream.
+    pub(crate) async fn next(&mut self) -> Result<Option<Element>, AppError> {
+        let mut stack: Vec<Element> = Vec::new();
+        loop {
+            self.buf.clear();
+            let event = self
+                .reader
+                .read_event_into_async(&mut self.buf)
+                .await
+                .map_err(xml_error)?
+                .into_owned();
+            match event {
+                Event::Start(start) => {
+                    if stack.is_empty() && start.local_name().as_ref() == b"stream" {
+                        continue;
+                    }
+                    stack.push(Element::from_start(&start)?);
+                }
+                Event::Empty(start) => {
+                    let el = Element::from_start(&start)?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(el),
+                        None => return Ok(Some(el)),
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(top) = stack.last_mut() {
+                        top.text.push_str(&text.unescape().map_err(xml_error)?);
+                    }
+                }
+                Event::CData(data) => {
+                    if let Some(top) = stack.last_mut() {
+                        top.text.push_str(&String::from_utf8_lossy(&data));
+                    }
+                }
+                Event::End(_) => match stack.pop() {
+                    // `</stream:stream>`: the server is closing.
+                    None => return Ok(None),
+                    Some(el) => match stack.last_mut() {
+                        Some(parent) => parent.children.push(el),
+                        None => return Ok(Some(el)),
+                    },
+                },
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    async fn expect(&mut self, name: &str) -> Result<Element, AppError> {
+        match self.next().await? {
+            Some(el) if el.name == name => Ok(el),
+            Some(el) => Err(AppError::Transport(format!(
+                "XMPP server sent <{}> where <{name}> was expected",
+                el.name
+            ))),
+            None => Err(AppError::Transport("XMPP server closed the stream".into())),
+        }
+    }
+}
+
+async fn write<W: AsyncWrite + Unpin>(writer: &mut W, data: &str) -> Result<(), AppError> {
+    writer.write_all(data.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn stream_header(domain: &str) -> String {
+    format!(
+        "<?xml version='1.0'?><stream:stream to='{}' version='1.0' xmlns='jabber:client' \
+         xmlns:stream='http://etherx.jabber.org/streams'>",
+        escape(domain)
+    )
+}
+
+/// SASL PLAIN login and resource binding over an already-encrypted stream.
+/// Returns the full JID the server bound.
+pub(crate) async fn login<R, W>(
+    reader: &mut StanzaReader<R>,
+    writer: &mut W,
+    config: &XmppConfig,
+) -> Result<String, AppError>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    write(writer, &stream_header(config.domain())).await?;
+    let features = reader.expect("features").await?;
+    let offers_plain = features
+        .child_ns("mechanisms", NS_SASL)
+        .is_some_and(|m| m.children.iter().any(|c| c.text == "PLAIN"));
+    if !offers_plain {
+        return Err(AppError::Transport(
+            "XMPP server does not offer SASL PLAIN".into(),
+        ));
+    }
+    let token = base64::engine::general_purpose::STANDARD.encode(format!(
+        "\0{}\0{}",
+        config.local(),
+        config.password
+    ));
+    write(
+        writer,
+        &format!("<auth xmlns='{NS_SASL}' mechanism='PLAIN'>{token}</auth>"),
+    )
+    .await?;
+    match reader.next().await? {
+        Some(el) if el.name == "success" => {}
+        Some(el) => {
+            let reason = el
+                .children
+                .first()
+                .map_or("unknown", |c| c.name.as_str())
+                .to_string();
+            return Err(AppError::Transport(format!("XMPP login failed: {reason}")));
+        }
+        None => return Err(AppError::Transport("XMPP server closed the stream".into())),
+    }
+
+    write(writer, &stream_header(config.domain())).await?;
+    reader.expect("features").await?;
+    write(
+        writer,
+        &format!(
+            "<iq type='set' id='bind'><bind xmlns='{NS_BIND}'><resource>{RESOURCE}</resource></bind></iq>"
+        ),
+    )
+    .await?;
+    let bound = loop {
+        let el = reader.expect("iq").await?;
+        if el.attr("id") != Some("bind") {
+            continue;
+        }
+        if el.attr("type") != Some("result") {
+            return Err(AppError::Transport("XMPP resource binding failed".into()));
+        }
+        break el
+            .child("bind")
+            .and_then(|b| b.child("jid"))
+            .map(|j| j.text.clone())
+            .unwrap_or_else(|| format!("{}/{RESOURCE}", config.jid));
+    };
+    write(writer, "<presence/>").await?;
+    Ok(bound)
+}
+
+type TlsRead = tokio::io::ReadHalf<tokio_rustls::client::TlsStream<TcpStream>>;
+type TlsWrite = tokio::io::WriteHalf<tokio_rustls::client::TlsStream<TcpStream>>;
+
+/// A logged-in stream, ready for [`Link::run`].
+pub(crate) struct Session {
+    reader: StanzaReader<BufReader<TlsRead>>,
+    writer: TlsWrite,
+    outbound: mpsc::UnboundedReceiver<String>,
+}
+
+/// Shared between the listener and [`XmppApi`]: replies are queued here and
+/// written by whichever connection is current.
+pub(crate) struct Link {
+    pub(crate) config: XmppConfig,
+    net: NetConfig,
+    outbound: Mutex<Option<mpsc::UnboundedSender<String>>>,
+    /// Shared-file URLs from recent messages, keyed by attachment id.
+    oob_urls: DashMap<String, String>,
+}
+
+impl Link {
+    pub(crate) fn new(config: XmppConfig, net: NetConfig) -> Self {
+        Self {
+            config,
+            net,
+            outbound: Mutex::new(None),
+            oob_urls: DashMap::new(),
+        }
+    }
+
+    /// Connect and hand incoming messages to the router until the stream
+    /// closes.
+    pub(crate) async fn listen(&self, state: &Arc<State>) -> Result<(), AppError> {
+        let session = self.connect().await?;
+        crate::lifecycle::announce_online(state).await;
+        self.run(session, |envelope| {
+            crate::dispatch_envelope(state, envelope)
+        })
+        .await
+    }
+
+    /// Connect, require STARTTLS, log in and bind.
+    pub(crate) async fn connect(&self) -> Result<Session, AppError> {
+        let (host, port) = self.config.server();
+        let domain = self.config.domain().to_string();
+        let tcp = self.net.connect_tcp(&host, port).await?;
+
+        let mut plain = StanzaReader::new(BufReader::new(tcp));
+        write(plain.reader.get_mut().get_mut(), &stream_header(&domain)).await?;
+        let features = plain.expect("features").await?;
+        if features.child_ns("starttls", NS_TLS).is_none() {
+            return Err(AppError::Transport(
+                "XMPP server does not offer STARTTLS".into(),
+            ));
+        }
+        write(
+            plain.reader.get_mut().get_mut(),
+            &format!("<starttls xmlns='{NS_TLS}'/>"),
+        )
+        .await?;
+        plain.expect("proceed").await?;
+        // The server waits for the TLS handshake, so nothing is left buffered.
+        let tcp = plain.into_inner().into_inner();
+
+        let server_name = rustls_pki_types::ServerName::try_from(domain)
+            .map_err(|e| AppError::Transport(format!("Invalid XMPP domain: {e}")))?;
+        let tls = tokio_rustls::TlsConnector::from(self.net.tls_config()?)
+            .connect(server_name, tcp)
+            .await?;
+        let (read, mut writer) = tokio::io::split(tls);
+        let mut reader = StanzaReader::new(BufReader::new(read));
+        let bound = login(&mut reader, &mut writer, &self.config).await?;
+        info!("XMPP connected as {bound}");
+
+        let (tx, outbound) = mpsc::unbounded_channel();
+        *self.outbound.lock().unwrap_or_else(|e| e.into_inner()) = Some(tx);
+        Ok(Session {
+            reader,
+            writer,
+            outbound,
+        })
+    }
+
+    /// Pump the stream until it closes: hand messages to `on_message`, write
+    /// queued replies, answer pings and keep the connection alive.
+    pub(crate) async fn run(
+        &self,
+        session: Session,
+        mut on_message: impl FnMut(ParsedEnvelope),
+    ) -> Result<(), AppError> {
+        let Session {
+            mut reader,
+            mut writer,
+            mut outbound,
+        } = session;
+        // Reading isn't cancel-safe, so it gets its own task.
+        let (in_tx, mut inbound) = mpsc::channel(64);
+        let reader_task = tokio::spawn(async move {
+            loop {
+                let next = reader.next().await;
+                let done = !matches!(next, Ok(Some(_)));
+                if in_tx.send(next).await.is_err() || done {
+                    break;
+                }
+            }
+        });
+        let mut keepalive = tokio::time::interval(KEEPALIVE);
+        keepalive.tick().await;
+
+        let result = loop {
+            let written = tokio::select! {
+                stanza = inbound.recv() => match stanza {
+                    Some(Ok(Some(el))) => {
+                        if let Some(reply) = iq_reply(&el) {
+                            write(&mut writer, &reply).await
+                        } else {
+                            if let Some(env) = self.envelope(&el) {
+                                on_message(env);
+                            }
+                            Ok(())
+                        }
+                    }
+                    Some(Err(e)) => break Err(e),
+                    _ => break Ok(()),
+                },
+                Some(stanza) = outbound.recv() => write(&mut writer, &stanza).await,
+                _ = keepalive.tick() => write(&mut writer, " ").await,
+            };
+            if let Err(e) = written {
+                break Err(e);
+            }
+        };
+        reader_task.abort();
+        *self.outbound.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        result
+    }
+
+    fn send(&self, stanza: String) -> Result<(), AppError> {
+        let guard = self.outbound.lock().unwrap_or_else(|e| e.into_inner());
+        match guard.as_ref() {
+            Some(tx) if tx.send(stanza).is_ok() => Ok(()),
+            _ => Err(AppError::Transport("XMPP is not connected".into())),
+        }
+    }
+
+    /// Normalise a `<message>` stanza. Group chat, errors and our own
+    /// account's messages are skipped.
+    pub(crate) fn envelope(&self, el: &Element) -> Option<ParsedEnvelope> {
+        if el.name != "message" {
+            return None;
+        }
+        if matches!(el.attr("type"), Some("error") | Some("groupchat")) {
+            return None;
+        }
+        let from = bare_jid(el.attr("from")?);
+        if from == self.config.jid {
+            return None;
+        }
+        // The body of an OMEMO message is only the client's fallback text.
+        if NS_OMEMO
+            .iter()
+            .any(|ns| el.child_ns("encrypted", ns).is_some())
+        {
+            warn!(
+                from,
+                "Dropped an OMEMO-encrypted message; OMEMO isn't supported"
+            );
+            return None;
+        }
+
+        let url = el
+            .child_ns("x", NS_OOB)
+            .and_then(|x| x.child("url"))
+            .map(|u| u.text.trim().to_string())
+            .filter(|u| !u.is_empty());
+        let body = el.child("body").map(|b| b.text.trim()).unwrap_or_default();
+        let mut attachments = Vec::new();
+        if let Some(url) = &url {
+            let id = format!("oob-{:x}", crate::helpers::hash_message(url));
+            self.oob_urls.insert(id.clone(), url.clone());
+            attachments.push(AttachmentInfo {
+                id,
+                content_type: String::new(),
+                filename: url.rsplit('/').next().map(str::to_string),
+                size: None,
+                voice_note: false,
+            });
+        }
+        // Clients that share a file also put its URL in the body.
+        let text = if url.as_deref() == Some(body) {
+            ""
+        } else {
+            body
+        };
+        let message_text = if !text.is_empty() {
+            text.to_string()
+        } else if !attachments.is_empty() {
+            "Describe this attachment.".to_string()
+        } else {
+            return None;
+        };
+
+        Some(ParsedEnvelope {
+            source: from.to_string(),
+            message_text,
+            is_sync: false,
+            source_uuid: String::new(),
+            source_name: from.split('@').next().unwrap_or(from).to_string(),
+            attachments,
+            group_id: None,
+            thread_id: None,
+            sent_at: None,
+        })
+    }
+}
+
+fn bare_jid(jid: &str) -> &str {
+    jid.split_once('/').map_or(jid, |(bare, _)| bare)
+}
+
+/// Answer server pings; refuse any other query addressed to us.
+fn iq_reply(el: &Element) -> Option<String> {
+    if el.name != "iq" || el.attr("type") != Some("get") {
+        return None;
+    }
+    let id = escape(el.attr("id").unwrap_or_default()).into_owned();
+    let to = el
+        .attr("from")
+        .map(|f| format!(" to='{}'", escape(f)))
+        .unwrap_or_default();
+    if el.child_ns("ping", NS_PING).is_some() {
+        return Some(format!("<iq type='result' id='{id}'{to}/>"));
+    }
+    Some(format!(
+        "<iq type='error' id='{id}'{to}><error type='cancel'>\
+         <service-unavailable xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></iq>"
+    ))
+}
+
+fn chat_message(to: &str, body: Option<&str>, state: &str) -> String {
+    let body = body
+        .map(|b| format!("<body>{}</body>", escape(b)))
+        .unwrap_or_default();
+    format!(
+        "<message to='{}' type='chat' id='{}'>{body}<{state} xmlns='{NS_CHATSTATES}'/></message>",
+        escape(to),
+        uuid::Uuid::new_v4()
+    )
+}
+
+pub(crate) struct XmppApi {
+    pub(crate) link: Arc<Link>,
+}
+
+#[async_trait]
+impl SignalApi for XmppApi {
+    async fn send_msg(&self, recipient: &str, message: &str) -> Result<(), AppError> {
+        self.link
+            .send(chat_message(recipient, Some(message), "active"))
+    }
+
+    async fn set_typing(&self, recipient: &str, typing: bool) -> Result<(), AppError> {
+        let state = if typing { "composing" } else { "paused" };
+        if let Err(e) = self.link.send(chat_message(recipient, None, state)) {
+            debug!("Typing indicator failed: {e}");
+        }
+        Ok(())
+    }
+
+    async fn download_attachment(&self, attachment: &AttachmentInfo) -> Result<PathBuf, AppError> {
+        let url = self
+            .link
+            .oob_urls
+            .get(&attachment.id)
+            .map(|u| u.clone())
+            .ok_or_else(|| AppError::Transport(format!("Unknown attachment {}", attachment.id)))?;
+        // The URL comes from whoever sent the message, so it gets the same
+        // checks as a fetched link.
+        let bytes = crate::fetch::fetch_file(&self.link.net, &url)
+            .await
+            .map_err(|e| AppError::Transport(format!("Failed to download {url}: {e}")))?;
+        let path = attachment_tmp_path(attachment);
+        std::fs::write(&path, &bytes)?;
+        debug!("Downloaded {url} to {}", path.display());
+        Ok(path)
+    }
+
+    async fn send_attachment(
+        &self,
+        recipient: &str,
+        _data: &[u8],
+        _content_type: &str,
+        filename: &str,
+    ) -> Result<(), AppError> {
+        warn!(
+            recipient,
+            filename, "Sending files over XMPP is not supported"
+        );
+        Err(AppError::Transport(
+            "Sending files over XMPP is not supported yet".into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> XmppConfig {
+        XmppConfig {
+            jid: "bot@example.org".into(),
+            password: "secret".into(),
+            server: None,
+        }
+    }
+
+    async fn parse_one(xml: &str) -> Element {
+        let mut reader = StanzaReader::new(BufReader::new(xml.as_bytes()));
+        reader.next().await.unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_config_server_defaults_to_domain() {
+        assert_eq!(config().server(), ("example.org".to_string(), 5222));
+        assert_eq!(config().local(), "bot");
+    }
+
+    #[tokio::test]
+    async fn test_reader_skips_stream_header_and_builds_tree() {
+        let xml = "<?xml version='1.0'?><stream:stream xmlns='jabber:client' \
+                   xmlns:stream='http://etherx.jabber.org/streams'>\
+                   <message from='ann@example.org/phone' type='chat'><body>1 &lt; 2</body></message>";
+        let el = parse_one(xml).await;
+        assert_eq!(el.name, "message");
+        assert_eq!(el.attr("from"), Some("ann@example.org/phone"));
+        assert_eq!(el.child("body").unwrap().text, "1 < 2");
+    }
+
+    #[tokio::test]
+    async fn test_envelope_uses_bare_jid_and_skips_own_and_groupchat() {
+        let link = Link::new(config(), NetConfig::default());
+        let el = parse_one(
+            "<message from='ann@example.org/phone' type='chat'><body>hi</body></message>",
+        )
+        .await;
+        let env = link.envelope(&el).unwrap();
+        assert_eq!(env.source, "ann@example.org");
+        assert_eq!(env.source_name, "ann");
+        assert_eq!(env.message_text, "hi");
+
+        let own = parse_one("<message from='bot@example.org/x'><body>hi</body></message>").await;
+        assert!(link.envelope(&own).is_none());
+        let muc = parse_one(
+            "<message from='room@muc.example.org/ann' type='groupchat'><body>hi</body></message>",
+        )
+        .await;
+        assert!(link.envelope(&muc).is_none());
+        let typing = parse_one(
+            "<message from='ann@example.org/p' type='chat'>\
+             <composing xmlns='http://jabber.org/protocol/chatstates'/></message>",
+        )
+        .await;
+        assert!(link.envelope(&typing).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_envelope_shared_file_becomes_attachment() {
+        let link = Link::new(config(), NetConfig::default());
+        let el = parse_one(
+            "<message from='ann@example.org/p' type='chat'>\
+             <body>https://up.example.org/a/photo.jpg</body>\
+             <x xmlns='jabber:x:oob'><url>https://up.example.org/a/photo.jpg</url></x></message>",
+        )
+        .await;
+        let env = link.envelope(&el).unwrap();
+        assert_eq!(env.message_text, "Describe this attachment.");
+        assert_eq!(env.attachments[0].filename.as_deref(), Some("photo.jpg"));
+        assert!(link.oob_urls.contains_key(&env.attachments[0].id));
+    }
+
+    #[tokio::test]
+    async fn test_envelope_drops_omemo_messages() {
+        let link = Link::new(config(), NetConfig::default());
+        let el = parse_one(
+            "<message from='ann@example.org/p' type='chat'>\
+             <encrypted xmlns='eu.siacs.conversations.axolotl'><header sid='1'/></encrypted>\
+             <body>I sent you an OMEMO encrypted message but your client doesn't seem to support that.</body>\
+             </message>",
+        )
+        .await;
+        assert!(link.envelope(&el).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_iq_ping_is_answered() {
+        let ping = parse_one(
+            "<iq from='example.org' type='get' id='p1'><ping xmlns='urn:xmpp:ping'/></iq>",
+        )
+        .await;
+        assert_eq!(
+            iq_reply(&ping).as_deref(),
+            Some("<iq type='result' id='p1' to='example.org'/>")
+        );
+        let version = parse_one(
+            "<iq from='a@b/c' type='get' id='v1'><query xmlns='jabber:iq:version'/></iq>",
+        )
+        .await;
+        assert!(iq_reply(&version).unwrap().contains("service-unavailable"));
+        let result = parse_one("<iq type='result' id='r1'/>").await;
+        assert!(iq_reply(&result).is_none());
+    }
+
+    #[test]
+    fn test_chat_message_escapes_body() {
+        let stanza = chat_message("ann@example.org", Some("<b>&"), "active");
+        assert!(stanza.starts_with("<message to='ann@example.org' type='chat'"));
+        assert!(stanza.contains("<body>&lt;b&gt;&amp;</body>"));
+        assert!(stanza.contains("<active xmlns='http://jabber.org/protocol/chatstates'/>"));
+        assert!(!chat_message("a@b", None, "composing").contains("<body>"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_goes_through_proxy() {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        // A proxy that tunnels to a server without STARTTLS.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut request = String::new();
+            stream.read_line(&mut request).await.unwrap();
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 2 {
+                line.clear();
+            }
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\n\r\n")
+                .await
+                .unwrap();
+            let mut header = [0u8; 512];
+            let _ = stream.read(&mut header).await.unwrap();
+            stream
+                .get_mut()
+                .write_all(
+                    b"<stream:stream xmlns='jabber:client' \
+                      xmlns:stream='http://etherx.jabber.org/streams'>\
+                      <stream:features/>",
+                )
+                .await
+                .unwrap();
+            request
+        });
+        let net = NetConfig {
+            proxy: Some(proxy),
+            ..Default::default()
+        };
+        let err = Link::new(config(), net).connect().await.err().unwrap();
+        assert!(err.to_string().contains("STARTTLS"), "{err}");
+        assert!(server
+            .await
+            .unwrap()
+            .starts_with("CONNECT example.org:5222 "));
+    }
+
+    #[tokio::test]
+    async fn test_send_without_connection_errors() {
+        let api = XmppApi {
+            link: Arc::new(Link::new(config(), NetConfig::default())),
+        };
+        assert!(api.send_msg("ann@example.org", "hi").await.is_err());
+        assert!(api.set_typing("ann@example.org", true).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_shared_file_on_internal_host_refused() {
+        let api = XmppApi {
+            link: Arc::new(Link::new(config(), NetConfig::default())),
+        };
+        for (id, url) in [
+            ("a1", "http://169.254.169.254/latest/meta-data/"),
+            ("a2", "http://localhost:8080/admin"),
+            ("a3", "file:///etc/passwd"),
+        ] {
+            api.link.oob_urls.insert(id.to_string(), url.to_string());
+            let attachment = AttachmentInfo {
+                id: id.to_string(),
+                content_type: "application/octet-stream".to_string(),
+                filename: None,
+                size: None,
+                voice_note: false,
+            };
+            assert!(api.download_attachment(&attachment).await.is_err(), "{url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_binds_resource() {
+        let xml = "<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\
+                   <stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+                   <mechanism>SCRAM-SHA-1</mechanism><mechanism>PLAIN</mechanism>\
+                   </mechanisms></stream:features>\
+                   <success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'/>\
+                   <stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\
+                   <stream:features><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/></stream:features>\
+                   <iq type='result' id='bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'>\
+                   <jid>bot@example.org/ccchat</jid></bind></iq>";
+        let mut reader = StanzaReader::new(BufReader::new(xml.as_bytes()));
+        let mut sent = Vec::new();
+        let bound = login(&mut reader, &mut sent, &config()).await.unwrap();
+        assert_eq!(bound, "bot@example.org/ccchat");
+
+        let sent = String::from_utf8(sent).unwrap();
+        let token = base64::engine::general_purpose::STANDARD.encode("\0bot\0secret");
+        assert!(sent.contains(&format!(">{token}</auth>")));
+        assert!(sent.contains("<resource>ccchat</resource>"));
+        assert!(sent.ends_with("<presence/>"));
+    }
+
+    #[tokio::test]
+    async fn test_login_reports_sasl_failure() {
+        let xml = "<stream:stream xmlns:stream='http://etherx.jabber.org/streams'>\
+                   <stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+                   <mechanism>PLAIN</mechanism></mechanisms></stream:features>\
+                   <failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><not-authorized/></failure>";
+        let mut reader = StanzaReader::new(BufReader::new(xml.as_bytes()));
+        let mut sink = Vec::new();
+        let err = login(&mut reader, &mut sink, &config()).await.unwrap_err();
+        assert!(err.to_string().contains("not-authorized"));
+        assert!(String::from_utf8(sink)
+            .unwrap()
+            .contains("mechanism='PLAIN'"));
+    }
+}
@@ -0,0 +1,380 @@
+//! Spoken replies (`/voice on`).
+//!
+//! Claude's reply is turned into speech by one of three engines — macOS
+//! `say`, a local `piper` model, or the ElevenLabs API — and sent as an audio
+//! attachment. Code blocks and Markdown markup are dropped first since they
+//! read badly aloud. WAV output from piper is re-encoded to AAC with `ffmpeg`
+//! when it's installed, so it arrives as a compact voice note.
+
+use reqwest::Client;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::constants::{TMP_DIR, TTS_MAX_CHARS, TTS_TIMEOUT_SECS};
+use crate::error::AppError;
+
+const ELEVENLABS_URL: &str = "https://api.elevenlabs.io/v1/text-to-speech";
+/// ElevenLabs' stock "Rachel" voice, used when `--tts-voice` is not set.
+const ELEVENLABS_DEFAULT_VOICE: &str = "21m00Tcm4TlvDq8ikWAM";
+const ELEVENLABS_MODEL: &str = "eleven_multilingual_v2";
+const CODE_SPOKEN: &str = "(code omitted)";
+
+/// Speech engine selected with `--tts`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum TtsEngine {
+    /// macOS `say`
+    Say,
+    /// Local piper model (`--tts-voice` is the .onnx path)
+    Piper,
+    /// ElevenLabs API (`ELEVENLABS_API_KEY`)
+    Elevenlabs,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TtsConfig {
+    /// None = voice replies unavailable.
+    pub(crate) engine: Option<TtsEngine>,
+    /// `say -v` voice name, piper model path, or ElevenLabs voice ID.
+    pub(crate) voice: Option<String>,
+    pub(crate) elevenlabs_api_key: Option<String>,
+}
+
+impl TtsConfig {
+    /// Catch missing settings at startup rather than on the first reply.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match self.engine {
+            Some(TtsEngine::Piper) if self.voice.is_none() => {
+                Err("--tts piper needs --tts-voice <model.onnx>".to_string())
+            }
+            Some(TtsEngine::Elevenlabs) if self.elevenlabs_api_key.is_none() => {
+                Err("--tts elevenlabs needs ELEVENLABS_API_KEY".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Per-sender `/voice` setting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum VoiceMode {
+    #[default]
+    Off,
+    /// Text reply followed by a voice note.
+    On,
+    /// Voice note instead of text (text if synthesis fails).
+    Only,
+}
+
+impl VoiceMode {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "on" => Some(Self::On),
+            "only" => Some(Self::Only),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::On => "on",
+            Self::Only => "only",
+        }
+    }
+}
+
+/// Synthesized speech ready to send.
+#[derive(Debug)]
+pub(crate) struct VoiceNote {
+    pub(crate) data: Vec<u8>,
+    pub(crate) content_type: &'static str,
+    pub(crate) filename: &'static str,
+}
+
+/// The parts of a Markdown reply worth reading aloud, cut to [`TTS_MAX_CHARS`].
+pub(crate) fn speakable(text: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            if !in_fence {
+                lines.push(CODE_SPOKEN.to_string());
+            }
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let line = line
+            .trim_start()
+            .trim_start_matches('#')
+            .trim_start_matches('>')
+            .trim_start();
+        lines.push(strip_inline(line));
+    }
+    let spoken = lines.join("\n").trim().to_string();
+    if spoken.chars().count() <= TTS_MAX_CHARS {
+        return spoken;
+    }
+    let cut: String = spoken.chars().take(TTS_MAX_CHARS).collect();
+    match cut.rfind(['.', '!', '?']) {
+        Some(end) => cut[..=end].to_string(),
+        None => cut,
+    }
+}
+
+/// Drop emphasis and code markers and keep only the text of `[links](url)`.
+fn strip_inline(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.split_once("](") {
+            Some((label, tail)) if !label.contains('[') => match tail.find(')') {
+                Some(close) => {
+                    out.push_str(label);
+                    rest = &tail[close + 1..];
+                }
+                None => {
+                    out.push('[');
+                    rest = after;
+                }
+            },
+            _ => {
+                out.push('[');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out.replace("**", "").replace("__", "").replace('`', "")
+}
+
+/// Speak `text` with the configured engine.
+pub(crate) async fn synthesize(
+    config: &TtsConfig,
+    http: &Client,
+    text: &str,
+) -> Result<VoiceNote, AppError> {
+    let voice = config.voice.as_deref();
+    match config.engine {
+        None => Err(AppError::Other(
+            "Voice replies are not configured".to_string(),
+        )),
+        Some(TtsEngine::Say) => say(text, voice).await,
+        Some(TtsEngine::Piper) => {
+            let model = voice.ok_or_else(|| AppError::Other("No piper model".to_string()))?;
+            let wav = piper(text, model).await?;
+            Ok(match to_aac(&wav).await {
+                Ok(aac) => VoiceNote {
+                    data: aac,
+                    content_type: "audio/aac",
+                    filename: "reply.aac",
+                },
+                Err(_) => VoiceNote {
+                    data: wav,
+                    content_type: "audio/wav",
+                    filename: "reply.wav",
+                },
+            })
+        }
+        Some(TtsEngine::Elevenlabs) => {
+            let key = config
+                .elevenlabs_api_key
+                .as_deref()
+                .ok_or_else(|| AppError::Other("No ElevenLabs API key".to_string()))?;
+            elevenlabs(http, key, voice.unwrap_or(ELEVENLABS_DEFAULT_VOICE), text).await
+        }
+    }
+}
+
+fn timeout() -> Duration {
+    Duration::from_secs(TTS_TIMEOUT_SECS)
+}
+
+/// Wait for a speech process, mapping a timeout or non-zero exit to an error.
+async fn finish(name: &str, child: tokio::process::Child) -> Result<Vec<u8>, AppError> {
+    let output = tokio::time::timeout(timeout(), child.wait_with_output())
+        .await
+        .map_err(|_| AppError::Other(format!("{name} timed out")))??;
+    if !output.status.success() {
+        return Err(AppError::Other(format!(
+            "{name} exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// macOS `say`, writing AAC in an M4A container.
+async fn say(text: &str, voice: Option<&str>) -> Result<VoiceNote, AppError> {
+    let dir = PathBuf::from(TMP_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("say-{}.m4a", uuid::Uuid::new_v4()));
+    let mut cmd = Command::new("say");
+    cmd.arg("-o").arg(&path).arg("--data-format=aac");
+    if let Some(voice) = voice {
+        cmd.args(["-v", voice]);
+    }
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+    }
+    let result = finish("say", child)
+        .await
+        .and_then(|_| Ok(std::fs::read(&path)?));
+    let _ = std::fs::remove_file(&path);
+    Ok(VoiceNote {
+        data: result?,
+        content_type: "audio/mp4",
+        filename: "reply.m4a",
+    })
+}
+
+/// `piper`, reading text on stdin and writing WAV to stdout.
+async fn piper(text: &str, model: &str) -> Result<Vec<u8>, AppError> {
+    let mut child = Command::new("piper")
+        .args(["--model", model, "--output_file", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+    }
+    finish("piper", child).await
+}
+
+/// Re-encode WAV as an AAC (ADTS) stream with `ffmpeg`.
+async fn to_aac(wav: &[u8]) -> Result<Vec<u8>, AppError> {
+    let mut child = Command::new("ffmpeg")
+        .args([
+            "-loglevel",
+            "error",
+            "-i",
+            "pipe:0",
+            "-c:a",
+            "aac",
+            "-f",
+            "adts",
+            "pipe:1",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| AppError::Other("ffmpeg stdin unavailable".to_string()))?;
+    let wav = wav.to_vec();
+    // Feed stdin concurrently so a full stdout pipe can't stall ffmpeg.
+    let feed = tokio::spawn(async move { stdin.write_all(&wav).await });
+    let aac = finish("ffmpeg", child).await?;
+    let _ = feed.await;
+    if aac.is_empty() {
+        return Err(AppError::Other("ffmpeg produced no audio".to_string()));
+    }
+    Ok(aac)
+}
+
+async fn elevenlabs(
+    http: &Client,
+    key: &str,
+    voice: &str,
+    text: &str,
+) -> Result<VoiceNote, AppError> {
+    let resp = http
+        .post(format!("{ELEVENLABS_URL}/{voice}"))
+        .header("xi-api-key", key)
+        .header("accept", "audio/mpeg")
+        .json(&serde_json::json!({ "text": text, "model_id": ELEVENLABS_MODEL }))
+        .timeout(timeout())
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(AppError::Other(format!(
+            "ElevenLabs returned {status}: {body}"
+        )));
+    }
+    Ok(VoiceNote {
+        data: resp.bytes().await?.to_vec(),
+        content_type: "audio/mpeg",
+        filename: "reply.mp3",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speakable_drops_code_and_markup() {
+        let text = "## Result\n\nUse **cargo** with `--release`:\n```sh\ncargo build --release\n```\n> See [the docs](https://doc.rust-lang.org) for more.";
+        assert_eq!(
+            speakable(text),
+            "Result\n\nUse cargo with --release:\n(code omitted)\nSee the docs for more."
+        );
+    }
+
+    #[test]
+    fn test_speakable_cuts_at_sentence() {
+        let text = "Short sentence. ".repeat(TTS_MAX_CHARS / 8);
+        let spoken = speakable(&text);
+        assert!(spoken.chars().count() <= TTS_MAX_CHARS);
+        assert!(spoken.ends_with("sentence."));
+    }
+
+    #[test]
+    fn test_strip_inline_keeps_plain_brackets() {
+        assert_eq!(strip_inline("array[0] and [x]"), "array[0] and [x]");
+    }
+
+    #[test]
+    fn test_voice_mode_parse_round_trip() {
+        for mode in [VoiceMode::Off, VoiceMode::On, VoiceMode::Only] {
+            assert_eq!(VoiceMode::parse(mode.as_str()), Some(mode));
+        }
+        assert_eq!(VoiceMode::parse("loud"), None);
+    }
+
+    #[test]
+    fn test_validate_requires_engine_settings() {
+        let mut config = TtsConfig {
+            engine: Some(TtsEngine::Piper),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        config.voice = Some("en_US-amy-medium.onnx".to_string());
+        assert!(config.validate().is_ok());
+
+        config.engine = Some(TtsEngine::Elevenlabs);
+        assert!(config.validate().is_err());
+        config.elevenlabs_api_key = Some("key".to_string());
+        assert!(config.validate().is_ok());
+
+        assert!(TtsConfig::default().validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_synthesize_unconfigured_is_error() {
+        let result = synthesize(&TtsConfig::default(), &Client::new(), "hello").await;
+        assert!(result.is_err());
+    }
+}
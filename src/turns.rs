@@ -0,0 +1,136 @@
+//! Speakers and turn-taking in shared group sessions.
+//!
+//! Everyone in a bound group talks to one Claude session, so each message
+//! is prefixed with its sender's name (`[Alice]: ...`) and Claude can tell
+//! people apart and answer them by name. Turns are taken one at a time:
+//! each speaker's burst of messages is debounced on its own rather than
+//! merged with someone else's, and a turn that has to wait behind another
+//! speaker's is queued with a raised hand (✋ on the message) until that
+//! one has been answered.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::signal::ParsedEnvelope;
+use crate::state::State;
+
+/// Reaction on a message waiting for someone else's turn to finish.
+pub(crate) const RAISED_HAND_EMOJI: &str = "✋";
+
+/// Added to the system prompt of group sessions.
+pub(crate) const GROUP_PROMPT: &str = "This is a group chat shared by several people. Each of their messages starts with the sender's name in brackets, like \"[Alice]: ...\". Keep track of who said what, and address people by name when answering one of them.";
+
+/// The message text with its sender's name in front.
+pub(crate) fn attributed(envelope: &ParsedEnvelope) -> String {
+    let name = match envelope.source_name.trim() {
+        "" => envelope.source.as_str(),
+        name => name,
+    };
+    format!("[{name}]: {}", envelope.message_text)
+}
+
+/// Debounce buffer for a message: one per speaker in a group, so two
+/// people's bursts never merge into one prompt.
+pub(crate) fn buffer_key(reply_to: &str, speaker: Option<&str>) -> String {
+    match speaker {
+        Some(speaker) => format!("{reply_to}\n{speaker}"),
+        None => reply_to.to_string(),
+    }
+}
+
+/// Queue a turn for `speaker` in `chat`; returns how many turns from other
+/// people are ahead of it.
+pub(crate) fn raise(state: &State, chat: &str, speaker: &str) -> usize {
+    let mut queue = state.raised_hands.entry(chat.to_string()).or_default();
+    let ahead = queue.iter().filter(|s| s.as_str() != speaker).count();
+    queue.push_back(speaker.to_string());
+    ahead
+}
+
+/// `speaker`'s turn in `chat` has been answered (or given up).
+pub(crate) fn lower(state: &State, chat: &str, speaker: &str) {
+    let emptied = state.raised_hands.get_mut(chat).is_some_and(|mut queue| {
+        if let Some(i) = queue.iter().position(|s| s == speaker) {
+            queue.remove(i);
+        }
+        queue.is_empty()
+    });
+    if emptied {
+        state
+            .raised_hands
+            .remove_if(chat, |_, queue: &VecDeque<String>| queue.is_empty());
+    }
+}
+
+/// Queue the turn `envelope` starts, raising a hand on it if someone else
+/// is ahead.
+pub(crate) fn take_turn(state: &Arc<State>, chat: &str, envelope: &ParsedEnvelope) {
+    let ahead = raise(state, chat, &envelope.source);
+    let (true, Some(timestamp)) = (ahead > 0, envelope.sent_at) else {
+        return;
+    };
+    tracing::debug!(chat = %chat, sender = %envelope.source, ahead, "Turn queued behind another speaker");
+    let state = Arc::clone(state);
+    let chat = chat.to_string();
+    let author = envelope.source.clone();
+    tokio::spawn(async move {
+        if let Err(e) = state
+            .signal_api
+            .send_reaction(&chat, RAISED_HAND_EMOJI, &author, timestamp)
+            .await
+        {
+            tracing::debug!(chat = %chat, "Raised hand reaction failed: {e}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::tests::test_state_with;
+    use crate::traits::{MockClaudeRunner, MockSignalApi};
+
+    #[test]
+    fn test_raise_and_lower() {
+        let state = test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        let chat = "group.abc";
+        assert_eq!(raise(&state, chat, "+alice"), 0);
+        // Alice's own earlier turn doesn't make her wait for anyone else.
+        assert_eq!(raise(&state, chat, "+alice"), 0);
+        assert_eq!(raise(&state, chat, "+bob"), 2);
+        lower(&state, chat, "+alice");
+        lower(&state, chat, "+alice");
+        assert_eq!(raise(&state, chat, "+alice"), 1);
+        lower(&state, chat, "+bob");
+        lower(&state, chat, "+alice");
+        assert!(!state.raised_hands.contains_key(chat));
+        // Lowering with nothing queued is harmless.
+        lower(&state, chat, "+carol");
+    }
+
+    #[test]
+    fn test_attributed_and_buffer_key() {
+        let env = ParsedEnvelope {
+            source: "+alice".to_string(),
+            message_text: "deploy it".to_string(),
+            is_sync: false,
+            source_uuid: String::new(),
+            source_name: "Alice".to_string(),
+            attachments: Vec::new(),
+            group_id: Some("abc".to_string()),
+            thread_id: None,
+            sent_at: None,
+        };
+        assert_eq!(attributed(&env), "[Alice]: deploy it");
+        let unnamed = ParsedEnvelope {
+            source_name: String::new(),
+            ..env
+        };
+        assert_eq!(attributed(&unnamed), "[+alice]: deploy it");
+        assert_eq!(buffer_key("+1", None), "+1");
+        assert_ne!(
+            buffer_key("group.abc", Some("+alice")),
+            buffer_key("group.abc", Some("+bob"))
+        );
+    }
+}
@@ -1,107 +1,348 @@
+//! Outgoing webhooks: events POSTed to URLs, for wiring the bridge into
+//! automations (n8n, Home Assistant, ...).
+//!
+//! `--webhook-url` gets every event as `{"event", "sender", "detail",
+//! "timestamp"}`. More hooks go under `webhooks` in the `--config` file,
+//! each with the [`EVENTS`] it wants, optional headers and optionally a
+//! payload template in which `{{event}}`, `{{sender}}`, `{{detail}}` and
+//! `{{timestamp}}` are replaced. A template that is JSON once filled in is
+//! sent as JSON, anything else as plain text. Deliveries that fail to
+//! connect or get a 429 or 5xx are retried with backoff.
+
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tracing::warn;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{debug, warn};
 
-pub(crate) fn fire_webhook(url: &str, event: &str, sender: &str, detail: &str) {
-    let url = url.to_string();
-    let event_owned = event.to_string();
-    let payload = json!({
-        "event": event,
-        "sender": sender,
-        "detail": detail,
-        "timestamp": crate::helpers::epoch_now(),
-    });
-    tokio::spawn(async move {
-        let client = reqwest::Client::new();
-        if let Err(e) = client.post(&url).json(&payload).send().await {
-            warn!(event = %event_owned, "Webhook failed: {e}");
-        }
-    });
+use crate::state::State;
+
+/// Events a hook can subscribe to.
+pub(crate) const EVENTS: [&str; 6] = [
+    "message_received",
+    "message_handled",
+    "error",
+    "budget_threshold",
+    "approval_requested",
+    "shutdown",
+];
+
+/// One webhook from the config file.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct Hook {
+    pub(crate) url: String,
+    /// Events to send (empty: all of them).
+    #[serde(default)]
+    pub(crate) events: Vec<String>,
+    /// Payload with `{{placeholders}}`; the standard JSON object when unset.
+    #[serde(default)]
+    pub(crate) template: Option<String>,
+    #[serde(default)]
+    pub(crate) headers: BTreeMap<String, String>,
 }
 
-pub(crate) fn fire_if_configured(url: &Option<String>, event: &str, sender: &str, detail: &str) {
-    if let Some(ref url) = url {
-        fire_webhook(url, event, sender, detail);
+impl Hook {
+    fn wants(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event)
+    }
+
+    /// The body to send for an event, and its content type.
+    fn body(&self, event: &str, sender: &str, detail: &str, timestamp: i64) -> (String, &str) {
+        let Some(template) = &self.template else {
+            return (
+                payload(event, sender, detail, timestamp).to_string(),
+                "application/json",
+            );
+        };
+        let filled = fill(template, event, sender, detail, timestamp);
+        let content_type = if serde_json::from_str::<serde_json::Value>(&filled).is_ok() {
+            "application/json"
+        } else {
+            "text/plain; charset=utf-8"
+        };
+        (filled, content_type)
     }
 }
 
-#[cfg(test)]
-pub(crate) fn build_webhook_payload(event: &str, sender: &str, detail: &str) -> serde_json::Value {
+/// Where events go.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Webhooks {
+    pub(crate) hooks: Vec<Hook>,
+    /// Day's spend (USD, UTC day) that sends `budget_threshold` once.
+    pub(crate) daily_budget_usd: Option<f64>,
+}
+
+impl Webhooks {
+    /// `--webhook-url` for every event, then the config file's hooks.
+    pub(crate) fn new(
+        url: Option<String>,
+        hooks: Vec<Hook>,
+        daily_budget_usd: Option<f64>,
+    ) -> Self {
+        let mut all: Vec<Hook> = url
+            .filter(|url| !url.is_empty())
+            .map(|url| Hook {
+                url,
+                ..Default::default()
+            })
+            .into_iter()
+            .collect();
+        for hook in hooks {
+            for event in hook.events.iter().filter(|e| !EVENTS.contains(&e.as_str())) {
+                warn!(url = %hook.url, "Unknown webhook event {event:?}; see {}", EVENTS.join(", "));
+            }
+            all.push(hook);
+        }
+        Self {
+            hooks: all,
+            daily_budget_usd,
+        }
+    }
+}
+
+/// The standard payload.
+fn payload(event: &str, sender: &str, detail: &str, timestamp: i64) -> serde_json::Value {
     json!({
         "event": event,
         "sender": sender,
         "detail": detail,
-        "timestamp": crate::helpers::epoch_now(),
+        "timestamp": timestamp,
     })
 }
 
+/// `template` with its placeholders filled. Values are escaped as JSON
+/// string contents, so they can go between quotes in a JSON template.
+fn fill(template: &str, event: &str, sender: &str, detail: &str, timestamp: i64) -> String {
+    let escape = |s: &str| {
+        let quoted = serde_json::Value::from(s).to_string();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    template
+        .replace("{{event}}", &escape(event))
+        .replace("{{sender}}", &escape(sender))
+        .replace("{{detail}}", &escape(detail))
+        .replace("{{timestamp}}", &timestamp.to_string())
+}
+
+/// Send `event` to every hook that wants it, in the background.
+pub(crate) fn notify(state: &State, event: &str, sender: &str, detail: &str) {
+    let timestamp = crate::helpers::epoch_now();
+    for hook in state
+        .config
+        .webhooks
+        .hooks
+        .iter()
+        .filter(|h| h.wants(event))
+    {
+        let (body, content_type) = hook.body(event, sender, detail, timestamp);
+        let request = state
+            .http
+            .post(&hook.url)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(body);
+        let request = hook.headers.iter().fold(request, |request, (name, value)| {
+            request.header(name, value)
+        });
+        let event = event.to_string();
+        tokio::spawn(async move { deliver(request, &event).await });
+    }
+}
+
+/// POST `request`, retrying failures worth retrying.
+async fn deliver(request: reqwest::RequestBuilder, event: &str) -> bool {
+    let mut delay = Duration::from_millis(crate::constants::WEBHOOK_RETRY_BASE_MS);
+    for attempt in 0..=crate::constants::WEBHOOK_RETRIES {
+        if attempt > 0 {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+        let Some(request) = request.try_clone() else {
+            break;
+        };
+        match request.send().await {
+            Ok(resp) if resp.status().is_success() => return true,
+            Ok(resp)
+                if resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                    && !resp.status().is_server_error() =>
+            {
+                warn!(event, status = %resp.status(), "Webhook refused");
+                return false;
+            }
+            Ok(resp) => debug!(event, attempt, status = %resp.status(), "Webhook failed"),
+            Err(e) => debug!(event, attempt, "Webhook failed: {e}"),
+        }
+    }
+    warn!(event, "Webhook failed after retries");
+    false
+}
+
+/// Send `budget_threshold` the first time the day's spend passes
+/// `daily_budget_usd`. Called after a run's cost is recorded.
+pub(crate) fn check_daily_budget(state: &State) {
+    use std::sync::atomic::Ordering;
+    let Some(threshold) = state.config.webhooks.daily_budget_usd else {
+        return;
+    };
+    let today = crate::helpers::epoch_now() / crate::constants::SECS_PER_DAY;
+    if state.metrics.budget_alerted_day.load(Ordering::Relaxed) == today {
+        return;
+    }
+    let spent = crate::ledger::spent_today();
+    if spent < threshold {
+        return;
+    }
+    if state
+        .metrics
+        .budget_alerted_day
+        .swap(today, Ordering::Relaxed)
+        != today
+    {
+        notify(
+            state,
+            "budget_threshold",
+            "",
+            &format!("${spent:.2} spent today, over ${threshold:.2}"),
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_fire_webhook_builds_payload() {
-        let payload = build_webhook_payload("message_received", "+user", "");
-        assert!(payload.is_object());
-        assert!(payload.get("event").is_some());
-        assert!(payload.get("sender").is_some());
-        assert!(payload.get("detail").is_some());
-        assert!(payload.get("timestamp").is_some());
+    fn test_payload() {
+        let payload = payload("error", "+user", "timeout", 1700000000);
+        assert_eq!(payload["event"], "error");
+        assert_eq!(payload["sender"], "+user");
+        assert_eq!(payload["detail"], "timeout");
+        assert_eq!(payload["timestamp"], 1700000000);
     }
 
     #[test]
-    fn test_fire_webhook_includes_timestamp() {
-        let payload = build_webhook_payload("error", "+user", "timeout");
-        assert!(payload["timestamp"].is_i64() || payload["timestamp"].is_u64());
+    fn test_hook_events_and_templates() {
+        let hook = Hook {
+            url: "http://n8n.local/hook".to_string(),
+            events: vec!["error".to_string()],
+            template: Some(r#"{"text": "{{event}} for {{sender}}: {{detail}}"}"#.to_string()),
+            headers: BTreeMap::new(),
+        };
+        assert!(hook.wants("error"));
+        assert!(!hook.wants("message_received"));
+        let (body, content_type) = hook.body("error", "+1", "said \"no\"", 5);
+        assert_eq!(content_type, "application/json");
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["text"], "error for +1: said \"no\"");
+
+        let plain = Hook {
+            template: Some("{{event}} at {{timestamp}}".to_string()),
+            ..hook
+        };
+        assert_eq!(
+            plain.body("shutdown", "", "", 5),
+            ("shutdown at 5".to_string(), "text/plain; charset=utf-8")
+        );
     }
 
     #[test]
-    fn test_fire_webhook_includes_event() {
-        let payload = build_webhook_payload("session_created", "+user", "");
-        assert_eq!(payload["event"], "session_created");
+    fn test_webhooks_new() {
+        let webhooks = Webhooks::new(
+            Some("http://all".to_string()),
+            vec![Hook {
+                url: "http://errors".to_string(),
+                events: vec!["error".to_string()],
+                ..Default::default()
+            }],
+            None,
+        );
+        assert_eq!(webhooks.hooks.len(), 2);
+        assert!(webhooks.hooks[0].wants("shutdown"));
+        assert!(Webhooks::new(Some(String::new()), vec![], None)
+            .hooks
+            .is_empty());
     }
 
-    #[test]
-    fn test_fire_if_configured_none_is_noop() {
+    fn state_with_hooks(urls: &[&str]) -> State {
+        use crate::traits::{MockClaudeRunner, MockSignalApi};
+        let mut state =
+            crate::state::tests::test_state_with(MockSignalApi::new(), MockClaudeRunner::new());
+        state.config_mut().webhooks = Webhooks::new(
+            None,
+            urls.iter()
+                .map(|url| Hook {
+                    url: url.to_string(),
+                    events: vec!["error".to_string()],
+                    ..Default::default()
+                })
+                .collect(),
+            None,
+        );
+        state
+    }
+
+    #[tokio::test]
+    async fn test_notify_without_hooks_is_noop() {
         // Should simply not panic
-        fire_if_configured(&None, "test", "+user", "");
+        notify(&state_with_hooks(&[]), "error", "+user", "");
     }
 
     #[tokio::test]
-    async fn test_fire_if_configured_some_fires() {
+    async fn test_notify_sends_subscribed_events() {
         let server = wiremock::MockServer::start().await;
         wiremock::Mock::given(wiremock::matchers::method("POST"))
-            .and(wiremock::matchers::path("/"))
+            .and(wiremock::matchers::body_partial_json(
+                json!({"event": "error", "sender": "+user", "detail": "boom"}),
+            ))
             .respond_with(wiremock::ResponseTemplate::new(200))
             .expect(1)
             .mount(&server)
             .await;
-        fire_if_configured(&Some(server.uri()), "test_event", "+user", "detail");
+        let state = state_with_hooks(&[&server.uri()]);
+        notify(&state, "error", "+user", "boom");
+        notify(&state, "message_received", "+user", "");
         // Give the spawned task time to complete
-        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-    }
-
-    #[test]
-    fn test_webhook_payload_has_sender() {
-        let payload = build_webhook_payload("message_received", "+1234567890", "");
-        assert_eq!(payload["sender"], "+1234567890");
-    }
-
-    #[test]
-    fn test_webhook_payload_has_detail() {
-        let payload = build_webhook_payload("error", "+user", "connection refused");
-        assert_eq!(payload["detail"], "connection refused");
+        tokio::time::sleep(Duration::from_millis(100)).await;
     }
 
     #[tokio::test]
-    async fn test_webhook_does_not_block() {
-        // fire_webhook with a non-existent URL should return immediately
+    async fn test_notify_does_not_block() {
+        let state = state_with_hooks(&["http://192.0.2.1:1/nonexistent"]);
         let start = std::time::Instant::now();
-        fire_webhook("http://192.0.2.1:1/nonexistent", "test", "+user", "");
-        let elapsed = start.elapsed();
+        notify(&state, "error", "+user", "");
         assert!(
-            elapsed.as_millis() < 50,
-            "fire_webhook should not block, took {}ms",
-            elapsed.as_millis()
+            start.elapsed().as_millis() < 50,
+            "notify should not block, took {}ms",
+            start.elapsed().as_millis()
         );
     }
+
+    #[tokio::test]
+    async fn test_deliver_retries_server_errors() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(wiremock::ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .and(wiremock::matchers::header("x-token", "s3cret"))
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let request = reqwest::Client::new()
+            .post(server.uri())
+            .header("x-token", "s3cret")
+            .body("{}");
+        assert!(deliver(request, "error").await);
+
+        let refused = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(wiremock::ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&refused)
+            .await;
+        assert!(!deliver(reqwest::Client::new().post(refused.uri()), "error").await);
+    }
 }
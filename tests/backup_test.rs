@@ -0,0 +1,88 @@
+//! The restore steps in the user guide ("Backups"), run against the built
+//! binary: snapshot a data directory, then restore it on a "new machine".
+
+use std::path::Path;
+use std::process::Command;
+
+/// Helper: path to the built binary. `cargo test` compiles it for us.
+fn ccchat_bin() -> String {
+    let mut path = std::env::current_exe()
+        .expect("cannot determine test exe path")
+        .parent()
+        .expect("no parent dir")
+        .parent()
+        .expect("no grandparent dir")
+        .to_path_buf();
+    path.push("ccchat");
+    path.to_string_lossy().to_string()
+}
+
+/// Run `ccchat <args>` on `data_dir` and return stdout, failing on error.
+fn ccchat(data_dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(ccchat_bin())
+        .env("CCCHAT_DATA_DIR", data_dir)
+        .env("CCCHAT_BACKUP_PASSPHRASE", "correct horse battery")
+        .env_remove("CCCHAT_BACKUP_DIR")
+        .args(args)
+        .output()
+        .expect("failed to run ccchat");
+    assert!(
+        output.status.success(),
+        "ccchat {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr),
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn backup_now_then_restore_on_a_new_machine() {
+    let base = std::env::temp_dir().join(format!("ccchat_backup_it_{}", uuid::Uuid::new_v4()));
+    let old = base.join("old");
+    std::fs::create_dir_all(old.join("memories")).unwrap();
+    std::fs::write(old.join("allowed.json"), r#"{"allowed":[{"id":"+1"}]}"#).unwrap();
+    let conn = rusqlite::Connection::open(old.join("memories/+1.db")).unwrap();
+    conn.execute_batch("CREATE TABLE facts (fact TEXT); INSERT INTO facts VALUES ('likes tea');")
+        .unwrap();
+    drop(conn);
+
+    // ccchat backup now
+    let out = ccchat(&old, &["backup", "now"]);
+    assert!(out.contains("Backed up 2 file(s)"), "{out}");
+
+    // ccchat backup list
+    let listed = ccchat(&old, &["backup", "list"]);
+    let snapshot = listed
+        .lines()
+        .last()
+        .and_then(|line| line.split(" (").next())
+        .expect("a snapshot")
+        .to_string();
+    assert!(snapshot.ends_with(".tar.zst.enc"), "{listed}");
+
+    // Copy the snapshot over; on the new machine: ccchat backup restore <file>
+    let new = base.join("new");
+    let copied = base.join(Path::new(&snapshot).file_name().unwrap());
+    std::fs::copy(&snapshot, &copied).unwrap();
+    let out = ccchat(&new, &["backup", "restore", copied.to_str().unwrap()]);
+    assert!(out.contains("Restored 2 file(s)"), "{out}");
+
+    assert_eq!(
+        std::fs::read_to_string(new.join("allowed.json")).unwrap(),
+        r#"{"allowed":[{"id":"+1"}]}"#
+    );
+    let conn = rusqlite::Connection::open(new.join("memories/+1.db")).unwrap();
+    let fact: String = conn
+        .query_row("SELECT fact FROM facts", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(fact, "likes tea");
+
+    // Restoring over live data needs --force
+    let refused = Command::new(ccchat_bin())
+        .env("CCCHAT_DATA_DIR", &new)
+        .env("CCCHAT_BACKUP_PASSPHRASE", "correct horse battery")
+        .args(["backup", "restore", copied.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(!refused.status.success());
+    std::fs::remove_dir_all(&base).unwrap();
+}
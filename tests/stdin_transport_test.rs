@@ -0,0 +1,74 @@
+//! End-to-end tests for the terminal REPL (`--transport stdin`).
+//!
+//! With `--dry-run` the binary echoes prompts instead of calling Claude, so
+//! these run the full message pipeline without a messenger or the CLI.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Helper: path to the built binary. `cargo test` compiles it for us.
+fn ccchat_bin() -> String {
+    let mut path = std::env::current_exe()
+        .expect("cannot determine test exe path")
+        .parent()
+        .expect("no parent dir")
+        .parent()
+        .expect("no grandparent dir")
+        .to_path_buf();
+    path.push("ccchat");
+    path.to_string_lossy().to_string()
+}
+
+/// Pipe `input` into a dry-run REPL with a throwaway HOME and return stdout.
+fn run_repl(input: &str) -> String {
+    let home = std::env::temp_dir().join(format!("ccchat_repl_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&home).expect("create home");
+    let mut child = Command::new(ccchat_bin())
+        .env("HOME", &home)
+        .env_remove("CCCHAT_TRANSPORT")
+        .args([
+            "--account",
+            "+15550001111",
+            "--transport",
+            "stdin",
+            "--dry-run",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run ccchat");
+    child
+        .stdin
+        .take()
+        .expect("stdin")
+        .write_all(input.as_bytes())
+        .expect("write stdin");
+    let output = child.wait_with_output().expect("wait");
+    let _ = std::fs::remove_dir_all(&home);
+
+    assert!(
+        output.status.success(),
+        "expected exit 0 at end of input, got {:?}\nstderr: {}",
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr),
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn repl_replies_in_order_and_exits_at_eof() {
+    let stdout = run_repl("hello there\n\n/help\n");
+
+    let reply = stdout.find("[dry run]").expect("prompt reply");
+    let help = stdout.find("ccchat commands:").expect("help reply");
+    assert!(reply < help, "replies out of order:\n{stdout}");
+    assert!(stdout.contains("hello there"), "{stdout}");
+}
+
+#[test]
+fn repl_keeps_logs_off_stdout() {
+    let stdout = run_repl("/status\n");
+
+    assert!(!stdout.contains("INFO"), "logs leaked to stdout:\n{stdout}");
+}